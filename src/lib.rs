@@ -0,0 +1,18057 @@
+// logcolor - tiny Rust CLI to colorize log levels in text (ERROR/WARN/INFO/DEBUG)
+// No external crates. Useful for quickly reading logs in terminals.
+//
+// Usage:
+//   cargo run --release -- <path-to-log-file>
+//   cat app.log | cargo run --release --
+//   cargo build --release && ./target/release/logcolor app.log
+//   ./target/release/logcolor --theme light app.log
+//   ./target/release/logcolor --theme colorblind app.log
+//   ./target/release/logcolor --color=always app.log | less -R
+//   ./target/release/logcolor app.log.1.gz   (gzip is noticed by content, name or not)
+//
+// This is the library (`rustc --crate-type lib --crate-name logcolor src/lib.rs`):
+// `Highlighter::builder`, `Highlighter::find_level`, `Highlighter::highlight_line`
+// and `Highlighter::spans` are the API. The binary, src/main.rs, parses the
+// command line and hands it to `run`, which is there for it and nothing else.
+// The doctests run against the library:
+//   rustdoc --test --crate-name logcolor --extern logcolor=liblogcolor.rlib src/lib.rs
+//
+// Ctrl+C to stop when reading from a never-ending stream. While one is piped in,
+// Enter draws a `──── mark 14:32:18 ────` divider and `m` a numbered one.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const ITALIC: &str = "\x1b[3m";
+const UNDERLINE: &str = "\x1b[4m";
+const REVERSE: &str = "\x1b[7m";
+
+/// canonical severity of a line, independent of the spelling used in the log.
+/// variants are ordered from least to most severe.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// npm's `silly`, even chattier than trace.
+    Silly,
+    Trace,
+    Debug,
+    /// npm/winston request logging, just below info.
+    Http,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+impl Level {
+    const ALL: [Level; 8] = [
+        Level::Silly,
+        Level::Trace,
+        Level::Debug,
+        Level::Http,
+        Level::Info,
+        Level::Warn,
+        Level::Error,
+        Level::Fatal,
+    ];
+
+    /// `ERROR`, `WARN` and so on, as `--min-level` and `--config` take them.
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Silly => "SILLY",
+            Level::Trace => "TRACE",
+            Level::Debug => "DEBUG",
+            Level::Http => "HTTP",
+            Level::Info => "INFO",
+            Level::Warn => "WARN",
+            Level::Error => "ERROR",
+            Level::Fatal => "FATAL",
+        }
+    }
+
+    /// the level an upper-case token like `WARNING` or `ERR` stands for.
+    pub fn from_token(token: &str) -> Option<Level> {
+        match token {
+            "FATAL" | "PANIC" | "FTL" | "CRITICAL" => Some(Level::Fatal),
+            "ERROR" | "ERR" | "SEVERE" => Some(Level::Error),
+            "WARN" | "WARNING" | "WRN" => Some(Level::Warn),
+            "INFO" | "INF" | "CONFIG" | "LOG" | "NOTE" | "SYSTEM" | "NOTICE" => Some(Level::Info),
+            "HTTP" => Some(Level::Http),
+            "DEBUG" | "DBG" | "FINE" => Some(Level::Debug),
+            "TRACE" | "VRB" | "VERBOSE" | "FINER" | "FINEST" => Some(Level::Trace),
+            "SILLY" => Some(Level::Silly),
+            _ => None,
+        }
+    }
+
+    /// syslog severities 0 (emerg) to 7 (debug), as found in the PRI field.
+    fn from_syslog_severity(severity: u8) -> Level {
+        match severity {
+            0..=3 => Level::Error,
+            4 => Level::Warn,
+            5 | 6 => Level::Info,
+            _ => Level::Debug,
+        }
+    }
+}
+
+/// a foreground color as configured; how it is written out depends on the
+/// `ColorDepth` of the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    /// the 16 classic colors, 8..=15 being the bright variants.
+    Basic(u8),
+    /// xterm 256-color palette index.
+    Indexed(u8),
+    Rgb(u8, u8, u8),
+}
+
+/// how many colors the output can show, from least to most capable.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorDepth {
+    None,
+    Basic,
+    Ansi256,
+    TrueColor,
+}
+
+impl ColorDepth {
+    fn describe(self) -> &'static str {
+        match self {
+            ColorDepth::None => "no color",
+            ColorDepth::Basic => "8 colors",
+            ColorDepth::Ansi256 => "256 colors",
+            ColorDepth::TrueColor => "truecolor",
+        }
+    }
+}
+
+/// xterm's default values for the 16 classic colors.
+const BASIC_RGB: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// channel values of the 6x6x6 cube in the 256-color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).unsigned_abs().pow(2);
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// nearest of the 8 colors every color terminal has.
+fn rgb_to_basic(rgb: (u8, u8, u8)) -> u8 {
+    (0..8u8).min_by_key(|&i| distance(rgb, BASIC_RGB[usize::from(i)])).unwrap()
+}
+
+/// nearest entry of the color cube or the grayscale ramp.
+fn rgb_to_indexed(rgb: (u8, u8, u8)) -> u8 {
+    let nearest_level =
+        |v: u8| (0..6u8).min_by_key(|&i| CUBE_LEVELS[usize::from(i)].abs_diff(v)).unwrap();
+    let (r, g, b) = (nearest_level(rgb.0), nearest_level(rgb.1), nearest_level(rgb.2));
+    let cube = 16 + 36 * r + 6 * g + b;
+    let avg = ((u16::from(rgb.0) + u16::from(rgb.1) + u16::from(rgb.2)) / 3) as u8;
+    let gray = 232 + (avg.saturating_sub(3) / 10).min(23);
+    if distance(rgb, Color::Indexed(gray).to_rgb()) < distance(rgb, Color::Indexed(cube).to_rgb()) {
+        gray
+    } else {
+        cube
+    }
+}
+
+impl Color {
+    fn to_rgb(self) -> (u8, u8, u8) {
+        match self {
+            Color::Basic(n) => BASIC_RGB[usize::from(n & 15)],
+            Color::Indexed(n @ 0..=15) => BASIC_RGB[usize::from(n)],
+            Color::Indexed(n @ 16..=231) => {
+                let n = usize::from(n - 16);
+                (CUBE_LEVELS[n / 36], CUBE_LEVELS[n / 6 % 6], CUBE_LEVELS[n % 6])
+            }
+            Color::Indexed(n) => {
+                let v = 8 + 10 * (n - 232);
+                (v, v, v)
+            }
+            Color::Rgb(r, g, b) => (r, g, b),
+        }
+    }
+
+    /// the closest color the terminal can actually display.
+    fn downconvert(self, depth: ColorDepth) -> Color {
+        match (self, depth) {
+            (Color::Basic(n), ColorDepth::Basic) => Color::Basic(n & 7),
+            (Color::Basic(_), _) => self,
+            (Color::Indexed(n @ 0..=15), _) => Color::Basic(n).downconvert(depth),
+            (Color::Indexed(_), ColorDepth::Ansi256 | ColorDepth::TrueColor) => self,
+            (Color::Rgb(..), ColorDepth::TrueColor) => self,
+            (Color::Rgb(..), ColorDepth::Ansi256) => Color::Indexed(rgb_to_indexed(self.to_rgb())),
+            (_, _) => Color::Basic(rgb_to_basic(self.to_rgb())),
+        }
+    }
+
+    /// as a config file or a `--rules` style would give it.
+    fn name(self) -> String {
+        match self {
+            Color::Basic(8) => "gray".to_string(),
+            Color::Basic(n @ 0..=7) => COLOR_NAMES[usize::from(n)].to_string(),
+            Color::Basic(n) => format!("bright_{}", COLOR_NAMES[usize::from(n & 7)]),
+            Color::Indexed(n) => n.to_string(),
+            Color::Rgb(r, g, b) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+
+    /// SGR sequence selecting this color as foreground.
+    fn fg_sgr(self, depth: ColorDepth) -> String {
+        match self.downconvert(depth) {
+            Color::Basic(n @ 0..=7) => format!("\x1b[{}m", 30 + n),
+            Color::Basic(n) => format!("\x1b[{}m", 90 + (n & 7)),
+            Color::Indexed(n) => format!("\x1b[38;5;{}m", n),
+            Color::Rgb(r, g, b) => format!("\x1b[38;2;{};{};{}m", r, g, b),
+        }
+    }
+}
+
+const RED: Color = Color::Basic(1);
+const BRIGHT_RED: Color = Color::Basic(9);
+const YELLOW: Color = Color::Basic(3);
+const GREEN: Color = Color::Basic(2);
+const BLUE: Color = Color::Basic(4);
+const MAGENTA: Color = Color::Basic(5);
+const CYAN: Color = Color::Basic(6);
+const GRAY: Color = Color::Basic(8);
+
+// the Okabe-Ito set, which stays apart under the common color vision
+// deficiencies.
+const CVD_ORANGE: Color = Color::Rgb(230, 159, 0);
+const CVD_VERMILLION: Color = Color::Rgb(213, 94, 0);
+const CVD_SKY_BLUE: Color = Color::Rgb(86, 180, 233);
+const CVD_BLUE: Color = Color::Rgb(0, 114, 178);
+const CVD_YELLOW: Color = Color::Rgb(240, 228, 66);
+const CVD_PURPLE: Color = Color::Rgb(204, 121, 167);
+const CVD_GRAY: Color = Color::Rgb(153, 153, 153);
+
+// solarized's accents, for its dark variant.
+const SOL_YELLOW: Color = Color::Rgb(181, 137, 0);
+const SOL_MAGENTA: Color = Color::Rgb(211, 54, 130);
+const SOL_RED: Color = Color::Rgb(220, 50, 47);
+const SOL_VIOLET: Color = Color::Rgb(108, 113, 196);
+const SOL_BLUE: Color = Color::Rgb(38, 139, 210);
+const SOL_CYAN: Color = Color::Rgb(42, 161, 152);
+const SOL_GREEN: Color = Color::Rgb(133, 153, 0);
+const SOL_BASE1: Color = Color::Rgb(147, 161, 161);
+
+/// attributes per level, indexed by `Level as usize`: http dimmed.
+const HTTP_DIM: [&[&str]; 8] = [&[], &[], &[], &[DIM], &[], &[], &[], &[]];
+
+/// one color per level. the dark palette is the historical default;
+/// the light one avoids yellow/cyan, which are unreadable on white backgrounds;
+/// the colorblind one does without the red/green contrast; the monochrome
+/// one has no colors at all, only attributes.
+struct Palette {
+    fatal: Color,
+    error: Color,
+    warn: Color,
+    info: Color,
+    /// drawn dimmed.
+    http: Color,
+    debug: Color,
+    /// also used for silly.
+    trace: Color,
+    /// used dimmed for secondary structure such as syslog structured data.
+    muted: Color,
+    /// draw error and fatal tokens in reverse video, so they stand out even
+    /// without telling hues apart.
+    inverse_errors: bool,
+    /// attributes on top of the colors, indexed by `Level as usize`.
+    attrs: [&'static [&'static str]; 8],
+    /// false to leave the colors above unused.
+    colored: bool,
+}
+
+const DARK_PALETTE: Palette = Palette {
+    fatal: BRIGHT_RED,
+    error: RED,
+    warn: YELLOW,
+    info: GREEN,
+    http: BLUE,
+    debug: CYAN,
+    trace: MAGENTA,
+    muted: CYAN,
+    inverse_errors: false,
+    attrs: HTTP_DIM,
+    colored: true,
+};
+
+const LIGHT_PALETTE: Palette = Palette {
+    fatal: BRIGHT_RED,
+    error: RED,
+    warn: MAGENTA,
+    info: GREEN,
+    http: BLUE,
+    debug: BLUE,
+    trace: GRAY,
+    muted: BLUE,
+    inverse_errors: false,
+    attrs: HTTP_DIM,
+    colored: true,
+};
+
+const COLORBLIND_PALETTE: Palette = Palette {
+    fatal: CVD_VERMILLION,
+    error: CVD_ORANGE,
+    warn: CVD_YELLOW,
+    info: CVD_SKY_BLUE,
+    http: CVD_BLUE,
+    debug: CVD_GRAY,
+    trace: CVD_PURPLE,
+    muted: CVD_SKY_BLUE,
+    inverse_errors: true,
+    attrs: HTTP_DIM,
+    colored: true,
+};
+
+const SOLARIZED_DARK_PALETTE: Palette = Palette {
+    fatal: SOL_MAGENTA,
+    error: SOL_RED,
+    warn: SOL_YELLOW,
+    info: SOL_GREEN,
+    http: SOL_BLUE,
+    debug: SOL_CYAN,
+    trace: SOL_VIOLET,
+    muted: SOL_BASE1,
+    inverse_errors: false,
+    attrs: HTTP_DIM,
+    colored: true,
+};
+
+/// for when colors don't tell anything apart: errors reversed, warnings in
+/// italics, the chatty levels dim.
+const MONOCHROME_PALETTE: Palette = Palette {
+    fatal: GRAY,
+    error: GRAY,
+    warn: GRAY,
+    info: GRAY,
+    http: GRAY,
+    debug: GRAY,
+    trace: GRAY,
+    muted: GRAY,
+    inverse_errors: true,
+    attrs: [
+        &[DIM, ITALIC],
+        &[DIM, ITALIC],
+        &[DIM],
+        &[DIM],
+        &[],
+        &[ITALIC],
+        &[BOLD],
+        &[BOLD, UNDERLINE],
+    ],
+    colored: false,
+};
+
+impl Palette {
+    fn color(&self, level: Level) -> Color {
+        match level {
+            Level::Fatal => self.fatal,
+            Level::Error => self.error,
+            Level::Warn => self.warn,
+            Level::Info => self.info,
+            Level::Http => self.http,
+            Level::Debug => self.debug,
+            Level::Trace | Level::Silly => self.trace,
+        }
+    }
+}
+
+/// the escape sequences actually written for each level, rendered once for
+/// the palette and depth in effect. with `ColorDepth::None` nothing is styled.
+#[derive(Clone)]
+struct Styles {
+    depth: ColorDepth,
+    /// indexed by `Level as usize`.
+    levels: [String; 8],
+    /// faint text for headers and other fields nobody reads first.
+    dim: String,
+    muted: String,
+    inverse_errors: bool,
+    /// each level's color and attributes, for `Style`s; indexed like
+    /// `levels`. the palette's, or a config file's.
+    looks: [Style; 8],
+    muted_color: Option<Color>,
+    /// `--output-format html`: each level's looks come after its
+    /// `level_tag`, for `Html` to draw with the level's class.
+    tagged: bool,
+}
+
+impl Styles {
+    fn new(palette: &Palette, depth: ColorDepth) -> Styles {
+        let inverse_errors = palette.inverse_errors;
+        let looks = Level::ALL.map(|level| Style {
+            attrs: palette.attrs[level as usize].to_vec(),
+            fg: palette.colored.then(|| palette.color(level)),
+        });
+        let muted_color = palette.colored.then_some(palette.muted);
+        Styles {
+            depth,
+            levels: looks.clone().map(|look| look.sgr(depth)),
+            dim: if depth == ColorDepth::None { String::new() } else { DIM.to_string() },
+            muted: Style { attrs: vec![DIM], fg: muted_color }.sgr(depth),
+            inverse_errors,
+            looks,
+            muted_color,
+            tagged: false,
+        }
+    }
+
+    /// each level's looks tagged with the level, for `Html`.
+    fn tagged(mut self) -> Styles {
+        self.tagged = true;
+        for level in Level::ALL {
+            let sgr = &mut self.levels[level as usize];
+            if !sgr.is_empty() {
+                *sgr = format!("{}{}", level_tag(level, LevelLook::Line), sgr);
+            }
+        }
+        self
+    }
+
+    /// the levels a config file gives a look of their own, over the palette.
+    fn with_scheme(mut self, scheme: &Scheme) -> Styles {
+        for (i, look) in scheme.levels.iter().enumerate() {
+            if let Some(look) = look {
+                self.levels[i] = look.sgr(self.depth);
+                if self.tagged && !self.levels[i].is_empty() {
+                    let tag = level_tag(Level::ALL[i], LevelLook::Line);
+                    self.levels[i].insert_str(0, tag);
+                }
+                self.looks[i] = look.clone();
+            }
+        }
+        self
+    }
+
+    fn enabled(&self) -> bool {
+        self.depth != ColorDepth::None
+    }
+
+    fn color(&self, level: Level) -> &str {
+        &self.levels[level as usize]
+    }
+
+    /// write `text` wrapped in `sgr` and a reset, or bare when color is off.
+    fn paint(&self, out: &mut dyn Write, sgr: &str, text: &str) -> io::Result<()> {
+        if self.enabled() && !sgr.is_empty() {
+            write!(out, "{}{}{}", sgr, text, RESET)
+        } else {
+            write!(out, "{}", text)
+        }
+    }
+
+    /// the bold, level-colored treatment used for level tokens.
+    fn paint_level(&self, out: &mut dyn Write, level: Level, text: &str) -> io::Result<()> {
+        self.paint(out, &self.level_style(level, BOLD).sgr(self.depth), text)
+    }
+
+    /// a level guessed from the wording rather than given: underlined, not
+    /// bold, so it reads as a hint.
+    fn paint_inferred(&self, out: &mut dyn Write, level: Level, text: &str) -> io::Result<()> {
+        self.paint(out, &self.level_style(level, UNDERLINE).sgr(self.depth), text)
+    }
+
+    /// `weight` (bold for a level token, underline for an inferred one) in
+    /// the level's color, reversed for errors when the palette says so.
+    fn level_style(&self, level: Level, weight: &'static str) -> Style {
+        let look = &self.looks[level as usize];
+        let mut attrs = vec![weight];
+        if self.inverse_errors && level >= Level::Error {
+            attrs.push(REVERSE);
+        }
+        for &attr in &look.attrs {
+            if !attrs.contains(&attr) {
+                attrs.push(attr);
+            }
+        }
+        let mut style = Style { attrs, fg: look.fg };
+        if self.tagged && !style.sgr(self.depth).is_empty() {
+            let kind = match weight {
+                BOLD => LevelLook::Token,
+                UNDERLINE => LevelLook::Inferred,
+                _ => LevelLook::Line,
+            };
+            style.attrs.insert(0, level_tag(level, kind));
+        }
+        style
+    }
+
+    /// the level's color as `color` has it, for a whole line.
+    fn line_style(&self, level: Level) -> Style {
+        let mut style = self.looks[level as usize].clone();
+        if self.tagged && !style.sgr(self.depth).is_empty() {
+            style.attrs.insert(0, level_tag(level, LevelLook::Line));
+        }
+        style
+    }
+
+    fn dim_style(&self) -> Style {
+        Style { attrs: vec![DIM], fg: None }
+    }
+
+    fn muted_style(&self) -> Style {
+        Style { attrs: vec![DIM], fg: self.muted_color }
+    }
+
+    /// never bold, so an `--extras` find doesn't compete with the level
+    /// token; in monochrome only the attribute.
+    fn extra_style(&self, extra: Extra) -> Style {
+        let (attr, color) = match extra {
+            Extra::Url => (UNDERLINE, BLUE),
+            Extra::Uuid => (ITALIC, MAGENTA),
+            Extra::Ip => (UNDERLINE, CYAN),
+            Extra::Duration => (ITALIC, CYAN),
+            Extra::Hex => (DIM, MAGENTA),
+        };
+        Style { attrs: vec![attr], fg: self.muted_color.map(|_| color) }
+    }
+}
+
+/// what a span of a line is, for embedders that style by meaning rather
+/// than take our escape codes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SpanKind {
+    Level,
+    Timestamp,
+    /// a name: a module, logger, file or error code.
+    Entity,
+    /// a `--rules` highlight.
+    Highlight,
+    /// a structured record's message.
+    Message,
+    /// an `--extras` find: an address, id, URL or duration.
+    Extra,
+    Plain,
+}
+
+/// a piece of a line and how it's drawn. `Highlighter::spans` covers a line
+/// with these, ordered and non-overlapping.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Span {
+    pub range: std::ops::Range<usize>,
+    pub style: Style,
+    pub kind: SpanKind,
+}
+
+impl Span {
+    fn new(range: std::ops::Range<usize>, style: Style, kind: SpanKind) -> Span {
+        Span { range, style, kind }
+    }
+
+    fn plain(range: std::ops::Range<usize>) -> Span {
+        Span::new(range, Style::default(), SpanKind::Plain)
+    }
+}
+
+/// `marks` in order, any overlapping an earlier one dropped, and the gaps
+/// between them filled with plain spans so the whole line is covered.
+fn cover(line: &str, mut marks: Vec<Span>) -> Vec<Span> {
+    marks.sort_by_key(|m| m.range.start);
+    let mut spans = Vec::with_capacity(2 * marks.len() + 1);
+    let mut pos = 0;
+    for mark in marks {
+        if mark.range.start < pos || mark.range.is_empty() || mark.range.end > line.len() {
+            continue;
+        }
+        if mark.range.start > pos {
+            spans.push(Span::plain(pos..mark.range.start));
+        }
+        pos = mark.range.end;
+        spans.push(mark);
+    }
+    if pos < line.len() {
+        spans.push(Span::plain(pos..line.len()));
+    }
+    spans
+}
+
+/// `mark` drawn over covering `spans`, splitting those it lands in.
+fn overlay(spans: Vec<Span>, mark: Span) -> Vec<Span> {
+    let over = mark.range.clone();
+    if over.is_empty() {
+        return spans;
+    }
+    let mut out = Vec::with_capacity(spans.len() + 2);
+    let mut mark = Some(mark);
+    for span in spans {
+        if span.range.end <= over.start || span.range.start >= over.end {
+            out.push(span);
+            continue;
+        }
+        if span.range.start < over.start {
+            out.push(Span { range: span.range.start..over.start, ..span.clone() });
+        }
+        out.extend(mark.take());
+        if span.range.end > over.end {
+            out.push(Span { range: over.end..span.range.end, ..span });
+        }
+    }
+    out
+}
+
+/// the ANSI renderer: each span's text in its style and a reset, or with
+/// color off just the text.
+fn write_spans(out: &mut dyn Write, line: &str, spans: &[Span], styles: &Styles) -> io::Result<()> {
+    for span in spans {
+        styles.paint(out, &span.style.sgr(styles.depth), &line[span.range.clone()])?;
+    }
+    Ok(())
+}
+
+/// the plain renderer: the spans' text and nothing else, whatever the
+/// color depth.
+fn write_plain_spans(out: &mut dyn Write, line: &str, spans: &[Span]) -> io::Result<()> {
+    spans.iter().try_for_each(|span| write!(out, "{}", &line[span.range.clone()]))
+}
+
+/// colors handed out to names (modules, hosts, ...) so the same name always
+/// gets the same color. red and yellow are left to the levels.
+const HASH_COLORS_BASIC: [u8; 4] = [2, 4, 5, 6];
+const HASH_COLORS_256: [u8; 12] = [33, 39, 43, 72, 75, 99, 107, 114, 135, 141, 170, 176];
+
+fn fnv1a(text: &str) -> u64 {
+    text.bytes()
+        .fold(0xcbf2_9ce4_8422_2325, |h, b| (h ^ u64::from(b)).wrapping_mul(0x100_0000_01b3))
+}
+
+/// a stable color for `name`, picked from the richest palette available.
+fn hash_color(name: &str, depth: ColorDepth) -> Color {
+    let h = fnv1a(name) as usize;
+    if depth >= ColorDepth::Ansi256 {
+        Color::Indexed(HASH_COLORS_256[h % HASH_COLORS_256.len()])
+    } else {
+        Color::Basic(HASH_COLORS_BASIC[h % HASH_COLORS_BASIC.len()])
+    }
+}
+
+/// value of `--color`.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    pub fn parse(s: &str) -> Option<ColorMode> {
+        match s {
+            "auto" => Some(ColorMode::Auto),
+            "always" => Some(ColorMode::Always),
+            "never" => Some(ColorMode::Never),
+            _ => None,
+        }
+    }
+}
+
+/// everything about the environment that feeds the color decision, gathered
+/// up front so `decide_color_depth` stays a pure function.
+struct TermInfo {
+    term: Option<String>,
+    colorterm: Option<String>,
+    no_color: bool,
+    stdout_tty: bool,
+    /// `colors` capability from the compiled terminfo entry, when one was found.
+    terminfo_colors: Option<i32>,
+    /// `TERM_PROGRAM`, which tells which extensions like marks there are.
+    term_program: Option<String>,
+    /// whether a Windows console on stdout took escape sequences once asked
+    /// to; `None` for anything but a Windows console.
+    console_escapes: Option<bool>,
+}
+
+impl TermInfo {
+    fn from_env() -> TermInfo {
+        let term = env::var("TERM").ok().filter(|t| !t.is_empty());
+        TermInfo {
+            terminfo_colors: term.as_deref().and_then(terminfo_max_colors),
+            term,
+            colorterm: env::var("COLORTERM").ok(),
+            no_color: env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty()),
+            stdout_tty: io::stdout().is_terminal(),
+            term_program: env::var("TERM_PROGRAM").ok().filter(|t| !t.is_empty()),
+            console_escapes: term::enable_escapes(),
+        }
+    }
+}
+
+/// how to set a mark in the scrollback that the terminal can jump to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MarkSequence {
+    /// iTerm2's own `SetMark`.
+    Iterm2,
+    /// FinalTerm's prompt mark, OSC 133, which the others took up.
+    FinalTerm,
+}
+
+impl MarkSequence {
+    fn detect(info: &TermInfo) -> Option<MarkSequence> {
+        match info.term_program.as_deref()? {
+            "iTerm.app" => Some(MarkSequence::Iterm2),
+            "WezTerm" | "vscode" | "ghostty" => Some(MarkSequence::FinalTerm),
+            _ => None,
+        }
+    }
+
+    fn osc(self) -> &'static str {
+        match self {
+            MarkSequence::Iterm2 => "\x1b]1337;SetMark\x07",
+            MarkSequence::FinalTerm => "\x1b]133;A\x07",
+        }
+    }
+}
+
+/// `--marks`: which lines get a jump mark, and how it is set.
+#[derive(Clone, Copy, Debug)]
+struct JumpMarks {
+    threshold: Level,
+    sequence: MarkSequence,
+}
+
+impl JumpMarks {
+    /// `line` as rendered, with the mark in front if its level qualifies.
+    fn apply(&self, line: &str, infer: &[Inference]) -> String {
+        match line_level(&visible_text(line), infer) {
+            Some(level) if level >= self.threshold => format!("{}{}", self.sequence.osc(), line),
+            _ => line.to_string(),
+        }
+    }
+}
+
+/// what the terminal itself can do, ignoring whether we should use it.
+fn terminal_capability(info: &TermInfo) -> (ColorDepth, String) {
+    let term = info.term.as_deref().unwrap_or("");
+    match info.console_escapes {
+        Some(false) => {
+            return (ColorDepth::None, "the console doesn't take escape sequences".to_string());
+        }
+        // the Windows 10 console does 24-bit color, and sets no TERM
+        Some(true) if term.is_empty() => {
+            return (
+                ColorDepth::TrueColor,
+                "a Windows console taking escape sequences".to_string(),
+            );
+        }
+        _ => {}
+    }
+    if term.is_empty() || term == "dumb" {
+        let shown = if term.is_empty() { "unset" } else { "dumb" };
+        return (ColorDepth::None, format!("TERM is {}", shown));
+    }
+    if let Some(ct) = info.colorterm.as_deref().filter(|ct| *ct == "truecolor" || *ct == "24bit") {
+        return (ColorDepth::TrueColor, format!("COLORTERM={}", ct));
+    }
+    match info.terminfo_colors {
+        Some(n) if n >= 0x100_0000 => {
+            (ColorDepth::TrueColor, format!("terminfo for {} advertises {} colors", term, n))
+        }
+        Some(n) if n >= 256 => {
+            (ColorDepth::Ansi256, format!("terminfo for {} advertises {} colors", term, n))
+        }
+        Some(n) if n >= 8 => {
+            (ColorDepth::Basic, format!("terminfo for {} advertises {} colors", term, n))
+        }
+        Some(n) if n > 0 => {
+            (ColorDepth::None, format!("terminfo for {} advertises only {} colors", term, n))
+        }
+        Some(_) => (ColorDepth::None, format!("terminfo for {} has no colors capability", term)),
+        None if term.contains("256color") => {
+            (ColorDepth::Ansi256, format!("no terminfo entry, TERM={} suggests 256 colors", term))
+        }
+        None => {
+            (ColorDepth::Basic, format!("no terminfo entry for TERM={}, assuming 8 colors", term))
+        }
+    }
+}
+
+fn decide_color_depth(mode: ColorMode, info: &TermInfo) -> (ColorDepth, String) {
+    match mode {
+        ColorMode::Never => (ColorDepth::None, "--color=never".to_string()),
+        ColorMode::Always => {
+            let (depth, why) = terminal_capability(info);
+            if depth == ColorDepth::None {
+                (ColorDepth::Basic, format!("--color=always ({}, using 8 colors)", why))
+            } else {
+                (depth, format!("--color=always, {}", why))
+            }
+        }
+        ColorMode::Auto if info.no_color => (ColorDepth::None, "NO_COLOR is set".to_string()),
+        ColorMode::Auto if !info.stdout_tty => {
+            (ColorDepth::None, "stdout is not a terminal".to_string())
+        }
+        ColorMode::Auto => terminal_capability(info),
+    }
+}
+
+/// locate the compiled terminfo entry for `term` the way ncurses does.
+fn terminfo_path(term: &str) -> Option<PathBuf> {
+    let first = term.chars().next()?;
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    if let Some(dir) = env::var_os("TERMINFO") {
+        dirs.push(dir.into());
+    }
+    if let Some(home) = env::var_os("HOME") {
+        dirs.push(PathBuf::from(home).join(".terminfo"));
+    }
+    if let Ok(list) = env::var("TERMINFO_DIRS") {
+        dirs.extend(list.split(':').filter(|d| !d.is_empty()).map(PathBuf::from));
+    }
+    dirs.extend(
+        ["/etc/terminfo", "/lib/terminfo", "/usr/share/terminfo", "/usr/lib/terminfo"]
+            .iter()
+            .map(PathBuf::from),
+    );
+    // linux uses the first letter as subdirectory, macOS its hex code.
+    let subdirs = [first.to_string(), format!("{:x}", u32::from(first))];
+    dirs.iter()
+        .flat_map(|d| subdirs.iter().map(move |s| d.join(s).join(term)))
+        .find(|p| p.is_file())
+}
+
+fn terminfo_max_colors(term: &str) -> Option<i32> {
+    let data = std::fs::read(terminfo_path(term)?).ok()?;
+    parse_terminfo_colors(&data)
+}
+
+/// pull the `colors` number out of a compiled terminfo entry (see term(5)).
+/// returns -1 when the entry exists but does not define it.
+fn parse_terminfo_colors(data: &[u8]) -> Option<i32> {
+    const MAX_COLORS: usize = 13;
+    let short = |i: usize| data.get(i..i + 2).map(|b| i16::from_le_bytes([b[0], b[1]]));
+    let num_size = match short(0)? {
+        0o432 => 2,
+        0o1036 => 4,
+        _ => return None,
+    };
+    let names = usize::try_from(short(2)?).ok()?;
+    let bools = usize::try_from(short(4)?).ok()?;
+    let nums = usize::try_from(short(6)?).ok()?;
+    if MAX_COLORS >= nums {
+        return Some(-1);
+    }
+    let mut offset = 12 + names + bools;
+    // the numbers section is aligned to an even offset.
+    offset += offset % 2;
+    let at = offset + MAX_COLORS * num_size;
+    let bytes = data.get(at..at + num_size)?;
+    Some(if num_size == 2 {
+        i32::from(i16::from_le_bytes([bytes[0], bytes[1]]))
+    } else {
+        i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Background {
+    Dark,
+    Light,
+}
+
+/// value of `--theme`. `Auto` asks the terminal what its background looks like.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ThemeChoice {
+    Auto,
+    Dark,
+    Light,
+    /// deuteranopia-safe, whatever the background.
+    Colorblind,
+    SolarizedDark,
+    /// attributes only, no colors.
+    Monochrome,
+}
+
+impl ThemeChoice {
+    pub fn parse(s: &str) -> Option<ThemeChoice> {
+        match s {
+            "auto" => Some(ThemeChoice::Auto),
+            "dark" => Some(ThemeChoice::Dark),
+            "light" => Some(ThemeChoice::Light),
+            "colorblind" => Some(ThemeChoice::Colorblind),
+            "solarized-dark" => Some(ThemeChoice::SolarizedDark),
+            "monochrome" => Some(ThemeChoice::Monochrome),
+            _ => None,
+        }
+    }
+}
+
+/// per-level looks from the config file, over the palette's.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Scheme {
+    /// indexed by `Level as usize`; `None` keeps the palette's.
+    levels: [Option<Style>; 8],
+}
+
+/// where the config file is looked for without `--config`:
+/// `$XDG_CONFIG_HOME/logcolor/config`, by default `~/.config/logcolor/config`.
+fn config_path(var: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let dir = match var("XDG_CONFIG_HOME").filter(|dir| Path::new(dir).is_absolute()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(var("HOME").filter(|home| !home.is_empty())?).join(".config"),
+    };
+    Some(dir.join(PROGRAM).join("config"))
+}
+
+fn read_scheme(path: &Path) -> Result<Scheme, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read config '{}': {}", path.display(), e))?;
+    parse_scheme(&text, &path.display().to_string())
+}
+
+/// a config file: one `LEVEL=STYLE` per line, like `WARN=bright_yellow`,
+/// `INFO=dim` or `ERROR=red,bold`, STYLE in the words of a `--rules` style.
+/// blank lines and `#` comments are skipped; levels not mentioned keep the
+/// palette's look. errors name `origin` and the line number.
+fn parse_scheme(text: &str, origin: &str) -> Result<Scheme, String> {
+    let mut scheme = Scheme::default();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |msg: String| format!("{}:{}: {}", origin, n + 1, msg);
+        let (name, spec) = line
+            .split_once('=')
+            .ok_or_else(|| err(format!("expected LEVEL=STYLE, got '{}'", line)))?;
+        let name = name.trim();
+        let level = Level::from_token(&name.to_uppercase())
+            .ok_or_else(|| err(format!("unknown level '{}'", name)))?;
+        if spec.trim().is_empty() {
+            return Err(err(format!("no style for {}", level.name())));
+        }
+        scheme.levels[level as usize] = Some(parse_style(spec).map_err(err)?);
+    }
+    Ok(scheme)
+}
+
+/// `--print-config`: the scheme in effect, in the config file's own format.
+fn scheme_text(styles: &Styles, origin: &str) -> String {
+    let mut text = format!("# {}\n# level tokens are drawn bold on top of this\n", origin);
+    for level in Level::ALL.into_iter().rev() {
+        let mut look = styles.level_style(level, BOLD);
+        look.attrs.retain(|&attr| attr != BOLD);
+        text.push_str(&format!("{}={}\n", level.name(), look.spec()));
+    }
+    text
+}
+
+/// `--print-theme`: the escape sequences written for each level, spelled out
+/// with `\e` for the escape character, and a sample of each.
+fn theme_text(styles: &Styles, origin: &str) -> String {
+    let shown = |sgr: &str| match sgr {
+        "" => "(none)".to_string(),
+        sgr => sgr.replace('\x1b', "\\e"),
+    };
+    let sample = |sgr: &str, text: &str| match sgr {
+        "" => text.to_string(),
+        sgr => format!("{}{}{}", sgr, text, RESET),
+    };
+    let mut text = format!("# {}\n# LEVEL token inferred line\n", origin);
+    for level in Level::ALL.into_iter().rev() {
+        let token = styles.level_style(level, BOLD).sgr(styles.depth);
+        let inferred = styles.level_style(level, UNDERLINE).sgr(styles.depth);
+        let line = styles.color(level);
+        text.push_str(&format!(
+            "{} {} {} {}  {} {} {}\n",
+            level.name(),
+            shown(&token),
+            shown(&inferred),
+            shown(line),
+            sample(&token, level.name()),
+            sample(&inferred, "inferred"),
+            sample(line, "line"),
+        ));
+    }
+    text.push_str(&format!("dim {}\nmuted {}\n", shown(&styles.dim), shown(&styles.muted)));
+    text
+}
+
+/// `COLORFGBG` is set by rxvt, konsole and friends as "fg;bg" (sometimes
+/// "fg;default;bg"). the last field is the background color index.
+fn background_from_colorfgbg(value: &str) -> Option<Background> {
+    let bg: u8 = value.rsplit(';').next()?.trim().parse().ok()?;
+    match bg {
+        0..=6 | 8 => Some(Background::Dark),
+        7 | 9..=15 => Some(Background::Light),
+        _ => None,
+    }
+}
+
+/// parse the reply to an OSC 11 query, e.g. `ESC ] 11 ; rgb:ffff/ffff/ffff ESC \`.
+/// each channel may have 1 to 4 hex digits; returns channels scaled to 0.0..=1.0.
+fn parse_osc11_response(buf: &[u8]) -> Option<(f64, f64, f64)> {
+    let text = String::from_utf8_lossy(buf);
+    let start = text.find("]11;")? + 4;
+    let rest = &text[start..];
+    let rest = rest.strip_prefix("rgba:").or_else(|| rest.strip_prefix("rgb:"))?;
+    let end = rest.find(['\x07', '\x1b']).unwrap_or(rest.len());
+    let mut channels = rest[..end].split('/').map(|hex| {
+        if hex.is_empty() || hex.len() > 4 {
+            return None;
+        }
+        let max = (1u32 << (4 * hex.len())) - 1;
+        u32::from_str_radix(hex, 16).ok().map(|v| f64::from(v) / f64::from(max))
+    });
+    let r = channels.next()??;
+    let g = channels.next()??;
+    let b = channels.next()??;
+    Some((r, g, b))
+}
+
+fn background_from_rgb((r, g, b): (f64, f64, f64)) -> Background {
+    // Rec. 709 relative luminance; good enough to tell white from black.
+    let luminance = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    if luminance > 0.5 { Background::Light } else { Background::Dark }
+}
+
+/// how long we give the terminal to answer the background query.
+const OSC_QUERY_TIMEOUT: Duration = Duration::from_millis(150);
+
+#[cfg(unix)]
+mod term {
+    use std::fs::{File, OpenOptions};
+    use std::io::{Read, Write};
+    use std::os::raw::{c_int, c_short, c_ulong, c_void};
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::time::{Duration, Instant};
+
+    #[repr(C)]
+    struct PollFd {
+        fd: c_int,
+        events: c_short,
+        revents: c_short,
+    }
+
+    #[cfg(target_os = "linux")]
+    type NfdsT = std::os::raw::c_ulong;
+    #[cfg(not(target_os = "linux"))]
+    type NfdsT = std::os::raw::c_uint;
+
+    const POLLIN: c_short = 1;
+    const TCSANOW: c_int = 0;
+    #[cfg(target_os = "linux")]
+    const TIOCGWINSZ: c_ulong = 0x5413;
+    #[cfg(not(target_os = "linux"))]
+    const TIOCGWINSZ: c_ulong = 0x4008_7468;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const SIGUSR1: c_int = 10;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const SIGUSR1: c_int = 30;
+    const SIGWINCH: c_int = 28;
+    const SIGHUP: c_int = 1;
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+    const SIG_ERR: usize = usize::MAX;
+
+    extern "C" {
+        fn pipe(fds: *mut c_int) -> c_int;
+        fn write(fd: c_int, buf: *const c_void, count: usize) -> isize;
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+        fn localtime_r(time: *const i64, tm: *mut c_void) -> *mut c_void;
+        fn tcgetattr(fd: c_int, termios: *mut c_void) -> c_int;
+        fn tcsetattr(fd: c_int, action: c_int, termios: *const c_void) -> c_int;
+        fn cfmakeraw(termios: *mut c_void);
+        fn poll(fds: *mut PollFd, nfds: NfdsT, timeout: c_int) -> c_int;
+        fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+        fn fcntl(fd: c_int, cmd: c_int, ...) -> c_int;
+    }
+
+    const F_GETFD: c_int = 1;
+
+    /// descriptor `fd` as a file, if it is open.
+    pub fn fd_file(fd: c_int) -> Option<File> {
+        // SAFETY: F_GETFD only asks whether the descriptor is open.
+        let open = fd > 2 && unsafe { fcntl(fd, F_GETFD) } != -1;
+        // SAFETY: the descriptor was handed to us to write to, and nothing
+        // else in the process uses it.
+        open.then(|| unsafe { File::from_raw_fd(fd) })
+    }
+
+    /// terminals here take escape sequences as they come.
+    pub fn enable_escapes() -> Option<bool> {
+        None
+    }
+
+    /// width of the terminal on stdout, if it is one.
+    pub fn columns() -> Option<usize> {
+        // struct winsize: rows, columns, then pixel sizes nobody fills in
+        let mut winsize = [0u16; 4];
+        // SAFETY: TIOCGWINSZ writes one struct winsize, four shorts.
+        let ok = unsafe { ioctl(1, TIOCGWINSZ, winsize.as_mut_ptr()) } == 0;
+        (ok && winsize[1] > 0).then_some(usize::from(winsize[1]))
+    }
+
+    /// write ends of the pipes the SIGUSR1 and SIGWINCH handlers poke.
+    static SIGNAL_PIPE: AtomicI32 = AtomicI32::new(-1);
+    static WINCH_PIPE: AtomicI32 = AtomicI32::new(-1);
+    static INTERRUPT_PIPE: AtomicI32 = AtomicI32::new(-1);
+    static HANGUP_PIPE: AtomicI32 = AtomicI32::new(-1);
+
+    fn poke(pipe: &AtomicI32) {
+        let fd = pipe.load(Ordering::Relaxed);
+        if fd >= 0 {
+            // SAFETY: write(2) is async-signal-safe; a full pipe just drops the poke.
+            unsafe {
+                write(fd, [1u8].as_ptr().cast(), 1);
+            }
+        }
+    }
+
+    extern "C" fn poke_signal_pipe(_signum: c_int) {
+        poke(&SIGNAL_PIPE);
+    }
+
+    extern "C" fn poke_winch_pipe(_signum: c_int) {
+        poke(&WINCH_PIPE);
+    }
+
+    extern "C" fn poke_interrupt_pipe(_signum: c_int) {
+        poke(&INTERRUPT_PIPE);
+    }
+
+    extern "C" fn poke_hangup_pipe(_signum: c_int) {
+        poke(&HANGUP_PIPE);
+    }
+
+    /// call `f` on a thread of its own each time the process gets SIGUSR1.
+    pub fn on_sigusr1(f: impl FnMut() + Send + 'static) -> bool {
+        on_signal(SIGUSR1, &SIGNAL_PIPE, poke_signal_pipe, f)
+    }
+
+    /// call `f` on a thread of its own each time the terminal is resized.
+    pub fn on_sigwinch(f: impl FnMut() + Send + 'static) -> bool {
+        on_signal(SIGWINCH, &WINCH_PIPE, poke_winch_pipe, f)
+    }
+
+    /// call `f` on a thread of its own each time the process gets SIGHUP.
+    pub fn on_sighup(f: impl FnMut() + Send + 'static) -> bool {
+        on_signal(SIGHUP, &HANGUP_PIPE, poke_hangup_pipe, f)
+    }
+
+    /// call `f` on a thread of its own on SIGINT or SIGTERM, instead of dying.
+    pub fn on_interrupt(f: impl FnMut() + Send + 'static) -> bool {
+        // SAFETY: the handler is async-signal-safe, see above.
+        on_signal(SIGINT, &INTERRUPT_PIPE, poke_interrupt_pipe, f)
+            && unsafe { signal(SIGTERM, poke_interrupt_pipe) != SIG_ERR }
+    }
+
+    /// `handler` only writes to the pipe in `poked`; the thread does the work.
+    fn on_signal(
+        signum: c_int,
+        poked: &'static AtomicI32,
+        handler: extern "C" fn(c_int),
+        mut f: impl FnMut() + Send + 'static,
+    ) -> bool {
+        let mut fds = [0 as c_int; 2];
+        // SAFETY: pipe(2) fills in two descriptors.
+        if unsafe { pipe(fds.as_mut_ptr()) } != 0 {
+            return false;
+        }
+        poked.store(fds[1], Ordering::Relaxed);
+        // SAFETY: the read end is ours alone from here on.
+        let mut pokes = unsafe { File::from_raw_fd(fds[0]) };
+        std::thread::spawn(move || {
+            let mut byte = [0u8; 1];
+            while let Ok(1) = pokes.read(&mut byte) {
+                f();
+            }
+        });
+        // SAFETY: the handlers are async-signal-safe, see above.
+        unsafe { signal(signum, handler) != SIG_ERR }
+    }
+
+    /// hour, minute and second of `secs` since the epoch in local time.
+    pub fn local_time_of_day(secs: i64) -> Option<(u32, u32, u32)> {
+        // struct tm starts with tm_sec, tm_min, tm_hour; the rest is room
+        let mut tm = [0 as c_int; 16];
+        // SAFETY: time_t is 64 bits on the platforms we build for, and the
+        // buffer is larger than any struct tm.
+        let ok = unsafe { !localtime_r(&secs, tm.as_mut_ptr().cast()).is_null() };
+        ok.then(|| (tm[2] as u32, tm[1] as u32, tm[0] as u32))
+    }
+
+    /// opaque storage big enough for `struct termios` on every unix we care
+    /// about; `cfmakeraw` does the editing for us, except for `lflag` below.
+    type TermiosBuf = [u64; 64];
+
+    #[cfg(target_vendor = "apple")]
+    type TcFlag = c_ulong;
+    #[cfg(not(target_vendor = "apple"))]
+    type TcFlag = u32;
+
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const KEY_MODE_CLEARS: TcFlag = 0o1 | 0o2 | 0o10; // ISIG | ICANON | ECHO
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const KEY_MODE_CLEARS: TcFlag = 0x80 | 0x100 | 0x8; // ISIG | ICANON | ECHO
+
+    /// `c_lflag`, the fourth `tcflag_t` at the start of every struct termios.
+    fn lflag(termios: &mut TermiosBuf) -> &mut TcFlag {
+        // SAFETY: in bounds and aligned, the buffer is u64s.
+        unsafe { &mut *termios.as_mut_ptr().cast::<TcFlag>().add(3) }
+    }
+
+    /// puts the terminal in raw mode for as long as it is alive.
+    struct RawMode {
+        fd: c_int,
+        saved: TermiosBuf,
+    }
+
+    impl RawMode {
+        fn enable(tty: &File) -> Option<RawMode> {
+            let fd = tty.as_raw_fd();
+            let mut saved: TermiosBuf = [0; 64];
+            // SAFETY: the buffer is larger than any platform's struct termios.
+            if unsafe { tcgetattr(fd, saved.as_mut_ptr().cast()) } != 0 {
+                return None;
+            }
+            let mut raw = saved;
+            // SAFETY: `raw` holds a termios filled in by tcgetattr above.
+            let ok = unsafe {
+                cfmakeraw(raw.as_mut_ptr().cast());
+                tcsetattr(fd, TCSANOW, raw.as_ptr().cast()) == 0
+            };
+            ok.then_some(RawMode { fd, saved })
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: restoring the exact bytes tcgetattr gave us.
+            unsafe {
+                tcsetattr(self.fd, TCSANOW, self.saved.as_ptr().cast());
+            }
+        }
+    }
+
+    /// keeps the terminal reading single keys, unechoed, until dropped.
+    pub struct KeyReader {
+        _mode: RawMode,
+    }
+
+    /// feed each key pressed on /dev/tty to `on_key` from a thread of its own,
+    /// which keeps working when stdin is the log stream. output processing is
+    /// left alone; signals are off so that ^C and ^\ come through as bytes,
+    /// and end the program with the terminal put back first.
+    pub fn read_keys(mut on_key: impl FnMut(u8) + Send + 'static) -> Option<KeyReader> {
+        let mut tty = File::open("/dev/tty").ok()?;
+        let fd = tty.as_raw_fd();
+        let mut saved: TermiosBuf = [0; 64];
+        // SAFETY: the buffer is larger than any platform's struct termios.
+        if unsafe { tcgetattr(fd, saved.as_mut_ptr().cast()) } != 0 {
+            return None;
+        }
+        let mut keys = saved;
+        *lflag(&mut keys) &= !KEY_MODE_CLEARS;
+        // SAFETY: `keys` holds a termios filled in by tcgetattr above.
+        if unsafe { tcsetattr(fd, TCSANOW, keys.as_ptr().cast()) } != 0 {
+            return None;
+        }
+        std::thread::spawn(move || {
+            let mut byte = [0u8; 1];
+            while let Ok(1) = tty.read(&mut byte) {
+                if matches!(byte[0], 0x03 | 0x1c) {
+                    drop(RawMode { fd, saved });
+                    super::end_interrupted();
+                    std::process::exit(130);
+                }
+                on_key(byte[0]);
+            }
+        });
+        Some(KeyReader { _mode: RawMode { fd, saved } })
+    }
+
+    #[cfg(target_os = "linux")]
+    extern "C" {
+        fn inotify_init1(flags: c_int) -> c_int;
+        fn inotify_add_watch(fd: c_int, path: *const std::os::raw::c_char, mask: u32) -> c_int;
+    }
+
+    #[cfg(target_os = "linux")]
+    const IN_CLOEXEC: c_int = 0o2_000_000;
+    /// IN_MODIFY, IN_ATTRIB, IN_CLOSE_WRITE, IN_MOVED_FROM, IN_MOVED_TO,
+    /// IN_CREATE, IN_DELETE, IN_DELETE_SELF and IN_MOVE_SELF.
+    #[cfg(target_os = "linux")]
+    const DIR_CHANGES: u32 = 0x2 | 0x4 | 0x8 | 0x40 | 0x80 | 0x100 | 0x200 | 0x400 | 0x800;
+
+    /// an inotify watch on a directory, which hears of its files being
+    /// written to, created, moved or removed.
+    pub struct DirNotify {
+        #[cfg(target_os = "linux")]
+        events: File,
+    }
+
+    impl DirNotify {
+        /// `None` where there is no inotify, or it can't watch `dir`.
+        #[cfg(target_os = "linux")]
+        pub fn new(dir: &std::path::Path) -> Option<DirNotify> {
+            use std::os::unix::ffi::OsStrExt;
+            let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+            // SAFETY: inotify_init1 takes flags only and returns a new descriptor.
+            let fd = unsafe { inotify_init1(IN_CLOEXEC) };
+            if fd < 0 {
+                return None;
+            }
+            // SAFETY: the descriptor is ours alone from here on.
+            let events = unsafe { File::from_raw_fd(fd) };
+            // SAFETY: `path` is NUL-terminated and outlives the call.
+            let watch = unsafe { inotify_add_watch(fd, path.as_ptr(), DIR_CHANGES) };
+            (watch >= 0).then_some(DirNotify { events })
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        pub fn new(_dir: &std::path::Path) -> Option<DirNotify> {
+            None
+        }
+
+        /// wait up to `timeout` for a change; says whether there was one.
+        /// the events themselves are read and thrown away, what changed
+        /// is for the caller to look at.
+        #[cfg(target_os = "linux")]
+        pub fn wait(&mut self, timeout: Duration) -> bool {
+            if !wait_readable(self.events.as_raw_fd(), timeout) {
+                return false;
+            }
+            let mut events = [0u8; 4096];
+            let _ = self.events.read(&mut events);
+            true
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        pub fn wait(&mut self, timeout: Duration) -> bool {
+            std::thread::sleep(timeout);
+            false
+        }
+    }
+
+    /// wait up to `timeout` for `fd` to become readable.
+    fn wait_readable(fd: c_int, timeout: Duration) -> bool {
+        let mut pfd = PollFd { fd, events: POLLIN, revents: 0 };
+        let ms = c_int::try_from(timeout.as_millis()).unwrap_or(c_int::MAX);
+        // SAFETY: a single valid pollfd.
+        unsafe { poll(&mut pfd, 1, ms) > 0 && pfd.revents & POLLIN != 0 }
+    }
+
+    /// a primary device attributes reply looks like `ESC [ ? 6 4 ; 1 c`.
+    fn ends_with_da1_reply(buf: &[u8]) -> bool {
+        let Some(start) = buf.windows(3).rposition(|w| w == b"\x1b[?") else {
+            return false;
+        };
+        buf[start + 3..].ends_with(b"c")
+    }
+
+    /// send `query` followed by a DA1 request and collect the answer.
+    ///
+    /// every terminal answers DA1, so its reply acts as a sentinel: once it
+    /// arrives we know whether the real query was understood, without sitting
+    /// on the timeout and without reading past it into whatever the user types.
+    pub fn query(query: &str, timeout: Duration) -> Option<Vec<u8>> {
+        let mut tty = OpenOptions::new().read(true).write(true).open("/dev/tty").ok()?;
+        let _raw = RawMode::enable(&tty)?;
+        tty.write_all(query.as_bytes()).ok()?;
+        tty.write_all(b"\x1b[c").ok()?;
+        tty.flush().ok()?;
+
+        let deadline = Instant::now() + timeout;
+        let fd = tty.as_raw_fd();
+        let mut reply = Vec::new();
+        let mut byte = [0u8; 1];
+        while !ends_with_da1_reply(&reply) {
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() || !wait_readable(fd, left) {
+                break;
+            }
+            match tty.read(&mut byte) {
+                Ok(1) => reply.push(byte[0]),
+                _ => break,
+            }
+        }
+        Some(reply)
+    }
+}
+
+#[cfg(not(unix))]
+mod term {
+    #[cfg(windows)]
+    use std::fs::{File, OpenOptions};
+    #[cfg(windows)]
+    use std::io::Read;
+    #[cfg(windows)]
+    use std::os::windows::io::{AsRawHandle, RawHandle};
+    use std::time::Duration;
+
+    /// BY_HANDLE_FILE_INFORMATION.
+    #[cfg(windows)]
+    #[repr(C)]
+    #[derive(Default)]
+    struct FileInformation {
+        attributes: u32,
+        /// creation, last access and last write, as FILETIMEs.
+        times: [u32; 6],
+        volume_serial: u32,
+        size_high: u32,
+        size_low: u32,
+        links: u32,
+        index_high: u32,
+        index_low: u32,
+    }
+
+    /// CONSOLE_SCREEN_BUFFER_INFO.
+    #[cfg(windows)]
+    #[repr(C)]
+    #[derive(Default)]
+    struct ScreenBufferInfo {
+        size: [i16; 2],
+        cursor: [i16; 2],
+        attributes: u16,
+        /// left, top, right and bottom, inclusive.
+        window: [i16; 4],
+        max_window: [i16; 2],
+    }
+
+    #[cfg(windows)]
+    extern "system" {
+        fn GetFileInformationByHandle(file: RawHandle, info: *mut FileInformation) -> i32;
+        fn GetConsoleMode(console: RawHandle, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: RawHandle, mode: u32) -> i32;
+        fn GetConsoleScreenBufferInfo(console: RawHandle, info: *mut ScreenBufferInfo) -> i32;
+    }
+
+    /// ENABLE_PROCESSED_INPUT, ENABLE_LINE_INPUT and ENABLE_ECHO_INPUT: off,
+    /// keys come one at a time, unechoed, ^C as a byte.
+    #[cfg(windows)]
+    const KEY_MODE_CLEARS: u32 = 0x1 | 0x2 | 0x4;
+
+    #[cfg(windows)]
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x4;
+
+    pub fn query(_query: &str, _timeout: Duration) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// turn on escape sequence processing for the consoles on stdout and
+    /// stderr; whether stdout's took it, or `None` when it isn't a console.
+    /// consoles older than Windows 10 don't.
+    #[cfg(windows)]
+    pub fn enable_escapes() -> Option<bool> {
+        let enable = |handle: RawHandle| {
+            let mut mode = 0;
+            // SAFETY: fills in one DWORD, or fails for anything but a console.
+            if unsafe { GetConsoleMode(handle, &mut mode) } == 0 {
+                return None;
+            }
+            let on = mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING;
+            // SAFETY: as above; a mode made from the one it returned.
+            Some(on == mode || unsafe { SetConsoleMode(handle, on) } != 0)
+        };
+        enable(std::io::stderr().as_raw_handle());
+        enable(std::io::stdout().as_raw_handle())
+    }
+
+    #[cfg(not(windows))]
+    pub fn enable_escapes() -> Option<bool> {
+        None
+    }
+
+    /// width of the console window on stdout, if it is one.
+    #[cfg(windows)]
+    pub fn columns() -> Option<usize> {
+        let mut info = ScreenBufferInfo::default();
+        // SAFETY: fills in one CONSOLE_SCREEN_BUFFER_INFO, or fails for
+        // anything but a console.
+        let ok =
+            unsafe { GetConsoleScreenBufferInfo(std::io::stdout().as_raw_handle(), &mut info) };
+        let cols = i32::from(info.window[2]) - i32::from(info.window[0]) + 1;
+        usize::try_from(cols).ok().filter(|&cols| ok != 0 && cols > 0)
+    }
+
+    #[cfg(not(windows))]
+    pub fn columns() -> Option<usize> {
+        None
+    }
+
+    /// the volume serial number and file index of `file`, which between
+    /// them say which file it is the way a device and inode do.
+    #[cfg(windows)]
+    pub fn file_id(file: &File) -> Option<(u64, u64)> {
+        let mut info = FileInformation::default();
+        // SAFETY: fills in one BY_HANDLE_FILE_INFORMATION for a handle we hold.
+        let ok = unsafe { GetFileInformationByHandle(file.as_raw_handle(), &mut info) } != 0;
+        let index = u64::from(info.index_high) << 32 | u64::from(info.index_low);
+        ok.then_some((u64::from(info.volume_serial), index))
+    }
+
+    #[cfg(not(windows))]
+    pub fn file_id(_file: &std::fs::File) -> Option<(u64, u64)> {
+        None
+    }
+
+    pub fn local_time_of_day(_secs: i64) -> Option<(u32, u32, u32)> {
+        None
+    }
+
+    pub fn fd_file(_fd: i32) -> Option<std::fs::File> {
+        None
+    }
+
+    /// keeps the console reading single keys, unechoed, until dropped.
+    #[cfg(windows)]
+    pub struct KeyReader {
+        console: File,
+        saved: u32,
+    }
+
+    #[cfg(windows)]
+    impl Drop for KeyReader {
+        fn drop(&mut self) {
+            // SAFETY: puts back the mode GetConsoleMode gave for this handle.
+            unsafe {
+                SetConsoleMode(self.console.as_raw_handle(), self.saved);
+            }
+        }
+    }
+
+    #[cfg(not(windows))]
+    pub struct KeyReader;
+
+    pub fn on_sigusr1(_f: impl FnMut() + Send + 'static) -> bool {
+        false
+    }
+
+    pub fn on_sigwinch(_f: impl FnMut() + Send + 'static) -> bool {
+        false
+    }
+
+    pub fn on_interrupt(_f: impl FnMut() + Send + 'static) -> bool {
+        false
+    }
+
+    pub fn on_sighup(_f: impl FnMut() + Send + 'static) -> bool {
+        false
+    }
+
+    /// feed each key pressed on the console to `on_key` from a thread of its
+    /// own, read from `CONIN$` so it keeps working when stdin is the log
+    /// stream. ^C and ^\ come through as bytes, and end the program with
+    /// the console put back first.
+    #[cfg(windows)]
+    pub fn read_keys(mut on_key: impl FnMut(u8) + Send + 'static) -> Option<KeyReader> {
+        let console = OpenOptions::new().read(true).write(true).open("CONIN$").ok()?;
+        let mut saved = 0;
+        // SAFETY: fills in one DWORD for a console handle we hold.
+        if unsafe { GetConsoleMode(console.as_raw_handle(), &mut saved) } == 0 {
+            return None;
+        }
+        // SAFETY: as above; a mode made from the one it returned.
+        if unsafe { SetConsoleMode(console.as_raw_handle(), saved & !KEY_MODE_CLEARS) } == 0 {
+            return None;
+        }
+        let reader = KeyReader { console, saved };
+        let mut keys = reader.console.try_clone().ok()?;
+        let restore = reader.console.try_clone().ok()?;
+        std::thread::spawn(move || {
+            let mut byte = [0u8; 1];
+            while let Ok(1) = keys.read(&mut byte) {
+                if matches!(byte[0], 0x03 | 0x1c) {
+                    drop(KeyReader { console: restore, saved });
+                    super::end_interrupted();
+                    std::process::exit(130);
+                }
+                on_key(byte[0]);
+            }
+        });
+        Some(reader)
+    }
+
+    #[cfg(not(windows))]
+    pub fn read_keys(_on_key: impl FnMut(u8) + Send + 'static) -> Option<KeyReader> {
+        None
+    }
+
+    pub struct DirNotify;
+
+    impl DirNotify {
+        pub fn new(_dir: &std::path::Path) -> Option<DirNotify> {
+            None
+        }
+
+        pub fn wait(&mut self, timeout: Duration) -> bool {
+            std::thread::sleep(timeout);
+            false
+        }
+    }
+}
+
+fn detect_background() -> (Option<Background>, String) {
+    if let Ok(value) = env::var("COLORFGBG") {
+        if let Some(bg) = background_from_colorfgbg(&value) {
+            return (Some(bg), format!("COLORFGBG={}", value));
+        }
+    }
+    // only bother the terminal when we are actually drawing on it.
+    if !io::stdout().is_terminal() {
+        return (None, "stdout is not a terminal, not querying".to_string());
+    }
+    match term::query("\x1b]11;?\x1b\\", OSC_QUERY_TIMEOUT) {
+        None => (None, "could not query /dev/tty".to_string()),
+        Some(reply) => match parse_osc11_response(&reply) {
+            Some(rgb) => (Some(background_from_rgb(rgb)), "OSC 11 reply".to_string()),
+            None => (None, "terminal did not answer OSC 11".to_string()),
+        },
+    }
+}
+
+/// `probe` is false when nothing will be colored, so there is no point in
+/// talking to the terminal.
+fn select_palette(choice: ThemeChoice, probe: bool) -> (&'static Palette, String) {
+    let (background, why) = match choice {
+        ThemeChoice::Colorblind => {
+            return (&COLORBLIND_PALETTE, "colorblind (--theme)".to_string());
+        }
+        ThemeChoice::SolarizedDark => {
+            return (&SOLARIZED_DARK_PALETTE, "solarized-dark (--theme)".to_string());
+        }
+        ThemeChoice::Monochrome => {
+            return (&MONOCHROME_PALETTE, "monochrome (--theme)".to_string());
+        }
+        ThemeChoice::Dark => (Background::Dark, "--theme dark".to_string()),
+        ThemeChoice::Light => (Background::Light, "--theme light".to_string()),
+        ThemeChoice::Auto if !probe => (Background::Dark, "color disabled".to_string()),
+        ThemeChoice::Auto => match detect_background() {
+            (Some(bg), why) => (bg, why),
+            (None, why) => (Background::Dark, format!("{}, assuming dark", why)),
+        },
+    };
+    match background {
+        Background::Dark => (&DARK_PALETTE, format!("dark ({})", why)),
+        Background::Light => (&LIGHT_PALETTE, format!("light ({})", why)),
+    }
+}
+
+/// where a level word may appear to count as a level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TokenMatch {
+    /// standing alone, or with `--loose` anywhere, even inside another word
+    /// (the historical behavior).
+    Loose,
+    /// only standing alone; for words short or common enough to occur
+    /// inside ordinary text (`INF` in `INFINITY`).
+    Word,
+    /// only as `WORD:` at the very start of the line, the way JUL's
+    /// SimpleFormatter prints them (`FINE` would be everywhere otherwise).
+    LineStart,
+    /// only as a standalone `WORD:` label anywhere in the line, the way
+    /// PostgreSQL prints them (`LOG` is too common a word otherwise).
+    Label,
+    /// only as `[WORD]`, MySQL style.
+    Bracketed,
+}
+
+/// the level words we look for, longer spellings first so `WARNING` wins over
+/// `WARN`.
+const LEVEL_TOKENS: &[(&str, TokenMatch)] = &[
+    ("FATAL", TokenMatch::Loose),
+    ("ERROR", TokenMatch::Loose),
+    ("ERR", TokenMatch::Loose),
+    ("WARNING", TokenMatch::Loose),
+    ("WARN", TokenMatch::Loose),
+    ("INFO", TokenMatch::Loose),
+    ("DEBUG", TokenMatch::Loose),
+    ("TRACE", TokenMatch::Loose),
+    // npm/winston
+    ("VERBOSE", TokenMatch::Word),
+    ("SILLY", TokenMatch::Word),
+    ("HTTP", TokenMatch::Word),
+    // Serilog's `{Level:u3}` abbreviations
+    ("FTL", TokenMatch::Word),
+    ("WRN", TokenMatch::Word),
+    ("INF", TokenMatch::Word),
+    ("DBG", TokenMatch::Word),
+    ("VRB", TokenMatch::Word),
+    // java.util.logging
+    ("SEVERE", TokenMatch::LineStart),
+    ("CONFIG", TokenMatch::LineStart),
+    ("FINEST", TokenMatch::LineStart),
+    ("FINER", TokenMatch::LineStart),
+    ("FINE", TokenMatch::LineStart),
+    // PostgreSQL
+    ("PANIC", TokenMatch::Word),
+    ("LOG", TokenMatch::Label),
+    // MySQL/MariaDB
+    ("NOTE", TokenMatch::Bracketed),
+    ("SYSTEM", TokenMatch::Bracketed),
+    // syslog's notice, Python's critical
+    ("NOTICE", TokenMatch::Word),
+    ("CRITICAL", TokenMatch::Word),
+];
+
+/// one word to read a level from: a `LEVEL_TOKENS` one, or a
+/// `--level-word WORD=LEVEL`.
+#[doc(hidden)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LevelWord {
+    /// uppercase.
+    pub word: String,
+    level: Level,
+    how: TokenMatch,
+}
+
+impl LevelWord {
+    pub fn parse(spec: &str) -> Result<LevelWord, String> {
+        let bad = || format!("expected WORD=LEVEL for '--level-word', got '{}'", spec);
+        let (word, level) = spec.split_once('=').ok_or_else(bad)?;
+        let word = word.trim().to_ascii_uppercase();
+        let level = Level::from_token(&level.trim().to_uppercase()).ok_or_else(|| {
+            format!("unknown level '{}' in '--level-word {}'", level.trim(), spec)
+        })?;
+        if word.is_empty() || !word.bytes().all(is_word_byte) {
+            return Err(format!("'--level-word {}': a word is letters, digits and _", spec));
+        }
+        // standing alone, so `CRIT` stays out of `CRITICAL`
+        Ok(LevelWord { word, level, how: TokenMatch::Word })
+    }
+}
+
+/// the words detection looks for, and the bytes they can start with.
+#[derive(Clone, Debug)]
+struct LevelWords {
+    /// longer spellings first, so `WARNING` wins over `WARN`.
+    words: Vec<LevelWord>,
+    starts: [bool; 256],
+    /// indices into `words` by their first byte, in the same order.
+    by_first: Vec<Vec<usize>>,
+    /// `--loose`: the `TokenMatch::Loose` words count inside other words.
+    loose: bool,
+    /// `--strict-prefix`: only words ending within this many characters.
+    prefix: Option<usize>,
+}
+
+impl LevelWords {
+    /// `LEVEL_TOKENS` and `extra` on top; an extra word that is also a
+    /// built-in one takes its place with the level given.
+    fn new(extra: &[LevelWord], loose: bool) -> LevelWords {
+        let table = LEVEL_TOKENS.iter().map(|&(word, how)| LevelWord {
+            word: word.to_string(),
+            // every token in the table is one `from_token` knows
+            level: Level::from_token(word).unwrap_or(Level::Info),
+            how,
+        });
+        let table = table.filter(|w| !extra.iter().any(|e| e.word == w.word));
+        let mut words: Vec<LevelWord> = extra.iter().cloned().chain(table).collect();
+        words.sort_by_key(|w| std::cmp::Reverse(w.word.len()));
+        let mut starts = LEVEL_STARTS;
+        for w in extra {
+            let first = w.word.as_bytes()[0];
+            starts[usize::from(first.to_ascii_uppercase())] = true;
+            starts[usize::from(first.to_ascii_lowercase())] = true;
+        }
+        let mut by_first = vec![Vec::new(); 256];
+        for (i, w) in words.iter().enumerate() {
+            by_first[usize::from(w.word.as_bytes()[0])].push(i);
+        }
+        LevelWords { words, starts, by_first, loose, prefix: None }
+    }
+}
+
+/// the session's level words, set from `--level-word` before the first
+/// line is read; shared by everything that looks for a level, which is
+/// more places than could each be handed a table.
+static LEVEL_WORDS: std::sync::OnceLock<LevelWords> = std::sync::OnceLock::new();
+
+fn level_words() -> &'static LevelWords {
+    LEVEL_WORDS.get_or_init(|| LevelWords::new(&[], false))
+}
+
+/// the level a token `find_level` returned stands for, its brackets and
+/// colon trimmed and uppercased; a `--level-word` one included.
+fn token_level(raw: &str) -> Option<Level> {
+    level_words().level(raw)
+}
+
+impl LevelWords {
+    fn level(&self, raw: &str) -> Option<Level> {
+        let word = self.words.iter().find(|w| w.word == raw);
+        word.map(|w| w.level).or_else(|| Level::from_token(raw))
+    }
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// the bytes a `LEVEL_TOKENS` token can start with, in either case. made
+/// from the table, so it changes along with it.
+const LEVEL_STARTS: [bool; 256] = level_starts(LEVEL_TOKENS);
+
+const fn level_starts(tokens: &[(&str, TokenMatch)]) -> [bool; 256] {
+    let mut starts = [false; 256];
+    let mut i = 0;
+    while i < tokens.len() {
+        let first = tokens[i].0.as_bytes()[0];
+        starts[first.to_ascii_uppercase() as usize] = true;
+        starts[first.to_ascii_lowercase() as usize] = true;
+        i += 1;
+    }
+    starts
+}
+
+/// attempt to detect a level token in the line.
+/// common patterns: "[ERROR]", "ERROR:", "error", "ERR", etc.
+/// returns (index_of_token_start, token_string) if found
+///
+/// lines without a single byte any token starts with are passed over after
+/// one scan. benchmark: 1M such lines (57 MB, `--color always`, output to
+/// a file) took 33.0s before and 1.4s after, byte-identical.
+fn find_level(line: &str) -> Option<(usize, &str)> {
+    level_words().find(line)
+}
+
+impl LevelWords {
+    fn find<'a>(&self, line: &'a str) -> Option<(usize, &'a str)> {
+        if !line.bytes().any(|b| self.starts[usize::from(b)]) {
+            return None;
+        }
+        self.scan(line)
+    }
+
+    fn scan<'a>(&self, line: &'a str) -> Option<(usize, &'a str)> {
+        let candidates = self.candidates(line);
+        best_candidate(&candidates).map(|c| (c.pos, c.token))
+    }
+}
+
+/// what makes one level word in a line a likelier level than another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Evidence {
+    /// the value of a JSON line's `"level"`, or another of `JSON_LEVEL_KEYS`.
+    JsonKey,
+    /// the value of `level=`, or another of `JSON_LEVEL_KEYS`, logfmt style.
+    KeyValue,
+    /// `[ERROR]`, `(ERROR)`, or closing a group, as in `[12:03:04 WRN]`.
+    Bracketed,
+    /// `ERROR:`, or `ERROR -`.
+    Labeled,
+    /// among the first few words.
+    LineStart,
+    /// right next to the line's timestamp.
+    Timestamp,
+    /// within the first `LEVEL_START_BYTES` bytes.
+    Early,
+    Uppercase,
+    /// not glued to the letters around it.
+    WholeWord,
+}
+
+impl Evidence {
+    const ALL: [Evidence; 9] = [
+        Evidence::JsonKey,
+        Evidence::KeyValue,
+        Evidence::Bracketed,
+        Evidence::Labeled,
+        Evidence::LineStart,
+        Evidence::Timestamp,
+        Evidence::Early,
+        Evidence::Uppercase,
+        Evidence::WholeWord,
+    ];
+
+    fn points(self) -> u32 {
+        match self {
+            // the record says so, whatever its message says
+            Evidence::JsonKey | Evidence::KeyValue => 10,
+            Evidence::Bracketed => 3,
+            Evidence::Labeled | Evidence::LineStart | Evidence::Timestamp => 2,
+            Evidence::Early | Evidence::Uppercase | Evidence::WholeWord => 1,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Evidence::JsonKey => "json level key",
+            Evidence::KeyValue => "level=",
+            Evidence::Bracketed => "bracketed",
+            Evidence::Labeled => "colon",
+            Evidence::LineStart => "line start",
+            Evidence::Timestamp => "after timestamp",
+            Evidence::Early => "early",
+            Evidence::Uppercase => "all caps",
+            Evidence::WholeWord => "whole word",
+        }
+    }
+}
+
+/// how many words may come before a level word for it to count as being at
+/// the start of the line: a timestamp, a thread and a logger, say.
+const LEVEL_START_WORDS: usize = 3;
+
+/// how far into a line a level word counts as early, rather than buried in
+/// the message.
+const LEVEL_START_BYTES: usize = 40;
+
+/// one place in a line a level word could be read from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct LevelCandidate<'a> {
+    pos: usize,
+    /// the word with the brackets or colon around it.
+    token: &'a str,
+    level: Level,
+    /// a bit for each `Evidence`.
+    evidence: u16,
+}
+
+impl LevelCandidate<'_> {
+    fn has(&self, evidence: Evidence) -> bool {
+        self.evidence & (1 << evidence as u16) != 0
+    }
+
+    fn score(&self) -> u32 {
+        Evidence::ALL.iter().filter(|&&e| self.has(e)).map(|e| e.points()).sum()
+    }
+
+    /// `ERROR at 5: 7 (bracketed 3, all caps 1, ...)`, for `--explain`.
+    fn explain(&self) -> String {
+        let found = Evidence::ALL.iter().filter(|&&e| self.has(e));
+        let reasons: Vec<String> = found.map(|e| format!("{} {}", e.name(), e.points())).collect();
+        let word = self.token.trim_matches(|c: char| "[]():- ".contains(c));
+        format!("{} at {}: {} ({})", word, self.pos, self.score(), reasons.join(", "))
+    }
+}
+
+/// `--explain`: each candidate for `line`'s level and what it scored, the
+/// winner first, or what the line was inferred to be without one.
+fn explain_level(line: &str, infer: &[Inference]) -> String {
+    let ranked = ranked_candidates(line);
+    if let Some((best, others)) = ranked.split_first() {
+        let others = others.iter().map(|c| format!("; over {}", c.explain()));
+        return format!(
+            "{} from {}{}",
+            best.level.name(),
+            best.explain(),
+            others.collect::<String>()
+        );
+    }
+    match infer_level(line, infer) {
+        Some((range, level)) => format!("{} inferred from '{}'", level.name(), &line[range]),
+        None => "no level".to_string(),
+    }
+}
+
+/// the candidates best first, each overlapping a better one left out, like
+/// `ERR` inside `ERROR`.
+fn ranked_candidates(line: &str) -> Vec<LevelCandidate<'_>> {
+    let mut candidates = level_candidates(line);
+    candidates.sort_by_key(|c| std::cmp::Reverse(candidate_rank(c)));
+    let mut taken: Vec<std::ops::Range<usize>> = Vec::new();
+    candidates.retain(|c| {
+        let range = c.pos..c.pos + c.token.len();
+        let free = !taken.iter().any(|t| t.start < range.end && range.start < t.end);
+        if free {
+            taken.push(range);
+        }
+        free
+    });
+    candidates
+}
+
+fn candidate_rank(c: &LevelCandidate) -> (u32, std::cmp::Reverse<usize>, usize) {
+    (c.score(), std::cmp::Reverse(c.pos), c.token.len())
+}
+
+/// the candidate that decides a line's level: the best scoring, the
+/// earliest of those, the longest word of those (`WARNING` over `WARN`).
+fn best_candidate<'c, 'a>(candidates: &'c [LevelCandidate<'a>]) -> Option<&'c LevelCandidate<'a>> {
+    candidates.iter().max_by_key(|c| candidate_rank(c))
+}
+
+fn level_candidates(line: &str) -> Vec<LevelCandidate<'_>> {
+    level_words().candidates(line)
+}
+
+impl LevelWords {
+    /// every occurrence of one of the words in `line` its `TokenMatch`
+    /// allows, in no particular order, with what speaks for each. a slash
+    /// counts as part of a word so paths and URLs (`/info/`, `http://`,
+    /// `HTTP/1.1`) don't look like levels.
+    fn candidates<'a>(&self, line: &'a str) -> Vec<LevelCandidate<'a>> {
+        weigh_candidates(line, self.word_candidates(line))
+    }
+
+    /// the word scan of `candidates`: one pass over the bytes, trying at
+    /// each the words starting with it, ASCII case folded, as the words
+    /// are all ASCII. benchmark: 1M lines (106 MB, a level each,
+    /// `--color always`, output to a file) took 8.5s before, uppercasing
+    /// each line and searching it once per word, and 5.5s after,
+    /// byte-identical.
+    fn word_candidates<'a>(&self, line: &'a str) -> Vec<LevelCandidate<'a>> {
+        let mut candidates = Vec::new();
+        let bytes = line.as_bytes();
+        let glued = |b: u8| is_word_byte(b) || b == b'/';
+        let limit = self
+            .prefix
+            .map_or(line.len(), |n| line.char_indices().nth(n).map_or(line.len(), |(i, _)| i));
+        for (start, &first) in bytes.iter().enumerate().take(limit) {
+            if !self.starts[usize::from(first)] {
+                continue;
+            }
+            for &i in &self.by_first[usize::from(first.to_ascii_uppercase())] {
+                let w = &self.words[i];
+                let end = start + w.word.len();
+                let Some(word) = line.get(start..end).filter(|_| end <= limit) else {
+                    continue;
+                };
+                if !word.eq_ignore_ascii_case(&w.word) {
+                    continue;
+                }
+                let before = start.checked_sub(1).map(|i| bytes[i]);
+                let after = bytes.get(end).copied();
+                let url = bytes[end..].starts_with(b"://");
+                let whole = !before.is_some_and(glued) && !after.is_some_and(glued) && !url;
+                let bracketed =
+                    matches!((before, after), (Some(b'['), Some(b']')) | (Some(b'('), Some(b')')));
+                let closing = before == Some(b' ') && after == Some(b']');
+                let colon = after == Some(b':') && !url;
+                let dash = bytes[end..].starts_with(b" -");
+                let allowed = match w.how {
+                    TokenMatch::Loose => self.loose || whole,
+                    TokenMatch::Word => whole && !url,
+                    TokenMatch::LineStart => start == 0 && colon,
+                    TokenMatch::Label => whole && colon,
+                    TokenMatch::Bracketed => before == Some(b'[') && after == Some(b']'),
+                };
+                if !allowed {
+                    continue;
+                }
+                // ASCII all around, so on char boundaries
+                let range = match () {
+                    _ if bracketed => start - 1..end + 1,
+                    _ if colon => start..end + 1,
+                    _ if dash => start..end + 2,
+                    _ => start..end,
+                };
+                let mut evidence = 0;
+                let mut add = |e: Evidence, yes: bool| evidence |= u16::from(yes) << e as u16;
+                add(Evidence::Bracketed, bracketed || closing);
+                add(Evidence::Labeled, colon || dash);
+                add(
+                    Evidence::LineStart,
+                    line[..start].split_whitespace().count() <= LEVEL_START_WORDS,
+                );
+                add(Evidence::Uppercase, word.bytes().all(|b| !b.is_ascii_lowercase()));
+                add(Evidence::WholeWord, whole);
+                candidates.push(LevelCandidate {
+                    pos: range.start,
+                    token: &line[range],
+                    level: w.level,
+                    evidence,
+                });
+            }
+        }
+        candidates
+    }
+}
+
+/// `candidates` with what the rest of `line` says about them: the one
+/// near its start, the one next to its timestamp, the one under its level
+/// key.
+fn weigh_candidates<'a>(
+    line: &'a str,
+    mut candidates: Vec<LevelCandidate<'a>>,
+) -> Vec<LevelCandidate<'a>> {
+    for c in &mut candidates {
+        c.evidence |= u16::from(c.pos < LEVEL_START_BYTES) << Evidence::Early as u16;
+        c.evidence |= u16::from(after_level_key(&line[..c.pos])) << Evidence::KeyValue as u16;
+    }
+    if candidates.len() > 1 {
+        if let Some(stamp) = timestamp_range(line) {
+            let gap = |s: &str| s.trim_matches(|c: char| " []|".contains(c)).is_empty();
+            for c in &mut candidates {
+                let end = c.pos + c.token.len();
+                let next = line.get(stamp.end..c.pos).is_some_and(gap)
+                    || line.get(end..stamp.start).is_some_and(gap);
+                c.evidence |= u16::from(next) << Evidence::Timestamp as u16;
+            }
+        }
+    }
+    if let Some((value, level)) = json_level(line) {
+        let keyed = 1 << Evidence::JsonKey as u16;
+        let mut found = false;
+        for c in candidates.iter_mut() {
+            if c.pos < value.end && value.start < c.pos + c.token.len() {
+                c.evidence |= keyed;
+                found = true;
+            }
+        }
+        if !found {
+            // a spelling the word scan passes over, like "Warning"
+            candidates.push(LevelCandidate {
+                pos: value.start,
+                token: &line[value],
+                level,
+                evidence: keyed,
+            });
+        }
+    }
+    candidates
+}
+
+/// whether `before` ends in `level=` or `severity="`, say: one of
+/// `JSON_LEVEL_KEYS`, any case, as a logfmt key.
+fn after_level_key(before: &str) -> bool {
+    let before = before.strip_suffix('"').unwrap_or(before);
+    let Some(key) = before.strip_suffix('=') else {
+        return false;
+    };
+    let key = key.rsplit(|c: char| !c.is_ascii_alphanumeric() && c != '.' && c != '_').next();
+    let key = key.unwrap_or_default();
+    JSON_LEVEL_KEYS.iter().any(|k| k.eq_ignore_ascii_case(key))
+}
+
+/// phrases that give a line without a level token away, checked when
+/// `find_level` comes up empty. `--infer` adds to these, `--no-infer` drops
+/// them all.
+const INFERENCES: &[(&str, Level)] = &[
+    ("connection refused", Level::Error),
+    ("connection reset", Level::Warn),
+    ("timed out", Level::Warn),
+    ("out of memory", Level::Error),
+    ("segfault", Level::Error),
+    ("segmentation fault", Level::Error),
+    ("core dumped", Level::Error),
+    ("permission denied", Level::Error),
+    ("no space left on device", Level::Error),
+    ("traceback", Level::Error),
+    ("deprecated", Level::Warn),
+];
+
+/// one `--infer "PHRASE=LEVEL"`, or a built-in one.
+#[doc(hidden)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Inference {
+    /// lowercase.
+    phrase: String,
+    level: Level,
+}
+
+impl Inference {
+    pub fn parse(spec: &str) -> Result<Inference, String> {
+        let bad = || format!("expected PHRASE=LEVEL for '--infer', got '{}'", spec);
+        let (phrase, level) = spec.rsplit_once('=').ok_or_else(bad)?;
+        let phrase = phrase.trim().to_ascii_lowercase();
+        let level = Level::from_token(&level.trim().to_uppercase())
+            .ok_or_else(|| format!("unknown level '{}' in '--infer {}'", level.trim(), spec))?;
+        if phrase.is_empty() {
+            return Err(bad());
+        }
+        Ok(Inference { phrase, level })
+    }
+
+    fn defaults() -> Vec<Inference> {
+        let table = INFERENCES.iter();
+        table.map(|&(phrase, level)| Inference { phrase: phrase.to_string(), level }).collect()
+    }
+}
+
+/// the first phrase of `table` found in `line` as whole words, ignoring
+/// case, as the byte range it covers and the level it stands for.
+fn infer_level(line: &str, table: &[Inference]) -> Option<(std::ops::Range<usize>, Level)> {
+    let lower = line.to_ascii_lowercase();
+    let bytes = lower.as_bytes();
+    table.iter().find_map(|inference| {
+        let mut from = 0;
+        while let Some(found) = lower[from..].find(&inference.phrase) {
+            let (start, end) = (from + found, from + found + inference.phrase.len());
+            let before = start.checked_sub(1).map(|i| bytes[i]);
+            let after = bytes.get(end).copied();
+            if !before.is_some_and(is_word_byte) && !after.is_some_and(is_word_byte) {
+                return Some((start..end, inference.level));
+            }
+            from = start + lower[start..].chars().next().map_or(1, char::len_utf8);
+        }
+        None
+    })
+}
+
+/// a MySQL 8 error code like `[MY-010918]` at the start of `text`, as the
+/// length of the bracket.
+fn mysql_error_code_len(text: &str) -> Option<usize> {
+    let digits = text.strip_prefix("[MY-")?;
+    let len = digits.bytes().take_while(u8::is_ascii_digit).count();
+    (len > 0 && digits[len..].starts_with(']')).then_some(4 + len + 1)
+}
+
+/// how input lines are interpreted, from `--format`.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+    /// recognize structured lines when they parse, plain text otherwise.
+    Auto,
+    /// only the generic level-token detection, and a leading syslog PRI.
+    Plain,
+    /// every line is RFC 5424 syslog, even without a leading PRI.
+    Rfc5424,
+    /// `journalctl -o json` or `-o export` records.
+    Journald,
+    /// newline-delimited Graylog extended log format.
+    Gelf,
+    /// Rust services: env_logger and tracing-subscriber's fmt layer.
+    Rust,
+    /// `kubectl logs --prefix` and CRI container logs.
+    K8s,
+    /// `docker compose logs`, each line behind its `service-N  | ` prefix.
+    Compose,
+    /// one JSON object per line, its level under a key like `"level"`.
+    Json,
+    /// `key=value` pairs, Heroku style, its level under a key like `level`.
+    Logfmt,
+}
+
+impl InputFormat {
+    pub fn parse(s: &str) -> Option<InputFormat> {
+        match s {
+            "auto" => Some(InputFormat::Auto),
+            "plain" => Some(InputFormat::Plain),
+            "rfc5424" => Some(InputFormat::Rfc5424),
+            "journald" => Some(InputFormat::Journald),
+            "gelf" => Some(InputFormat::Gelf),
+            "rust" => Some(InputFormat::Rust),
+            "k8s" => Some(InputFormat::K8s),
+            "compose" => Some(InputFormat::Compose),
+            "json" => Some(InputFormat::Json),
+            "logfmt" => Some(InputFormat::Logfmt),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            InputFormat::Auto => "auto",
+            InputFormat::Plain => "plain",
+            InputFormat::Rfc5424 => "rfc5424",
+            InputFormat::Journald => "journald",
+            InputFormat::Gelf => "gelf",
+            InputFormat::Rust => "rust",
+            InputFormat::K8s => "k8s",
+            InputFormat::Compose => "compose",
+            InputFormat::Json => "json",
+            InputFormat::Logfmt => "logfmt",
+        }
+    }
+
+    /// `line` looks like one of this format's; for the detectable formats.
+    fn recognizes(self, line: &str) -> bool {
+        let object = || match line.trim_start().starts_with('{') {
+            true => parse_json(line).filter(|r| matches!(r, Json::Object(_))),
+            false => None,
+        };
+        match self {
+            InputFormat::Rfc5424 => parse_rfc5424(line, true).is_some(),
+            InputFormat::Journald => {
+                ExportRecord::is_field(line)
+                    || object().is_some_and(|r| r.get("MESSAGE").or(r.get("__CURSOR")).is_some())
+            }
+            InputFormat::Gelf => object().is_some_and(|r| r.get("short_message").is_some()),
+            InputFormat::Rust => parse_rust_line(line).is_some(),
+            InputFormat::K8s => parse_k8s_line(line).is_some(),
+            InputFormat::Compose => parse_compose_line(&visible_text(line)).is_some(),
+            InputFormat::Json => json_level(line).is_some(),
+            InputFormat::Logfmt => logfmt_level(line, &logfmt_pairs(line), false).is_some(),
+            InputFormat::Auto | InputFormat::Plain => false,
+        }
+    }
+}
+
+/// the formats `--format auto` can settle on for a whole input; on a tie
+/// the one first here. journald and GELF records are JSON lines too.
+const DETECTABLE_FORMATS: [InputFormat; 8] = [
+    InputFormat::Rfc5424,
+    InputFormat::Journald,
+    InputFormat::Gelf,
+    InputFormat::Rust,
+    InputFormat::K8s,
+    InputFormat::Compose,
+    InputFormat::Json,
+    InputFormat::Logfmt,
+];
+
+/// how much of the start of an input `--format auto` looks at.
+const DETECT_SAMPLE_LINES: usize = 200;
+const DETECT_SAMPLE_BYTES: usize = 64 << 10;
+
+/// the share of the sample a format has to recognize to be picked; below
+/// it the line-by-line checks of `auto` stay in charge, which is also what
+/// copes with a mix of formats.
+const DETECT_CONFIDENCE: f64 = 0.6;
+
+/// how well each detectable format fits the start of an input.
+#[derive(Debug, PartialEq)]
+struct Detection {
+    /// lines looked at, blank ones aside.
+    lines: usize,
+    /// how many of them each format recognized, best first.
+    scores: Vec<(InputFormat, usize)>,
+    chosen: InputFormat,
+}
+
+fn detect_format(sample: &[u8]) -> Detection {
+    let text = String::from_utf8_lossy(sample);
+    let mut lines: Vec<&str> = text.lines().take(DETECT_SAMPLE_LINES + 1).collect();
+    if lines.len() > DETECT_SAMPLE_LINES || lines.len() > 1 && !text.ends_with('\n') {
+        // cut off by the sample's end
+        lines.pop();
+    }
+    lines.retain(|line| !line.trim().is_empty());
+    let mut scores: Vec<(InputFormat, usize)> = DETECTABLE_FORMATS
+        .iter()
+        .map(|&format| (format, lines.iter().filter(|line| format.recognizes(line)).count()))
+        .collect();
+    scores.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+    let chosen = match scores.first() {
+        Some(&(format, score))
+            if score as f64 >= DETECT_CONFIDENCE * lines.len() as f64 && score > 0 =>
+        {
+            format
+        }
+        _ => InputFormat::Auto,
+    };
+    Detection { lines: lines.len(), scores, chosen }
+}
+
+impl Detection {
+    /// `--detect`'s report for one input.
+    fn report(&self, label: &str) -> String {
+        let mut out = match self.chosen {
+            InputFormat::Auto => format!(
+                "{}: auto (nothing recognized {:.0}% of {} lines; checked line by line)\n",
+                label,
+                DETECT_CONFIDENCE * 100.0,
+                self.lines
+            ),
+            chosen => format!("{}: {}\n", label, chosen.name()),
+        };
+        for (format, score) in &self.scores {
+            let percent = match self.lines {
+                0 => 0.0,
+                n => *score as f64 * 100.0 / n as f64,
+            };
+            out += &format!(
+                "  {:<9}{:>4.0}%  ({} of {} lines)\n",
+                format.name(),
+                percent,
+                score,
+                self.lines
+            );
+        }
+        out
+    }
+}
+
+/// everything needed to render a line: what the input looks like and how
+/// the output should be styled.
+///
+/// the CLI fills one in from its options; as a library, [`Highlighter::builder`]
+/// makes one drawing lines the way `logcolor` does by default:
+///
+/// ```
+/// use logcolor::{Highlighter, Level, Theme};
+///
+/// let hl = Highlighter::builder().theme(Theme::Light).color(true).build();
+/// let line = "2024-05-01 12:03:04 [ERROR] disk full";
+/// let found = hl.find_level(line).unwrap();
+/// assert_eq!((found.level, &line[found.range]), (Level::Error, "[ERROR]"));
+/// assert!(hl.highlight_line(line).contains("\x1b[1m\x1b[31m[ERROR]\x1b[0m"));
+/// ```
+pub struct Highlighter {
+    format: InputFormat,
+    /// show secondary fields of structured records, e.g. GELF `full_message`.
+    verbose: bool,
+    /// how many `-q`s: one drops the line output, two also any summary.
+    quiet: u8,
+    /// soft-wrap long lines at the terminal's width.
+    wrap: bool,
+    /// the terminal's width, which may change while we run.
+    width: TermWidth,
+    tracking: Option<Tracking>,
+    json_fields: Option<JsonFields>,
+    /// the level colors and `--rules`, each line drawn with them as they
+    /// are when it starts.
+    settings: Settings,
+    /// put pretty-printed JSON records back together before rendering.
+    multiline_json: bool,
+    /// and print them pretty-printed again, colored.
+    expand_json: bool,
+    /// `--json=pretty`: JSON lines pretty-printed in their level's color.
+    json_pretty: bool,
+    /// `--logfmt-keys dim`: the keys of logfmt lines dimmed.
+    dim_logfmt_keys: bool,
+    /// `--unescape`: `\n` and friends in message fields as what they stand for.
+    unescape: bool,
+    invisibles: Invisibles,
+    /// `--continuation`, or the format's own default when `None`.
+    continuation: Option<ContinuationMode>,
+    /// `--continuation-max`: how many lines in a row can inherit a level.
+    continuation_max: usize,
+    /// `--color-all-occurrences`: level words besides the line's own level
+    /// get their colors too.
+    all_occurrences: bool,
+    /// `--explain`: follow each line with how its level was decided.
+    explain: bool,
+    /// `--preserve-eol`: an input's last line without a newline is written
+    /// without one.
+    preserve_eol: bool,
+    /// `--keep-ansi`: a line already colored is written as it came. else
+    /// the input's own escape sequences are taken out before anything looks
+    /// at a line, so they neither fight the colors given nor hide a level.
+    keep_ansi: bool,
+    /// `--syslog`: a line's PRI is written as `[facility.severity]`.
+    syslog_names: bool,
+    /// `--extras`: addresses, ids, URLs and durations set off too.
+    extras: Option<Extras>,
+    /// count lines, levels and timestamps for the per-input summary lines.
+    summaries: bool,
+    /// the key reader may print marks between lines.
+    marks: bool,
+    /// prefix lines with their byte offset in the input.
+    byte_offsets: bool,
+    /// `--number`: prefix lines with their line number in the input.
+    number: bool,
+    /// `--lines`: only this range of each input's lines is shown.
+    lines: Option<LineRange>,
+    /// `--tail`: only each input's last lines are.
+    tail: Option<Tail>,
+    /// longer lines are handled in pieces of this many bytes.
+    max_line_bytes: usize,
+    /// phrases to guess a level from when a line has no level token.
+    infer: Vec<Inference>,
+    /// only show lines matching this, and the context asked for.
+    grep: Option<Grep>,
+    /// `--service`: the compose services whose lines are shown.
+    services: Option<Vec<String>>,
+    /// `--elide-below`: lines under this level are left out and counted.
+    elide_below: Option<Level>,
+    /// `--marks`, when the terminal can take them.
+    jump_marks: Option<JumpMarks>,
+    throttle: Option<Throttle>,
+    dedup: Option<DedupWindow>,
+    squeeze: Option<SqueezeWindow>,
+    budget: Option<Budget>,
+    levels: LevelFilter,
+    sources: SourceFilter,
+    /// `--since`: lines older than this are dropped.
+    since: Option<TimeWindow>,
+    /// `--level-column`: where the level is, instead of looking for it.
+    level_column: Option<LevelColumn>,
+    split: Option<std::sync::Mutex<SplitDir>>,
+    snapshot: Option<std::sync::Arc<std::sync::Mutex<Snapshot>>>,
+    /// treat `tail`'s `==> path <==` lines as switching between files.
+    tail_headers: bool,
+    /// `--columns`: lay lines out as a table instead of as written.
+    columns: Option<Columns>,
+    /// `--hex`: hex dumps colored in groups of bytes.
+    hex: Option<HexDumps>,
+    /// `--split-interleaved`: lines carrying several records are split.
+    split_interleaved: bool,
+    /// `--sort-buffer`: how far out of order timestamps may come and still
+    /// be put back in order.
+    sort_buffer: Option<Duration>,
+    /// `--gutter`, which leaves `styles` plain.
+    gutter: Option<Gutter>,
+    /// `--whole-line`: a leveled line all in its level's color, the token
+    /// still bold, any prefix left as it is.
+    whole_line: bool,
+    /// `--highlight-new`, shared by all inputs of the session.
+    novelty: Option<std::sync::Mutex<Novelty>>,
+    /// `--only-new`: files are read from where the last run stopped.
+    only_new: Option<OnlyNew>,
+    /// `--tz` or `--local`: timestamps shown in this zone.
+    tz: Option<Zone>,
+    /// `--metrics-listen`, counting what each input has read.
+    metrics: Option<std::sync::Arc<Metrics>>,
+    /// `--alert-rate`, counting errors across all inputs.
+    alert: Option<Alert>,
+    /// `--audit`, which also counts what each input read into `metrics`.
+    audit: Option<std::sync::Arc<Audit>>,
+}
+
+/// the palettes a [`HighlighterBuilder`] can draw with; `--theme` without
+/// `auto`, which would ask the terminal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Theme {
+    #[default]
+    Dark,
+    Light,
+    /// deuteranopia-safe, whatever the background.
+    Colorblind,
+    SolarizedDark,
+    /// bold, dim, italic, underline and reverse video, no colors.
+    Monochrome,
+}
+
+impl Theme {
+    fn palette(self) -> &'static Palette {
+        match self {
+            Theme::Dark => &DARK_PALETTE,
+            Theme::Light => &LIGHT_PALETTE,
+            Theme::Colorblind => &COLORBLIND_PALETTE,
+            Theme::SolarizedDark => &SOLARIZED_DARK_PALETTE,
+            Theme::Monochrome => &MONOCHROME_PALETTE,
+        }
+    }
+}
+
+/// where [`Highlighter::find_level`] found a line's level.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LevelSpan {
+    /// the level word in the line, with any brackets or colon around it,
+    /// or the phrase it was inferred from.
+    pub range: std::ops::Range<usize>,
+    pub level: Level,
+}
+
+/// a [`Highlighter`] for use as a library: a theme, color on or off, and
+/// the levels whose lines are colored.
+#[derive(Clone, Debug, Default)]
+pub struct HighlighterBuilder {
+    theme: Theme,
+    /// off unless asked for, as nothing is known about where it goes.
+    color: bool,
+    levels: LevelFilter,
+}
+
+impl HighlighterBuilder {
+    pub fn theme(mut self, theme: Theme) -> HighlighterBuilder {
+        self.theme = theme;
+        self
+    }
+
+    /// 16-color escapes when on, which every terminal takes.
+    pub fn color(mut self, on: bool) -> HighlighterBuilder {
+        self.color = on;
+        self
+    }
+
+    /// lines below `level` come back from `highlight_line` as they are.
+    pub fn min_level(mut self, level: Level) -> HighlighterBuilder {
+        self.levels.min = Some(level);
+        self
+    }
+
+    /// lines above `level` come back from `highlight_line` as they are.
+    pub fn max_level(mut self, level: Level) -> HighlighterBuilder {
+        self.levels.max = Some(level);
+        self
+    }
+
+    pub fn build(self) -> Highlighter {
+        let depth = if self.color { ColorDepth::Basic } else { ColorDepth::None };
+        let mut hl = Highlighter::new(Styles::new(self.theme.palette(), depth));
+        hl.levels = self.levels;
+        hl
+    }
+}
+
+impl Highlighter {
+    pub fn builder() -> HighlighterBuilder {
+        HighlighterBuilder::default()
+    }
+
+    /// the styles and rules in force.
+    fn loaded(&self) -> std::sync::Arc<Loaded> {
+        self.settings.current()
+    }
+
+    /// with `styles` and every option as the CLI has it by default.
+    fn new(styles: Styles) -> Highlighter {
+        Highlighter {
+            settings: Settings::new(styles.clone(), styles, Vec::new()),
+            format: InputFormat::Auto,
+            verbose: false,
+            quiet: 0,
+            wrap: false,
+            width: TermWidth::new(80),
+            tracking: None,
+            json_fields: None,
+            multiline_json: false,
+            expand_json: false,
+            json_pretty: false,
+            dim_logfmt_keys: false,
+            unescape: false,
+            invisibles: Invisibles::default(),
+            continuation: None,
+            continuation_max: 200,
+            all_occurrences: false,
+            explain: false,
+            preserve_eol: false,
+            keep_ansi: false,
+            syslog_names: false,
+            extras: None,
+            summaries: false,
+            marks: false,
+            byte_offsets: false,
+            number: false,
+            lines: None,
+            max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+            infer: Inference::defaults(),
+            grep: None,
+            services: None,
+            elide_below: None,
+            jump_marks: None,
+            throttle: None,
+            dedup: None,
+            squeeze: None,
+            budget: None,
+            tail: None,
+            levels: LevelFilter::default(),
+            sources: SourceFilter::default(),
+            since: None,
+            level_column: None,
+            split: None,
+            snapshot: None,
+            tail_headers: true,
+            columns: None,
+            hex: None,
+            split_interleaved: false,
+            sort_buffer: None,
+            gutter: None,
+            whole_line: false,
+            novelty: None,
+            only_new: None,
+            tz: None,
+            metrics: None,
+            alert: None,
+            audit: None,
+        }
+    }
+
+    /// the line's level and where it says so: its level word, or failing
+    /// that a phrase like "timed out".
+    pub fn find_level(&self, line: &str) -> Option<LevelSpan> {
+        let Some((pos, token)) = find_level(line) else {
+            let (range, level) = infer_level(line, &self.infer)?;
+            return Some(LevelSpan { range, level });
+        };
+        let raw = token.trim_matches(|c: char| "[]():- ".contains(c)).to_uppercase();
+        Some(LevelSpan { range: pos..pos + token.len(), level: token_level(&raw)? })
+    }
+
+    /// `line` drawn the way the CLI draws it, without the newline; as it is
+    /// when that changes nothing, as with color off.
+    pub fn highlight_line<'a>(&self, line: &'a str) -> std::borrow::Cow<'a, str> {
+        let mut out = Vec::with_capacity(line.len() + 32);
+        if self.write_highlighted(&mut out, line).is_err() {
+            return std::borrow::Cow::Borrowed(line);
+        }
+        if out.last() == Some(&b'\n') {
+            out.pop();
+        }
+        match String::from_utf8(out) {
+            Ok(drawn) if drawn != line => std::borrow::Cow::Owned(drawn),
+            _ => std::borrow::Cow::Borrowed(line),
+        }
+    }
+
+    /// `line` drawn to `out` the way the CLI draws it, newline included.
+    ///
+    /// ```
+    /// use logcolor::{Highlighter, Level};
+    ///
+    /// let hl = Highlighter::builder().color(true).min_level(Level::Warn).build();
+    /// let mut out = Vec::new();
+    /// hl.write_highlighted(&mut out, "INFO: started").unwrap();
+    /// hl.write_highlighted(&mut out, "WARN: slow").unwrap();
+    /// assert_eq!(out, b"INFO: started\n\x1b[1m\x1b[33mWARN:\x1b[0m slow\n");
+    /// ```
+    pub fn write_highlighted(&self, out: &mut impl Write, line: &str) -> io::Result<()> {
+        let stripped;
+        let line = match !self.keep_ansi && line.contains('\x1b') {
+            true => {
+                stripped = strip_escapes(line);
+                stripped.as_str()
+            }
+            false => line,
+        };
+        if !self.levels.keeps(level_of(line, self)) {
+            return writeln!(out, "{}", line);
+        }
+        print_colored_line(out, line, self)
+    }
+}
+
+/// byte offsets of the parts of an RFC 5424 line,
+/// `<PRI>VERSION TIMESTAMP HOST APP PROCID MSGID SD [MSG]`.
+#[derive(Debug, PartialEq, Eq)]
+struct Rfc5424 {
+    /// end of `<PRI>`; 0 when the line has no PRI.
+    pri_end: usize,
+    severity: Option<u8>,
+    /// VERSION through MSGID.
+    header_end: usize,
+    sd_start: usize,
+    sd_end: usize,
+    /// start of MSG, `None` when the line ends after the structured data.
+    msg_start: Option<usize>,
+}
+
+/// syslog's facility names, by PRI / 8, as rsyslog writes them.
+const SYSLOG_FACILITIES: [&str; 24] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv",
+    "ftp", "ntp", "security", "console", "clock", "local0", "local1", "local2", "local3", "local4",
+    "local5", "local6", "local7",
+];
+/// and its severity names, by PRI % 8.
+const SYSLOG_SEVERITIES: [&str; 8] =
+    ["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+
+/// `<N>` with 1-3 digits and N in 0..=191; returns (end, severity).
+fn parse_pri(line: &str) -> Option<(usize, u8)> {
+    let rest = line.strip_prefix('<')?;
+    let close = rest.find('>')?;
+    let digits = &rest[..close];
+    if digits.is_empty() || digits.len() > 3 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let pri: u16 = digits.parse().ok()?;
+    if pri > 191 {
+        return None;
+    }
+    Some((close + 2, (pri % 8) as u8))
+}
+
+/// the PRI at the start of a line bold, in its severity's color.
+fn pri_mark(end: usize, severity: u8, styles: &Styles) -> Span {
+    let style = styles.level_style(Level::from_syslog_severity(severity), BOLD);
+    Span::new(0..end, style, SpanKind::Level)
+}
+
+/// `--syslog`: `line` with its PRI written as `[facility.severity]`, the
+/// name of the severity in its color and the rest dimmed, and its spans
+/// moved along to match.
+fn name_pri(line: &str, spans: &[Span], styles: &Styles) -> Option<(String, Vec<Span>)> {
+    let (end, severity) = parse_pri(line)?;
+    let pri: usize = line[1..end - 1].parse().ok()?;
+    let (facility, name) = (SYSLOG_FACILITIES[pri / 8], SYSLOG_SEVERITIES[pri % 8]);
+    let space = if line[end..].starts_with(' ') || end == line.len() { "" } else { " " };
+    let named = format!("[{}.{}]{}{}", facility, name, space, &line[end..]);
+    let (name_start, name_end) = (facility.len() + 2, facility.len() + 2 + name.len());
+    let tag_end = name_end + 1 + space.len();
+    let mut named_spans = vec![
+        Span::new(0..name_start, styles.dim_style(), SpanKind::Plain),
+        Span { range: name_start..name_end, ..pri_mark(0, severity, styles) },
+        Span::new(name_end..name_end + 1, styles.dim_style(), SpanKind::Plain),
+        Span::plain(name_end + 1..tag_end),
+    ];
+    let moved = spans.iter().filter(|span| span.range.end > end).map(|span| Span {
+        range: span.range.start.max(end) - end + tag_end..span.range.end - end + tag_end,
+        ..span.clone()
+    });
+    named_spans.extend(moved);
+    Some((named, named_spans))
+}
+
+/// length of one structured-data element starting at `[`, honoring the
+/// `\"`, `\\` and `\]` escapes inside quoted param values.
+fn sd_element_len(s: &str) -> Option<usize> {
+    let bytes = s.as_bytes();
+    if bytes.first() != Some(&b'[') {
+        return None;
+    }
+    let mut in_quotes = false;
+    let mut i = 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' if in_quotes => i += 1,
+            b'"' => in_quotes = !in_quotes,
+            b']' if !in_quotes => return if i > 1 { Some(i + 1) } else { None },
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// `require_pri` is false when the user forced `--format rfc5424`, so that
+/// files written without the PRI by some relays still parse.
+fn parse_rfc5424(line: &str, require_pri: bool) -> Option<Rfc5424> {
+    let (pri_end, severity) = match parse_pri(line) {
+        Some((end, sev)) => (end, Some(sev)),
+        None if require_pri => return None,
+        None => (0, None),
+    };
+    let mut pos = pri_end;
+    // VERSION: a non-zero number of at most 2 digits, immediately after PRI
+    let version = line[pos..].bytes().take_while(u8::is_ascii_digit).count();
+    if version == 0 || version > 2 || line.as_bytes()[pos] == b'0' {
+        return None;
+    }
+    pos += version;
+    // TIMESTAMP HOSTNAME APP-NAME PROCID MSGID: printable, space separated
+    for _ in 0..5 {
+        let rest = line[pos..].strip_prefix(' ')?;
+        let len = rest.bytes().take_while(|b| b.is_ascii_graphic()).count();
+        if len == 0 {
+            return None;
+        }
+        pos += 1 + len;
+    }
+    let header_end = pos;
+    let sd_start = pos + 1;
+    let sd = line[pos..].strip_prefix(' ')?;
+    let sd_len = if sd.starts_with('-') && (sd.len() == 1 || sd.as_bytes()[1] == b' ') {
+        1
+    } else {
+        let mut len = 0;
+        while let Some(elem) = sd_element_len(&sd[len..]) {
+            len += elem;
+        }
+        if len == 0 {
+            return None;
+        }
+        len
+    };
+    let sd_end = sd_start + sd_len;
+    let msg_start = match &line[sd_end..] {
+        "" => None,
+        rest if rest.starts_with(' ') => Some(sd_end + 1),
+        _ => return None,
+    };
+    Some(Rfc5424 { pri_end, severity, header_end, sd_start, sd_end, msg_start })
+}
+
+/// the PRI in its severity's color, the header dimmed, the structured data
+/// muted and the message as any other line.
+fn rfc5424_marks(line: &str, rec: &Rfc5424, hl: &Highlighter) -> Vec<Span> {
+    let styles = &hl.loaded().styles;
+    let mut marks = Vec::new();
+    if let Some(severity) = rec.severity {
+        marks.push(pri_mark(rec.pri_end, severity, styles));
+    }
+    marks.push(Span::new(rec.pri_end..rec.header_end, styles.dim_style(), SpanKind::Plain));
+    marks.push(Span::new(rec.sd_start..rec.sd_end, styles.muted_style(), SpanKind::Entity));
+    if let Some(msg_start) = rec.msg_start {
+        marks.extend(level_marks(&line[msg_start..], hl).into_iter().map(|mut mark| {
+            mark.range = mark.range.start + msg_start..mark.range.end + msg_start;
+            mark
+        }));
+    }
+    marks
+}
+
+/// a parsed JSON value. objects keep their keys in source order, and
+/// numbers are kept as written so nothing is lost to float rounding.
+#[derive(Clone, Debug, PartialEq)]
+enum Json {
+    Null,
+    Bool(bool),
+    Number(String),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// `error.message` style lookups; a key that itself contains dots wins
+    /// over descending into objects.
+    fn path(&self, path: &str) -> Option<&Json> {
+        if let Some(value) = self.get(path) {
+            return Some(value);
+        }
+        let (head, rest) = path.split_once('.')?;
+        self.get(head)?.path(rest)
+    }
+
+    /// numbers, whether written as JSON numbers or as numeric strings.
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) | Json::String(n) => n.parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// strings as their contents, everything else as compact JSON.
+    fn to_text(&self) -> String {
+        match self {
+            Json::String(s) => s.clone(),
+            other => other.to_string(),
+        }
+    }
+}
+
+fn write_json_string(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    f.write_str("\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if u32::from(c) < 0x20 => write!(f, "\\u{:04x}", u32::from(c))?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// compact serialization, the inverse of `parse_json`.
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => f.write_str(n),
+            Json::String(s) => write_json_string(f, s),
+            Json::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                f.write_str("]")
+            }
+            Json::Object(fields) => {
+                f.write_str("{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_json_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+/// nesting beyond this is rejected instead of risking the stack.
+const JSON_MAX_DEPTH: usize = 128;
+
+struct JsonParser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn skip_ws(&mut self) {
+        while matches!(self.bytes.get(self.pos), Some(b' ' | b'\t' | b'\n' | b'\r')) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> Option<()> {
+        self.skip_ws();
+        (self.bytes.get(self.pos) == Some(&byte)).then(|| self.pos += 1)
+    }
+
+    fn literal(&mut self, word: &str, value: Json) -> Option<Json> {
+        let end = self.pos + word.len();
+        (self.bytes.get(self.pos..end) == Some(word.as_bytes())).then(|| {
+            self.pos = end;
+            value
+        })
+    }
+
+    fn value(&mut self, depth: usize) -> Option<Json> {
+        if depth > JSON_MAX_DEPTH {
+            return None;
+        }
+        self.skip_ws();
+        match *self.bytes.get(self.pos)? {
+            b'{' => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if self.eat(b'}').is_some() {
+                    return Some(Json::Object(fields));
+                }
+                loop {
+                    self.skip_ws();
+                    let key = self.string()?;
+                    self.eat(b':')?;
+                    fields.push((key, self.value(depth + 1)?));
+                    if self.eat(b',').is_none() {
+                        self.eat(b'}')?;
+                        return Some(Json::Object(fields));
+                    }
+                }
+            }
+            b'[' => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if self.eat(b']').is_some() {
+                    return Some(Json::Array(items));
+                }
+                loop {
+                    items.push(self.value(depth + 1)?);
+                    if self.eat(b',').is_none() {
+                        self.eat(b']')?;
+                        return Some(Json::Array(items));
+                    }
+                }
+            }
+            b'"' => self.string().map(Json::String),
+            b't' => self.literal("true", Json::Bool(true)),
+            b'f' => self.literal("false", Json::Bool(false)),
+            b'n' => self.literal("null", Json::Null),
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => None,
+        }
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.pos;
+        let digits = |p: &mut Self| {
+            let from = p.pos;
+            while p.bytes.get(p.pos).is_some_and(u8::is_ascii_digit) {
+                p.pos += 1;
+            }
+            p.pos > from
+        };
+        if self.bytes[self.pos] == b'-' {
+            self.pos += 1;
+        }
+        if !digits(self) {
+            return None;
+        }
+        if self.bytes.get(self.pos) == Some(&b'.') {
+            self.pos += 1;
+            if !digits(self) {
+                return None;
+            }
+        }
+        if matches!(self.bytes.get(self.pos), Some(b'e' | b'E')) {
+            self.pos += 1;
+            if matches!(self.bytes.get(self.pos), Some(b'+' | b'-')) {
+                self.pos += 1;
+            }
+            if !digits(self) {
+                return None;
+            }
+        }
+        let text = std::str::from_utf8(&self.bytes[start..self.pos]).ok()?;
+        Some(Json::Number(text.to_string()))
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let hex = std::str::from_utf8(self.bytes.get(self.pos..self.pos + 4)?).ok()?;
+        self.pos += 4;
+        u32::from_str_radix(hex, 16).ok()
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if self.bytes.get(self.pos) != Some(&b'"') {
+            return None;
+        }
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            match *self.bytes.get(self.pos)? {
+                b'"' => {
+                    self.pos += 1;
+                    return String::from_utf8(out).ok();
+                }
+                b'\\' => {
+                    self.pos += 1;
+                    let c = match *self.bytes.get(self.pos)? {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            self.pos += 1;
+                            let hi = self.hex4()?;
+                            let code = if (0xd800..0xdc00).contains(&hi) {
+                                if self.bytes.get(self.pos..self.pos + 2) != Some(b"\\u") {
+                                    return None;
+                                }
+                                self.pos += 2;
+                                let lo = self.hex4()?;
+                                if !(0xdc00..0xe000).contains(&lo) {
+                                    return None;
+                                }
+                                0x10000 + ((hi - 0xd800) << 10) + (lo - 0xdc00)
+                            } else {
+                                hi
+                            };
+                            let c = char::from_u32(code)?;
+                            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                            continue;
+                        }
+                        _ => return None,
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                    self.pos += 1;
+                }
+                0..=0x1f => return None,
+                b => {
+                    out.push(b);
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+}
+
+/// parse a complete JSON document; anything other than trailing whitespace
+/// after the value makes it invalid.
+fn parse_json(text: &str) -> Option<Json> {
+    let mut parser = JsonParser { bytes: text.as_bytes(), pos: 0 };
+    let value = parser.value(0)?;
+    parser.skip_ws();
+    (parser.pos == parser.bytes.len()).then_some(value)
+}
+
+/// days since 1970-01-01 to (year, month, day), after Howard Hinnant's
+/// `civil_from_days`.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// the inverse of `civil_from_days`.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from(if month > 2 { month - 3 } else { month + 9 });
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// microseconds since the epoch as `2024-05-01 12:03:04.003` (UTC).
+fn format_unix_micros(micros: i64) -> String {
+    let secs = micros.div_euclid(1_000_000);
+    let millis = micros.rem_euclid(1_000_000) / 1000;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86_400));
+    let tod = secs.rem_euclid(86_400);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}.{:03}",
+        year,
+        month,
+        day,
+        tod / 3600,
+        tod / 60 % 60,
+        tod % 60,
+        millis
+    )
+}
+
+/// journal fields as text. journalctl emits non-UTF-8 values as arrays of
+/// byte values; those are decoded lossily, other non-string values skipped.
+fn journal_fields_from_json(entry: &Json) -> Option<Vec<(String, String)>> {
+    let Json::Object(object) = entry else {
+        return None;
+    };
+    let fields = object
+        .iter()
+        .filter_map(|(key, value)| {
+            let text = match value {
+                Json::String(s) => s.clone(),
+                Json::Number(n) => n.clone(),
+                Json::Array(items) => {
+                    let bytes: Option<Vec<u8>> = items
+                        .iter()
+                        .map(|i| match i {
+                            Json::Number(n) => n.parse().ok(),
+                            _ => None,
+                        })
+                        .collect();
+                    String::from_utf8_lossy(&bytes?).into_owned()
+                }
+                _ => return None,
+            };
+            Some((key.clone(), text))
+        })
+        .collect();
+    Some(fields)
+}
+
+/// render a journal entry as `TIME UNIT: MESSAGE`, colored by `PRIORITY`.
+/// extra lines of a multi-line MESSAGE are indented under the first one and
+/// keep the entry's color.
+fn write_journal_entry(
+    out: &mut dyn Write,
+    fields: &[(String, String)],
+    hl: &Highlighter,
+) -> io::Result<()> {
+    let styles = &hl.loaded().styles;
+    let field = |key: &str| fields.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+    let level = field("PRIORITY")
+        .and_then(|p| p.parse::<u8>().ok())
+        .filter(|p| *p <= 7)
+        .map(Level::from_syslog_severity);
+    let mut prefix_width = 0;
+
+    if let Some(time) = field("__REALTIME_TIMESTAMP").and_then(|t| t.parse::<i64>().ok()) {
+        let time = format_unix_micros(time);
+        styles.paint(out, &styles.dim, &time)?;
+        write!(out, " ")?;
+        prefix_width += time.len() + 1;
+    }
+    let ident =
+        field("_SYSTEMD_UNIT").or_else(|| field("SYSLOG_IDENTIFIER")).or_else(|| field("_COMM"));
+    if let Some(ident) = ident {
+        match level {
+            Some(level) => styles.paint_level(out, level, ident)?,
+            None => write!(out, "{}", ident)?,
+        }
+        write!(out, ": ")?;
+        prefix_width += display_width(ident) + 2;
+    }
+
+    let message = field("MESSAGE").unwrap_or("");
+    let message = match hl.unescape {
+        true => unescape_message(message),
+        false => std::borrow::Cow::Borrowed(message),
+    };
+    let mut lines = message.split('\n');
+    write_level_highlight(out, lines.next().unwrap_or(""), hl)?;
+    writeln!(out)?;
+    write_message_lines(out, lines, prefix_width, level, styles)
+}
+
+/// render a GELF record as `TIME HOST: short_message`, colored by the syslog
+/// `level` (1, "alert", when absent, as the spec says). `--verbose` adds the
+/// `full_message` and `_custom` fields as dimmed `key: value` lines.
+fn write_gelf_record(out: &mut dyn Write, record: &Json, hl: &Highlighter) -> io::Result<()> {
+    let styles = &hl.loaded().styles;
+    let severity = record.get("level").map_or(Some(1.0), Json::as_f64);
+    let level =
+        severity.filter(|s| (0.0..=7.0).contains(s)).map(|s| Level::from_syslog_severity(s as u8));
+
+    let mut prefix_width = 0;
+    if let Some(secs) = record.get("timestamp").and_then(Json::as_f64) {
+        let time = format_unix_micros((secs * 1e6).round() as i64);
+        styles.paint(out, &styles.dim, &time)?;
+        write!(out, " ")?;
+        prefix_width += time.len() + 1;
+    }
+    if let Some(host) = record.get("host").map(Json::to_text) {
+        match level {
+            Some(level) => styles.paint_level(out, level, &host)?,
+            None => write!(out, "{}", host)?,
+        }
+        write!(out, ": ")?;
+        prefix_width += display_width(&host) + 2;
+    }
+    let short = record.get("short_message").map(Json::to_text).unwrap_or_default();
+    let short = match hl.unescape {
+        true => unescape_message(&short).into_owned(),
+        false => short,
+    };
+    let mut lines = short.split('\n');
+    write_level_highlight(out, lines.next().unwrap_or(""), hl)?;
+    writeln!(out)?;
+    write_message_lines(out, lines, prefix_width, level, styles)?;
+
+    if !hl.verbose {
+        return Ok(());
+    }
+    let Json::Object(fields) = record else {
+        return Ok(());
+    };
+    let extra = fields.iter().filter(|(key, _)| key == "full_message" || key.starts_with('_'));
+    for (key, value) in extra {
+        let mut lines = value.to_text();
+        if hl.unescape {
+            lines = unescape_message(&lines).into_owned();
+        }
+        // indent the rest of a multi-line value under its first line
+        lines = lines.replace('\n', &format!("\n{:width$}", "", width = display_width(key) + 4));
+        styles.paint(out, &styles.dim, &format!("  {}: {}", key, lines))?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// `--unescape` shows no more lines than this of one message.
+const UNESCAPE_MAX_LINES: usize = 500;
+
+/// how far `--unescape` indents the lines after the first of a JSON line's
+/// message.
+const UNESCAPE_INDENT: usize = 4;
+
+/// top-level keys of a JSON line whose string value `--unescape` expands.
+const JSON_MESSAGE_KEYS: [&str; 8] =
+    ["msg", "message", "error", "err", "exception", "stack", "stacktrace", "stack_trace"];
+
+/// `--unescape`: `\n`, `\t`, `\r`, `\"` and `\\` in `text` as the
+/// characters, as a message encoded twice on its way into JSON leaves them.
+/// past `UNESCAPE_MAX_LINES` lines the rest is counted instead.
+fn unescape_message(text: &str) -> std::borrow::Cow<'_, str> {
+    if !text.contains(['\\', '\n']) {
+        return std::borrow::Cow::Borrowed(text);
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let escaped = match (c, chars.peek()) {
+            ('\\', Some('n')) => '\n',
+            ('\\', Some('t')) => '\t',
+            ('\\', Some('r')) => '\r',
+            ('\\', Some(&c @ ('"' | '\\'))) => c,
+            _ => {
+                out.push(c);
+                continue;
+            }
+        };
+        chars.next();
+        out.push(escaped);
+    }
+    let lines = out.split('\n').count();
+    if lines > UNESCAPE_MAX_LINES {
+        let kept = out.split('\n').take(UNESCAPE_MAX_LINES).map(str::len).sum::<usize>();
+        out.truncate(kept + UNESCAPE_MAX_LINES - 1);
+        let cut = with_commas(lines - UNESCAPE_MAX_LINES);
+        out.push_str(&format!("\n\u{22ef} {} more lines cut by --unescape \u{22ef}", cut));
+    }
+    std::borrow::Cow::Owned(out)
+}
+
+/// the lines after the first of a multi-line message, each indented by
+/// `indent` and in the record's level color.
+fn write_message_lines<'a>(
+    out: &mut dyn Write,
+    lines: impl Iterator<Item = &'a str>,
+    indent: usize,
+    level: Option<Level>,
+    styles: &Styles,
+) -> io::Result<()> {
+    for line in lines {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        write!(out, "{:width$}", "", width = indent)?;
+        match level {
+            Some(level) => styles.paint(out, styles.color(level), line)?,
+            None => write!(out, "{}", line)?,
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+/// the byte ranges inside the quotes of the string values of a JSON line's
+/// top-level `JSON_MESSAGE_KEYS`.
+fn json_message_spans(line: &str) -> Vec<std::ops::Range<usize>> {
+    json_string_values(line, &JSON_MESSAGE_KEYS)
+}
+
+/// looks like a JSON object, before anything is parsed.
+fn json_object_line(line: &str) -> bool {
+    let line = line.trim();
+    line.starts_with('{') && line.ends_with('}')
+}
+
+/// the level a JSON object line gives under the first of its
+/// `JSON_LEVEL_KEYS` found, and where the value is; `None` for the lines
+/// that aren't one, malformed ones included, and those without a level.
+fn json_level(line: &str) -> Option<(std::ops::Range<usize>, Level)> {
+    if !json_object_line(line) {
+        return None;
+    }
+    json_string_values(line, &JSON_LEVEL_KEYS).into_iter().find_map(|value| {
+        let level = token_level(&line[value.clone()].to_uppercase())?;
+        Some((value, level))
+    })
+}
+
+/// the byte ranges inside the quotes of the string values of a JSON line's
+/// top-level `keys`, by a scan that doesn't need the line to parse.
+fn json_string_values(line: &str, keys: &[&str]) -> Vec<std::ops::Range<usize>> {
+    let bytes = line.as_bytes();
+    let mut spans = Vec::new();
+    let mut depth = 0usize;
+    // the last key read at the top level, and whether its value is next
+    let mut key = None;
+    let mut value_next = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' => {
+                let start = i + 1;
+                let mut end = start;
+                while end < bytes.len() && bytes[end] != b'"' {
+                    end += if bytes[end] == b'\\' { 2 } else { 1 };
+                }
+                let end = end.min(bytes.len());
+                if depth == 1 && value_next {
+                    if key.is_some_and(|key: &str| keys.contains(&key)) {
+                        spans.push(start..end);
+                    }
+                    value_next = false;
+                } else if depth == 1 {
+                    key = Some(&line[start..end]);
+                }
+                i = end + 1;
+                continue;
+            }
+            b'{' | b'[' => {
+                depth += 1;
+                value_next = false;
+            }
+            b'}' | b']' => depth = depth.saturating_sub(1),
+            b':' if depth == 1 => value_next = true,
+            b' ' | b'\t' => {}
+            _ => value_next = false,
+        }
+        i += 1;
+    }
+    spans
+}
+
+/// `--unescape` for a JSON line rendered as it is: its message values
+/// unescaped in place as far as their first lines, and the lines after them
+/// returned to go under it. `None` when there's nothing to unescape.
+fn unescape_json_line(line: &str) -> Option<(String, Vec<String>)> {
+    if !line.trim_start().starts_with('{') {
+        return None;
+    }
+    let mut first = String::with_capacity(line.len());
+    let mut rest = Vec::new();
+    let mut copied = 0;
+    for span in json_message_spans(line) {
+        let Some(Json::String(value)) = parse_json(&format!("\"{}\"", &line[span.clone()])) else {
+            continue;
+        };
+        let value = unescape_message(&value);
+        if value == line[span.clone()] {
+            continue;
+        }
+        let mut lines = value.split('\n');
+        let head = lines.next().unwrap_or_default();
+        first.push_str(&line[copied..span.start]);
+        first.push_str(head.strip_suffix('\r').unwrap_or(head));
+        rest.extend(lines.map(str::to_string));
+        copied = span.end;
+    }
+    if copied == 0 {
+        return None;
+    }
+    first.push_str(&line[copied..]);
+    Some((first, rest))
+}
+
+/// keys whose value is the record's severity, for `--json-fields`.
+const JSON_LEVEL_KEYS: [&str; 5] = ["level", "lvl", "severity", "loglevel", "log.level"];
+
+/// one `key=value` of a logfmt line; `value` is inside any quotes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct LogfmtPair {
+    key: std::ops::Range<usize>,
+    value: std::ops::Range<usize>,
+}
+
+/// the `key=value` pairs of a logfmt line. words that aren't pairs, like
+/// free text at the end of the line, are passed over; a quoted value runs
+/// to its closing quote, spaces and `\"` inside it included.
+fn logfmt_pairs(line: &str) -> Vec<LogfmtPair> {
+    let bytes = line.as_bytes();
+    let word_end = |from: usize| from + bytes[from..].iter().take_while(|b| **b != b' ').count();
+    let mut pairs = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b' ' {
+            i += 1;
+            continue;
+        }
+        let key_start = i;
+        let key_len = match bytes[i] {
+            b if b.is_ascii_alphanumeric() || b == b'_' => bytes[i..]
+                .iter()
+                .take_while(|&&b| b.is_ascii_alphanumeric() || b"_.-/@".contains(&b))
+                .count(),
+            _ => 0,
+        };
+        if key_len == 0 || bytes.get(i + key_len) != Some(&b'=') {
+            i = word_end(i);
+            continue;
+        }
+        let key = key_start..key_start + key_len;
+        i += key_len + 1;
+        let value = if bytes.get(i) == Some(&b'"') {
+            let mut end = i + 1;
+            while end < bytes.len() && bytes[end] != b'"' {
+                end += if bytes[end] == b'\\' { 2 } else { 1 };
+            }
+            let value = i + 1..end.min(bytes.len());
+            i = word_end(value.end);
+            value
+        } else {
+            let value = i..word_end(i);
+            i = value.end;
+            value
+        };
+        pairs.push(LogfmtPair { key, value });
+    }
+    pairs
+}
+
+/// the value of the level key of a logfmt line, and the level it names.
+/// unless `forced` by `--format logfmt` the line takes two pairs or more,
+/// so that prose with an `=` in it isn't taken for logfmt.
+fn logfmt_level(
+    line: &str,
+    pairs: &[LogfmtPair],
+    forced: bool,
+) -> Option<(std::ops::Range<usize>, Level)> {
+    if pairs.len() < 2 && !forced {
+        return None;
+    }
+    pairs.iter().find_map(|pair| {
+        let key = &line[pair.key.clone()];
+        JSON_LEVEL_KEYS.iter().find(|k| k.eq_ignore_ascii_case(key))?;
+        let level = token_level(&line[pair.value.clone()].to_uppercase())?;
+        Some((pair.value.clone(), level))
+    })
+}
+
+/// a logfmt line's level value in its color, its message values bold and
+/// with `--logfmt-keys dim` the keys dimmed.
+fn logfmt_marks(
+    line: &str,
+    pairs: &[LogfmtPair],
+    level: Option<(std::ops::Range<usize>, Level)>,
+    hl: &Highlighter,
+) -> Vec<Span> {
+    let styles = &hl.loaded().styles;
+    let mut marks = Vec::new();
+    if let Some((value, level)) = level {
+        marks.push(Span::new(value, styles.level_style(level, BOLD), SpanKind::Level));
+    }
+    let bold = Style { attrs: vec![BOLD], fg: None };
+    for pair in pairs {
+        if hl.dim_logfmt_keys {
+            let key = pair.key.start..pair.key.end + 1;
+            marks.push(Span::new(key, styles.dim_style(), SpanKind::Plain));
+        }
+        let key = &line[pair.key.clone()];
+        if JSON_MESSAGE_KEYS.iter().any(|k| k.eq_ignore_ascii_case(key)) {
+            marks.push(Span::new(pair.value.clone(), bold.clone(), SpanKind::Message));
+        }
+    }
+    if let Some(range) = timestamp_range(line) {
+        marks.push(Span::new(range, Style::default(), SpanKind::Timestamp));
+    }
+    marks
+}
+
+/// `--json-fields` and `--json-rest`.
+struct JsonFields {
+    fields: Vec<String>,
+    /// append the fields that weren't asked for, dimmed.
+    rest: bool,
+}
+
+/// just the selected fields of a JSON record, in order and without their keys:
+/// the level colored, missing ones as `-`, objects and arrays compact. with
+/// `unescape`, what comes after the first line of a multi-line field is
+/// returned with the column it started at, to go under the line.
+fn write_json_fields(
+    out: &mut dyn Write,
+    record: &Json,
+    spec: &JsonFields,
+    styles: &Styles,
+    unescape: bool,
+) -> io::Result<Vec<(usize, String)>> {
+    let mut rest = Vec::new();
+    let mut col = 0;
+    for (i, field) in spec.fields.iter().enumerate() {
+        if i > 0 {
+            write!(out, " ")?;
+            col += 1;
+        }
+        let Some(value) = record.path(field) else {
+            styles.paint(out, &styles.dim, "-")?;
+            col += 1;
+            continue;
+        };
+        let mut text = value.to_text();
+        if unescape {
+            if let Some((first, more)) = unescape_message(&text).split_once('\n') {
+                rest.push((col, more.to_string()));
+                text = first.to_string();
+            }
+        }
+        col += display_width(&text);
+        let is_level = JSON_LEVEL_KEYS.contains(&field.as_str())
+            || field.rsplit_once('.').is_some_and(|(_, last)| JSON_LEVEL_KEYS.contains(&last));
+        match token_level(&text.to_uppercase()).filter(|_| is_level) {
+            Some(level) => styles.paint_level(out, level, &text)?,
+            None => write!(out, "{}", text)?,
+        }
+    }
+    if !spec.rest {
+        return Ok(rest);
+    }
+    let Json::Object(fields) = record else {
+        return Ok(rest);
+    };
+    for (key, value) in fields.iter().filter(|(key, _)| !spec.fields.contains(key)) {
+        let value = match value {
+            Json::String(s) if !s.contains(char::is_whitespace) && !s.is_empty() => s.clone(),
+            other => other.to_string(),
+        };
+        write!(out, " ")?;
+        styles.paint(out, &styles.dim, &format!("{}={}", key, value))?;
+    }
+    Ok(rest)
+}
+
+/// collects `journalctl -o export` records: `KEY=value` lines terminated by
+/// an empty line. binary fields (a bare `KEY` followed by a length-prefixed
+/// blob) cannot pass through the line reader and are ignored.
+#[derive(Default)]
+struct ExportRecord {
+    fields: Vec<(String, String)>,
+}
+
+impl ExportRecord {
+    fn is_field(line: &str) -> bool {
+        line.split_once('=').is_some_and(|(key, _)| {
+            !key.is_empty()
+                && key.bytes().all(|b| b.is_ascii_uppercase() || b.is_ascii_digit() || b == b'_')
+        })
+    }
+
+    fn push(&mut self, line: &str) {
+        if let Some((key, value)) = line.split_once('=') {
+            self.fields.push((key.to_string(), value.to_string()));
+        }
+    }
+
+    fn flush(&mut self, out: &mut dyn Write, hl: &Highlighter) -> io::Result<()> {
+        if self.fields.is_empty() {
+            return Ok(());
+        }
+        let fields = std::mem::take(&mut self.fields);
+        write_journal_entry(out, &fields, hl)
+    }
+}
+
+/// the generic path: bold and color the first level token found, or failing
+/// that underline a phrase the level can be inferred from.
+fn write_level_highlight(out: &mut dyn Write, line: &str, hl: &Highlighter) -> io::Result<()> {
+    if !hl.loaded().styles.enabled() {
+        return write!(out, "{}", line);
+    }
+    write_spans(out, line, &cover(line, level_marks(line, hl)), &hl.loaded().styles)
+}
+
+/// what `write_level_highlight` draws.
+fn level_marks(line: &str, hl: &Highlighter) -> Vec<Span> {
+    let styles = &hl.loaded().styles;
+    let mut marks = Vec::new();
+    let found = find_level(line);
+    let ruled = hl.settings.level(line);
+    if found.is_none() {
+        if let Some((range, level)) = infer_level(line, &hl.infer) {
+            let level = ruled.unwrap_or(level);
+            marks.push(Span::new(range, styles.level_style(level, UNDERLINE), SpanKind::Level));
+        }
+    }
+    if found.is_some() && hl.all_occurrences {
+        // the others in their own colors, but not bold, so the line's own
+        // level still stands out
+        for other in ranked_candidates(line).into_iter().skip(1) {
+            let range = other.pos..other.pos + other.token.len();
+            marks.push(Span::new(range, styles.level_style(other.level, ""), SpanKind::Level));
+        }
+    }
+    if let Some((pos, token)) = found {
+        // token may include bracket/colon; normalize to raw level text
+        let raw = token
+            .trim_matches(|c: char| {
+                c == '['
+                    || c == ']'
+                    || c == '('
+                    || c == ')'
+                    || c == ':'
+                    || c == '-'
+                    || c.is_whitespace()
+            })
+            .to_uppercase();
+        let end = pos + token.len();
+        if let Some(level) = ruled.or_else(|| token_level(&raw)) {
+            marks.push(Span::new(pos..end, styles.level_style(level, BOLD), SpanKind::Level));
+        }
+        if let Some(len) = line[end..].strip_prefix(' ').and_then(mysql_error_code_len) {
+            marks.push(Span::new(end + 1..end + 1 + len, styles.dim_style(), SpanKind::Entity));
+        }
+    }
+    marks
+}
+
+/// a JSON line's level value in its color, whatever else the line says,
+/// and its message values in bold.
+fn json_marks(line: &str, styles: &Styles) -> Vec<Span> {
+    let mut marks = Vec::new();
+    if let Some((value, level)) = json_level(line) {
+        marks.push(Span::new(value, styles.level_style(level, BOLD), SpanKind::Level));
+    }
+    let bold = Style { attrs: vec![BOLD], fg: None };
+    for message in json_message_spans(line).into_iter().filter(|m| !m.is_empty()) {
+        marks.push(Span::new(message, bold.clone(), SpanKind::Message));
+    }
+    marks
+}
+
+/// byte ranges of an env_logger or tracing line, e.g.
+/// `[2024-05-01T12:03:04Z ERROR my_crate::module] message` or
+/// `2024-05-01T12:03:04.123456Z ERROR span{a=1}: my_crate::module: message`.
+#[derive(Debug, PartialEq, Eq)]
+struct RustLine {
+    timestamp: Option<std::ops::Range<usize>>,
+    level_range: std::ops::Range<usize>,
+    level: Level,
+    /// tracing's span context, `outer{a=1}:inner`.
+    spans: Option<std::ops::Range<usize>>,
+    module: std::ops::Range<usize>,
+    msg_start: usize,
+}
+
+fn is_iso_timestamp(s: &str) -> bool {
+    let b = s.as_bytes();
+    b.len() >= 19
+        && b[..4].iter().all(u8::is_ascii_digit)
+        && b[4] == b'-'
+        && b[7] == b'-'
+        && b[10] == b'T'
+        && b[13] == b':'
+}
+
+fn is_rust_path(s: &str) -> bool {
+    !s.is_empty()
+        && s.split("::").all(|seg| {
+            seg.bytes().next().is_some_and(|b| b.is_ascii_alphabetic() || b == b'_')
+                && seg.bytes().all(is_word_byte)
+        })
+}
+
+fn rust_level(word: &str) -> Option<Level> {
+    match word {
+        "ERROR" => Some(Level::Error),
+        "WARN" => Some(Level::Warn),
+        "INFO" => Some(Level::Info),
+        "DEBUG" => Some(Level::Debug),
+        "TRACE" => Some(Level::Trace),
+        _ => None,
+    }
+}
+
+/// split off the next space-separated word at `pos`, skipping leading
+/// padding; returns its range.
+fn next_word(line: &str, mut pos: usize) -> Option<std::ops::Range<usize>> {
+    pos += line[pos..].bytes().take_while(|&b| b == b' ').count();
+    let len = line[pos..].find(' ').unwrap_or(line.len() - pos);
+    (len > 0).then_some(pos..pos + len)
+}
+
+fn parse_rust_line(line: &str) -> Option<RustLine> {
+    if let Some(inner) = line.strip_prefix('[') {
+        // env_logger: `[TIMESTAMP LEVEL target] msg`, timestamp optional
+        let close = inner.find(']')? + 1;
+        let mut ranges = Vec::new();
+        let mut pos = 1;
+        while let Some(word) = next_word(&line[..close], pos) {
+            pos = word.end;
+            ranges.push(word);
+        }
+        let (timestamp, level_range, module) = match ranges.as_slice() {
+            [ts, lvl, module] if is_iso_timestamp(&line[ts.clone()]) => {
+                (Some(ts.clone()), lvl.clone(), module.clone())
+            }
+            [lvl, module] => (None, lvl.clone(), module.clone()),
+            _ => return None,
+        };
+        let level = rust_level(&line[level_range.clone()])?;
+        if !is_rust_path(&line[module.clone()]) {
+            return None;
+        }
+        let msg_start = close + 1 + usize::from(line[close + 1..].starts_with(' '));
+        return Some(RustLine { timestamp, level_range, level, spans: None, module, msg_start });
+    }
+
+    // tracing: `TIMESTAMP LEVEL [spans: ]target: msg`, level padded to 5
+    let timestamp =
+        next_word(line, 0).filter(|r| r.start == 0 && is_iso_timestamp(&line[r.clone()]))?;
+    let level_range = next_word(line, timestamp.end)?;
+    let level = rust_level(&line[level_range.clone()])?;
+    let rest_start = level_range.end + 1;
+    let rest = line.get(rest_start..)?;
+    let first = rest.find(": ")?;
+    let (spans, module) = if rest[..first].contains('{') {
+        let target_start = first + 2;
+        let target_len = rest[target_start..].find(": ")?;
+        (Some(rest_start..rest_start + first), target_start..target_start + target_len)
+    } else {
+        (None, 0..first)
+    };
+    let module = rest_start + module.start..rest_start + module.end;
+    if !is_rust_path(&line[module.clone()]) {
+        return None;
+    }
+    let msg_start = module.end + 2;
+    Some(RustLine { timestamp: Some(timestamp), level_range, level, spans, module, msg_start })
+}
+
+/// `key=value` pairs in `text` (starting at byte `base` of the line), as the
+/// ranges of their `key=` part. keys are identifiers, possibly dotted like
+/// tracing's `time.busy`.
+fn kv_key_ranges(text: &str, base: usize) -> Vec<std::ops::Range<usize>> {
+    let bytes = text.as_bytes();
+    let mut ranges = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let at_word_start = i == 0 || !(is_word_byte(bytes[i - 1]) || bytes[i - 1] == b'.');
+        if at_word_start && (bytes[i].is_ascii_alphabetic() || bytes[i] == b'_') {
+            let len = bytes[i..].iter().take_while(|&&b| is_word_byte(b) || b == b'.').count();
+            let end = i + len;
+            if bytes.get(end) == Some(&b'=') && bytes.get(end + 1).is_some_and(|&b| b != b' ') {
+                ranges.push(base + i..base + end + 1);
+            }
+            i = end.max(i + 1);
+        } else {
+            i += 1;
+        }
+    }
+    ranges
+}
+
+/// dimmed timestamp, colored level, module in its own stable color, and
+/// the `key=` parts of span fields and message fields dimmed.
+fn rust_marks(line: &str, rec: &RustLine, styles: &Styles) -> Vec<Span> {
+    let mut marks = Vec::new();
+    if let Some(ts) = &rec.timestamp {
+        marks.push(Span::new(ts.clone(), styles.dim_style(), SpanKind::Timestamp));
+    }
+    let level = styles.level_style(rec.level, BOLD);
+    marks.push(Span::new(rec.level_range.clone(), level, SpanKind::Level));
+    let key = |range| Span::new(range, styles.dim_style(), SpanKind::Plain);
+    if let Some(spans) = &rec.spans {
+        marks.extend(kv_key_ranges(&line[spans.clone()], spans.start).into_iter().map(key));
+    }
+    let module = &line[rec.module.clone()];
+    let color = Style { attrs: Vec::new(), fg: Some(hash_color(module, styles.depth)) };
+    marks.push(Span::new(rec.module.clone(), color, SpanKind::Entity));
+    let msg_start = rec.msg_start.min(line.len());
+    marks.extend(kv_key_ranges(&line[msg_start..], msg_start).into_iter().map(key));
+    marks
+}
+
+/// where a line says it comes from: a module or class, or a source file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct SourceLocation {
+    range: std::ops::Range<usize>,
+    /// a `conn.go:142` rather than a module or class name.
+    file: bool,
+}
+
+impl SourceLocation {
+    /// what the location is matched and colored by: a file loses its line
+    /// number, so every line from it looks the same.
+    fn name<'a>(&self, line: &'a str) -> &'a str {
+        let text = &line[self.range.clone()];
+        let numbered = |n: &str| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit());
+        match text.rsplit_once(':') {
+            Some((file, number)) if self.file && numbered(number) => file,
+            _ => text,
+        }
+    }
+}
+
+/// the source location of `line`, for the formats that have an unmistakable
+/// one: a Rust line's module, a glog line's `conn.go:142`, the class after a
+/// log4j-style level (`c.x.y.Service - ...`), or a JSON record's `logger` or
+/// `caller` field.
+fn source_location(line: &str, format: InputFormat) -> Option<SourceLocation> {
+    if matches!(format, InputFormat::Auto | InputFormat::Rust) {
+        if let Some(rec) = parse_rust_line(line).filter(|rec| !rec.module.is_empty()) {
+            return Some(SourceLocation { range: rec.module, file: false });
+        }
+    }
+    if line.trim_start().starts_with('{') {
+        return json_location(line, "logger", false)
+            .or_else(|| json_location(line, "caller", true));
+    }
+    glog_location(line).or_else(|| class_location(line))
+}
+
+/// the string value of `key` in a one-line JSON record, found in the text
+/// so it can be colored where it is; one with escapes in it is skipped.
+fn json_location(line: &str, key: &str, file: bool) -> Option<SourceLocation> {
+    let quoted = format!("\"{}\"", key);
+    let after = line.find(&quoted)? + quoted.len();
+    let rest = line[after..].trim_start().strip_prefix(':')?.trim_start();
+    let start = line.len() - rest.strip_prefix('"')?.len();
+    let len =
+        line[start..].find(['"', '\\']).filter(|&len| line[start + len..].starts_with('"'))?;
+    (len > 0).then(|| SourceLocation { range: start..start + len, file })
+}
+
+/// `I0501 12:03:04.123456  4242 conn.go:142] message`: the `file:line`
+/// closing glog's header.
+fn glog_location(line: &str) -> Option<SourceLocation> {
+    let b = line.as_bytes();
+    let leveled = b.len() > 6 && b"DIWEF".contains(&b[0]) && b[5] == b' ';
+    if !leveled || !b[1..5].iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let close = line.find(']')?;
+    let mut header = line[6..close].split_whitespace();
+    let (time, thread, location) = (header.next()?, header.next()?, header.next()?);
+    let (file, number) = location.rsplit_once(':')?;
+    let timed = time.len() >= 8 && time.as_bytes()[2] == b':';
+    let numbered = |n: &str| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit());
+    if !timed
+        || !numbered(thread)
+        || !numbered(number)
+        || file.is_empty()
+        || header.next().is_some()
+    {
+        return None;
+    }
+    Some(SourceLocation { range: close - location.len()..close, file: true })
+}
+
+/// the dotted class name after a level token, past a pid, Spring's `---`
+/// and a `[thread]`, when a ` - ` or `: ` ends it: log4j, logback and Spring
+/// Boot's default layouts.
+fn class_location(line: &str) -> Option<SourceLocation> {
+    let (pos, token) = find_level(line)?;
+    let mut start = pos + token.len();
+    loop {
+        let rest = &line[start..];
+        let word = rest.trim_start();
+        let skipped = rest.len() - word.len();
+        let len = match word.as_bytes().first()? {
+            b'[' => word.find(']')? + 1,
+            _ => word.find(' ').unwrap_or(word.len()),
+        };
+        let skip = &word[..len];
+        if skip.starts_with('[') || skip == "---" || skip.bytes().all(|b| b.is_ascii_digit()) {
+            start += skipped + len;
+            continue;
+        }
+        start += skipped;
+        break;
+    }
+    let rest = &line[start..];
+    let len = rest.find([' ', ':']).unwrap_or(rest.len());
+    let class = &rest[..len];
+    let segment = |s: &str| {
+        s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_' || c == '$')
+            && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+    };
+    let after = rest[len..].trim_start_matches(' ');
+    let ended = after.is_empty() || after.starts_with("- ") || after.starts_with(':');
+    (class.contains('.') && class.split('.').all(segment) && ended)
+        .then(|| SourceLocation { range: start..start + len, file: false })
+}
+
+fn print_colored_line(out: &mut dyn Write, line: &str, hl: &Highlighter) -> io::Result<()> {
+    render_line(out, line, hl, hl.format)
+}
+
+/// like `print_colored_line`, but with `format` overriding `hl.format`, as
+/// decided by sniffing the start of the stream.
+fn render_line(
+    out: &mut dyn Write,
+    line: &str,
+    hl: &Highlighter,
+    format: InputFormat,
+) -> io::Result<()> {
+    if hl.keep_ansi && line.contains('\x1b') {
+        return writeln!(out, "{}", line);
+    }
+    // the structured renderings unescape their own messages
+    let as_is =
+        hl.json_fields.is_none() && !matches!(format, InputFormat::Journald | InputFormat::Gelf);
+    match unescape_json_line(line).filter(|_| hl.unescape && as_is) {
+        Some((first, rest)) => {
+            render_one_line(out, &first, hl, format)?;
+            let lines = rest.iter().map(String::as_str);
+            write_message_lines(
+                out,
+                lines,
+                UNESCAPE_INDENT,
+                level_of(line, hl),
+                &hl.loaded().styles,
+            )
+        }
+        None => render_one_line(out, line, hl, format),
+    }
+}
+
+fn render_one_line(
+    out: &mut dyn Write,
+    line: &str,
+    hl: &Highlighter,
+    format: InputFormat,
+) -> io::Result<()> {
+    let drawn = match hl.settings.has_rules() {
+        false => render_record(out, line, hl, format)?,
+        true => {
+            // not drawn from spans, so the highlights go on afterwards
+            let mut highlighted = LineWriter::new(&mut *out, |line: &str| {
+                Some(apply_highlights(line, &hl.loaded().rules, &hl.loaded().styles))
+            });
+            render_record(&mut highlighted, line, hl, format)?
+        }
+    };
+    if drawn {
+        return Ok(());
+    }
+    if !hl.loaded().styles.enabled() {
+        let spans = hl.spans_as(line, format);
+        match name_pri(line, &spans, &hl.loaded().styles).filter(|_| hl.syslog_names) {
+            Some((named, spans)) => write_plain_spans(out, &named, &spans)?,
+            None => write_plain_spans(out, line, &spans)?,
+        }
+        return writeln!(out);
+    }
+    if let Some(column) = &hl.level_column {
+        // the column is the only say; lines without it are unleveled
+        let marks = column.level(line).map(|(field, level)| {
+            Span::new(field, hl.loaded().styles.level_style(level, BOLD), SpanKind::Level)
+        });
+        let spans = hl.ruled(line, cover(line, marks.into_iter().collect()));
+        write_spans(out, line, &spans, &hl.loaded().styles)?;
+        return writeln!(out);
+    }
+    let spans = hl.spans_as(line, format);
+    match name_pri(line, &spans, &hl.loaded().styles).filter(|_| hl.syslog_names) {
+        Some((named, spans)) => write_spans(out, &named, &spans, &hl.loaded().styles)?,
+        None => write_spans(out, line, &spans, &hl.loaded().styles)?,
+    }
+    writeln!(out)
+}
+
+/// `line` as one of the structured renderings draws it, if it is a record
+/// of theirs: whether it was.
+fn render_record(
+    out: &mut dyn Write,
+    line: &str,
+    hl: &Highlighter,
+    format: InputFormat,
+) -> io::Result<bool> {
+    if format == InputFormat::Journald {
+        if let Some(fields) = parse_json(line).as_ref().and_then(journal_fields_from_json) {
+            return write_journal_entry(out, &fields, hl).map(|_| true);
+        }
+    }
+    if format == InputFormat::Gelf {
+        if let Some(record) = parse_json(line).filter(|r| matches!(r, Json::Object(_))) {
+            return write_gelf_record(out, &record, hl).map(|_| true);
+        }
+    }
+    if format == InputFormat::Json && hl.json_pretty {
+        let record = json_object_line(line).then(|| parse_json(line)).flatten();
+        if let Some(record) = record.filter(|r| matches!(r, Json::Object(_))) {
+            let loaded = hl.loaded();
+            let tint = json_level(line).map(|(_, level)| loaded.styles.color(level));
+            write_json_pretty(out, &record, &loaded.styles, 0, false, tint)?;
+            writeln!(out)?;
+            return Ok(true);
+        }
+    }
+    if let Some(spec) = &hl.json_fields {
+        let record = line.trim_start().starts_with('{').then(|| parse_json(line)).flatten();
+        if let Some(record) = record.filter(|r| matches!(r, Json::Object(_))) {
+            let rest = write_json_fields(out, &record, spec, &hl.loaded().styles, hl.unescape)?;
+            writeln!(out)?;
+            let level = level_of(line, hl);
+            for (col, more) in rest {
+                write_message_lines(out, more.split('\n'), col, level, &hl.loaded().styles)?;
+            }
+            return Ok(true);
+        }
+    }
+    if let Some(columns) = &hl.columns {
+        write_columns(out, &line_cells(line, format, &hl.infer), columns, hl)?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+impl Highlighter {
+    /// how `line` is drawn, `--rules` highlights included, as spans that
+    /// are in order, don't overlap and cover it.
+    ///
+    /// ```
+    /// use logcolor::{Highlighter, SpanKind};
+    ///
+    /// let hl = Highlighter::builder().color(true).build();
+    /// let line = "WARN: slow";
+    /// let spans = hl.spans(line);
+    /// assert_eq!(&line[spans[0].range.clone()], "WARN:");
+    /// assert_eq!(spans[0].kind, SpanKind::Level);
+    /// ```
+    pub fn spans(&self, line: &str) -> Vec<Span> {
+        self.spans_as(line, self.format)
+    }
+
+    /// `spans`, with `format` overriding `self.format`.
+    fn spans_as(&self, line: &str, format: InputFormat) -> Vec<Span> {
+        self.ruled(line, line_spans(line, self, format))
+    }
+
+    /// `--rules` highlights drawn over `spans`, which cover `line`.
+    fn ruled(&self, line: &str, mut spans: Vec<Span>) -> Vec<Span> {
+        let loaded = self.loaded();
+        let found = loaded.rules.iter().filter(|r| matches!(r.action, RuleAction::Highlight));
+        let found = found.filter_map(|r| Some((r.matcher.find(line)?, &r.style)));
+        let highlights: Vec<_> = found.collect();
+        // the rule that starts first wins, as when they restyle a line
+        let mut taken: Vec<std::ops::Range<usize>> = Vec::new();
+        for (range, style) in highlights {
+            if taken.iter().any(|t| t.start < range.end && range.start < t.end) {
+                continue;
+            }
+            taken.push(range.clone());
+            spans = overlay(spans, Span::new(range, style.clone(), SpanKind::Highlight));
+        }
+        spans
+    }
+}
+
+/// the spans of the line-based formats; what `render_line` draws once color
+/// is on and the line isn't a structured record.
+fn line_spans(line: &str, hl: &Highlighter, format: InputFormat) -> Vec<Span> {
+    let spans = format_spans(line, hl, format);
+    match &hl.extras {
+        Some(extras) => extra_spans(line, spans, extras, &hl.loaded().styles),
+        None => spans,
+    }
+}
+
+/// `line_spans` before `--extras`.
+fn format_spans(line: &str, hl: &Highlighter, format: InputFormat) -> Vec<Span> {
+    if format == InputFormat::Rust {
+        if let Some(rec) = parse_rust_line(line) {
+            return cover(line, rust_marks(line, &rec, &hl.loaded().styles));
+        }
+    }
+    let rfc5424 = match format {
+        InputFormat::Auto => parse_rfc5424(line, true),
+        InputFormat::Rfc5424 => parse_rfc5424(line, false),
+        InputFormat::Plain
+        | InputFormat::Journald
+        | InputFormat::Gelf
+        | InputFormat::Rust
+        | InputFormat::K8s
+        | InputFormat::Compose
+        | InputFormat::Json
+        | InputFormat::Logfmt => None,
+    };
+    if let Some(rec) = rfc5424 {
+        return cover(line, rfc5424_marks(line, &rec, hl));
+    }
+    let json = match format {
+        InputFormat::Json => json_object_line(line),
+        InputFormat::Auto => json_level(line).is_some(),
+        _ => false,
+    };
+    if json {
+        return cover(line, json_marks(line, &hl.loaded().styles));
+    }
+    let forced = format == InputFormat::Logfmt;
+    if (forced || format == InputFormat::Auto) && line.contains('=') {
+        let pairs = logfmt_pairs(line);
+        let level = logfmt_level(line, &pairs, forced);
+        if level.is_some() || forced && !pairs.is_empty() {
+            return cover(line, logfmt_marks(line, &pairs, level, hl));
+        }
+    }
+    let mut marks = level_marks(line, hl);
+    if let Some((end, severity)) = parse_pri(line) {
+        // a BSD syslog line, or one that isn't quite RFC 5424
+        marks.push(pri_mark(end, severity, &hl.loaded().styles));
+    }
+    if let Some(range) = timestamp_range(line) {
+        marks.push(Span::new(range, Style::default(), SpanKind::Timestamp));
+    }
+    let mut spans = cover(line, marks);
+    if let Some(location) = source_location(line, format).filter(|_| !line.contains('\x1b')) {
+        // the location in its own stable color, over the level's
+        let color = hash_color(location.name(line), hl.loaded().styles.depth);
+        let color = Style { attrs: Vec::new(), fg: Some(color) };
+        spans = overlay(spans, Span::new(location.range, color, SpanKind::Entity));
+    }
+    spans
+}
+
+/// what `--extras` sets off in a line besides its level.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Extra {
+    Url,
+    Uuid,
+    Ip,
+    Duration,
+    Hex,
+}
+
+impl Extra {
+    /// in the order they're looked for, an earlier one winning where two
+    /// overlap: the host of a URL is part of the URL, not an IP of its own.
+    pub const ALL: [Extra; 5] = [Extra::Url, Extra::Uuid, Extra::Ip, Extra::Duration, Extra::Hex];
+
+    pub fn parse(name: &str) -> Option<Extra> {
+        match name {
+            "url" => Some(Extra::Url),
+            "uuid" => Some(Extra::Uuid),
+            "ip" => Some(Extra::Ip),
+            "duration" => Some(Extra::Duration),
+            "hex" => Some(Extra::Hex),
+            _ => None,
+        }
+    }
+
+    fn ranges(self, line: &str, extras: &Extras) -> Vec<std::ops::Range<usize>> {
+        let b = line.as_bytes();
+        let at = |i: usize| match self {
+            Extra::Url => url_at(b, i),
+            Extra::Uuid => uuid_at(b, i),
+            Extra::Ip => ipv4_at(b, i, extras.versions).or_else(|| ipv6_at(b, i)),
+            Extra::Duration => duration_at(b, i),
+            Extra::Hex => hex_id_at(b, i),
+        };
+        let mut found = Vec::new();
+        let mut i = 0;
+        while i < b.len() {
+            match at(i) {
+                Some(end) => {
+                    found.push(i..end);
+                    i = end;
+                }
+                None => i += 1,
+            }
+        }
+        found
+    }
+}
+
+/// `--extras`: which of them, and how an IPv4 address is told from a
+/// version.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Extras {
+    /// in `Extra::ALL`'s order.
+    kinds: Vec<Extra>,
+    /// dotted quads named as versions count as addresses; not with
+    /// `--no-version-ips`.
+    versions: bool,
+}
+
+impl Extras {
+    /// what `line` has of them, in no particular order, none overlapping.
+    fn find(&self, line: &str) -> Vec<(std::ops::Range<usize>, Extra)> {
+        let mut found: Vec<(std::ops::Range<usize>, Extra)> = Vec::new();
+        for &extra in &self.kinds {
+            for range in extra.ranges(line, self) {
+                if !found.iter().any(|(r, _)| r.start < range.end && range.start < r.end) {
+                    found.push((range, extra));
+                }
+            }
+        }
+        found
+    }
+}
+
+/// `spans` with the `--extras` in their unstyled stretches set off. an
+/// extra reaching into anything styled already, like the level token or a
+/// timestamp, is left out rather than cut short.
+fn extra_spans(line: &str, spans: Vec<Span>, extras: &Extras, styles: &Styles) -> Vec<Span> {
+    let plain: Vec<_> = spans
+        .iter()
+        .filter(|span| span.kind == SpanKind::Plain && span.style == Style::default())
+        .map(|span| span.range.clone())
+        .collect();
+    let mut spans = spans;
+    for (range, extra) in extras.find(line) {
+        if plain.iter().any(|p| p.start <= range.start && range.end <= p.end) {
+            spans = overlay(spans, Span::new(range, styles.extra_style(extra), SpanKind::Extra));
+        }
+    }
+    spans
+}
+
+/// an extra starts at `i` only where a word doesn't already go on.
+fn starts_word(b: &[u8], i: usize) -> bool {
+    i == 0 || !is_word_byte(b[i - 1])
+}
+
+/// the end of an `http://` or `https://` URL at `i`, less any punctuation
+/// after it that belongs to the sentence.
+fn url_at(b: &[u8], i: usize) -> Option<usize> {
+    if !starts_word(b, i) {
+        return None;
+    }
+    let scheme = [&b"https://"[..], b"http://"]
+        .into_iter()
+        .find(|s| b[i..].get(..s.len()).is_some_and(|p| p.eq_ignore_ascii_case(s)))?;
+    let host = i + scheme.len();
+    let mut end = host;
+    while end < b.len() && !b[end].is_ascii_whitespace() && !b"\"'<>`".contains(&b[end]) {
+        end += 1;
+    }
+    let unbalanced = |url: &[u8], open: u8, close: u8| {
+        url.iter().filter(|&&c| c == close).count() > url.iter().filter(|&&c| c == open).count()
+    };
+    while end > host {
+        let url = &b[i..end];
+        let trailing = match b[end - 1] {
+            b'.' | b',' | b';' | b':' | b'!' | b'?' => true,
+            b')' => unbalanced(url, b'(', b')'),
+            b']' => unbalanced(url, b'[', b']'),
+            _ => false,
+        };
+        if !trailing {
+            break;
+        }
+        end -= 1;
+    }
+    (end > host).then_some(end)
+}
+
+/// the end of a UUID, 8-4-4-4-12 hex digits, at `i`.
+fn uuid_at(b: &[u8], i: usize) -> Option<usize> {
+    if !starts_word(b, i) || i > 0 && b[i - 1] == b'-' {
+        return None;
+    }
+    let mut pos = i;
+    for (n, len) in [8, 4, 4, 4, 12].into_iter().enumerate() {
+        if n > 0 {
+            (b.get(pos) == Some(&b'-')).then_some(())?;
+            pos += 1;
+        }
+        b.get(pos..pos + len).filter(|group| group.iter().all(u8::is_ascii_hexdigit))?;
+        pos += len;
+    }
+    let ends = b.get(pos).is_none_or(|&c| !is_word_byte(c) && c != b'-');
+    ends.then_some(pos)
+}
+
+/// the end of a dotted quad at `i`, each part a number up to 255. one in a
+/// longer run of dotted numbers isn't; one named as a version, like
+/// `version 1.2.3.4` or `app_version=1.2.3.4`, only when `versions`.
+fn ipv4_at(b: &[u8], i: usize, versions: bool) -> Option<usize> {
+    if !starts_word(b, i) || i > 0 && b[i - 1] == b'.' {
+        return None;
+    }
+    let mut pos = i;
+    for n in 0..4 {
+        if n > 0 {
+            (b.get(pos) == Some(&b'.')).then_some(())?;
+            pos += 1;
+        }
+        let digits = b[pos..].iter().take(4).take_while(|c| c.is_ascii_digit()).count();
+        let part = std::str::from_utf8(&b[pos..pos + digits]).ok()?;
+        part.parse::<u8>().ok().filter(|_| (1..=3).contains(&digits))?;
+        pos += digits;
+    }
+    let more = b.get(pos) == Some(&b'.') && b.get(pos + 1).is_some_and(u8::is_ascii_digit);
+    if more || b.get(pos).is_some_and(|&c| is_word_byte(c)) {
+        return None;
+    }
+    if !versions {
+        let before = String::from_utf8_lossy(&b[..i]);
+        let word = before.trim_end_matches([' ', '=', ':']);
+        let word =
+            &word[word.len() - word.bytes().rev().take_while(|&c| is_word_byte(c)).count()..];
+        let word = word.to_ascii_lowercase();
+        if word.ends_with("version") || word == "ver" || word == "v" {
+            return None;
+        }
+    }
+    Some(pos)
+}
+
+/// the end of an IPv6 address at `i`: eight groups of up to four hex
+/// digits, or fewer around one `::`, the last two maybe a dotted quad.
+/// with a `::`, a decimal digit too, so `cafe::face` and Rust paths aren't.
+fn ipv6_at(b: &[u8], i: usize) -> Option<usize> {
+    if i > 0 && (is_word_byte(b[i - 1]) || b[i - 1] == b':') {
+        return None;
+    }
+    let mut end = i;
+    while end < b.len() && (b[end].is_ascii_hexdigit() || b[end] == b':' || b[end] == b'.') {
+        end += 1;
+    }
+    if b.get(end).is_some_and(|&c| is_word_byte(c)) {
+        return None;
+    }
+    // a sentence's full stop or colon after it
+    while end > i && (b[end - 1] == b'.' || b[end - 1] == b':' && !b[..end].ends_with(b"::")) {
+        end -= 1;
+    }
+    let text = std::str::from_utf8(&b[i..end]).ok()?;
+    let compressed = text.matches("::").count();
+    if compressed > 1 || text.contains(":::") || text.matches(':').count() < 2 {
+        return None;
+    }
+    if compressed == 1 && !text.bytes().any(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let parts: Vec<&str> = text.split(':').collect();
+    let mut groups = 0;
+    for (n, part) in parts.iter().enumerate() {
+        let last = n + 1 == parts.len();
+        if part.is_empty() {
+            // only the `::`, with its empty part at either end
+            let edge = n == 0 || last;
+            let beside =
+                parts.get(n + 1).is_some_and(|p| p.is_empty()) || n > 0 && parts[n - 1].is_empty();
+            (compressed == 1 && (beside || !edge)).then_some(())?;
+            continue;
+        }
+        if last && part.contains('.') {
+            ipv4_at(part.as_bytes(), 0, true).filter(|&e| e == part.len())?;
+            groups += 2;
+            continue;
+        }
+        (part.len() <= 4 && part.bytes().all(|c| c.is_ascii_hexdigit())).then_some(())?;
+        groups += 1;
+    }
+    let fits = if compressed == 1 { groups <= 7 } else { groups == 8 };
+    fits.then_some(end)
+}
+
+/// duration units, longer ones first where one starts another.
+const DURATION_UNITS: [&str; 9] = ["ns", "us", "\u{b5}s", "ms", "min", "s", "m", "h", "d"];
+
+/// the end of a duration like `153ms`, `2.5s` or Go's `1h2m3.5s` at `i`.
+fn duration_at(b: &[u8], i: usize) -> Option<usize> {
+    if !starts_word(b, i) || i > 0 && b[i - 1] == b'.' {
+        return None;
+    }
+    let mut pos = i;
+    loop {
+        let whole = b[pos..].iter().take_while(|c| c.is_ascii_digit()).count();
+        if whole == 0 {
+            return None;
+        }
+        pos += whole;
+        if b.get(pos) == Some(&b'.') {
+            let fraction = b[pos + 1..].iter().take_while(|c| c.is_ascii_digit()).count();
+            if fraction == 0 {
+                return None;
+            }
+            pos += 1 + fraction;
+        }
+        let unit = DURATION_UNITS.iter().find(|unit| b[pos..].starts_with(unit.as_bytes()))?;
+        pos += unit.len();
+        match b.get(pos) {
+            Some(c) if c.is_ascii_digit() => continue,
+            Some(&c) if is_word_byte(c) => return None,
+            _ => return Some(pos),
+        }
+    }
+}
+
+/// the fewest digits a bare hex id has, as a span id's 16; a `0x` value
+/// needs only 8.
+const HEX_ID_DIGITS: usize = 16;
+
+/// the end of a long hex identifier at `i`: a trace id, a commit, a
+/// pointer. a bare one needs a letter in it, or it's just a big number.
+fn hex_id_at(b: &[u8], i: usize) -> Option<usize> {
+    if !starts_word(b, i) {
+        return None;
+    }
+    let prefixed = b[i..].len() > 2 && b[i] == b'0' && matches!(b[i + 1], b'x' | b'X');
+    let start = if prefixed { i + 2 } else { i };
+    let digits = b[start..].iter().take_while(|c| c.is_ascii_hexdigit()).count();
+    let end = start + digits;
+    if b.get(end).is_some_and(|&c| is_word_byte(c)) {
+        return None;
+    }
+    let long = digits >= if prefixed { HEX_ID_DIGITS / 2 } else { HEX_ID_DIGITS };
+    let letters = prefixed || b[start..end].iter().any(u8::is_ascii_alphabetic);
+    (long && letters).then_some(end)
+}
+
+/// the widest a timestamp cell gets, `2024-05-01T12:03:04.123Z`.
+const COLUMN_TIMESTAMP_WIDTH: usize = 24;
+/// `ERROR`, `DEBUG` and the like; the level names are never longer.
+const COLUMN_LEVEL_WIDTH: usize = 5;
+const DEFAULT_SOURCE_WIDTH: usize = 16;
+/// what the message column keeps before the other columns shrink.
+const COLUMN_MIN_MESSAGE: usize = 20;
+
+/// `--columns`: timestamp, level and source in fixed-width cells, each line
+/// starting its message at the same column.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Columns {
+    /// `--source-width`; 0 leaves the source column out.
+    source_width: usize,
+}
+
+impl Columns {
+    /// widths of the timestamp and source cells on a terminal `width` wide,
+    /// 0 for a column left out; the message takes what the cells leave. on a
+    /// narrow terminal the source gives way first, then the timestamp.
+    fn widths(&self, width: usize) -> (usize, usize) {
+        let room = width.saturating_sub(COLUMN_LEVEL_WIDTH + 1 + COLUMN_MIN_MESSAGE);
+        let timestamp = if room > COLUMN_TIMESTAMP_WIDTH { COLUMN_TIMESTAMP_WIDTH } else { 0 };
+        let source = self.source_width.min(room.saturating_sub(timestamp + 2));
+        // one character and the ellipsis, or nothing
+        (timestamp, if source >= 2 { source } else { 0 })
+    }
+}
+
+/// one line cut up for `--columns`; a cell is `None` when that part of the
+/// line couldn't be found, and printed blank.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Cells<'a> {
+    timestamp: Option<&'a str>,
+    level: Option<Level>,
+    /// the level was guessed from the message's wording.
+    inferred: bool,
+    source: Option<&'a str>,
+    message: &'a str,
+}
+
+/// the cells of `line`, from the same parsing that colors it: a Rust line's
+/// module, an RFC 5424 line's app name, else a leading timestamp and level
+/// token. a line with neither, like a stack frame, is all message.
+fn line_cells<'a>(line: &'a str, format: InputFormat, infer: &[Inference]) -> Cells<'a> {
+    if matches!(format, InputFormat::Auto | InputFormat::Rust) {
+        if let Some(rec) = parse_rust_line(line) {
+            return Cells {
+                timestamp: rec.timestamp.map(|r| &line[r]),
+                level: Some(rec.level),
+                inferred: false,
+                source: Some(&line[rec.module]).filter(|m| !m.is_empty()),
+                message: &line[rec.msg_start.min(line.len())..],
+            };
+        }
+    }
+    let rfc5424 = match format {
+        InputFormat::Auto => parse_rfc5424(line, true),
+        InputFormat::Rfc5424 => parse_rfc5424(line, false),
+        _ => None,
+    };
+    if let Some(rec) = rfc5424 {
+        // VERSION TIMESTAMP HOST APP
+        let mut header = line[rec.pri_end..rec.header_end].split(' ').skip(1);
+        let timestamp = header.next().filter(|t| *t != "-");
+        return Cells {
+            timestamp,
+            level: rec.severity.map(Level::from_syslog_severity),
+            inferred: false,
+            source: header.nth(1).filter(|app| *app != "-"),
+            message: rec.msg_start.map_or("", |start| &line[start..]),
+        };
+    }
+    let separator = |c: char| " []|:-".contains(c);
+    let pri = parse_pri(line);
+    let lead = pri.map_or(0, |(end, _)| end);
+    let timestamp = timestamp_range(line)
+        .filter(|ts| ts.start >= lead && line[lead..ts.start].trim_matches(separator).is_empty());
+    let mut start = timestamp.as_ref().map_or(lead, |ts| ts.end);
+    let rest = &line[start..];
+    let mut cells = Cells { timestamp: timestamp.map(|ts| &line[ts]), ..Cells::default() };
+    match find_level(rest) {
+        Some((pos, token)) => {
+            let raw = token.trim_matches(|c: char| "[]():- ".contains(c)).to_uppercase();
+            cells.level = token_level(&raw);
+            if cells.level.is_some() && rest[..pos].trim_matches(separator).is_empty() {
+                start += pos + token.len();
+            }
+        }
+        None => {
+            cells.level = infer_level(rest, infer).map(|(_, level)| level);
+            cells.inferred = cells.level.is_some();
+        }
+    }
+    if let Some((_, severity)) = pri {
+        // the PRI over whatever the message says
+        (cells.level, cells.inferred) = (Some(Level::from_syslog_severity(severity)), false);
+    }
+    if let Some(location) = source_location(line, format) {
+        cells.source = Some(&line[location.range.clone()]);
+        // a header's location goes to its cell; one inside a record stays
+        if location.range.start >= start && !line.trim_start().starts_with('{') {
+            start = location.range.end;
+        }
+    }
+    cells.message = match start {
+        0 => line,
+        _ => {
+            let message = line[start..].trim_start_matches([' ', ':', '|', ']']);
+            message.strip_prefix("- ").unwrap_or(message).trim_start()
+        }
+    };
+    cells
+}
+
+/// `text` cut to `width` columns, with an ellipsis when it didn't fit, and
+/// the padding still needed to fill them.
+fn fit_cell(text: &str, width: usize) -> (String, usize) {
+    let cells = visible_cells(text);
+    let text_width: usize = cells.iter().map(|c| c.2).sum();
+    if text_width <= width {
+        return (text.to_string(), width - text_width);
+    }
+    // cut before the first char with columns that don't fit, so the marks
+    // and joined parts of the last grapheme kept stay with it
+    let mut used = 0;
+    let mut end = text.len();
+    for &(i, _, w) in &cells {
+        if w > 0 && used + w + 1 > width {
+            end = i;
+            break;
+        }
+        used += w;
+    }
+    (format!("{}\u{2026}", &text[..end]), width - used - 1)
+}
+
+/// a line for `--columns`: the timestamp dimmed, the level colored, the
+/// source in its own hashed color, and the message highlighted as usual.
+fn write_columns(
+    out: &mut dyn Write,
+    cells: &Cells,
+    columns: &Columns,
+    hl: &Highlighter,
+) -> io::Result<()> {
+    let styles = &hl.loaded().styles;
+    let (timestamp_width, source_width) = columns.widths(hl.width.get());
+    if timestamp_width > 0 {
+        let (timestamp, pad) = fit_cell(cells.timestamp.unwrap_or(""), timestamp_width);
+        styles.paint(out, &styles.dim, &timestamp)?;
+        write!(out, "{:1$} ", "", pad)?;
+    }
+    let name = cells.level.map_or("", Level::name);
+    match cells.level {
+        Some(level) if cells.inferred => styles.paint_inferred(out, level, name)?,
+        Some(level) => styles.paint_level(out, level, name)?,
+        None => {}
+    }
+    write!(out, "{:1$} ", "", COLUMN_LEVEL_WIDTH - name.len())?;
+    if source_width > 0 {
+        let (source, pad) = fit_cell(cells.source.unwrap_or(""), source_width);
+        let color = cells.source.map(|s| hash_color(s, styles.depth).fg_sgr(styles.depth));
+        styles.paint(out, &color.unwrap_or_default(), &source)?;
+        write!(out, "{:1$} ", "", pad)?;
+    }
+    write_level_highlight(out, cells.message, hl)?;
+    writeln!(out)
+}
+
+/// a line of `kubectl logs --prefix` (`[pod/api-7f9c/app] message`), of a
+/// CRI container log (`2024-05-01T12:03:04.123Z stdout F message`), or both.
+#[derive(Debug, PartialEq, Eq)]
+struct K8sLine {
+    /// `[pod/NAME/CONTAINER]`, brackets included.
+    prefix: Option<std::ops::Range<usize>>,
+    /// the pod's name inside the prefix, which picks its color.
+    pod: std::ops::Range<usize>,
+    timestamp: Option<std::ops::Range<usize>>,
+    /// tagged `P`: the runtime split the line, and the next record from the
+    /// same container has more of it.
+    partial: bool,
+    /// where the message starts, after the stream and tag columns.
+    message: usize,
+}
+
+fn parse_k8s_line(line: &str) -> Option<K8sLine> {
+    let (mut prefix, mut pod, mut pos) = (None, 0..0, 0);
+    if line.starts_with('[') {
+        let close = line.find(']')?;
+        let mut parts = line[1..close].split('/');
+        let (kind, name, container) = (parts.next()?, parts.next()?, parts.next()?);
+        let fields = [kind, name, container];
+        if parts.next().is_some() || fields.iter().any(|f| f.is_empty() || f.contains(' ')) {
+            return None;
+        }
+        prefix = Some(0..close + 1);
+        pod = 2 + kind.len()..2 + kind.len() + name.len();
+        pos = close + 1;
+        match line.as_bytes().get(pos) {
+            Some(b' ') => pos += 1,
+            None => {}
+            Some(_) => return None,
+        }
+    }
+    let rest = &line[pos..];
+    let word = rest.find(' ').map_or(rest, |end| &rest[..end]);
+    let mut timestamp = None;
+    if word.as_bytes().get(10) == Some(&b'T') && parse_iso_seconds(word).is_some() {
+        timestamp = Some(pos..pos + word.len());
+        pos = (pos + word.len() + 1).min(line.len());
+    }
+    let mut partial = false;
+    let columns =
+        line[pos..].strip_prefix("stdout ").or_else(|| line[pos..].strip_prefix("stderr "));
+    match columns.filter(|_| timestamp.is_some()) {
+        Some(tag) if tag == "F" || tag == "P" || tag.starts_with("F ") || tag.starts_with("P ") => {
+            partial = tag.starts_with('P');
+            pos = (line.len() - tag.len() + 2).min(line.len());
+        }
+        _ if prefix.is_none() => return None,
+        _ => {}
+    }
+    Some(K8sLine { prefix, pod, timestamp, partial, message: pos })
+}
+
+/// the start of a Kubernetes record split by its container runtime, waiting
+/// for the rest of the message.
+struct K8sPartial {
+    /// the first record as read, for its prefix and timestamp.
+    first: String,
+    message: String,
+}
+
+/// a Kubernetes container log line: the prefix in its pod's color, the
+/// timestamp dimmed, the stream and tag dropped, and the message rendered
+/// like any other line. `P` records are held until their line is complete.
+fn write_k8s_line(
+    out: &mut dyn Write,
+    line: String,
+    k8s: &K8sLine,
+    hl: &Highlighter,
+    state: &mut ReaderState,
+) -> io::Result<()> {
+    let source = k8s.prefix.clone().map_or("", |prefix| &line[prefix]);
+    let held = state.k8s_partial.iter().position(|(key, _)| key == source);
+    let message = &line[k8s.message..];
+    if k8s.partial {
+        match held {
+            Some(i) => state.k8s_partial[i].1.message.push_str(message),
+            None => {
+                let partial = K8sPartial { message: message.to_string(), first: line.clone() };
+                state.k8s_partial.push((source.to_string(), partial));
+            }
+        }
+        let i = held.unwrap_or(state.k8s_partial.len() - 1);
+        if state.k8s_partial[i].1.message.len() <= hl.max_line_bytes {
+            return Ok(());
+        }
+        let (_, partial) = state.k8s_partial.remove(i);
+        return write_k8s_record(out, &partial.first, &partial.message, hl);
+    }
+    match held.map(|i| state.k8s_partial.remove(i).1) {
+        Some(mut partial) => {
+            partial.message.push_str(message);
+            write_k8s_record(out, &partial.first, &partial.message, hl)
+        }
+        None => write_k8s_record(out, &line, message, hl),
+    }
+}
+
+/// a whole Kubernetes record, with the prefix and timestamp of `first`.
+fn write_k8s_record(
+    out: &mut dyn Write,
+    first: &str,
+    message: &str,
+    hl: &Highlighter,
+) -> io::Result<()> {
+    let Some(k8s) = parse_k8s_line(first) else {
+        return print_colored_line(out, message, hl);
+    };
+    if let Some(columns) = &hl.columns {
+        // the pod is the source when there is one; the runtime's timestamp
+        // stands in for the message's own
+        let mut cells = line_cells(message, InputFormat::Auto, &hl.infer);
+        cells.timestamp = cells.timestamp.or(k8s.timestamp.map(|ts| &first[ts]));
+        if k8s.prefix.is_some() {
+            cells.source = Some(&first[k8s.pod]);
+        }
+        return write_columns(out, &cells, columns, hl);
+    }
+    let styles = &hl.loaded().styles;
+    if let Some(prefix) = k8s.prefix {
+        let color = hash_color(&first[k8s.pod], styles.depth).fg_sgr(styles.depth);
+        styles.paint(out, &color, &first[prefix])?;
+        write!(out, " ")?;
+    }
+    if let Some(timestamp) = k8s.timestamp {
+        styles.paint(out, &styles.dim, &first[timestamp])?;
+        write!(out, " ")?;
+    }
+    let format = match parse_rust_line(message) {
+        Some(_) => InputFormat::Rust,
+        None => InputFormat::Auto,
+    };
+    render_line(out, message, hl, format)
+}
+
+/// a `docker compose logs` line, `api-1       | message`, with compose's own
+/// colors already taken out.
+#[derive(Debug, PartialEq, Eq)]
+struct ComposeLine<'a> {
+    /// the container, service name and replica number.
+    name: &'a str,
+    message: &'a str,
+}
+
+fn parse_compose_line(text: &str) -> Option<ComposeLine<'_>> {
+    let name_len =
+        text.bytes().take_while(|&b| b.is_ascii_alphanumeric() || b"-_.".contains(&b)).count();
+    let rest = &text[name_len..];
+    let padding = rest.bytes().take_while(|&b| b == b' ').count();
+    let message = rest[padding..].strip_prefix('|').filter(|_| name_len > 0 && padding > 0)?;
+    Some(ComposeLine {
+        name: &text[..name_len],
+        message: message.strip_prefix(' ').unwrap_or(message),
+    })
+}
+
+impl ComposeLine<'_> {
+    /// the service the container belongs to: `api` for `api-1`, and for
+    /// compose v1's `project_api_1` too.
+    fn service(&self) -> &str {
+        let number = self.name.trim_end_matches(|c: char| c.is_ascii_digit());
+        let base = match number.len() < self.name.len() {
+            true => number.strip_suffix(['-', '_']).unwrap_or(self.name),
+            false => self.name,
+        };
+        base.rsplit('_').next().unwrap_or(base)
+    }
+
+    /// not filtered out by `--service`, which may name a service or one
+    /// container of it.
+    fn wanted(&self, services: Option<&[String]>) -> bool {
+        services.is_none_or(|list| list.iter().any(|s| s == self.name || s == self.service()))
+    }
+}
+
+/// a compose line with the prefix in its service's color and padded to the
+/// widest seen so far, and the message rendered like any other line.
+fn write_compose_line(
+    out: &mut dyn Write,
+    line: &ComposeLine,
+    hl: &Highlighter,
+    state: &mut ReaderState,
+) -> io::Result<()> {
+    if let Some(columns) = &hl.columns {
+        let cells = line_cells(line.message, InputFormat::Auto, &hl.infer);
+        return write_columns(out, &Cells { source: Some(line.name), ..cells }, columns, hl);
+    }
+    let styles = &hl.loaded().styles;
+    let name_width = display_width(line.name);
+    state.compose_width = state.compose_width.max(name_width);
+    let color = hash_color(line.service(), styles.depth).fg_sgr(styles.depth);
+    styles.paint(out, &color, line.name)?;
+    write!(out, "{:1$} ", "", state.compose_width - name_width)?;
+    styles.paint(out, &color, "|")?;
+    write!(out, " ")?;
+    let format = match parse_rust_line(line.message) {
+        Some(_) => InputFormat::Rust,
+        None => InputFormat::Auto,
+    };
+    render_line(out, line.message, hl, format)
+}
+
+/// count `line` into `summary`; in compose mode only the message, and only
+/// for the services shown.
+fn record_line(summary: &mut Summary, line: &str, hl: &Highlighter) {
+    let ruled = hl.settings.level(line);
+    if hl.format != InputFormat::Compose {
+        let level = match &hl.level_column {
+            Some(column) => column.level(line).map(|(_, level)| level),
+            None => line_level(line, &hl.infer),
+        };
+        return summary.record_as(line, ruled.or(level));
+    }
+    let text = visible_text(line);
+    let level = |text: &str| ruled.or_else(|| line_level(text, &hl.infer));
+    match parse_compose_line(&text) {
+        Some(compose) if !compose.wanted(hl.services.as_deref()) => {}
+        Some(compose) => summary.record_as(compose.message, level(compose.message)),
+        None => summary.record_as(&text, level(&text)),
+    }
+}
+
+/// the level `line` counts under, in compose mode that of its message.
+fn level_of(line: &str, hl: &Highlighter) -> Option<Level> {
+    if let Some(level) = hl.settings.level(line) {
+        return Some(level);
+    }
+    if let Some(column) = &hl.level_column {
+        return column.level(line).map(|(_, level)| level);
+    }
+    if hl.format == InputFormat::Json && json_object_line(line) {
+        // only the record's own say counts
+        return json_level(line).map(|(_, level)| level);
+    }
+    if hl.format != InputFormat::Compose {
+        return line_level(line, &hl.infer);
+    }
+    let text = visible_text(line);
+    line_level(parse_compose_line(&text).map_or(text.as_str(), |c| c.message), &hl.infer)
+}
+
+/// the file named by a `==> path <==` header, which `tail` prints when
+/// following several files.
+fn tail_header(line: &str) -> Option<&str> {
+    line.strip_prefix("==> ")?.strip_suffix(" <==").filter(|path| !path.is_empty())
+}
+
+fn write_tail_header(out: &mut dyn Write, path: &str, styles: &Styles) -> io::Result<()> {
+    let color = format!("{}{}", BOLD, hash_color(path, styles.depth).fg_sgr(styles.depth));
+    styles.paint(out, BOLD, "==> ")?;
+    styles.paint(out, &color, path)?;
+    styles.paint(out, BOLD, " <==")?;
+    writeln!(out)
+}
+
+/// what an event line passes on to the lines after it, like the color of
+/// a stack trace.
+#[derive(Clone, Copy, Default)]
+struct Continuation {
+    event_level: Option<Level>,
+    event_indent: usize,
+    event_run: usize,
+    in_statement: bool,
+    in_go_panic: bool,
+}
+
+/// `--continuation`: which lines without a level of their own are drawn in
+/// the color of the event line above them.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContinuationMode {
+    /// any indented line.
+    Indent,
+    /// lines indented further than the event line.
+    Deeper,
+    /// every line, until the next one with a level.
+    Any,
+    Off,
+}
+
+impl ContinuationMode {
+    pub fn parse(s: &str) -> Option<ContinuationMode> {
+        match s {
+            "indent" => Some(ContinuationMode::Indent),
+            "deeper" => Some(ContinuationMode::Deeper),
+            "any" => Some(ContinuationMode::Any),
+            "off" => Some(ContinuationMode::Off),
+            _ => None,
+        }
+    }
+
+    /// what a format gets without `--continuation`; a runtime prefixing
+    /// every line leaves nothing to go by.
+    fn default_for(format: InputFormat) -> ContinuationMode {
+        match format {
+            InputFormat::K8s | InputFormat::Compose => ContinuationMode::Off,
+            _ => ContinuationMode::Indent,
+        }
+    }
+
+    /// whether `line` goes with an event line indented `event_indent`; the
+    /// lines of a Java or Python stack trace do whatever their indent.
+    fn continues(self, line: &str, event_indent: usize) -> bool {
+        let indent = indent_width(line);
+        match self {
+            ContinuationMode::Off => false,
+            _ if is_trace_line(line) => true,
+            ContinuationMode::Indent => indent > 0,
+            ContinuationMode::Deeper => indent > event_indent,
+            ContinuationMode::Any => true,
+        }
+    }
+}
+
+/// `at com.x.Y(Y.java:12)`, `Caused by: ...`, `... 12 more` and the like,
+/// which belong to the exception above them even when a logger strips
+/// their indent.
+fn is_trace_line(line: &str) -> bool {
+    let line = line.trim_start();
+    ["at ", "Caused by:", "Suppressed:", "... ", "Traceback (most recent call last):"]
+        .iter()
+        .any(|start| line.starts_with(start))
+}
+
+/// `java.lang.IllegalStateException: pool exhausted`, `ValueError: bad` and
+/// the like: the line a stack trace hangs from, under the event that logged
+/// it and often not indented.
+fn is_exception_header(line: &str) -> bool {
+    let name = line.split(':').next().unwrap_or_default();
+    let class = name.rsplit('.').next().unwrap_or_default();
+    let named = name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '$'));
+    let thrown = ["Exception", "Error", "Throwable"].iter().any(|end| class.ends_with(end));
+    (named && thrown) || line.starts_with("Exception in thread ")
+}
+
+/// the columns of leading whitespace in `line`, tabs expanded; a blank line
+/// has none.
+fn indent_width(line: &str) -> usize {
+    let mut col = 0;
+    for c in line.chars() {
+        match c {
+            ' ' => col += 1,
+            '\t' => col += TAB_WIDTH - col % TAB_WIDTH,
+            _ => return col,
+        }
+    }
+    0
+}
+
+/// the files `tail` switches between with its headers, each with its own
+/// counts and continuation, so a stack trace stays with its file.
+#[derive(Default)]
+struct TailFiles {
+    current: Option<usize>,
+    files: Vec<(String, Summary, Continuation)>,
+}
+
+impl TailFiles {
+    fn switch(&mut self, path: &str, state: &mut ReaderState) {
+        let now = Continuation {
+            event_level: state.event_level,
+            event_indent: state.event_indent,
+            event_run: state.event_run,
+            in_statement: state.in_statement,
+            in_go_panic: state.in_go_panic,
+        };
+        if let Some(i) = self.current {
+            self.files[i].2 = now;
+        }
+        let i = match self.files.iter().position(|f| f.0 == path) {
+            Some(i) => i,
+            None => {
+                self.files.push((path.to_string(), Summary::default(), Continuation::default()));
+                self.files.len() - 1
+            }
+        };
+        let next = self.files[i].2;
+        (state.event_level, state.event_indent) = (next.event_level, next.event_indent);
+        state.event_run = next.event_run;
+        (state.in_statement, state.in_go_panic) = (next.in_statement, next.in_go_panic);
+        self.current = Some(i);
+    }
+
+    fn record(&mut self, line: &str, hl: &Highlighter) {
+        if let Some(i) = self.current {
+            record_line(&mut self.files[i].1, line, hl);
+        }
+    }
+
+    fn into_summaries(self) -> Vec<(String, Summary)> {
+        self.files.into_iter().map(|(path, summary, _)| (path, summary)).collect()
+    }
+}
+
+/// the first line of a java.util.logging SimpleFormatter record,
+/// `May 01, 2024 12:03:04 PM com.x.Y method`; its level is on the next line.
+fn is_jul_header(line: &str) -> bool {
+    fn digits(s: &str, min: usize, max: usize) -> bool {
+        (min..=max).contains(&s.len()) && s.bytes().all(|b| b.is_ascii_digit())
+    }
+    let mut parts = line.split(' ');
+    let (Some(month), Some(day), Some(year), Some(time), Some(ampm), Some(source)) =
+        (parts.next(), parts.next(), parts.next(), parts.next(), parts.next(), parts.next())
+    else {
+        return false;
+    };
+    let month_ok = month.len() == 3
+        && month.as_bytes()[0].is_ascii_uppercase()
+        && month.bytes().skip(1).all(|b| b.is_ascii_lowercase());
+    let time_ok = {
+        let t: Vec<&str> = time.split(':').collect();
+        t.len() == 3 && digits(t[0], 1, 2) && digits(t[1], 2, 2) && digits(t[2], 2, 2)
+    };
+    month_ok
+        && day.strip_suffix(',').is_some_and(|d| digits(d, 1, 2))
+        && digits(year, 4, 4)
+        && time_ok
+        && (ampm == "AM" || ampm == "PM")
+        && !source.is_empty()
+}
+
+/// the level of a JUL record's second line, `SEVERE: something broke`.
+fn jul_record_level(line: &str) -> Option<Level> {
+    let (word, _) = line.split_once(':')?;
+    match word {
+        "SEVERE" | "WARNING" | "INFO" | "CONFIG" | "FINE" | "FINER" | "FINEST" => {
+            Level::from_token(word)
+        }
+        _ => None,
+    }
+}
+
+/// PostgreSQL's follow-up lines belonging to the event logged just before.
+const CONTINUATION_LABELS: [&str; 4] = ["STATEMENT", "DETAIL", "HINT", "CONTEXT"];
+
+/// range of a continuation label like `DETAIL:` in `line`, with its colon.
+fn find_continuation_label(line: &str) -> Option<std::ops::Range<usize>> {
+    let bytes = line.as_bytes();
+    CONTINUATION_LABELS
+        .iter()
+        .filter_map(|label| {
+            let pos = line
+                .match_indices(&format!("{}:", label))
+                .map(|(pos, _)| pos)
+                .find(|&pos| pos == 0 || !is_word_byte(bytes[pos - 1]))?;
+            Some(pos..pos + label.len() + 1)
+        })
+        .min_by_key(|r| r.start)
+}
+
+/// in auto mode, how many lines at the start of a stream may identify it as
+/// coming from a Rust service.
+const SNIFF_LINES: usize = 5;
+
+/// what `process_reader` remembers between lines.
+#[derive(Default)]
+struct ReaderState {
+    export: ExportRecord,
+    /// a JUL header line waiting to learn its level from the next line.
+    jul_header: Option<String>,
+    /// level of the last event line, for continuation lines to inherit.
+    event_level: Option<Level>,
+    /// how far that line was indented, for `--continuation deeper`.
+    event_indent: usize,
+    /// continuation lines since it, for `--continuation-max`.
+    event_run: usize,
+    /// inside a PostgreSQL `STATEMENT:`, whose SQL may run over several lines.
+    in_statement: bool,
+    /// inside a Go panic's goroutine dump.
+    in_go_panic: bool,
+    lines_seen: usize,
+    /// one of the first `SNIFF_LINES` lines was env_logger/tracing output.
+    sniffed_rust: bool,
+    /// split Kubernetes records, by the prefix of the container they're from.
+    k8s_partial: Vec<(String, K8sPartial)>,
+    /// the longest compose container name so far, to line up the pipes.
+    compose_width: usize,
+    /// what `--format auto` found the input to be, going by its start.
+    detected: Option<InputFormat>,
+    /// lines of a pretty-printed JSON record still missing its closing brace.
+    json_lines: Vec<String>,
+    json_depth: i32,
+    json_bytes: usize,
+}
+
+/// give up on reassembling a JSON record, and print its lines as they
+/// came, once it grows past this.
+const MULTILINE_JSON_MAX_BYTES: usize = 1 << 20;
+
+/// how many more brackets `line` opens than it closes, strings aside.
+fn json_nesting(line: &str) -> i32 {
+    let (mut depth, mut in_string, mut escaped) = (0, false, false);
+    for b in line.bytes() {
+        match b {
+            _ if escaped => escaped = false,
+            b'\\' if in_string => escaped = true,
+            b'"' => in_string = !in_string,
+            _ if in_string => {}
+            b'{' | b'[' => depth += 1,
+            b'}' | b']' => depth -= 1,
+            _ => {}
+        }
+    }
+    depth
+}
+
+/// `--expand-json`: the record pretty-printed, keys muted and the level
+/// colored. `tint`, for `--json=pretty`, is the record's level color for
+/// the rest of it, message values in bold.
+fn write_json_pretty(
+    out: &mut dyn Write,
+    value: &Json,
+    styles: &Styles,
+    indent: usize,
+    is_level: bool,
+    tint: Option<&str>,
+) -> io::Result<()> {
+    let paint = |out: &mut dyn Write, text: &str| match tint {
+        Some(sgr) => styles.paint(out, sgr, text),
+        None => write!(out, "{}", text),
+    };
+    match value {
+        Json::Object(fields) if !fields.is_empty() => {
+            paint(out, "{")?;
+            writeln!(out)?;
+            for (i, (key, value)) in fields.iter().enumerate() {
+                write!(out, "{:1$}", "", indent + 2)?;
+                styles.paint(out, &styles.muted, &Json::String(key.clone()).to_string())?;
+                write!(out, ": ")?;
+                let is_level = JSON_LEVEL_KEYS.contains(&key.as_str());
+                let bold = tint.map(|sgr| format!("{}{}", BOLD, sgr));
+                let tint = match JSON_MESSAGE_KEYS.contains(&key.as_str()) {
+                    true => bold.as_deref(),
+                    false => tint,
+                };
+                write_json_pretty(out, value, styles, indent + 2, is_level, tint)?;
+                writeln!(out, "{}", if i + 1 < fields.len() { "," } else { "" })?;
+            }
+            write!(out, "{:1$}", "", indent)?;
+            paint(out, "}")
+        }
+        Json::Array(items) if !items.is_empty() => {
+            paint(out, "[")?;
+            writeln!(out)?;
+            for (i, item) in items.iter().enumerate() {
+                write!(out, "{:1$}", "", indent + 2)?;
+                write_json_pretty(out, item, styles, indent + 2, false, tint)?;
+                writeln!(out, "{}", if i + 1 < items.len() { "," } else { "" })?;
+            }
+            write!(out, "{:1$}", "", indent)?;
+            paint(out, "]")
+        }
+        Json::String(text) if is_level => match token_level(&text.to_uppercase()) {
+            Some(level) => styles.paint_level(out, level, &value.to_string()),
+            None => paint(out, &value.to_string()),
+        },
+        other => paint(out, &other.to_string()),
+    }
+}
+
+/// a gathered `--multiline-json` record: one compact line, pretty-printed
+/// with `--expand-json`, or if it doesn't parse the lines as they were.
+fn flush_json_record(
+    out: &mut dyn Write,
+    hl: &Highlighter,
+    state: &mut ReaderState,
+) -> io::Result<()> {
+    let lines = std::mem::take(&mut state.json_lines);
+    (state.json_depth, state.json_bytes) = (0, 0);
+    match parse_json(&lines.join("\n")).filter(|r| matches!(r, Json::Object(_))) {
+        Some(record) if hl.expand_json => {
+            write_json_pretty(out, &record, &hl.loaded().styles, 0, false, None)?;
+            writeln!(out)
+        }
+        Some(record) => dispatch_line(out, record.to_string(), hl, state),
+        None => lines.into_iter().try_for_each(|line| dispatch_line(out, line, hl, state)),
+    }
+}
+
+fn process_line(
+    out: &mut dyn Write,
+    line: String,
+    hl: &Highlighter,
+    state: &mut ReaderState,
+) -> io::Result<()> {
+    let opens_record = || line.trim_start().starts_with('{') && json_nesting(&line) > 0;
+    if hl.multiline_json && (!state.json_lines.is_empty() || opens_record()) {
+        state.json_depth += json_nesting(&line);
+        state.json_bytes += line.len();
+        state.json_lines.push(line);
+        if state.json_depth > 0 && state.json_bytes <= MULTILINE_JSON_MAX_BYTES {
+            return Ok(());
+        }
+        return flush_json_record(out, hl, state);
+    }
+    dispatch_line(out, line, hl, state)
+}
+
+/// one line of input, after any multi-line JSON reassembly.
+fn dispatch_line(
+    out: &mut dyn Write,
+    line: String,
+    hl: &Highlighter,
+    state: &mut ReaderState,
+) -> io::Result<()> {
+    let line = hl.tz.as_ref().and_then(|zone| zone.convert(&line)).unwrap_or(line);
+    if let Some(header) = state.jul_header.take() {
+        match jul_record_level(&line) {
+            Some(level) => {
+                hl.loaded().styles.paint(out, hl.loaded().styles.color(level), &header)?;
+                writeln!(out)?;
+            }
+            None => print_colored_line(out, &header, hl)?,
+        }
+    }
+    let format = state.detected.unwrap_or(hl.format);
+    if format == InputFormat::Compose {
+        state.lines_seen += 1;
+        let text = visible_text(&line);
+        return match parse_compose_line(&text) {
+            Some(compose) if !compose.wanted(hl.services.as_deref()) => Ok(()),
+            Some(compose) => write_compose_line(out, &compose, hl, state),
+            None => print_colored_line(out, &line, hl),
+        };
+    }
+    if matches!(format, InputFormat::Auto | InputFormat::K8s) {
+        if let Some(k8s) = parse_k8s_line(&line) {
+            state.lines_seen += 1;
+            return write_k8s_line(out, line, &k8s, hl, state);
+        }
+    }
+    if format == InputFormat::Auto
+        && state.lines_seen < SNIFF_LINES
+        && !state.sniffed_rust
+        && parse_rust_line(&line).is_some()
+    {
+        state.sniffed_rust = true;
+    }
+    state.lines_seen += 1;
+    match format {
+        InputFormat::Auto if state.sniffed_rust => render_line(out, &line, hl, InputFormat::Rust),
+        InputFormat::Auto if is_jul_header(&line) => {
+            state.jul_header = Some(line);
+            Ok(())
+        }
+        InputFormat::Journald if ExportRecord::is_field(&line) => {
+            state.export.push(&line);
+            Ok(())
+        }
+        InputFormat::Journald if line.is_empty() && !state.export.fields.is_empty() => {
+            state.export.flush(out, hl)
+        }
+        InputFormat::Journald => {
+            state.export.flush(out, hl)?;
+            render_line(out, &line, hl, format)
+        }
+        InputFormat::Auto | InputFormat::Plain => write_event_line(out, &line, hl, state),
+        _ => render_line(out, &line, hl, format),
+    }
+}
+
+/// the lines of a Go panic after `panic: ...` itself.
+#[derive(Debug, PartialEq, Eq)]
+enum GoLine {
+    /// `goroutine 1 [running]:`
+    Goroutine,
+    /// `\t/src/app/main.go:42 +0x1a`, with the range of `file:line`.
+    Location(std::ops::Range<usize>),
+    /// function frames, `created by ...`, `[signal ...]` and blank lines.
+    Other,
+}
+
+fn is_go_panic(line: &str) -> bool {
+    line.starts_with("panic: ") || line.starts_with("fatal error: ")
+}
+
+/// classify a line following a Go panic, or `None` once the dump is over.
+fn go_panic_line(line: &str) -> Option<GoLine> {
+    if let Some(path) = line.strip_prefix('\t') {
+        let end = path.find(" +0x").unwrap_or(path.len());
+        return Some(GoLine::Location(1..1 + end));
+    }
+    if line.starts_with("goroutine ") && line.ends_with("]:") {
+        return Some(GoLine::Goroutine);
+    }
+    let frame = line.contains('(') && (line.ends_with(')') || line.ends_with("...)"));
+    let other = line.is_empty()
+        || line.starts_with(' ')
+        || line.starts_with("created by ")
+        || line.starts_with("[signal ")
+        || line.starts_with("...additional frames elided...");
+    (frame || other).then_some(GoLine::Other)
+}
+
+/// a Go panic: the `panic:` line in error style, goroutine headers bold, and
+/// frame locations picked out.
+fn write_go_panic_line(
+    out: &mut dyn Write,
+    line: &str,
+    kind: Option<&GoLine>,
+    styles: &Styles,
+) -> io::Result<()> {
+    match kind {
+        None => styles.paint_level(out, Level::Error, line)?,
+        Some(GoLine::Goroutine) => styles.paint(out, BOLD, line)?,
+        Some(GoLine::Location(path)) => {
+            write!(out, "{}", &line[..path.start])?;
+            styles.paint(out, &styles.muted, &line[path.clone()])?;
+            styles.paint(out, &styles.dim, &line[path.end..])?;
+        }
+        Some(GoLine::Other) => write!(out, "{}", line)?,
+    }
+    writeln!(out)
+}
+
+/// plain text lines, tracking events so PostgreSQL's `DETAIL:`, `HINT:` and
+/// friends, and the SQL of a `STATEMENT:`, are drawn in the color of the
+/// event they belong to.
+fn write_event_line(
+    out: &mut dyn Write,
+    line: &str,
+    hl: &Highlighter,
+    state: &mut ReaderState,
+) -> io::Result<()> {
+    if state.in_go_panic {
+        match go_panic_line(line) {
+            Some(kind) => return write_go_panic_line(out, line, Some(&kind), &hl.loaded().styles),
+            None => state.in_go_panic = false,
+        }
+    }
+    if is_go_panic(line) {
+        state.in_go_panic = true;
+        state.event_level = Some(Level::Error);
+        (state.event_indent, state.event_run) = (0, 0);
+        state.in_statement = false;
+        return write_go_panic_line(out, line, None, &hl.loaded().styles);
+    }
+    if let Some((_, severity)) = parse_pri(line) {
+        // a syslog record, whatever its message says
+        state.event_level = Some(Level::from_syslog_severity(severity));
+        (state.event_indent, state.event_run) = (0, 0);
+        state.in_statement = false;
+        return print_colored_line(out, line, hl);
+    }
+    let label = find_continuation_label(line);
+    let primary = find_level(line).filter(|&(pos, token)| {
+        label.as_ref().is_none_or(|l| pos < l.start)
+            // a stray word in the SQL doesn't end the statement
+            && (!state.in_statement || token.ends_with([':', ']', ')']))
+    });
+    if let Some((_, token)) = primary {
+        let raw = token.trim_matches(|c: char| "[]():-".contains(c) || c.is_whitespace());
+        state.event_level = token_level(&raw.to_uppercase());
+        (state.event_indent, state.event_run) = (indent_width(line), 0);
+        state.in_statement = false;
+        return print_colored_line(out, line, hl);
+    }
+    let Some(level) = state.event_level else {
+        return print_colored_line(out, line, hl);
+    };
+    let styles = &hl.loaded().styles;
+    if !styles.enabled() {
+        return writeln!(out, "{}", line);
+    }
+    let mode = hl.continuation.unwrap_or_else(|| ContinuationMode::default_for(hl.format));
+    let shaped = mode.continues(line, state.event_indent);
+    let continues =
+        state.event_run < hl.continuation_max && shaped && infer_level(line, &hl.infer).is_none();
+    if continues {
+        state.event_run += 1;
+    } else if !shaped
+        && label.is_none()
+        && !state.in_statement
+        && !line.trim().is_empty()
+        && !is_exception_header(line)
+    {
+        // a line of its own: what comes after it no longer goes with the
+        // event. a blank one, as between `tail`'s files, isn't one
+        state.event_level = None;
+    }
+    let event = styles.line_style(level);
+    let marks = match label {
+        Some(label) => {
+            state.in_statement = &line[label.clone()] == "STATEMENT:";
+            let mut label_style = event.clone();
+            label_style.attrs.splice(0..0, [BOLD, DIM]);
+            // a label starting or ending the line leaves nothing on that side
+            vec![
+                Span::new(0..label.start, event.clone(), SpanKind::Plain),
+                Span::new(label.clone(), label_style, SpanKind::Entity),
+                Span::new(label.end..line.len(), event, SpanKind::Plain),
+            ]
+        }
+        None if state.in_statement || continues => {
+            vec![Span::new(0..line.len(), event, SpanKind::Plain)]
+        }
+        None => return print_colored_line(out, line, hl),
+    };
+    write_spans(out, line, &hl.ruled(line, cover(line, marks)), styles)?;
+    writeln!(out)
+}
+
+/// write out whatever is still held back when the input ends.
+fn finish_reader(out: &mut dyn Write, hl: &Highlighter, state: &mut ReaderState) -> io::Result<()> {
+    if !state.json_lines.is_empty() {
+        flush_json_record(out, hl, state)?;
+    }
+    if let Some(header) = state.jul_header.take() {
+        print_colored_line(out, &header, hl)?;
+    }
+    for (_, partial) in std::mem::take(&mut state.k8s_partial) {
+        write_k8s_record(out, &partial.first, &partial.message, hl)?;
+    }
+    state.export.flush(out, hl)
+}
+
+/// width to `--wrap` at: the terminal's, else `$COLUMNS`, else 80.
+fn terminal_columns() -> usize {
+    term::columns()
+        .or_else(|| env::var("COLUMNS").ok()?.parse().ok())
+        .filter(|&cols| cols > 0)
+        .unwrap_or(80)
+}
+
+/// how often the terminal's width is measured again where it can't tell us
+/// it was resized.
+const WIDTH_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// the terminal width that `--wrap`, `--columns` and the marks are laid out
+/// to, shared by everything drawing to it and kept current on a resize.
+#[derive(Clone, Debug)]
+struct TermWidth(std::sync::Arc<std::sync::atomic::AtomicUsize>);
+
+impl TermWidth {
+    fn new(cols: usize) -> TermWidth {
+        TermWidth(std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(cols)))
+    }
+
+    fn get(&self) -> usize {
+        self.0.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// take a new measurement; true when the width changed.
+    fn refresh(&self, measure: &dyn Fn() -> usize) -> bool {
+        let cols = measure();
+        self.0.swap(cols, std::sync::atomic::Ordering::Relaxed) != cols
+    }
+
+    /// measure again on each SIGWINCH, or once a second without it.
+    fn follow(&self, measure: fn() -> usize) {
+        let width = self.clone();
+        if term::on_sigwinch(move || {
+            width.refresh(&measure);
+        }) {
+            return;
+        }
+        let width = self.clone();
+        thread::spawn(move || {
+            loop {
+                thread::sleep(WIDTH_POLL_INTERVAL);
+                width.refresh(&measure);
+            }
+        });
+    }
+}
+
+/// code points a terminal draws in no columns: combining marks, format
+/// characters and the hangul jamo that join a syllable, as inclusive
+/// ranges. generated from Unicode 14.0 (general categories Mn, Me and Cf).
+#[rustfmt::skip]
+const ZERO_WIDTH: &[(u32, u32)] = &[
+    (0x300, 0x36f), (0x483, 0x489), (0x591, 0x5bd), (0x5bf, 0x5bf), (0x5c1, 0x5c2), (0x5c4, 0x5c5),
+    (0x5c7, 0x5c7), (0x600, 0x605), (0x610, 0x61a), (0x61c, 0x61c), (0x64b, 0x65f), (0x670, 0x670),
+    (0x6d6, 0x6dd), (0x6df, 0x6e4), (0x6e7, 0x6e8), (0x6ea, 0x6ed), (0x70f, 0x70f), (0x711, 0x711),
+    (0x730, 0x74a), (0x7a6, 0x7b0), (0x7eb, 0x7f3), (0x7fd, 0x7fd), (0x816, 0x819), (0x81b, 0x823),
+    (0x825, 0x827), (0x829, 0x82d), (0x859, 0x85b), (0x890, 0x89f), (0x8ca, 0x902), (0x93a, 0x93a),
+    (0x93c, 0x93c), (0x941, 0x948), (0x94d, 0x94d), (0x951, 0x957), (0x962, 0x963), (0x981, 0x981),
+    (0x9bc, 0x9bc), (0x9c1, 0x9c4), (0x9cd, 0x9cd), (0x9e2, 0x9e3), (0x9fe, 0xa02), (0xa3c, 0xa3c),
+    (0xa41, 0xa51), (0xa70, 0xa71), (0xa75, 0xa75), (0xa81, 0xa82), (0xabc, 0xabc), (0xac1, 0xac8),
+    (0xacd, 0xacd), (0xae2, 0xae3), (0xafa, 0xb01), (0xb3c, 0xb3c), (0xb3f, 0xb3f), (0xb41, 0xb44),
+    (0xb4d, 0xb56), (0xb62, 0xb63), (0xb82, 0xb82), (0xbc0, 0xbc0), (0xbcd, 0xbcd), (0xc00, 0xc00),
+    (0xc04, 0xc04), (0xc3c, 0xc3c), (0xc3e, 0xc40), (0xc46, 0xc56), (0xc62, 0xc63), (0xc81, 0xc81),
+    (0xcbc, 0xcbc), (0xcbf, 0xcbf), (0xcc6, 0xcc6), (0xccc, 0xccd), (0xce2, 0xce3), (0xd00, 0xd01),
+    (0xd3b, 0xd3c), (0xd41, 0xd44), (0xd4d, 0xd4d), (0xd62, 0xd63), (0xd81, 0xd81), (0xdca, 0xdca),
+    (0xdd2, 0xdd6), (0xe31, 0xe31), (0xe34, 0xe3a), (0xe47, 0xe4e), (0xeb1, 0xeb1), (0xeb4, 0xebc),
+    (0xec8, 0xecd), (0xf18, 0xf19), (0xf35, 0xf35), (0xf37, 0xf37), (0xf39, 0xf39), (0xf71, 0xf7e),
+    (0xf80, 0xf84), (0xf86, 0xf87), (0xf8d, 0xfbc), (0xfc6, 0xfc6), (0x102d, 0x1030),
+    (0x1032, 0x1037), (0x1039, 0x103a), (0x103d, 0x103e), (0x1058, 0x1059), (0x105e, 0x1060),
+    (0x1071, 0x1074), (0x1082, 0x1082), (0x1085, 0x1086), (0x108d, 0x108d), (0x109d, 0x109d),
+    (0x1160, 0x11ff), (0x135d, 0x135f), (0x1712, 0x1714), (0x1732, 0x1733), (0x1752, 0x1753),
+    (0x1772, 0x1773), (0x17b4, 0x17b5), (0x17b7, 0x17bd), (0x17c6, 0x17c6), (0x17c9, 0x17d3),
+    (0x17dd, 0x17dd), (0x180b, 0x180f), (0x1885, 0x1886), (0x18a9, 0x18a9), (0x1920, 0x1922),
+    (0x1927, 0x1928), (0x1932, 0x1932), (0x1939, 0x193b), (0x1a17, 0x1a18), (0x1a1b, 0x1a1b),
+    (0x1a56, 0x1a56), (0x1a58, 0x1a60), (0x1a62, 0x1a62), (0x1a65, 0x1a6c), (0x1a73, 0x1a7f),
+    (0x1ab0, 0x1b03), (0x1b34, 0x1b34), (0x1b36, 0x1b3a), (0x1b3c, 0x1b3c), (0x1b42, 0x1b42),
+    (0x1b6b, 0x1b73), (0x1b80, 0x1b81), (0x1ba2, 0x1ba5), (0x1ba8, 0x1ba9), (0x1bab, 0x1bad),
+    (0x1be6, 0x1be6), (0x1be8, 0x1be9), (0x1bed, 0x1bed), (0x1bef, 0x1bf1), (0x1c2c, 0x1c33),
+    (0x1c36, 0x1c37), (0x1cd0, 0x1cd2), (0x1cd4, 0x1ce0), (0x1ce2, 0x1ce8), (0x1ced, 0x1ced),
+    (0x1cf4, 0x1cf4), (0x1cf8, 0x1cf9), (0x1dc0, 0x1dff), (0x200b, 0x200f), (0x202a, 0x202e),
+    (0x2060, 0x206f), (0x20d0, 0x20f0), (0x2cef, 0x2cf1), (0x2d7f, 0x2d7f), (0x2de0, 0x2dff),
+    (0x302a, 0x302d), (0x3099, 0x309a), (0xa66f, 0xa672), (0xa674, 0xa67d), (0xa69e, 0xa69f),
+    (0xa6f0, 0xa6f1), (0xa802, 0xa802), (0xa806, 0xa806), (0xa80b, 0xa80b), (0xa825, 0xa826),
+    (0xa82c, 0xa82c), (0xa8c4, 0xa8c5), (0xa8e0, 0xa8f1), (0xa8ff, 0xa8ff), (0xa926, 0xa92d),
+    (0xa947, 0xa951), (0xa980, 0xa982), (0xa9b3, 0xa9b3), (0xa9b6, 0xa9b9), (0xa9bc, 0xa9bd),
+    (0xa9e5, 0xa9e5), (0xaa29, 0xaa2e), (0xaa31, 0xaa32), (0xaa35, 0xaa36), (0xaa43, 0xaa43),
+    (0xaa4c, 0xaa4c), (0xaa7c, 0xaa7c), (0xaab0, 0xaab0), (0xaab2, 0xaab4), (0xaab7, 0xaab8),
+    (0xaabe, 0xaabf), (0xaac1, 0xaac1), (0xaaec, 0xaaed), (0xaaf6, 0xaaf6), (0xabe5, 0xabe5),
+    (0xabe8, 0xabe8), (0xabed, 0xabed), (0xfb1e, 0xfb1e), (0xfe00, 0xfe0f), (0xfe20, 0xfe2f),
+    (0xfeff, 0xfeff), (0xfff9, 0xfffb), (0x101fd, 0x101fd), (0x102e0, 0x102e0), (0x10376, 0x1037a),
+    (0x10a01, 0x10a0f), (0x10a38, 0x10a3f), (0x10ae5, 0x10ae6), (0x10d24, 0x10d27),
+    (0x10eab, 0x10eac), (0x10f46, 0x10f50), (0x10f82, 0x10f85), (0x11001, 0x11001),
+    (0x11038, 0x11046), (0x11070, 0x11070), (0x11073, 0x11074), (0x1107f, 0x11081),
+    (0x110b3, 0x110b6), (0x110b9, 0x110ba), (0x110bd, 0x110bd), (0x110c2, 0x110cd),
+    (0x11100, 0x11102), (0x11127, 0x1112b), (0x1112d, 0x11134), (0x11173, 0x11173),
+    (0x11180, 0x11181), (0x111b6, 0x111be), (0x111c9, 0x111cc), (0x111cf, 0x111cf),
+    (0x1122f, 0x11231), (0x11234, 0x11234), (0x11236, 0x11237), (0x1123e, 0x1123e),
+    (0x112df, 0x112df), (0x112e3, 0x112ea), (0x11300, 0x11301), (0x1133b, 0x1133c),
+    (0x11340, 0x11340), (0x11366, 0x11374), (0x11438, 0x1143f), (0x11442, 0x11444),
+    (0x11446, 0x11446), (0x1145e, 0x1145e), (0x114b3, 0x114b8), (0x114ba, 0x114ba),
+    (0x114bf, 0x114c0), (0x114c2, 0x114c3), (0x115b2, 0x115b5), (0x115bc, 0x115bd),
+    (0x115bf, 0x115c0), (0x115dc, 0x115dd), (0x11633, 0x1163a), (0x1163d, 0x1163d),
+    (0x1163f, 0x11640), (0x116ab, 0x116ab), (0x116ad, 0x116ad), (0x116b0, 0x116b5),
+    (0x116b7, 0x116b7), (0x1171d, 0x1171f), (0x11722, 0x11725), (0x11727, 0x1172b),
+    (0x1182f, 0x11837), (0x11839, 0x1183a), (0x1193b, 0x1193c), (0x1193e, 0x1193e),
+    (0x11943, 0x11943), (0x119d4, 0x119db), (0x119e0, 0x119e0), (0x11a01, 0x11a0a),
+    (0x11a33, 0x11a38), (0x11a3b, 0x11a3e), (0x11a47, 0x11a47), (0x11a51, 0x11a56),
+    (0x11a59, 0x11a5b), (0x11a8a, 0x11a96), (0x11a98, 0x11a99), (0x11c30, 0x11c3d),
+    (0x11c3f, 0x11c3f), (0x11c92, 0x11ca7), (0x11caa, 0x11cb0), (0x11cb2, 0x11cb3),
+    (0x11cb5, 0x11cb6), (0x11d31, 0x11d45), (0x11d47, 0x11d47), (0x11d90, 0x11d91),
+    (0x11d95, 0x11d95), (0x11d97, 0x11d97), (0x11ef3, 0x11ef4), (0x13430, 0x13438),
+    (0x16af0, 0x16af4), (0x16b30, 0x16b36), (0x16f4f, 0x16f4f), (0x16f8f, 0x16f92),
+    (0x16fe4, 0x16fe4), (0x1bc9d, 0x1bc9e), (0x1bca0, 0x1cf46), (0x1d167, 0x1d169),
+    (0x1d173, 0x1d182), (0x1d185, 0x1d18b), (0x1d1aa, 0x1d1ad), (0x1d242, 0x1d244),
+    (0x1da00, 0x1da36), (0x1da3b, 0x1da6c), (0x1da75, 0x1da75), (0x1da84, 0x1da84),
+    (0x1da9b, 0x1daaf), (0x1e000, 0x1e02a), (0x1e130, 0x1e136), (0x1e2ae, 0x1e2ae),
+    (0x1e2ec, 0x1e2ef), (0x1e8d0, 0x1e8d6), (0x1e944, 0x1e94a), (0xe0001, 0xe01ef),
+];
+
+/// code points drawn in two columns: East Asian Width W and F, which
+/// covers CJK and emoji, as inclusive ranges generated from Unicode 14.0.
+#[rustfmt::skip]
+const WIDE: &[(u32, u32)] = &[
+    (0x378, 0x379), (0x380, 0x383), (0x38b, 0x38b), (0x38d, 0x38d), (0x3a2, 0x3a2), (0x530, 0x530),
+    (0x557, 0x558), (0x58b, 0x58c), (0x590, 0x590), (0x5c8, 0x5cf), (0x5eb, 0x5ee), (0x5f5, 0x5ff),
+    (0x70e, 0x70e), (0x74b, 0x74c), (0x7b2, 0x7bf), (0x7fb, 0x7fc), (0x82e, 0x82f), (0x83f, 0x83f),
+    (0x85c, 0x85d), (0x85f, 0x85f), (0x86b, 0x86f), (0x88f, 0x88f), (0x892, 0x897), (0x984, 0x984),
+    (0x98d, 0x98e), (0x991, 0x992), (0x9a9, 0x9a9), (0x9b1, 0x9b1), (0x9b3, 0x9b5), (0x9ba, 0x9bb),
+    (0x9c5, 0x9c6), (0x9c9, 0x9ca), (0x9cf, 0x9d6), (0x9d8, 0x9db), (0x9de, 0x9de), (0x9e4, 0x9e5),
+    (0x9ff, 0xa00), (0xa04, 0xa04), (0xa0b, 0xa0e), (0xa11, 0xa12), (0xa29, 0xa29), (0xa31, 0xa31),
+    (0xa34, 0xa34), (0xa37, 0xa37), (0xa3a, 0xa3b), (0xa3d, 0xa3d), (0xa43, 0xa46), (0xa49, 0xa4a),
+    (0xa4e, 0xa50), (0xa52, 0xa58), (0xa5d, 0xa5d), (0xa5f, 0xa65), (0xa77, 0xa80), (0xa84, 0xa84),
+    (0xa8e, 0xa8e), (0xa92, 0xa92), (0xaa9, 0xaa9), (0xab1, 0xab1), (0xab4, 0xab4), (0xaba, 0xabb),
+    (0xac6, 0xac6), (0xaca, 0xaca), (0xace, 0xacf), (0xad1, 0xadf), (0xae4, 0xae5), (0xaf2, 0xaf8),
+    (0xb00, 0xb00), (0xb04, 0xb04), (0xb0d, 0xb0e), (0xb11, 0xb12), (0xb29, 0xb29), (0xb31, 0xb31),
+    (0xb34, 0xb34), (0xb3a, 0xb3b), (0xb45, 0xb46), (0xb49, 0xb4a), (0xb4e, 0xb54), (0xb58, 0xb5b),
+    (0xb5e, 0xb5e), (0xb64, 0xb65), (0xb78, 0xb81), (0xb84, 0xb84), (0xb8b, 0xb8d), (0xb91, 0xb91),
+    (0xb96, 0xb98), (0xb9b, 0xb9b), (0xb9d, 0xb9d), (0xba0, 0xba2), (0xba5, 0xba7), (0xbab, 0xbad),
+    (0xbba, 0xbbd), (0xbc3, 0xbc5), (0xbc9, 0xbc9), (0xbce, 0xbcf), (0xbd1, 0xbd6), (0xbd8, 0xbe5),
+    (0xbfb, 0xbff), (0xc0d, 0xc0d), (0xc11, 0xc11), (0xc29, 0xc29), (0xc3a, 0xc3b), (0xc45, 0xc45),
+    (0xc49, 0xc49), (0xc4e, 0xc54), (0xc57, 0xc57), (0xc5b, 0xc5c), (0xc5e, 0xc5f), (0xc64, 0xc65),
+    (0xc70, 0xc76), (0xc8d, 0xc8d), (0xc91, 0xc91), (0xca9, 0xca9), (0xcb4, 0xcb4), (0xcba, 0xcbb),
+    (0xcc5, 0xcc5), (0xcc9, 0xcc9), (0xcce, 0xcd4), (0xcd7, 0xcdc), (0xcdf, 0xcdf), (0xce4, 0xce5),
+    (0xcf0, 0xcf0), (0xcf3, 0xcff), (0xd0d, 0xd0d), (0xd11, 0xd11), (0xd45, 0xd45), (0xd49, 0xd49),
+    (0xd50, 0xd53), (0xd64, 0xd65), (0xd80, 0xd80), (0xd84, 0xd84), (0xd97, 0xd99), (0xdb2, 0xdb2),
+    (0xdbc, 0xdbc), (0xdbe, 0xdbf), (0xdc7, 0xdc9), (0xdcb, 0xdce), (0xdd5, 0xdd5), (0xdd7, 0xdd7),
+    (0xde0, 0xde5), (0xdf0, 0xdf1), (0xdf5, 0xe00), (0xe3b, 0xe3e), (0xe5c, 0xe80), (0xe83, 0xe83),
+    (0xe85, 0xe85), (0xe8b, 0xe8b), (0xea4, 0xea4), (0xea6, 0xea6), (0xebe, 0xebf), (0xec5, 0xec5),
+    (0xec7, 0xec7), (0xece, 0xecf), (0xeda, 0xedb), (0xee0, 0xeff), (0xf48, 0xf48), (0xf6d, 0xf70),
+    (0xf98, 0xf98), (0xfbd, 0xfbd), (0xfcd, 0xfcd), (0xfdb, 0xfff), (0x10c6, 0x10c6),
+    (0x10c8, 0x10cc), (0x10ce, 0x10cf), (0x1100, 0x115f), (0x1249, 0x1249), (0x124e, 0x124f),
+    (0x1257, 0x1257), (0x1259, 0x1259), (0x125e, 0x125f), (0x1289, 0x1289), (0x128e, 0x128f),
+    (0x12b1, 0x12b1), (0x12b6, 0x12b7), (0x12bf, 0x12bf), (0x12c1, 0x12c1), (0x12c6, 0x12c7),
+    (0x12d7, 0x12d7), (0x1311, 0x1311), (0x1316, 0x1317), (0x135b, 0x135c), (0x137d, 0x137f),
+    (0x139a, 0x139f), (0x13f6, 0x13f7), (0x13fe, 0x13ff), (0x169d, 0x169f), (0x16f9, 0x16ff),
+    (0x1716, 0x171e), (0x1737, 0x173f), (0x1754, 0x175f), (0x176d, 0x176d), (0x1771, 0x1771),
+    (0x1774, 0x177f), (0x17de, 0x17df), (0x17ea, 0x17ef), (0x17fa, 0x17ff), (0x181a, 0x181f),
+    (0x1879, 0x187f), (0x18ab, 0x18af), (0x18f6, 0x18ff), (0x191f, 0x191f), (0x192c, 0x192f),
+    (0x193c, 0x193f), (0x1941, 0x1943), (0x196e, 0x196f), (0x1975, 0x197f), (0x19ac, 0x19af),
+    (0x19ca, 0x19cf), (0x19db, 0x19dd), (0x1a1c, 0x1a1d), (0x1a5f, 0x1a5f), (0x1a7d, 0x1a7e),
+    (0x1a8a, 0x1a8f), (0x1a9a, 0x1a9f), (0x1aae, 0x1aaf), (0x1acf, 0x1aff), (0x1b4d, 0x1b4f),
+    (0x1b7f, 0x1b7f), (0x1bf4, 0x1bfb), (0x1c38, 0x1c3a), (0x1c4a, 0x1c4c), (0x1c89, 0x1c8f),
+    (0x1cbb, 0x1cbc), (0x1cc8, 0x1ccf), (0x1cfb, 0x1cff), (0x1f16, 0x1f17), (0x1f1e, 0x1f1f),
+    (0x1f46, 0x1f47), (0x1f4e, 0x1f4f), (0x1f58, 0x1f58), (0x1f5a, 0x1f5a), (0x1f5c, 0x1f5c),
+    (0x1f5e, 0x1f5e), (0x1f7e, 0x1f7f), (0x1fb5, 0x1fb5), (0x1fc5, 0x1fc5), (0x1fd4, 0x1fd5),
+    (0x1fdc, 0x1fdc), (0x1ff0, 0x1ff1), (0x1ff5, 0x1ff5), (0x1fff, 0x1fff), (0x2065, 0x2065),
+    (0x2072, 0x2073), (0x208f, 0x208f), (0x209d, 0x209f), (0x20c1, 0x20cf), (0x20f1, 0x20ff),
+    (0x218c, 0x218f), (0x231a, 0x231b), (0x2329, 0x232a), (0x23e9, 0x23ec), (0x23f0, 0x23f0),
+    (0x23f3, 0x23f3), (0x2427, 0x243f), (0x244b, 0x245f), (0x25fd, 0x25fe), (0x2614, 0x2615),
+    (0x2648, 0x2653), (0x267f, 0x267f), (0x2693, 0x2693), (0x26a1, 0x26a1), (0x26aa, 0x26ab),
+    (0x26bd, 0x26be), (0x26c4, 0x26c5), (0x26ce, 0x26ce), (0x26d4, 0x26d4), (0x26ea, 0x26ea),
+    (0x26f2, 0x26f3), (0x26f5, 0x26f5), (0x26fa, 0x26fa), (0x26fd, 0x26fd), (0x2705, 0x2705),
+    (0x270a, 0x270b), (0x2728, 0x2728), (0x274c, 0x274c), (0x274e, 0x274e), (0x2753, 0x2755),
+    (0x2757, 0x2757), (0x2795, 0x2797), (0x27b0, 0x27b0), (0x27bf, 0x27bf), (0x2b1b, 0x2b1c),
+    (0x2b50, 0x2b50), (0x2b55, 0x2b55), (0x2b74, 0x2b75), (0x2b96, 0x2b96), (0x2cf4, 0x2cf8),
+    (0x2d26, 0x2d26), (0x2d28, 0x2d2c), (0x2d2e, 0x2d2f), (0x2d68, 0x2d6e), (0x2d71, 0x2d7e),
+    (0x2d97, 0x2d9f), (0x2da7, 0x2da7), (0x2daf, 0x2daf), (0x2db7, 0x2db7), (0x2dbf, 0x2dbf),
+    (0x2dc7, 0x2dc7), (0x2dcf, 0x2dcf), (0x2dd7, 0x2dd7), (0x2ddf, 0x2ddf), (0x2e5e, 0x3029),
+    (0x302e, 0x303e), (0x3040, 0x3098), (0x309b, 0x3247), (0x3250, 0x4dbf), (0x4e00, 0xa4cf),
+    (0xa62c, 0xa63f), (0xa6f8, 0xa6ff), (0xa7cb, 0xa7cf), (0xa7d2, 0xa7d2), (0xa7d4, 0xa7d4),
+    (0xa7da, 0xa7f1), (0xa82d, 0xa82f), (0xa83a, 0xa83f), (0xa878, 0xa87f), (0xa8c6, 0xa8cd),
+    (0xa8da, 0xa8df), (0xa954, 0xa95e), (0xa960, 0xa97f), (0xa9ce, 0xa9ce), (0xa9da, 0xa9dd),
+    (0xa9ff, 0xa9ff), (0xaa37, 0xaa3f), (0xaa4e, 0xaa4f), (0xaa5a, 0xaa5b), (0xaac3, 0xaada),
+    (0xaaf7, 0xab00), (0xab07, 0xab08), (0xab0f, 0xab10), (0xab17, 0xab1f), (0xab27, 0xab27),
+    (0xab2f, 0xab2f), (0xab6c, 0xab6f), (0xabee, 0xabef), (0xabfa, 0xd7af), (0xd7c7, 0xd7ca),
+    (0xd7fc, 0xd7ff), (0xf900, 0xfaff), (0xfb07, 0xfb12), (0xfb18, 0xfb1c), (0xfb37, 0xfb37),
+    (0xfb3d, 0xfb3d), (0xfb3f, 0xfb3f), (0xfb42, 0xfb42), (0xfb45, 0xfb45), (0xfbc3, 0xfbd2),
+    (0xfd90, 0xfd91), (0xfdc8, 0xfdce), (0xfdd0, 0xfdef), (0xfe10, 0xfe1f), (0xfe30, 0xfe6f),
+    (0xfe75, 0xfe75), (0xfefd, 0xfefe), (0xff00, 0xff60), (0xffbf, 0xffc1), (0xffc8, 0xffc9),
+    (0xffd0, 0xffd1), (0xffd8, 0xffd9), (0xffdd, 0xffe7), (0xffef, 0xfff8), (0xfffe, 0xffff),
+    (0x1000c, 0x1000c), (0x10027, 0x10027), (0x1003b, 0x1003b), (0x1003e, 0x1003e),
+    (0x1004e, 0x1004f), (0x1005e, 0x1007f), (0x100fb, 0x100ff), (0x10103, 0x10106),
+    (0x10134, 0x10136), (0x1018f, 0x1018f), (0x1019d, 0x1019f), (0x101a1, 0x101cf),
+    (0x101fe, 0x1027f), (0x1029d, 0x1029f), (0x102d1, 0x102df), (0x102fc, 0x102ff),
+    (0x10324, 0x1032c), (0x1034b, 0x1034f), (0x1037b, 0x1037f), (0x1039e, 0x1039e),
+    (0x103c4, 0x103c7), (0x103d6, 0x103ff), (0x1049e, 0x1049f), (0x104aa, 0x104af),
+    (0x104d4, 0x104d7), (0x104fc, 0x104ff), (0x10528, 0x1052f), (0x10564, 0x1056e),
+    (0x1057b, 0x1057b), (0x1058b, 0x1058b), (0x10593, 0x10593), (0x10596, 0x10596),
+    (0x105a2, 0x105a2), (0x105b2, 0x105b2), (0x105ba, 0x105ba), (0x105bd, 0x105ff),
+    (0x10737, 0x1073f), (0x10756, 0x1075f), (0x10768, 0x1077f), (0x10786, 0x10786),
+    (0x107b1, 0x107b1), (0x107bb, 0x107ff), (0x10806, 0x10807), (0x10809, 0x10809),
+    (0x10836, 0x10836), (0x10839, 0x1083b), (0x1083d, 0x1083e), (0x10856, 0x10856),
+    (0x1089f, 0x108a6), (0x108b0, 0x108df), (0x108f3, 0x108f3), (0x108f6, 0x108fa),
+    (0x1091c, 0x1091e), (0x1093a, 0x1093e), (0x10940, 0x1097f), (0x109b8, 0x109bb),
+    (0x109d0, 0x109d1), (0x10a04, 0x10a04), (0x10a07, 0x10a0b), (0x10a14, 0x10a14),
+    (0x10a18, 0x10a18), (0x10a36, 0x10a37), (0x10a3b, 0x10a3e), (0x10a49, 0x10a4f),
+    (0x10a59, 0x10a5f), (0x10aa0, 0x10abf), (0x10ae7, 0x10aea), (0x10af7, 0x10aff),
+    (0x10b36, 0x10b38), (0x10b56, 0x10b57), (0x10b73, 0x10b77), (0x10b92, 0x10b98),
+    (0x10b9d, 0x10ba8), (0x10bb0, 0x10bff), (0x10c49, 0x10c7f), (0x10cb3, 0x10cbf),
+    (0x10cf3, 0x10cf9), (0x10d28, 0x10d2f), (0x10d3a, 0x10e5f), (0x10e7f, 0x10e7f),
+    (0x10eaa, 0x10eaa), (0x10eae, 0x10eaf), (0x10eb2, 0x10eff), (0x10f28, 0x10f2f),
+    (0x10f5a, 0x10f6f), (0x10f8a, 0x10faf), (0x10fcc, 0x10fdf), (0x10ff7, 0x10fff),
+    (0x1104e, 0x11051), (0x11076, 0x1107e), (0x110c3, 0x110cc), (0x110ce, 0x110cf),
+    (0x110e9, 0x110ef), (0x110fa, 0x110ff), (0x11135, 0x11135), (0x11148, 0x1114f),
+    (0x11177, 0x1117f), (0x111e0, 0x111e0), (0x111f5, 0x111ff), (0x11212, 0x11212),
+    (0x1123f, 0x1127f), (0x11287, 0x11287), (0x11289, 0x11289), (0x1128e, 0x1128e),
+    (0x1129e, 0x1129e), (0x112aa, 0x112af), (0x112eb, 0x112ef), (0x112fa, 0x112ff),
+    (0x11304, 0x11304), (0x1130d, 0x1130e), (0x11311, 0x11312), (0x11329, 0x11329),
+    (0x11331, 0x11331), (0x11334, 0x11334), (0x1133a, 0x1133a), (0x11345, 0x11346),
+    (0x11349, 0x1134a), (0x1134e, 0x1134f), (0x11351, 0x11356), (0x11358, 0x1135c),
+    (0x11364, 0x11365), (0x1136d, 0x1136f), (0x11375, 0x113ff), (0x1145c, 0x1145c),
+    (0x11462, 0x1147f), (0x114c8, 0x114cf), (0x114da, 0x1157f), (0x115b6, 0x115b7),
+    (0x115de, 0x115ff), (0x11645, 0x1164f), (0x1165a, 0x1165f), (0x1166d, 0x1167f),
+    (0x116ba, 0x116bf), (0x116ca, 0x116ff), (0x1171b, 0x1171c), (0x1172c, 0x1172f),
+    (0x11747, 0x117ff), (0x1183c, 0x1189f), (0x118f3, 0x118fe), (0x11907, 0x11908),
+    (0x1190a, 0x1190b), (0x11914, 0x11914), (0x11917, 0x11917), (0x11936, 0x11936),
+    (0x11939, 0x1193a), (0x11947, 0x1194f), (0x1195a, 0x1199f), (0x119a8, 0x119a9),
+    (0x119d8, 0x119d9), (0x119e5, 0x119ff), (0x11a48, 0x11a4f), (0x11aa3, 0x11aaf),
+    (0x11af9, 0x11bff), (0x11c09, 0x11c09), (0x11c37, 0x11c37), (0x11c46, 0x11c4f),
+    (0x11c6d, 0x11c6f), (0x11c90, 0x11c91), (0x11ca8, 0x11ca8), (0x11cb7, 0x11cff),
+    (0x11d07, 0x11d07), (0x11d0a, 0x11d0a), (0x11d37, 0x11d39), (0x11d3b, 0x11d3b),
+    (0x11d3e, 0x11d3e), (0x11d48, 0x11d4f), (0x11d5a, 0x11d5f), (0x11d66, 0x11d66),
+    (0x11d69, 0x11d69), (0x11d8f, 0x11d8f), (0x11d92, 0x11d92), (0x11d99, 0x11d9f),
+    (0x11daa, 0x11edf), (0x11ef9, 0x11faf), (0x11fb1, 0x11fbf), (0x11ff2, 0x11ffe),
+    (0x1239a, 0x123ff), (0x1246f, 0x1246f), (0x12475, 0x1247f), (0x12544, 0x12f8f),
+    (0x12ff3, 0x12fff), (0x1342f, 0x1342f), (0x13439, 0x143ff), (0x14647, 0x167ff),
+    (0x16a39, 0x16a3f), (0x16a5f, 0x16a5f), (0x16a6a, 0x16a6d), (0x16abf, 0x16abf),
+    (0x16aca, 0x16acf), (0x16aee, 0x16aef), (0x16af6, 0x16aff), (0x16b46, 0x16b4f),
+    (0x16b5a, 0x16b5a), (0x16b62, 0x16b62), (0x16b78, 0x16b7c), (0x16b90, 0x16e3f),
+    (0x16e9b, 0x16eff), (0x16f4b, 0x16f4e), (0x16f88, 0x16f8e), (0x16fa0, 0x16fe3),
+    (0x16fe5, 0x1bbff), (0x1bc6b, 0x1bc6f), (0x1bc7d, 0x1bc7f), (0x1bc89, 0x1bc8f),
+    (0x1bc9a, 0x1bc9b), (0x1bca4, 0x1ceff), (0x1cf2e, 0x1cf2f), (0x1cf47, 0x1cf4f),
+    (0x1cfc4, 0x1cfff), (0x1d0f6, 0x1d0ff), (0x1d127, 0x1d128), (0x1d1eb, 0x1d1ff),
+    (0x1d246, 0x1d2df), (0x1d2f4, 0x1d2ff), (0x1d357, 0x1d35f), (0x1d379, 0x1d3ff),
+    (0x1d455, 0x1d455), (0x1d49d, 0x1d49d), (0x1d4a0, 0x1d4a1), (0x1d4a3, 0x1d4a4),
+    (0x1d4a7, 0x1d4a8), (0x1d4ad, 0x1d4ad), (0x1d4ba, 0x1d4ba), (0x1d4bc, 0x1d4bc),
+    (0x1d4c4, 0x1d4c4), (0x1d506, 0x1d506), (0x1d50b, 0x1d50c), (0x1d515, 0x1d515),
+    (0x1d51d, 0x1d51d), (0x1d53a, 0x1d53a), (0x1d53f, 0x1d53f), (0x1d545, 0x1d545),
+    (0x1d547, 0x1d549), (0x1d551, 0x1d551), (0x1d6a6, 0x1d6a7), (0x1d7cc, 0x1d7cd),
+    (0x1da8c, 0x1da9a), (0x1daa0, 0x1daa0), (0x1dab0, 0x1deff), (0x1df1f, 0x1dfff),
+    (0x1e007, 0x1e007), (0x1e019, 0x1e01a), (0x1e022, 0x1e022), (0x1e025, 0x1e025),
+    (0x1e02b, 0x1e0ff), (0x1e12d, 0x1e12f), (0x1e13e, 0x1e13f), (0x1e14a, 0x1e14d),
+    (0x1e150, 0x1e28f), (0x1e2af, 0x1e2bf), (0x1e2fa, 0x1e2fe), (0x1e300, 0x1e7df),
+    (0x1e7e7, 0x1e7e7), (0x1e7ec, 0x1e7ec), (0x1e7ef, 0x1e7ef), (0x1e7ff, 0x1e7ff),
+    (0x1e8c5, 0x1e8c6), (0x1e8d7, 0x1e8ff), (0x1e94c, 0x1e94f), (0x1e95a, 0x1e95d),
+    (0x1e960, 0x1ec70), (0x1ecb5, 0x1ed00), (0x1ed3e, 0x1edff), (0x1ee04, 0x1ee04),
+    (0x1ee20, 0x1ee20), (0x1ee23, 0x1ee23), (0x1ee25, 0x1ee26), (0x1ee28, 0x1ee28),
+    (0x1ee33, 0x1ee33), (0x1ee38, 0x1ee38), (0x1ee3a, 0x1ee3a), (0x1ee3c, 0x1ee41),
+    (0x1ee43, 0x1ee46), (0x1ee48, 0x1ee48), (0x1ee4a, 0x1ee4a), (0x1ee4c, 0x1ee4c),
+    (0x1ee50, 0x1ee50), (0x1ee53, 0x1ee53), (0x1ee55, 0x1ee56), (0x1ee58, 0x1ee58),
+    (0x1ee5a, 0x1ee5a), (0x1ee5c, 0x1ee5c), (0x1ee5e, 0x1ee5e), (0x1ee60, 0x1ee60),
+    (0x1ee63, 0x1ee63), (0x1ee65, 0x1ee66), (0x1ee6b, 0x1ee6b), (0x1ee73, 0x1ee73),
+    (0x1ee78, 0x1ee78), (0x1ee7d, 0x1ee7d), (0x1ee7f, 0x1ee7f), (0x1ee8a, 0x1ee8a),
+    (0x1ee9c, 0x1eea0), (0x1eea4, 0x1eea4), (0x1eeaa, 0x1eeaa), (0x1eebc, 0x1eeef),
+    (0x1eef2, 0x1efff), (0x1f004, 0x1f004), (0x1f02c, 0x1f02f), (0x1f094, 0x1f09f),
+    (0x1f0af, 0x1f0b0), (0x1f0c0, 0x1f0c0), (0x1f0cf, 0x1f0d0), (0x1f0f6, 0x1f0ff),
+    (0x1f18e, 0x1f18e), (0x1f191, 0x1f19a), (0x1f1ae, 0x1f1e5), (0x1f200, 0x1f320),
+    (0x1f32d, 0x1f335), (0x1f337, 0x1f37c), (0x1f37e, 0x1f393), (0x1f3a0, 0x1f3ca),
+    (0x1f3cf, 0x1f3d3), (0x1f3e0, 0x1f3f0), (0x1f3f4, 0x1f3f4), (0x1f3f8, 0x1f43e),
+    (0x1f440, 0x1f440), (0x1f442, 0x1f4fc), (0x1f4ff, 0x1f53d), (0x1f54b, 0x1f54e),
+    (0x1f550, 0x1f567), (0x1f57a, 0x1f57a), (0x1f595, 0x1f596), (0x1f5a4, 0x1f5a4),
+    (0x1f5fb, 0x1f64f), (0x1f680, 0x1f6c5), (0x1f6cc, 0x1f6cc), (0x1f6d0, 0x1f6d2),
+    (0x1f6d5, 0x1f6df), (0x1f6eb, 0x1f6ef), (0x1f6f4, 0x1f6ff), (0x1f774, 0x1f77f),
+    (0x1f7d9, 0x1f7ff), (0x1f80c, 0x1f80f), (0x1f848, 0x1f84f), (0x1f85a, 0x1f85f),
+    (0x1f888, 0x1f88f), (0x1f8ae, 0x1f8af), (0x1f8b2, 0x1f8ff), (0x1f90c, 0x1f93a),
+    (0x1f93c, 0x1f945), (0x1f947, 0x1f9ff), (0x1fa54, 0x1fa5f), (0x1fa6e, 0x1faff),
+    (0x1fb93, 0x1fb93), (0x1fbcb, 0x1fbef), (0x1fbfa, 0xe0000), (0xe0002, 0xe001f),
+    (0xe0080, 0xe00ff), (0xe01f0, 0xeffff), (0xffffe, 0xfffff), (0x10fffe, 0x10ffff),
+];
+
+fn in_table(table: &[(u32, u32)], c: char) -> bool {
+    let c = c as u32;
+    table
+        .binary_search_by(|&(first, last)| match (last < c, first > c) {
+            (true, _) => std::cmp::Ordering::Less,
+            (_, true) => std::cmp::Ordering::Greater,
+            _ => std::cmp::Ordering::Equal,
+        })
+        .is_ok()
+}
+
+/// display columns taken by `c` on its own: two for wide east asian
+/// characters and emoji, none for combining marks and controls.
+fn char_width(c: char) -> usize {
+    match c as u32 {
+        0..0x20 | 0x7f..0xa0 => 0,
+        0xa0..0x300 => 1,
+        _ if in_table(ZERO_WIDTH, c) => 0,
+        _ if in_table(WIDE, c) => 2,
+        _ => 1,
+    }
+}
+
+const ZERO_WIDTH_JOINER: char = '\u{200d}';
+const EMOJI_PRESENTATION: char = '\u{fe0f}';
+
+fn is_regional_indicator(c: char) -> bool {
+    ('\u{1f1e6}'..='\u{1f1ff}').contains(&c)
+}
+
+fn is_skin_tone(c: char) -> bool {
+    ('\u{1f3fb}'..='\u{1f3ff}').contains(&c)
+}
+
+/// display columns taken by `s`, escape sequences aside.
+fn display_width(s: &str) -> usize {
+    visible_cells(s).iter().map(|c| c.2).sum()
+}
+
+/// length of the escape sequence at the start of `s` (CSI or OSC).
+fn escape_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    match bytes.get(1) {
+        Some(b'[') => {
+            bytes[2..].iter().position(|b| (0x40..=0x7e).contains(b)).map_or(bytes.len(), |p| p + 3)
+        }
+        Some(b']') => {
+            let bel = s[2..].find('\x07').map(|p| p + 3);
+            let st = s[2..].find("\x1b\\").map(|p| p + 4);
+            bel.into_iter().chain(st).min().unwrap_or(bytes.len())
+        }
+        _ => 1,
+    }
+}
+
+/// don't bother with a hanging indent that leaves less room than this.
+const MIN_WRAP_ROOM: usize = 20;
+
+/// the visible characters of a styled line: byte offset, char, columns.
+/// the columns between tab stops.
+const TAB_WIDTH: usize = 8;
+
+/// a grapheme's columns all sit on its first char: what a zero width
+/// joiner, a skin tone or the second flag letter adds to the emoji before
+/// takes none, and an emoji presentation selector widens it instead. so
+/// cutting before a char that takes columns never splits one.
+fn visible_cells(line: &str) -> Vec<(usize, char, usize)> {
+    let mut cells: Vec<(usize, char, usize)> = Vec::new();
+    let mut col = 0;
+    let mut i = 0;
+    // the last char was the first of a flag's two letters
+    let mut flag = false;
+    while let Some(c) = line[i..].chars().next() {
+        if c == '\x1b' {
+            i += escape_len(&line[i..]);
+            continue;
+        }
+        let before = cells.last().map(|cell| (cell.1, cell.2));
+        let mut w = match c {
+            '\t' => TAB_WIDTH - col % TAB_WIDTH,
+            _ if before.is_some_and(|(b, _)| b == ZERO_WIDTH_JOINER) => 0,
+            _ if is_skin_tone(c) && before.is_some_and(|(_, w)| w == 2) => 0,
+            _ => char_width(c),
+        };
+        if is_regional_indicator(c) {
+            if flag {
+                w = 0;
+            }
+            flag = !flag;
+        } else if w > 0 {
+            flag = false;
+        }
+        if let Some(last) = cells.last_mut().filter(|last| last.2 == 1) {
+            if c == EMOJI_PRESENTATION || w == 0 && is_regional_indicator(c) {
+                last.2 = 2;
+                col += 1;
+            }
+        }
+        cells.push((i, c, w));
+        col += w;
+        i += c.len_utf8();
+    }
+    cells
+}
+
+/// fold an already styled line into rows of at most `width` columns,
+/// breaking at spaces where possible. continuation rows start with `marker`
+/// (`marker_width` columns), indented to where the message begins after the
+/// level, and carry on with whatever styling was active at the break.
+fn wrap_styled_line(line: &str, width: usize, marker: &str, marker_width: usize) -> String {
+    let cells = visible_cells(line);
+    if cells.iter().map(|c| c.2).sum::<usize>() <= width {
+        return line.to_string();
+    }
+    let visible: String = cells.iter().map(|c| c.1).collect();
+    let mut indent = find_level(&visible).map_or(0, |(pos, token)| {
+        let rest = &visible[pos + token.len()..];
+        let skip = rest.len() - rest.trim_start_matches([' ', ':', '-']).len();
+        display_width(&visible[..pos + token.len() + skip])
+    });
+    if indent + marker_width + MIN_WRAP_ROOM > width {
+        indent = 0;
+    }
+    let room = width.saturating_sub(indent + marker_width).max(1);
+
+    // which cells are dropped (the spaces at a break) and which start a row
+    let mut skip = vec![false; cells.len()];
+    let mut row_start = vec![false; cells.len()];
+    let (mut start, mut cap) = (0, width);
+    loop {
+        let (mut end, mut used) = (start, 0);
+        while end < cells.len() && used + cells[end].2 <= cap {
+            used += cells[end].2;
+            end += 1;
+        }
+        if end == cells.len() {
+            break;
+        }
+        // even a row too narrow for one char takes a whole grapheme
+        let mut end = end.max(start + 1);
+        while end < cells.len() && cells[end].2 == 0 {
+            end += 1;
+        }
+        if end == cells.len() {
+            break;
+        }
+        let brk = (start + 1..=end).rev().find(|&k| cells[k].1 == ' ').unwrap_or(end);
+        let mut next = brk;
+        while next < cells.len() && cells[next].1 == ' ' {
+            next += 1;
+        }
+        if next == cells.len() {
+            break;
+        }
+        skip[brk..next].fill(true);
+        row_start[next] = true;
+        (start, cap) = (next, room);
+    }
+
+    let mut out = String::with_capacity(line.len() + 16);
+    let mut active = String::new();
+    let mut cell = 0;
+    let mut i = 0;
+    while let Some(c) = line[i..].chars().next() {
+        if c == '\x1b' {
+            let esc = &line[i..i + escape_len(&line[i..])];
+            out.push_str(esc);
+            if is_style_escape(esc) {
+                if esc == RESET || esc == "\x1b[m" {
+                    active.clear();
+                } else {
+                    active.push_str(esc);
+                }
+            }
+            i += esc.len();
+            continue;
+        }
+        if row_start[cell] {
+            if !active.is_empty() {
+                out.push_str(RESET);
+            }
+            out.push('\n');
+            out.extend(std::iter::repeat_n(' ', indent));
+            out.push_str(marker);
+            out.push_str(&active);
+        }
+        if !skip[cell] {
+            out.push(c);
+        }
+        cell += 1;
+        i += c.len_utf8();
+    }
+    out
+}
+
+/// the `↪` that starts a wrapped row, two columns wide.
+fn wrap_marker(styles: &Styles) -> String {
+    let mut marker = Vec::new();
+    // writing into a Vec can't fail
+    let _ = styles.paint(&mut marker, &styles.dim, "\u{21aa}");
+    marker.push(b' ');
+    String::from_utf8(marker).unwrap_or_default()
+}
+
+/// whether the locale's character set is UTF-8, going by `LC_ALL`,
+/// `LC_CTYPE` and `LANG` in the order the C library looks at them.
+fn utf8_locale(var: impl Fn(&str) -> Option<String>) -> bool {
+    let locale =
+        ["LC_ALL", "LC_CTYPE", "LANG"].iter().find_map(|name| var(name).filter(|v| !v.is_empty()));
+    locale.is_some_and(|locale| {
+        let locale = locale.to_ascii_lowercase();
+        locale.contains("utf-8") || locale.contains("utf8")
+    })
+}
+
+const GUTTER_GLYPH: &str = "\u{258c}";
+
+/// `--gutter`: a line's severity as a colored mark in the left margin, the
+/// line itself left as it came so it copies out clean. ERROR and FATAL get
+/// the mark in their color, WARN in its own, the rest a blank margin.
+struct Gutter {
+    error: String,
+    warn: String,
+    blank: String,
+}
+
+impl Gutter {
+    /// with color off there is nothing to tell the marks apart by, and a
+    /// terminal outside a UTF-8 locale may not draw `▌`, so those get `!`
+    /// and `?` instead.
+    fn new(glyph: Option<&str>, styles: &Styles, utf8: bool) -> Gutter {
+        let glyph = glyph.unwrap_or(GUTTER_GLYPH);
+        let plain = !styles.enabled() || (!utf8 && !glyph.is_ascii());
+        let (error, warn) = if plain { ("!", "?") } else { (glyph, glyph) };
+        let mark = |level: Level, glyph: &str| {
+            let mut mark = Vec::new();
+            // writing into a Vec can't fail
+            let _ = styles.paint(&mut mark, styles.color(level), glyph);
+            String::from_utf8(mark).unwrap_or_default() + " "
+        };
+        let width = display_width(glyph).max(1);
+        Gutter {
+            error: mark(Level::Error, error),
+            warn: mark(Level::Warn, warn),
+            blank: " ".repeat(if plain { 2 } else { width + 1 }),
+        }
+    }
+
+    fn mark(&self, level: Option<Level>) -> &str {
+        match level {
+            Some(Level::Error | Level::Fatal) => &self.error,
+            Some(Level::Warn) => &self.warn,
+            _ => &self.blank,
+        }
+    }
+}
+
+/// where a tracked value starts and ends, the `value` of `key=value` or
+/// `key: value`; quoted values keep their quotes.
+fn tracked_values(visible: &str, key: &str) -> Vec<std::ops::Range<usize>> {
+    let bytes = visible.as_bytes();
+    let mut found = Vec::new();
+    for (pos, _) in visible.match_indices(key) {
+        if pos > 0 && (is_word_byte(bytes[pos - 1]) || bytes[pos - 1] == b'.') {
+            continue;
+        }
+        let after = &visible[pos + key.len()..];
+        let sep = if after.starts_with('=') {
+            1
+        } else if after.starts_with(": ") {
+            2
+        } else {
+            continue;
+        };
+        let start = pos + key.len() + sep;
+        let value = &visible[start..];
+        let len = match value.strip_prefix('"') {
+            Some(quoted) => quoted.find('"').map_or(value.len(), |q| q + 2),
+            None => value
+                .find(|c: char| c.is_whitespace() || ",;)]}&".contains(c))
+                .unwrap_or(value.len()),
+        };
+        if len > 0 {
+            found.push(start..start + len);
+        }
+    }
+    found
+}
+
+/// `--track` and `--track-filter`: keys whose values get a color of their
+/// own, and optionally one key=value whose lines are emphasized while the
+/// rest are dimmed.
+struct Tracking {
+    keys: Vec<String>,
+    filter: Option<(String, String)>,
+}
+
+impl Tracking {
+    /// restyle an already styled line.
+    fn apply(&self, line: &str, styles: &Styles) -> String {
+        if !styles.enabled() {
+            return line.to_string();
+        }
+        let visible = visible_text(line);
+        let mut spans = Vec::new();
+        let mut matched = false;
+        for key in &self.keys {
+            for range in tracked_values(&visible, key) {
+                let value = visible[range.clone()].trim_matches('"');
+                if self.filter.as_ref().is_some_and(|(k, v)| k == key && v == value) {
+                    matched = true;
+                }
+                spans.push((range, hash_color(value, styles.depth).fg_sgr(styles.depth)));
+            }
+        }
+        let line_sgr = match &self.filter {
+            None => "",
+            Some(_) if matched => BOLD,
+            Some(_) => DIM,
+        };
+        restyle(line, spans, line_sgr)
+    }
+}
+
+/// `line` without its escape sequences, each taken out whole.
+fn strip_escapes(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(esc) = rest.find('\x1b') {
+        stripped.push_str(&rest[..esc]);
+        rest = &rest[esc + escape_len(&rest[esc..])..];
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// the text of a styled line without its escape sequences.
+fn visible_text(line: &str) -> String {
+    visible_cells(line).iter().map(|c| c.1).collect()
+}
+
+/// add styling on top of an already styled line: each span (a byte range of
+/// its `visible_text`) drawn with its sgr, and `line_sgr` under everything,
+/// kept up across the line's own resets. overlapping spans lose to the one
+/// that starts first.
+fn restyle(line: &str, mut spans: Vec<(std::ops::Range<usize>, String)>, line_sgr: &str) -> String {
+    let cells = visible_cells(line);
+    // visible byte offset -> index of its cell
+    let mut cell_at = Vec::with_capacity(line.len() + 1);
+    for (k, c) in cells.iter().enumerate() {
+        cell_at.extend(std::iter::repeat_n(k, c.1.len_utf8()));
+    }
+    cell_at.push(cells.len());
+    spans.sort_by_key(|s| s.0.start);
+
+    let mut out = String::with_capacity(line.len() + 32);
+    out.push_str(line_sgr);
+    let mut active = String::new();
+    let mut span = spans.iter().map(|(r, sgr)| (cell_at[r.start], cell_at[r.end], sgr)).peekable();
+    let mut open: Option<usize> = None;
+    let mut cell = 0;
+    let mut i = 0;
+    while let Some(c) = line[i..].chars().next() {
+        if c == '\x1b' {
+            let esc = &line[i..i + escape_len(&line[i..])];
+            out.push_str(esc);
+            if is_style_escape(esc) {
+                if esc == RESET || esc == "\x1b[m" {
+                    active.clear();
+                    out.push_str(line_sgr);
+                } else {
+                    active.push_str(esc);
+                }
+            }
+            i += esc.len();
+            continue;
+        }
+        // closed only here, after any escapes the line has at this point
+        if open == Some(cell) {
+            out.push_str(RESET);
+            out.push_str(line_sgr);
+            out.push_str(&active);
+            open = None;
+        }
+        if open.is_none() {
+            while span.next_if(|s| s.0 < cell).is_some() {}
+            if let Some((_, end, sgr)) = span.next_if(|s| s.0 == cell && s.1 > cell) {
+                out.push_str(sgr);
+                open = Some(end);
+            }
+        }
+        out.push(c);
+        cell += 1;
+        i += c.len_utf8();
+    }
+    if open.is_some() || !line_sgr.is_empty() {
+        out.push_str(RESET);
+    }
+    out
+}
+
+/// one step of a `MiniRegex`.
+#[derive(Debug)]
+enum RegexNode {
+    Char(char),
+    Any,
+    /// inclusive ranges, and whether the class is negated.
+    Class(Vec<(char, char)>, bool),
+    Start,
+    End,
+}
+
+#[derive(Debug)]
+struct RegexPiece {
+    node: RegexNode,
+    min: usize,
+    max: usize,
+}
+
+/// a small backtracking regex: literals, `.`, classes like `[a-f0-9]` and
+/// `[^ ]`, `\d` `\w` `\s`, the quantifiers `*` `+` `?`, and the anchors `^`
+/// `$`. enough for log patterns without pulling in a crate.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct MiniRegex {
+    pieces: Vec<RegexPiece>,
+    ignore_case: bool,
+}
+
+impl MiniRegex {
+    pub fn new(pattern: &str, ignore_case: bool) -> Result<MiniRegex, String> {
+        let mut pieces: Vec<RegexPiece> = Vec::new();
+        let mut chars = pattern.chars().peekable();
+        let fold = |c: char| if ignore_case { c.to_ascii_lowercase() } else { c };
+        while let Some(c) = chars.next() {
+            let node = match c {
+                '*' | '+' | '?' => {
+                    let last = pieces
+                        .last_mut()
+                        .filter(|p| p.min == 1 && p.max == 1)
+                        .filter(|p| !matches!(p.node, RegexNode::Start | RegexNode::End))
+                        .ok_or_else(|| format!("nothing to repeat before '{}'", c))?;
+                    (last.min, last.max) = match c {
+                        '*' => (0, usize::MAX),
+                        '+' => (1, usize::MAX),
+                        _ => (0, 1),
+                    };
+                    continue;
+                }
+                '.' => RegexNode::Any,
+                '^' if pieces.is_empty() => RegexNode::Start,
+                '$' if chars.peek().is_none() => RegexNode::End,
+                '\\' => {
+                    let escaped = chars.next().ok_or("trailing backslash")?;
+                    match class_escape(escaped) {
+                        Some((ranges, negated)) => RegexNode::Class(ranges, negated),
+                        None => RegexNode::Char(fold(escaped)),
+                    }
+                }
+                '[' => {
+                    let negated = chars.next_if_eq(&'^').is_some();
+                    let mut ranges = Vec::new();
+                    loop {
+                        let c = chars.next().ok_or("unterminated character class")?;
+                        let lo = match c {
+                            ']' if !ranges.is_empty() => break,
+                            '\\' => {
+                                let escaped = chars.next().ok_or("trailing backslash")?;
+                                if let Some((more, false)) = class_escape(escaped) {
+                                    ranges.extend(more);
+                                    continue;
+                                }
+                                escaped
+                            }
+                            c => c,
+                        };
+                        let range = chars.peek() == Some(&'-')
+                            && chars.clone().nth(1).is_some_and(|c| c != ']');
+                        let hi = if range { chars.nth(1).unwrap_or(lo) } else { lo };
+                        ranges.push((fold(lo), fold(hi)));
+                    }
+                    RegexNode::Class(ranges, negated)
+                }
+                c => RegexNode::Char(fold(c)),
+            };
+            pieces.push(RegexPiece { node, min: 1, max: 1 });
+        }
+        Ok(MiniRegex { pieces, ignore_case })
+    }
+
+    /// byte range of the leftmost (then longest) match.
+    fn find(&self, text: &str) -> Option<std::ops::Range<usize>> {
+        self.find_at(text, 0)
+    }
+
+    /// the first match starting at or after byte `from`.
+    fn find_at(&self, text: &str, from: usize) -> Option<std::ops::Range<usize>> {
+        let starts = text[from..].char_indices().map(|(i, _)| from + i).chain([text.len()]);
+        for start in starts {
+            if let Some(end) = self.match_at(&self.pieces, text, start) {
+                return Some(start..end);
+            }
+            if matches!(self.pieces.first(), Some(RegexPiece { node: RegexNode::Start, .. })) {
+                break;
+            }
+        }
+        None
+    }
+
+    fn matches_char(&self, node: &RegexNode, c: char) -> bool {
+        let c = if self.ignore_case { c.to_ascii_lowercase() } else { c };
+        match node {
+            RegexNode::Char(want) => c == *want,
+            RegexNode::Any => true,
+            RegexNode::Class(ranges, negated) => {
+                ranges.iter().any(|&(lo, hi)| (lo..=hi).contains(&c)) != *negated
+            }
+            RegexNode::Start | RegexNode::End => false,
+        }
+    }
+
+    fn match_at(&self, pieces: &[RegexPiece], text: &str, pos: usize) -> Option<usize> {
+        let Some((piece, rest)) = pieces.split_first() else {
+            return Some(pos);
+        };
+        match piece.node {
+            RegexNode::Start => return (pos == 0).then(|| self.match_at(rest, text, pos))?,
+            RegexNode::End => return (pos == text.len()).then_some(pos),
+            _ => {}
+        }
+        // greedy: take as many as allowed, then give back one at a time
+        let mut ends = vec![pos];
+        let mut chars = text[pos..].chars();
+        while ends.len() <= piece.max {
+            match chars.next() {
+                Some(c) if self.matches_char(&piece.node, c) => {
+                    ends.push(ends[ends.len() - 1] + c.len_utf8())
+                }
+                _ => break,
+            }
+        }
+        (piece.min..ends.len()).rev().find_map(|k| self.match_at(rest, text, ends[k]))
+    }
+}
+
+/// `\d`, `\w`, `\s` and their negations, as class ranges.
+fn class_escape(c: char) -> Option<(Vec<(char, char)>, bool)> {
+    let ranges = match c.to_ascii_lowercase() {
+        'd' => vec![('0', '9')],
+        'w' => vec![('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')],
+        's' => vec![(' ', ' '), ('\t', '\t'), ('\r', '\r'), ('\n', '\n')],
+        _ => return None,
+    };
+    Some((ranges, c.is_ascii_uppercase()))
+}
+
+/// a style spec such as `bold,red`, `underline+#ff8800` or `reverse,208`:
+/// attributes and at most one foreground color, joined by `,` or `+`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Style {
+    /// the attributes' escape codes, like `"\x1b[1m"` for bold.
+    pub attrs: Vec<&'static str>,
+    pub fg: Option<Color>,
+}
+
+const COLOR_NAMES: [&str; 8] =
+    ["black", "red", "green", "yellow", "blue", "magenta", "cyan", "white"];
+
+/// the words `parse_style` takes for attributes.
+const ATTR_NAMES: [(&str, &str); 5] = [
+    ("bold", BOLD),
+    ("dim", DIM),
+    ("italic", ITALIC),
+    ("underline", UNDERLINE),
+    ("reverse", REVERSE),
+];
+
+fn parse_style(spec: &str) -> Result<Style, String> {
+    let mut style = Style { attrs: Vec::new(), fg: None };
+    for word in spec.split([',', '+']).map(str::trim).filter(|w| !w.is_empty()) {
+        if let Some(&(_, attr)) = ATTR_NAMES.iter().find(|(name, _)| *name == word) {
+            style.attrs.push(attr);
+            continue;
+        }
+        let (bright, name) = match word.strip_prefix("bright-").or(word.strip_prefix("bright_")) {
+            Some(name) => (8, name),
+            None => (0, word),
+        };
+        let color = if let Some(n) = COLOR_NAMES.iter().position(|&c| c == name) {
+            Color::Basic(bright + n as u8)
+        } else if matches!(word, "gray" | "grey") {
+            GRAY
+        } else if let Some(hex) = word.strip_prefix('#').filter(|h| h.len() == 6) {
+            let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+            match (channel(0), channel(2), channel(4)) {
+                (Some(r), Some(g), Some(b)) => Color::Rgb(r, g, b),
+                _ => return Err(format!("bad color '{}'", word)),
+            }
+        } else if let Ok(index) = word.parse::<u8>() {
+            Color::Indexed(index)
+        } else {
+            return Err(format!("unknown style '{}'", word));
+        };
+        if style.fg.replace(color).is_some() {
+            return Err(format!("more than one color in '{}'", spec));
+        }
+    }
+    Ok(style)
+}
+
+impl Style {
+    /// in `parse_style`'s words again, e.g. `bold,bright_yellow`.
+    fn spec(&self) -> String {
+        let attrs = self.attrs.iter().filter_map(|&attr| {
+            ATTR_NAMES.iter().find(|(_, sgr)| *sgr == attr).map(|(name, _)| name.to_string())
+        });
+        let words: Vec<String> = attrs.chain(self.fg.map(Color::name)).collect();
+        words.join(",")
+    }
+
+    fn sgr(&self, depth: ColorDepth) -> String {
+        if depth == ColorDepth::None {
+            return String::new();
+        }
+        let mut sgr: String = self.attrs.concat();
+        if let Some(fg) = self.fg {
+            sgr.push_str(&fg.fg_sgr(depth));
+        }
+        sgr
+    }
+}
+
+/// the left column of a rules file: a substring, or `/regex/` (`/regex/i`
+/// ignoring case).
+#[doc(hidden)]
+#[derive(Debug)]
+pub enum Matcher {
+    Substring(String),
+    Regex(MiniRegex),
+}
+
+impl Matcher {
+    fn parse(text: &str) -> Result<Matcher, String> {
+        Matcher::parse_folding(text, false)
+    }
+
+    /// as `parse`, but with `ignore_case` every match is ASCII
+    /// case-insensitive, substrings included, as with `/regex/i`.
+    pub fn parse_folding(text: &str, ignore_case: bool) -> Result<Matcher, String> {
+        let regex = |body: &str, ignore_case| MiniRegex::new(body, ignore_case).map(Matcher::Regex);
+        match text.strip_prefix('/') {
+            Some(rest) if rest.ends_with("/i") && rest.len() > 2 => {
+                regex(&rest[..rest.len() - 2], true)
+            }
+            Some(rest) if rest.ends_with('/') && rest.len() > 1 => {
+                regex(&rest[..rest.len() - 1], ignore_case)
+            }
+            _ if text.is_empty() => Err("empty matcher".to_string()),
+            _ if ignore_case => regex(&literal_regex(text), true),
+            _ => Ok(Matcher::Substring(text.to_string())),
+        }
+    }
+
+    pub fn find(&self, text: &str) -> Option<std::ops::Range<usize>> {
+        match self {
+            Matcher::Substring(s) => text.find(s.as_str()).map(|pos| pos..pos + s.len()),
+            Matcher::Regex(re) => re.find(text),
+        }
+    }
+
+    /// every non-empty, non-overlapping match, left to right.
+    pub fn find_all(&self, text: &str) -> Vec<std::ops::Range<usize>> {
+        let mut found = Vec::new();
+        let mut from = 0;
+        while from <= text.len() {
+            let hit = match self {
+                Matcher::Substring(s) => {
+                    text[from..].find(s.as_str()).map(|p| from + p..from + p + s.len())
+                }
+                Matcher::Regex(re) => re.find_at(text, from),
+            };
+            let Some(hit) = hit else { break };
+            if hit.is_empty() {
+                from = hit.end + text[hit.end..].chars().next().map_or(1, char::len_utf8);
+                continue;
+            }
+            from = hit.end;
+            found.push(hit);
+        }
+        found
+    }
+}
+
+/// a `MiniRegex` pattern matching `text` as it is: everything but letters,
+/// digits and spaces escaped.
+fn literal_regex(text: &str) -> String {
+    let mut pattern = String::with_capacity(text.len());
+    for c in text.chars() {
+        if !c.is_alphanumeric() && c != ' ' {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern
+}
+
+/// `--grep`, with grep's `-B` and `-A` lines of context around matches.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Grep {
+    pub matcher: Matcher,
+    pub before: usize,
+    pub after: usize,
+}
+
+impl Grep {
+    /// the matches in an already rendered line, bold and underlined.
+    fn emphasize(&self, line: &str) -> String {
+        let visible = visible_text(line);
+        let sgr = format!("{}{}", BOLD, UNDERLINE);
+        let spans: Vec<_> =
+            self.matcher.find_all(&visible).into_iter().map(|r| (r, sgr.clone())).collect();
+        if spans.is_empty() {
+            return line.to_string();
+        }
+        restyle(line, spans, "")
+    }
+}
+
+/// what `ContextWindow::push` lets through, each with its input offset.
+#[derive(Debug, PartialEq, Eq)]
+enum Shown {
+    /// `--` between groups of lines that aren't next to each other.
+    Separator,
+    Context(String, u64),
+    Match(String, u64),
+}
+
+/// decides which lines `--grep` shows: the matches, up to `before` lines
+/// kept back in case a match comes, and `after` lines once one has.
+struct ContextWindow {
+    before: usize,
+    after: usize,
+    /// the last lines not shown, numbered, at most `before` of them.
+    recent: std::collections::VecDeque<(usize, String, u64)>,
+    after_left: usize,
+    line_no: usize,
+    last_shown: Option<usize>,
+    /// lines shown so far, matches and context.
+    shown: usize,
+}
+
+impl ContextWindow {
+    fn new(grep: &Grep) -> ContextWindow {
+        ContextWindow {
+            before: grep.before,
+            after: grep.after,
+            recent: std::collections::VecDeque::new(),
+            after_left: 0,
+            line_no: 0,
+            last_shown: None,
+            shown: 0,
+        }
+    }
+
+    fn push(&mut self, line: String, offset: u64, matched: bool) -> Vec<Shown> {
+        self.line_no += 1;
+        let mut shown = Vec::new();
+        if matched {
+            let first = self.recent.front().map_or(self.line_no, |r| r.0);
+            if self.last_shown.is_some_and(|last| first > last + 1) {
+                shown.push(Shown::Separator);
+            }
+            shown.extend(
+                self.recent.drain(..).map(|(_, line, offset)| Shown::Context(line, offset)),
+            );
+            shown.push(Shown::Match(line, offset));
+            self.after_left = self.after;
+        } else if self.after_left > 0 {
+            self.after_left -= 1;
+            shown.push(Shown::Context(line, offset));
+        } else {
+            if self.before > 0 {
+                self.recent.push_back((self.line_no, line, offset));
+            }
+            if self.recent.len() > self.before {
+                self.recent.pop_front();
+            }
+            return shown;
+        }
+        self.last_shown = Some(self.line_no);
+        self.shown += shown.iter().filter(|s| !matches!(s, Shown::Separator)).count();
+        shown
+    }
+
+    /// the line pushed last was shown, so the rest of it should be too.
+    fn showing(&self) -> bool {
+        self.last_shown == Some(self.line_no)
+    }
+}
+
+/// what a rule does to the lines it matches.
+#[derive(Debug, PartialEq, Eq)]
+enum RuleAction {
+    /// style just the match.
+    Highlight,
+    /// style the whole line.
+    Line,
+    /// drop the line.
+    Suppress,
+    /// take the line for one of this level: its token drawn so, and it
+    /// counted, filtered and colored as one.
+    Level(Level),
+}
+
+#[derive(Debug)]
+struct Rule {
+    matcher: Matcher,
+    style: Style,
+    action: RuleAction,
+}
+
+/// the level colors of the config file and the `--rules`, read from their
+/// files again when SIGHUP asks for it. the swap happens between lines, and
+/// a line holds on to the set it started with.
+struct Settings {
+    /// the config file, `--config`'s or the one found by default.
+    config: Option<PathBuf>,
+    rules: Option<String>,
+    /// the palette's looks, that the config file's are drawn over.
+    base: Styles,
+    current: std::sync::Mutex<std::sync::Arc<Loaded>>,
+    /// set from the signal thread, taken by the reader.
+    reload: std::sync::Arc<std::sync::atomic::AtomicBool>,
+}
+
+/// what `Settings` has in force.
+struct Loaded {
+    styles: Styles,
+    rules: Vec<Rule>,
+}
+
+impl Settings {
+    /// `styles`, `base` with a config file's looks, and `rules`; without
+    /// files to read them from again.
+    fn new(base: Styles, styles: Styles, rules: Vec<Rule>) -> Settings {
+        Settings {
+            config: None,
+            rules: None,
+            base,
+            current: std::sync::Mutex::new(std::sync::Arc::new(Loaded { styles, rules })),
+            reload: Default::default(),
+        }
+    }
+
+    fn current(&self) -> std::sync::Arc<Loaded> {
+        self.current.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// what is in force, changed while the options are set up and nothing
+    /// shares it yet.
+    fn current_mut(&mut self) -> &mut Loaded {
+        let current = self.current.get_mut().unwrap_or_else(|e| e.into_inner());
+        std::sync::Arc::get_mut(current).expect("settings changed while in use")
+    }
+
+    /// draw with `styles`, the config file's looks and all.
+    fn set_styles(&mut self, styles: Styles) {
+        self.base = styles.clone();
+        self.current_mut().styles = styles;
+    }
+
+    /// there are rules to apply, now or after a reload.
+    fn has_rules(&self) -> bool {
+        self.rules.is_some() || !self.current().rules.is_empty()
+    }
+
+    /// there is a file to read again on SIGHUP.
+    fn reloadable(&self) -> bool {
+        self.config.is_some() || self.rules.is_some()
+    }
+
+    /// the level the last `level=` rule matching `line` gives it.
+    fn level(&self, line: &str) -> Option<Level> {
+        let current = self.current();
+        let leveled = current.rules.iter().rev().filter_map(|r| match r.action {
+            RuleAction::Level(level) => Some((&r.matcher, level)),
+            _ => None,
+        });
+        leveled.filter(|(matcher, _)| matcher.find(line).is_some()).map(|(_, level)| level).next()
+    }
+
+    /// read the files again if SIGHUP came since the last line; if either
+    /// is broken, why the old colors and rules are still in force.
+    fn reload_if_asked(&self) -> Option<Result<(), String>> {
+        if !self.reloadable() || !self.reload.swap(false, std::sync::atomic::Ordering::Relaxed) {
+            return None;
+        }
+        let scheme = self.config.as_deref().map(read_scheme).transpose();
+        let rules = self.rules.as_deref().map(read_rules).transpose();
+        Some(scheme.and_then(|scheme| Ok((scheme, rules?))).map(|(scheme, rules)| {
+            let styles = self.base.clone().with_scheme(&scheme.unwrap_or_default());
+            let loaded = Loaded { styles, rules: rules.unwrap_or_default() };
+            *self.current.lock().unwrap_or_else(|e| e.into_inner()) = std::sync::Arc::new(loaded);
+        }))
+    }
+}
+
+fn read_rules(path: &str) -> Result<Vec<Rule>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to read rules '{}': {}", path, e))?;
+    parse_rules(&text, path)
+}
+
+/// the marker a reload leaves in the output, dim, or red when the config
+/// file or the rules didn't parse.
+fn write_reload_notice(
+    out: &mut dyn Write,
+    reloaded: Result<(), String>,
+    styles: &Styles,
+) -> io::Result<()> {
+    match reloaded {
+        Ok(_) => styles.paint(out, &styles.dim, "--- configuration reloaded ---")?,
+        Err(e) => {
+            let notice = format!("--- configuration not reloaded: {} ---", e);
+            styles.paint(out, styles.color(Level::Error), &notice)?;
+        }
+    }
+    writeln!(out)
+}
+
+/// a rules file: one `MATCHER<TAB>STYLE<TAB>ACTION` per line, `-` for no
+/// style, the action defaulting to `highlight`. blank lines and `#` comments
+/// are skipped. errors name `origin` and the line number.
+fn parse_rules(text: &str, origin: &str) -> Result<Vec<Rule>, String> {
+    let mut rules = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        if line.trim().is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let err = |msg: String| format!("{}:{}: {}", origin, n + 1, msg);
+        let mut columns = line.split('\t');
+        let matcher = Matcher::parse(columns.next().unwrap_or_default()).map_err(err)?;
+        let style = match columns.next().map(str::trim) {
+            None | Some("-") => Style { attrs: Vec::new(), fg: None },
+            Some(spec) => parse_style(spec).map_err(err)?,
+        };
+        let action = match columns.next().map(str::trim) {
+            None | Some("highlight") => RuleAction::Highlight,
+            Some("line") => RuleAction::Line,
+            Some("suppress") => RuleAction::Suppress,
+            Some(other) => match other.strip_prefix("level=") {
+                Some(level) => RuleAction::Level(
+                    Level::from_token(&level.to_uppercase())
+                        .ok_or_else(|| err(format!("unknown level '{}'", level)))?,
+                ),
+                None => return Err(err(format!("unknown action '{}'", other))),
+            },
+        };
+        if columns.next().is_some() {
+            return Err(err("expected at most three tab-separated columns".to_string()));
+        }
+        rules.push(Rule { matcher, style, action });
+    }
+    Ok(rules)
+}
+
+/// fewer bytes than this in a row are taken for an id, not a dump.
+const HEX_MIN_BYTES: usize = 8;
+/// bytes colored alike before the color switches.
+const HEX_GROUP_BYTES: usize = 4;
+
+/// a hex dump in a line: where it is, and each byte's token (its `0x`
+/// included) and value.
+#[derive(Debug, PartialEq, Eq)]
+struct HexRun {
+    range: std::ops::Range<usize>,
+    bytes: Vec<(std::ops::Range<usize>, u8)>,
+}
+
+/// the hex dumps in `text`: at least `HEX_MIN_BYTES` bytes written
+/// `0x48 0x54 ...` or `48 54 ...` (spaces or commas between), or as one
+/// unbroken `48545450...` with a 0x or at least one of a-f in it.
+fn find_hex_runs(text: &str) -> Vec<HexRun> {
+    let b = text.as_bytes();
+    let boundary = |i: usize| i == 0 || !b[i - 1].is_ascii_alphanumeric();
+    let ends = |i: usize| b.get(i).is_none_or(|c| !c.is_ascii_alphanumeric());
+    let prefix = |i: usize| b[i..].starts_with(b"0x") || b[i..].starts_with(b"0X");
+    let hex_value = |i: usize| -> Option<u8> {
+        let digits = text.get(i..i + 2)?;
+        digits
+            .bytes()
+            .all(|c| c.is_ascii_hexdigit())
+            .then(|| u8::from_str_radix(digits, 16).ok())?
+    };
+    // one `0x48` or `48` token at `i`: its digits' start and its end
+    let token = |i: usize| -> Option<(usize, u8)> {
+        let digits = if prefix(i) { i + 2 } else { i };
+        let value = hex_value(digits)?;
+        ends(digits + 2).then_some((digits, value))
+    };
+    let mut runs = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        if !boundary(i) {
+            i += 1;
+            continue;
+        }
+        let mut bytes = Vec::new();
+        let mut pos = i;
+        while let Some((digits, value)) = token(pos) {
+            bytes.push((pos..digits + 2, value));
+            let next = digits + 2;
+            let gap = b[next..].iter().take_while(|&&c| c == b' ' || c == b',').count();
+            if gap == 0 || gap > 2 || next + gap >= b.len() {
+                break;
+            }
+            pos = next + gap;
+        }
+        if bytes.len() >= HEX_MIN_BYTES {
+            let end = bytes.last().map_or(i, |(r, _)| r.end);
+            runs.push(HexRun { range: i..end, bytes });
+            i = end;
+            continue;
+        }
+        let digits = if prefix(i) { i + 2 } else { i };
+        let len = b[digits..].iter().take_while(|c| c.is_ascii_hexdigit()).count();
+        let lettered = prefix(i) || b[digits..digits + len].iter().any(|c| c.is_ascii_alphabetic());
+        if len >= 2 * HEX_MIN_BYTES && len % 2 == 0 && lettered && ends(digits + len) {
+            let mut bytes: Vec<_> = (digits..digits + len)
+                .step_by(2)
+                .filter_map(|at| Some((at..at + 2, hex_value(at)?)))
+                .collect();
+            // the 0x goes with the first byte
+            bytes[0].0.start = i;
+            runs.push(HexRun { range: i..digits + len, bytes });
+            i = digits + len;
+            continue;
+        }
+        i += 1;
+    }
+    runs
+}
+
+/// `--hex` and `--hex-ascii`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct HexDumps {
+    /// follow each dump with its printable characters, dimmed.
+    ascii: bool,
+}
+
+impl HexDumps {
+    /// restyle an already styled line: the bytes of its dumps colored four
+    /// at a time in two alternating colors, each dump followed by `|HTTP|`
+    /// when asked for.
+    fn apply(&self, line: &str, styles: &Styles) -> String {
+        let visible = visible_text(line);
+        let runs = find_hex_runs(&visible);
+        if runs.is_empty() {
+            return line.to_string();
+        }
+        let mut styled = line.to_string();
+        if styles.enabled() {
+            let colors = [Color::Basic(6), Color::Basic(4)].map(|c| c.fg_sgr(styles.depth));
+            let spans = runs.iter().flat_map(|run| {
+                run.bytes.chunks(HEX_GROUP_BYTES).enumerate().map(|(n, group)| {
+                    (group[0].0.start..group[group.len() - 1].0.end, colors[n % 2].clone())
+                })
+            });
+            styled = restyle(line, spans.collect(), "");
+        }
+        if !self.ascii {
+            return styled;
+        }
+        // where each dump ends in the styled line, placed from the last one
+        // back so the earlier positions hold
+        let cells = visible_cells(&styled);
+        let mut at = Vec::with_capacity(visible.len() + 1);
+        for cell in &cells {
+            at.extend(std::iter::repeat_n(cell.0, cell.1.len_utf8()));
+        }
+        at.push(styled.len());
+        for run in runs.iter().rev() {
+            let printable: String = run
+                .bytes
+                .iter()
+                .map(|&(_, b)| if (0x20..0x7f).contains(&b) { char::from(b) } else { '.' })
+                .collect();
+            let pos = at[run.range.end];
+            let active = active_sgr(&styled[..pos]);
+            // the line's own looks are put down for the note, if it has any
+            let reset = if active.is_empty() { "" } else { RESET };
+            let note = match styles.enabled() {
+                true => format!(" {}{}|{}|{}{}", reset, styles.dim, printable, RESET, active),
+                false => format!(" |{}|", printable),
+            };
+            styled.insert_str(pos, &note);
+        }
+        styled
+    }
+}
+
+/// the characters that draw as nothing, or as something they aren't, and
+/// their placeholder names. the bidi ones can reorder what the terminal
+/// shows, so they're flagged.
+#[rustfmt::skip]
+const INVISIBLES: &[(char, &str, bool)] = &[
+    ('\u{00a0}', "NBSP", false), ('\u{00ad}', "SHY", false), ('\u{061c}', "ALM", false),
+    ('\u{180e}', "MVS", false), ('\u{200b}', "ZWSP", false), ('\u{200c}', "ZWNJ", false),
+    ('\u{200d}', "ZWJ", false), ('\u{200e}', "LRM", false), ('\u{200f}', "RLM", false),
+    ('\u{202a}', "LRE", true), ('\u{202b}', "RLE", true), ('\u{202c}', "PDF", true),
+    ('\u{202d}', "LRO", true), ('\u{202e}', "RLO", true), ('\u{2060}', "WJ", false),
+    ('\u{2066}', "LRI", true), ('\u{2067}', "RLI", true), ('\u{2068}', "FSI", true),
+    ('\u{2069}', "PDI", true), ('\u{feff}', "BOM", false),
+];
+
+/// the placeholder name of `c` and whether it's a bidi override, embedding
+/// or isolate.
+fn invisible(c: char) -> Option<(&'static str, bool)> {
+    let i = INVISIBLES.binary_search_by_key(&c, |&(c, ..)| c).ok()?;
+    Some((INVISIBLES[i].1, INVISIBLES[i].2))
+}
+
+/// `--show-invisibles` and `--allow-bidi`. by default only the bidi
+/// controls are replaced, so a line can't flip the text around it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Invisibles {
+    show: bool,
+    allow_bidi: bool,
+}
+
+impl Invisibles {
+    fn active(&self) -> bool {
+        self.show || !self.allow_bidi
+    }
+
+    fn replaced(&self, c: char) -> Option<&'static str> {
+        let (name, bidi) = invisible(c)?;
+        (self.show || (bidi && !self.allow_bidi)).then_some(name)
+    }
+
+    /// an already styled line with the characters asked for drawn as
+    /// `⟨ZWSP⟩`, colored, and the line's own style picked up after each.
+    fn apply(&self, line: &str, styles: &Styles) -> String {
+        // every one of them starts with one of these bytes
+        if !line.bytes().any(|b| matches!(b, 0xc2 | 0xd8 | 0xe1 | 0xe2 | 0xef)) {
+            return line.to_string();
+        }
+        let color = Color::Basic(5).fg_sgr(styles.depth);
+        let mut out = String::with_capacity(line.len() + 16);
+        for (i, c) in line.char_indices() {
+            match self.replaced(c) {
+                Some(name) if styles.enabled() => {
+                    // a reset ahead of it only to put down a style that's on
+                    let resume = active_sgr(&line[..i]);
+                    let reset = if resume.is_empty() { "" } else { RESET };
+                    out.push_str(&format!(
+                        "{}{}\u{27e8}{}\u{27e9}{}{}",
+                        reset, color, name, RESET, resume
+                    ));
+                }
+                Some(name) => out.push_str(&format!("\u{27e8}{}\u{27e9}", name)),
+                None => out.push(c),
+            }
+        }
+        out
+    }
+}
+
+/// an SGR sequence, or a level's tag ahead of one: what lasts until a reset.
+fn is_style_escape(esc: &str) -> bool {
+    esc.ends_with('m') || esc.starts_with(LEVEL_TAG_PREFIX)
+}
+
+/// the SGR sequences in effect at the end of a styled `prefix`.
+fn active_sgr(prefix: &str) -> String {
+    let mut active = String::new();
+    let mut i = 0;
+    while let Some(c) = prefix[i..].chars().next() {
+        if c != '\x1b' {
+            i += c.len_utf8();
+            continue;
+        }
+        let esc = &prefix[i..i + escape_len(&prefix[i..])];
+        if esc == RESET || esc == "\x1b[m" {
+            active.clear();
+        } else if is_style_escape(esc) {
+            active.push_str(esc);
+        }
+        i += esc.len();
+    }
+    active
+}
+
+/// run the rules that act on whole lines, in order, over an already styled
+/// line. highlights are drawn with the line's spans, and `level=` rules
+/// are taken into account with its level by `level_of`.
+fn apply_rules(line: &str, rules: &[Rule], styles: &Styles) -> Option<String> {
+    let visible = visible_text(line);
+    let mut line_sgr = String::new();
+    for rule in rules.iter().filter(|rule| rule.matcher.find(&visible).is_some()) {
+        match rule.action {
+            RuleAction::Suppress => return None,
+            RuleAction::Line => line_sgr = rule.style.sgr(styles.depth),
+            RuleAction::Highlight | RuleAction::Level(_) => {}
+        }
+    }
+    if !styles.enabled() || line_sgr.is_empty() {
+        return Some(line.to_string());
+    }
+    Some(restyle(line, Vec::new(), &line_sgr))
+}
+
+/// the rules' highlights over an already styled line, for what isn't drawn
+/// from spans.
+fn apply_highlights(line: &str, rules: &[Rule], styles: &Styles) -> String {
+    let visible = visible_text(line);
+    let highlights = rules.iter().filter(|r| matches!(r.action, RuleAction::Highlight));
+    let found =
+        highlights.filter_map(|r| Some((r.matcher.find(&visible)?, r.style.sgr(styles.depth))));
+    let spans: Vec<_> = found.collect();
+    if !styles.enabled() || spans.is_empty() {
+        return line.to_string();
+    }
+    restyle(line, spans, "")
+}
+
+/// hands every complete line written through it to `transform` on the way
+/// to `inner`; lines it returns `None` for are dropped.
+struct LineWriter<'a, F: FnMut(&str) -> Option<String>> {
+    inner: &'a mut dyn Write,
+    transform: F,
+    pending: Vec<u8>,
+}
+
+impl<'a, F: FnMut(&str) -> Option<String>> LineWriter<'a, F> {
+    fn new(inner: &'a mut dyn Write, transform: F) -> LineWriter<'a, F> {
+        LineWriter { inner, transform, pending: Vec::new() }
+    }
+}
+
+impl<F: FnMut(&str) -> Option<String>> Write for LineWriter<'_, F> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        while let Some(nl) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=nl).collect();
+            let text = String::from_utf8_lossy(&line[..nl]);
+            if let Some(mut line) = (self.transform)(&text) {
+                // in one piece, for `marks`
+                line.push('\n');
+                self.inner.write_all(line.as_bytes())?;
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<F: FnMut(&str) -> Option<String>> Drop for LineWriter<'_, F> {
+    fn drop(&mut self) {
+        // an unterminated last line goes out as is
+        let _ = self.inner.write_all(&self.pending);
+    }
+}
+
+/// `2024-05-01T12:03:04.5+02:00` (or with a space for the `T`) as seconds
+/// since the epoch; the offset is optional and taken as UTC when missing.
+fn parse_iso_seconds(s: &str) -> Option<f64> {
+    let b = s.as_bytes();
+    let num = |range: std::ops::Range<usize>| -> Option<i64> {
+        let digits = b.get(range)?;
+        digits
+            .iter()
+            .all(u8::is_ascii_digit)
+            .then(|| digits.iter().fold(0, |n, d| n * 10 + i64::from(d - b'0')))
+    };
+    if b.len() < 19 || b[4] != b'-' || b[7] != b'-' || !matches!(b[10], b'T' | b' ') {
+        return None;
+    }
+    if b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+    let (month, day) = (num(5..7)? as u32, num(8..10)? as u32);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    let days = days_from_civil(num(0..4)?, month, day);
+    let mut secs = (days * 86_400 + num(11..13)? * 3600 + num(14..16)? * 60 + num(17..19)?) as f64;
+    let mut pos = 19;
+    if matches!(b.get(pos), Some(b'.' | b',')) {
+        let digits = b[pos + 1..].iter().take_while(|c| c.is_ascii_digit()).count();
+        secs +=
+            s[pos + 1..pos + 1 + digits].parse::<f64>().unwrap_or(0.0) / 10f64.powi(digits as i32);
+        pos += 1 + digits;
+    }
+    if let Some(sign @ (b'+' | b'-')) = b.get(pos) {
+        let hours = num(pos + 1..pos + 3)?;
+        let minutes = num(pos + 4..pos + 6).or_else(|| num(pos + 3..pos + 5)).unwrap_or(0);
+        let offset = (hours * 3600 + minutes * 60) as f64;
+        secs += if *sign == b'+' { -offset } else { offset };
+    }
+    Some(secs)
+}
+
+const MONTHS: [&str; 12] =
+    ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// BSD syslog's `May  1 12:03:04`, which has no year, as seconds into 1970.
+fn parse_syslog_seconds(s: &str) -> Option<f64> {
+    let month = MONTHS.iter().position(|m| s.starts_with(m))? as u32 + 1;
+    let rest = s.get(3..)?.trim_start();
+    let (day, rest) = rest.split_once(' ')?;
+    let time = rest.get(..8)?;
+    let mut parts = time.split(':').map(|p| p.parse::<i64>().ok());
+    let (h, m, sec) = (parts.next()??, parts.next()??, parts.next()??);
+    let days = days_from_civil(1970, month, day.parse().ok()?);
+    Some((days * 86_400 + h * 3600 + m * 60 + sec) as f64)
+}
+
+/// where zones are looked up, unless `$TZDIR` says otherwise.
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+/// one of a zone's kinds of local time: its offset from UTC in seconds and
+/// what it's called, `EDT`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct LocalType {
+    offset: i64,
+    dst: bool,
+    abbrev: String,
+}
+
+/// a POSIX TZ rule such as `EST5EDT,M3.2.0,M11.1.0`: standard time, and
+/// when there's daylight time the two yearly changes to and from it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct PosixTz {
+    std: LocalType,
+    dst: Option<(LocalType, Change, Change)>,
+}
+
+/// `Mm.w.d/time`: weekday `d` (0 is Sunday) of week `w` (5 being the last)
+/// of month `m`, `time` seconds into that day's local time.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Change {
+    month: u32,
+    week: u32,
+    weekday: u32,
+    time: i64,
+}
+
+/// a zone abbreviation, `EST` or `<+0330>`, and what follows it.
+fn tz_name(spec: &str) -> Option<(&str, &str)> {
+    if let Some(quoted) = spec.strip_prefix('<') {
+        let end = quoted.find('>')?;
+        return Some((&quoted[..end], &quoted[end + 1..]));
+    }
+    let len = spec.bytes().take_while(u8::is_ascii_alphabetic).count();
+    (len >= 3).then(|| spec.split_at(len))
+}
+
+/// `[+-]hh[:mm[:ss]]` as seconds, and what follows it.
+fn tz_offset(spec: &str) -> Option<(i64, &str)> {
+    let (sign, mut rest) = match spec.as_bytes().first()? {
+        b'-' => (-1, &spec[1..]),
+        b'+' => (1, &spec[1..]),
+        _ => (1, spec),
+    };
+    let mut secs = 0;
+    for (i, unit) in [3600, 60, 1].into_iter().enumerate() {
+        if i > 0 {
+            match rest.strip_prefix(':') {
+                Some(after) => rest = after,
+                None => break,
+            }
+        }
+        let len = rest.bytes().take_while(u8::is_ascii_digit).take(3).count();
+        secs += unit * rest[..len].parse::<i64>().ok()?;
+        rest = &rest[len..];
+    }
+    Some((sign * secs, rest))
+}
+
+impl Change {
+    fn parse(spec: &str) -> Option<Change> {
+        let (date, time) = match spec.split_once('/') {
+            Some((date, time)) => (date, tz_offset(time).filter(|(_, rest)| rest.is_empty())?.0),
+            None => (spec, 7200),
+        };
+        let mut parts = date.strip_prefix('M')?.split('.').map(|p| p.parse::<u32>().ok());
+        let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+        let valid = (1..=12).contains(&month) && (1..=5).contains(&week) && weekday <= 6;
+        (valid && parts.next().is_none()).then_some(Change { month, week, weekday, time })
+    }
+
+    /// when it happens in `year`, for a clock `offset` ahead of UTC.
+    fn at(&self, year: i64, offset: i64) -> i64 {
+        let first = days_from_civil(year, self.month, 1);
+        let next = match self.month {
+            12 => days_from_civil(year + 1, 1, 1),
+            month => days_from_civil(year, month + 1, 1),
+        };
+        // 1970-01-01 was a Thursday
+        let first_weekday = (first + 4).rem_euclid(7);
+        let mut day = first + (i64::from(self.weekday) - first_weekday).rem_euclid(7);
+        day += 7 * (i64::from(self.week) - 1);
+        while day >= next {
+            day -= 7;
+        }
+        day * 86_400 + self.time - offset
+    }
+}
+
+impl PosixTz {
+    fn parse(spec: &str) -> Option<PosixTz> {
+        let (name, rest) = tz_name(spec)?;
+        let (offset, rest) = tz_offset(rest)?;
+        // POSIX counts west of Greenwich as positive
+        let std = LocalType { offset: -offset, dst: false, abbrev: name.to_string() };
+        if rest.is_empty() {
+            return Some(PosixTz { std, dst: None });
+        }
+        let (name, rest) = tz_name(rest)?;
+        let (offset, rest) = match tz_offset(rest) {
+            Some((offset, rest)) => (-offset, rest),
+            None => (std.offset + 3600, rest),
+        };
+        let dst = LocalType { offset, dst: true, abbrev: name.to_string() };
+        // the US rules, POSIX's default
+        let changes = if rest.is_empty() { "M3.2.0,M11.1.0" } else { rest.strip_prefix(',')? };
+        let (start, end) = changes.split_once(',')?;
+        Some(PosixTz { std, dst: Some((dst, Change::parse(start)?, Change::parse(end)?)) })
+    }
+
+    fn local_type(&self, t: i64) -> &LocalType {
+        let Some((dst, start, end)) = &self.dst else {
+            return &self.std;
+        };
+        let year = civil_from_days((t + self.std.offset).div_euclid(86_400)).0;
+        // the change to daylight time is given in standard time and back
+        let (start, end) = (start.at(year, self.std.offset), end.at(year, dst.offset));
+        let daylight = match start < end {
+            true => start <= t && t < end,
+            // the southern hemisphere's, over the new year
+            false => !(end <= t && t < start),
+        };
+        if daylight { dst } else { &self.std }
+    }
+}
+
+/// `--tz` and `--local`: a zone as read from its TZif file, the moments its
+/// local time changed and what to, and the rule for times after the last.
+#[derive(Debug)]
+struct Zone {
+    /// (UTC seconds, index into `types`), in order.
+    transitions: Vec<(i64, usize)>,
+    types: Vec<LocalType>,
+    rule: Option<PosixTz>,
+}
+
+impl Zone {
+    /// a zone by its IANA name, `US/Eastern`.
+    fn load(name: &str) -> Result<Zone, String> {
+        if name.is_empty() || name.starts_with('/') || name.split('/').any(|part| part == "..") {
+            return Err(format!("'{}' is not a time zone name", name));
+        }
+        let dir = env::var("TZDIR").ok().filter(|dir| !dir.is_empty());
+        let path = Path::new(dir.as_deref().unwrap_or(ZONEINFO_DIR)).join(name);
+        match std::fs::read(&path) {
+            Ok(data) => Zone::parse(&data)
+                .ok_or_else(|| format!("{} is not a time zone file", path.display())),
+            // still there when the zone files aren't
+            Err(_) if name == "UTC" => {
+                let utc = LocalType { offset: 0, dst: false, abbrev: "UTC".to_string() };
+                Ok(Zone::ruled(PosixTz { std: utc, dst: None }))
+            }
+            Err(_) => Err(format!("unknown time zone '{}': there's no {}", name, path.display())),
+        }
+    }
+
+    /// the local zone: `$TZ` (a name, `:name`, a path or a POSIX rule), or
+    /// failing that `/etc/localtime`, or UTC.
+    fn local() -> Result<Zone, String> {
+        let tz = env::var("TZ").unwrap_or_default();
+        let tz = tz.strip_prefix(':').unwrap_or(&tz);
+        if tz.starts_with('/') {
+            let data =
+                std::fs::read(tz).map_err(|e| format!("failed to read $TZ {}: {}", tz, e))?;
+            return Zone::parse(&data).ok_or_else(|| format!("{} is not a time zone file", tz));
+        }
+        if !tz.is_empty() {
+            return Zone::load(tz).or_else(|e| PosixTz::parse(tz).map(Zone::ruled).ok_or(e));
+        }
+        match std::fs::read("/etc/localtime").ok().and_then(|data| Zone::parse(&data)) {
+            Some(zone) => Ok(zone),
+            None => Zone::load("UTC"),
+        }
+    }
+
+    fn ruled(rule: PosixTz) -> Zone {
+        Zone { transitions: Vec::new(), types: vec![rule.std.clone()], rule: Some(rule) }
+    }
+
+    /// a TZif file (RFC 8536), version 1 or the 64-bit data of later ones.
+    fn parse(data: &[u8]) -> Option<Zone> {
+        let counts = |at: usize| -> Option<[usize; 6]> {
+            let header = data.get(at..at + 44).filter(|h| h.starts_with(b"TZif"))?;
+            let count = |i: usize| {
+                let bytes = [
+                    header[20 + 4 * i],
+                    header[21 + 4 * i],
+                    header[22 + 4 * i],
+                    header[23 + 4 * i],
+                ];
+                u32::from_be_bytes(bytes) as usize
+            };
+            Some([count(0), count(1), count(2), count(3), count(4), count(5)])
+        };
+        let version = *data.get(4)?;
+        let [ut, std, leap, times, types, chars] = counts(0)?;
+        let v1_len = times * 5 + types * 6 + chars + leap * 8 + std + ut;
+        let (mut pos, time_len) = (44, if version >= b'2' { 8 } else { 4 });
+        let [ut, std, leap, times, types, chars] = match version >= b'2' {
+            true => {
+                pos += v1_len + 44;
+                counts(44 + v1_len)?
+            }
+            false => [ut, std, leap, times, types, chars],
+        };
+        let mut take = |len: usize| {
+            pos += len;
+            data.get(pos - len..pos)
+        };
+        let (at, indices, records) = (take(times * time_len)?, take(times)?, take(types * 6)?);
+        let names = take(chars)?;
+        take(leap * (time_len + 4) + std + ut)?;
+        let types: Vec<LocalType> = records
+            .chunks(6)
+            .map(|record| {
+                let name = names.get(usize::from(record[5])..).unwrap_or_default();
+                let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+                LocalType {
+                    offset: i32::from_be_bytes([record[0], record[1], record[2], record[3]]).into(),
+                    dst: record[4] != 0,
+                    abbrev: String::from_utf8_lossy(name).into_owned(),
+                }
+            })
+            .collect();
+        let transitions: Vec<(i64, usize)> = at
+            .chunks(time_len)
+            .zip(indices)
+            .map(|(at, &index)| {
+                let at = at.iter().fold(0u64, |n, &b| n << 8 | u64::from(b));
+                // sign-extend the 32-bit times of version 1
+                let at = if time_len == 4 { i64::from(at as u32 as i32) } else { at as i64 };
+                (at, usize::from(index))
+            })
+            .collect();
+        if types.is_empty() || transitions.iter().any(|&(_, index)| index >= types.len()) {
+            return None;
+        }
+        let footer = data.get(pos..).and_then(|footer| std::str::from_utf8(footer).ok());
+        let rule = footer
+            .filter(|_| version >= b'2')
+            .and_then(|footer| PosixTz::parse(footer.strip_prefix('\n')?.split('\n').next()?));
+        Some(Zone { transitions, types, rule })
+    }
+
+    /// the local time in effect at `t`, UTC seconds. there's only ever one:
+    /// in the hour repeated when the clocks go back the abbreviation tells
+    /// the two apart, and the hour skipped when they go forward never comes.
+    fn local_type(&self, t: i64) -> &LocalType {
+        let after = self.transitions.partition_point(|&(at, _)| at <= t);
+        match &self.rule {
+            Some(rule) if after == self.transitions.len() => rule.local_type(t),
+            _ if after == 0 => &self.types[0],
+            _ => &self.types[self.transitions[after - 1].1],
+        }
+    }
+
+    /// `line` with the first timestamp that says which zone it's in (a `Z`
+    /// or an offset) written in this one instead, its abbreviation after it.
+    fn convert(&self, line: &str) -> Option<String> {
+        let range = timestamp_range(line)?;
+        let ts = &line[range.clone()];
+        let b = ts.as_bytes();
+        parse_iso_seconds(ts)?;
+        let mut clock = 19;
+        if matches!(b.get(clock), Some(b'.' | b',')) {
+            clock += 1 + b[clock + 1..].iter().take_while(|c| c.is_ascii_digit()).count();
+        }
+        if clock == ts.len() {
+            return None;
+        }
+        let secs = parse_iso_seconds(&format!("{}{}", &ts[..19], &ts[clock..]))? as i64;
+        let local = self.local_type(secs);
+        let at = secs + local.offset;
+        let (year, month, day) = civil_from_days(at.div_euclid(86_400));
+        let tod = at.rem_euclid(86_400);
+        Some(format!(
+            "{}{:04}-{:02}-{:02}{}{:02}:{:02}:{:02}{} {}{}",
+            &line[..range.start],
+            year,
+            month,
+            day,
+            &ts[10..11],
+            tod / 3600,
+            tod / 60 % 60,
+            tod % 60,
+            &ts[19..clock],
+            local.abbrev,
+            &line[range.end..]
+        ))
+    }
+}
+
+/// how far into a line to look for its timestamp.
+const TIMESTAMP_SEARCH_BYTES: usize = 64;
+
+/// the first timestamp near the start of `line`, as seconds.
+fn line_timestamp(line: &str) -> Option<f64> {
+    let (_, rest) = parse_pri(line).map_or((0, line), |(len, _)| (len, &line[len..]));
+    if let Some(secs) = parse_syslog_seconds(rest) {
+        return Some(secs);
+    }
+    let b = line.as_bytes();
+    (0..b.len().min(TIMESTAMP_SEARCH_BYTES))
+        .filter(|&i| b[i].is_ascii_digit() && (i == 0 || !b[i - 1].is_ascii_alphanumeric()))
+        .find_map(|i| parse_iso_seconds(line.get(i..)?))
+}
+
+/// where `line_timestamp`'s timestamp is in the line.
+fn timestamp_range(line: &str) -> Option<std::ops::Range<usize>> {
+    let lead = parse_pri(line).map_or(0, |(len, _)| len);
+    let rest = &line[lead..];
+    if parse_syslog_seconds(rest).is_some() {
+        let day = rest[3..].trim_start();
+        let len = rest.len() - day.len() + day.find(' ')? + 9;
+        return Some(lead..lead + len);
+    }
+    let b = line.as_bytes();
+    let start = (0..b.len().min(TIMESTAMP_SEARCH_BYTES))
+        .filter(|&i| b[i].is_ascii_digit() && (i == 0 || !b[i - 1].is_ascii_alphanumeric()))
+        .find(|&i| line.get(i..).and_then(parse_iso_seconds).is_some())?;
+    let b = &b[start..];
+    let mut len = 19;
+    if matches!(b.get(len), Some(b'.' | b',')) {
+        len += 1 + b[len + 1..].iter().take_while(|c| c.is_ascii_digit()).count();
+    }
+    match b.get(len) {
+        Some(b'Z') => len += 1,
+        Some(b'+' | b'-') => {
+            len += 1 + b[len + 1..].iter().take_while(|c| c.is_ascii_digit() || **c == b':').count()
+        }
+        _ => {}
+    }
+    Some(start..start + len)
+}
+
+/// `48211` as `48,211`.
+#[doc(hidden)]
+pub fn with_commas(n: usize) -> String {
+    let digits = n.to_string();
+    let mut out = String::new();
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// a span of time in its two largest units, `4.2s`, `3m07s`, `2h15m`, `3d04h`.
+fn format_span(secs: f64) -> String {
+    let whole = secs as u64;
+    match whole {
+        0..=59 => format!("{:.1}s", secs),
+        60..=3599 => format!("{}m{:02}s", whole / 60, whole % 60),
+        3600..=86_399 => format!("{}h{:02}m", whole / 3600, whole / 60 % 60),
+        _ => format!("{}d{:02}h", whole / 86_400, whole / 3600 % 24),
+    }
+}
+
+/// the level a line is counted under, from a syslog PRI or a logfmt level
+/// key, else its level token or failing that an inferred phrase;
+/// Kubernetes prefixes aside.
+fn line_level(line: &str, infer: &[Inference]) -> Option<Level> {
+    if let Some((_, severity)) = parse_pri(line) {
+        return Some(Level::from_syslog_severity(severity));
+    }
+    if is_go_panic(line) {
+        // a crash in one service, not the `PANIC` of a database going down
+        return Some(Level::Error);
+    }
+    if let Some((_, level)) = logfmt_level(line, &logfmt_pairs(line), false) {
+        return Some(level);
+    }
+    let message = parse_k8s_line(line).map_or(line, |k8s| &line[k8s.message..]);
+    match find_level(message) {
+        Some((_, token)) => {
+            let raw = token.trim_matches(|c: char| "[]():- ".contains(c)).to_uppercase();
+            token_level(&raw)
+        }
+        None => infer_level(message, infer).map(|(_, level)| level),
+    }
+}
+
+/// `--since`: a point in time, or a length of time back from now.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Since {
+    At(f64),
+    Ago(Duration),
+}
+
+/// `10m`, `2h`, `1d` or `90s` back from now, or a timestamp like
+/// `2024-05-01T12:00:00` (UTC unless it says otherwise) or `2024-05-01`.
+#[doc(hidden)]
+pub fn parse_since(value: &str) -> Result<Since, String> {
+    let unit = value.char_indices().last().and_then(|(i, unit)| match unit {
+        's' => Some((i, 1.0)),
+        'm' => Some((i, 60.0)),
+        'h' => Some((i, 3600.0)),
+        'd' => Some((i, 86_400.0)),
+        _ => None,
+    });
+    let ago = unit.and_then(|(i, scale)| {
+        let n = value[..i].parse::<f64>().ok().filter(|n| *n >= 0.0)?;
+        Duration::try_from_secs_f64(n * scale).ok()
+    });
+    let date = format!("{}T00:00:00", value);
+    let at = parse_iso_seconds(value)
+        .or_else(|| (value.len() == 10).then(|| parse_iso_seconds(&date))?);
+    match (ago, at) {
+        (Some(ago), _) => Ok(Since::Ago(ago)),
+        (None, Some(at)) => Ok(Since::At(at)),
+        (None, None) => Err(format!(
+            "invalid value '{}' for '--since' (expected a time ago like 10m or 2h, or a \
+             timestamp like 2024-05-01T12:00:00)",
+            value
+        )),
+    }
+}
+
+/// the wall clock, as seconds since the epoch.
+fn wall_clock() -> f64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0.0, |d| d.as_secs_f64())
+}
+
+/// when the system came up, from `/proc/uptime`. after a suspend, kernel
+/// timestamps trail this by the time spent asleep, as with `dmesg -T`.
+fn boot_time() -> Option<f64> {
+    let uptime = std::fs::read_to_string("/proc/uptime").ok()?;
+    let secs: f64 = uptime.split_whitespace().next()?.parse().ok()?;
+    Some(wall_clock() - secs)
+}
+
+/// a kernel log's `[12345.678901]`, seconds since boot.
+fn kernel_timestamp(line: &str) -> Option<f64> {
+    let rest = line.strip_prefix('[')?.trim_start();
+    let stamp = &rest[..rest.find(']')?];
+    let (whole, fraction) = stamp.split_once('.')?;
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    (digits(whole) && digits(fraction)).then(|| stamp.parse().ok()).flatten()
+}
+
+/// `--since` and what it needs to place a line's timestamp on the clock.
+#[derive(Clone, Debug, PartialEq)]
+struct TimeWindow {
+    since: Since,
+    /// `--since-boot`: when the system booted, for kernel timestamps.
+    boot: Option<f64>,
+    /// reading a live stream, where a time ago is counted back from each
+    /// line's arrival; reading files it is counted back from `started`.
+    following: bool,
+    started: f64,
+}
+
+impl TimeWindow {
+    fn new(since: Since, boot: Option<f64>) -> TimeWindow {
+        TimeWindow { since, boot, following: false, started: wall_clock() }
+    }
+
+    /// the oldest timestamp kept at `now`.
+    fn cutoff(&self, now: f64) -> f64 {
+        match self.since {
+            Since::At(at) => at,
+            Since::Ago(ago) if self.following => now - ago.as_secs_f64(),
+            Since::Ago(ago) => self.started - ago.as_secs_f64(),
+        }
+    }
+
+    /// whether `line` is recent enough, or `None` when it has no timestamp
+    /// to tell by.
+    fn keeps(&self, line: &str, now: f64) -> Option<bool> {
+        let kernel = self.boot.and_then(|boot| Some(boot + kernel_timestamp(line)?));
+        let stamp = kernel.or_else(|| Some(in_recent_year(line_timestamp(line)?, now)))?;
+        Some(stamp >= self.cutoff(now))
+    }
+}
+
+/// a BSD syslog timestamp comes without a year and parses into 1970; move
+/// it to the latest year that doesn't put it in the future.
+fn in_recent_year(secs: f64, now: f64) -> f64 {
+    if secs >= 366.0 * 86_400.0 {
+        return secs;
+    }
+    let (year, _, _) = civil_from_days(now.div_euclid(86_400.0) as i64);
+    let (_, month, day) = civil_from_days(secs.div_euclid(86_400.0) as i64);
+    let placed = |year| (days_from_civil(year, month, day) * 86_400) as f64 + secs % 86_400.0;
+    match placed(year) {
+        // a day of slack for clocks and time zones
+        t if t > now + 86_400.0 => placed(year - 1),
+        t => t,
+    }
+}
+
+/// the codes `--level-column` reads when not given a map of its own.
+const LEVEL_COLUMN_CODES: [(&str, Level); 6] = [
+    ("E", Level::Error),
+    ("W", Level::Warn),
+    ("I", Level::Info),
+    ("D", Level::Debug),
+    ("F", Level::Fatal),
+    ("T", Level::Trace),
+];
+
+/// where `--level-column`, `--level-field` and `--level-pattern` look.
+#[doc(hidden)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LevelPlace {
+    /// `--level-column N[-M]`: the 0-based start and width of the field;
+    /// `--level-column-chars` counts characters, not bytes.
+    Columns { start: usize, width: usize, chars: bool },
+    /// `--level-field N`, 0-based here, between `--level-delim`s or else
+    /// runs of blanks.
+    Field { index: usize, delim: Option<String> },
+    /// `--level-pattern`: the template's text before and after `{level}`.
+    Pattern { before: String, after: String },
+}
+
+/// the level sits in the same place in every line, which then has the only
+/// say: columns, a delimited field, or a spot in a template.
+#[doc(hidden)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LevelColumn {
+    pub place: LevelPlace,
+    /// what each code found there means; with none given, the usual one
+    /// letter codes and the level names themselves.
+    pub map: Option<Vec<(String, Level)>>,
+}
+
+/// `E=ERROR,W=WARN,I=INFO`, as `--level-column` and `--level-map` take it.
+#[doc(hidden)]
+pub fn parse_level_map(map: &str) -> Option<Vec<(String, Level)>> {
+    map.split(',')
+        .map(|entry| {
+            let (code, name) = entry.split_once('=').filter(|(c, _)| !c.is_empty())?;
+            Some((code.to_string(), Level::from_token(&name.to_uppercase())?))
+        })
+        .collect()
+}
+
+impl LevelColumn {
+    pub fn parse(value: &str) -> Result<LevelColumn, String> {
+        let err = |why: &str| format!("invalid value '{}' for '--level-column' ({})", value, why);
+        let (columns, map) = match value.split_once(':') {
+            Some((columns, map)) => (columns, Some(map)),
+            None => (value, None),
+        };
+        let column = |n: &str| n.parse::<usize>().ok().filter(|&n| n > 0);
+        let (first, last) = match columns.split_once('-') {
+            Some((first, last)) => (column(first), column(last)),
+            None => (column(columns), column(columns)),
+        };
+        let (first, last) = first
+            .zip(last)
+            .filter(|(f, l)| f <= l)
+            .ok_or_else(|| err("expected a column like 57 or 57-61, counted from 1"))?;
+        let map = match map {
+            None => None,
+            Some(map) => Some(
+                parse_level_map(map)
+                    .ok_or_else(|| err("expected a map like E=ERROR,W=WARN,I=INFO"))?,
+            ),
+        };
+        let place = LevelPlace::Columns { start: first - 1, width: last - first + 1, chars: false };
+        Ok(LevelColumn { place, map })
+    }
+
+    /// `--level-pattern`: a template like `| {level} |`.
+    pub fn pattern(template: &str) -> Result<LevelColumn, String> {
+        let (before, after) = template
+            .split_once("{level}")
+            .filter(|&(before, after)| !after.contains("{level}") && before.len() + after.len() > 0)
+            .ok_or_else(|| {
+                format!(
+                    "invalid value '{}' for '--level-pattern' (expected a template like \
+                     '| {{level}} |', with {{level}} once and some text around it)",
+                    template
+                )
+            })?;
+        let place = LevelPlace::Pattern { before: before.to_string(), after: after.to_string() };
+        Ok(LevelColumn { place, map: None })
+    }
+
+    /// the columns' byte range in `line`, cut short if the line ends inside
+    /// them; `None` when the line is shorter than that.
+    fn columns(
+        line: &str,
+        start: usize,
+        width: usize,
+        chars: bool,
+    ) -> Option<std::ops::Range<usize>> {
+        if chars {
+            let mut bounds = line.char_indices().map(|(i, _)| i).chain(std::iter::once(line.len()));
+            let start = bounds.nth(start).filter(|&i| i < line.len())?;
+            let end = bounds.nth(width - 1).unwrap_or(line.len());
+            return Some(start..end);
+        }
+        let end = (start + width).min(line.len());
+        (start < line.len() && line.is_char_boundary(start) && line.is_char_boundary(end))
+            .then_some(start..end)
+    }
+
+    /// the level in `line`'s field, and where it is.
+    pub fn level(&self, line: &str) -> Option<(std::ops::Range<usize>, Level)> {
+        match &self.place {
+            &LevelPlace::Columns { start, width, chars } => {
+                let field = LevelColumn::columns(line, start, width, chars)?;
+                let level = self.code_level(line[field.clone()].trim())?;
+                Some((field, level))
+            }
+            LevelPlace::Field { index, delim } => {
+                let field = delimited_field(line, *index, delim.as_deref())?;
+                Some((field.clone(), self.code_level(&line[field])?))
+            }
+            // the first place the template fits with a level in it
+            LevelPlace::Pattern { before, after } => pattern_captures(line, before, after)
+                .into_iter()
+                .find_map(|range| Some((range.clone(), self.code_level(&line[range])?))),
+        }
+    }
+
+    fn code_level(&self, code: &str) -> Option<Level> {
+        match &self.map {
+            Some(map) => map.iter().find(|(c, _)| c == code).map(|&(_, level)| level),
+            None => LEVEL_COLUMN_CODES
+                .iter()
+                .find(|(c, _)| *c == code)
+                .map(|&(_, level)| level)
+                .or_else(|| Level::from_token(&code.to_uppercase())),
+        }
+    }
+}
+
+/// field `index` of `line`, split at `delim` or else at runs of blanks,
+/// without the blanks around it; `None` for a line with fewer fields, or
+/// an empty one there.
+fn delimited_field(
+    line: &str,
+    index: usize,
+    delim: Option<&str>,
+) -> Option<std::ops::Range<usize>> {
+    let field = match delim {
+        Some(delim) => line.split(delim).nth(index)?,
+        None => line.split_whitespace().nth(index)?,
+    };
+    // a piece of `line`, so its offset is where it starts
+    let start = field.as_ptr() as usize - line.as_ptr() as usize;
+    let code = field.trim();
+    let start = start + field.len() - field.trim_start().len();
+    (!code.is_empty()).then_some(start..start + code.len())
+}
+
+/// where `{level}` of a `--level-pattern` could be in `line`: after each
+/// `before` (each word, for a template starting with `{level}`) up to the
+/// next `after` (the end of the word, for one ending with it), trimmed.
+fn pattern_captures(line: &str, before: &str, after: &str) -> Vec<std::ops::Range<usize>> {
+    let starts: Vec<usize> = match before.is_empty() {
+        true => std::iter::once(0)
+            .chain(line.match_indices(char::is_whitespace).map(|(i, c)| i + c.len()))
+            .collect(),
+        false => line.match_indices(before).map(|(i, _)| i + before.len()).collect(),
+    };
+    starts
+        .into_iter()
+        .filter_map(|start| {
+            let rest = &line[start..];
+            let len = match after.is_empty() {
+                true => rest.find(char::is_whitespace).unwrap_or(rest.len()),
+                false => rest.find(after)?,
+            };
+            let code = rest[..len].trim();
+            let start = start + rest.len() - rest.trim_start().len();
+            (!code.is_empty()).then_some(start..start + code.len())
+        })
+        .collect()
+}
+
+/// which levels get printed at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct LevelFilter {
+    /// `--max-level`: lines above it are dropped.
+    max: Option<Level>,
+    /// `--min-level`: lines below it are dropped.
+    min: Option<Level>,
+    /// `--drop-unknown`: lines without a level are dropped too.
+    drop_unknown: bool,
+}
+
+impl LevelFilter {
+    /// lines without a level are kept unless `drop_unknown`; they are often
+    /// a kept line's continuation.
+    fn keeps(&self, level: Option<Level>) -> bool {
+        match level {
+            Some(level) => {
+                self.max.is_none_or(|max| level <= max) && self.min.is_none_or(|min| level >= min)
+            }
+            None => !self.drop_unknown,
+        }
+    }
+
+    /// which of the filters left out a line with `level`, for `--audit`.
+    fn dropped_by(&self, level: Option<Level>) -> Suppressor {
+        match level.zip(self.max) {
+            Some((level, max)) if level > max => Suppressor::MaxLevel,
+            _ => Suppressor::MinLevel,
+        }
+    }
+}
+
+/// `--module`, `--module-exclude` and `--file`: which source locations are
+/// printed. a pattern is a glob matched against the whole name or any tail
+/// of it after a `::`, `.` or `/`, so `net::*` takes in `my_crate::net::conn`.
+#[doc(hidden)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceFilter {
+    pub modules: Vec<String>,
+    pub module_excludes: Vec<String>,
+    pub files: Vec<String>,
+    /// `--module-strict`: drop lines the filter can't say anything about.
+    pub strict: bool,
+}
+
+impl SourceFilter {
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty() && self.module_excludes.is_empty() && self.files.is_empty()
+    }
+
+    /// lines without a location, or with one of a kind nobody asked about,
+    /// are kept unless strict.
+    fn keeps(&self, line: &str, location: Option<&SourceLocation>) -> bool {
+        let matches = |patterns: &[String], name: &str| {
+            let tails = name.match_indices([':', '.', '/']).map(|(i, _)| &name[i + 1..]);
+            let mut names = std::iter::once(name).chain(tails);
+            names.any(|name| patterns.iter().any(|p| glob_match(p, name)))
+        };
+        match location {
+            _ if self.is_empty() => true,
+            None => !self.strict,
+            Some(location) if location.file && self.files.is_empty() => !self.strict,
+            Some(location) if location.file => matches(&self.files, location.name(line)),
+            Some(_) if self.modules.is_empty() && self.module_excludes.is_empty() => !self.strict,
+            Some(location) => {
+                let name = location.name(line);
+                (self.modules.is_empty() || matches(&self.modules, name))
+                    && !matches(&self.module_excludes, name)
+            }
+        }
+    }
+}
+
+/// `--elide-below`: drops the lines under a level, keeping count of each run
+/// of them for the placeholder shown in their stead.
+struct Elision {
+    threshold: Level,
+    /// the line before was dropped, so lines without a level are too.
+    dropping: bool,
+    gap: Summary,
+}
+
+impl Elision {
+    fn new(threshold: Level) -> Elision {
+        Elision { threshold, dropping: false, gap: Summary::default() }
+    }
+
+    /// whether to drop a line with `level`; lines without one, like stack
+    /// frames, go the way of the line before them.
+    fn drops(&mut self, level: Option<Level>) -> bool {
+        if let Some(level) = level {
+            self.dropping = level < self.threshold;
+        }
+        if self.dropping {
+            self.gap.lines += 1;
+            if let Some(level) = level {
+                self.gap.levels[level as usize] += 1;
+            }
+        }
+        self.dropping
+    }
+
+    /// `  ⋯ 37 lines elided (35 DEBUG, 2 TRACE) ⋯` for the lines dropped
+    /// since the last one shown, if there were any; the ones without a level
+    /// count as `other`, so the parts add up to the whole.
+    fn take_gap(&mut self) -> Option<String> {
+        let gap = std::mem::take(&mut self.gap);
+        if gap.lines == 0 {
+            return None;
+        }
+        let mut counts: Vec<String> = Level::ALL
+            .iter()
+            .rev()
+            .filter(|&&level| gap.levels[level as usize] > 0)
+            .map(|&level| format!("{} {}", with_commas(gap.levels[level as usize]), level.name()))
+            .collect();
+        let other = gap.lines - gap.levels.iter().sum::<usize>();
+        if other > 0 {
+            counts.push(format!("{} other", with_commas(other)));
+        }
+        let lines = match gap.lines {
+            1 => "1 line".to_string(),
+            n => format!("{} lines", with_commas(n)),
+        };
+        Some(format!("  \u{22ef} {} elided ({}) \u{22ef}", lines, counts.join(", ")))
+    }
+}
+
+/// `--throttle`: how many lines of each level a second may print.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Throttle {
+    lines: usize,
+    /// for ERROR and FATAL, so the lines that matter still get through.
+    errors: usize,
+}
+
+/// by default errors may print this many times the `--throttle` cap.
+const THROTTLE_ERROR_FACTOR: usize = 4;
+
+/// `--throttle`'s count of the current one-second window.
+struct ThrottleWindow {
+    caps: Throttle,
+    start: Option<Instant>,
+    /// lines printed so far in the window, by level, lines without one last.
+    printed: [usize; Level::ALL.len() + 1],
+    suppressed: usize,
+    /// the class of the last line with a level, which the lines after it
+    /// without one count against.
+    class: usize,
+    /// the last line was printed.
+    showing: bool,
+}
+
+impl ThrottleWindow {
+    fn new(caps: Throttle) -> ThrottleWindow {
+        ThrottleWindow {
+            caps,
+            start: None,
+            printed: [0; Level::ALL.len() + 1],
+            suppressed: 0,
+            class: Level::ALL.len(),
+            showing: true,
+        }
+    }
+
+    /// whether a line with `level` arriving `now` may be printed, and the
+    /// notice for the window before if this one starts a new window.
+    fn admit(&mut self, level: Option<Level>, now: Instant) -> (Option<String>, bool) {
+        let mut notice = None;
+        if self.start.is_none_or(|start| now.duration_since(start) >= Duration::from_secs(1)) {
+            notice = self.take_notice();
+            self.start = Some(now);
+            self.printed = [0; Level::ALL.len() + 1];
+        }
+        if let Some(level) = level {
+            self.class = level as usize;
+        }
+        let cap = match self.class {
+            class if class == Level::Error as usize || class == Level::Fatal as usize => {
+                self.caps.errors
+            }
+            _ => self.caps.lines,
+        };
+        self.showing = self.printed[self.class] < cap;
+        match self.showing {
+            true => self.printed[self.class] += 1,
+            false => self.suppressed += 1,
+        }
+        (notice, self.showing)
+    }
+
+    /// `  ⋯ suppressed 8,241 lines in the last second ⋯`, if any were.
+    fn take_notice(&mut self) -> Option<String> {
+        let lines = match std::mem::take(&mut self.suppressed) {
+            0 => return None,
+            1 => "1 line".to_string(),
+            n => format!("{} lines", with_commas(n)),
+        };
+        Some(format!("  \u{22ef} suppressed {} in the last second \u{22ef}", lines))
+    }
+}
+
+/// `--budget`: at most so many lines of each level, e.g. 500 INFO. the lines
+/// counted are those left after `--since`, `--max-level`, `--module`,
+/// `--elide-below`, `--dedup-window` and `--throttle`; `--grep` and its
+/// context only look at what the budget keeps.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Budget {
+    pub limits: [Option<usize>; Level::ALL.len()],
+    /// the input is a live stream, which can't be held back to its end, so
+    /// each level gets its first lines and then a notice instead of the rest.
+    streaming: bool,
+}
+
+/// a line `--budget` holds back until the end of the input: an input line
+/// with the offset its record started at, the rest of an over-long one, or
+/// a notice of our own.
+#[derive(Debug, PartialEq, Eq)]
+enum HeldLine {
+    Line(String, u64),
+    Cut(String),
+    Notice(String),
+}
+
+/// a record as held: a line with a level and those after it without one,
+/// the notice standing in for the first of a level's lines left out, or
+/// nothing once it has been.
+#[derive(Debug)]
+enum Held {
+    Record(Vec<HeldLine>),
+    Gap(Level),
+    Dropped,
+}
+
+/// one input's `--budget`. a file is held back to its end, so each level
+/// keeps its first and its last lines, the ones nearest the failure, in
+/// their places; only the lines of levels without a budget and those that
+/// may yet be among the last are kept in memory.
+struct BudgetWindow {
+    budget: Budget,
+    held: Vec<Held>,
+    /// the record that lines without a level go with.
+    last: Option<usize>,
+    /// records with each level so far.
+    seen: [usize; Level::ALL.len()],
+    /// for each level, the held records past its first ones, oldest first.
+    tails: [std::collections::VecDeque<usize>; Level::ALL.len()],
+    /// streaming: the last record was shown.
+    showing: bool,
+}
+
+impl BudgetWindow {
+    fn new(budget: Budget) -> BudgetWindow {
+        BudgetWindow {
+            budget,
+            held: Vec::new(),
+            last: None,
+            seen: [0; Level::ALL.len()],
+            tails: Default::default(),
+            showing: true,
+        }
+    }
+
+    fn holding(&self) -> bool {
+        !self.budget.streaming
+    }
+
+    /// hold `line`, which starts a record if it has a level; the oldest of a
+    /// level's last lines is dropped when one more comes.
+    fn hold(&mut self, level: Option<Level>, line: HeldLine) {
+        if let HeldLine::Notice(_) = line {
+            self.held.push(Held::Record(vec![line]));
+            return;
+        }
+        if let (None, Some(last)) = (level, self.last) {
+            // goes the way of its record
+            if let Held::Record(lines) = &mut self.held[last] {
+                lines.push(line);
+            }
+            return;
+        }
+        self.last = Some(self.held.len());
+        self.held.push(Held::Record(vec![line]));
+        let Some((level, limit)) = level.and_then(|l| Some((l, self.budget.limits[l as usize]?)))
+        else {
+            return;
+        };
+        let class = level as usize;
+        self.seen[class] += 1;
+        if self.seen[class] <= limit - limit / 2 {
+            return;
+        }
+        self.tails[class].push_back(self.held.len() - 1);
+        if self.tails[class].len() > limit / 2 {
+            let dropped = self.tails[class].pop_front().unwrap_or_default();
+            let first = self.seen[class] == limit + 1;
+            self.held[dropped] = if first { Held::Gap(level) } else { Held::Dropped };
+        }
+    }
+
+    /// the held lines in order, with a notice where each level's lines
+    /// were left out.
+    fn release(&mut self) -> Vec<HeldLine> {
+        let mut lines = Vec::new();
+        for held in std::mem::take(&mut self.held) {
+            match held {
+                Held::Record(record) => lines.extend(record),
+                Held::Gap(level) => {
+                    let limit = self.budget.limits[level as usize].unwrap_or_default();
+                    let left_out = self.seen[level as usize] - limit;
+                    lines.push(HeldLine::Notice(format!(
+                        "  \u{22ef} {} {} left out by --budget, {} kept \u{22ef}",
+                        with_commas(left_out),
+                        match left_out {
+                            1 => format!("{} line", level.name()),
+                            _ => format!("{} lines", level.name()),
+                        },
+                        with_commas(limit)
+                    )));
+                }
+                Held::Dropped => {}
+            }
+        }
+        self.last = None;
+        lines
+    }
+
+    /// streaming: whether a line with `level` may be printed, and the notice
+    /// for a level whose budget it has just used up.
+    fn admit(&mut self, level: Option<Level>) -> (Option<String>, bool) {
+        let Some(level) = level else {
+            return (None, self.showing);
+        };
+        let class = level as usize;
+        self.seen[class] += 1;
+        let limit = self.budget.limits[class];
+        self.showing = limit.is_none_or(|limit| self.seen[class] <= limit);
+        let notice = limit.filter(|&limit| self.seen[class] == limit + 1).map(|limit| {
+            format!(
+                "  \u{22ef} --budget of {} {} lines used up, the rest are left out \u{22ef}",
+                with_commas(limit),
+                level.name()
+            )
+        });
+        (notice, self.showing)
+    }
+
+    /// held: the records left out so far, for `--audit`.
+    fn left_out(&self) -> usize {
+        let limits = self.budget.limits.iter().zip(&self.seen);
+        limits.filter_map(|(limit, &seen)| seen.checked_sub((*limit)?)).sum()
+    }
+
+    /// streaming: `  ⋯ 1,234 lines left out by --budget (1,200 INFO, 34 WARN) ⋯`.
+    fn take_notice(&mut self) -> Option<String> {
+        let counts: Vec<(Level, usize)> = Level::ALL
+            .iter()
+            .rev()
+            .filter_map(|&level| {
+                let limit = self.budget.limits[level as usize]?;
+                let seen = std::mem::take(&mut self.seen[level as usize]);
+                Some((level, seen.checked_sub(limit).filter(|&n| n > 0)?))
+            })
+            .collect();
+        let lines = match counts.iter().map(|&(_, n)| n).sum() {
+            0 => return None,
+            1 => "1 line".to_string(),
+            n => format!("{} lines", with_commas(n)),
+        };
+        let counts: Vec<String> = counts
+            .iter()
+            .map(|&(level, n)| format!("{} {}", with_commas(n), level.name()))
+            .collect();
+        Some(format!("  \u{22ef} {} left out by --budget ({}) \u{22ef}", lines, counts.join(", ")))
+    }
+}
+
+/// a notice of ours: written, or held with the lines `--budget` holds back.
+fn put_notice(
+    out: &mut dyn Write,
+    notice: Option<String>,
+    budget: &mut Option<BudgetWindow>,
+    styles: &Styles,
+) -> io::Result<()> {
+    match (notice, budget) {
+        (Some(notice), Some(window)) if window.holding() => {
+            window.hold(None, HeldLine::Notice(notice));
+            Ok(())
+        }
+        (notice, _) => write_notice(out, notice, styles),
+    }
+}
+
+/// `--dedup-window`: how many distinct recent messages of each level to
+/// hold back repeats of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct DedupWindow {
+    size: usize,
+    /// `--dedup-errors`: ERROR and FATAL lines too, which otherwise always show.
+    errors: bool,
+}
+
+/// a repeat-heavy stretch gets its roll-up at least this often, even if
+/// nothing else is printed.
+const DEDUP_ROLLUP_INTERVAL: Duration = Duration::from_secs(5);
+
+/// what a message is compared by: numbers, hex ids and with them any
+/// timestamp folded to `#`.
+fn normalize_message(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if !c.is_ascii_digit() {
+            out.push(c);
+            continue;
+        }
+        while chars.peek().is_some_and(|c| c.is_ascii_hexdigit() || *c == 'x') {
+            chars.next();
+        }
+        out.push('#');
+    }
+    out
+}
+
+/// the last few distinct messages of each level, most recent first, and
+/// how many repeats of them were held back since the last roll-up.
+struct Dedup {
+    config: DedupWindow,
+    recent: Vec<std::collections::VecDeque<String>>,
+    /// as for `--throttle`, lines without a level count with the one before.
+    class: usize,
+    showing: bool,
+    repeats: usize,
+    /// the messages repeated since the last roll-up; no more than the
+    /// windows hold, so memory stays flat.
+    repeated: HashSet<(usize, String)>,
+    since: Option<Instant>,
+}
+
+impl Dedup {
+    fn new(config: DedupWindow) -> Dedup {
+        Dedup {
+            config,
+            recent: vec![std::collections::VecDeque::new(); Level::ALL.len() + 1],
+            class: Level::ALL.len(),
+            showing: true,
+            repeats: 0,
+            repeated: HashSet::new(),
+            since: None,
+        }
+    }
+
+    /// whether `line` may be printed, and a roll-up to print before it (or
+    /// in its place, when it's a repeat and the last roll-up was a while ago).
+    fn admit(&mut self, line: &str, level: Option<Level>, now: Instant) -> (Option<String>, bool) {
+        if let Some(level) = level {
+            self.class = level as usize;
+        }
+        let error = self.class == Level::Error as usize || self.class == Level::Fatal as usize;
+        if error && !self.config.errors {
+            self.showing = true;
+            return (self.take_rollup(), true);
+        }
+        let message = normalize_message(line);
+        let recent = &mut self.recent[self.class];
+        match recent.iter().position(|m| *m == message) {
+            Some(i) => {
+                let seen = recent.remove(i).unwrap_or_default();
+                recent.push_front(seen);
+                self.repeats += 1;
+                if self.repeated.len() < self.config.size * self.recent.len() {
+                    self.repeated.insert((self.class, message));
+                }
+                self.showing = false;
+                let since = *self.since.get_or_insert(now);
+                let due = now.duration_since(since) >= DEDUP_ROLLUP_INTERVAL;
+                (due.then(|| self.take_rollup()).flatten(), false)
+            }
+            None => {
+                recent.push_front(message);
+                recent.truncate(self.config.size);
+                self.showing = true;
+                (self.take_rollup(), true)
+            }
+        }
+    }
+
+    /// `  ⋯ 3 recent messages repeated 412 times ⋯`, if any were.
+    fn take_rollup(&mut self) -> Option<String> {
+        self.since = None;
+        let repeats = std::mem::take(&mut self.repeats);
+        let messages = std::mem::take(&mut self.repeated).len();
+        let plural = |n: usize, word: &str| match n {
+            1 => format!("1 {}", word),
+            n => format!("{} {}s", with_commas(n), word),
+        };
+        (repeats > 0).then(|| {
+            format!(
+                "  \u{22ef} {} repeated {} \u{22ef}",
+                plural(messages, "recent message"),
+                plural(repeats, "time")
+            )
+        })
+    }
+}
+
+/// `--squeeze`: a repeat may be of any of the last `size` distinct lines;
+/// plain `--squeeze` is 1, the line just before.
+struct SqueezeWindow {
+    size: usize,
+    /// on a live stream, the one `Squeeze` of the session, which the thread
+    /// `start_squeeze_ticks` starts writes out as well.
+    live: Option<std::sync::Arc<std::sync::Mutex<Squeeze>>>,
+    /// `--follow` or `--watch-glob`: an input is read in batches, and a run
+    /// goes on from one batch to the next.
+    following: bool,
+}
+
+/// a run of repeats on a live stream is written out once the stream has
+/// been quiet this long.
+const SQUEEZE_TICK: Duration = Duration::from_secs(1);
+
+/// what `--squeeze` compares lines by: the line less a leading timestamp.
+fn squeeze_key(line: &str) -> &str {
+    let separator = |c: char| " []|:-<>".contains(c);
+    match timestamp_range(line).filter(|ts| line[..ts.start].trim_matches(separator).is_empty()) {
+        Some(ts) => line[ts.end..].trim_start_matches(separator),
+        None => line,
+    }
+}
+
+/// the last few distinct lines, most recent first, and the run of repeats
+/// of them held back since the last line shown.
+struct Squeeze {
+    size: usize,
+    recent: std::collections::VecDeque<String>,
+    showing: bool,
+    repeats: usize,
+    /// the lines the run repeats; no more than `size` of them.
+    repeated: HashSet<String>,
+    /// when the run's latest repeat came.
+    last: Option<Instant>,
+}
+
+impl Squeeze {
+    fn new(size: usize) -> Squeeze {
+        Squeeze {
+            size,
+            recent: std::collections::VecDeque::new(),
+            showing: true,
+            repeats: 0,
+            repeated: HashSet::new(),
+            last: None,
+        }
+    }
+
+    /// whether `line` may be printed, and the count of the run it ends to
+    /// print before it.
+    fn admit(&mut self, line: &str, now: Instant) -> (Option<String>, bool) {
+        let key = squeeze_key(line);
+        match self.recent.iter().position(|seen| seen == key) {
+            Some(i) => {
+                let seen = self.recent.remove(i).unwrap_or_default();
+                self.repeated.insert(seen.clone());
+                self.recent.push_front(seen);
+                self.repeats += 1;
+                self.showing = false;
+                self.last = Some(now);
+                (None, false)
+            }
+            None => {
+                self.recent.push_front(key.to_string());
+                self.recent.truncate(self.size);
+                self.showing = true;
+                (self.take_notice(), true)
+            }
+        }
+    }
+
+    /// `  ⋯ last line repeated 14,302 times ⋯`, if a run was held back.
+    fn take_notice(&mut self) -> Option<String> {
+        self.last = None;
+        let repeats = std::mem::take(&mut self.repeats);
+        let lines = match std::mem::take(&mut self.repeated).len() {
+            1 => "last line".to_string(),
+            n => format!("last {} lines", n),
+        };
+        let times = match repeats {
+            1 => "1 time".to_string(),
+            n => format!("{} times", with_commas(n)),
+        };
+        (repeats > 0).then(|| format!("  \u{22ef} {} repeated {} \u{22ef}", lines, times))
+    }
+
+    /// the count of a run the stream has gone quiet after.
+    fn take_stale(&mut self, now: Instant) -> Option<String> {
+        let stale = self.last.is_some_and(|last| now.duration_since(last) >= SQUEEZE_TICK);
+        stale.then(|| self.take_notice()).flatten()
+    }
+}
+
+/// `--squeeze` on a live stream: a run's count is written once the stream
+/// goes quiet, not only when a different line comes, which may be hours.
+fn start_squeeze_ticks(
+    squeeze: std::sync::Arc<std::sync::Mutex<Squeeze>>,
+    styles: Styles,
+    mut out: Output,
+) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(SQUEEZE_TICK / 4);
+            // held while writing, so the reader's next line comes after
+            let mut squeeze = squeeze.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(notice) = squeeze.take_stale(Instant::now()) {
+                let _ = write_notice(&mut out, Some(notice), &styles).and_then(|_| out.flush());
+            }
+        }
+    });
+}
+
+/// the count of a run `--squeeze` still holds back as a followed input
+/// ends, however it ends.
+fn flush_squeeze(hl: &Highlighter, out: &mut dyn Write) -> io::Result<()> {
+    let live = hl.squeeze.as_ref().and_then(|window| window.live.as_ref());
+    let notice = live.and_then(|live| live.lock().unwrap_or_else(|e| e.into_inner()).take_notice());
+    write_notice(out, notice, &hl.loaded().styles)
+}
+
+/// how many distinct messages `--highlight-new` remembers before it starts
+/// forgetting the oldest.
+const NOVELTY_LIMIT: usize = 100_000;
+
+/// `--highlight-new`: the messages seen so far, as hashes of their
+/// `normalize_message`, oldest first so the set stays bounded.
+struct Novelty {
+    seen: HashSet<u64>,
+    order: std::collections::VecDeque<u64>,
+    limit: usize,
+    /// forgetting is said once, the first time.
+    warned: bool,
+}
+
+impl Novelty {
+    fn new(limit: usize) -> Novelty {
+        Novelty {
+            seen: HashSet::new(),
+            order: std::collections::VecDeque::new(),
+            limit,
+            warned: false,
+        }
+    }
+
+    /// whether `line` is the first of its message, which is remembered
+    /// from now on. blank lines are never new.
+    fn first_seen(&mut self, line: &str) -> bool {
+        let message = normalize_message(line);
+        if message.is_empty() || self.seen.contains(&fnv1a(&message)) {
+            return false;
+        }
+        if self.order.len() >= self.limit {
+            if !self.warned {
+                eprintln!(
+                    "--highlight-new: over {} distinct messages, forgetting the oldest",
+                    with_commas(self.limit)
+                );
+                self.warned = true;
+            }
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        self.seen.insert(fnv1a(&message));
+        self.order.push_back(fnv1a(&message));
+        true
+    }
+}
+
+/// `--alert-rate`: so many ERROR and FATAL lines within a window of time
+/// set off an alert.
+#[doc(hidden)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AlertRate {
+    pub errors: usize,
+    window: Duration,
+    /// as given, like `50/10s`, for the banner.
+    text: String,
+}
+
+impl AlertRate {
+    /// a count, a slash and a time, `50/10s`.
+    pub fn parse(value: &str) -> Result<AlertRate, String> {
+        let invalid = || {
+            format!(
+                "invalid value '{}' for '--alert-rate' (expected errors per time, like 50/10s)",
+                value
+            )
+        };
+        let (count, window) = value.split_once('/').ok_or_else(invalid)?;
+        let errors = count.parse().ok().filter(|&n: &usize| n > 0).ok_or_else(invalid)?;
+        let window = parse_interval("alert-rate", window).map_err(|_| invalid())?;
+        Ok(AlertRate { errors, window, text: value.to_string() })
+    }
+}
+
+/// what a line did to a `RateAlarm`, with the errors in the window after it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AlarmChange {
+    Raised(usize),
+    Cleared(usize),
+}
+
+/// `--alert-rate`'s sliding window of error times. once raised the alert
+/// stays up until the rate falls under half the threshold, so a rate
+/// hovering around it doesn't flap.
+struct RateAlarm {
+    rate: AlertRate,
+    /// when the errors still in the window arrived, oldest first. at most
+    /// `rate.errors` are kept: the ones dropped are older than the rest, so
+    /// the count is exact whenever it's under the threshold.
+    times: std::collections::VecDeque<Instant>,
+    alerting: bool,
+}
+
+impl RateAlarm {
+    fn new(rate: AlertRate) -> RateAlarm {
+        RateAlarm { rate, times: std::collections::VecDeque::new(), alerting: false }
+    }
+
+    /// count a line arriving `now`. lines that aren't errors count too, as
+    /// the time passing that can clear the alert.
+    fn observe(&mut self, error: bool, now: Instant) -> Option<AlarmChange> {
+        while self.times.front().is_some_and(|&t| now.duration_since(t) >= self.rate.window) {
+            self.times.pop_front();
+        }
+        if error {
+            if self.times.len() == self.rate.errors {
+                self.times.pop_front();
+            }
+            self.times.push_back(now);
+        }
+        let count = self.times.len();
+        match self.alerting {
+            false if count >= self.rate.errors => {
+                self.alerting = true;
+                Some(AlarmChange::Raised(count))
+            }
+            true if count * 2 < self.rate.errors => {
+                self.alerting = false;
+                Some(AlarmChange::Cleared(count))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// `--alert-rate` and what to do when it goes off, shared by all inputs of
+/// the session.
+struct Alert {
+    alarm: std::sync::Mutex<RateAlarm>,
+    /// `--alert-bell`
+    bell: bool,
+    /// `--exec-on-alert`: run by `sh -c` each time the alert is raised.
+    exec: Option<String>,
+}
+
+impl Alert {
+    /// count a line with `level` arriving `now`, running `--exec-on-alert`
+    /// if that raised the alert.
+    fn observe(&self, level: Option<Level>, now: Instant) -> Option<AlarmChange> {
+        let mut alarm = self.alarm.lock().unwrap_or_else(|e| e.into_inner());
+        let change = alarm.observe(level >= Some(Level::Error), now);
+        if let (Some(AlarmChange::Raised(count)), Some(command)) = (change, &self.exec) {
+            let spawned = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(command)
+                .env("LOGCOLOR_ALERT_COUNT", count.to_string())
+                .env("LOGCOLOR_ALERT_RATE", &alarm.rate.text)
+                .stdin(std::process::Stdio::null())
+                .spawn();
+            match spawned {
+                // reaped off to the side so the lines keep coming
+                Ok(mut child) => drop(std::thread::spawn(move || child.wait())),
+                Err(e) => eprintln!("failed to run --exec-on-alert: {}", e),
+            }
+        }
+        change
+    }
+
+    fn alerting(&self) -> bool {
+        self.alarm.lock().unwrap_or_else(|e| e.into_inner()).alerting
+    }
+
+    /// the red line across the terminal an alert starts with, or the calm
+    /// notice it ends with.
+    fn write_change(
+        &self,
+        out: &mut dyn Write,
+        change: AlarmChange,
+        width: usize,
+        styles: &Styles,
+    ) -> io::Result<()> {
+        let rate = self.alarm.lock().unwrap_or_else(|e| e.into_inner()).rate.clone();
+        let window = rate.text.split_once('/').map_or("", |(_, window)| window);
+        let errors = |n: usize| match n {
+            1 => "1 error".to_string(),
+            n => format!("{} errors", with_commas(n)),
+        };
+        match change {
+            AlarmChange::Raised(count) => {
+                if self.bell {
+                    write!(out, "\x07")?;
+                }
+                let text = format!(
+                    " \u{25b2} ALERT  {} in the last {} (--alert-rate {})",
+                    errors(count),
+                    window,
+                    rate.text
+                );
+                let pad = width.saturating_sub(display_width(&text));
+                match styles.enabled() {
+                    true => {
+                        let sgr = format!("{}{}{}", BOLD, REVERSE, styles.color(Level::Error));
+                        styles.paint(out, &sgr, &format!("{}{}", text, " ".repeat(pad)))?;
+                    }
+                    false => write!(out, "{}", text)?,
+                }
+                writeln!(out)
+            }
+            AlarmChange::Cleared(count) => {
+                let notice = format!(
+                    "  \u{22ef} alert over: {} in the last {}, under half of {} \u{22ef}",
+                    errors(count),
+                    window,
+                    rate.text
+                );
+                write_notice(out, Some(notice), styles)
+            }
+        }
+    }
+}
+
+/// `--compare-threshold` unless given: a signature needs twice (or half)
+/// the lines it had before to count as changed.
+const DEFAULT_COMPARE_THRESHOLD: f64 = 2.0;
+
+/// one of the error signatures `--compare` counts: how many lines had it
+/// and the first of them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Signature {
+    count: usize,
+    example: String,
+}
+
+/// the ERROR and FATAL lines of `input` by their `normalize_message`.
+fn error_signatures(
+    input: impl BufRead,
+    infer: &[Inference],
+) -> io::Result<std::collections::HashMap<String, Signature>> {
+    let mut signatures = std::collections::HashMap::new();
+    for line in input.split(b'\n') {
+        let line = line?;
+        let line = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(&line));
+        let line = visible_text(&line);
+        if line_level(&line, infer).is_none_or(|level| level < Level::Error) {
+            continue;
+        }
+        let signature = signatures
+            .entry(normalize_message(&line))
+            .or_insert_with(|| Signature { count: 0, example: line.clone() });
+        signature.count += 1;
+    }
+    Ok(signatures)
+}
+
+/// `--compare`: the signatures only `new` has, those only `old` had, and
+/// those whose count went up or down by at least `threshold` times, each
+/// with a line it came up in.
+fn write_comparison(
+    out: &mut dyn Write,
+    (old_name, old): (&str, &std::collections::HashMap<String, Signature>),
+    (new_name, new): (&str, &std::collections::HashMap<String, Signature>),
+    threshold: f64,
+    hl: &Highlighter,
+) -> io::Result<()> {
+    let styles = &hl.loaded().styles;
+    let added = new.iter().filter(|(key, _)| !old.contains_key(*key));
+    let added: Vec<_> = added.map(|(_, sig)| (0, sig.count, sig)).collect();
+    let resolved = old.iter().filter(|(key, _)| !new.contains_key(*key));
+    let resolved: Vec<_> = resolved.map(|(_, sig)| (sig.count, 0, sig)).collect();
+    let changed = new.iter().filter_map(|(key, sig)| {
+        let before = old.get(key)?.count;
+        let ratio = sig.count.max(before) as f64 / sig.count.min(before) as f64;
+        (ratio >= threshold).then_some((before, sig.count, sig))
+    });
+    let changed: Vec<_> = changed.collect();
+    let sections = [
+        (format!("new in {}", new_name), styles.color(Level::Error).to_string(), added),
+        (format!("resolved since {}", old_name), GREEN.fg_sgr(styles.depth), resolved),
+        (
+            format!("changed by {}x or more", threshold),
+            styles.color(Level::Warn).to_string(),
+            changed,
+        ),
+    ];
+    for (title, sgr, mut found) in sections {
+        // the most lines first, whether before or after
+        found.sort_by(|a, b| b.0.max(b.1).cmp(&a.0.max(a.1)).then(a.2.example.cmp(&b.2.example)));
+        let title = format!("──── {} ({}) ────", title, with_commas(found.len()));
+        styles.paint(out, &format!("{}{}", BOLD, sgr), &title)?;
+        writeln!(out)?;
+        for (before, after, sig) in found {
+            match (before, after) {
+                (0, n) | (n, 0) => write!(out, "{:>9}× ", with_commas(n))?,
+                (before, after) => write!(out, "{:>9} ", format!("{}→{}", before, after))?,
+            }
+            print_colored_line(out, &sig.example, hl)?;
+        }
+    }
+    Ok(())
+}
+
+/// `Some(true)` when `line` is a first occurrence for `--highlight-new`.
+fn judge_novelty(line: &str, hl: &Highlighter) -> Option<bool> {
+    let novelty = hl.novelty.as_ref()?;
+    Some(novelty.lock().unwrap_or_else(|e| e.into_inner()).first_seen(line))
+}
+
+/// a rendered line behind its `--highlight-new` gutter: `NEW` and bold for
+/// the first of a message, dimmed for a repeat, blank for our own notices.
+fn novelty_gutter(line: &str, novel: Option<bool>, styles: &Styles) -> String {
+    match novel {
+        Some(true) if styles.enabled() => {
+            format!("{}NEW{} {}", BOLD, RESET, restyle(line, Vec::new(), BOLD))
+        }
+        Some(true) => format!("NEW {}", line),
+        Some(false) if styles.enabled() => {
+            format!("    {}", restyle(line, Vec::new(), &styles.dim))
+        }
+        _ => format!("    {}", line),
+    }
+}
+
+/// a dimmed line of our own, like an elision placeholder, if there is one.
+fn write_notice(out: &mut dyn Write, notice: Option<String>, styles: &Styles) -> io::Result<()> {
+    match notice {
+        Some(notice) => {
+            styles.paint(out, &styles.dim, &notice)?;
+            writeln!(out)
+        }
+        None => Ok(()),
+    }
+}
+
+/// what one input contained, for the summary lines.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Summary {
+    lines: usize,
+    levels: [usize; Level::ALL.len()],
+    /// first and last timestamps found, as seconds.
+    first: Option<f64>,
+    last: Option<f64>,
+    /// the same, for each file `tail` headers named.
+    tail_files: Vec<(String, Summary)>,
+    /// reading it failed partway, reported already.
+    read_failed: bool,
+}
+
+impl Summary {
+    /// count `line` under `level`.
+    fn record_as(&mut self, line: &str, level: Option<Level>) {
+        self.lines += 1;
+        if let Some(level) = level {
+            self.levels[level as usize] += 1;
+        }
+        if let Some(secs) = line_timestamp(line) {
+            self.first.get_or_insert(secs);
+            self.last = Some(secs);
+        }
+    }
+
+    /// fold `other` into a running total.
+    fn add(&mut self, other: &Summary) {
+        self.lines += other.lines;
+        for (total, n) in self.levels.iter_mut().zip(other.levels) {
+            *total += n;
+        }
+        self.first = match (self.first, other.first) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.last = match (self.last, other.last) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    /// `── api.log.3: 48,211 lines, 17 ERROR, 210 WARN, 4.2s span ──`
+    fn write(&self, out: &mut dyn Write, label: &str, styles: &Styles) -> io::Result<()> {
+        let plural = if self.lines == 1 { "" } else { "s" };
+        let mut text = format!("── {}: {} line{}", label, with_commas(self.lines), plural);
+        let fatal = self.levels[Level::Fatal as usize];
+        for level in [Level::Fatal, Level::Error, Level::Warn] {
+            if level != Level::Fatal || fatal > 0 {
+                let n = with_commas(self.levels[level as usize]);
+                text += &format!(", {} {}", n, level.name());
+            }
+        }
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            text += &format!(", {} span", format_span((last - first).max(0.0)));
+        }
+        styles.paint(out, &styles.dim, &format!("{} ──", text))?;
+        writeln!(out)
+    }
+
+    /// lines at `level` or above, for `--fail-on`.
+    fn at_least(&self, level: Level) -> usize {
+        self.levels[level as usize..].iter().sum()
+    }
+
+    fn unmatched(&self) -> usize {
+        self.lines - self.levels.iter().sum::<usize>()
+    }
+
+    /// the `--stats` table, `ERROR 42  WARN 310  INFO 12894  DEBUG 0  unmatched 77  total 13323`.
+    fn table(&self, styles: &Styles) -> String {
+        let mut out = Vec::new();
+        for level in Level::ALL.into_iter().rev() {
+            let n = self.levels[level as usize];
+            let always = matches!(level, Level::Error | Level::Warn | Level::Info | Level::Debug);
+            if always || n > 0 {
+                // writing into a Vec can't fail
+                let _ = styles.paint_level(&mut out, level, level.name());
+                let _ = write!(out, " {}  ", n);
+            }
+        }
+        let _ = write!(out, "unmatched {}  total {}", self.unmatched(), self.lines);
+        String::from_utf8_lossy(&out).into_owned()
+    }
+
+    /// the fields `--stats-format json` gives for the total and for each file.
+    fn to_json(&self) -> Json {
+        let count = |n: usize| Json::Number(n.to_string());
+        let mut levels: Vec<(String, Json)> = Level::ALL
+            .into_iter()
+            .rev()
+            .map(|level| (level.name().to_lowercase(), count(self.levels[level as usize])))
+            .collect();
+        levels.push(("unmatched".to_string(), count(self.unmatched())));
+        let timestamp = |secs: Option<f64>| match secs {
+            Some(secs) => {
+                let text = format_unix_micros((secs * 1e6).round() as i64);
+                Json::String(format!("{}Z", text.replacen(' ', "T", 1)))
+            }
+            None => Json::Null,
+        };
+        Json::Object(vec![
+            ("lines".to_string(), count(self.lines)),
+            ("levels".to_string(), Json::Object(levels)),
+            ("first_timestamp".to_string(), timestamp(self.first)),
+            ("last_timestamp".to_string(), timestamp(self.last)),
+        ])
+    }
+}
+
+/// how `--stats` are written.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StatsFormat {
+    Human,
+    Json,
+}
+
+impl StatsFormat {
+    pub fn parse(s: &str) -> Option<StatsFormat> {
+        match s {
+            "human" => Some(StatsFormat::Human),
+            "json" => Some(StatsFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+/// the `--stats-format json` object: the total, then `files` when there was
+/// more than one input.
+fn stats_json(total: &Summary, files: &[(String, Summary)], elapsed: Duration) -> Json {
+    let Json::Object(mut fields) = total.to_json() else { unreachable!() };
+    fields.push((
+        "elapsed_seconds".to_string(),
+        Json::Number(format!("{:.3}", elapsed.as_secs_f64())),
+    ));
+    if files.len() > 1 {
+        let files = files.iter().map(|(path, summary)| {
+            let Json::Object(mut fields) = summary.to_json() else { unreachable!() };
+            fields.insert(0, ("path".to_string(), Json::String(path.clone())));
+            Json::Object(fields)
+        });
+        fields.push(("files".to_string(), Json::Array(files.collect())));
+    }
+    Json::Object(fields)
+}
+
+/// how much of the output may wait in memory before it goes out, unless
+/// every line is flushed.
+const OUTPUT_BUFFER_BYTES: usize = 64 << 10;
+
+/// set once stdout's reader has gone away, as `head` does once it has its
+/// lines: the end of the run rather than an error.
+static OUTPUT_CLOSED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+/// set once writing the output failed some other way, reported already.
+static OUTPUT_FAILED: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+fn output_closed() -> bool {
+    OUTPUT_CLOSED.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+fn note_closed(e: &io::Error) {
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        OUTPUT_CLOSED.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
+/// a write error while rendering: stdout closed comes back, to stop the
+/// reading without a word; anything else is reported, the first time, and
+/// comes back as dealt with.
+fn write_failed(e: io::Error) -> io::Result<()> {
+    note_closed(&e);
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        return Err(e);
+    }
+    if !OUTPUT_FAILED.swap(true, std::sync::atomic::Ordering::Relaxed) {
+        eprintln!("write error: {}", e);
+    }
+    Ok(())
+}
+
+/// what the program ends with once stdout is closed: the status a SIGPIPE
+/// would have left.
+fn end_closed() -> ! {
+    end_audit("output closed");
+    exit(141);
+}
+
+/// stdout as everything shares it: the lines, marks from the key reader,
+/// summaries. block-buffered unless `line_flush`, and a clone can flush it
+/// from another thread, as SIGUSR1 does.
+#[derive(Clone)]
+struct Output {
+    inner: std::sync::Arc<std::sync::Mutex<io::BufWriter<Box<dyn Write + Send>>>>,
+    line_flush: bool,
+}
+
+impl Output {
+    fn new(sink: Box<dyn Write + Send>, line_flush: bool) -> Output {
+        let buffered = io::BufWriter::with_capacity(OUTPUT_BUFFER_BYTES, sink);
+        Output { inner: std::sync::Arc::new(std::sync::Mutex::new(buffered)), line_flush }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, io::BufWriter<Box<dyn Write + Send>>> {
+        // whoever panicked holding it left bytes, not broken invariants
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// flush on the way out, unless another thread is in the middle of a
+    /// write, which may be stuck on a full pipe.
+    fn try_flush(&self) {
+        if let Ok(mut inner) = self.inner.try_lock() {
+            let _ = inner.flush();
+        }
+    }
+
+    /// a last word on the way out, as for `try_flush`.
+    fn try_write(&self, bytes: &[u8]) {
+        if let Ok(mut inner) = self.inner.try_lock() {
+            let _ = inner.write_all(bytes);
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut inner = self.lock();
+        let written = inner.write(buf).inspect_err(note_closed)?;
+        if self.line_flush && buf[..written].contains(&b'\n') {
+            inner.flush().inspect_err(note_closed)?;
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.lock().flush().inspect_err(note_closed)
+    }
+}
+
+/// stdout, and with `--mirror` a second sink getting every byte stdout
+/// does. the mirror failing (its reader gone, its terminal closed) stops the
+/// mirroring, with a word about it once; stdout goes on.
+/// `--crlf`: every line ends in `\r\n`, for the Windows tools that want
+/// it; one already ending that way is left alone.
+struct Crlf<W> {
+    inner: W,
+    /// the last byte written was a `\r`.
+    after_cr: bool,
+}
+
+impl<W: Write> Crlf<W> {
+    fn new(inner: W) -> Crlf<W> {
+        Crlf { inner, after_cr: false }
+    }
+}
+
+impl<W: Write> Write for Crlf<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut start = 0;
+        for (i, &b) in buf.iter().enumerate() {
+            let after_cr = if i == 0 { self.after_cr } else { buf[i - 1] == b'\r' };
+            if b == b'\n' && !after_cr {
+                self.inner.write_all(&buf[start..i])?;
+                self.inner.write_all(b"\r")?;
+                start = i;
+            }
+        }
+        self.inner.write_all(&buf[start..])?;
+        if let Some(&last) = buf.last() {
+            self.after_cr = last == b'\r';
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// `--preserve-eol`: while the line being written is an input's last and
+/// came without a newline, the newline ending what is written for it is
+/// held back; anything written after lets it through.
+struct HeldNewline<'a> {
+    inner: &'a mut dyn Write,
+    unterminated: &'a std::cell::Cell<bool>,
+    held: bool,
+}
+
+impl<'a> HeldNewline<'a> {
+    fn new(inner: &'a mut dyn Write, unterminated: &'a std::cell::Cell<bool>) -> HeldNewline<'a> {
+        HeldNewline { inner, unterminated, held: false }
+    }
+}
+
+impl Write for HeldNewline<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if std::mem::take(&mut self.held) {
+            self.inner.write_all(b"\n")?;
+        }
+        match buf.strip_suffix(b"\n").filter(|_| self.unterminated.get()) {
+            Some(rest) => {
+                self.inner.write_all(rest)?;
+                self.held = true;
+            }
+            None => self.inner.write_all(buf)?,
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// lines ending in `\r\n` written that way: while the line being written
+/// came with one, so does every newline written for it.
+struct KeptCrlf<'a> {
+    crlf: Crlf<&'a mut dyn Write>,
+    crlf_line: &'a std::cell::Cell<bool>,
+}
+
+impl<'a> KeptCrlf<'a> {
+    fn new(inner: &'a mut dyn Write, crlf_line: &'a std::cell::Cell<bool>) -> KeptCrlf<'a> {
+        KeptCrlf { crlf: Crlf::new(inner), crlf_line }
+    }
+}
+
+impl Write for KeptCrlf<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.crlf_line.get() {
+            return self.crlf.write(buf);
+        }
+        self.crlf.inner.write_all(buf)?;
+        if let Some(&last) = buf.last() {
+            self.crlf.after_cr = last == b'\r';
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.crlf.flush()
+    }
+}
+
+/// `--output-format`: escape codes for a terminal, or HTML to paste
+/// somewhere that shows none.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputFormat {
+    Ansi,
+    Html,
+}
+
+impl OutputFormat {
+    pub fn parse(s: &str) -> Option<OutputFormat> {
+        match s {
+            "ansi" => Some(OutputFormat::Ansi),
+            "html" => Some(OutputFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+/// written last to an `Html` writer: closes what `--html-standalone` opened.
+/// a private OSC, so nothing else writes it, and it's dropped elsewhere.
+const HTML_END: &str = "\x1b]logcolor;end\x07";
+
+/// how a level's text is drawn, each with a class of its own in HTML.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LevelLook {
+    /// the level token, bold.
+    Token,
+    /// a phrase the level was inferred from, underlined.
+    Inferred,
+    /// the rest of a line drawn in the level's looks.
+    Line,
+}
+
+impl LevelLook {
+    const ALL: [LevelLook; 3] = [LevelLook::Token, LevelLook::Inferred, LevelLook::Line];
+}
+
+/// the class `Html` gives `level`'s `look`, like `lvl-warn-inferred`.
+fn level_class(level: Level, look: LevelLook) -> String {
+    let suffix = match look {
+        LevelLook::Token => "",
+        LevelLook::Inferred => "-inferred",
+        LevelLook::Line => "-line",
+    };
+    format!("lvl-{}{}", level.name().to_lowercase(), suffix)
+}
+
+/// what `Styles::tagged` writes ahead of a level's SGR: a private OSC like
+/// `HTML_END`, naming the level's class.
+const LEVEL_TAG_PREFIX: &str = "\x1b]logcolor;lvl-";
+
+fn level_tag(level: Level, look: LevelLook) -> &'static str {
+    static TAGS: std::sync::OnceLock<Vec<String>> = std::sync::OnceLock::new();
+    let tags = TAGS.get_or_init(|| {
+        let tags =
+            Level::ALL.into_iter().flat_map(|level| LevelLook::ALL.map(|look| (level, look)));
+        tags.map(|(level, look)| format!("\x1b]logcolor;{}\x07", level_class(level, look)))
+            .collect()
+    });
+    &tags[level as usize * LevelLook::ALL.len() + look as usize]
+}
+
+/// `--output-format html`: what would go to the terminal, as HTML. text is
+/// escaped, SGR sequences become `<span>`s (a level's own looks, as
+/// `Styles::tagged` marks them, a class like `lvl-error`, anything else an
+/// inline style) and other escapes are dropped, so every way a line gets
+/// drawn comes out the same in both.
+struct Html<W> {
+    inner: W,
+    /// the page's background, which reverse video draws text in.
+    background: &'static str,
+    /// `--html-standalone`: the page's start, up to `<pre>`, until written.
+    header: Option<String>,
+    standalone: bool,
+    /// an escape sequence begun, maybe in an earlier write.
+    escape: Vec<u8>,
+    /// spans to open once more text comes: a level's class, with the SGR
+    /// after its tag, or SGR sequences drawn inline.
+    pending: Vec<(Option<String>, String)>,
+    /// `<span>`s not closed yet.
+    open: usize,
+}
+
+/// the page `--html-standalone` puts the lines in.
+const HTML_FOOTER: &str = "</pre>\n</body>\n</html>\n";
+
+impl<W: Write> Html<W> {
+    fn new(inner: W, styles: &Styles, light: bool, standalone: bool) -> Html<W> {
+        let (background, foreground) = match light {
+            true => ("#ffffff", "#1e1e1e"),
+            false => ("#1e1e1e", "#d4d4d4"),
+        };
+        let header = standalone.then(|| {
+            let mut rules = String::new();
+            for level in Level::ALL {
+                for look in LevelLook::ALL {
+                    let style = match look {
+                        LevelLook::Token => styles.level_style(level, BOLD),
+                        LevelLook::Inferred => styles.level_style(level, UNDERLINE),
+                        LevelLook::Line => styles.looks[level as usize].clone(),
+                    };
+                    // the tag is no SGR, and leaves no CSS
+                    let css = sgr_css(&style.sgr(styles.depth), background);
+                    if !css.is_empty() {
+                        rules.push_str(&format!(".{} {{ {} }}\n", level_class(level, look), css));
+                    }
+                }
+            }
+            format!(
+                "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n\
+                 <title>logcolor</title>\n<style>\n\
+                 body {{ background: {}; color: {}; }}\n\
+                 pre {{ font-family: ui-monospace, Menlo, Consolas, monospace; }}\n\
+                 {}</style>\n</head>\n<body>\n<pre>\n",
+                background, foreground, rules
+            )
+        });
+        Html {
+            inner,
+            background,
+            header,
+            standalone,
+            escape: Vec::new(),
+            pending: Vec::new(),
+            open: 0,
+        }
+    }
+
+    /// a whole escape sequence, `self.escape`, taken in.
+    fn escaped(&mut self, out: &mut Vec<u8>) {
+        let escape = std::mem::take(&mut self.escape);
+        let Ok(escape) = String::from_utf8(escape) else { return };
+        if escape == HTML_END {
+            self.close(out);
+            if self.standalone {
+                out.extend_from_slice(HTML_FOOTER.as_bytes());
+            }
+            return;
+        }
+        if let Some(class) = escape.strip_prefix(LEVEL_TAG_PREFIX) {
+            let class = format!("lvl-{}", class.trim_end_matches('\x07'));
+            self.pending.push((Some(class), String::new()));
+            return;
+        }
+        if !escape.starts_with("\x1b[") || !escape.ends_with('m') {
+            return;
+        }
+        match &escape[2..escape.len() - 1] {
+            "" | "0" => {
+                self.pending.clear();
+                self.close(out);
+            }
+            _ => match self.pending.last_mut() {
+                Some((_, sgr)) => sgr.push_str(&escape),
+                None => self.pending.push((None, escape)),
+            },
+        }
+    }
+
+    fn close(&mut self, out: &mut Vec<u8>) {
+        for _ in 0..std::mem::take(&mut self.open) {
+            out.extend_from_slice(b"</span>");
+        }
+    }
+}
+
+/// the CSS for what `sgr`'s codes do, `background` showing through reverse
+/// video.
+fn sgr_css(sgr: &str, background: &str) -> String {
+    let mut codes = Vec::new();
+    for seq in sgr.split('\x1b').filter_map(|s| s.strip_prefix('[')?.strip_suffix('m')) {
+        codes.extend(seq.split(';').map(|code| code.parse::<u8>().unwrap_or(0)));
+    }
+    let hex = |color: Color| {
+        let (r, g, b) = color.to_rgb();
+        format!("#{:02x}{:02x}{:02x}", r, g, b)
+    };
+    let (mut decls, mut fg, mut reverse) = (Vec::new(), None, false);
+    let mut codes = codes.into_iter();
+    while let Some(code) = codes.next() {
+        match code {
+            1 => decls.push("font-weight: bold".to_string()),
+            2 => decls.push("opacity: 0.6".to_string()),
+            3 => decls.push("font-style: italic".to_string()),
+            4 => decls.push("text-decoration: underline".to_string()),
+            7 => reverse = true,
+            30..=37 => fg = Some(Color::Basic(code - 30)),
+            90..=97 => fg = Some(Color::Basic(code - 90 + 8)),
+            38 => {
+                fg = match (codes.next(), codes.next()) {
+                    (Some(5), Some(n)) => Some(Color::Indexed(n)),
+                    (Some(2), Some(r)) => match (codes.next(), codes.next()) {
+                        (Some(g), Some(b)) => Some(Color::Rgb(r, g, b)),
+                        _ => fg,
+                    },
+                    _ => fg,
+                }
+            }
+            _ => {}
+        }
+    }
+    match (fg.map(hex), reverse) {
+        (Some(fg), true) => decls.push(format!("color: {}; background: {}", background, fg)),
+        (None, true) => decls.push(format!("color: {}; background: currentColor", background)),
+        (Some(fg), false) => decls.push(format!("color: {}", fg)),
+        (None, false) => {}
+    }
+    decls.join("; ")
+}
+
+impl<W: Write> Write for Html<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut out = Vec::with_capacity(buf.len() + 64);
+        if let Some(header) = self.header.take() {
+            out.extend_from_slice(header.as_bytes());
+        }
+        for &b in buf {
+            if !self.escape.is_empty() {
+                self.escape.push(b);
+                let whole = match self.escape.as_slice() {
+                    [0x1b, b'['] | [0x1b, b']'] => false,
+                    [0x1b, b'[', .., last] => (0x40..=0x7e).contains(last),
+                    [0x1b, b']', .., b'\x07'] | [0x1b, b']', .., 0x1b, b'\\'] => true,
+                    [0x1b, b']', ..] => false,
+                    // a lone ESC, dropped as `escape_len` does
+                    _ => {
+                        self.escape.clear();
+                        if b == 0x1b {
+                            self.escape.push(b);
+                            continue;
+                        }
+                        false
+                    }
+                };
+                if whole {
+                    self.escaped(&mut out);
+                }
+                if !self.escape.is_empty() || whole {
+                    continue;
+                }
+            } else if b == 0x1b {
+                self.escape.push(b);
+                continue;
+            }
+            // a line's looks and then its token's, say, each their own span
+            for (class, sgr) in std::mem::take(&mut self.pending) {
+                match class {
+                    Some(class) => write!(out, "<span class=\"{}\">", class)?,
+                    None => write!(out, "<span style=\"{}\">", sgr_css(&sgr, self.background))?,
+                }
+                self.open += 1;
+            }
+            match b {
+                b'<' => out.extend_from_slice(b"&lt;"),
+                b'>' => out.extend_from_slice(b"&gt;"),
+                b'&' => out.extend_from_slice(b"&amp;"),
+                _ => out.push(b),
+            }
+        }
+        self.inner.write_all(&out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct Tee {
+    primary: Box<dyn Write + Send>,
+    mirror: Option<(String, Box<dyn Write + Send>)>,
+}
+
+impl Tee {
+    fn new(primary: Box<dyn Write + Send>, mirror: Option<(String, Box<dyn Write + Send>)>) -> Tee {
+        Tee { primary, mirror }
+    }
+
+    fn mirror_failed(&mut self, e: io::Error) {
+        if let Some((name, _)) = self.mirror.take() {
+            eprintln!("stopped mirroring to {}: {}", name, e);
+        }
+    }
+}
+
+impl Write for Tee {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.primary.write(buf)?;
+        // what stdout took and no more, so a short write doesn't repeat
+        if let Some((_, mirror)) = &mut self.mirror {
+            if let Err(e) = mirror.write_all(&buf[..written]) {
+                self.mirror_failed(e);
+            }
+        }
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some((_, mirror)) = &mut self.mirror {
+            if let Err(e) = mirror.flush() {
+                self.mirror_failed(e);
+            }
+        }
+        self.primary.flush()
+    }
+}
+
+/// escape sequences taken out of what is written, for a copy that is read
+/// as plain text: the `--output` file, or stdout when only the file is to
+/// have colors.
+struct Plain<W> {
+    inner: W,
+    /// an escape sequence begun, maybe in an earlier write.
+    escape: Vec<u8>,
+}
+
+impl<W: Write> Plain<W> {
+    fn new(inner: W) -> Plain<W> {
+        Plain { inner, escape: Vec::new() }
+    }
+}
+
+impl<W: Write> Write for Plain<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut out = Vec::with_capacity(buf.len());
+        for &b in buf {
+            if self.escape.is_empty() {
+                match b {
+                    0x1b => self.escape.push(b),
+                    _ => out.push(b),
+                }
+                continue;
+            }
+            self.escape.push(b);
+            let whole = match self.escape.as_slice() {
+                [0x1b, b'['] | [0x1b, b']'] => false,
+                [0x1b, b'[', .., last] => (0x40..=0x7e).contains(last),
+                [0x1b, b']', .., b'\x07'] | [0x1b, b']', .., 0x1b, b'\\'] => true,
+                [0x1b, b']', ..] => false,
+                // a lone ESC, dropped as `escape_len` does
+                _ => {
+                    self.escape.clear();
+                    match b {
+                        0x1b => self.escape.push(b),
+                        _ => out.push(b),
+                    }
+                    continue;
+                }
+            };
+            if whole {
+                self.escape.clear();
+            }
+        }
+        self.inner.write_all(&out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// `--output`: a file getting the output as well as stdout, or instead of
+/// it. failing to write it ends the program, with a word about it; a copy
+/// with a hole in it is no copy.
+struct OutputFile {
+    path: String,
+    file: std::fs::File,
+}
+
+impl OutputFile {
+    fn open(path: &str, append: bool) -> io::Result<OutputFile> {
+        let mut options = std::fs::OpenOptions::new();
+        match append {
+            true => options.append(true),
+            false => options.write(true).truncate(true),
+        };
+        let file = options.create(true).open(path)?;
+        Ok(OutputFile { path: path.to_string(), file })
+    }
+
+    fn failed(&self, e: io::Error) -> ! {
+        eprintln!("failed to write --output {}: {}", self.path, e);
+        end_audit("failed");
+        exit(1);
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.file.write(buf) {
+            Err(e) if e.kind() != io::ErrorKind::Interrupted => self.failed(e),
+            written => written,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file.flush() {
+            Err(e) => self.failed(e),
+            flushed => flushed,
+        }
+    }
+}
+
+/// `──── mark 14:32:18 ────` across `width` columns.
+fn mark_line(label: &str, width: usize) -> String {
+    let fill = width.saturating_sub(display_width(label) + 6).max(4);
+    format!("──── {} {}", label, "─".repeat(fill))
+}
+
+/// the wall clock as `HH:MM:SS`, local time where we can tell.
+fn clock_now() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+    let (h, m, s) = term::local_time_of_day(secs).unwrap_or_else(|| {
+        let tod = secs.rem_euclid(86_400);
+        ((tod / 3600) as u32, (tod / 60 % 60) as u32, (tod % 60) as u32)
+    });
+    format!("{:02}:{:02}:{:02}", h, m, s)
+}
+
+/// while following, Enter draws a divider and `m` a numbered one, so
+/// "everything after this point" is easy to find again.
+fn start_marks(styles: &Styles, width: TermWidth, mut out: Output) -> Option<term::KeyReader> {
+    let sgr = if styles.enabled() {
+        format!("{}{}", BOLD, styles.color(Level::Debug))
+    } else {
+        String::new()
+    };
+    let mut count = 0;
+    term::read_keys(move |key| {
+        let label = match key {
+            b'\r' | b'\n' => format!("mark {}", clock_now()),
+            b'm' => {
+                count += 1;
+                format!("mark #{} {}", count, clock_now())
+            }
+            _ => return,
+        };
+        let line = mark_line(&label, width.get());
+        let text = if sgr.is_empty() { line } else { format!("{}{}{}", sgr, line, RESET) };
+        let _ = out.write_all(format!("{}\n", text).as_bytes());
+    })
+}
+
+/// how often `--retry` looks for a file that isn't there yet.
+const RETRY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// open `path`, and with `retry` keep polling while it doesn't exist, giving
+/// up after `timeout` if there is one. the wait is announced once on `notice`.
+fn open_input(
+    path: &str,
+    retry: bool,
+    timeout: Option<Duration>,
+    notice: &mut dyn Write,
+    styles: &Styles,
+) -> io::Result<File> {
+    let started = Instant::now();
+    let mut announced = false;
+    loop {
+        match File::open(path) {
+            Err(e) if retry && e.kind() == io::ErrorKind::NotFound => {
+                if timeout.is_some_and(|t| started.elapsed() >= t) {
+                    return Err(e);
+                }
+                if !announced {
+                    styles.paint(notice, &styles.dim, &format!("--- waiting for {} ---", path))?;
+                    writeln!(notice)?;
+                    announced = true;
+                }
+                thread::sleep(RETRY_POLL_INTERVAL);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// an input read through gunzip when it starts with gzip's magic bytes,
+/// whatever it is called (rotated logs don't always keep their `.gz`), and
+/// as it is otherwise. members `cat`ed together come out one after another.
+struct Gunzip<R> {
+    inner: R,
+    /// the input as errors name it.
+    label: String,
+    input: Box<[u8]>,
+    pos: usize,
+    len: usize,
+    /// bits taken from `input` but not used yet, the first in the lowest.
+    bits: u64,
+    nbits: u32,
+    state: GzipState,
+    last_block: bool,
+    /// what the current member has inflated to. `emitted` of it has been
+    /// read, and more than the 32K a distance reaches back gets dropped.
+    window: Vec<u8>,
+    emitted: usize,
+    crc: u32,
+    member_len: u64,
+}
+
+/// what `Gunzip` reads next.
+enum GzipState {
+    /// the first bytes, to tell gzip from anything else.
+    Sniff,
+    /// not gzip: passed through.
+    Plain,
+    /// a member's header, at the start or after another member.
+    Header,
+    /// a deflate block's header.
+    Block,
+    /// the bytes left in a stored block.
+    Stored(usize),
+    /// a block of Huffman codes, literal/length and distance.
+    Codes(Box<(Huffman, Huffman)>),
+    /// the CRC-32 and length after the last block.
+    Trailer,
+    Done,
+    /// given again to every read after the first, not taken for the end.
+    Failed(io::ErrorKind, String),
+}
+
+/// inflating past this much in one go, the window gets read out first.
+const INFLATE_CHUNK: usize = 32 * 1024;
+/// how far back a deflate distance reaches.
+const INFLATE_WINDOW: usize = 32 * 1024;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] =
+    [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// the order a dynamic block gives its code length code's lengths in.
+const CODE_LENGTH_ORDER: [usize; 19] =
+    [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// a canonical Huffman code: how many codes there are of each length, and
+/// the symbols in code order.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        // more codes of a length than there is room for can't be decoded
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = 2 * left - count as i32;
+            if left < 0 {
+                return Err(corrupt_gzip("oversubscribed Huffman code"));
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    /// the code tables of a fixed Huffman block.
+    fn fixed() -> (Huffman, Huffman) {
+        let mut lengths = [8u8; 288];
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        // both built from lengths that fit
+        (Huffman::new(&lengths).unwrap(), Huffman::new(&[5; 30]).unwrap())
+    }
+}
+
+fn corrupt_gzip(why: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("corrupt gzip data ({})", why))
+}
+
+/// the CRC-32 gzip keeps of a member, `crc` carried on over `bytes`.
+fn crc32(crc: u32, bytes: &[u8]) -> u32 {
+    static TABLE: std::sync::OnceLock<[u32; 256]> = std::sync::OnceLock::new();
+    let table = TABLE.get_or_init(|| {
+        let mut table = [0; 256];
+        for (n, entry) in table.iter_mut().enumerate() {
+            *entry = (0..8).fold(n as u32, |c, _| match c & 1 {
+                1 => 0xedb8_8320 ^ (c >> 1),
+                _ => c >> 1,
+            });
+        }
+        table
+    });
+    !bytes.iter().fold(!crc, |c, &b| table[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8))
+}
+
+impl<R: Read> Gunzip<R> {
+    fn new(inner: R, label: String) -> Gunzip<R> {
+        Gunzip {
+            inner,
+            label,
+            input: vec![0; 64 * 1024].into_boxed_slice(),
+            pos: 0,
+            len: 0,
+            bits: 0,
+            nbits: 0,
+            state: GzipState::Sniff,
+            last_block: false,
+            window: Vec::new(),
+            emitted: 0,
+            crc: 0,
+            member_len: 0,
+        }
+    }
+
+    /// read more into `input` once it is used up; false at the end.
+    fn fill(&mut self) -> io::Result<bool> {
+        if self.pos < self.len {
+            return Ok(true);
+        }
+        (self.pos, self.len) = (0, 0);
+        loop {
+            match self.inner.read(&mut self.input) {
+                Ok(n) => {
+                    self.len = n;
+                    return Ok(n > 0);
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn take_bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.nbits < n {
+            if !self.fill()? {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "gzip data cut short"));
+            }
+            self.bits |= (self.input[self.pos] as u64) << self.nbits;
+            self.pos += 1;
+            self.nbits += 8;
+        }
+        let value = (self.bits & ((1u64 << n) - 1)) as u32;
+        self.bits >>= n;
+        self.nbits -= n;
+        Ok(value)
+    }
+
+    fn take_u8(&mut self) -> io::Result<u8> {
+        self.take_bits(8).map(|b| b as u8)
+    }
+
+    fn take_u32(&mut self) -> io::Result<u32> {
+        (0..4).try_fold(0, |value, i| Ok(value | (self.take_u8()? as u32) << (8 * i)))
+    }
+
+    /// drop the rest of the byte the last bits came from.
+    fn align(&mut self) {
+        let partial = self.nbits % 8;
+        self.bits >>= partial;
+        self.nbits -= partial;
+    }
+
+    fn decode(&mut self, code: &Huffman) -> io::Result<u16> {
+        let (mut code_bits, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &code.counts[1..] {
+            code_bits |= self.take_bits(1)? as i32;
+            let count = count as i32;
+            if code_bits - first < count {
+                return Ok(code.symbols[(index + code_bits - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code_bits <<= 1;
+        }
+        Err(corrupt_gzip("invalid Huffman code"))
+    }
+
+    /// a member's header, up to where its deflate data starts.
+    fn header(&mut self) -> io::Result<()> {
+        let (id1, id2) = (self.take_u8()?, self.take_u8()?);
+        if (id1, id2) != (0x1f, 0x8b) {
+            return Err(corrupt_gzip("not a gzip member"));
+        }
+        if self.take_u8()? != 8 {
+            return Err(corrupt_gzip("unknown compression method"));
+        }
+        let flags = self.take_u8()?;
+        if flags & 0xe0 != 0 {
+            return Err(corrupt_gzip("reserved header flags set"));
+        }
+        // mtime, extra flags and OS
+        for _ in 0..6 {
+            self.take_u8()?;
+        }
+        if flags & 0x04 != 0 {
+            let extra = self.take_u8()? as usize | (self.take_u8()? as usize) << 8;
+            for _ in 0..extra {
+                self.take_u8()?;
+            }
+        }
+        // the file name and the comment, each ended by a NUL
+        for flag in [0x08, 0x10] {
+            if flags & flag != 0 {
+                while self.take_u8()? != 0 {}
+            }
+        }
+        if flags & 0x02 != 0 {
+            self.take_bits(16)?;
+        }
+        (self.last_block, self.crc, self.member_len) = (false, 0, 0);
+        self.window.clear();
+        self.emitted = 0;
+        Ok(())
+    }
+
+    /// the tables of a dynamic Huffman block, from its header.
+    fn dynamic_codes(&mut self) -> io::Result<(Huffman, Huffman)> {
+        let literals = self.take_bits(5)? as usize + 257;
+        let distances = self.take_bits(5)? as usize + 1;
+        let code_lengths = self.take_bits(4)? as usize + 4;
+        if literals > 286 || distances > 30 {
+            return Err(corrupt_gzip("too many length or distance codes"));
+        }
+        let mut lengths = [0u8; 19];
+        for &symbol in &CODE_LENGTH_ORDER[..code_lengths] {
+            lengths[symbol] = self.take_bits(3)? as u8;
+        }
+        let length_code = Huffman::new(&lengths)?;
+        let mut lengths = Vec::with_capacity(literals + distances);
+        while lengths.len() < literals + distances {
+            let (value, repeat) = match self.decode(&length_code)? {
+                len @ 0..=15 => (len as u8, 1),
+                16 => {
+                    let last =
+                        *lengths.last().ok_or_else(|| corrupt_gzip("repeat with no length"))?;
+                    (last, 3 + self.take_bits(2)?)
+                }
+                17 => (0, 3 + self.take_bits(3)?),
+                _ => (0, 11 + self.take_bits(7)?),
+            };
+            if lengths.len() + repeat as usize > literals + distances {
+                return Err(corrupt_gzip("too many code lengths"));
+            }
+            lengths.resize(lengths.len() + repeat as usize, value);
+        }
+        if lengths[256] == 0 {
+            return Err(corrupt_gzip("no end-of-block code"));
+        }
+        Ok((Huffman::new(&lengths[..literals])?, Huffman::new(&lengths[literals..])?))
+    }
+
+    /// inflate some more into `window`, or pass the state on.
+    fn step(&mut self) -> io::Result<()> {
+        self.state = match std::mem::replace(&mut self.state, GzipState::Done) {
+            GzipState::Sniff => {
+                let gzip = self.fill()? && self.input[self.pos] == 0x1f && {
+                    // a second byte, without losing the first
+                    if self.len == 1 {
+                        let n = self.inner.read(&mut self.input[1..])?;
+                        self.len += n;
+                    }
+                    self.len > 1 && self.input[1] == 0x8b
+                };
+                match gzip {
+                    true => GzipState::Header,
+                    false => GzipState::Plain,
+                }
+            }
+            GzipState::Header => {
+                self.header()?;
+                GzipState::Block
+            }
+            GzipState::Block if self.last_block => GzipState::Trailer,
+            GzipState::Block => {
+                self.last_block = self.take_bits(1)? == 1;
+                match self.take_bits(2)? {
+                    0 => {
+                        self.align();
+                        let (len, nlen) = (self.take_bits(16)?, self.take_bits(16)?);
+                        if len != !nlen & 0xffff {
+                            return Err(corrupt_gzip("stored block length doesn't check"));
+                        }
+                        GzipState::Stored(len as usize)
+                    }
+                    1 => GzipState::Codes(Box::new(Huffman::fixed())),
+                    2 => GzipState::Codes(Box::new(self.dynamic_codes()?)),
+                    _ => return Err(corrupt_gzip("invalid block type")),
+                }
+            }
+            GzipState::Stored(left) => {
+                let n = left.min(INFLATE_CHUNK);
+                for _ in 0..n {
+                    let byte = self.take_u8()?;
+                    self.window.push(byte);
+                }
+                match left - n {
+                    0 => GzipState::Block,
+                    left => GzipState::Stored(left),
+                }
+            }
+            GzipState::Codes(codes) => {
+                let (literal, distance) = &*codes;
+                let target = self.window.len() + INFLATE_CHUNK;
+                let mut ended = false;
+                while self.window.len() < target {
+                    let symbol = self.decode(literal)? as usize;
+                    if symbol < 256 {
+                        self.window.push(symbol as u8);
+                        continue;
+                    }
+                    if symbol == 256 {
+                        ended = true;
+                        break;
+                    }
+                    let i = symbol - 257;
+                    if i >= LENGTH_BASE.len() {
+                        return Err(corrupt_gzip("invalid length code"));
+                    }
+                    let len =
+                        LENGTH_BASE[i] as usize + self.take_bits(LENGTH_EXTRA[i] as u32)? as usize;
+                    let i = self.decode(distance)? as usize;
+                    if i >= DISTANCE_BASE.len() {
+                        return Err(corrupt_gzip("invalid distance code"));
+                    }
+                    let back = DISTANCE_BASE[i] as usize
+                        + self.take_bits(DISTANCE_EXTRA[i] as u32)? as usize;
+                    if back > self.window.len() {
+                        return Err(corrupt_gzip("distance too far back"));
+                    }
+                    let from = self.window.len() - back;
+                    // the copy may overlap what it writes, so a byte at a time
+                    for k in 0..len {
+                        let byte = self.window[from + k];
+                        self.window.push(byte);
+                    }
+                }
+                match ended {
+                    true => GzipState::Block,
+                    false => GzipState::Codes(codes),
+                }
+            }
+            GzipState::Trailer => {
+                self.align();
+                let (crc, len) = (self.take_u32()?, self.take_u32()?);
+                if crc != self.crc || len != self.member_len as u32 {
+                    return Err(corrupt_gzip("checksum or length doesn't match"));
+                }
+                // nothing after a member but another one
+                match self.nbits == 0 && !self.fill()? {
+                    true => GzipState::Done,
+                    false => GzipState::Header,
+                }
+            }
+            state => state,
+        };
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for Gunzip<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            match self.state {
+                GzipState::Plain if self.pos < self.len => {
+                    let n = buf.len().min(self.len - self.pos);
+                    buf[..n].copy_from_slice(&self.input[self.pos..self.pos + n]);
+                    self.pos += n;
+                    return Ok(n);
+                }
+                GzipState::Plain => return self.inner.read(buf),
+                GzipState::Done if self.emitted == self.window.len() => return Ok(0),
+                GzipState::Failed(kind, ref why) => return Err(io::Error::new(kind, why.clone())),
+                _ => {}
+            }
+            if self.emitted < self.window.len() {
+                let pending = &self.window[self.emitted..];
+                let n = buf.len().min(pending.len());
+                buf[..n].copy_from_slice(&pending[..n]);
+                self.crc = crc32(self.crc, &pending[..n]);
+                self.member_len += n as u64;
+                self.emitted += n;
+                return Ok(n);
+            }
+            if self.window.len() > 2 * INFLATE_WINDOW {
+                let drop = self.window.len() - INFLATE_WINDOW;
+                self.window.drain(..drop);
+                self.emitted -= drop;
+            }
+            if let Err(e) = self.step() {
+                let why = format!("{} in {}", e, self.label);
+                self.state = GzipState::Failed(e.kind(), why.clone());
+                return Err(io::Error::new(e.kind(), why));
+            }
+        }
+    }
+}
+
+/// `--max-line-bytes` unless given: past this, a line is cut into pieces
+/// rather than held in memory whole.
+#[doc(hidden)]
+pub const DEFAULT_MAX_LINE_BYTES: usize = 1 << 20;
+
+/// one line of input, or a piece of one cut off at the line length limit.
+#[derive(Debug, PartialEq, Eq)]
+struct RawLine {
+    text: String,
+    /// bytes it took up in the input, newline and any `\r` included.
+    consumed: usize,
+    /// a later piece of an over-long line.
+    continued: bool,
+    /// split off the record before it by `--split-interleaved`.
+    spliced: bool,
+    /// the input ended before its newline.
+    unterminated: bool,
+    /// it ended in `\r\n`, which is how it is written out too.
+    crlf: bool,
+    /// let out by `--sort-buffer` after lines with later timestamps.
+    late: bool,
+}
+
+/// like `BufRead::lines`, but never holding more than `max_line` bytes of a
+/// line, saying how much of the input each one took up, and reading what
+/// isn't UTF-8 too (see `decode_raw`).
+struct RawLines<R> {
+    reader: R,
+    max_line: usize,
+    /// the start of a character split by the last cut.
+    carry: Vec<u8>,
+    /// the last line was cut short.
+    cut: bool,
+    /// the input has run out.
+    eof: bool,
+}
+
+impl<R: BufRead> RawLines<R> {
+    fn new(reader: R, max_line: usize) -> RawLines<R> {
+        RawLines { reader, max_line, carry: Vec::new(), cut: false, eof: false }
+    }
+
+    /// up to `max_line` bytes and the newline ending them, if it came first.
+    fn read_piece(&mut self) -> io::Result<(Vec<u8>, bool)> {
+        let mut buf = std::mem::take(&mut self.carry);
+        loop {
+            let available = self.reader.fill_buf()?;
+            if available.is_empty() {
+                self.eof = true;
+                return Ok((buf, false));
+            }
+            if buf.len() >= self.max_line {
+                // a newline right at the cut still belongs to this piece
+                let ends = available[0] == b'\n';
+                if ends {
+                    buf.push(b'\n');
+                    self.reader.consume(1);
+                }
+                return Ok((buf, ends));
+            }
+            let room = available.len().min(self.max_line - buf.len());
+            match available[..room].iter().position(|&b| b == b'\n') {
+                Some(nl) => {
+                    buf.extend_from_slice(&available[..=nl]);
+                    self.reader.consume(nl + 1);
+                    return Ok((buf, true));
+                }
+                None => {
+                    buf.extend_from_slice(&available[..room]);
+                    self.reader.consume(room);
+                }
+            }
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for RawLines<R> {
+    type Item = io::Result<RawLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (mut bytes, ended) = match self.read_piece() {
+            Ok(piece) => piece,
+            Err(e) => return Some(Err(e)),
+        };
+        if bytes.is_empty() {
+            return None;
+        }
+        if !ended && !self.eof {
+            // don't cut a character in two; its start waits for the next piece
+            let partial = partial_char_len(&bytes);
+            if partial < bytes.len() {
+                self.carry = bytes.split_off(bytes.len() - partial);
+            }
+        }
+        let consumed = bytes.len();
+        let mut crlf = false;
+        if ended {
+            bytes.pop();
+            if bytes.last() == Some(&b'\r') {
+                bytes.pop();
+                crlf = true;
+            }
+        }
+        let continued = std::mem::replace(&mut self.cut, !ended);
+        let unterminated = !ended && self.eof;
+        Some(Ok(RawLine {
+            text: decode_raw(bytes),
+            consumed,
+            continued,
+            spliced: false,
+            unterminated,
+            crlf,
+            late: false,
+        }))
+    }
+}
+
+/// how many bytes at the end of `bytes` are the start of a character the
+/// rest of which hasn't been read yet.
+fn partial_char_len(bytes: &[u8]) -> usize {
+    for back in 1..=bytes.len().min(3) {
+        let b = bytes[bytes.len() - back];
+        if b & 0xc0 == 0x80 {
+            continue;
+        }
+        let needs = match b {
+            0xc2..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf4 => 4,
+            _ => 0,
+        };
+        return if back < needs { back } else { 0 };
+    }
+    0
+}
+
+/// the chars standing in for bytes that aren't UTF-8: byte `b` is
+/// `RAW_BYTE_BASE + b`, in a private use plane no log writes to. they go
+/// through detection and styling like any char, and back out as the byte
+/// they stand for (see `RawBytes`), so a line with binary garbage in it is
+/// colored like the rest and written out unchanged.
+const RAW_BYTE_BASE: u32 = 0x10fd00;
+
+fn raw_byte_char(b: u8) -> char {
+    char::from_u32(RAW_BYTE_BASE + u32::from(b)).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+
+/// the byte `c` stands for, if it is one of the `RAW_BYTE_BASE` chars.
+fn raw_byte_of(c: char) -> Option<u8> {
+    let b = u32::from(c).checked_sub(RAW_BYTE_BASE)?;
+    u8::try_from(b).ok().filter(|&b| b >= 0x80)
+}
+
+/// a line read as text, each byte of an invalid sequence as its
+/// `RAW_BYTE_BASE` char. one of those chars in the input itself is taken
+/// apart into its four bytes the same way, so it comes back out as it was.
+fn decode_raw(bytes: Vec<u8>) -> String {
+    // every one of them is encoded starting with 0xf4
+    let bytes = match String::from_utf8(bytes) {
+        Ok(text) if !text.as_bytes().contains(&0xf4) => return text,
+        Ok(text) => text.into_bytes(),
+        Err(e) => e.into_bytes(),
+    };
+    let mut text = String::with_capacity(bytes.len() + 16);
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match raw_byte_of(c) {
+                Some(_) => {
+                    let mut encoded = [0; 4];
+                    c.encode_utf8(&mut encoded).bytes().for_each(|b| text.push(raw_byte_char(b)));
+                }
+                None => text.push(c),
+            }
+        }
+        chunk.invalid().iter().for_each(|&b| text.push(raw_byte_char(b)));
+    }
+    text
+}
+
+/// what is written with the `RAW_BYTE_BASE` chars in it turned back into
+/// the bytes they stand for.
+struct RawBytes<'a> {
+    inner: &'a mut dyn Write,
+    /// the start of one of them, cut off by the end of the last write.
+    carry: Vec<u8>,
+}
+
+impl<'a> RawBytes<'a> {
+    fn new(inner: &'a mut dyn Write) -> RawBytes<'a> {
+        RawBytes { inner, carry: Vec::new() }
+    }
+}
+
+impl Write for RawBytes<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.carry.is_empty() && !buf.contains(&0xf4) {
+            self.inner.write_all(buf)?;
+            return Ok(buf.len());
+        }
+        let mut bytes = std::mem::take(&mut self.carry);
+        bytes.extend_from_slice(buf);
+        let partial = partial_char_len(&bytes);
+        self.carry = bytes.split_off(bytes.len() - partial);
+        let mut out = Vec::with_capacity(bytes.len());
+        for chunk in bytes.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                match raw_byte_of(c) {
+                    Some(b) => out.push(b),
+                    None => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                }
+            }
+            out.extend_from_slice(chunk.invalid());
+        }
+        self.inner.write_all(&out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.carry.is_empty() {
+            self.inner.write_all(&std::mem::take(&mut self.carry))?;
+        }
+        self.inner.flush()
+    }
+}
+
+/// whether `text` starts like a record: a timestamp, maybe bracketed, then
+/// a level token with nothing but separators between them.
+fn starts_record(text: &str) -> bool {
+    let separator = |c: char| " []|:-<>".contains(c);
+    let Some(ts) =
+        timestamp_range(text).filter(|ts| text[..ts.start].trim_matches(separator).is_empty())
+    else {
+        return false;
+    };
+    // the first word decides; a level further on belongs to the message
+    let rest = &text[ts.end..];
+    let word = rest.trim_start_matches(separator);
+    let rest = &rest[..rest.len() - word.len() + word.find(' ').unwrap_or(word.len())];
+    find_level(rest).is_some_and(|(pos, _)| rest[..pos].trim_matches(separator).is_empty())
+}
+
+/// `--split-interleaved`: where the records spliced into `line` start, as
+/// processes writing one file without locking leave them, e.g. `... INFO
+/// start2024-05-01 12:03:04 ERROR boom`. a split needs a record start right
+/// after something other than a space, outside any quotes, in a line that
+/// is a record itself; a timestamp quoted in a message doesn't qualify.
+fn interleaved_splits(line: &str) -> Vec<usize> {
+    let mut splits = Vec::new();
+    if !starts_record(line) {
+        return splits;
+    }
+    let b = line.as_bytes();
+    let mut quoted = false;
+    let mut from = timestamp_range(line).map_or(0, |ts| ts.end);
+    for i in 1..b.len() {
+        if b[i - 1] == b'"' {
+            quoted = !quoted;
+        }
+        if i < from || quoted || !b[i].is_ascii_digit() {
+            continue;
+        }
+        let at = if b[i - 1] == b'[' { i - 1 } else { i };
+        if at == 0 || b[at - 1].is_ascii_whitespace() || b[at - 1] == b'"' {
+            continue;
+        }
+        if line.get(i..).and_then(parse_iso_seconds).is_some() && starts_record(&line[at..]) {
+            splits.push(at);
+            from = at + timestamp_range(&line[at..]).map_or(1, |ts| ts.end);
+        }
+    }
+    splits
+}
+
+/// `line` as the records `interleaved_splits` finds in it, each but the
+/// first marked as spliced; they share out what the line took up.
+fn split_interleaved(line: RawLine) -> Vec<RawLine> {
+    let splits = match line.continued {
+        true => Vec::new(),
+        false => interleaved_splits(&line.text),
+    };
+    if splits.is_empty() {
+        return vec![line];
+    }
+    let mut records = Vec::with_capacity(splits.len() + 1);
+    let mut rest = line.consumed;
+    let ends = std::iter::once(0).chain(splits.iter().copied());
+    for (start, end) in ends.zip(splits.iter().copied().chain(Some(line.text.len()))) {
+        let last = end == line.text.len();
+        let consumed = if last { rest } else { end - start };
+        rest -= consumed;
+        records.push(RawLine {
+            text: line.text[start..end].to_string(),
+            consumed,
+            continued: false,
+            spliced: start > 0,
+            unterminated: last && line.unterminated,
+            crlf: line.crlf,
+            late: false,
+        });
+    }
+    records
+}
+
+/// `--sort-buffer` lets out the oldest records, whatever their age, once it
+/// holds more lines than this.
+const SORT_BUFFER_LINES: usize = 10_000;
+
+/// a record `--sort-buffer` holds: a line with a timestamp and the lines
+/// after it without one.
+#[derive(Debug)]
+struct Sorting {
+    at: f64,
+    /// arrival order, for records with the same timestamp.
+    seq: u64,
+    lines: Vec<RawLine>,
+}
+
+impl Sorting {
+    fn key(&self) -> (f64, u64) {
+        (self.at, self.seq)
+    }
+}
+
+impl PartialEq for Sorting {
+    fn eq(&self, other: &Sorting) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Sorting {}
+
+impl PartialOrd for Sorting {
+    fn partial_cmp(&self, other: &Sorting) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Sorting {
+    fn cmp(&self, other: &Sorting) -> std::cmp::Ordering {
+        let ((at, seq), (other_at, other_seq)) = (self.key(), other.key());
+        at.total_cmp(&other_at).then(seq.cmp(&other_seq))
+    }
+}
+
+/// `--sort-buffer`: puts records whose timestamps arrive slightly out of
+/// order, like those of several pods merged, back in order. each is held
+/// until one at least `window` seconds newer has come, or too many lines
+/// are held. one older than what has already gone out comes out as soon as
+/// it can, marked late, rather than going back in history. lines without a
+/// timestamp go with the record before them, or straight out before there
+/// has been one. without a window, lines pass straight through.
+struct SortBuffer<I> {
+    lines: I,
+    window: Option<f64>,
+    /// the records held, oldest on top.
+    heap: std::collections::BinaryHeap<std::cmp::Reverse<Sorting>>,
+    /// the last record to come, which the lines after it may still join.
+    open: Option<Sorting>,
+    /// lines in `heap` and `open`.
+    held: usize,
+    /// the newest timestamp seen.
+    newest: Option<f64>,
+    /// the newest timestamp let out.
+    released: Option<f64>,
+    ready: std::collections::VecDeque<io::Result<RawLine>>,
+    seq: u64,
+    done: bool,
+}
+
+impl<I: Iterator<Item = io::Result<RawLine>>> SortBuffer<I> {
+    fn new(lines: I, window: Option<Duration>) -> SortBuffer<I> {
+        SortBuffer {
+            lines,
+            window: window.map(|window| window.as_secs_f64()),
+            heap: std::collections::BinaryHeap::new(),
+            open: None,
+            held: 0,
+            newest: None,
+            released: None,
+            ready: std::collections::VecDeque::new(),
+            seq: 0,
+            done: false,
+        }
+    }
+
+    fn push(&mut self, mut line: RawLine) {
+        let at = match line.continued {
+            true => None,
+            false => line_timestamp(&line.text),
+        };
+        let Some(at) = at else {
+            match &mut self.open {
+                Some(open) => {
+                    open.lines.push(line);
+                    self.held += 1;
+                    self.release(false);
+                }
+                None => self.ready.push_back(Ok(line)),
+            }
+            return;
+        };
+        self.close();
+        line.late = self.released.is_some_and(|released| at < released);
+        self.newest = Some(self.newest.map_or(at, |newest| newest.max(at)));
+        self.seq += 1;
+        self.held += 1;
+        self.open = Some(Sorting { at, seq: self.seq, lines: vec![line] });
+        self.release(false);
+    }
+
+    /// the open record can't take any more lines.
+    fn close(&mut self) {
+        if let Some(open) = self.open.take() {
+            self.heap.push(std::cmp::Reverse(open));
+        }
+    }
+
+    fn let_out(&mut self, record: Sorting) {
+        self.held -= record.lines.len();
+        self.released = Some(self.released.map_or(record.at, |released| released.max(record.at)));
+        self.ready.extend(record.lines.into_iter().map(Ok));
+    }
+
+    /// let out the records that are due, or with `all` every one.
+    fn release(&mut self, all: bool) {
+        if all {
+            self.close();
+        }
+        let window = self.window.unwrap_or_default();
+        while let Some(std::cmp::Reverse(oldest)) = self.heap.peek() {
+            let due = all
+                || self.held > SORT_BUFFER_LINES
+                || self.newest.is_some_and(|newest| oldest.at <= newest - window)
+                || self.released.is_some_and(|released| oldest.at < released);
+            if !due {
+                break;
+            }
+            if let Some(std::cmp::Reverse(oldest)) = self.heap.pop() {
+                self.let_out(oldest);
+            }
+        }
+        if self.held > SORT_BUFFER_LINES {
+            // one record past the cap by itself: the lines after it can
+            // follow it straight out, as nothing older is left
+            if let Some(open) = self.open.take() {
+                self.let_out(open);
+            }
+        }
+    }
+}
+
+impl<I: Iterator<Item = io::Result<RawLine>>> Iterator for SortBuffer<I> {
+    type Item = io::Result<RawLine>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.window.is_none() {
+            return self.lines.next();
+        }
+        loop {
+            if let Some(line) = self.ready.pop_front() {
+                return Some(line);
+            }
+            if self.done {
+                return None;
+            }
+            match self.lines.next() {
+                Some(Ok(line)) => self.push(line),
+                Some(Err(e)) => {
+                    self.release(true);
+                    self.ready.push_back(Err(e));
+                }
+                None => {
+                    self.release(true);
+                    self.done = true;
+                }
+            }
+        }
+    }
+}
+
+/// how many dimmed lines `--lines` shows on either side of its range.
+const LINES_CONTEXT: usize = 3;
+
+/// `--lines START:END`, counted from 1, both ends included.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LineRange {
+    start: usize,
+    end: Option<usize>,
+}
+
+impl LineRange {
+    /// `1000:2000`, `1000:`, `:2000`, or just `1000` for the one line.
+    pub fn parse(value: &str) -> Result<LineRange, String> {
+        let err =
+            || format!("invalid value '{}' for '--lines' (expected a range like 1000:2000)", value);
+        let number = |n: &str| n.parse::<usize>().ok().filter(|&n| n > 0).ok_or_else(err);
+        let (start, end) = match value.split_once(':') {
+            Some((start, end)) => (
+                if start.is_empty() { 1 } else { number(start)? },
+                if end.is_empty() { None } else { Some(number(end)?) },
+            ),
+            None => (number(value)?, Some(number(value)?)),
+        };
+        if end.is_some_and(|end| end < start) {
+            return Err(format!("'--lines {}' ends before it starts", value));
+        }
+        Ok(LineRange { start, end })
+    }
+
+    /// the lines before the first dimmed one, which aren't read at all.
+    pub fn skipped(&self) -> usize {
+        self.start.saturating_sub(LINES_CONTEXT + 1)
+    }
+
+    /// the last line to read, the last dimmed one after the range.
+    fn last_read(&self) -> Option<usize> {
+        self.end.map(|end| end.saturating_add(LINES_CONTEXT))
+    }
+
+    fn contains(&self, line: usize) -> bool {
+        line >= self.start && self.end.is_none_or(|end| line <= end)
+    }
+}
+
+/// move `reader` past its first `lines` lines without looking at them
+/// beyond counting newlines, returning the lines and bytes it passed.
+fn skip_lines(reader: &mut impl BufRead, lines: usize) -> io::Result<(usize, u64)> {
+    let (mut skipped, mut bytes) = (0, 0);
+    while skipped < lines {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        let newlines = buf.iter().filter(|&&b| b == b'\n').count();
+        let used = match skipped + newlines < lines {
+            true => {
+                skipped += newlines;
+                buf.len()
+            }
+            false => {
+                let mut ends = buf.iter().enumerate().filter(|(_, &b)| b == b'\n');
+                let (end, _) = ends.nth(lines - skipped - 1).unwrap_or((buf.len() - 1, &0));
+                skipped = lines;
+                end + 1
+            }
+        };
+        reader.consume(used);
+        bytes += used as u64;
+    }
+    Ok((skipped, bytes))
+}
+
+/// how many record starts `--number` remembers to find the numbers of
+/// lines written late, as `--grep` context; all of them while `--budget`
+/// holds an input back.
+const NUMBER_LOOKBACK: usize = 4096;
+
+/// a line number as grep -n writes it, `NNN:`, padded to line up.
+fn number_prefix(number: usize, styles: &Styles) -> String {
+    let text = format!("{:>6}:", number);
+    if styles.enabled() { format!("{}{}{}", styles.dim, text, RESET) } else { text }
+}
+
+/// `--byte-offsets`: where a line started in its input, right-aligned like
+/// `cat -n` and ended like grep -b; after the line number with both.
+fn offset_prefix(offset: u64, styles: &Styles) -> String {
+    let text = format!("{:>8}:", offset);
+    if styles.enabled() { format!("{}{}{}", styles.dim, text, RESET) } else { text }
+}
+
+/// `--grep`'s share of the input: matches rendered as usual, the context
+/// around them too but dimmed, groups apart from each other split by `--`.
+fn write_grep_lines(
+    out: &mut dyn Write,
+    shown: Vec<Shown>,
+    hl: &Highlighter,
+    state: &mut ReaderState,
+    line_offset: &std::cell::Cell<u64>,
+    novel: &std::cell::Cell<Option<bool>>,
+) -> io::Result<()> {
+    // lines grep leaves out are judged new or not once they do show
+    for item in shown {
+        match item {
+            Shown::Separator => {
+                novel.set(None);
+                hl.loaded().styles.paint(out, &hl.loaded().styles.dim, "--")?;
+                writeln!(out)?;
+            }
+            Shown::Context(line, offset) => {
+                line_offset.set(offset);
+                novel.set(judge_novelty(&line, hl));
+                let mut spans = hl.spans(&line);
+                for span in &mut spans {
+                    span.style.attrs.insert(0, DIM);
+                }
+                write_spans(out, &line, &spans, &hl.loaded().styles)?;
+                writeln!(out)?;
+            }
+            Shown::Match(line, offset) => {
+                line_offset.set(offset);
+                novel.set(judge_novelty(&line, hl));
+                process_line(out, line, hl, state)?;
+            }
+        }
+    }
+    novel.set(None);
+    Ok(())
+}
+
+/// render everything `r` has to `out`.
+fn process_reader<R: Read>(r: R, hl: &Highlighter, out: &mut dyn Write) -> io::Result<Summary> {
+    let mut reader = BufReader::with_capacity(DETECT_SAMPLE_BYTES, r);
+    let (skipped, skipped_bytes) = match &hl.lines {
+        Some(range) => skip_lines(&mut reader, range.skipped())?,
+        None => (0, 0),
+    };
+    let detected = match hl.format {
+        InputFormat::Auto => reader.fill_buf().ok().map(|sample| detect_format(sample).chosen),
+        _ => None,
+    };
+    let lines = RawLines::new(reader, hl.max_line_bytes).flat_map(|line| match line {
+        Ok(line) if hl.split_interleaved => split_interleaved(line).into_iter().map(Ok).collect(),
+        line => vec![line],
+    });
+    let lines = SortBuffer::new(lines, hl.sort_buffer);
+    let mut summary = Summary::default();
+    let mut tail = TailFiles::default();
+    if hl.quiet > 0 {
+        // nothing renders, so at most the lines get counted
+        let mut line_no = skipped;
+        for maybe_line in lines {
+            match maybe_line {
+                Ok(line) if !line.continued => {
+                    line_no += 1;
+                    if let Some(range) = &hl.lines {
+                        if range.last_read().is_some_and(|last| line_no > last) {
+                            break;
+                        }
+                        if !range.contains(line_no) {
+                            continue;
+                        }
+                    }
+                    if let Some(path) = tail_header(&line.text).filter(|_| hl.tail_headers) {
+                        tail.switch(path, &mut ReaderState::default());
+                        continue;
+                    }
+                    if hl.summaries {
+                        record_line(&mut summary, &line.text, hl);
+                        tail.record(&line.text, hl);
+                    }
+                    let newline = !(hl.preserve_eol && line.unterminated);
+                    if let Err(e) = copy_line(&line.text, hl, newline) {
+                        eprintln!("{}", e);
+                        break;
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("read error: {}", e);
+                    summary.read_failed = true;
+                    break;
+                }
+            }
+        }
+        summary.tail_files = tail.into_summaries();
+        return Ok(summary);
+    }
+    // lines the way they came: bytes that aren't UTF-8 as they were, and
+    // `\r\n` where the input had it
+    let mut raw_bytes = RawBytes::new(out);
+    let crlf_line = std::cell::Cell::new(false);
+    let mut kept_crlf = KeptCrlf::new(&mut raw_bytes, &crlf_line);
+    let out: &mut dyn Write = &mut kept_crlf;
+    // with `--preserve-eol`, the input's last line came without a newline
+    let last_unterminated = std::cell::Cell::new(false);
+    let mut held_newline;
+    let out: &mut dyn Write = match hl.preserve_eol {
+        true => {
+            held_newline = HeldNewline::new(out, &last_unterminated);
+            &mut held_newline
+        }
+        false => out,
+    };
+    let mut whole_lines;
+    let squeeze_ticks = hl.squeeze.as_ref().is_some_and(|window| window.live.is_some());
+    let handle: &mut dyn Write = match hl.marks || squeeze_ticks {
+        true => {
+            // a line at a time, so a mark from the key reader or a count
+            // from the squeeze ticker can't land mid-line
+            whole_lines = LineWriter::new(out, |line: &str| Some(line.to_string()));
+            &mut whole_lines
+        }
+        false => out,
+    };
+    let mut marked;
+    let handle: &mut dyn Write = match &hl.jump_marks {
+        Some(marks) => {
+            marked = LineWriter::new(handle, |line: &str| Some(marks.apply(line, &hl.infer)));
+            &mut marked
+        }
+        None => handle,
+    };
+    let mut wrapped;
+    let handle: &mut dyn Write = match hl.wrap {
+        true => {
+            let marker = wrap_marker(&hl.loaded().styles);
+            wrapped = LineWriter::new(handle, move |line: &str| {
+                Some(wrap_styled_line(line, hl.width.get(), &marker, 2))
+            });
+            &mut wrapped
+        }
+        false => handle,
+    };
+    // the level of the line being written, for `--gutter`
+    let margin = std::cell::Cell::new(None);
+    let mut margined;
+    let handle: &mut dyn Write = match &hl.gutter {
+        Some(gutter) => {
+            margined = LineWriter::new(handle, |line: &str| {
+                Some(format!("{}{}", gutter.mark(margin.take()), line))
+            });
+            &mut margined
+        }
+        None => handle,
+    };
+    // offset of the oldest input line behind what is being written
+    let line_offset = std::cell::Cell::new(0);
+    // where each recent record started and its line number, for `--number`
+    let record_numbers = std::cell::RefCell::new(std::collections::VecDeque::new());
+    let mut numbered;
+    let handle: &mut dyn Write = match hl.number {
+        true => {
+            numbered = LineWriter::new(handle, |line: &str| {
+                let numbers = record_numbers.borrow();
+                let at = line_offset.get();
+                let i = numbers.partition_point(|&(start, _)| start <= at);
+                let number = i.checked_sub(1).map_or(0, |i| numbers[i].1);
+                Some(number_prefix(number, &hl.loaded().styles) + line)
+            });
+            &mut numbered
+        }
+        false => handle,
+    };
+    let mut offsets;
+    let handle: &mut dyn Write = match hl.byte_offsets {
+        true => {
+            offsets = LineWriter::new(handle, |line: &str| {
+                Some(offset_prefix(line_offset.get(), &hl.loaded().styles) + line)
+            });
+            &mut offsets
+        }
+        false => handle,
+    };
+    // whether the input line being written is new, for `--highlight-new`
+    let novel = std::cell::Cell::new(None);
+    let mut gutter;
+    let handle: &mut dyn Write = match hl.novelty {
+        Some(_) => {
+            gutter = LineWriter::new(handle, |line: &str| {
+                Some(novelty_gutter(line, novel.get(), &hl.loaded().styles))
+            });
+            &mut gutter
+        }
+        None => handle,
+    };
+    // whether the input line being written was spliced into the one before
+    let spliced_mark = std::cell::Cell::new(false);
+    let mut marked_splices;
+    let handle: &mut dyn Write = match hl.split_interleaved {
+        true => {
+            let mark = {
+                let mut mark = Vec::new();
+                // writing into a Vec can't fail
+                let _ = hl.loaded().styles.paint(&mut mark, &hl.loaded().styles.dim, "\u{269f} ");
+                String::from_utf8(mark).unwrap_or_default()
+            };
+            let spliced = &spliced_mark;
+            marked_splices = LineWriter::new(handle, move |line: &str| {
+                Some(if spliced.get() { format!("{}{}", mark, line) } else { line.to_string() })
+            });
+            &mut marked_splices
+        }
+        false => handle,
+    };
+    // whether the input line being written came after later ones had gone out
+    let late_mark = std::cell::Cell::new(false);
+    let mut marked_late;
+    let handle: &mut dyn Write = match hl.sort_buffer {
+        Some(_) => {
+            let mark = {
+                let mut mark = Vec::new();
+                // writing into a Vec can't fail
+                let _ = hl.loaded().styles.paint(&mut mark, &hl.loaded().styles.dim, "late ");
+                String::from_utf8(mark).unwrap_or_default()
+            };
+            let late = &late_mark;
+            marked_late = LineWriter::new(handle, move |line: &str| {
+                Some(if late.get() { format!("{}{}", mark, line) } else { line.to_string() })
+            });
+            &mut marked_late
+        }
+        None => handle,
+    };
+    let mut shown_invisibles;
+    let handle: &mut dyn Write = match hl.invisibles.active() {
+        true => {
+            shown_invisibles = LineWriter::new(handle, |line: &str| {
+                Some(hl.invisibles.apply(line, &hl.loaded().styles))
+            });
+            &mut shown_invisibles
+        }
+        false => handle,
+    };
+    let mut dumps;
+    let handle: &mut dyn Write = match &hl.hex {
+        Some(hex) => {
+            dumps =
+                LineWriter::new(handle, |line: &str| Some(hex.apply(line, &hl.loaded().styles)));
+            &mut dumps
+        }
+        None => handle,
+    };
+    let mut tracked;
+    let handle: &mut dyn Write = match &hl.tracking {
+        Some(tracking) => {
+            tracked = LineWriter::new(handle, |line: &str| {
+                Some(tracking.apply(line, &hl.loaded().styles))
+            });
+            &mut tracked
+        }
+        None => handle,
+    };
+    let mut ruled;
+    let handle: &mut dyn Write = match hl.settings.has_rules() {
+        true => {
+            ruled = LineWriter::new(handle, |line: &str| {
+                let loaded = hl.loaded();
+                let kept = apply_rules(line, &loaded.rules, &loaded.styles);
+                if kept.is_none() {
+                    audited(hl, Suppressor::Rules, 1);
+                }
+                kept
+            });
+            &mut ruled
+        }
+        false => handle,
+    };
+    let mut emphasized;
+    let handle: &mut dyn Write = match &hl.grep {
+        Some(grep) if hl.loaded().styles.enabled() => {
+            emphasized = LineWriter::new(handle, |line: &str| Some(grep.emphasize(line)));
+            &mut emphasized
+        }
+        _ => handle,
+    };
+    // the input line being written is an error during an `--alert-rate` alert
+    let alarmed = std::cell::Cell::new(false);
+    let mut alarmed_lines;
+    let handle: &mut dyn Write = match hl.alert {
+        Some(_) if hl.loaded().styles.enabled() => {
+            let sgr = format!("{}{}", BOLD, hl.loaded().styles.color(Level::Error));
+            let alarmed = &alarmed;
+            alarmed_lines = LineWriter::new(handle, move |line: &str| {
+                Some(match alarmed.get() {
+                    true => restyle(line, Vec::new(), &sgr),
+                    false => line.to_string(),
+                })
+            });
+            &mut alarmed_lines
+        }
+        _ => handle,
+    };
+    let mut tinted;
+    let handle: &mut dyn Write = match hl.whole_line {
+        true if hl.loaded().styles.enabled() => {
+            tinted = LineWriter::new(handle, |line: &str| {
+                // ends in a reset whatever escapes the line brought along
+                Some(match level_of(&visible_text(line), hl) {
+                    Some(level) => restyle(line, Vec::new(), hl.loaded().styles.color(level)),
+                    None => line.to_string(),
+                })
+            });
+            &mut tinted
+        }
+        _ => handle,
+    };
+    // first to see each line as written, before any prefix
+    let mut judged;
+    let handle: &mut dyn Write = match hl.gutter {
+        Some(_) => {
+            judged = LineWriter::new(handle, |line: &str| {
+                margin.set(level_of(&visible_text(line), hl));
+                Some(line.to_string())
+            });
+            &mut judged
+        }
+        None => handle,
+    };
+    let mut explained;
+    let handle: &mut dyn Write = match hl.explain {
+        true => {
+            explained = LineWriter::new(handle, |line: &str| {
+                let text = visible_text(line);
+                if text.trim().is_empty() {
+                    return Some(line.to_string());
+                }
+                let mut note = Vec::new();
+                let why = format!("  \u{21b3} {}", explain_level(&text, &hl.infer));
+                // writing into a Vec can't fail
+                let _ = hl.loaded().styles.paint(&mut note, &hl.loaded().styles.dim, &why);
+                Some(format!("{}\n{}", line, String::from_utf8_lossy(&note)))
+            });
+            &mut explained
+        }
+        false => handle,
+    };
+    // the last of a line's way, once every filter has let it through
+    let write_admitted = |handle: &mut dyn Write,
+                          line: String,
+                          record_start: u64,
+                          state: &mut ReaderState,
+                          context: &mut Option<ContextWindow>| {
+        match (&hl.grep, context) {
+            (Some(grep), Some(window)) => {
+                let matched = grep.matcher.find(&line).is_some();
+                let shown = window.push(line, record_start, matched);
+                write_grep_lines(handle, shown, hl, state, &line_offset, &novel)
+            }
+            _ => {
+                novel.set(judge_novelty(&line, hl));
+                let written = process_line(handle, line, hl, state);
+                novel.set(None);
+                written
+            }
+        }
+    };
+    let mut state = ReaderState::default();
+    let mut context = hl.grep.as_ref().map(ContextWindow::new);
+    state.detected = detected.filter(|&format| format != InputFormat::Auto);
+    let mut elision = hl.elide_below.map(Elision::new);
+    let mut throttle = hl.throttle.map(ThrottleWindow::new);
+    let mut dedup = hl.dedup.map(Dedup::new);
+    let squeeze = hl.squeeze.as_ref().map(|window| match &window.live {
+        Some(live) => live.clone(),
+        None => std::sync::Arc::new(std::sync::Mutex::new(Squeeze::new(window.size))),
+    });
+    let squeezing = || squeeze.as_ref().map(|s| s.lock().unwrap_or_else(|e| e.into_inner()));
+    let mut budget = hl.budget.map(BudgetWindow::new);
+    // the last line was left out by `hl.levels` or `hl.since`
+    let mut filtered = false;
+    let mut too_old = false;
+    let (mut offset, mut record_start) = (skipped_bytes, skipped_bytes);
+    let mut line_no = skipped;
+    // the line being read is one of `--lines`'s dimmed ones
+    let mut padding = false;
+    let cut_marker = {
+        let mut marker = Vec::new();
+        // writing into a Vec can't fail
+        let _ = hl.loaded().styles.paint(&mut marker, &hl.loaded().styles.dim, "\u{2938} ");
+        String::from_utf8(marker).unwrap_or_default()
+    };
+
+    for mut maybe_line in lines {
+        if let Ok(line) = maybe_line.as_mut() {
+            if !hl.keep_ansi && line.text.contains('\x1b') {
+                line.text = strip_escapes(&line.text);
+            }
+        }
+        if let Ok(line) = &maybe_line {
+            last_unterminated.set(hl.preserve_eol && line.unterminated);
+            crlf_line.set(line.crlf);
+        }
+        match maybe_line {
+            Ok(RawLine { text, consumed, continued: true, .. }) => {
+                line_offset.set(offset);
+                offset += consumed as u64;
+                if padding
+                    || context.as_ref().is_some_and(|window| !window.showing())
+                    || elision.as_ref().is_some_and(|elision| elision.dropping)
+                    || throttle.as_ref().is_some_and(|window| !window.showing)
+                    || dedup.as_ref().is_some_and(|dedup| !dedup.showing)
+                    || squeezing().is_some_and(|squeeze| !squeeze.showing)
+                    || budget.as_ref().is_some_and(|window| !window.showing)
+                    || filtered
+                {
+                    continue;
+                }
+                if let Some(window) = budget.as_mut().filter(|window| window.holding()) {
+                    window.hold(None, HeldLine::Cut(text));
+                    continue;
+                }
+                // the rest of an over-long line: no level to find in it
+                let written = match state.json_lines.is_empty() {
+                    true => Ok(()),
+                    false => flush_json_record(handle, hl, &mut state),
+                };
+                let written = written.and_then(|_| writeln!(handle, "{}{}", cut_marker, text));
+                if let Err(e) = written {
+                    write_failed(e)?;
+                    break;
+                }
+            }
+            Ok(RawLine {
+                text: line,
+                consumed,
+                continued: false,
+                spliced,
+                unterminated,
+                late,
+                ..
+            }) => {
+                if let Some(reloaded) = hl.settings.reload_if_asked() {
+                    if let Err(e) = write_reload_notice(handle, reloaded, &hl.loaded().styles) {
+                        write_failed(e)?;
+                        break;
+                    }
+                }
+                // lines held back until a record is complete come out
+                // labeled with where the record began
+                let held = state.jul_header.is_some()
+                    || !state.json_lines.is_empty()
+                    || !state.export.fields.is_empty();
+                if !held {
+                    record_start = offset;
+                }
+                line_no += 1;
+                if hl.number {
+                    let mut numbers = record_numbers.borrow_mut();
+                    let holding = budget.as_ref().is_some_and(BudgetWindow::holding);
+                    if numbers.len() >= 2 * NUMBER_LOOKBACK && !holding {
+                        numbers.drain(..NUMBER_LOOKBACK);
+                    }
+                    numbers.push_back((offset, line_no));
+                }
+                line_offset.set(record_start);
+                if let Some(range) = &hl.lines {
+                    if range.last_read().is_some_and(|last| line_no > last) {
+                        break;
+                    }
+                    padding = !range.contains(line_no);
+                    if padding {
+                        line_offset.set(offset);
+                        offset += consumed as u64;
+                        let written =
+                            hl.loaded().styles.paint(handle, &hl.loaded().styles.dim, &line);
+                        if let Err(e) = written.and_then(|_| writeln!(handle)) {
+                            write_failed(e)?;
+                            break;
+                        }
+                        continue;
+                    }
+                }
+                offset += consumed as u64;
+                if let Some(path) = tail_header(&line).filter(|_| hl.tail_headers) {
+                    // what is held back belongs to the file before
+                    let written = finish_reader(handle, hl, &mut state)
+                        .and_then(|_| write_tail_header(handle, path, &hl.loaded().styles));
+                    tail.switch(path, &mut state);
+                    if let Err(e) = written {
+                        write_failed(e)?;
+                        break;
+                    }
+                    continue;
+                }
+                if hl.summaries {
+                    record_line(&mut summary, &line, hl);
+                    tail.record(&line, hl);
+                }
+                if let Err(e) = copy_line(&line, hl, !(hl.preserve_eol && unterminated)) {
+                    eprintln!("{}", e);
+                    break;
+                }
+                let leveled = hl.levels != LevelFilter::default()
+                    || elision.is_some()
+                    || throttle.is_some()
+                    || dedup.is_some()
+                    || budget.is_some()
+                    || hl.alert.is_some();
+                let level = match leveled {
+                    true => level_of(&line, hl),
+                    false => None,
+                };
+                if let Some(window) = &hl.since {
+                    // lines without a timestamp go with the line before
+                    if let Some(keep) = window.keeps(&line, wall_clock()) {
+                        too_old = !keep;
+                    }
+                }
+                let location = match hl.sources.is_empty() {
+                    true => None,
+                    false => source_location(&line, hl.format),
+                };
+                filtered = too_old
+                    || !hl.levels.keeps(level)
+                    || !hl.sources.keeps(&line, location.as_ref());
+                if filtered {
+                    let by = match (too_old, hl.levels.keeps(level)) {
+                        (true, _) => Suppressor::Since,
+                        (false, false) => hl.levels.dropped_by(level),
+                        (false, true) => Suppressor::Source,
+                    };
+                    audited(hl, by, 1);
+                    continue;
+                }
+                if let Some(alert) = &hl.alert {
+                    let change = alert.observe(level, Instant::now());
+                    let written = match change {
+                        Some(change) => {
+                            alert.write_change(handle, change, hl.width.get(), &hl.loaded().styles)
+                        }
+                        None => Ok(()),
+                    };
+                    if let Err(e) = written {
+                        write_failed(e)?;
+                        break;
+                    }
+                    alarmed.set(level >= Some(Level::Error) && alert.alerting());
+                }
+                if let Some(elision) = &mut elision {
+                    if elision.drops(level) {
+                        audited(hl, Suppressor::ElideBelow, 1);
+                        continue;
+                    }
+                }
+                let gap = elision.as_mut().and_then(Elision::take_gap);
+                let mut written = put_notice(handle, gap, &mut budget, &hl.loaded().styles);
+                if let Some(mut squeeze) = squeezing() {
+                    let (count, admitted) = squeeze.admit(&line, Instant::now());
+                    written = written
+                        .and_then(|_| put_notice(handle, count, &mut budget, &hl.loaded().styles));
+                    if !admitted {
+                        audited(hl, Suppressor::Squeeze, 1);
+                        if let Err(e) = written {
+                            write_failed(e)?;
+                            break;
+                        }
+                        continue;
+                    }
+                }
+                if let Some(dedup) = &mut dedup {
+                    let (rollup, admitted) = dedup.admit(&line, level, Instant::now());
+                    written = written
+                        .and_then(|_| put_notice(handle, rollup, &mut budget, &hl.loaded().styles));
+                    if !admitted {
+                        audited(hl, Suppressor::Dedup, 1);
+                        if let Err(e) = written {
+                            write_failed(e)?;
+                            break;
+                        }
+                        continue;
+                    }
+                }
+                if let Some(window) = &mut throttle {
+                    let (notice, admitted) = window.admit(level, Instant::now());
+                    written = written
+                        .and_then(|_| put_notice(handle, notice, &mut budget, &hl.loaded().styles));
+                    if !admitted {
+                        audited(hl, Suppressor::Throttle, 1);
+                        if let Err(e) = written {
+                            write_failed(e)?;
+                            break;
+                        }
+                        continue;
+                    }
+                }
+                if let Some(window) = &mut budget {
+                    if window.holding() {
+                        window.hold(level, HeldLine::Line(line, record_start));
+                        if let Err(e) = written {
+                            write_failed(e)?;
+                            break;
+                        }
+                        continue;
+                    }
+                    let (notice, admitted) = window.admit(level);
+                    written =
+                        written.and_then(|_| write_notice(handle, notice, &hl.loaded().styles));
+                    if !admitted {
+                        audited(hl, Suppressor::Budget, 1);
+                        if let Err(e) = written {
+                            write_failed(e)?;
+                            break;
+                        }
+                        continue;
+                    }
+                }
+                spliced_mark.set(spliced);
+                late_mark.set(late);
+                let written = written.and_then(|_| {
+                    write_admitted(handle, line, record_start, &mut state, &mut context)
+                });
+                spliced_mark.set(false);
+                late_mark.set(false);
+                if let Err(e) = written {
+                    write_failed(e)?;
+                    break;
+                }
+            }
+            Err(e) => {
+                eprintln!("read error: {}", e);
+                summary.read_failed = true;
+                break;
+            }
+        }
+    }
+    if let Some(window) = &context {
+        audited(hl, Suppressor::Grep, window.line_no - window.shown);
+    }
+    let gap = elision.as_mut().and_then(Elision::take_gap);
+    let suppressed = throttle.as_mut().and_then(ThrottleWindow::take_notice);
+    let rollup = dedup.as_mut().and_then(Dedup::take_rollup);
+    // a followed file's run goes on into its next batch
+    let squeezed = match hl.squeeze.as_ref().is_some_and(|window| window.following) {
+        true => None,
+        false => squeezing().and_then(|mut squeeze| squeeze.take_notice()),
+    };
+    let mut finished = Ok(());
+    let mut budget_notice = None;
+    if let Some(window) = budget.as_mut().filter(|window| window.holding()) {
+        audited(hl, Suppressor::Budget, window.left_out());
+        // what the budget kept, in order, before the notices for the end
+        for line in window.release() {
+            finished = finished.and_then(|_| match line {
+                HeldLine::Line(line, start) => {
+                    line_offset.set(start);
+                    write_admitted(handle, line, start, &mut state, &mut context)
+                }
+                HeldLine::Cut(text) => {
+                    let flushed = match state.json_lines.is_empty() {
+                        true => Ok(()),
+                        false => flush_json_record(handle, hl, &mut state),
+                    };
+                    flushed.and_then(|_| writeln!(handle, "{}{}", cut_marker, text))
+                }
+                HeldLine::Notice(notice) => write_notice(handle, Some(notice), &hl.loaded().styles),
+            });
+        }
+    } else {
+        budget_notice = budget.as_mut().and_then(BudgetWindow::take_notice);
+    }
+    let finished = finished
+        .and_then(|_| finish_reader(handle, hl, &mut state))
+        .and_then(|_| write_notice(handle, squeezed, &hl.loaded().styles))
+        .and_then(|_| write_notice(handle, gap, &hl.loaded().styles))
+        .and_then(|_| write_notice(handle, rollup, &hl.loaded().styles))
+        .and_then(|_| write_notice(handle, suppressed, &hl.loaded().styles))
+        .and_then(|_| write_notice(handle, budget_notice, &hl.loaded().styles));
+    if let Err(e) = finished {
+        write_failed(e)?;
+    }
+    summary.tail_files = tail.into_summaries();
+    Ok(summary)
+}
+
+/// the `--split-dir` file lines of `level` go to.
+fn split_file_name(level: Level) -> &'static str {
+    match level {
+        Level::Fatal | Level::Error => "errors.log",
+        Level::Warn => "warnings.log",
+        Level::Info | Level::Http => "info.log",
+        Level::Debug | Level::Trace | Level::Silly => "debug.log",
+    }
+}
+
+/// one of the `--split-dir` files, open for appending.
+struct SplitFile {
+    name: &'static str,
+    file: File,
+    size: u64,
+}
+
+/// `--split-dir`: a plain copy of every input line in a file for its level,
+/// rotated by size with `--split-max-size`.
+struct SplitDir {
+    dir: PathBuf,
+    max_size: Option<u64>,
+    /// how many rotated files (`errors.log.1` and on) to keep.
+    keep: usize,
+    files: Vec<SplitFile>,
+    /// where the last line with a level went; lines without one, like a
+    /// stack trace's, follow it. `other.log` until there has been one.
+    last: &'static str,
+}
+
+/// `--split-keep`'s default.
+#[doc(hidden)]
+pub const DEFAULT_SPLIT_KEEP: usize = 5;
+
+impl SplitDir {
+    fn new(dir: PathBuf, max_size: Option<u64>, keep: usize) -> io::Result<SplitDir> {
+        std::fs::create_dir_all(&dir)?;
+        Ok(SplitDir { dir, max_size, keep, files: Vec::new(), last: "other.log" })
+    }
+
+    fn open(&self, name: &str) -> io::Result<File> {
+        std::fs::OpenOptions::new().create(true).append(true).open(self.dir.join(name))
+    }
+
+    fn write(&mut self, level: Option<Level>, line: &str, newline: bool) -> io::Result<()> {
+        if let Some(level) = level {
+            self.last = split_file_name(level);
+        }
+        let i = match self.files.iter().position(|f| f.name == self.last) {
+            Some(i) => i,
+            None => {
+                let file = self.open(self.last)?;
+                let size = file.metadata()?.len();
+                self.files.push(SplitFile { name: self.last, file, size });
+                self.files.len() - 1
+            }
+        };
+        let record = match newline {
+            true => format!("{}\n", line),
+            false => line.to_string(),
+        };
+        let len = record.len() as u64;
+        if self.max_size.is_some_and(|max| self.files[i].size > 0 && self.files[i].size + len > max)
+        {
+            self.rotate(i)?;
+        }
+        // one write per line, so nothing lands half in one file and half in the next
+        RawBytes::new(&mut self.files[i].file).write_all(record.as_bytes())?;
+        self.files[i].size += len;
+        Ok(())
+    }
+
+    /// `errors.log` becomes `errors.log.1`, `.1` becomes `.2` and so on, the
+    /// oldest past `keep` going away, and writing carries on in a new file.
+    /// each step is a rename, so every line is in exactly one of them.
+    fn rotate(&mut self, i: usize) -> io::Result<()> {
+        let name = self.files[i].name;
+        let numbered = |n: usize| self.dir.join(format!("{}.{}", name, n));
+        match self.keep {
+            0 => std::fs::remove_file(self.dir.join(name))?,
+            keep => {
+                if numbered(keep).exists() {
+                    std::fs::remove_file(numbered(keep))?;
+                }
+                for n in (1..keep).rev() {
+                    if numbered(n).exists() {
+                        std::fs::rename(numbered(n), numbered(n + 1))?;
+                    }
+                }
+                std::fs::rename(self.dir.join(name), numbered(1))?;
+            }
+        }
+        self.files[i].file = self.open(name)?;
+        self.files[i].size = 0;
+        Ok(())
+    }
+}
+
+/// `--snapshot`: the last few lines at or above a level, kept in a file
+/// that is rewritten every so often for something else to read.
+struct Snapshot {
+    path: PathBuf,
+    lines: usize,
+    level: Level,
+    recent: std::collections::VecDeque<String>,
+    /// lines have come in since the file was last written.
+    dirty: bool,
+}
+
+/// the `--snapshot-*` defaults.
+#[doc(hidden)]
+pub const DEFAULT_SNAPSHOT_LINES: usize = 50;
+#[doc(hidden)]
+pub const DEFAULT_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(5);
+
+impl Snapshot {
+    fn new(path: PathBuf, lines: usize, level: Level) -> Snapshot {
+        Snapshot { path, lines, level, recent: std::collections::VecDeque::new(), dirty: true }
+    }
+
+    fn push(&mut self, level: Option<Level>, line: &str) {
+        if level.is_some_and(|level| level >= self.level) {
+            if self.recent.len() == self.lines {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(line.to_string());
+            self.dirty = true;
+        }
+    }
+
+    /// write the lines to a temp file next to the snapshot and rename it
+    /// over it, so a reader gets the old file or the new one, whole.
+    fn write(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        let name = self.path.file_name().map_or("snapshot".into(), |n| n.to_string_lossy());
+        let temp = self.path.with_file_name(format!(".{}.{}.tmp", name, std::process::id()));
+        let written = File::create(&temp).and_then(|mut file| {
+            self.recent.iter().try_for_each(|line| writeln!(file, "{}", line))?;
+            file.sync_all()
+        });
+        if let Err(e) = written.and_then(|_| std::fs::rename(&temp, &self.path)) {
+            let _ = std::fs::remove_file(&temp);
+            return Err(e);
+        }
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+/// keep rewriting the snapshot every `interval`, for as long as we run.
+fn start_snapshots(snapshot: std::sync::Arc<std::sync::Mutex<Snapshot>>, interval: Duration) {
+    thread::spawn(move || {
+        loop {
+            thread::sleep(interval);
+            let mut snapshot = snapshot.lock().unwrap_or_else(|e| e.into_inner());
+            if let Err(e) = snapshot.write() {
+                eprintln!("failed to write snapshot {}: {}", snapshot.path.display(), e);
+            }
+        }
+    });
+}
+
+/// what one input has been read of, for `--metrics-listen`.
+struct InputCounters {
+    /// indexed by `Level as usize`, and one past them for lines without.
+    lines: [std::sync::atomic::AtomicU64; Level::ALL.len() + 1],
+    bytes: std::sync::atomic::AtomicU64,
+    /// the last timestamp read, as the bits of its seconds; NaN for none.
+    last_timestamp: std::sync::atomic::AtomicU64,
+}
+
+impl InputCounters {
+    fn new() -> InputCounters {
+        InputCounters {
+            lines: Default::default(),
+            bytes: Default::default(),
+            last_timestamp: std::sync::atomic::AtomicU64::new(f64::NAN.to_bits()),
+        }
+    }
+
+    fn record(&self, line: &str, hl: &Highlighter) {
+        use std::sync::atomic::Ordering::Relaxed;
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let class = level_of(line, hl).map_or(Level::ALL.len(), |level| level as usize);
+        self.lines[class].fetch_add(1, Relaxed);
+        if let Some(secs) = line_timestamp(line) {
+            self.last_timestamp.store(secs.to_bits(), Relaxed);
+        }
+    }
+}
+
+/// `--metrics-listen`: the counters of every input read so far, in the
+/// order they were first read.
+#[derive(Default)]
+struct Metrics {
+    inputs: std::sync::Mutex<Vec<(String, std::sync::Arc<InputCounters>)>>,
+}
+
+impl Metrics {
+    /// the counters for `name`, new ones the first time it's read.
+    fn input(&self, name: &str) -> std::sync::Arc<InputCounters> {
+        let mut inputs = self.inputs.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some((_, counters)) = inputs.iter().find(|(known, _)| known == name) {
+            return counters.clone();
+        }
+        let counters = std::sync::Arc::new(InputCounters::new());
+        inputs.push((name.to_string(), counters.clone()));
+        counters
+    }
+
+    /// the lines of each level read from all inputs so far, for `--stats`
+    /// cut short.
+    fn summary(&self) -> Summary {
+        use std::sync::atomic::Ordering::Relaxed;
+        let mut summary = Summary::default();
+        for (_, counters) in self.inputs.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            let counts = counters.lines.iter().map(|n| n.load(Relaxed) as usize);
+            for (i, n) in counts.enumerate() {
+                summary.lines += n;
+                if let Some(level) = summary.levels.get_mut(i) {
+                    *level += n;
+                }
+            }
+        }
+        summary
+    }
+
+    /// for `--audit`: the lines of each level and bytes read from each input.
+    fn to_json(&self) -> Json {
+        use std::sync::atomic::Ordering::Relaxed;
+        let inputs = self.inputs.lock().unwrap_or_else(|e| e.into_inner());
+        let inputs = inputs.iter().map(|(name, counters)| {
+            let levels = Level::ALL.iter().map(|level| level.name().to_ascii_lowercase());
+            let lines = levels
+                .chain(["none".to_string()])
+                .zip(&counters.lines)
+                .map(|(level, n)| (level, Json::Number(n.load(Relaxed).to_string())))
+                .collect();
+            Json::Object(vec![
+                ("input".to_string(), Json::String(name.clone())),
+                ("bytes".to_string(), Json::Number(counters.bytes.load(Relaxed).to_string())),
+                ("lines".to_string(), Json::Object(lines)),
+            ])
+        });
+        Json::Array(inputs.collect())
+    }
+
+    /// the Prometheus text format.
+    fn render(&self) -> String {
+        use std::sync::atomic::Ordering::Relaxed;
+        let inputs = self.inputs.lock().unwrap_or_else(|e| e.into_inner());
+        let label =
+            |name: &str| name.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+        let mut out = String::new();
+        out.push_str("# HELP logcolor_lines_total Lines read, by level.\n");
+        out.push_str("# TYPE logcolor_lines_total counter\n");
+        for (name, counters) in inputs.iter() {
+            let levels = Level::ALL.iter().map(|level| level.name().to_ascii_lowercase());
+            for (level, n) in levels.chain(["none".to_string()]).zip(&counters.lines) {
+                out.push_str(&format!(
+                    "logcolor_lines_total{{input=\"{}\",level=\"{}\"}} {}\n",
+                    label(name),
+                    level,
+                    n.load(Relaxed)
+                ));
+            }
+        }
+        out.push_str("# HELP logcolor_bytes_read_total Bytes read.\n");
+        out.push_str("# TYPE logcolor_bytes_read_total counter\n");
+        for (name, counters) in inputs.iter() {
+            let bytes = counters.bytes.load(Relaxed);
+            out.push_str(&format!(
+                "logcolor_bytes_read_total{{input=\"{}\"}} {}\n",
+                label(name),
+                bytes
+            ));
+        }
+        out.push_str("# HELP logcolor_last_timestamp_seconds The last timestamp read.\n");
+        out.push_str("# TYPE logcolor_last_timestamp_seconds gauge\n");
+        for (name, counters) in inputs.iter() {
+            let secs = f64::from_bits(counters.last_timestamp.load(Relaxed));
+            if !secs.is_nan() {
+                out.push_str(&format!(
+                    "logcolor_last_timestamp_seconds{{input=\"{}\"}} {}\n",
+                    label(name),
+                    secs
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// how often `--audit` appends what it has counted so far, so a session
+/// killed outright still leaves most of its story behind.
+const AUDIT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// what left a line out, as `--audit` counts it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Suppressor {
+    MaxLevel,
+    /// `--min-level`, and the lines `--drop-unknown` leaves out.
+    MinLevel,
+    Since,
+    /// `--module`, `--module-exclude` and `--file`.
+    Source,
+    Grep,
+    ElideBelow,
+    Dedup,
+    Squeeze,
+    Throttle,
+    /// held back inputs count records, a line with a level and the ones
+    /// after it.
+    Budget,
+    /// a `--rules` `suppress`.
+    Rules,
+}
+
+impl Suppressor {
+    const ALL: [Suppressor; 11] = [
+        Suppressor::MaxLevel,
+        Suppressor::MinLevel,
+        Suppressor::Since,
+        Suppressor::Source,
+        Suppressor::Grep,
+        Suppressor::ElideBelow,
+        Suppressor::Dedup,
+        Suppressor::Squeeze,
+        Suppressor::Throttle,
+        Suppressor::Budget,
+        Suppressor::Rules,
+    ];
+
+    fn name(self) -> &'static str {
+        match self {
+            Suppressor::MaxLevel => "max_level",
+            Suppressor::MinLevel => "min_level",
+            Suppressor::Since => "since",
+            Suppressor::Source => "source",
+            Suppressor::Grep => "grep",
+            Suppressor::ElideBelow => "elide_below",
+            Suppressor::Dedup => "dedup",
+            Suppressor::Squeeze => "squeeze",
+            Suppressor::Throttle => "throttle",
+            Suppressor::Budget => "budget",
+            Suppressor::Rules => "rules",
+        }
+    }
+}
+
+/// `--audit`: a JSON lines record of the session, appended to a file. a
+/// `start` record with the configuration, a `progress` record every
+/// `AUDIT_INTERVAL` and an `end` record however the session ends. the lines
+/// themselves only bump counters; the writing is done off to the side.
+struct Audit {
+    file: std::sync::Mutex<File>,
+    started: f64,
+    metrics: std::sync::Arc<Metrics>,
+    suppressed: [std::sync::atomic::AtomicU64; Suppressor::ALL.len()],
+    /// the end record is written once, whoever gets there first.
+    ended: std::sync::atomic::AtomicBool,
+}
+
+/// the session's `--audit`, for the ways out of the program that don't
+/// come back through `main`.
+static AUDIT: std::sync::OnceLock<std::sync::Arc<Audit>> = std::sync::OnceLock::new();
+
+impl Audit {
+    fn start(
+        path: &str,
+        config: Json,
+        metrics: std::sync::Arc<Metrics>,
+    ) -> io::Result<std::sync::Arc<Audit>> {
+        let file = std::fs::OpenOptions::new().append(true).create(true).open(path)?;
+        let audit = std::sync::Arc::new(Audit {
+            file: std::sync::Mutex::new(file),
+            started: wall_clock(),
+            metrics,
+            suppressed: Default::default(),
+            ended: Default::default(),
+        });
+        audit.append("start", vec![("config".to_string(), config)])?;
+        let progress = std::sync::Arc::downgrade(&audit);
+        thread::spawn(move || {
+            loop {
+                thread::sleep(AUDIT_INTERVAL);
+                match progress.upgrade() {
+                    Some(audit) if !audit.ended.load(std::sync::atomic::Ordering::Relaxed) => {
+                        if let Err(e) = audit.append("progress", audit.counts()) {
+                            eprintln!("failed to write --audit: {}", e);
+                            return;
+                        }
+                    }
+                    _ => return,
+                }
+            }
+        });
+        Ok(audit)
+    }
+
+    fn suppress(&self, what: Suppressor, lines: usize) {
+        self.suppressed[what as usize]
+            .fetch_add(lines as u64, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    fn counts(&self) -> Vec<(String, Json)> {
+        let suppressed = Suppressor::ALL.iter().zip(&self.suppressed).map(|(what, n)| {
+            let n = n.load(std::sync::atomic::Ordering::Relaxed);
+            (what.name().to_string(), Json::Number(n.to_string()))
+        });
+        vec![
+            ("inputs".to_string(), self.metrics.to_json()),
+            ("suppressed".to_string(), Json::Object(suppressed.collect())),
+        ]
+    }
+
+    /// one record, written in one go so records from several writers don't mix.
+    fn append(&self, event: &str, fields: Vec<(String, Json)>) -> io::Result<()> {
+        let mut record = vec![
+            ("event".to_string(), Json::String(event.to_string())),
+            ("time".to_string(), Json::String(audit_time(wall_clock()))),
+        ];
+        record.extend(fields);
+        let line = format!("{}\n", Json::Object(record));
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        file.write_all(line.as_bytes())
+    }
+
+    /// the `end` record, with how the session ended: `finished`, `failed`
+    /// or `interrupted`.
+    fn end(&self, how: &str) {
+        if self.ended.swap(true, std::sync::atomic::Ordering::Relaxed) {
+            return;
+        }
+        let mut fields = vec![
+            ("started".to_string(), Json::String(audit_time(self.started))),
+            ("exit".to_string(), Json::String(how.to_string())),
+        ];
+        fields.extend(self.counts());
+        if let Err(e) = self.append("end", fields) {
+            eprintln!("failed to write --audit: {}", e);
+        }
+    }
+}
+
+/// seconds since the epoch as `2024-05-01T12:03:04.003Z`.
+fn audit_time(secs: f64) -> String {
+    format_unix_micros((secs * 1e6) as i64).replacen(' ', "T", 1) + "Z"
+}
+
+fn end_audit(how: &str) {
+    if let Some(audit) = AUDIT.get() {
+        audit.end(how);
+    }
+}
+
+/// `--stats` while following a stream, which only Ctrl+C ends: what to
+/// print then.
+struct InterruptedStats {
+    metrics: std::sync::Arc<Metrics>,
+    format: StatsFormat,
+    output: Option<String>,
+    styles: Styles,
+    started: Instant,
+}
+
+static INTERRUPTED_STATS: std::sync::OnceLock<InterruptedStats> = std::sync::OnceLock::new();
+
+/// what Ctrl+C leaves behind before the program goes: the `--stats` so
+/// far and the end of the `--audit`.
+fn end_interrupted() {
+    if let Some(stats) = INTERRUPTED_STATS.get() {
+        let total = stats.metrics.summary();
+        let elapsed = stats.started.elapsed();
+        let written = write_stats(
+            stats.format,
+            stats.output.as_deref(),
+            (&total, &[]),
+            elapsed,
+            &stats.styles,
+        );
+        if let Err(e) = written {
+            eprintln!("failed to write stats: {}", e);
+        }
+    }
+    end_audit("interrupted");
+}
+
+/// count `lines` left out by `what` into the session's `--audit`, if any.
+fn audited(hl: &Highlighter, what: Suppressor, lines: usize) {
+    if let Some(audit) = &hl.audit {
+        audit.suppress(what, lines);
+    }
+}
+
+/// the configuration `--audit` records: the arguments in effect, the
+/// `LOGCOLOR_OPTS` ones first, the config file the level colors come from,
+/// and the environment that can change output.
+fn audit_config(
+    full_args: &[String],
+    config: Option<&Path>,
+    env: impl Fn(&str) -> Option<String>,
+) -> Json {
+    let args = full_args.iter().map(|arg| Json::String(arg.clone()));
+    let config = config.map_or(Json::Null, |path| Json::String(path.display().to_string()));
+    let vars = [DEFAULT_OPTS_VAR, "TERM", "COLORTERM", "NO_COLOR", "COLUMNS", "TZ", "TZDIR"];
+    let env = vars.iter().filter_map(|&var| Some((var.to_string(), Json::String(env(var)?))));
+    Json::Object(vec![
+        ("args".to_string(), Json::Array(args.collect())),
+        ("config_file".to_string(), config),
+        ("env".to_string(), Json::Object(env.collect())),
+    ])
+}
+
+/// an input on its way to `process_reader`, counted into its
+/// `--metrics-listen` counters when there are any.
+struct Metered<'a, R> {
+    inner: R,
+    counters: Option<(std::sync::Arc<InputCounters>, &'a Highlighter)>,
+    /// the start of a line still being read, no longer than a line gets.
+    partial: Vec<u8>,
+}
+
+impl<'a, R: Read> Metered<'a, R> {
+    fn new(inner: R, name: &str, hl: &'a Highlighter) -> Metered<'a, R> {
+        let counters = hl.metrics.as_ref().map(|metrics| (metrics.input(name), hl));
+        Metered { inner, counters, partial: Vec::new() }
+    }
+}
+
+impl<R: Read> Read for Metered<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        let Some((counters, hl)) = &self.counters else {
+            return Ok(n);
+        };
+        counters.bytes.fetch_add(n as u64, std::sync::atomic::Ordering::Relaxed);
+        let mut rest = &buf[..n];
+        loop {
+            let end = rest.iter().position(|&b| b == b'\n');
+            let piece = &rest[..end.unwrap_or(rest.len())];
+            let room = hl.max_line_bytes.saturating_sub(self.partial.len());
+            self.partial.extend_from_slice(&piece[..piece.len().min(room)]);
+            // the last line may not have a newline
+            if end.is_none() && (n > 0 || self.partial.is_empty()) {
+                break;
+            }
+            counters.record(&String::from_utf8_lossy(&self.partial), hl);
+            self.partial.clear();
+            match end {
+                Some(end) => rest = &rest[end + 1..],
+                None => break,
+            }
+        }
+        Ok(n)
+    }
+}
+
+/// how long a `--metrics-listen` client gets to send its request.
+const METRICS_CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// answer one connection: `GET /metrics` with the metrics, anything else
+/// with a 404.
+fn serve_metrics(mut stream: std::net::TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(METRICS_CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(METRICS_CLIENT_TIMEOUT))?;
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    // the request line and headers; a body is never wanted
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        let n = stream.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut words = request.lines().next().unwrap_or_default().split(' ');
+    let path = words.nth(1).map(|target| target.split('?').next().unwrap_or_default());
+    let (status, body) = match (request.starts_with("GET "), path) {
+        (true, Some("/metrics")) => ("200 OK", metrics.render()),
+        _ => ("404 Not Found", "not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// `--metrics-listen`: a thread answering one connection at a time, until
+/// dropped.
+struct MetricsServer {
+    addr: std::net::SocketAddr,
+    stop: std::sync::Arc<std::sync::atomic::AtomicBool>,
+    thread: Option<thread::JoinHandle<()>>,
+}
+
+impl MetricsServer {
+    fn start(addr: &str, metrics: std::sync::Arc<Metrics>) -> io::Result<MetricsServer> {
+        let listener = std::net::TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let stop = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let stopping = stop.clone();
+        let thread = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopping.load(std::sync::atomic::Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let _ = serve_metrics(stream, &metrics);
+                }
+            }
+        });
+        Ok(MetricsServer { addr, stop, thread: Some(thread) })
+    }
+}
+
+impl Drop for MetricsServer {
+    /// wake the accept loop up to see `stop`, and wait for it to finish.
+    fn drop(&mut self) {
+        self.stop.store(true, std::sync::atomic::Ordering::SeqCst);
+        let mut addr = self.addr;
+        if addr.ip().is_unspecified() {
+            addr.set_ip(match addr {
+                std::net::SocketAddr::V4(_) => std::net::Ipv4Addr::LOCALHOST.into(),
+                std::net::SocketAddr::V6(_) => std::net::Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        if std::net::TcpStream::connect(addr).is_ok() {
+            if let Some(thread) = self.thread.take() {
+                let _ = thread.join();
+            }
+        }
+    }
+}
+
+/// the copies of `line` kept apart from the output: in its `--split-dir`
+/// file and in the `--snapshot`. `newline` is whether the split file gets
+/// one after it.
+fn copy_line(line: &str, hl: &Highlighter, newline: bool) -> Result<(), String> {
+    if hl.split.is_none() && hl.snapshot.is_none() {
+        return Ok(());
+    }
+    let level = level_of(line, hl);
+    if let Some(split) = &hl.split {
+        let mut split = split.lock().unwrap_or_else(|e| e.into_inner());
+        split
+            .write(level, line, newline)
+            .map_err(|e| format!("failed to write to --split-dir: {}", e))?;
+    }
+    if let Some(snapshot) = &hl.snapshot {
+        snapshot.lock().unwrap_or_else(|e| e.into_inner()).push(level, line);
+    }
+    Ok(())
+}
+
+/// `--retry` and `--retry-timeout`.
+#[derive(Clone, Copy)]
+struct Retry {
+    enabled: bool,
+    timeout: Option<Duration>,
+}
+
+/// open and render one input to `out`. errors come back ready to print.
+fn process_input(
+    source: &InputSource,
+    hl: &Highlighter,
+    retry: Retry,
+    out: &mut dyn Write,
+) -> Result<Summary, String> {
+    let result = match source {
+        InputSource::Stdin => {
+            let stdin = Gunzip::new(io::stdin(), source.label());
+            match hl.tail {
+                Some(tail) => process_reader(
+                    Metered::new(TailLines::new(stdin, tail), "(stdin)", hl),
+                    hl,
+                    out,
+                ),
+                None => process_reader(Metered::new(stdin, "(stdin)", hl), hl, out),
+            }
+        }
+        InputSource::File(path) => {
+            match open_input(
+                path,
+                retry.enabled,
+                retry.timeout,
+                &mut io::stderr(),
+                &hl.loaded().styles,
+            ) {
+                Ok(file) => match hl.only_new {
+                    Some(only_new) => std::fs::canonicalize(path).and_then(|canonical| {
+                        let state = state_path(&canonical, |name| env::var(name).ok());
+                        process_new((path, file), state, only_new, hl, out)
+                    }),
+                    None if hl.tail.is_some() => {
+                        process_tail((path, file), hl.tail.unwrap(), hl, out)
+                    }
+                    None => process_reader(
+                        Metered::new(Gunzip::new(file, source.label()), path, hl),
+                        hl,
+                        out,
+                    ),
+                },
+                Err(e) => return Err(format!("failed to open {}: {}", source.label(), e)),
+            }
+        }
+    };
+    result.map_err(|e| format!("error processing {}: {}", source.label(), e))
+}
+
+/// `--only-new`, `--state-ttl` and `--reset-state`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct OnlyNew {
+    /// saved positions older than this are not trusted.
+    ttl: Option<Duration>,
+    /// forget the saved position and read the file whole.
+    reset: bool,
+}
+
+/// which file a path names, and how long it is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FileIdentity {
+    dev: u64,
+    ino: u64,
+    len: u64,
+}
+
+impl FileIdentity {
+    #[cfg(unix)]
+    fn of(file: &File) -> io::Result<FileIdentity> {
+        use std::os::unix::fs::MetadataExt;
+        let meta = file.metadata()?;
+        Ok(FileIdentity { dev: meta.dev(), ino: meta.ino(), len: meta.len() })
+    }
+
+    /// on Windows, the volume serial number and file index; elsewhere
+    /// nothing to tell files apart by.
+    #[cfg(not(unix))]
+    fn of(file: &File) -> io::Result<FileIdentity> {
+        let (dev, ino) = term::file_id(file).unwrap_or((0, 0));
+        Ok(FileIdentity { dev, ino, len: file.metadata()?.len() })
+    }
+}
+
+/// how far into a file the last `--only-new` run read, kept between runs
+/// as `logcolor-state 1 DEV INO OFFSET PATH`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct ReadState {
+    dev: u64,
+    ino: u64,
+    offset: u64,
+}
+
+impl ReadState {
+    fn parse(text: &str) -> Option<ReadState> {
+        let mut words = text.strip_prefix("logcolor-state 1 ")?.split(' ');
+        let mut number = || words.next()?.trim().parse().ok();
+        Some(ReadState { dev: number()?, ino: number()?, offset: number()? })
+    }
+
+    fn render(&self, path: &Path) -> String {
+        format!("logcolor-state 1 {} {} {} {}\n", self.dev, self.ino, self.offset, path.display())
+    }
+
+    /// where to carry on reading the file now at the path, or why the
+    /// saved position can't be trusted for it.
+    fn resume(
+        &self,
+        now: FileIdentity,
+        age: Duration,
+        ttl: Option<Duration>,
+    ) -> Result<u64, &'static str> {
+        if (self.dev, self.ino) != (now.dev, now.ino) {
+            return Err("the file was replaced");
+        }
+        if now.len < self.offset {
+            return Err("the file shrank");
+        }
+        if ttl.is_some_and(|ttl| age > ttl) {
+            return Err("it is older than --state-ttl");
+        }
+        Ok(self.offset)
+    }
+}
+
+/// where `--only-new` keeps its position in the file at `canonical`:
+/// `$XDG_STATE_HOME/logcolor/`, by default `~/.local/state/logcolor/`,
+/// under a name hashed from the path.
+fn state_path(canonical: &Path, var: impl Fn(&str) -> Option<String>) -> Option<PathBuf> {
+    let dir = match var("XDG_STATE_HOME").filter(|dir| Path::new(dir).is_absolute()) {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(var("HOME").filter(|home| !home.is_empty())?).join(".local/state"),
+    };
+    let name = format!("{:016x}.state", fnv1a(&canonical.to_string_lossy()));
+    Some(dir.join(PROGRAM).join(name))
+}
+
+/// where the last whole line read ends, at or before the current position:
+/// a line still being written is read again, whole, next time.
+fn line_boundary(file: &mut File, start: u64) -> io::Result<u64> {
+    let end = file.stream_position()?;
+    let back = (end - start).min(64 << 10);
+    file.seek(io::SeekFrom::Start(end - back))?;
+    let mut tail = vec![0; back as usize];
+    file.read_exact(&mut tail)?;
+    Ok(match tail.iter().rposition(|&b| b == b'\n') {
+        Some(nl) => end - back + nl as u64 + 1,
+        // no line ended in what was looked at
+        None if back < end - start => end,
+        None => start,
+    })
+}
+
+/// `--tail N`: how many of an input's last lines to show.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Tail {
+    lines: usize,
+    /// stdin is a live stream: its last lines come out once it pauses, not
+    /// only at its end, and the rest as it comes.
+    streaming: bool,
+}
+
+/// how much `--tail` reads at a time, going back from the end of a file.
+const TAIL_CHUNK_BYTES: usize = 64 << 10;
+
+/// how long a live stream has to pause for `--tail` to take what came
+/// before as its history.
+const TAIL_SETTLE: Duration = Duration::from_millis(500);
+
+/// where the last `lines` lines of `file` start, found reading back from its
+/// end a chunk at a time; 0 when it has no more lines than that. a last
+/// line without its newline counts as one.
+fn tail_offset(file: &mut (impl Read + Seek), lines: usize) -> io::Result<u64> {
+    let len = file.seek(io::SeekFrom::End(0))?;
+    if lines == 0 {
+        return Ok(len);
+    }
+    let mut chunk = vec![0; TAIL_CHUNK_BYTES];
+    let (mut end, mut seen) = (len, 0);
+    while end > 0 {
+        let start = end.saturating_sub(TAIL_CHUNK_BYTES as u64);
+        let piece = &mut chunk[..(end - start) as usize];
+        file.seek(io::SeekFrom::Start(start))?;
+        file.read_exact(piece)?;
+        for (i, _) in piece.iter().enumerate().rev().filter(|&(_, &b)| b == b'\n') {
+            let next = start + i as u64 + 1;
+            // the newline ending the last line doesn't start another
+            if next < len {
+                seen += 1;
+                if seen == lines {
+                    return Ok(next);
+                }
+            }
+        }
+        end = start;
+    }
+    Ok(0)
+}
+
+/// `--tail` for an input that can't seek to its end: read whole, keeping
+/// only the last lines, which come out at the end. a live stream is read in
+/// a thread of its own, so that a pause in it can end the history instead;
+/// what comes after passes straight through.
+struct TailLines<R> {
+    feed: TailFeed<R>,
+    lines: usize,
+    ring: std::collections::VecDeque<Vec<u8>>,
+    /// a line not ended yet.
+    partial: Vec<u8>,
+    /// the history gave way to passing through.
+    released: bool,
+    /// what's ready to be read, from `pos` on.
+    ready: Vec<u8>,
+    pos: usize,
+}
+
+enum TailFeed<R> {
+    Reader(R),
+    Live(std::sync::mpsc::Receiver<io::Result<Vec<u8>>>),
+}
+
+/// what `TailLines` got from its input.
+enum Fed {
+    Data(Vec<u8>),
+    Paused,
+    End,
+}
+
+impl<R: Read + Send + 'static> TailLines<R> {
+    fn new(mut inner: R, tail: Tail) -> TailLines<R> {
+        let feed = match tail.streaming {
+            false => TailFeed::Reader(inner),
+            true => {
+                let (send, receive) = std::sync::mpsc::channel();
+                thread::spawn(move || {
+                    loop {
+                        let mut chunk = vec![0; TAIL_CHUNK_BYTES];
+                        let read = inner.read(&mut chunk).map(|n| {
+                            chunk.truncate(n);
+                            chunk
+                        });
+                        let end = read.as_ref().map_or(true, Vec::is_empty);
+                        if send.send(read).is_err() || end {
+                            break;
+                        }
+                    }
+                });
+                TailFeed::Live(receive)
+            }
+        };
+        TailLines {
+            feed,
+            lines: tail.lines,
+            ring: std::collections::VecDeque::new(),
+            partial: Vec::new(),
+            released: false,
+            ready: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl<R: Read> TailLines<R> {
+    /// the next of the input; a pause only when `settle` and something came.
+    fn next(&mut self, settle: bool) -> io::Result<Fed> {
+        let fed = |read: io::Result<Vec<u8>>| match read? {
+            chunk if chunk.is_empty() => Ok(Fed::End),
+            chunk => Ok(Fed::Data(chunk)),
+        };
+        match &mut self.feed {
+            TailFeed::Reader(inner) => {
+                let mut chunk = vec![0; TAIL_CHUNK_BYTES];
+                let n = inner.read(&mut chunk)?;
+                chunk.truncate(n);
+                fed(Ok(chunk))
+            }
+            TailFeed::Live(receive) if !settle => receive.recv().map_or(Ok(Fed::End), fed),
+            TailFeed::Live(receive) => match receive.recv_timeout(TAIL_SETTLE) {
+                Ok(read) => fed(read),
+                Err(std::sync::mpsc::RecvTimeoutError::Timeout) => Ok(Fed::Paused),
+                Err(std::sync::mpsc::RecvTimeoutError::Disconnected) => Ok(Fed::End),
+            },
+        }
+    }
+
+    /// `chunk`'s lines into the ring, which keeps only the last ones.
+    fn keep(&mut self, chunk: &[u8]) {
+        for piece in chunk.split_inclusive(|&b| b == b'\n') {
+            self.partial.extend_from_slice(piece);
+            if piece.ends_with(b"\n") {
+                self.ring.push_back(std::mem::take(&mut self.partial));
+                if self.ring.len() > self.lines {
+                    self.ring.pop_front();
+                }
+            }
+        }
+    }
+}
+
+impl<R: Read> Read for TailLines<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.ready.len() {
+            let held = !self.ring.is_empty() || !self.partial.is_empty();
+            match self.next(!self.released && held)? {
+                Fed::Data(chunk) if self.released => (self.ready, self.pos) = (chunk, 0),
+                Fed::Data(chunk) => self.keep(&chunk),
+                Fed::End if self.released => return Ok(0),
+                Fed::Paused | Fed::End => {
+                    // a line without its newline yet counts as one
+                    if !self.partial.is_empty() && self.ring.len() == self.lines {
+                        self.ring.pop_front();
+                    }
+                    if self.lines == 0 {
+                        self.partial.clear();
+                    }
+                    let mut history: Vec<u8> = self.ring.drain(..).flatten().collect();
+                    history.append(&mut self.partial);
+                    (self.ready, self.pos) = (history, 0);
+                    self.released = true;
+                }
+            }
+        }
+        let n = buf.len().min(self.ready.len() - self.pos);
+        buf[..n].copy_from_slice(&self.ready[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// `--tail`: render the last lines of the file at `path`, seeking to them;
+/// a gzipped file or one that isn't a regular file is read through
+/// `TailLines` instead.
+fn process_tail(
+    (path, mut file): (&str, File),
+    tail: Tail,
+    hl: &Highlighter,
+    out: &mut dyn Write,
+) -> io::Result<Summary> {
+    let regular = file.metadata()?.is_file();
+    let mut magic = [0; 2];
+    if regular && !(file.read_exact(&mut magic).is_ok() && magic == [0x1f, 0x8b]) {
+        let start = tail_offset(&mut file, tail.lines)?;
+        file.seek(io::SeekFrom::Start(start))?;
+        return process_reader(Metered::new(&file, path, hl), hl, out);
+    }
+    if regular {
+        file.rewind()?;
+    }
+    let label = format!("'{}'", path);
+    let tail = Tail { streaming: false, ..tail };
+    process_reader(Metered::new(TailLines::new(Gunzip::new(file, label), tail), path, hl), hl, out)
+}
+
+/// `--only-new`: where the last run left off in the file at `path`, as saved
+/// in `state`. a saved position that no longer fits the file is dropped with
+/// a notice, and the file read whole.
+fn saved_offset(
+    path: &str,
+    identity: FileIdentity,
+    state: Option<&Path>,
+    only_new: OnlyNew,
+    styles: &Styles,
+) -> io::Result<u64> {
+    let saved = state.filter(|_| !only_new.reset).and_then(|state| {
+        let text = std::fs::read_to_string(state).ok()?;
+        let modified = std::fs::metadata(state).and_then(|m| m.modified()).ok()?;
+        Some((text, modified.elapsed().unwrap_or_default()))
+    });
+    let mut start = 0;
+    if let Some((text, age)) = saved {
+        let resumed = ReadState::parse(&text)
+            .ok_or("it can't be read")
+            .and_then(|saved| saved.resume(identity, age, only_new.ttl));
+        match resumed {
+            Ok(offset) => start = offset,
+            Err(why) => {
+                let notice = format!("--- saved position in {} dropped: {} ---", path, why);
+                styles.paint(&mut io::stderr(), &styles.dim, &notice)?;
+                eprintln!();
+            }
+        }
+    }
+    Ok(start)
+}
+
+/// `--only-new`: keep `offset` into the file at `path` in `state` for the
+/// next run, complaining on stderr if it can't be.
+fn save_offset(state: &Path, path: &str, canonical: &Path, identity: FileIdentity, offset: u64) {
+    let saved = ReadState { dev: identity.dev, ino: identity.ino, offset };
+    let written = state
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::write(state, saved.render(canonical)));
+    if let Err(e) = written {
+        eprintln!("failed to save the position in {} to {}: {}", path, state.display(), e);
+    }
+}
+
+/// `--only-new`: read `file` at `path` from where the last run left off, as
+/// saved in `state`, and save how far this one got.
+fn process_new(
+    (path, mut file): (&str, File),
+    state: Option<PathBuf>,
+    only_new: OnlyNew,
+    hl: &Highlighter,
+    out: &mut dyn Write,
+) -> io::Result<Summary> {
+    let canonical = std::fs::canonicalize(path)?;
+    let identity = FileIdentity::of(&file)?;
+    let start = saved_offset(path, identity, state.as_deref(), only_new, &hl.loaded().styles)?;
+    file.seek(io::SeekFrom::Start(start))?;
+    let summary = process_reader(Metered::new(&file, path, hl), hl, out)?;
+    let offset = line_boundary(&mut file, start)?;
+    if let Some(state) = state {
+        save_offset(&state, path, &canonical, identity, offset);
+    }
+    Ok(summary)
+}
+
+/// `--follow`: render the file at `path` and then the lines added to it as
+/// they come, like `tail -f`, until interrupted. with `--only-new` it starts
+/// where the last run left off, and saves how far it has got as it goes;
+/// with `--tail` at its last lines.
+fn follow_file(
+    path: &str,
+    hl: &Highlighter,
+    retry: Retry,
+    out: &mut dyn Write,
+) -> Result<(), String> {
+    let failed = |e: io::Error| format!("error following {}: {}", path, e);
+    let mut file =
+        open_input(path, retry.enabled, retry.timeout, &mut io::stderr(), &hl.loaded().styles)
+            .map_err(|e| format!("failed to open {}: {}", path, e))?;
+    let canonical = std::fs::canonicalize(path).map_err(failed)?;
+    let state = hl.only_new.and_then(|_| state_path(&canonical, |name| env::var(name).ok()));
+    let mut saved = None;
+    let pos = match hl.only_new {
+        Some(only_new) => {
+            let identity = FileIdentity::of(&file).map_err(failed)?;
+            saved_offset(path, identity, state.as_deref(), only_new, &hl.loaded().styles)
+                .map_err(failed)?
+        }
+        None => match hl.tail {
+            // `tail -n N -f`
+            Some(tail) => tail_offset(&mut file, tail.lines).map_err(failed)?,
+            None => 0,
+        },
+    };
+    file.seek(io::SeekFrom::Start(pos)).map_err(failed)?;
+    let mut watched = Watched { name: path.to_string(), file, pos, partial: Vec::new() };
+    let dir = Path::new(path).parent().filter(|dir| !dir.as_os_str().is_empty());
+    let mut watcher = file_watcher(dir.unwrap_or(Path::new(".")));
+    loop {
+        watched.read_new(Path::new(path), hl, out).map_err(failed)?;
+        out.flush().map_err(failed)?;
+        if let Some(state) = &state {
+            // a line still being written is read again, whole, next time
+            let offset = watched.pos - watched.partial.len() as u64;
+            let identity = FileIdentity::of(&watched.file).map_err(failed)?;
+            if saved != Some((identity.dev, identity.ino, offset)) {
+                save_offset(state, path, &canonical, identity, offset);
+                saved = Some((identity.dev, identity.ino, offset));
+            }
+        }
+        watcher.wait();
+    }
+}
+
+/// how often `--watch-glob` looks for new lines and new files.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// how long a native watcher sleeps without hearing of a change before
+/// looking anyway, for the changes it can't hear of, like a file written
+/// on another host sharing a network filesystem.
+const WATCH_IDLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// what `--watch-glob` waits on between looks at its directory.
+trait FileWatcher {
+    /// block until the directory may have changed; it may not have.
+    fn wait(&mut self);
+}
+
+/// wakes up every `WATCH_POLL_INTERVAL`: works anywhere, on any filesystem.
+struct PollWatcher;
+
+impl FileWatcher for PollWatcher {
+    fn wait(&mut self) {
+        thread::sleep(WATCH_POLL_INTERVAL);
+    }
+}
+
+/// inotify on Linux: sleeps until something in the directory changes, so a
+/// quiet log costs no wakeups.
+struct NativeWatcher {
+    notify: term::DirNotify,
+}
+
+impl FileWatcher for NativeWatcher {
+    fn wait(&mut self) {
+        self.notify.wait(WATCH_IDLE_INTERVAL);
+    }
+}
+
+/// the native watcher where `dir` can be watched that way, else polling.
+fn file_watcher(dir: &Path) -> Box<dyn FileWatcher> {
+    match term::DirNotify::new(dir) {
+        Some(notify) => Box::new(NativeWatcher { notify }),
+        None => Box::new(PollWatcher),
+    }
+}
+
+/// `path` no longer names the file open as `file`: it was rotated away and
+/// a new one put in its place.
+#[cfg(unix)]
+fn replaced(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let id = |meta: std::fs::Metadata| (meta.dev(), meta.ino());
+    identity_changed(file.metadata().ok().map(id), std::fs::metadata(path).ok().map(id))
+}
+
+/// the name has to be opened to learn what it names; the file isn't kept
+/// open, so it can still be rotated away.
+#[cfg(not(unix))]
+fn replaced(file: &File, path: &Path) -> bool {
+    let named = File::open(path).ok().and_then(|named| term::file_id(&named));
+    identity_changed(term::file_id(file), named)
+}
+
+/// whether the file open and the one under its name are told apart by
+/// their ids, `(dev, ino)` or what stands in for them; not knowing either
+/// is no change.
+fn identity_changed(open: Option<(u64, u64)>, named: Option<(u64, u64)>) -> bool {
+    matches!((open, named), (Some(open), Some(named)) if open != named)
+}
+
+/// one file `--watch-glob` or `--follow` is following.
+struct Watched {
+    name: String,
+    file: File,
+    /// how far into the file has been read.
+    pos: u64,
+    /// the start of a line whose newline hasn't been written yet.
+    partial: Vec<u8>,
+}
+
+impl Watched {
+    /// render the lines written to the file since the last read. a file
+    /// truncated in place is read again from the top; one rotated away is
+    /// read to its end and the file now at `path` from the top. says
+    /// whether there were any lines.
+    fn read_new(&mut self, path: &Path, hl: &Highlighter, out: &mut dyn Write) -> io::Result<bool> {
+        let mut any = false;
+        let rotated = replaced(&self.file, path);
+        if self.file.metadata().is_ok_and(|m| m.len() < self.pos) {
+            // truncated in place: start again from the top
+            self.pos = self.file.seek(io::SeekFrom::Start(0))?;
+            self.partial.clear();
+        }
+        let mut fresh = Vec::new();
+        self.pos += self.file.read_to_end(&mut fresh)? as u64;
+        self.partial.extend_from_slice(&fresh);
+        if rotated {
+            if let Ok(file) = File::open(path) {
+                // a last line without its newline won't be getting one
+                let unterminated = !self.partial.ends_with(b"\n") && !self.partial.is_empty();
+                if unterminated && !hl.preserve_eol {
+                    self.partial.push(b'\n');
+                }
+                let notice = format!("--- {} was rotated, following the new one ---", self.name);
+                let rest = std::mem::take(&mut self.partial);
+                let name = path.to_string_lossy();
+                process_reader(Metered::new(rest.as_slice(), &name, hl), hl, out)?;
+                if unterminated && hl.preserve_eol {
+                    // kept out of the split files, but the notice needs a line of its own
+                    writeln!(out)?;
+                }
+                hl.loaded().styles.paint(out, &hl.loaded().styles.dim, &notice)?;
+                writeln!(out)?;
+                *self = Watched { name: self.name.clone(), file, pos: 0, partial: Vec::new() };
+                self.pos = self.file.read_to_end(&mut self.partial)? as u64;
+                any = true;
+            }
+        }
+        let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') else { return Ok(any) };
+        let rest = self.partial.split_off(end + 1);
+        let complete = std::mem::replace(&mut self.partial, rest);
+        let name = path.to_string_lossy();
+        process_reader(Metered::new(complete.as_slice(), &name, hl), hl, out)?;
+        Ok(true)
+    }
+}
+
+/// `--watch-glob`: follows every file in a directory matching a glob,
+/// including the ones created after it started, like a new file per day.
+struct GlobWatch {
+    dir: PathBuf,
+    glob: String,
+    /// stop following files once they are removed.
+    forget_missing: bool,
+    files: Vec<Watched>,
+    started: bool,
+}
+
+impl GlobWatch {
+    fn new(dir: PathBuf, glob: String, forget_missing: bool) -> GlobWatch {
+        GlobWatch { dir, glob, forget_missing, files: Vec::new(), started: false }
+    }
+
+    /// pick up files that have started matching, and with `forget_missing`
+    /// let go of the ones that are gone. files there from the start are
+    /// followed from their end, like `tail -f -n 0`; later ones from the top.
+    fn rescan(&mut self, out: &mut dyn Write, styles: &Styles) -> io::Result<()> {
+        if self.forget_missing {
+            let mut gone = Vec::new();
+            self.files.retain(|w| {
+                let exists = self.dir.join(&w.name).exists();
+                if !exists {
+                    gone.push(w.name.clone());
+                }
+                exists
+            });
+            for name in gone {
+                styles.paint(out, &styles.dim, &format!("--- stopped following {} ---", name))?;
+                writeln!(out)?;
+            }
+        }
+        let mut names: Vec<String> = std::fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| glob_match(&self.glob, name))
+            .filter(|name| !self.files.iter().any(|w| &w.name == name))
+            .collect();
+        names.sort();
+        for name in names {
+            let path = self.dir.join(&name);
+            if !path.is_file() {
+                continue;
+            }
+            let Ok(mut file) = File::open(&path) else { continue };
+            let pos = match self.started {
+                true => 0,
+                false => file.seek(io::SeekFrom::End(0))?,
+            };
+            styles.paint(out, BOLD, &format!("--- now following {} ---", name))?;
+            writeln!(out)?;
+            self.files.push(Watched { name, file, pos, partial: Vec::new() });
+        }
+        self.started = true;
+        Ok(())
+    }
+
+    /// render the lines written to the followed files since the last read,
+    /// a file at a time, so lines come out in about the order they arrived.
+    /// says whether there were any lines.
+    fn read_new(&mut self, hl: &Highlighter, out: &mut dyn Write) -> io::Result<bool> {
+        let mut any = false;
+        for watched in &mut self.files {
+            any |= watched.read_new(&self.dir.join(&watched.name), hl, out)?;
+        }
+        Ok(any)
+    }
+
+    /// follow until interrupted, waiting on `watcher` between looks.
+    fn run(
+        &mut self,
+        hl: &Highlighter,
+        out: &mut dyn Write,
+        watcher: &mut dyn FileWatcher,
+    ) -> io::Result<()> {
+        loop {
+            self.rescan(out, &hl.loaded().styles)?;
+            self.read_new(hl, out)?;
+            out.flush()?;
+            watcher.wait();
+        }
+    }
+}
+
+/// a spool moves from memory to a temp file once it holds this much.
+const SPOOL_SPILL_BYTES: usize = 8 << 20;
+
+/// finished spools waiting for their turn may keep this much in memory
+/// between them; past it, they spill too.
+const SPOOL_MEMORY_BUDGET: usize = 64 << 20;
+
+/// told apart the temp files of spools in one process.
+static SPOOL_FILES: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+/// the rendered output of an input processed ahead of its turn under
+/// `--jobs`, in memory while it is small and in a temp file after.
+#[derive(Default)]
+struct Spool {
+    memory: Vec<u8>,
+    file: Option<(io::BufWriter<File>, PathBuf)>,
+}
+
+impl Spool {
+    fn spill(&mut self) -> io::Result<()> {
+        if self.file.is_some() {
+            return Ok(());
+        }
+        let n = SPOOL_FILES.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let path = env::temp_dir().join(format!("logcolor-{}-{}.spool", std::process::id(), n));
+        let file =
+            std::fs::OpenOptions::new().read(true).write(true).create_new(true).open(&path)?;
+        let mut writer = io::BufWriter::new(file);
+        let written = writer.write_all(&self.memory);
+        // from here on Drop cleans up
+        self.file = Some((writer, path));
+        self.memory = Vec::new();
+        written
+    }
+
+    fn copy_to(mut self, out: &mut dyn Write) -> io::Result<()> {
+        match self.file.take() {
+            Some((writer, path)) => {
+                let copied =
+                    writer.into_inner().map_err(|e| e.into_error()).and_then(|mut file| {
+                        file.seek(io::SeekFrom::Start(0))?;
+                        io::copy(&mut file, out)
+                    });
+                let _ = std::fs::remove_file(path);
+                copied.map(drop)
+            }
+            None => out.write_all(&self.memory),
+        }
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some((writer, _)) = &mut self.file {
+            return writer.write(buf);
+        }
+        self.memory.extend_from_slice(buf);
+        if self.memory.len() > SPOOL_SPILL_BYTES {
+            self.spill()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some((writer, _)) => writer.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for Spool {
+    fn drop(&mut self) {
+        if let Some((_, path)) = &self.file {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+/// `--jobs`: up to `jobs` inputs are read and rendered at once, each into a
+/// spool, and copied to `out` in the order given as soon as everything before
+/// them has been, so the output is the same as one at a time. `done` hears
+/// about each input in that order too, right after its output.
+///
+/// benchmark: 50 rotations of a 3.3 MB log (`logcolor --color always dir/`
+/// against the same with `-j 4`, output to /dev/null) gave byte-identical
+/// output in 95s and 90s on a single-core box; coloring is CPU-bound, so the
+/// win comes with more cores, or with files on slow disks, where one file's
+/// reads overlap another's coloring. a single big file gains nothing.
+fn process_in_parallel(
+    inputs: &[InputSource],
+    jobs: usize,
+    hl: &Highlighter,
+    retry: Retry,
+    out: &mut dyn Write,
+    mut done: impl FnMut(&InputSource, Result<Summary, String>),
+) {
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let (tx, rx) = std::sync::mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs.min(inputs.len()) {
+            let (next, tx) = (&next, tx.clone());
+            scope.spawn(move || {
+                loop {
+                    let i = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    let Some(source) = inputs.get(i) else { break };
+                    let mut spool = Spool::default();
+                    let result = process_input(source, hl, retry, &mut spool);
+                    if tx.send((i, spool, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(tx);
+        let mut ready = std::collections::BTreeMap::new();
+        let mut emitted = 0;
+        for (i, mut spool, result) in rx {
+            let waiting: usize = ready.values().map(|(s, _): &(Spool, _)| s.memory.len()).sum();
+            if i != emitted && waiting + spool.memory.len() > SPOOL_MEMORY_BUDGET {
+                if let Err(e) = spool.spill() {
+                    eprintln!("failed to spool {}: {}", inputs[i].label(), e);
+                }
+            }
+            ready.insert(i, (spool, result));
+            while let Some((spool, result)) = ready.remove(&emitted) {
+                let result = result.and_then(|summary| match spool.copy_to(out) {
+                    Ok(()) => Ok(summary),
+                    Err(e) => Err(format!("write error: {}", e)),
+                });
+                done(&inputs[emitted], result);
+                emitted += 1;
+            }
+        }
+    });
+}
+
+/// where a stretch of input comes from, in command line order.
+#[doc(hidden)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum InputSource {
+    Stdin,
+    File(String),
+}
+
+impl InputSource {
+    pub fn from_arg(arg: String) -> InputSource {
+        if arg == "-" { InputSource::Stdin } else { InputSource::File(arg) }
+    }
+
+    /// how the source is named in messages.
+    pub fn label(&self) -> String {
+        match self {
+            InputSource::Stdin => "(stdin)".to_string(),
+            InputSource::File(path) => format!("'{}'", path),
+        }
+    }
+}
+
+/// shell-style wildcard match of a file name: `*`, `?` and `[...]` classes
+/// (`[!...]` negated).
+fn glob_match(pattern: &str, name: &str) -> bool {
+    fn at(p: &[char], n: &[char]) -> bool {
+        match p.first() {
+            None => n.is_empty(),
+            Some('*') => (0..=n.len()).any(|i| at(&p[1..], &n[i..])),
+            Some('?') => !n.is_empty() && at(&p[1..], &n[1..]),
+            Some('[') if p.len() > 2 && p[2..].contains(&']') => {
+                let close = p[2..].iter().position(|&c| c == ']').unwrap() + 2;
+                let (negated, class) = match p[1] {
+                    '!' | '^' => (true, &p[2..close]),
+                    _ => (false, &p[1..close]),
+                };
+                let Some(&c) = n.first() else { return false };
+                let mut hit = false;
+                let mut i = 0;
+                while i < class.len() {
+                    if i + 2 < class.len() && class[i + 1] == '-' {
+                        hit |= (class[i]..=class[i + 2]).contains(&c);
+                        i += 3;
+                    } else {
+                        hit |= class[i] == c;
+                        i += 1;
+                    }
+                }
+                hit != negated && at(&p[close + 1..], &n[1..])
+            }
+            Some(&c) => n.first() == Some(&c) && at(&p[1..], &n[1..]),
+        }
+    }
+    let p: Vec<char> = pattern.chars().collect();
+    let n: Vec<char> = name.chars().collect();
+    at(&p, &n)
+}
+
+/// order of the files found in a directory argument, from `--sort`.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SortKey {
+    Name,
+    /// oldest first, so rotations read in the order they were written.
+    Mtime,
+}
+
+impl SortKey {
+    pub fn parse(s: &str) -> Option<SortKey> {
+        match s {
+            "name" => Some(SortKey::Name),
+            "mtime" => Some(SortKey::Mtime),
+            _ => None,
+        }
+    }
+}
+
+/// rotations we can't read yet; left out of directory listings.
+const COMPRESSED_EXTENSIONS: [&str; 5] = ["gz", "bz2", "xz", "zst", "zip"];
+
+/// how directory arguments turn into files.
+#[doc(hidden)]
+pub struct DirScan {
+    pub glob: String,
+    pub recursive: bool,
+    pub sort: SortKey,
+}
+
+impl DirScan {
+    /// whether a file name belongs in the listing: it matches the glob, by
+    /// itself or once a rotation number (`app.log.3`) is taken off.
+    fn wants(&self, name: &str) -> bool {
+        if name.starts_with('.') {
+            return false;
+        }
+        if name.rsplit_once('.').is_some_and(|(_, ext)| COMPRESSED_EXTENSIONS.contains(&ext)) {
+            return false;
+        }
+        let unrotated = name
+            .rsplit_once('.')
+            .filter(|(_, n)| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+            .map(|(base, _)| base);
+        glob_match(&self.glob, name) || unrotated.is_some_and(|base| glob_match(&self.glob, base))
+    }
+
+    /// the files under `dir` to read, in `sort` order.
+    fn files(&self, dir: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut found = Vec::new();
+        let mut visited = HashSet::new();
+        self.walk(dir, &mut found, &mut visited)?;
+        match self.sort {
+            SortKey::Name => found.sort(),
+            SortKey::Mtime => {
+                let mtime = |p: &PathBuf| std::fs::metadata(p).and_then(|m| m.modified()).ok();
+                found.sort_by_cached_key(|p| (mtime(p), p.clone()));
+            }
+        }
+        Ok(found)
+    }
+
+    fn walk(
+        &self,
+        dir: &Path,
+        found: &mut Vec<PathBuf>,
+        visited: &mut HashSet<PathBuf>,
+    ) -> io::Result<()> {
+        // a symlink back up the tree would otherwise send us round forever
+        if !visited.insert(std::fs::canonicalize(dir)?) {
+            return Ok(());
+        }
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name =
+                path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            if path.is_dir() {
+                if self.recursive && !name.starts_with('.') {
+                    self.walk(&path, found, visited)?;
+                }
+            } else if self.wants(&name) {
+                found.push(path);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// what the command line asked for, as the binary parsed it.
+#[doc(hidden)]
+pub struct Cli {
+    pub theme: ThemeChoice,
+    pub config: Option<String>,
+    pub print_config: bool,
+    pub print_theme: bool,
+    pub color: ColorMode,
+    /// `--color-depth`, over what the terminal is taken to support.
+    pub color_depth: Option<ColorDepth>,
+    pub format: InputFormat,
+    pub verbose: bool,
+    /// read one after the other, like cat; stdin when empty.
+    pub inputs: Vec<InputSource>,
+    pub scan: DirScan,
+    pub debug_caps: bool,
+    pub demo: bool,
+    pub detect: bool,
+    pub compare: bool,
+    pub compare_threshold: Option<f64>,
+    pub quiet: u8,
+    pub summary_only: bool,
+    pub stats: bool,
+    pub stats_only: bool,
+    pub stats_format: StatsFormat,
+    pub stats_output: Option<String>,
+    pub byte_offsets: bool,
+    pub number: bool,
+    pub lines: Option<LineRange>,
+    pub max_line_bytes: usize,
+    pub jobs: usize,
+    /// `-o`, and how the file is written.
+    pub output: Option<String>,
+    pub output_only: bool,
+    pub output_color: bool,
+    pub append: bool,
+    pub mirror: Option<String>,
+    pub mirror_fd: Option<i32>,
+    pub unbuffered: bool,
+    pub tail: Option<usize>,
+    pub follow: bool,
+    pub retry: bool,
+    pub retry_timeout: Option<Duration>,
+    pub only_new: bool,
+    pub state_ttl: Option<Duration>,
+    pub reset_state: bool,
+    pub wrap: bool,
+    pub track: Vec<String>,
+    pub track_filter: Option<(String, String)>,
+    pub json_fields: Vec<String>,
+    pub json_rest: bool,
+    pub grep: Option<Grep>,
+    pub services: Option<Vec<String>>,
+    pub columns: bool,
+    pub source_width: Option<usize>,
+    pub elide_below: Option<Level>,
+    pub max_level: Option<Level>,
+    pub min_level: Option<Level>,
+    pub drop_unknown: bool,
+    pub fail_on: Option<Level>,
+    pub sources: SourceFilter,
+    pub since: Option<Since>,
+    pub since_boot: bool,
+    pub tz: Option<String>,
+    pub local: bool,
+    pub level_column: Option<LevelColumn>,
+    pub level_column_chars: bool,
+    pub level_field: Option<usize>,
+    pub level_delim: Option<String>,
+    pub level_pattern: Option<LevelColumn>,
+    pub level_map: Option<Vec<(String, Level)>>,
+    pub strict_prefix: Option<usize>,
+    pub marks: Option<Level>,
+    pub throttle: Option<usize>,
+    pub throttle_errors: Option<usize>,
+    pub alert_rate: Option<AlertRate>,
+    pub alert_bell: bool,
+    pub exec_on_alert: Option<String>,
+    pub dedup_window: Option<usize>,
+    pub dedup_errors: bool,
+    pub squeeze: bool,
+    pub squeeze_window: Option<usize>,
+    pub budget: Option<Budget>,
+    pub highlight_new: bool,
+    pub baseline: Option<String>,
+    pub hex: bool,
+    pub hex_ascii: bool,
+    /// `--extras`, in `Extra::ALL`'s order.
+    pub extras: Option<Vec<Extra>>,
+    pub no_version_ips: bool,
+    pub split_interleaved: bool,
+    pub sort_buffer: Option<Duration>,
+    pub gutter: bool,
+    pub whole_line: bool,
+    pub gutter_glyph: Option<String>,
+    pub no_tail_headers: bool,
+    pub snapshot: Option<String>,
+    pub snapshot_lines: usize,
+    pub snapshot_level: Level,
+    pub snapshot_interval: Duration,
+    pub metrics_listen: Option<String>,
+    pub audit: Option<String>,
+    pub split_dir: Option<String>,
+    pub split_max_size: Option<usize>,
+    pub split_keep: usize,
+    pub watch_glob: Option<String>,
+    pub forget_missing: bool,
+    pub infer: Vec<Inference>,
+    pub no_infer: bool,
+    pub level_words: Vec<LevelWord>,
+    pub loose: bool,
+    pub rules: Option<String>,
+    pub check_rules: bool,
+    pub completions: Option<String>,
+    pub multiline_json: bool,
+    pub expand_json: bool,
+    pub json_pretty: bool,
+    pub dim_logfmt_keys: bool,
+    pub unescape: bool,
+    pub show_invisibles: bool,
+    pub allow_bidi: bool,
+    pub continuation: Option<ContinuationMode>,
+    pub continuation_max: usize,
+    pub all_occurrences: bool,
+    pub explain: bool,
+    pub crlf: bool,
+    pub preserve_eol: bool,
+    pub output_format: OutputFormat,
+    pub html_standalone: bool,
+    pub keep_ansi: bool,
+    pub syslog_names: bool,
+    pub help: bool,
+}
+
+/// a positive length of time: `500ms`, `5s`, `1m`, `12h`, `7d`, or plain
+/// seconds.
+#[doc(hidden)]
+pub fn parse_interval(option: &str, value: &str) -> Result<Duration, String> {
+    let (number, scale) = if let Some(ms) = value.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(m) = value.strip_suffix('m') {
+        (m, 60.0)
+    } else if let Some(h) = value.strip_suffix('h') {
+        (h, 3600.0)
+    } else if let Some(d) = value.strip_suffix('d') {
+        (d, 86_400.0)
+    } else {
+        (value.strip_suffix('s').unwrap_or(value), 1.0)
+    };
+    number
+        .parse::<f64>()
+        .ok()
+        .and_then(|n| Duration::try_from_secs_f64(n * scale).ok())
+        .filter(|d| !d.is_zero())
+        .ok_or_else(|| {
+            format!(
+                "invalid value '{}' for '--{}' (expected a time like 5s or 500ms)",
+                value, option
+            )
+        })
+}
+
+/// options prepended to the command line, so the real ones override them.
+#[doc(hidden)]
+pub const DEFAULT_OPTS_VAR: &str = "LOGCOLOR_OPTS";
+
+/// the name completions are registered for.
+#[doc(hidden)]
+pub const PROGRAM: &str = "logcolor";
+
+/// how long `--demo` lets its over-long line's pieces be, so the cut shows
+/// without a megabyte of output.
+const DEMO_LINE_BYTES: usize = 160;
+
+/// the sample log `--demo` renders: a line for each level token, the
+/// formats we know, a stack trace, an over-long line, and a line for each
+/// of the highlights asked for, so they can be seen with the flags given.
+/// each section is its own input, so it gets its own format detection.
+fn demo_log(hl: &Highlighter) -> Vec<String> {
+    let stamp =
+        |second: usize| format!("2024-05-01T12:03:{:02}.{:03}Z", second % 60, second * 7 % 1000);
+    let mut levels = String::new();
+    for (i, &(token, how)) in LEVEL_TOKENS.iter().enumerate() {
+        levels += &match how {
+            TokenMatch::LineStart => format!("{}: a java.util.logging record\n", token),
+            TokenMatch::Label => format!("{} {}:  a PostgreSQL message\n", stamp(i), token),
+            TokenMatch::Bracketed => {
+                format!("{} 0 [{}] [MY-010116] a MySQL message\n", stamp(i), token)
+            }
+            TokenMatch::Loose | TokenMatch::Word => {
+                format!("{} {} a line at this level\n", stamp(i), token)
+            }
+        };
+    }
+    if let Some(inference) = hl.infer.first() {
+        levels += &format!("{} no level token, but {} all the same\n", stamp(40), inference.phrase);
+    }
+    let mut sections = vec![
+        levels,
+        format!(
+            "{{\"time\":\"{}\",\"level\":\"error\",\"msg\":\"payment declined\",\"order\":\"A-1042\"}}\n",
+            stamp(41)
+        ),
+        format!("time={} level=warn msg=\"cache miss rate high\" ratio=0.42\n", stamp(42)),
+        "[2024-05-01T12:03:43Z INFO  demo::server] listening on 0.0.0.0:8080\n".to_string(),
+        format!(
+            "<34>1 {} web demo 4242 ID47 [origin ip=\"10.0.0.7\"] an RFC 5424 record\n",
+            stamp(44)
+        ),
+        format!(
+            "{} ERROR request failed\n\
+             java.lang.IllegalStateException: pool exhausted\n\
+             \x20   at com.example.Pool.take(Pool.java:42)\n\
+             \x20   at com.example.Server.handle(Server.java:17)\n\
+             Caused by: java.net.SocketTimeoutException: read timed out\n\
+             \x20   ... 12 more\n",
+            stamp(45)
+        ),
+    ];
+    let mut long = format!("{} INFO a line longer than --max-line-bytes:", stamp(46));
+    while long.len() < hl.max_line_bytes + 40 {
+        long.push_str(" and on");
+    }
+    sections.push(long + "\n");
+    let mut highlights = String::new();
+    if hl.hex.is_some() {
+        highlights += &format!(
+            "{} DEBUG payload: 0x48 0x54 0x54 0x50 0x2f 0x31 0x2e 0x31 0x0d 0x0a\n",
+            stamp(47)
+        );
+    }
+    for key in hl.tracking.iter().flat_map(|tracking| &tracking.keys) {
+        highlights +=
+            &format!("{} INFO tracked {}=demo-{} and {}=other\n", stamp(48), key, key, key);
+    }
+    for rule in hl.loaded().rules.iter() {
+        if let Matcher::Substring(text) = &rule.matcher {
+            highlights += &format!("{} INFO a line with {} in it, for the rule\n", stamp(49), text);
+        }
+    }
+    sections.push(highlights);
+    sections.retain(|section| !section.is_empty());
+    sections
+}
+
+/// write `--stats` where they were asked for.
+fn write_stats(
+    format: StatsFormat,
+    output: Option<&str>,
+    (total, files): (&Summary, &[(String, Summary)]),
+    elapsed: Duration,
+    styles: &Styles,
+) -> io::Result<()> {
+    // a file is read by something else, never colored
+    let plain = Styles::new(&DARK_PALETTE, ColorDepth::None);
+    let text = match format {
+        StatsFormat::Human => total.table(if output.is_some() { &plain } else { styles }),
+        StatsFormat::Json => stats_json(total, files, elapsed).to_string(),
+    };
+    match output {
+        Some(path) => std::fs::write(path, text + "\n"),
+        None => writeln!(io::stderr(), "{}", text),
+    }
+}
+
+/// how a run that got to the end of its inputs went, for the binary's
+/// exit status.
+#[doc(hidden)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Outcome {
+    /// reading or writing failed somewhere.
+    pub failed: bool,
+    /// lines at the `--fail-on` level or above.
+    pub found: usize,
+}
+
+/// the logcolor CLI once its arguments are parsed: `full_args` are the ones
+/// in effect, for `--audit`, and `from_env` says if `LOGCOLOR_OPTS` added
+/// some. an error is for the binary to print before it exits.
+#[doc(hidden)]
+pub fn run(
+    cli: Cli,
+    full_args: &[String],
+    from_env: Option<&str>,
+    started: Instant,
+) -> Result<Outcome, String> {
+    let rules = match &cli.rules {
+        Some(path) => read_rules(path)?,
+        None => Vec::new(),
+    };
+    if cli.check_rules {
+        eprintln!("{}: {} rules ok", cli.rules.as_deref().unwrap_or_default(), rules.len());
+        return Ok(Outcome::default());
+    }
+    // before anything looks for a level
+    let words = LevelWords::new(&cli.level_words, cli.loose);
+    let _ = LEVEL_WORDS.set(LevelWords { prefix: cli.strict_prefix, ..words });
+    let info = TermInfo::from_env();
+    let (depth, depth_why) = match cli.output_format {
+        // colors go in the HTML, whatever reads it
+        OutputFormat::Html if cli.color != ColorMode::Never => {
+            (ColorDepth::TrueColor, "because of --output-format html".to_string())
+        }
+        _ => decide_color_depth(cli.color, &info),
+    };
+    let (depth, depth_why) = match cli.color_depth {
+        Some(forced) if depth != ColorDepth::None => {
+            (forced, format!("--color-depth (otherwise {}: {})", depth.describe(), depth_why))
+        }
+        _ => (depth, depth_why),
+    };
+    // the --output file's colors go by --output-color, not by stdout
+    let file_depth = match (cli.output_color, cli.output_format, cli.color) {
+        (false, _, _) | (_, OutputFormat::Html, _) | (_, _, ColorMode::Never) => None,
+        (true, ..) => {
+            let (capable, why) = decide_color_depth(ColorMode::Always, &info);
+            Some((cli.color_depth.unwrap_or(capable), why))
+        }
+    };
+    let stdout_depth = if cli.output_only { ColorDepth::None } else { depth };
+    let (depth, depth_why) = match file_depth {
+        Some((file_depth, why)) if cli.output_only || file_depth > depth => {
+            (file_depth, format!("--output-color, {}; stdout: {}", why, depth_why))
+        }
+        _ if cli.output_only => (ColorDepth::None, "--output without --output-color".to_string()),
+        _ => (depth, depth_why),
+    };
+    let line_flush = cli.unbuffered || cli.follow || info.stdout_tty;
+    let buffering_why = match (cli.unbuffered, info.stdout_tty) {
+        (true, _) => "flushed after every line, because of --unbuffered".to_string(),
+        (false, true) => "flushed after every line, because stdout is a terminal".to_string(),
+        (false, false) if cli.follow => "flushed after every line, because of --follow".to_string(),
+        (false, false) => format!(
+            "buffered {} KiB at a time, because stdout is not a terminal \
+             (--unbuffered, or SIGUSR1 to flush now)",
+            OUTPUT_BUFFER_BYTES >> 10
+        ),
+    };
+    let (palette, palette_why) = select_palette(cli.theme, depth != ColorDepth::None);
+    let config = match &cli.config {
+        Some(path) => Some(PathBuf::from(path)),
+        None => config_path(|name| env::var(name).ok()).filter(|path| path.is_file()),
+    };
+    let scheme = config.as_deref().map(read_scheme).transpose()?;
+    let config_why = match &config {
+        Some(path) => format!("level colors from {}", path.display()),
+        None => "no config file, the palette's level colors".to_string(),
+    };
+    let base = Styles::new(palette, depth);
+    let styles = base.clone().with_scheme(&scheme.unwrap_or_default());
+    if cli.print_config {
+        print!("{}", scheme_text(&styles, &format!("{}; {}", palette_why, config_why)));
+        return Ok(Outcome::default());
+    }
+    if cli.print_theme {
+        let origin = format!("{}, {}; {}", palette_why, depth.describe(), config_why);
+        print!("{}", theme_text(&styles, &origin));
+        return Ok(Outcome::default());
+    }
+    let (base, styles) = match cli.output_format {
+        OutputFormat::Html => (base.tagged(), styles.tagged()),
+        OutputFormat::Ansi => (base, styles),
+    };
+    let sequence = MarkSequence::detect(&info);
+    let marks_why = match (sequence, info.stdout_tty) {
+        (Some(sequence), true) => format!("{:?} (TERM_PROGRAM), for --marks", sequence),
+        (Some(_), false) => "none, because stdout is not a terminal".to_string(),
+        (None, _) => format!(
+            "none, because TERM_PROGRAM {} is not known to support them",
+            info.term_program.as_deref().map_or("(unset)".to_string(), |p| format!("{:?}", p))
+        ),
+    };
+    let tz = match (&cli.tz, cli.local) {
+        (Some(name), _) => Some(Zone::load(name)),
+        (None, true) => Some(Zone::local()),
+        (None, false) => None,
+    };
+    let tz = tz.transpose()?;
+    let jump_marks = match (cli.marks, sequence) {
+        (Some(threshold), Some(sequence)) if info.stdout_tty => {
+            Some(JumpMarks { threshold, sequence })
+        }
+        _ => None,
+    };
+    if cli.debug_caps {
+        let show = |v: &Option<String>| v.clone().unwrap_or_else(|| "(unset)".to_string());
+        eprintln!("TERM:       {}", show(&info.term));
+        eprintln!("COLORTERM:  {}", show(&info.colorterm));
+        eprintln!("NO_COLOR:   {}", if info.no_color { "set" } else { "(unset)" });
+        eprintln!("stdout tty: {}", if info.stdout_tty { "yes" } else { "no" });
+        if let Some(escapes) = info.console_escapes {
+            eprintln!("console:    {}", if escapes { "escapes on" } else { "no escapes" });
+        }
+        eprintln!("options:    {}", from_env.unwrap_or("(command line only)"));
+        match info.term.as_deref().and_then(terminfo_path) {
+            Some(path) => {
+                eprintln!("terminfo:   {} (colors={:?})", path.display(), info.terminfo_colors)
+            }
+            None => eprintln!("terminfo:   (not found)"),
+        }
+        eprintln!("color:      {}, because {}", depth.describe(), depth_why);
+        eprintln!("palette:    {}", palette_why);
+        eprintln!("config:     {}", config_why);
+        eprintln!("output:     {}", buffering_why);
+        eprintln!("marks:      {}", marks_why);
+        return Ok(Outcome::default());
+    }
+    let mut hl = Highlighter {
+        format: cli.format,
+        verbose: cli.verbose,
+        quiet: if cli.summary_only || cli.stats_only { cli.quiet.max(1) } else { cli.quiet },
+        wrap: cli.wrap,
+        width: TermWidth::new(terminal_columns()),
+        columns: cli
+            .columns
+            .then(|| Columns { source_width: cli.source_width.unwrap_or(DEFAULT_SOURCE_WIDTH) }),
+        tracking: (!cli.track.is_empty())
+            .then_some(Tracking { keys: cli.track, filter: cli.track_filter }),
+        json_fields: (!cli.json_fields.is_empty())
+            .then_some(JsonFields { fields: cli.json_fields, rest: cli.json_rest }),
+        settings: Settings {
+            config: config.clone(),
+            rules: cli.rules.clone(),
+            ..Settings::new(base, styles, rules)
+        },
+        multiline_json: cli.multiline_json,
+        expand_json: cli.expand_json,
+        json_pretty: cli.json_pretty,
+        dim_logfmt_keys: cli.dim_logfmt_keys,
+        unescape: cli.unescape,
+        invisibles: Invisibles { show: cli.show_invisibles, allow_bidi: cli.allow_bidi },
+        continuation: cli.continuation,
+        continuation_max: cli.continuation_max,
+        all_occurrences: cli.all_occurrences,
+        explain: cli.explain,
+        preserve_eol: cli.preserve_eol,
+        keep_ansi: cli.keep_ansi,
+        syslog_names: cli.syslog_names,
+        extras: cli.extras.map(|kinds| Extras { kinds, versions: !cli.no_version_ips }),
+        summaries: false,
+        marks: false,
+        byte_offsets: cli.byte_offsets,
+        number: cli.number,
+        lines: cli.lines,
+        max_line_bytes: cli.max_line_bytes,
+        infer: match cli.no_infer {
+            true => Vec::new(),
+            false => cli.infer.into_iter().chain(Inference::defaults()).collect(),
+        },
+        grep: cli.grep,
+        services: cli.services,
+        elide_below: cli.elide_below,
+        jump_marks,
+        throttle: cli.throttle.map(|lines| Throttle {
+            lines,
+            errors: cli.throttle_errors.unwrap_or(lines.saturating_mul(THROTTLE_ERROR_FACTOR)),
+        }),
+        dedup: cli.dedup_window.map(|size| DedupWindow { size, errors: cli.dedup_errors }),
+        squeeze: cli.squeeze.then(|| SqueezeWindow {
+            size: cli.squeeze_window.unwrap_or(1),
+            live: None,
+            following: false,
+        }),
+        budget: cli.budget,
+        tail: cli.tail.map(|lines| Tail { lines, streaming: false }),
+        levels: LevelFilter {
+            max: cli.max_level,
+            min: cli.min_level,
+            drop_unknown: cli.drop_unknown,
+        },
+        sources: cli.sources,
+        since: None,
+        level_column: cli.level_column,
+        split: None,
+        snapshot: None,
+        tail_headers: !cli.no_tail_headers,
+        hex: cli.hex.then_some(HexDumps { ascii: cli.hex_ascii }),
+        split_interleaved: cli.split_interleaved,
+        sort_buffer: cli.sort_buffer,
+        gutter: None,
+        whole_line: cli.whole_line,
+        novelty: None,
+        only_new: cli.only_new.then_some(OnlyNew { ttl: cli.state_ttl, reset: cli.reset_state }),
+        tz,
+        metrics: None,
+        alert: cli.alert_rate.map(|rate| Alert {
+            alarm: std::sync::Mutex::new(RateAlarm::new(rate)),
+            bell: cli.alert_bell,
+            exec: cli.exec_on_alert,
+        }),
+        audit: None,
+    };
+    if let Some(since) = cli.since {
+        let boot = match cli.since_boot {
+            true => match boot_time() {
+                Some(boot) => Some(boot),
+                None => {
+                    return Err("'--since-boot' needs /proc/uptime to tell when the system booted"
+                        .to_string());
+                }
+            },
+            false => None,
+        };
+        hl.since = Some(TimeWindow::new(since, boot));
+    }
+    if cli.highlight_new {
+        let mut novelty = Novelty::new(NOVELTY_LIMIT);
+        if let Some(path) = &cli.baseline {
+            match std::fs::read(path) {
+                Ok(bytes) => String::from_utf8_lossy(&bytes).lines().for_each(|line| {
+                    novelty.first_seen(line);
+                }),
+                Err(e) => return Err(format!("failed to read --baseline {}: {}", path, e)),
+            }
+        }
+        hl.novelty = Some(std::sync::Mutex::new(novelty));
+    }
+    if let Some(path) = cli.snapshot {
+        let snapshot = Snapshot::new(path.into(), cli.snapshot_lines, cli.snapshot_level);
+        let snapshot = std::sync::Arc::new(std::sync::Mutex::new(snapshot));
+        start_snapshots(snapshot.clone(), cli.snapshot_interval);
+        hl.snapshot = Some(snapshot);
+    }
+    if let Some(dir) = cli.split_dir {
+        let max_size = cli.split_max_size.map(|size| size as u64);
+        match SplitDir::new(PathBuf::from(&dir), max_size, cli.split_keep) {
+            Ok(split) => hl.split = Some(std::sync::Mutex::new(split)),
+            Err(e) => return Err(format!("failed to create --split-dir {}: {}", dir, e)),
+        }
+    }
+
+    let watch = cli.watch_glob.map(|pattern| {
+        let (dir, glob) = match (cli.inputs.first(), pattern.rsplit_once('/')) {
+            (Some(InputSource::File(dir)), _) => (PathBuf::from(dir), pattern),
+            (_, Some((dir, glob))) => (PathBuf::from(dir), glob.to_string()),
+            _ => (PathBuf::from("."), pattern),
+        };
+        if !dir.is_dir() {
+            return Err(format!(
+                "'--watch-glob' needs a directory to watch, not {}",
+                dir.display()
+            ));
+        }
+        Ok(GlobWatch::new(dir, glob, cli.forget_missing))
+    });
+    let watch = watch.transpose()?;
+
+    // no inputs reads stdin; "-" reads it at that point in the sequence
+    let inputs = if cli.inputs.is_empty() { vec![InputSource::Stdin] } else { cli.inputs };
+    let mut failed = false;
+    // directories stand for the logs inside them
+    let inputs: Vec<InputSource> = inputs
+        .into_iter()
+        .flat_map(|source| match &source {
+            InputSource::File(path) if Path::new(path).is_dir() => {
+                match cli.scan.files(Path::new(path)) {
+                    Ok(files) => files
+                        .into_iter()
+                        .map(|f| InputSource::File(f.to_string_lossy().into_owned()))
+                        .collect(),
+                    Err(e) => {
+                        eprintln!("failed to read directory {}: {}", source.label(), e);
+                        failed = true;
+                        Vec::new()
+                    }
+                }
+            }
+            _ => vec![source],
+        })
+        .collect();
+    if cli.detect {
+        for source in &inputs {
+            let mut sample = Vec::new();
+            let read = match source {
+                InputSource::Stdin => Gunzip::new(io::stdin(), source.label())
+                    .take(DETECT_SAMPLE_BYTES as u64)
+                    .read_to_end(&mut sample),
+                InputSource::File(path) => File::open(path).and_then(|f| {
+                    Gunzip::new(f, source.label())
+                        .take(DETECT_SAMPLE_BYTES as u64)
+                        .read_to_end(&mut sample)
+                }),
+            };
+            match read {
+                Ok(_) => print!("{}", detect_format(&sample).report(&source.label())),
+                Err(e) => {
+                    eprintln!("failed to read {}: {}", source.label(), e);
+                    failed = true;
+                }
+            }
+        }
+        return Ok(Outcome { failed, found: 0 });
+    }
+    if cli.compare {
+        let mut found = Vec::new();
+        for source in &inputs {
+            let signatures = match source {
+                InputSource::File(path) => File::open(path).and_then(|f| {
+                    error_signatures(BufReader::new(Gunzip::new(f, source.label())), &hl.infer)
+                }),
+                InputSource::Stdin => error_signatures(
+                    BufReader::new(Gunzip::new(io::stdin(), source.label())),
+                    &hl.infer,
+                ),
+            };
+            match signatures {
+                Ok(signatures) => found.push((source.label(), signatures)),
+                Err(e) => return Err(format!("failed to read {}: {}", source.label(), e)),
+            }
+        }
+        let threshold = cli.compare_threshold.unwrap_or(DEFAULT_COMPARE_THRESHOLD);
+        let (old, new) = (&found[0], &found[1]);
+        let mut out = io::stdout().lock();
+        let written =
+            write_comparison(&mut out, (&old.0, &old.1), (&new.0, &new.1), threshold, &hl);
+        if let Err(e) = written.and_then(|_| out.flush()) {
+            return Err(format!("failed to write the comparison: {}", e));
+        }
+        return Ok(Outcome::default());
+    }
+    // a summary after each file when there are several, or when asked for
+    let summary_lines = (inputs.len() > 1 || cli.summary_only) && hl.quiet < 2;
+    let stats = cli.stats && hl.quiet < 2;
+    hl.summaries = summary_lines || stats || cli.fail_on.is_some();
+    let mut total = Summary::default();
+    let mut per_file = Vec::new();
+    // a live stream piped in is followed much like a file with --follow
+    let piped_stdin = inputs.contains(&InputSource::Stdin)
+        && !io::stdin().is_terminal()
+        && std::fs::metadata("/dev/stdin").is_ok_and(|m| !m.is_file());
+    let mirror: Option<(String, Box<dyn Write + Send>)> = match (&cli.mirror, cli.mirror_fd) {
+        (Some(path), _) => match std::fs::OpenOptions::new().append(true).create(true).open(path) {
+            Ok(file) => Some((path.clone(), Box::new(file))),
+            Err(e) => return Err(format!("failed to open --mirror {}: {}", path, e)),
+        },
+        (None, Some(fd)) => match term::fd_file(fd) {
+            Some(file) => Some((format!("descriptor {}", fd), Box::new(file))),
+            None => return Err(format!("'--mirror-fd {}': no such open descriptor", fd)),
+        },
+        (None, None) => None,
+    };
+    let primary: Box<dyn Write + Send> = match cli.crlf {
+        true => Box::new(Crlf::new(io::stdout())),
+        false => Box::new(io::stdout()),
+    };
+    let primary: Box<dyn Write + Send> = match stdout_depth {
+        ColorDepth::None if depth != ColorDepth::None => Box::new(Plain::new(primary)),
+        _ => primary,
+    };
+    let primary: Box<dyn Write + Send> = match &cli.output {
+        Some(path) => {
+            let file = OutputFile::open(path, cli.append)
+                .map_err(|e| format!("failed to open --output {}: {}", path, e))?;
+            let file: Box<dyn Write + Send> = match cli.output_color {
+                true => Box::new(file),
+                false => Box::new(Plain::new(file)),
+            };
+            match cli.output_only {
+                true => file,
+                false => Box::new(Tee::new(primary, Some((format!("--output {}", path), file)))),
+            }
+        }
+        None => primary,
+    };
+    let stdout = Tee::new(primary, mirror);
+    let stdout: Box<dyn Write + Send> = match cli.output_format {
+        OutputFormat::Html => {
+            let light = std::ptr::eq(palette, &LIGHT_PALETTE);
+            Box::new(Html::new(stdout, &hl.loaded().styles, light, cli.html_standalone))
+        }
+        OutputFormat::Ansi => Box::new(stdout),
+    };
+    let mut output = Output::new(stdout, line_flush);
+    if !line_flush {
+        let nudged = output.clone();
+        term::on_sigusr1(move || {
+            let _ = nudged.clone().flush();
+        });
+    }
+    let following = piped_stdin || watch.is_some() || cli.follow;
+    let keys = ((piped_stdin || cli.follow) && hl.quiet == 0 && io::stdout().is_terminal())
+        .then(|| start_marks(&hl.loaded().styles, hl.width.clone(), output.clone()))
+        .flatten();
+    hl.marks = keys.is_some();
+    if let Some(window) = &mut hl.since {
+        window.following = following;
+    }
+    if let Some(budget) = &mut hl.budget {
+        budget.streaming = following;
+    }
+    if let Some(tail) = &mut hl.tail {
+        tail.streaming = piped_stdin;
+    }
+    let reload = hl.settings.reload.clone();
+    if hl.settings.reloadable() {
+        term::on_sighup(move || reload.store(true, std::sync::atomic::Ordering::Relaxed));
+    }
+    let metrics_server = cli.metrics_listen.map(|addr| {
+        let metrics = std::sync::Arc::new(Metrics::default());
+        hl.metrics = Some(metrics.clone());
+        MetricsServer::start(&addr, metrics)
+            .map_err(|e| format!("failed to listen on {} for --metrics-listen: {}", addr, e))
+    });
+    let metrics_server = metrics_server.transpose()?;
+    if cli.gutter {
+        let utf8 = utf8_locale(|name| env::var(name).ok());
+        hl.gutter = Some(Gutter::new(cli.gutter_glyph.as_deref(), &hl.loaded().styles, utf8));
+        hl.settings.set_styles(Styles::new(palette, ColorDepth::None));
+    }
+    if io::stdout().is_terminal() && (hl.wrap || hl.columns.is_some() || hl.marks) {
+        hl.width.follow(terminal_columns);
+    }
+    if cli.demo {
+        hl.max_line_bytes = hl.max_line_bytes.min(DEMO_LINE_BYTES);
+        let shown = demo_log(&hl)
+            .iter()
+            .try_for_each(|section| process_reader(section.as_bytes(), &hl, &mut output).map(drop));
+        if let Err(e) = shown.and_then(|_| output.flush()) {
+            if output_closed() {
+                end_closed();
+            }
+            return Err(format!("write error: {}", e));
+        }
+        return Ok(Outcome::default());
+    }
+    if let Some(path) = &cli.audit {
+        let metrics = hl.metrics.get_or_insert_with(Default::default).clone();
+        let config =
+            audit_config(full_args, hl.settings.config.as_deref(), |var| env::var(var).ok());
+        match Audit::start(path, config, metrics) {
+            Ok(audit) => {
+                let _ = AUDIT.set(audit.clone());
+                hl.audit = Some(audit);
+            }
+            Err(e) => return Err(format!("failed to open --audit {}: {}", path, e)),
+        }
+    }
+    if stats && following {
+        // counted as read, as the stream's summary never comes back
+        let _ = INTERRUPTED_STATS.set(InterruptedStats {
+            metrics: hl.metrics.get_or_insert_with(Default::default).clone(),
+            format: cli.stats_format,
+            output: cli.stats_output.clone(),
+            styles: hl.loaded().styles.clone(),
+            started,
+        });
+    }
+    let styles = hl.loaded().styles.clone();
+    if let Some(window) = &mut hl.squeeze {
+        window.following = watch.is_some() || cli.follow;
+        if following {
+            let live = std::sync::Arc::new(std::sync::Mutex::new(Squeeze::new(window.size)));
+            start_squeeze_ticks(live.clone(), styles.clone(), output.clone());
+            window.live = Some(live);
+        }
+    }
+    // the lines read before Ctrl+C aren't left in the buffer, nor a count
+    // `--squeeze` was holding
+    let interrupted = output.clone();
+    let squeezed = hl.squeeze.as_ref().and_then(|window| window.live.clone());
+    term::on_interrupt(move || {
+        if let Some(notice) = squeezed.as_ref().and_then(|s| s.try_lock().ok()?.take_notice()) {
+            let mut painted = Vec::new();
+            // writing into a Vec can't fail
+            let _ = write_notice(&mut painted, Some(notice), &styles);
+            interrupted.try_write(&painted);
+        }
+        interrupted.try_flush();
+        end_interrupted();
+        exit(130);
+    });
+    if let Some(mut watch) = watch {
+        let mut watcher = file_watcher(&watch.dir);
+        let watched = watch.run(&hl, &mut output, watcher.as_mut());
+        let _ = flush_squeeze(&hl, &mut output).and_then(|_| output.flush());
+        if let Err(e) = watched {
+            if output_closed() {
+                end_closed();
+            }
+            eprintln!("error following {}: {}", watch.dir.display(), e);
+        }
+        drop(metrics_server);
+        end_audit("failed");
+        return Ok(Outcome { failed: true, found: 0 });
+    }
+    let retry = Retry { enabled: cli.retry, timeout: cli.retry_timeout };
+    if let (true, Some(InputSource::File(path))) = (cli.follow, inputs.first()) {
+        let followed = follow_file(path, &hl, retry, &mut output);
+        let _ = flush_squeeze(&hl, &mut output).and_then(|_| output.flush());
+        if let Err(e) = followed {
+            if output_closed() {
+                end_closed();
+            }
+            eprintln!("{}", e);
+        }
+        drop(metrics_server);
+        end_audit("failed");
+        return Ok(Outcome { failed: true, found: 0 });
+    }
+    let mut done = |source: &InputSource, result: Result<Summary, String>| match result {
+        Ok(summary) if hl.summaries => {
+            failed |= summary.read_failed;
+            let label = match source {
+                InputSource::Stdin => "(stdin)",
+                InputSource::File(path) => path,
+            };
+            total.add(&summary);
+            // `tail a.log b.log | logcolor` is about a.log and b.log
+            let parts = match summary.tail_files.is_empty() {
+                true => vec![(label.to_string(), summary)],
+                false => summary.tail_files,
+            };
+            for (label, summary) in parts {
+                if summary_lines {
+                    let _ = summary
+                        .write(&mut output.clone(), &label, &hl.loaded().styles)
+                        .or_else(write_failed);
+                }
+                per_file.push((label, summary));
+            }
+        }
+        Ok(summary) => failed |= summary.read_failed,
+        Err(_) if output_closed() => {}
+        Err(e) => {
+            eprintln!("{}", e);
+            failed = true;
+        }
+    };
+    // stdin can't be read ahead of its turn; it may never end
+    if cli.jobs > 1 && !inputs.contains(&InputSource::Stdin) {
+        process_in_parallel(&inputs, cli.jobs, &hl, retry, &mut output.clone(), &mut done);
+    } else {
+        for source in inputs.iter().take_while(|_| !output_closed()) {
+            done(source, process_input(source, &hl, retry, &mut output.clone()));
+        }
+    }
+    if summary_lines && inputs.len() > 1 {
+        let label = format!("total, {} files", inputs.len());
+        let _ = total.write(&mut output, &label, &hl.loaded().styles).or_else(write_failed);
+    }
+    if stats {
+        let output = cli.stats_output.as_deref();
+        let counts = (&total, per_file.as_slice());
+        let elapsed = started.elapsed();
+        if let Err(e) = write_stats(cli.stats_format, output, counts, elapsed, &hl.loaded().styles)
+        {
+            eprintln!("failed to write stats: {}", e);
+            failed = true;
+        }
+    }
+    if let Some(snapshot) = &hl.snapshot {
+        let mut snapshot = snapshot.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = snapshot.write() {
+            eprintln!("failed to write snapshot {}: {}", snapshot.path.display(), e);
+            failed = true;
+        }
+    }
+    drop(keys);
+    drop(metrics_server);
+    if cli.output_format == OutputFormat::Html {
+        let _ = output.write_all(HTML_END.as_bytes());
+    }
+    let _ = output.flush().or_else(write_failed);
+    if output_closed() {
+        end_closed();
+    }
+    failed |= OUTPUT_FAILED.load(std::sync::atomic::Ordering::Relaxed);
+    end_audit(if failed { "failed" } else { "finished" });
+    let found = cli.fail_on.map_or(0, |level| total.at_least(level));
+    Ok(Outcome { failed, found })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn highlighter(palette: &Palette, depth: ColorDepth, format: InputFormat) -> Highlighter {
+        Highlighter { format, ..Highlighter::new(Styles::new(palette, depth)) }
+    }
+
+    fn render(hl: &Highlighter, line: &str) -> String {
+        let mut out = Vec::new();
+        print_colored_line(&mut out, line, hl).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn library_api() {
+        let hl = Highlighter::builder().color(true).build();
+        let cli = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        for line in demo_log(&cli).iter().flat_map(|section| section.lines()) {
+            // what the CLI draws by default, less the newline
+            assert_eq!(hl.highlight_line(line) + "\n", render(&cli, line), "{:?}", line);
+        }
+        let line = "12:03:04 [WARNING] disk at 91%";
+        let found = hl.find_level(line).unwrap();
+        assert_eq!((&line[found.range], found.level), ("[WARNING]", Level::Warn));
+        let found = hl.find_level("upstream timed out").unwrap();
+        assert_eq!((found.range, found.level), (9..18, Level::Warn));
+        assert_eq!(hl.find_level("nothing to see"), None);
+
+        let plain = Highlighter::builder().build();
+        assert!(matches!(plain.highlight_line(line), std::borrow::Cow::Borrowed(_)));
+        let mut out = Vec::new();
+        plain.write_highlighted(&mut out, line).unwrap();
+        assert_eq!(out, format!("{}\n", line).as_bytes());
+
+        let errors = Highlighter::builder().color(true).min_level(Level::Error).build();
+        assert_eq!(errors.highlight_line(line), line);
+        assert_ne!(errors.highlight_line("ERROR: x"), "ERROR: x");
+        let light = Highlighter::builder().theme(Theme::Light).color(true).build();
+        let magenta = MAGENTA.fg_sgr(ColorDepth::Basic);
+        assert_eq!(light.highlight_line("WARN x"), format!("{BOLD}{magenta}WARN{RESET} x"));
+    }
+
+    #[test]
+    fn colorfgbg_background() {
+        assert_eq!(background_from_colorfgbg("15;0"), Some(Background::Dark));
+        assert_eq!(background_from_colorfgbg("0;15"), Some(Background::Light));
+        assert_eq!(background_from_colorfgbg("0;default;7"), Some(Background::Light));
+        assert_eq!(background_from_colorfgbg("7;8"), Some(Background::Dark));
+        assert_eq!(background_from_colorfgbg("default"), None);
+        assert_eq!(background_from_colorfgbg(""), None);
+    }
+
+    #[test]
+    fn osc11_reply_parsing() {
+        let white = parse_osc11_response(b"\x1b]11;rgb:ffff/ffff/ffff\x1b\\").unwrap();
+        assert_eq!(background_from_rgb(white), Background::Light);
+        let black = parse_osc11_response(b"\x1b]11;rgb:0000/0000/0000\x07").unwrap();
+        assert_eq!(background_from_rgb(black), Background::Dark);
+        // short channels and trailing DA1 reply from the sentinel query
+        let solarized = parse_osc11_response(b"\x1b]11;rgb:fd/f6/e3\x1b\\\x1b[?62;22c").unwrap();
+        assert_eq!(background_from_rgb(solarized), Background::Light);
+        // a terminal that only answered DA1
+        assert_eq!(parse_osc11_response(b"\x1b[?1;2c"), None);
+        assert_eq!(parse_osc11_response(b"\x1b]11;rgb:zz/00/00\x07"), None);
+    }
+
+    #[test]
+    fn light_palette_avoids_yellow() {
+        let mut out = Vec::new();
+        let hl = highlighter(&LIGHT_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        print_colored_line(&mut out, "WARN disk almost full", &hl).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(!out.contains(&YELLOW.fg_sgr(ColorDepth::Basic)));
+        assert!(out.contains(&LIGHT_PALETTE.warn.fg_sgr(ColorDepth::Basic)));
+    }
+
+    fn term_info(term: &str, colors: Option<i32>) -> TermInfo {
+        TermInfo {
+            term: Some(term.to_string()),
+            colorterm: None,
+            no_color: false,
+            stdout_tty: true,
+            terminfo_colors: colors,
+            term_program: None,
+            console_escapes: None,
+        }
+    }
+
+    #[test]
+    fn color_depth_decision() {
+        let auto = |info: &TermInfo| decide_color_depth(ColorMode::Auto, info).0;
+        assert_eq!(auto(&term_info("dumb", None)), ColorDepth::None);
+        assert_eq!(auto(&term_info("xterm-mono", Some(-1))), ColorDepth::None);
+        assert_eq!(auto(&term_info("xterm", Some(8))), ColorDepth::Basic);
+        assert_eq!(auto(&term_info("xterm-256color", Some(256))), ColorDepth::Ansi256);
+        assert_eq!(auto(&term_info("screen-256color", None)), ColorDepth::Ansi256);
+        assert_eq!(auto(&term_info("vt220", None)), ColorDepth::Basic);
+
+        let truecolor =
+            TermInfo { colorterm: Some("truecolor".into()), ..term_info("xterm", Some(8)) };
+        assert_eq!(auto(&truecolor), ColorDepth::TrueColor);
+        let piped = TermInfo { stdout_tty: false, ..term_info("xterm", Some(8)) };
+        assert_eq!(auto(&piped), ColorDepth::None);
+        let no_color = TermInfo { no_color: true, ..term_info("xterm", Some(8)) };
+        assert_eq!(auto(&no_color), ColorDepth::None);
+
+        // --color=always wins over a dumb or piped terminal, --color=never over everything
+        let always = decide_color_depth(ColorMode::Always, &term_info("dumb", None)).0;
+        assert_eq!(always, ColorDepth::Basic);
+        assert_eq!(decide_color_depth(ColorMode::Always, &piped).0, ColorDepth::Basic);
+        let never = decide_color_depth(ColorMode::Never, &term_info("xterm-256color", Some(256))).0;
+        assert_eq!(never, ColorDepth::None);
+
+        // a Windows console: no TERM, and colors only once it takes escapes
+        let console = TermInfo { term: None, console_escapes: Some(true), ..term_info("", None) };
+        assert_eq!(auto(&console), ColorDepth::TrueColor);
+        let old_console = TermInfo { console_escapes: Some(false), ..console };
+        assert_eq!(auto(&old_console), ColorDepth::None);
+        let (always, why) = decide_color_depth(ColorMode::Always, &old_console);
+        assert_eq!(always, ColorDepth::Basic);
+        assert!(why.contains("doesn't take escape sequences"));
+        let msys = TermInfo { console_escapes: Some(true), ..term_info("xterm", Some(8)) };
+        assert_eq!(auto(&msys), ColorDepth::Basic);
+    }
+
+    #[test]
+    fn dumb_terminal_output_is_plain() {
+        let mut out = Vec::new();
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        print_colored_line(&mut out, "[ERROR] boom", &hl).unwrap();
+        assert_eq!(out, b"[ERROR] boom\n");
+    }
+
+    #[test]
+    fn rgb_downconversion() {
+        assert_eq!(rgb_to_basic((250, 10, 10)), 1);
+        assert_eq!(rgb_to_basic((255, 200, 0)), 3);
+        assert_eq!(rgb_to_basic((10, 10, 10)), 0);
+        assert_eq!(rgb_to_basic((240, 240, 240)), 7);
+        assert_eq!(rgb_to_basic((30, 60, 200)), 4);
+        assert_eq!(rgb_to_indexed((255, 0, 0)), 196);
+        assert_eq!(rgb_to_indexed((128, 128, 128)), 244);
+        let orange = Color::Rgb(255, 135, 0);
+        assert_eq!(orange.downconvert(ColorDepth::TrueColor), orange);
+        assert_eq!(orange.downconvert(ColorDepth::Ansi256), Color::Indexed(208));
+        assert_eq!(orange.downconvert(ColorDepth::Basic), Color::Basic(3));
+        assert_eq!(Color::Indexed(196).downconvert(ColorDepth::Basic), Color::Basic(1));
+        assert_eq!(Color::Basic(9).downconvert(ColorDepth::Basic), Color::Basic(1));
+        assert_eq!(Color::Indexed(208).fg_sgr(ColorDepth::Basic), "\x1b[33m");
+        assert_eq!(orange.fg_sgr(ColorDepth::TrueColor), "\x1b[38;2;255;135;0m");
+    }
+
+    #[test]
+    fn terminfo_colors_number() {
+        // legacy format: 12-byte header, names, bools, then aligned numbers
+        let mut entry = Vec::new();
+        for v in [0o432i16, 6, 1, 15, 0, 0] {
+            entry.extend_from_slice(&v.to_le_bytes());
+        }
+        entry.extend_from_slice(b"xterm\0");
+        entry.push(1);
+        entry.push(0); // alignment padding
+        for i in 0..15i16 {
+            entry.extend_from_slice(&(if i == 13 { 256i16 } else { -1 }).to_le_bytes());
+        }
+        assert_eq!(parse_terminfo_colors(&entry), Some(256));
+        // a monochrome entry with fewer numeric capabilities than `colors`
+        entry[6..8].copy_from_slice(&5i16.to_le_bytes());
+        assert_eq!(parse_terminfo_colors(&entry), Some(-1));
+        assert_eq!(parse_terminfo_colors(b"garbage"), None);
+    }
+
+    const RFC5424_LINE: &str =
+        "<165>1 2024-05-01T12:03:04.003Z host app 1234 ID47 [exampleSDID@32473 iut=\"3\"] message";
+
+    #[test]
+    fn rfc5424_parsing() {
+        let rec = parse_rfc5424(RFC5424_LINE, true).unwrap();
+        assert_eq!(rec.severity, Some(5));
+        assert_eq!(&RFC5424_LINE[..rec.pri_end], "<165>");
+        assert_eq!(
+            &RFC5424_LINE[rec.pri_end..rec.header_end],
+            "1 2024-05-01T12:03:04.003Z host app 1234 ID47"
+        );
+        assert_eq!(&RFC5424_LINE[rec.sd_start..rec.sd_end], "[exampleSDID@32473 iut=\"3\"]");
+        assert_eq!(&RFC5424_LINE[rec.msg_start.unwrap()..], "message");
+
+        // nil structured data, several elements, escaped bracket in a value, no MSG
+        let nil = parse_rfc5424("<34>1 - - su - ID47 - 'su root' failed", true).unwrap();
+        assert_eq!(nil.severity, Some(2));
+        let multi = "<14>1 - h a - - [a@1 x=\"q\\]z\"][b@2] hi";
+        let rec = parse_rfc5424(multi, true).unwrap();
+        assert_eq!(&multi[rec.sd_start..rec.sd_end], "[a@1 x=\"q\\]z\"][b@2]");
+        assert_eq!(parse_rfc5424("<14>1 - h a - - -", true).unwrap().msg_start, None);
+
+        // not 5424: BSD syslog, bad PRI, missing fields, garbage after SD
+        assert_eq!(parse_rfc5424("<34>Oct 11 22:14:15 mymachine su: failed", true), None);
+        assert_eq!(parse_rfc5424("<192>1 - - - - - - x", true), None);
+        assert_eq!(parse_rfc5424("<1a>1 - - - - - - x", true), None);
+        assert_eq!(parse_rfc5424("<14>1 - h a", true), None);
+        assert_eq!(parse_rfc5424("<14>1 - h a - - [x@1]junk", true), None);
+        // forced mode accepts a missing PRI
+        assert_eq!(parse_rfc5424("1 - h a - - - ERROR x", true), None);
+        assert_eq!(parse_rfc5424("1 - h a - - - ERROR x", false).unwrap().severity, None);
+    }
+
+    #[test]
+    fn rfc5424_rendering() {
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let out = render(&hl, "<163>1 - web app - - - db ERROR: gone");
+        // severity 3 colors the PRI red, the header is dimmed
+        assert!(out.starts_with("\x1b[1m\x1b[31m<163>\x1b[0m\x1b[2m1 - web app - -\x1b[0m"));
+        assert!(out.ends_with("db \x1b[1m\x1b[31mERROR:\x1b[0m gone\n"));
+
+        // level words in the header must not be picked up; app-name is "error-svc"
+        let out = render(&hl, "<14>1 - h error-svc - - - all good");
+        assert!(out.contains("\x1b[2m1 - h error-svc - -\x1b[0m"));
+        assert!(out.ends_with(" all good\n"));
+
+        let plain = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Plain);
+        assert!(!render(&plain, RFC5424_LINE).contains(DIM));
+        // lines that fail to parse keep the generic behavior
+        assert_eq!(render(&hl, "<bad> INFO x"), "<bad> \x1b[1m\x1b[32mINFO\x1b[0m x\n");
+    }
+
+    #[test]
+    fn syslog_priorities() {
+        // every facility with every severity
+        let levels = [Level::Error, Level::Error, Level::Error, Level::Error, Level::Warn];
+        let levels = [&levels[..], &[Level::Info, Level::Info, Level::Debug]].concat();
+        for pri in 0..=191 {
+            let line = format!("<{}>May  1 12:03:04 host app: done", pri);
+            let end = line.find('>').unwrap() + 1;
+            assert_eq!(parse_pri(&line), Some((end, (pri % 8) as u8)));
+            assert_eq!(line_level(&line, &[]), Some(levels[pri % 8]), "{}", line);
+        }
+        for line in ["<1234>x", "<>x", "<1a>x", "<192>x", " <11>x", "x <11>", "<11"] {
+            assert_eq!(parse_pri(line), None, "{}", line);
+        }
+        assert_eq!(line_level("<192> WARN x", &[]), Some(Level::Warn));
+
+        // the PRI wins over a level word later in the line
+        let rfc5424 = "<165>1 2024-05-01T12:03:04Z web app - - - ERROR budget spent";
+        assert_eq!(line_level(rfc5424, &[]), Some(Level::Info));
+        let bsd = "<12>May  1 12:03:04 web app: INFO disk 91% full";
+        assert_eq!(line_level(bsd, &[]), Some(Level::Warn));
+        assert_eq!(line_cells(bsd, InputFormat::Auto, &[]).level, Some(Level::Warn));
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let out = render(&hl, "<11>May  1 12:03:04 web app: gone");
+        assert_eq!(out, "\x1b[1m\x1b[31m<11>\x1b[0mMay  1 12:03:04 web app: gone\n");
+        // a PRI line starts an event of its own
+        let mut out = Vec::new();
+        let input = "ERROR first\n<14>second\n";
+        process_reader(input.as_bytes(), &hl, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.ends_with("\x1b[1m\x1b[32m<14>\x1b[0msecond\n"), "{:?}", out);
+
+        #[rustfmt::skip]
+        let names = [
+            (0, "kern.emerg"), (13, "user.notice"), (22, "mail.info"), (38, "auth.info"),
+            (85, "authpriv.notice"), (134, "local0.info"), (165, "local4.notice"),
+            (191, "local7.debug"),
+        ];
+        for (pri, name) in names {
+            let (named, _) = name_pri(&format!("<{}>x", pri), &[], &hl.loaded().styles).unwrap();
+            assert_eq!(named, format!("[{}] x", name));
+        }
+        hl.syslog_names = true;
+        let out = render(&hl, "<163>1 - web app - - - db gone");
+        let tag = "\x1b[2m[local4.\x1b[0m\x1b[1m\x1b[31merr\x1b[0m\x1b[2m]\x1b[0m ";
+        assert!(out.starts_with(&format!("{}\x1b[2m1 - web app - -\x1b[0m", tag)), "{:?}", out);
+        let plain = Highlighter {
+            syslog_names: true,
+            ..highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto)
+        };
+        assert_eq!(render(&plain, "<30> started"), "[daemon.info] started\n");
+        assert_eq!(render(&plain, "<1234>x"), "<1234>x\n");
+    }
+
+    #[test]
+    fn logfmt_lines() {
+        let values = |line: &str| -> Vec<(String, String)> {
+            let pairs = logfmt_pairs(line).into_iter();
+            pairs.map(|p| (line[p.key].to_string(), line[p.value].to_string())).collect()
+        };
+        let pair = |k: &str, v: &str| (k.to_string(), v.to_string());
+        // quoted values keep their spaces, `=` and escaped quotes
+        let quoted = r#"level=info msg="said \"a=b\" twice" path=/x empty= q="" cut="no end"#;
+        assert_eq!(
+            values(quoted),
+            [
+                pair("level", "info"),
+                pair("msg", r#"said \"a=b\" twice"#),
+                pair("path", "/x"),
+                pair("empty", ""),
+                pair("q", ""),
+                pair("cut", "no end"),
+            ]
+        );
+        // words that aren't pairs are passed over
+        let mixed = "12:03:04 level=warn msg=retry then the cache said ERROR twice --x=1 =y";
+        assert_eq!(values(mixed), [pair("level", "warn"), pair("msg", "retry")]);
+
+        let level = |line: &str, forced| logfmt_level(line, &logfmt_pairs(line), forced);
+        assert_eq!(level(mixed, false), Some((15..19, Level::Warn)));
+        assert_eq!(level("ts=1 Severity=ERR msg=x", false).map(|(_, l)| l), Some(Level::Error));
+        // no level key, one pair only, or a level key naming no level
+        assert_eq!(level("ts=1 msg=\"an error\" count=3", false), None);
+        assert_eq!(level("set level=error to see more", false), None);
+        assert_eq!(level("set level=error to see more", true), Some((10..15, Level::Error)));
+        assert_eq!(level("level=high risk=3", false), None);
+        assert_eq!(line_level("level=warning msg=\"error budget spent\"", &[]), Some(Level::Warn));
+
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let loaded = hl.loaded();
+        let (yellow, bold) = (loaded.styles.color(Level::Warn), BOLD);
+        let out = render(&hl, mixed);
+        assert_eq!(
+            out,
+            format!(
+                "12:03:04 level={}{}warn{} msg={}retry{} then the cache said ERROR twice --x=1 =y\n",
+                bold, yellow, RESET, bold, RESET
+            )
+        );
+        // prose with an `=` in it stays prose
+        let prose = "checked x=1 and found an ERROR";
+        assert!(render(&hl, prose).ends_with(&format!(
+            "{}{}ERROR{}\n",
+            bold,
+            hl.loaded().styles.color(Level::Error),
+            RESET
+        )));
+
+        let mut forced = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Logfmt);
+        forced.dim_logfmt_keys = true;
+        let out = render(&forced, "ts=1 msg=\"a b\"");
+        assert_eq!(out, format!("{0}ts={1}1 {0}msg={1}\"{2}a b{1}\"\n", DIM, RESET, bold));
+        assert_eq!(render(&forced, "no pairs here"), "no pairs here\n");
+
+        let sample = "ts=1 level=info msg=a\nts=2 level=debug msg=b\nts=3 lvl=warn msg=c\n";
+        assert_eq!(detect_format(sample.as_bytes()).chosen, InputFormat::Logfmt);
+    }
+
+    #[test]
+    fn json_parsing() {
+        let v =
+            parse_json(r#" {"a": [1, -2.5e3, true, null], "b": {"c": "x\"\u00e9\ud83d\ude00"}} "#)
+                .unwrap();
+        let expected = Json::Object(vec![
+            (
+                "a".into(),
+                Json::Array(vec![
+                    Json::Number("1".into()),
+                    Json::Number("-2.5e3".into()),
+                    Json::Bool(true),
+                    Json::Null,
+                ]),
+            ),
+            ("b".into(), Json::Object(vec![("c".into(), Json::String("x\"é😀".into()))])),
+        ]);
+        assert_eq!(v, expected);
+        for bad in ["", "{", "{\"a\":}", "[1,]", "01x", "\"\\q\"", "{} x", "\"a\nb\"", "tru"] {
+            assert_eq!(parse_json(bad), None, "{:?}", bad);
+        }
+        let deep = "[".repeat(1000) + &"]".repeat(1000);
+        assert_eq!(parse_json(&deep), None);
+    }
+
+    #[test]
+    fn unix_time_formatting() {
+        assert_eq!(format_unix_micros(0), "1970-01-01 00:00:00.000");
+        assert_eq!(format_unix_micros(1_714_565_000_123_456), "2024-05-01 12:03:20.123");
+        assert_eq!(format_unix_micros(951_782_400_000_000), "2000-02-29 00:00:00.000");
+    }
+
+    #[test]
+    fn journald_json_entries() {
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Journald);
+        let line = r#"{"__REALTIME_TIMESTAMP":"1714565000123456","PRIORITY":"3","_SYSTEMD_UNIT":"api.service","MESSAGE":"request failed\n  at handler\n  at main"}"#;
+        let out = render(&hl, line);
+        let red = "\x1b[31m";
+        assert_eq!(
+            out,
+            format!(
+                "\x1b[2m2024-05-01 12:03:20.123\x1b[0m \x1b[1m{red}api.service\x1b[0m: request failed\n\
+                 {pad}{red}  at handler\x1b[0m\n{pad}{red}  at main\x1b[0m\n",
+                pad = " ".repeat(37)
+            )
+        );
+
+        // missing fields, byte-array MESSAGE, unknown PRIORITY
+        let plain = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Journald);
+        assert_eq!(
+            render(&plain, r#"{"MESSAGE":[104,105],"SYSLOG_IDENTIFIER":"sshd"}"#),
+            "sshd: hi\n"
+        );
+        assert_eq!(render(&plain, r#"{"PRIORITY":"42"}"#), "\n");
+        assert_eq!(render(&plain, "{}"), "\n");
+        // anything that is not a JSON object goes down the generic path
+        assert_eq!(render(&plain, "plain text ERROR"), "plain text ERROR\n");
+        assert_eq!(render(&plain, "[1, 2]"), "[1, 2]\n");
+    }
+
+    #[test]
+    fn journald_export_records() {
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Journald);
+        let mut record = ExportRecord::default();
+        let mut out = Vec::new();
+        for line in ["__REALTIME_TIMESTAMP=0", "SYSLOG_IDENTIFIER=cron", "MESSAGE=a=b"] {
+            assert!(ExportRecord::is_field(line));
+            record.push(line);
+        }
+        assert!(!ExportRecord::is_field("lower=case"));
+        assert!(!ExportRecord::is_field("=x"));
+        record.flush(&mut out, &hl).unwrap();
+        record.flush(&mut out, &hl).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1970-01-01 00:00:00.000 cron: a=b\n");
+    }
+
+    #[test]
+    fn gelf_records() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Gelf);
+        let line = r#"{"version":"1.1","host":"web-1","short_message":"db down","full_message":"trace:\n  at x","timestamp":1714565000.5,"level":3,"_user_id":9001,"_ctx":{"a":[1,"b"]}}"#;
+        assert_eq!(
+            render(&hl, line),
+            "\x1b[2m2024-05-01 12:03:20.500\x1b[0m \x1b[1m\x1b[31mweb-1\x1b[0m: db down\n"
+        );
+        hl.verbose = true;
+        let out = render(&hl, line);
+        let extra: Vec<&str> = out.lines().skip(1).collect();
+        assert_eq!(
+            extra,
+            [
+                "\x1b[2m  full_message: trace:",
+                "                  at x\x1b[0m",
+                "\x1b[2m  _user_id: 9001\x1b[0m",
+                "\x1b[2m  _ctx: {\"a\":[1,\"b\"]}\x1b[0m",
+            ]
+        );
+
+        // level defaults to 1 (alert), a bogus level leaves the host uncolored
+        let out = render(&hl, r#"{"host":"h","short_message":"x"}"#);
+        assert!(out.starts_with("\x1b[1m\x1b[31mh\x1b[0m"));
+        let out = render(&hl, r#"{"host":"h","short_message":"x","level":"loud"}"#);
+        assert_eq!(out, "h: x\n");
+        // not GELF at all
+        assert_eq!(render(&hl, "\"just\""), "\"just\"\n");
+    }
+
+    #[test]
+    fn json_serialization_roundtrip() {
+        let text = r#"{"a":"q\"\\\n\u0001","b":[true,null,-1.5],"c":{}}"#;
+        assert_eq!(parse_json(text).unwrap().to_string(), text);
+    }
+
+    /// the level word picked for `line` and the level it maps to.
+    fn detected(line: &str) -> Option<(&str, Option<Level>)> {
+        find_level(line).map(|(pos, token)| {
+            let raw = token.trim_matches(|c: char| "[]():- ".contains(c)).to_uppercase();
+            (&line[pos..pos + token.len()], token_level(&raw))
+        })
+    }
+
+    #[test]
+    fn serilog_levels() {
+        // Serilog console sink, default `[{Timestamp:HH:mm:ss} {Level:u3}] {Message:lj}`
+        let console = [
+            ("[12:03:04 INF] Now listening on: http://localhost:5000", "INF", Level::Info),
+            ("[12:03:04 WRN] Slow request GET /api took 5012 ms", "WRN", Level::Warn),
+            ("[12:03:04 ERR] An unhandled exception has occurred", "ERR", Level::Error),
+            ("[12:03:04 FTL] Host terminated unexpectedly", "FTL", Level::Fatal),
+            ("[12:03:04 DBG] Hosting starting", "DBG", Level::Debug),
+            ("[12:03:04 VRB] Request matched endpoint", "VRB", Level::Trace),
+            // a later, more alarming word must not beat the level column
+            ("[12:03:04 INF] Retrying after ERROR from upstream", "INF", Level::Info),
+        ];
+        for (line, token, level) in console {
+            assert_eq!(detected(line), Some((token, Some(level))), "{}", line);
+        }
+        // Serilog file sink, `{Timestamp:yyyy-MM-dd HH:mm:ss.fff zzz} [{Level:u3}] {Message:lj}`
+        let file = "2024-05-01 12:03:04.003 +02:00 [WRN] Cache miss ratio high";
+        assert_eq!(detected(file), Some(("[WRN]", Some(Level::Warn))));
+
+        // the abbreviations must not fire inside words
+        assert_eq!(detected("value was INFINITY"), None);
+        assert_eq!(detected("INFLIGHT requests: 3"), None);
+        assert_eq!(detected("see DBGHELP.DLL"), None);
+        assert_eq!(detected("HTTPS_INF_x"), None);
+        // nor do the long spellings, unless --loose
+        assert_eq!(detected("information only"), None);
+        let loose = LevelWords::new(&[], true);
+        assert_eq!(loose.scan("information only"), Some((0, "info")));
+        assert_eq!(loose.scan("value was INFINITY"), None);
+    }
+
+    #[test]
+    fn npm_levels() {
+        let lines = [
+            ("2024-05-01 12:03:04 [http] GET /api 200", "[http]", Level::Http),
+            ("silly: cache probe", "silly:", Level::Silly),
+            ("verbose: loaded 12 plugins", "verbose:", Level::Trace),
+            ("http: POST /login 302 4ms", "http:", Level::Http),
+            ("npm verbose cli /usr/bin/node", "verbose", Level::Trace),
+        ];
+        for (line, token, level) in lines {
+            assert_eq!(detected(line), Some((token, Some(level))), "{}", line);
+        }
+        // URLs and protocol versions are not the http level
+        assert_eq!(detected("fetching http://example.com/x"), None);
+        assert_eq!(detected("GET /index.html HTTP/1.1 200"), None);
+        assert_eq!(detected("proxy: https://a.b"), None);
+        assert_eq!(detected("VERBOSELY described"), None);
+
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Plain);
+        assert!(render(&hl, "[http] GET /").starts_with("\x1b[1m\x1b[2m\x1b[34m[http]"));
+        let silly = render(&hl, "silly: x");
+        assert!(silly.starts_with(&format!("{}{}", BOLD, hl.loaded().styles.color(Level::Trace))));
+    }
+
+    #[test]
+    fn level_ordering() {
+        assert!(Level::Silly < Level::Trace);
+        assert!(Level::Trace < Level::Debug);
+        assert!(Level::Debug < Level::Http);
+        assert!(Level::Http < Level::Info);
+        assert!(Level::Info < Level::Warn);
+        assert!(Level::Warn < Level::Error);
+        assert!(Level::Error < Level::Fatal);
+        let mut sorted = Level::ALL;
+        sorted.sort();
+        assert_eq!(sorted, Level::ALL);
+        assert!(Level::ALL.iter().enumerate().all(|(i, l)| *l as usize == i));
+    }
+
+    fn render_stream(hl: &Highlighter, input: &str) -> String {
+        let mut out = Vec::new();
+        let mut state = ReaderState::default();
+        for line in input.lines() {
+            process_line(&mut out, line.to_string(), hl, &mut state).unwrap();
+        }
+        finish_reader(&mut out, hl, &mut state).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn jul_levels() {
+        let lines = [
+            ("SEVERE: something broke", Level::Error),
+            ("WARNING: low on disk", Level::Warn),
+            ("CONFIG: pool size 10", Level::Info),
+            ("FINE: cache hit", Level::Debug),
+            ("FINER: ENTRY com.x.Y method", Level::Trace),
+            ("FINEST: loop 3", Level::Trace),
+        ];
+        for (line, level) in lines {
+            assert_eq!(detected(line).and_then(|d| d.1), Some(level), "{}", line);
+            assert_eq!(jul_record_level(line), Some(level));
+        }
+        // only at the start of a line
+        assert_eq!(detected("the weather is fine: sunny"), None);
+        assert_eq!(detected("x SEVERE: y"), None);
+        assert_eq!(detected("CONFIGURED: yes"), None);
+    }
+
+    #[test]
+    fn jul_two_line_records() {
+        assert!(is_jul_header("May 01, 2024 12:03:04 PM com.example.Server start"));
+        assert!(is_jul_header("Jan 9, 2024 1:03:04 AM com.example.Server"));
+        assert!(!is_jul_header("May 01, 2024 12:03:04 com.example.Server start"));
+        assert!(!is_jul_header("may 01, 2024 12:03:04 PM x"));
+        assert!(!is_jul_header("SEVERE: x"));
+
+        // output of java.util.logging.SimpleFormatter with its default format
+        let jul = "May 01, 2024 12:03:04 PM com.example.Server handle\n\
+                   SEVERE: something broke\n\
+                   May 01, 2024 12:03:05 PM com.example.Server handle\n\
+                   INFO: recovered\n\
+                   May 01, 2024 12:03:06 PM com.example.Server tail";
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let out = render_stream(&hl, jul);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "\x1b[31mMay 01, 2024 12:03:04 PM com.example.Server handle\x1b[0m");
+        assert_eq!(lines[1], "\x1b[1m\x1b[31mSEVERE:\x1b[0m something broke");
+        assert_eq!(lines[2], "\x1b[32mMay 01, 2024 12:03:05 PM com.example.Server handle\x1b[0m");
+        assert_eq!(lines[3], "\x1b[1m\x1b[32mINFO:\x1b[0m recovered");
+        // a trailing header without its level line is still printed
+        assert_eq!(lines[4], "May 01, 2024 12:03:06 PM com.example.Server tail");
+        assert_eq!(lines.len(), 5);
+    }
+
+    #[test]
+    fn rust_line_parsing() {
+        let env = "[2024-05-01T12:03:04Z ERROR my_crate::module] connection reset";
+        let rec = parse_rust_line(env).unwrap();
+        assert_eq!(&env[rec.timestamp.clone().unwrap()], "2024-05-01T12:03:04Z");
+        assert_eq!(rec.level, Level::Error);
+        assert_eq!(&env[rec.module.clone()], "my_crate::module");
+        assert_eq!(&env[rec.msg_start..], "connection reset");
+        let bare = parse_rust_line("[WARN  my_crate] low memory").unwrap();
+        assert_eq!((bare.timestamp, bare.level), (None, Level::Warn));
+
+        let tracing = "2024-05-01T12:03:04.123456Z ERROR my_crate::module: message";
+        let rec = parse_rust_line(tracing).unwrap();
+        assert_eq!(&tracing[rec.level_range.clone()], "ERROR");
+        assert_eq!(&tracing[rec.module.clone()], "my_crate::module");
+        assert_eq!(&tracing[rec.msg_start..], "message");
+
+        let spans =
+            "2024-05-01T12:03:04.123456Z  INFO request{id=7}:db: app::db: close time.busy=1.2ms";
+        let rec = parse_rust_line(spans).unwrap();
+        assert_eq!(rec.level, Level::Info);
+        assert_eq!(&spans[rec.spans.clone().unwrap()], "request{id=7}:db");
+        assert_eq!(&spans[rec.module.clone()], "app::db");
+        assert_eq!(&spans[rec.msg_start..], "close time.busy=1.2ms");
+
+        for not_rust in [
+            "[2024-05-01T12:03:04Z ERROR] no target",
+            "[12:03:04 INF] serilog",
+            "2024-05-01T12:03:04Z FATAL app: x",
+            "2024-05-01 12:03:04 ERROR app: x",
+            "2024-05-01T12:03:04Z ERROR not a path: x",
+        ] {
+            assert_eq!(parse_rust_line(not_rust), None, "{}", not_rust);
+        }
+    }
+
+    #[test]
+    fn rust_line_rendering() {
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Ansi256, InputFormat::Rust);
+        let line = "2024-05-01T12:03:04.5Z  INFO req{id=7}: app::db: close time.busy=1.2ms";
+        let module = hash_color("app::db", ColorDepth::Ansi256).fg_sgr(ColorDepth::Ansi256);
+        assert_eq!(
+            render(&hl, line),
+            format!(
+                "\x1b[2m2024-05-01T12:03:04.5Z\x1b[0m  \x1b[1m\x1b[32mINFO\x1b[0m \
+                 req{{\x1b[2mid=\x1b[0m7}}: {module}app::db\x1b[0m: close \x1b[2mtime.busy=\x1b[0m1.2ms\n"
+            )
+        );
+        // the same module always gets the same color
+        assert_eq!(
+            hash_color("app::db", ColorDepth::Basic),
+            hash_color("app::db", ColorDepth::Basic)
+        );
+        assert_eq!(kv_key_ranges("a=1 b.c=x =y d= e", 10), [10..12, 14..18]);
+    }
+
+    #[test]
+    fn rust_format_is_sniffed() {
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let input = "starting up\n[2024-05-01T12:03:04Z INFO app] x=1\nplain WARN line";
+        let out = render_stream(&hl, input);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "starting up");
+        assert!(lines[1].contains("\x1b[2mx=\x1b[0m1"));
+        assert_eq!(lines[2], "plain \x1b[1m\x1b[33mWARN\x1b[0m line");
+
+        // too late in the stream to count
+        let late = format!("{}[2024-05-01T12:03:04Z INFO app] x=1", "a\n".repeat(SNIFF_LINES));
+        assert!(!render_stream(&hl, &late).contains("x=\x1b[0m"));
+    }
+
+    #[test]
+    fn postgres_levels() {
+        let line = "2024-05-01 12:00:00 UTC [42] LOG:  checkpoint starting: time";
+        assert_eq!(detected(line), Some(("LOG:", Some(Level::Info))));
+        let line = "2024-05-01 12:00:00 UTC [42] FATAL:  password authentication failed";
+        assert_eq!(detected(line), Some(("FATAL:", Some(Level::Fatal))));
+        assert_eq!(detected("[42] PANIC:  could not write"), Some(("PANIC:", Some(Level::Fatal))));
+        // the two-space label wins over a level word earlier in the line
+        let line = "[42] app=error_reporter LOG:  connection received";
+        assert_eq!(detected(line), Some(("LOG:", Some(Level::Info))));
+        assert_eq!(detected("please log in again"), None);
+        assert_eq!(detected("Log: rotated"), Some(("Log:", Some(Level::Info))));
+        assert_eq!(detected("don't panic"), Some(("panic", Some(Level::Fatal))));
+    }
+
+    #[test]
+    fn postgres_continuations() {
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let input = "[42] ERROR:  relation \"users\" does not exist\n\
+                     [42] STATEMENT:  select *\n\
+                     \tfrom users where note = 'warn'\n\
+                     [42] HINT:  check the search_path\n\
+                     [43] LOG:  disconnection\n\
+                     \tplain";
+        let out = render_stream(&hl, input);
+        let lines: Vec<&str> = out.lines().collect();
+        let red = RED.fg_sgr(ColorDepth::Basic);
+        assert_eq!(
+            lines[0],
+            format!("[42] {BOLD}{red}ERROR:{RESET}  relation \"users\" does not exist")
+        );
+        assert_eq!(
+            lines[1],
+            format!("{red}[42] {RESET}{BOLD}{DIM}{red}STATEMENT:{RESET}{red}  select *{RESET}")
+        );
+        assert_eq!(lines[2], format!("{red}\tfrom users where note = 'warn'{RESET}"));
+        // a label at the start of the line, or its end, has nothing painted beside it
+        let out = render_stream(&hl, "ERROR:  boom\nSTATEMENT:  select 1\nDETAIL:");
+        assert_eq!(
+            out.lines().skip(1).collect::<Vec<_>>(),
+            [
+                format!("{BOLD}{DIM}{red}STATEMENT:{RESET}{red}  select 1{RESET}"),
+                format!("{BOLD}{DIM}{red}DETAIL:{RESET}")
+            ]
+        );
+        assert!(lines[3].starts_with(&format!("{red}[42] {RESET}{BOLD}{DIM}{red}HINT:")));
+        assert!(
+            lines[4].contains(&format!("{BOLD}{}LOG:{RESET}", GREEN.fg_sgr(ColorDepth::Basic)))
+        );
+        // an indented line takes the color of the event above
+        assert_eq!(lines[5], format!("{}\tplain{RESET}", GREEN.fg_sgr(ColorDepth::Basic)));
+        let mut hl = hl;
+        hl.continuation = Some(ContinuationMode::Off);
+        assert_eq!(render_stream(&hl, input).lines().nth(5), Some("\tplain"));
+    }
+
+    #[test]
+    fn mysql_severity_brackets() {
+        let line = "2024-05-01T12:03:04.000000Z 0 [Note] [MY-010116] [Server] starting";
+        assert_eq!(detected(line), Some(("[Note]", Some(Level::Info))));
+        let line = "2024-05-01T12:03:04.000000Z 0 [System] [MY-010931] [Server] ready";
+        assert_eq!(detected(line), Some(("[System]", Some(Level::Info))));
+        // the subsystem bracket doesn't count, even when it looks like a level
+        let line = "2024-05-01T12:03:04.000000Z 7 [Warning] [MY-010918] [Error] x";
+        assert_eq!(detected(line), Some(("[Warning]", Some(Level::Warn))));
+        assert_eq!(detected("a note to self"), None);
+        assert_eq!(detected("the system is down"), None);
+
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let line = "2024-05-01T12:03:04.000000Z 0 [ERROR] [MY-000067] [Server] bad option";
+        assert_eq!(
+            render(&hl, line),
+            format!(
+                "2024-05-01T12:03:04.000000Z 0 {BOLD}{}[ERROR]{RESET} {DIM}[MY-000067]{RESET} \
+                 [Server] bad option\n",
+                RED.fg_sgr(ColorDepth::Basic)
+            )
+        );
+        assert_eq!(mysql_error_code_len("[MY-] x"), None);
+    }
+
+    #[test]
+    fn go_panic_dump() {
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let input = "panic: runtime error: index out of range [5] with length 3\n\
+                     \n\
+                     goroutine 1 [running]:\n\
+                     main.handler(0xc0000b4000)\n\
+                     \t/src/app/main.go:42 +0x1a\n\
+                     exit status 2";
+        let out = render_stream(&hl, input);
+        let lines: Vec<&str> = out.lines().collect();
+        let error =
+            Styles::new(&DARK_PALETTE, ColorDepth::Basic).levels[Level::Error as usize].clone();
+        assert_eq!(
+            lines[0],
+            format!(
+                "{BOLD}{error}panic: runtime error: index out of range [5] with length 3{RESET}"
+            )
+        );
+        assert_eq!(line_level("panic: runtime error: nil map", &[]), Some(Level::Error));
+        assert_eq!(line_level("fatal error: all goroutines are asleep", &[]), Some(Level::Error));
+        assert_eq!(lines[1], "");
+        assert_eq!(lines[2], format!("{BOLD}goroutine 1 [running]:{RESET}"));
+        assert_eq!(lines[3], "main.handler(0xc0000b4000)");
+        assert_eq!(
+            lines[4],
+            format!("\t{}/src/app/main.go:42{RESET}{DIM} +0x1a{RESET}", hl.loaded().styles.muted)
+        );
+        // the dump is over, back to ordinary lines
+        assert_eq!(lines[5], "exit status 2");
+        assert_eq!(go_panic_line("level=info msg=ok"), None);
+    }
+
+    #[test]
+    fn colorblind_theme() {
+        let (palette, _) = select_palette(ThemeChoice::Colorblind, true);
+        let hl = highlighter(palette, ColorDepth::TrueColor, InputFormat::Auto);
+        assert_eq!(
+            render(&hl, "ERROR: disk full"),
+            format!("{BOLD}{REVERSE}\x1b[38;2;230;159;0mERROR:{RESET} disk full\n")
+        );
+        assert_eq!(
+            render(&hl, "INFO: ok"),
+            format!("{BOLD}\x1b[38;2;86;180;233mINFO:{RESET} ok\n")
+        );
+        // with only eight colors orange and yellow meet; reverse video
+        // still tells error from warn
+        let hl = highlighter(palette, ColorDepth::Basic, InputFormat::Auto);
+        assert_eq!(hl.loaded().styles.color(Level::Error), hl.loaded().styles.color(Level::Warn));
+        assert!(render(&hl, "ERROR: x").contains(REVERSE));
+        assert!(!render(&hl, "WARN: x").contains(REVERSE));
+    }
+
+    #[test]
+    fn themes_and_depths() {
+        let (palette, _) = select_palette(ThemeChoice::Monochrome, true);
+        let hl = highlighter(palette, ColorDepth::TrueColor, InputFormat::Auto);
+        assert_eq!(
+            render(&hl, "ERROR: disk full"),
+            format!("{BOLD}{REVERSE}ERROR:{RESET} disk full\n")
+        );
+        assert_eq!(render(&hl, "WARN: slow"), format!("{BOLD}{ITALIC}WARN:{RESET} slow\n"));
+        assert_eq!(hl.loaded().styles.color(Level::Debug), DIM);
+        assert!(!render(&hl, "DEBUG: x\nINFO: y").contains("\x1b[3"));
+
+        // solarized is rgb, brought down to what the terminal has
+        let (palette, _) = select_palette(ThemeChoice::SolarizedDark, true);
+        let red = |depth| Styles::new(palette, depth).color(Level::Error).to_string();
+        assert_eq!(red(ColorDepth::TrueColor), "\x1b[38;2;220;50;47m");
+        assert_eq!(red(ColorDepth::Ansi256), "\x1b[38;5;166m");
+        assert_eq!(red(ColorDepth::Basic), "\x1b[31m");
+        let fatal = Styles::new(palette, ColorDepth::Ansi256).color(Level::Fatal).to_string();
+        assert_ne!(fatal, red(ColorDepth::Ansi256));
+
+        let text = theme_text(&Styles::new(palette, ColorDepth::Ansi256), "solarized-dark");
+        assert!(text.starts_with("# solarized-dark\n"));
+        assert!(text.contains("\nINFO \\e[1m\\e[38;5;100m \\e[4m\\e[38;5;100m \\e[38;5;100m "));
+        assert!(text.ends_with("dim \\e[2m\nmuted \\e[2m\\e[38;5;247m\n"));
+        let plain = theme_text(&Styles::new(palette, ColorDepth::None), "off");
+        assert!(plain.contains("\nWARN (none) (none) (none)  WARN inferred line\n"));
+    }
+
+    #[test]
+    fn retry_waits_for_missing_file() {
+        let styles = Styles::new(&DARK_PALETTE, ColorDepth::None);
+        let path = env::temp_dir().join(format!("logcolor-retry-{}.log", std::process::id()));
+        let path = path.to_str().unwrap().to_string();
+        let mut notice = Vec::new();
+        let err = open_input(&path, true, Some(Duration::ZERO), &mut notice, &styles).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+        assert!(notice.is_empty());
+
+        let creator = {
+            let path = path.clone();
+            thread::spawn(move || {
+                thread::sleep(RETRY_POLL_INTERVAL);
+                File::create(path).unwrap();
+            })
+        };
+        assert!(open_input(&path, true, None, &mut notice, &styles).is_ok());
+        creator.join().unwrap();
+        // announced once, however many polls it took
+        assert_eq!(String::from_utf8(notice).unwrap(), format!("--- waiting for {} ---\n", path));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn wrapping_counts_visible_columns() {
+        assert_eq!(wrap_styled_line("short line", 20, "> ", 2), "short line");
+        // the hanging indent puts continuations under the message
+        let line = "ERROR: the quick brown fox jumps over the lazy dog";
+        assert_eq!(
+            wrap_styled_line(line, 30, "> ", 2),
+            "ERROR: the quick brown fox\n       > jumps over the lazy\n       > dog"
+        );
+        // escapes take no room; styling is closed and reopened around a break
+        let styled =
+            format!("{BOLD}\x1b[31mERROR:{RESET} {DIM}aaaa bbbb cccc dddd eeee ffff gggg{RESET}");
+        let wrapped = wrap_styled_line(&styled, 30, "> ", 2);
+        assert_eq!(
+            wrapped,
+            format!(
+                "{BOLD}\x1b[31mERROR:{RESET} {DIM}aaaa bbbb cccc dddd{RESET}\n       > {DIM}eeee ffff gggg{RESET}"
+            )
+        );
+        // words longer than a row are cut, wide characters count twice
+        assert_eq!(wrap_styled_line("abcdefghij", 4, "", 0), "abcd\nefgh\nij");
+        assert_eq!(wrap_styled_line("日本語のログ", 6, "", 0), "日本語\nのログ");
+        assert_eq!(char_width('\u{301}'), 0);
+    }
+
+    #[test]
+    fn tracked_values_get_stable_colors() {
+        let visible = "pid=2231 ppid=1 trace_id: af92c1, user=\"a b\"";
+        let values = |key| -> Vec<&str> {
+            tracked_values(visible, key).into_iter().map(|r| &visible[r]).collect()
+        };
+        assert_eq!(values("pid"), ["2231"]);
+        assert_eq!(values("trace_id"), ["af92c1"]);
+        assert_eq!(values("user"), ["\"a b\""]);
+
+        let styles = Styles::new(&DARK_PALETTE, ColorDepth::Basic);
+        let tracking = Tracking { keys: vec!["pid".to_string()], filter: None };
+        let color = hash_color("2231", ColorDepth::Basic).fg_sgr(ColorDepth::Basic);
+        let line = format!("{BOLD}\x1b[32mINFO{RESET} pid=2231 up");
+        assert_eq!(
+            tracking.apply(&line, &styles),
+            format!("{BOLD}\x1b[32mINFO{RESET} pid={color}2231{RESET} up")
+        );
+
+        let tracking = Tracking {
+            keys: vec!["pid".to_string()],
+            filter: Some(("pid".to_string(), "7".to_string())),
+        };
+        let c7 = hash_color("7", ColorDepth::Basic).fg_sgr(ColorDepth::Basic);
+        assert_eq!(
+            tracking.apply("pid=7 a", &styles),
+            format!("{BOLD}pid={c7}7{RESET}{BOLD} a{RESET}")
+        );
+        let c8 = hash_color("8", ColorDepth::Basic).fg_sgr(ColorDepth::Basic);
+        assert_eq!(
+            tracking.apply("pid=8 a", &styles),
+            format!("{DIM}pid={c8}8{RESET}{DIM} a{RESET}")
+        );
+    }
+
+    #[test]
+    fn json_field_selection() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let fields = ["time", "level", "msg", "error.message", "ctx", "missing"];
+        let fields = fields.map(String::from).to_vec();
+        hl.json_fields = Some(JsonFields { fields, rest: false });
+        let line = r#"{"time":"12:00","level":"error","msg":"boom","error":{"message":"eof"},"ctx":{"a":[1,2]},"x":"y z"}"#;
+        assert_eq!(render(&hl, line), "12:00 error boom eof {\"a\":[1,2]} -\n");
+        // a key with a dot in it is found before descending
+        assert_eq!(
+            parse_json(r#"{"a.b":1,"a":{"b":2}}"#).unwrap().path("a.b").unwrap().to_text(),
+            "1"
+        );
+
+        hl.settings.set_styles(Styles::new(&DARK_PALETTE, ColorDepth::Basic));
+        hl.json_fields =
+            Some(JsonFields { fields: vec!["level".into(), "msg".into()], rest: true });
+        assert_eq!(
+            render(&hl, r#"{"level":"warn","msg":"hot","temp":90,"unit":"deg C"}"#),
+            format!(
+                "{BOLD}\x1b[33mwarn{RESET} hot {DIM}temp=90{RESET} {DIM}unit=\"deg C\"{RESET}\n"
+            )
+        );
+        // non-JSON lines are left to the usual path
+        assert_eq!(render(&hl, "plain text"), "plain text\n");
+    }
+
+    #[test]
+    fn mini_regex() {
+        let find = |pattern, text| MiniRegex::new(pattern, false).unwrap().find(text);
+        assert_eq!(find("E\\d+", "code E1234 seen"), Some(5..10));
+        assert_eq!(find("a.c", "xxabcxx"), Some(2..5));
+        assert_eq!(find("^abc", "xabc"), None);
+        assert_eq!(find("abc$", "abcabc"), Some(3..6));
+        assert_eq!(find("colou?r", "the color"), Some(4..9));
+        assert_eq!(find("[a-f0-9]+-", "id=deadbeef-1"), Some(3..12));
+        assert_eq!(find("[^ ]+\\.example", "host a.b.example up"), Some(5..16));
+        assert_eq!(find("/v1/[a-z]*s", "GET /v1/users"), Some(4..13));
+        assert_eq!(find("x*", "abc"), Some(0..0));
+        assert_eq!(find("[a-]+", "x-a-y"), Some(1..4));
+        let ci = MiniRegex::new("timeout", true).unwrap();
+        assert_eq!(ci.find("read TIMEOUT"), Some(5..12));
+        assert!(MiniRegex::new("*a", false).is_err());
+        assert!(MiniRegex::new("[ab", false).is_err());
+    }
+
+    #[test]
+    fn style_specs() {
+        assert_eq!(parse_style("bold,red").unwrap(), Style { attrs: vec![BOLD], fg: Some(RED) });
+        assert_eq!(parse_style("bright-red").unwrap().fg, Some(BRIGHT_RED));
+        assert_eq!(parse_style("underline+#ff8800").unwrap().fg, Some(Color::Rgb(255, 136, 0)));
+        assert_eq!(parse_style("208").unwrap().sgr(ColorDepth::Ansi256), "\x1b[38;5;208m");
+        assert!(parse_style("red,blue").is_err());
+        assert!(parse_style("sparkly").is_err());
+    }
+
+    #[test]
+    fn rules_file() {
+        let text = "# team rules\n\
+                    E1234\tbold,magenta\thighlight\n\
+                    /\\/v1\\/legacy/\tunderline\tline\n\
+                    healthz\t-\tsuppress\n\
+                    bad-host\t-\tlevel=ERROR\n";
+        let rules = parse_rules(text, "team.rules").unwrap();
+        assert_eq!(rules.len(), 4);
+        assert_eq!(rules[3].action, RuleAction::Level(Level::Error));
+
+        let err = parse_rules("ok\n\nx\tbold\tsparkle\n", "a.rules").unwrap_err();
+        assert_eq!(err, "a.rules:3: unknown action 'sparkle'");
+        assert_eq!(
+            parse_rules("x\tmauve\n", "a.rules").unwrap_err(),
+            "a.rules:1: unknown style 'mauve'"
+        );
+
+        let styles = Styles::new(&DARK_PALETTE, ColorDepth::Basic);
+        let apply = |line: &str| apply_rules(line, &rules, &styles);
+        assert_eq!(apply("GET /healthz 200"), None);
+        // highlights are drawn with the spans, or over what isn't drawn so
+        let e1234 = format!("got {BOLD}\x1b[35mE1234{RESET}");
+        assert_eq!(apply("got E1234"), Some("got E1234".to_string()));
+        assert_eq!(apply_highlights("got E1234", &rules, &styles), e1234);
+        assert_eq!(apply("GET /v1/legacy"), Some("\x1b[4mGET /v1/legacy\x1b[0m".to_string()));
+        // a level= rule changes the line's level, not what was drawn
+        let green = format!("{BOLD}\x1b[32mINFO{RESET} bad-host up");
+        assert_eq!(apply(&green), Some(green));
+        // suppression still applies without colors
+        let plain = Styles::new(&DARK_PALETTE, ColorDepth::None);
+        assert_eq!(apply_rules("GET /healthz", &rules, &plain), None);
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.settings.current_mut().rules = rules;
+        assert_eq!(render(&hl, "got E1234"), e1234 + "\n");
+        let red = format!("{BOLD}\x1b[31mINFO{RESET} bad-host up\n");
+        assert_eq!(render_stream(&hl, "INFO bad-host up\n"), red);
+        assert_eq!(level_of("bad-host down", &hl), Some(Level::Error));
+        hl.summaries = true;
+        hl.levels = LevelFilter { min: Some(Level::Error), ..LevelFilter::default() };
+        let mut out = Vec::new();
+        let input = "INFO up\nINFO bad-host up\nbad-host down\n";
+        let summary = process_reader(input.as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), red + "bad-host down\n");
+        assert_eq!(summary.levels[Level::Info as usize], 1);
+        assert_eq!(summary.levels[Level::Error as usize], 2);
+    }
+
+    #[test]
+    fn multiline_json_records() {
+        assert_eq!(json_nesting(r#"{"a": "}{", "b": ["#), 2);
+        assert_eq!(json_nesting(r#"  "c": "\"]" }"#), -1);
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.multiline_json = true;
+        let input =
+            "starting\n{\n  \"level\": \"error\",\n  \"tags\": [\"a\", \"b\"]\n}\n{}\nplain";
+        assert_eq!(
+            render_stream(&hl, input),
+            "starting\n{\"level\":\"error\",\"tags\":[\"a\",\"b\"]}\n{}\nplain\n"
+        );
+        // unbalanced at the end of input, or not JSON after all: as it came
+        assert_eq!(render_stream(&hl, "{\n  \"a\": 1,"), "{\n  \"a\": 1,\n");
+        assert_eq!(render_stream(&hl, "{ not\njson }"), "{ not\njson }\n");
+
+        hl.expand_json = true;
+        hl.settings.set_styles(Styles::new(&DARK_PALETTE, ColorDepth::Basic));
+        let muted = hl.loaded().styles.muted.clone();
+        assert_eq!(
+            render_stream(&hl, "{\"level\": \"warn\",\n\"n\": [1]}"),
+            format!(
+                "{{\n  {muted}\"level\"{RESET}: {BOLD}\x1b[33m\"warn\"{RESET},\n  \
+                 {muted}\"n\"{RESET}: [\n    1\n  ]\n}}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_match("*.log", "api.log"));
+        assert!(!glob_match("*.log", "api.log.1"));
+        assert!(glob_match("api-?.log", "api-3.log"));
+        assert!(glob_match("[a-c]*.txt", "b1.txt"));
+        assert!(!glob_match("[!a-c]*.txt", "b1.txt"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("[x", "[x"));
+    }
+
+    #[test]
+    fn directory_arguments() {
+        let root = env::temp_dir().join(format!("logcolor-dir-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        for name in ["b.log", "a.log", "a.log.1", "a.log.2.gz", ".hidden.log", "notes.txt"] {
+            File::create(root.join(name)).unwrap();
+        }
+        File::create(root.join("sub/c.log")).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&root, root.join("sub/loop")).unwrap();
+
+        let mut scan = DirScan { glob: "*.log".to_string(), recursive: false, sort: SortKey::Name };
+        let names = |scan: &DirScan| -> Vec<String> {
+            let files = scan.files(&root).unwrap();
+            files.iter().map(|f| f.strip_prefix(&root).unwrap().display().to_string()).collect()
+        };
+        assert_eq!(names(&scan), ["a.log", "a.log.1", "b.log"]);
+        scan.recursive = true;
+        assert_eq!(names(&scan), ["a.log", "a.log.1", "b.log", "sub/c.log"]);
+        scan.glob = "*.txt".to_string();
+        assert_eq!(names(&scan), ["notes.txt"]);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn summary_lines() {
+        assert_eq!(parse_iso_seconds("1970-01-02T00:00:01Z"), Some(86_401.0));
+        assert_eq!(parse_iso_seconds("1970-01-01 01:00:00.5+01:00 x"), Some(0.5));
+        assert_eq!(days_from_civil(2024, 5, 1), 19_844);
+        assert_eq!(civil_from_days(days_from_civil(2000, 2, 29)), (2000, 2, 29));
+        assert_eq!(
+            line_timestamp("<13>May  1 00:00:09 host app: hi"),
+            Some(120.0 * 86_400.0 + 9.0)
+        );
+        assert_eq!(
+            line_timestamp("[2024-05-01T12:03:04Z] x").map(|s| s as i64 % 86_400),
+            Some(43_384)
+        );
+        assert_eq!(line_timestamp("build 2024-05-01T12:03:04Z"), Some(1_714_564_984.0));
+        assert_eq!(line_timestamp("id=12024-05-01T12:03:04"), None);
+        assert_eq!(with_commas(48_211), "48,211");
+        assert_eq!(with_commas(1_000_000), "1,000,000");
+        assert_eq!(format_span(4.25), "4.2s");
+        assert_eq!(format_span(7_500.0), "2h05m");
+
+        let mut summary = Summary::default();
+        for line in [
+            "2024-05-01T12:00:00Z ERROR boom",
+            "2024-05-01T12:00:01Z WARN: careful",
+            "    at frame",
+            "2024-05-01T12:00:04.2Z error again",
+        ] {
+            summary.record_as(line, line_level(line, &[]));
+        }
+        assert_eq!(summary.lines, 4);
+        assert_eq!(summary.levels[Level::Error as usize], 2);
+        let mut out = Vec::new();
+        let styles = Styles::new(&DARK_PALETTE, ColorDepth::None);
+        summary.write(&mut out, "api.log.3", &styles).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(text, "── api.log.3: 4 lines, 2 ERROR, 1 WARN, 4.2s span ──\n");
+
+        let mut total = Summary { lines: 10, first: Some(0.0), ..Summary::default() };
+        total.add(&summary);
+        assert_eq!((total.lines, total.first), (14, Some(0.0)));
+        assert_eq!(total.last, summary.last);
+    }
+
+    #[test]
+    fn fail_on_counts() {
+        let input = "INFO up\nERROR boom\n    at frame\nFATAL gone\n<12>disk\nWARN slow\n";
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.summaries = true;
+        // what is printed doesn't change what is counted
+        for quiet in [0, 2] {
+            hl.quiet = quiet;
+            let summary = process_reader(input.as_bytes(), &hl, &mut io::sink()).unwrap();
+            assert_eq!(summary.at_least(Level::Fatal), 1);
+            assert_eq!(summary.at_least(Level::Error), 2);
+            assert_eq!(summary.at_least(Level::Warn), 4);
+            assert_eq!(summary.at_least(Level::Silly), 5);
+        }
+        assert_eq!(Summary::default().at_least(Level::Error), 0);
+    }
+
+    #[test]
+    fn mark_dividers() {
+        let line = mark_line("mark #3 14:32:18", 40);
+        assert_eq!(line, format!("──── mark #3 14:32:18 {}", "─".repeat(18)));
+        assert_eq!(line.chars().count(), 40);
+        assert!(mark_line("mark 14:32:18", 10).ends_with(" ────"));
+        let clock = clock_now();
+        assert!(clock.len() == 8 && clock.as_bytes()[2] == b':' && clock.as_bytes()[5] == b':');
+    }
+
+    #[test]
+    fn byte_offsets() {
+        let input = "one\r\ntwo\n\nlast";
+        let lines = RawLines::new(input.as_bytes(), DEFAULT_MAX_LINE_BYTES);
+        let lines: Vec<_> = lines.map(|l| l.map(|l| (l.text, l.consumed)).unwrap()).collect();
+        let expected = [("one", 5), ("two", 4), ("", 1), ("last", 4)];
+        let expected: Vec<_> = expected.iter().map(|&(l, n)| (l.to_string(), n)).collect();
+        assert_eq!(lines, expected);
+        assert_eq!(offset_prefix(42, &Styles::new(&DARK_PALETTE, ColorDepth::None)), "      42:");
+        let colored = offset_prefix(7, &Styles::new(&DARK_PALETTE, ColorDepth::Basic));
+        assert_eq!(colored, format!("{}       7:{}", DIM, RESET));
+
+        // with both, the number comes first, as grep -nb has it
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        (hl.number, hl.byte_offsets) = (true, true);
+        let mut out = Vec::new();
+        process_reader("INFO a\nERROR b\n".as_bytes(), &hl, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out, "     1:       0:INFO a\n     2:       7:ERROR b\n");
+    }
+
+    /// `len` bytes of `x`, made up as they are read.
+    struct Endless {
+        len: usize,
+    }
+
+    impl Read for Endless {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.len);
+            buf[..n].fill(b'x');
+            self.len -= n;
+            Ok(n)
+        }
+    }
+
+    /// a read that fails, after whatever comes before it.
+    struct Unreadable;
+
+    impl Read for Unreadable {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("input/output error"))
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    fn peak_rss_kib() -> usize {
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        let line = status.lines().find(|l| l.starts_with("VmHWM:")).unwrap();
+        line.split_whitespace().nth(1).unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn long_lines_are_cut() {
+        let pieces = |input: &str, max| -> Vec<(String, usize, bool)> {
+            let lines = RawLines::new(input.as_bytes(), max).map(Result::unwrap);
+            lines.map(|l| (l.text, l.consumed, l.continued)).collect()
+        };
+        let piece = |text: &str, consumed, continued| (text.to_string(), consumed, continued);
+        assert_eq!(
+            pieces("abcdefg\nhi\n", 3),
+            [
+                piece("abc", 3, false),
+                piece("def", 3, true),
+                piece("g", 2, true),
+                piece("hi", 3, false)
+            ]
+        );
+        assert_eq!(pieces("abc\nd", 3), [piece("abc", 4, false), piece("d", 1, false)]);
+        // the two-byte é isn't split, it moves to the next piece
+        assert_eq!(pieces("aé\n", 2), [piece("a", 1, false), piece("é", 3, true)]);
+
+        #[cfg(target_os = "linux")]
+        let before = peak_rss_kib();
+        let input = BufReader::new(Endless { len: 100 << 20 });
+        let mut count = 0;
+        for line in RawLines::new(input, DEFAULT_MAX_LINE_BYTES) {
+            let line = line.unwrap();
+            assert!(line.text.len() <= DEFAULT_MAX_LINE_BYTES && line.continued == (count > 0));
+            count += 1;
+        }
+        assert_eq!(count, 100);
+        #[cfg(target_os = "linux")]
+        assert!(peak_rss_kib() - before < 32 << 10, "peak memory grew by more than 32 MiB");
+    }
+
+    #[test]
+    fn gzip_input() {
+        let gunzip = |input: &[u8]| {
+            let mut out = Vec::new();
+            Gunzip::new(input, "'app.log.1.gz'".to_string()).read_to_end(&mut out).map(|_| out)
+        };
+        // a compressed member, then a stored one, as `cat a.gz b.gz` makes
+        let mut input = b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x02\x03\x73\x0d\x0a\xf2\x0f\x52\x48\xc9\x2c\
+            \xce\x56\x48\x2b\xcd\xc9\xe1\x0a\x77\x0c\xf2\x53\x28\x4a\x2d\x29\xaa\x54\x30\x44\xe6\x18\x21\
+            \x73\x8c\xb9\x00\xd3\xd3\x45\x1e\x37\x00\x00\x00"
+            .to_vec();
+        let stored =
+            b"\x1f\x8b\x08\x00\x00\x00\x00\x00\x04\x03\x01\x08\x00\xf7\xff\x49\x4e\x46\x4f\
+            \x20\x6f\x6b\x0a\x33\x56\x03\xc1\x08\x00\x00\x00";
+        input.extend_from_slice(stored);
+        let text = "ERROR disk full\nWARN retry 1\nWARN retry 2\nWARN retry 3\nINFO ok\n";
+        assert_eq!(gunzip(&input).unwrap(), text.as_bytes());
+        // one byte at a time, as a slow pipe may hand it over
+        let mut out = Vec::new();
+        let mut slow = Gunzip::new(io::BufReader::with_capacity(1, &input[..]), String::new());
+        io::copy(&mut slow, &mut out).unwrap();
+        assert_eq!(out, text.as_bytes());
+
+        // a `.gz` name isn't enough, nor is the first magic byte
+        assert_eq!(gunzip(b"INFO plain\n").unwrap(), b"INFO plain\n");
+        assert_eq!(gunzip(b"\x1f unit separator\n").unwrap(), b"\x1f unit separator\n");
+        assert_eq!(gunzip(b"\x1f").unwrap(), b"\x1f");
+        assert_eq!(gunzip(b"").unwrap(), b"");
+
+        let cut = gunzip(&input[..30]).unwrap_err();
+        assert_eq!(cut.to_string(), "gzip data cut short in 'app.log.1.gz'");
+        let mut bad = stored.to_vec();
+        bad[20] ^= 0x20;
+        let bad = gunzip(&bad).unwrap_err();
+        assert_eq!(bad.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            bad.to_string(),
+            "corrupt gzip data (checksum or length doesn't match) in 'app.log.1.gz'"
+        );
+        // and it stays an error, rather than looking like the end
+        let mut reader = Gunzip::new(&input[..30], String::new());
+        assert!(reader.read_to_end(&mut Vec::new()).is_err());
+        assert!(reader.read(&mut [0; 16]).is_err());
+        // which fails the run, cut short or corrupt
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let mut corrupt = input.clone();
+        corrupt[12] ^= 0xff;
+        for broken in [&input[..30], &corrupt[..]] {
+            let gunzip = Gunzip::new(broken, String::new());
+            let summary = process_reader(gunzip, &hl, &mut io::sink()).unwrap();
+            assert!(summary.read_failed);
+        }
+        let mut corrupt = Gunzip::new(&corrupt[..], String::new());
+        assert_eq!(
+            corrupt.read_to_end(&mut Vec::new()).unwrap_err().kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+
+    #[test]
+    fn html_output() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::TrueColor, InputFormat::Plain);
+        let tagged = hl.loaded().styles.clone().tagged();
+        hl.settings.set_styles(tagged);
+        let html = |hl: &Highlighter, input: &str, standalone: bool| {
+            let mut out = Html::new(Vec::new(), &hl.loaded().styles, false, standalone);
+            process_reader(input.as_bytes(), hl, &mut out).unwrap();
+            out.write_all(HTML_END.as_bytes()).unwrap();
+            String::from_utf8(out.inner).unwrap()
+        };
+        assert_eq!(
+            html(&hl, "12:03:04 ERROR <ERROR> a && b > c\n", false),
+            "12:03:04 <span class=\"lvl-error\">ERROR</span> &lt;ERROR&gt; a &amp;&amp; b &gt; c\n"
+        );
+        assert_eq!(
+            html(&hl, "timed out after 5s\n", false),
+            "<span class=\"lvl-warn-inferred\">timed out</span> after 5s\n"
+        );
+
+        // the input's own colors are left out, not escaped into view
+        let colored = "\x1b[31mWARN\x1b[0m disk \x1b]8;;http://x\x07link\x1b]8;;\x07\n";
+        assert_eq!(html(&hl, colored, false), "<span class=\"lvl-warn\">WARN</span> disk link\n");
+        assert_eq!(strip_escapes("a\x1b[1;31mb\x1b[0m\x1b[c"), "ab");
+
+        // a page around it, and sequences cut across writes still whole
+        let page = html(&hl, "INFO up\n", true);
+        assert!(page.starts_with("<!DOCTYPE html>"));
+        assert!(page.contains(".lvl-info { font-weight: bold; color: #00cd00 }"));
+        assert!(page.ends_with(
+            "<pre>\n<span class=\"lvl-info\">INFO</span> up\n</pre>\n</body>\n</html>\n"
+        ));
+        let mut out = Html::new(Vec::new(), &hl.loaded().styles, false, false);
+        for piece in ["\x1b", "[1m\x1b[38;5", ";208mhot\x1b[", "0m <", "b>"] {
+            out.write_all(piece.as_bytes()).unwrap();
+        }
+        assert_eq!(
+            String::from_utf8(out.inner).unwrap(),
+            "<span style=\"font-weight: bold; color: #ff8700\">hot</span> &lt;b&gt;"
+        );
+        assert_eq!(sgr_css("\x1b[7m\x1b[38;2;1;2;3m", "#fff"), "color: #fff; background: #010203");
+
+        // each level its own class, even where two look the same
+        for level in Level::ALL {
+            let mut out = Html::new(Vec::new(), &hl.loaded().styles, false, false);
+            hl.loaded().styles.paint_level(&mut out, level, "x").unwrap();
+            hl.loaded().styles.paint_inferred(&mut out, level, "y").unwrap();
+            let name = level.name().to_lowercase();
+            assert_eq!(
+                String::from_utf8(out.inner).unwrap(),
+                format!(
+                    "<span class=\"lvl-{name}\">x</span><span class=\"lvl-{name}-inferred\">y</span>"
+                )
+            );
+        }
+        assert_eq!(
+            html(&hl, "TRACE a\nVERBOSE b\nSILLY c\n", false),
+            "<span class=\"lvl-trace\">TRACE</span> a\n<span class=\"lvl-trace\">VERBOSE</span> b\n\
+             <span class=\"lvl-silly\">SILLY</span> c\n"
+        );
+        let page = html(&hl, "", true);
+        for level in Level::ALL {
+            assert!(
+                page.contains(&format!(".lvl-{} {{", level.name().to_lowercase())),
+                "{:?}",
+                level
+            );
+        }
+        // a wrapped row keeps its class
+        let line = format!("{}aaaa bbbb{}", hl.loaded().styles.color(Level::Warn), RESET);
+        let mut out = Html::new(Vec::new(), &hl.loaded().styles, false, false);
+        out.write_all(wrap_styled_line(&line, 5, "", 0).as_bytes()).unwrap();
+        assert_eq!(
+            String::from_utf8(out.inner).unwrap(),
+            "<span class=\"lvl-warn-line\">aaaa</span>\n<span class=\"lvl-warn-line\">bbbb</span>"
+        );
+    }
+
+    #[test]
+    fn bytes_that_are_not_utf8() {
+        let render_bytes = |hl: &Highlighter, input: &[u8]| {
+            let mut out = Vec::new();
+            process_reader(input, hl, &mut out).unwrap();
+            out
+        };
+        let mut input = b"2024-05-01 12:03:04 INFO start\n".to_vec();
+        input.extend_from_slice(b"2024-05-01 12:03:05 ERROR blob \xff\xfe\x00\xc3( \xe2\x82 end\n");
+        input.extend_from_slice("2024-05-01 12:03:06 WARN caf\u{e9} \u{10fd80}\n".as_bytes());
+        input.extend_from_slice(b"2024-05-01 12:03:07 INFO done\n");
+        let plain = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Plain);
+        assert_eq!(render_bytes(&plain, &input), input);
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Plain);
+        let out = render_bytes(&hl, &input);
+        let red = format!("{}{}ERROR{} blob ", BOLD, hl.loaded().styles.color(Level::Error), RESET);
+        let second = [red.as_bytes(), b"\xff\xfe\x00\xc3( \xe2\x82 end\n"].concat();
+        assert!(out.windows(second.len()).any(|w| w == second), "{:?}", out);
+        assert!(out.ends_with(
+            format!("{}{}INFO{} done\n", BOLD, GREEN.fg_sgr(ColorDepth::Basic), RESET).as_bytes()
+        ));
+
+        // `\r\n` stays, a bare `\n` too
+        let input = b"INFO one\r\n\xffWARN two\r\nthree\n";
+        assert_eq!(render_bytes(&plain, input), input);
+        let lines: Vec<RawLine> =
+            RawLines::new(&input[..], DEFAULT_MAX_LINE_BYTES).map(Result::unwrap).collect();
+        assert_eq!(lines.iter().map(|l| l.crlf).collect::<Vec<_>>(), [true, true, false]);
+        assert_eq!(lines[1].text, format!("{}WARN two", raw_byte_char(0xff)));
+
+        // a character cut at the end of the input is written as it was, once
+        assert_eq!(render_bytes(&plain, b"INFO \xe2\x82"), b"INFO \xe2\x82\n");
+        let mut cut = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Plain);
+        cut.max_line_bytes = 4;
+        let out = render_bytes(&cut, b"ab\xe2\x82\xacd\n");
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(out.split_once('\n').map(|(first, _)| first), Some("ab"));
+        assert!(out.contains('\u{20ac}'), "{:?}", out);
+    }
+
+    #[test]
+    fn stats_output() {
+        let mut a = Summary::default();
+        for line in ["2024-05-01T12:00:00Z ERROR boom", "WARNING: x", "plain"] {
+            a.record_as(line, line_level(line, &[]));
+        }
+        let b = Summary { lines: 2, first: Some(1.5), last: Some(3.0), ..Summary::default() };
+        let mut total = a.clone();
+        total.add(&b);
+        let plain = Styles::new(&DARK_PALETTE, ColorDepth::None);
+        assert_eq!(total.table(&plain), "ERROR 1  WARN 1  INFO 0  DEBUG 0  unmatched 3  total 5");
+
+        let files = [("a.log".to_string(), a), ("b.log".to_string(), b)];
+        let json = stats_json(&total, &files, Duration::from_millis(1250));
+        assert_eq!(json.path("lines"), Some(&Json::Number("5".to_string())));
+        assert_eq!(json.path("levels.error"), Some(&Json::Number("1".to_string())));
+        assert_eq!(json.path("levels.unmatched"), Some(&Json::Number("3".to_string())));
+        let first = Some(Json::String("1970-01-01T00:00:01.500Z".to_string()));
+        assert_eq!(json.path("first_timestamp").cloned(), first);
+        assert_eq!(json.path("elapsed_seconds"), Some(&Json::Number("1.250".to_string())));
+        let Some(Json::Array(per_file)) = json.get("files") else { panic!("no files") };
+        assert_eq!(per_file[1].get("path"), Some(&Json::String("b.log".to_string())));
+        assert_eq!(per_file[1].get("first_timestamp").cloned(), first);
+        assert_eq!(parse_json(&json.to_string()), Some(json));
+        assert!(stats_json(&files[0].1, &files[..1], Duration::ZERO).get("files").is_none());
+
+        // what a stream cut short by Ctrl+C has read, `WARNING` as `WARN`
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let metrics = std::sync::Arc::new(Metrics::default());
+        hl.metrics = Some(metrics.clone());
+        let input = "ERROR a\nWARNING b\nWARN c\nplain\n";
+        let mut reader = Metered::new(input.as_bytes(), "(stdin)", &hl);
+        io::copy(&mut reader, &mut io::sink()).unwrap();
+        let so_far = metrics.summary();
+        assert_eq!(so_far.table(&plain), "ERROR 1  WARN 2  INFO 0  DEBUG 0  unmatched 1  total 4");
+    }
+
+    #[test]
+    fn parallel_jobs_keep_order() {
+        let mut spool = Spool::default();
+        spool.write_all(b"in memory, ").unwrap();
+        spool.spill().unwrap();
+        spool.write_all(b"then on disk").unwrap();
+        let path = spool.file.as_ref().unwrap().1.clone();
+        let mut out = Vec::new();
+        spool.copy_to(&mut out).unwrap();
+        assert_eq!(out, b"in memory, then on disk");
+        assert!(!path.exists());
+
+        let root = env::temp_dir().join(format!("logcolor-jobs-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let mut inputs = Vec::new();
+        for i in 0..7 {
+            let path = root.join(format!("{}.log", i));
+            let body: String =
+                (0..50 * (7 - i)).map(|n| format!("ERROR {} line {}\n", i, n)).collect();
+            std::fs::write(&path, body).unwrap();
+            inputs.push(InputSource::File(path.to_string_lossy().into_owned()));
+        }
+        inputs
+            .insert(3, InputSource::File(root.join("missing.log").to_string_lossy().into_owned()));
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.summaries = true;
+        let retry = Retry { enabled: false, timeout: None };
+
+        let mut sequential = Vec::new();
+        let mut seen = Vec::new();
+        for source in &inputs {
+            let result = process_input(source, &hl, retry, &mut sequential);
+            seen.push(result.map(|s| s.lines));
+        }
+        let mut parallel = Vec::new();
+        let mut order = Vec::new();
+        process_in_parallel(&inputs, 3, &hl, retry, &mut parallel, |source, result| {
+            order.push((source.clone(), result.map(|s| s.lines)));
+        });
+        assert_eq!(String::from_utf8(parallel).unwrap(), String::from_utf8(sequential).unwrap());
+        assert_eq!(order.iter().map(|(s, _)| s.clone()).collect::<Vec<_>>(), inputs);
+        assert_eq!(order.into_iter().map(|(_, r)| r).collect::<Vec<_>>(), seen);
+        assert!(seen[3].as_ref().is_err_and(|e| e.starts_with("failed to open")));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn inferred_levels() {
+        let table = Inference::defaults();
+        let infer =
+            |line: &'static str| infer_level(line, &table).map(|(r, level)| (&line[r], level));
+        assert_eq!(
+            infer("dial tcp: Connection Refused"),
+            Some(("Connection Refused", Level::Error))
+        );
+        assert_eq!(infer("request TIMED OUT after 30s"), Some(("TIMED OUT", Level::Warn)));
+        assert_eq!(infer("Traceback (most recent call last):"), Some(("Traceback", Level::Error)));
+        assert_eq!(infer("flag is deprecated; use --x"), Some(("deprecated", Level::Warn)));
+        // near misses: glued to other words, or only part of the phrase
+        assert_eq!(infer("undeprecated api"), None);
+        assert_eq!(infer("segfaults_total=3"), None);
+        assert_eq!(infer("retimed outcome"), None);
+        assert_eq!(infer("timed  out"), None);
+        assert_eq!(infer("connection refusedly"), None);
+        // a later whole-word hit still counts after a glued one
+        assert_eq!(infer("xtimed out, timed out"), Some(("timed out", Level::Warn)));
+        assert_eq!(infer("ünicode timed out"), Some(("timed out", Level::Warn)));
+
+        let custom = Inference::parse("Lost Quorum = error").unwrap();
+        assert_eq!(custom, Inference { phrase: "lost quorum".to_string(), level: Level::Error });
+        assert!(Inference::parse("no level here").is_err());
+        assert!(Inference::parse("x=loud").is_err());
+        assert!(Inference::parse("=warn").is_err());
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let out = render(&hl, "upstream timed out");
+        assert_eq!(
+            out,
+            format!(
+                "upstream {}{}timed out{}\n",
+                UNDERLINE,
+                YELLOW.fg_sgr(ColorDepth::Basic),
+                RESET
+            )
+        );
+        // a real token wins over an inferred one
+        assert_eq!(
+            render(&hl, "INFO timed out"),
+            render(&hl, "INFO all good").replace("all good", "timed out")
+        );
+        hl.infer = vec![custom];
+        assert_eq!(render(&hl, "deprecated"), "deprecated\n");
+        assert!(render(&hl, "we LOST QUORUM").contains(UNDERLINE));
+
+        let mut summary = Summary::default();
+        summary.record_as("connection refused", line_level("connection refused", &table));
+        summary.record_as("connection refused", line_level("connection refused", &[]));
+        assert_eq!((summary.levels[Level::Error as usize], summary.unmatched()), (1, 1));
+    }
+
+    /// what has made it past an `Output`'s buffer.
+    #[derive(Clone, Default)]
+    struct Downstream(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl Write for Downstream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn unbuffered_output() {
+        let downstream = Downstream::default();
+        let seen = || String::from_utf8(downstream.0.lock().unwrap().clone()).unwrap();
+        let mut unbuffered = Output::new(Box::new(downstream.clone()), true);
+        write!(unbuffered, "ERROR first").unwrap();
+        assert_eq!(seen(), "");
+        writeln!(unbuffered, " line").unwrap();
+        assert_eq!(seen(), "ERROR first line\n");
+
+        let downstream = Downstream::default();
+        let seen = || String::from_utf8(downstream.0.lock().unwrap().clone()).unwrap();
+        let mut buffered = Output::new(Box::new(downstream.clone()), false);
+        writeln!(buffered, "held back").unwrap();
+        assert_eq!(seen(), "");
+        // what SIGUSR1 does, from another thread
+        let nudged = buffered.clone();
+        thread::spawn(move || nudged.clone().flush().unwrap()).join().unwrap();
+        assert_eq!(seen(), "held back\n");
+    }
+
+    #[test]
+    fn grep_context() {
+        let grep = |pattern: &str, before, after| Grep {
+            matcher: Matcher::parse(pattern).unwrap(),
+            before,
+            after,
+        };
+        let run = |grep: &Grep, input: &[&str]| -> Vec<String> {
+            let mut window = ContextWindow::new(grep);
+            let mut out = Vec::new();
+            for (i, line) in input.iter().enumerate() {
+                for shown in
+                    window.push(line.to_string(), i as u64, grep.matcher.find(line).is_some())
+                {
+                    out.push(match shown {
+                        Shown::Separator => "--".to_string(),
+                        Shown::Context(line, _) => format!("  {}", line),
+                        Shown::Match(line, _) => format!("> {}", line),
+                    });
+                }
+            }
+            out
+        };
+        let input = ["a", "b", "hit 1", "c", "hit 2", "d", "e", "f", "g", "hit 3", "h"];
+        // matches one line apart share their context, nothing twice
+        assert_eq!(
+            run(&grep("hit", 1, 1), &input),
+            ["  b", "> hit 1", "  c", "> hit 2", "  d", "--", "  g", "> hit 3", "  h"]
+        );
+        assert_eq!(
+            run(&grep("hit", 2, 3), &input),
+            [
+                "  a", "  b", "> hit 1", "  c", "> hit 2", "  d", "  e", "  f", "  g", "> hit 3",
+                "  h"
+            ]
+        );
+        assert_eq!(run(&grep("hit", 0, 0), &input), ["> hit 1", "--", "> hit 2", "--", "> hit 3"]);
+        assert_eq!(run(&grep("/^hit [12]$/", 0, 0), &["hit 1", "hit 2"]), ["> hit 1", "> hit 2"]);
+        let mut window = ContextWindow::new(&grep("x", 1, 0));
+        assert_eq!(window.push("a".to_string(), 0, false), []);
+        assert_eq!(
+            window.push("x".to_string(), 2, true),
+            [Shown::Context("a".to_string(), 0), Shown::Match("x".to_string(), 2)]
+        );
+        assert!(window.showing());
+
+        let emphasis = format!("{}{}", BOLD, UNDERLINE);
+        let user = grep("user 4821", 0, 0);
+        assert_eq!(
+            user.emphasize("id user 4821 user 4821"),
+            format!("id {0}user 4821{1} {0}user 4821{1}", emphasis, RESET)
+        );
+        assert_eq!(grep("/\\d*/", 0, 0).matcher.find_all("a1b22"), [1..2, 3..5]);
+
+        // context is drawn as usual, only dimmed
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.grep = Some(grep("hit", 1, 0));
+        let mut out = Vec::new();
+        process_reader("WARN slow\nERROR hit\n".as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "{DIM}{BOLD}\x1b[33mWARN{RESET}{DIM} slow{RESET}\n\
+                 {BOLD}\x1b[31mERROR{RESET} {emphasis}hit{RESET}\n"
+            )
+        );
+    }
+
+    #[test]
+    fn kubernetes_lines() {
+        let line = "[pod/error-page-7f9c/nginx] 2024-05-01T12:03:04.123Z stdout F GET / 200";
+        let k8s = parse_k8s_line(line).unwrap();
+        assert_eq!(&line[k8s.prefix.clone().unwrap()], "[pod/error-page-7f9c/nginx]");
+        assert_eq!(&line[k8s.pod.clone()], "error-page-7f9c");
+        assert_eq!(&line[k8s.timestamp.clone().unwrap()], "2024-05-01T12:03:04.123Z");
+        assert_eq!((&line[k8s.message..], k8s.partial), ("GET / 200", false));
+        let cri = parse_k8s_line("2024-05-01T12:03:04.123456789Z stderr P ERROR disk ").unwrap();
+        assert_eq!((cri.prefix, cri.message, cri.partial), (None, 40, true));
+        let plain = parse_k8s_line("[pod/api/app] connected").unwrap();
+        assert_eq!((plain.timestamp, plain.message), (None, 14));
+        assert_eq!(parse_k8s_line("2024-05-01T12:03:04Z stdout F").unwrap().message, 29);
+        for other in
+            ["[2024-05-01T12:03:04Z INFO app] hi", "2024-05-01T12:03:04Z ERROR hi", "[a/b] x"]
+        {
+            assert_eq!(parse_k8s_line(other), None, "{}", other);
+        }
+
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let input = "[pod/api-1/app] 2024-05-01T12:03:04Z stdout P ERROR disk \n\
+                     [pod/web-2/app] 2024-05-01T12:03:05Z stdout F WARN slow\n\
+                     [pod/api-1/app] 2024-05-01T12:03:06Z stdout F full\n\
+                     2024-05-01T12:03:07Z stdout P cut off";
+        let out = render_stream(&hl, input);
+        let api = hash_color("api-1", ColorDepth::Basic).fg_sgr(ColorDepth::Basic);
+        let web = hash_color("web-2", ColorDepth::Basic).fg_sgr(ColorDepth::Basic);
+        let level = |level| format!("{}{}", BOLD, hl.loaded().styles.color(level));
+        let expected = [
+            format!(
+                "{}[pod/web-2/app]{} {}2024-05-01T12:03:05Z{} {}WARN{} slow",
+                web,
+                RESET,
+                hl.loaded().styles.dim,
+                RESET,
+                level(Level::Warn),
+                RESET
+            ),
+            format!(
+                "{}[pod/api-1/app]{} {}2024-05-01T12:03:04Z{} {}ERROR{} disk full",
+                api,
+                RESET,
+                hl.loaded().styles.dim,
+                RESET,
+                level(Level::Error),
+                RESET
+            ),
+            format!("{}2024-05-01T12:03:07Z{} cut off", hl.loaded().styles.dim, RESET),
+        ];
+        assert_eq!(out, expected.join("\n") + "\n");
+        let plain = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::K8s);
+        assert_eq!(
+            render_stream(
+                &plain,
+                "2024-05-01T12:03:04Z stdout P a\n2024-05-01T12:03:04Z stdout F b\nother"
+            ),
+            "2024-05-01T12:03:04Z ab\nother\n"
+        );
+        let mut summary = Summary::default();
+        let line = "[pod/error-page/app] 2024-05-01T12:03:04Z stdout F ok";
+        summary.record_as(line, line_level(line, &[]));
+        assert_eq!(summary.levels, [0; 8]);
+    }
+
+    #[test]
+    fn compose_prefixes() {
+        let line = parse_compose_line("error-reporter-1  | INFO sent 3 reports").unwrap();
+        assert_eq!(
+            (line.name, line.message, line.service()),
+            ("error-reporter-1", "INFO sent 3 reports", "error-reporter")
+        );
+        assert_eq!(parse_compose_line("myapp_api_2 | x").unwrap().service(), "api");
+        assert_eq!(parse_compose_line("redis |").unwrap().message, "");
+        assert_eq!(parse_compose_line("redis| x"), None);
+        assert_eq!(parse_compose_line("Attaching to api-1, worker-1"), None);
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Compose);
+        let input = "\x1b[36mapi-1     |\x1b[0m ERROR db down\n\
+                     \x1b[33merror-reporter-1  |\x1b[0m shipped\n\
+                     api-2  | ok";
+        let api = hash_color("api", ColorDepth::Basic).fg_sgr(ColorDepth::Basic);
+        let reporter = hash_color("error-reporter", ColorDepth::Basic).fg_sgr(ColorDepth::Basic);
+        let error = format!("{}{}ERROR{}", BOLD, hl.loaded().styles.color(Level::Error), RESET);
+        let expected = [
+            format!("{0}api-1{1} {0}|{1} {2} db down", api, RESET, error),
+            format!("{0}error-reporter-1{1} {0}|{1} shipped", reporter, RESET),
+            format!("{0}api-2{1}            {0}|{1} ok", api, RESET),
+        ];
+        assert_eq!(render_stream(&hl, input), expected.join("\n") + "\n");
+
+        hl.services = Some(vec!["error-reporter".to_string(), "api-2".to_string()]);
+        hl.settings.set_styles(Styles::new(&DARK_PALETTE, ColorDepth::None));
+        assert_eq!(
+            render_stream(&hl, input),
+            "error-reporter-1 | shipped\napi-2            | ok\n"
+        );
+        let mut summary = Summary::default();
+        for line in input.lines() {
+            record_line(&mut summary, line, &hl);
+        }
+        assert_eq!((summary.lines, summary.levels[Level::Error as usize]), (2, 0));
+    }
+
+    #[test]
+    fn elided_lines() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.elide_below = Some(Level::Info);
+        let run = |hl: &Highlighter, input: &str| {
+            let mut out = Vec::new();
+            process_reader(input.as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let input = "INFO start\nDEBUG a\nDEBUG b\n  at frame\nTRACE c\nWARN slow\nERROR x\nINFO y\nDEBUG z\n";
+        assert_eq!(
+            run(&hl, input),
+            "INFO start\n  \u{22ef} 4 lines elided (2 DEBUG, 1 TRACE, 1 other) \u{22ef}\nWARN slow\nERROR x\nINFO y\n  \u{22ef} 1 line elided (1 DEBUG) \u{22ef}\n"
+        );
+        // nothing dropped, nothing said, and only one placeholder at the end
+        assert_eq!(run(&hl, "INFO a\nWARN b\n"), "INFO a\nWARN b\n");
+        assert_eq!(
+            run(&hl, "DEBUG a\nDEBUG b\n"),
+            "  \u{22ef} 2 lines elided (2 DEBUG) \u{22ef}\n"
+        );
+        hl.elide_below = Some(Level::Error);
+        assert_eq!(run(&hl, "plain\nERROR x\n"), "plain\nERROR x\n");
+    }
+
+    #[test]
+    fn watched_glob() {
+        let dir = env::temp_dir().join(format!("logcolor-watch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let append = |name: &str, text: &str| {
+            let mut file =
+                std::fs::OpenOptions::new().create(true).append(true).open(dir.join(name)).unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+        append("app-2024-05-01.log", "already there\n");
+        append("other.txt", "not matching\n");
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let mut watch = GlobWatch::new(dir.clone(), "app-*.log".to_string(), true);
+        let mut poll = || {
+            let mut out = Vec::new();
+            watch.rescan(&mut out, &hl.loaded().styles).unwrap();
+            watch.read_new(&hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(poll(), "--- now following app-2024-05-01.log ---\n");
+        append("app-2024-05-01.log", "INFO one\npar");
+        assert_eq!(poll(), "INFO one\n");
+        append("app-2024-05-01.log", "tial\n");
+        append("app-2024-05-02.log", "fresh\n");
+        assert_eq!(poll(), "--- now following app-2024-05-02.log ---\npartial\nfresh\n");
+        assert_eq!(poll(), "");
+        std::fs::remove_file(dir.join("app-2024-05-01.log")).unwrap();
+        assert_eq!(poll(), "--- stopped following app-2024-05-01.log ---\n");
+        std::fs::write(dir.join("app-2024-05-02.log"), "new\n").unwrap();
+        assert_eq!(poll(), "new\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn jump_marks() {
+        let iterm =
+            TermInfo { term_program: Some("iTerm.app".into()), ..term_info("xterm", Some(8)) };
+        assert_eq!(MarkSequence::detect(&iterm), Some(MarkSequence::Iterm2));
+        let wezterm = TermInfo { term_program: Some("WezTerm".into()), ..iterm };
+        assert_eq!(MarkSequence::detect(&wezterm), Some(MarkSequence::FinalTerm));
+        assert_eq!(MarkSequence::detect(&term_info("xterm", Some(8))), None);
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.jump_marks = Some(JumpMarks { threshold: Level::Error, sequence: MarkSequence::Iterm2 });
+        let mut out = Vec::new();
+        process_reader("INFO fine\nERROR broke\nFATAL worse\n".as_bytes(), &hl, &mut out).unwrap();
+        let plain = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let expected = format!(
+            "{}\x1b]1337;SetMark\x07{}\x1b]1337;SetMark\x07{}",
+            render(&plain, "INFO fine"),
+            render(&plain, "ERROR broke"),
+            render(&plain, "FATAL worse")
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn throttled_lines() {
+        let mut window = ThrottleWindow::new(Throttle { lines: 2, errors: 3 });
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut shown = Vec::new();
+        let mut notices = Vec::new();
+        let lines = [
+            (0, Some(Level::Info)),
+            (10, Some(Level::Info)),
+            (20, None),
+            (30, Some(Level::Info)),
+            (40, Some(Level::Debug)),
+            (50, Some(Level::Error)),
+            (60, Some(Level::Error)),
+            (70, Some(Level::Fatal)),
+            (80, Some(Level::Error)),
+            (90, Some(Level::Error)),
+            (1100, Some(Level::Info)),
+            (1200, Some(Level::Info)),
+        ];
+        for (i, (ms, level)) in lines.into_iter().enumerate() {
+            let (notice, admitted) = window.admit(level, at(ms));
+            notices.extend(notice.map(|n| (i, n)));
+            if admitted {
+                shown.push(i);
+            }
+        }
+        assert_eq!(shown, [0, 1, 4, 5, 6, 7, 8, 10, 11]);
+        assert_eq!(
+            notices,
+            [(10, "  \u{22ef} suppressed 3 lines in the last second \u{22ef}".to_string())]
+        );
+        assert_eq!(window.take_notice(), None);
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.throttle = Some(Throttle { lines: 1, errors: 1 });
+        hl.summaries = true;
+        let mut out = Vec::new();
+        let summary = process_reader("INFO a\nINFO b\nINFO c\n".as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "INFO a\n  \u{22ef} suppressed 2 lines in the last second \u{22ef}\n"
+        );
+        assert_eq!(summary.levels[Level::Info as usize], 3);
+    }
+
+    #[test]
+    fn format_detection() {
+        let rust = "[2024-05-01T12:03:04Z INFO  app::db] connected\n".repeat(9) + "  continued\n";
+        let found = detect_format(rust.as_bytes());
+        assert_eq!((found.chosen, found.lines), (InputFormat::Rust, 10));
+        assert_eq!(found.scores[0], (InputFormat::Rust, 9));
+        let k8s = "2024-05-01T12:03:04Z stdout F hi\n2024-05-01T12:03:04Z stdout P cut";
+        assert_eq!(detect_format(k8s.as_bytes()).lines, 1);
+        assert_eq!(detect_format(k8s.as_bytes()).chosen, InputFormat::K8s);
+        let gelf = r#"{"version":"1.1","host":"h","short_message":"x"}"#.to_string() + "\n";
+        assert_eq!(detect_format(gelf.as_bytes()).chosen, InputFormat::Gelf);
+        // plain text, and mixes without a clear winner, stay on the generic path
+        let plain = "ERROR boom\nsomething | else\n  at frame\n";
+        assert_eq!(detect_format(plain.as_bytes()).chosen, InputFormat::Auto);
+        let mixed = "<14>1 2024-05-01T12:03:04Z h app - - - x\n[pod/a/b] y\nplain\n";
+        assert_eq!(detect_format(mixed.as_bytes()).chosen, InputFormat::Auto);
+        assert_eq!(detect_format(b"").chosen, InputFormat::Auto);
+
+        let report = detect_format(rust.as_bytes()).report("app.log");
+        assert!(
+            report.starts_with("app.log: rust\n  rust       90%  (9 of 10 lines)\n"),
+            "{}",
+            report
+        );
+        assert!(
+            detect_format(plain.as_bytes())
+                .report("-")
+                .starts_with("-: auto (nothing recognized 60% of 3 lines")
+        );
+
+        // a detected format sticks for the whole input, unlike the first-lines sniffing
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let mut out = Vec::new();
+        process_reader("api-1  | a\nworker-1  | b\n".as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "api-1 | a\nworker-1 | b\n");
+    }
+
+    #[test]
+    fn split_files_rotate() {
+        let dir = env::temp_dir().join(format!("logcolor-split-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.quiet = 1;
+        hl.split = Some(std::sync::Mutex::new(SplitDir::new(dir.clone(), Some(24), 2).unwrap()));
+        let input = "starting\nERROR one\n  at a\nINFO ok\nERROR two\nERROR three\nERROR four\nERROR five\nERROR six\n";
+        process_reader(input.as_bytes(), &hl, &mut io::sink()).unwrap();
+        let read = |name: &str| {
+            std::fs::read_to_string(dir.join(name)).unwrap_or_else(|_| "(none)".to_string())
+        };
+        assert_eq!(read("other.log"), "starting\n");
+        assert_eq!(read("info.log"), "INFO ok\n");
+        // 24 bytes each at most, two old ones kept
+        assert_eq!(read("errors.log"), "ERROR six\n");
+        assert_eq!(read("errors.log.1"), "ERROR four\nERROR five\n");
+        assert_eq!(read("errors.log.2"), "ERROR two\nERROR three\n");
+        assert_eq!(read("errors.log.3"), "(none)");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn tail_headers() {
+        assert_eq!(tail_header("==> /var/log/error.log <=="), Some("/var/log/error.log"));
+        assert_eq!(tail_header("==>  <=="), None);
+        assert_eq!(tail_header("==> a.log <== x"), None);
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Plain);
+        hl.summaries = true;
+        let input = "==> a.log <==\nERROR:  boom\nDETAIL:  key 1\n\n==> error.log <==\nDETAIL:  not a's\nWARN w\n==> a.log <==\nDETAIL:  key 2\n";
+        let mut out = Vec::new();
+        let summary = process_reader(input.as_bytes(), &hl, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let header = |path: &str| {
+            let color = hash_color(path, ColorDepth::Basic).fg_sgr(ColorDepth::Basic);
+            format!("{1}==> {0}{1}{2}{3}{0}{1} <=={0}", RESET, BOLD, color, path)
+        };
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], header("a.log"));
+        assert_eq!(lines[4], header("error.log"));
+        // error.log doesn't carry on a.log's error, and a.log's still does when it comes back
+        let error = format!("{BOLD}{DIM}{}DETAIL:", hl.loaded().styles.color(Level::Error));
+        assert!(lines[2].starts_with(&error));
+        assert_eq!(lines[5], "DETAIL:  not a's");
+        assert!(lines[8].starts_with(&error));
+        assert_eq!(summary.lines, 6);
+        let files: Vec<_> = summary.tail_files.iter().map(|(p, s)| (p.as_str(), s.lines)).collect();
+        assert_eq!(files, [("a.log", 4), ("error.log", 2)]);
+        assert_eq!(summary.tail_files[1].1.levels[Level::Warn as usize], 1);
+
+        hl.tail_headers = false;
+        let out = render_stream(&hl, "==> error.log <==");
+        assert_ne!(out, format!("{}\n", header("error.log")));
+    }
+
+    #[test]
+    fn dedup_window() {
+        assert_eq!(
+            normalize_message(" 2024-05-01T12:03:04Z WARN retry 3 of req 0x1f2e "),
+            "#-#-#T#:#:#Z WARN retry # of req #"
+        );
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.dedup = Some(DedupWindow { size: 2, errors: false });
+        let run = |hl: &Highlighter, input: &str| {
+            let mut out = Vec::new();
+            process_reader(input.as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        // two messages alternating, one number apart each time
+        let input = "WARN a 1\nWARN b 1\nWARN a 2\nWARN b 2\nWARN a 3\nINFO other\nERROR x\nERROR x\nWARN c\nWARN d\nWARN a 4\n";
+        assert_eq!(
+            run(&hl, input),
+            "WARN a 1\nWARN b 1\n  \u{22ef} 2 recent messages repeated 3 times \u{22ef}\nINFO other\nERROR x\nERROR x\nWARN c\nWARN d\nWARN a 4\n"
+        );
+        hl.dedup = Some(DedupWindow { size: 2, errors: true });
+        assert_eq!(
+            run(&hl, "ERROR x\nERROR x\n"),
+            "ERROR x\n  \u{22ef} 1 recent message repeated 1 time \u{22ef}\n"
+        );
+
+        let mut dedup = Dedup::new(DedupWindow { size: 1, errors: false });
+        let start = Instant::now();
+        assert_eq!(dedup.admit("WARN a", Some(Level::Warn), start), (None, true));
+        assert_eq!(dedup.admit("WARN a", Some(Level::Warn), start), (None, false));
+        let later = start + DEDUP_ROLLUP_INTERVAL;
+        let (rollup, admitted) = dedup.admit("WARN a", Some(Level::Warn), later);
+        assert_eq!((rollup.is_some(), admitted), (true, false));
+    }
+
+    #[test]
+    fn squeeze_repeats() {
+        assert_eq!(squeeze_key("2024-05-01T12:03:04Z [INFO] a"), "INFO] a");
+        assert_eq!(squeeze_key("INFO 2024-05-01T12:03:04Z a"), "INFO 2024-05-01T12:03:04Z a");
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let run = |hl: &Highlighter, input: &str| {
+            let mut out = Vec::new();
+            process_reader(input.as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let input = "2024-05-01T12:03:04Z INFO a\n2024-05-01T12:03:05Z INFO a\n\
+                     2024-05-01T12:03:06Z INFO a\nb\nc\nb\nc\nERROR d\nERROR d\n";
+        hl.squeeze = Some(SqueezeWindow { size: 1, live: None, following: false });
+        assert_eq!(
+            run(&hl, input),
+            "2024-05-01T12:03:04Z INFO a\n  \u{22ef} last line repeated 2 times \u{22ef}\n\
+             b\nc\nb\nc\nERROR d\n  \u{22ef} last line repeated 1 time \u{22ef}\n"
+        );
+        hl.squeeze = Some(SqueezeWindow { size: 2, live: None, following: false });
+        assert!(
+            run(&hl, input).contains("b\nc\n  \u{22ef} last 2 lines repeated 2 times \u{22ef}\n")
+        );
+        // the count itself is no line of the input and has no level
+        hl.summaries = true;
+        let summary = process_reader("WARN x\nWARN x\n".as_bytes(), &hl, &mut Vec::new()).unwrap();
+        assert_eq!((summary.lines, summary.levels[Level::Warn as usize]), (2, 2));
+
+        let mut squeeze = Squeeze::new(1);
+        let start = Instant::now();
+        assert_eq!(squeeze.admit("a", start), (None, true));
+        assert_eq!(squeeze.admit("a", start), (None, false));
+        assert_eq!(squeeze.take_stale(start), None);
+        assert!(squeeze.take_stale(start + SQUEEZE_TICK).is_some());
+        assert_eq!(squeeze.admit("a", start + SQUEEZE_TICK), (None, false));
+    }
+
+    #[test]
+    fn max_level() {
+        let filter = LevelFilter { max: Some(Level::Debug), ..LevelFilter::default() };
+        assert!(filter.keeps(Some(Level::Trace)) && filter.keeps(Some(Level::Debug)));
+        assert!(!filter.keeps(Some(Level::Info)) && !filter.keeps(Some(Level::Fatal)));
+        assert!(filter.keeps(None));
+        assert!(LevelFilter::default().keeps(Some(Level::Fatal)));
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.levels = filter;
+        hl.summaries = true;
+        let mut out = Vec::new();
+        let input = "DEBUG a\nERROR b\nTRACE c\nplain\n";
+        let summary = process_reader(input.as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "DEBUG a\nTRACE c\nplain\n");
+        assert_eq!((summary.lines, summary.levels[Level::Error as usize]), (4, 1));
+    }
+
+    #[test]
+    fn snapshot_file() {
+        let dir = env::temp_dir().join(format!("logcolor-snapshot-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("last-errors.txt");
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let snapshot =
+            std::sync::Arc::new(std::sync::Mutex::new(Snapshot::new(path.clone(), 2, Level::Warn)));
+        hl.snapshot = Some(snapshot.clone());
+        let input = "WARN one\nINFO skip\nERROR two\n  at frame\nFATAL three\n";
+        process_reader(input.as_bytes(), &hl, &mut io::sink()).unwrap();
+        snapshot.lock().unwrap().write().unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ERROR two\nFATAL three\n");
+
+        // a reader holding the old file still reads it whole; the next open sees the new one
+        let mut old = File::open(&path).unwrap();
+        process_reader("WARN four\n".as_bytes(), &hl, &mut io::sink()).unwrap();
+        snapshot.lock().unwrap().write().unwrap();
+        let mut before = String::new();
+        old.read_to_string(&mut before).unwrap();
+        assert_eq!(before, "ERROR two\nFATAL three\n");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "FATAL three\nWARN four\n");
+        let names: Vec<_> =
+            std::fs::read_dir(&dir).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(names, ["last-errors.txt"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(parse_interval("x", "500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_interval("x", "1m"), Ok(Duration::from_secs(60)));
+        assert_eq!(parse_interval("x", "2.5"), Ok(Duration::from_millis(2500)));
+        assert!(parse_interval("x", "0s").is_err());
+    }
+
+    #[test]
+    fn column_cells() {
+        let infer = Inference::defaults();
+        let cells =
+            line_cells("2024-05-01 12:03:04,120 [ERROR] - disk full", InputFormat::Auto, &infer);
+        assert_eq!(cells.timestamp, Some("2024-05-01 12:03:04,120"));
+        assert_eq!(
+            (cells.level, cells.source, cells.message),
+            (Some(Level::Error), None, "disk full")
+        );
+        let rust = "[2024-05-01T12:03:04Z WARN  my_crate::db] slow query";
+        let cells = line_cells(rust, InputFormat::Auto, &infer);
+        assert_eq!(
+            (cells.timestamp, cells.source),
+            (Some("2024-05-01T12:03:04Z"), Some("my_crate::db"))
+        );
+        assert_eq!((cells.level, cells.message), (Some(Level::Warn), "slow query"));
+        let syslog = "<34>1 2024-05-01T12:03:04Z host su - ID47 - 'su root' failed";
+        let cells = line_cells(syslog, InputFormat::Auto, &infer);
+        assert_eq!((cells.timestamp, cells.source), (Some("2024-05-01T12:03:04Z"), Some("su")));
+        assert_eq!((cells.level, cells.message), (Some(Level::Error), "'su root' failed"));
+        let bsd = line_cells("May  1 12:03:04 web nginx: started", InputFormat::Auto, &infer);
+        assert_eq!((bsd.timestamp, bsd.message), (Some("May  1 12:03:04"), "web nginx: started"));
+        // a stack frame keeps its indent; a level word mid-message isn't cut out
+        let frame = line_cells("    at Foo.bar(Foo.java:10)", InputFormat::Auto, &infer);
+        assert_eq!(frame, Cells { message: "    at Foo.bar(Foo.java:10)", ..Cells::default() });
+        let late = line_cells("request failed with error 502", InputFormat::Auto, &infer);
+        assert_eq!(late.message, "request failed with error 502");
+    }
+
+    #[test]
+    fn columns_layout() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.columns = Some(Columns { source_width: 8 });
+        let out = render_stream(
+            &hl,
+            "2024-05-01T12:03:04.123Z INFO my_app::server: listening\n\
+             2024-05-01T12:03:05Z ERROR db: down\n  caused by: refused\n",
+        );
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], "2024-05-01T12:03:04.123Z INFO  my_app:\u{2026} listening");
+        assert_eq!(lines[1], "2024-05-01T12:03:05Z     ERROR db       down");
+        assert_eq!(lines[2], format!("{:40}  caused by: refused", ""));
+        // a narrow terminal loses the source column, then the timestamp
+        assert_eq!(Columns { source_width: 16 }.widths(80), (24, 16));
+        assert_eq!(Columns { source_width: 16 }.widths(52), (24, 0));
+        assert_eq!(Columns { source_width: 16 }.widths(40), (0, 12));
+        assert_eq!(Columns { source_width: 0 }.widths(80), (24, 0));
+    }
+
+    #[test]
+    fn highlight_new() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let mut novelty = Novelty::new(NOVELTY_LIMIT);
+        novelty.first_seen("12:00:01 INFO heartbeat 17");
+        hl.novelty = Some(std::sync::Mutex::new(novelty));
+        let input =
+            "12:03:04 INFO heartbeat 18\n12:03:05 ERROR disk full\n12:03:09 ERROR disk full\n";
+        let mut out = Vec::new();
+        process_reader(input.as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "    12:03:04 INFO heartbeat 18\nNEW 12:03:05 ERROR disk full\n    12:03:09 ERROR disk full\n"
+        );
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Plain);
+        hl.novelty = Some(std::sync::Mutex::new(Novelty::new(NOVELTY_LIMIT)));
+        let mut out = Vec::new();
+        process_reader("started\nstarted\n".as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[1mNEW\x1b[0m \x1b[1mstarted\x1b[0m\n    \x1b[2mstarted\x1b[0m\n"
+        );
+
+        // the oldest are forgotten past the limit
+        let mut small = Novelty::new(2);
+        assert!(small.first_seen("a") && small.first_seen("b") && small.first_seen("c"));
+        assert!(!small.first_seen("c") && small.first_seen("a"));
+    }
+
+    #[test]
+    fn width_follows_resizes() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Plain);
+        hl.wrap = true;
+        hl.width = TermWidth::new(20);
+        let wrapped = |hl: &Highlighter| {
+            let mut out = Vec::new();
+            process_reader("one two three four five six seven\n".as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(wrapped(&hl).lines().count(), 2);
+        // a resize seen through a clone reaches every renderer holding the width
+        let cols = std::cell::Cell::new(80);
+        let shared = hl.width.clone();
+        assert!(shared.refresh(&|| cols.get()));
+        assert_eq!(wrapped(&hl), "one two three four five six seven\n");
+        assert!(!shared.refresh(&|| cols.get()));
+        cols.set(12);
+        assert!(shared.refresh(&|| cols.get()) && hl.width.get() == 12);
+        assert_eq!(wrapped(&hl).lines().count(), 4);
+    }
+
+    #[test]
+    fn since_windows() {
+        assert_eq!(parse_since("10m"), Ok(Since::Ago(Duration::from_secs(600))));
+        assert_eq!(parse_since("1d"), Ok(Since::Ago(Duration::from_secs(86_400))));
+        assert_eq!(parse_since("2024-05-01"), Ok(Since::At(1_714_521_600.0)));
+        assert_eq!(parse_since("2024-05-01T00:00:10Z"), Ok(Since::At(1_714_521_610.0)));
+        assert!(parse_since("yesterday").is_err() && parse_since("-5m").is_err());
+
+        // files count back from the start, live streams from each line
+        let now = 1_714_521_600.0;
+        let mut window = TimeWindow::new(Since::Ago(Duration::from_secs(60)), None);
+        window.started = now;
+        let line = "2024-04-30T23:59:30Z INFO tick";
+        assert_eq!(window.keeps(line, now + 3600.0), Some(true));
+        window.following = true;
+        assert_eq!(window.keeps(line, now), Some(true));
+        assert_eq!(window.keeps(line, now + 3600.0), Some(false));
+        assert_eq!(window.keeps("  at frame", now), None);
+        // BSD syslog has no year; it's taken to be this one
+        assert_eq!(window.keeps("Apr 30 23:59:30 host app: tick", now), Some(true));
+        assert_eq!(in_recent_year(86_400.0 * 364.0, now), 1_703_980_800.0);
+
+        let kernel = TimeWindow { boot: Some(now - 100.0), ..window.clone() };
+        assert_eq!(kernel_timestamp("[   95.123456] usb 1-1: new device"), Some(95.123456));
+        assert_eq!(kernel.keeps("[   95.123456] usb 1-1: new device", now), Some(true));
+        assert_eq!(kernel.keeps("[   30.000000] early boot", now), Some(false));
+    }
+
+    #[test]
+    fn since_drops_old_lines() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Plain);
+        let mut window = TimeWindow::new(Since::At(1_714_521_600.0), None);
+        window.following = true;
+        hl.since = Some(window);
+        let input = "2024-04-30T23:00:00Z ERROR old\n  old detail\n\
+                     2024-05-01T00:00:01Z ERROR new\n  new detail\n";
+        let mut out = Vec::new();
+        process_reader(input.as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2024-05-01T00:00:01Z ERROR new\n  new detail\n"
+        );
+    }
+
+    #[test]
+    fn hex_runs() {
+        let spaced = "payload: 0x48 0x54 0x54 0x50 0x2f 0x31 0x2e 0x31 0x0d";
+        let runs = find_hex_runs(spaced);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(&spaced[runs[0].range.clone()], "0x48 0x54 0x54 0x50 0x2f 0x31 0x2e 0x31 0x0d");
+        assert_eq!(runs[0].bytes[0], (9..13, 0x48));
+        assert_eq!(find_hex_runs("rx 48,54,54,50,2f,31,2e,31 ok")[0].bytes.len(), 8);
+        let joined = "body=485454502f312e31 end";
+        assert_eq!(&joined[find_hex_runs(joined)[0].range.clone()], "485454502f312e31");
+        // ids, short runs and plain numbers are left alone
+        assert!(find_hex_runs("request 7f3a9c21 took 12 ms").is_empty());
+        assert!(find_hex_runs("0x01 0x02 0x03 only").is_empty());
+        assert!(find_hex_runs("at 1714521600123456 ns").is_empty());
+        assert!(find_hex_runs("01 02 03 04 05 06 07 08x").is_empty());
+    }
+
+    #[test]
+    fn hex_dumps() {
+        let plain = Styles::new(&DARK_PALETTE, ColorDepth::None);
+        let hex = HexDumps { ascii: true };
+        assert_eq!(
+            hex.apply("tx 48 54 54 50 2f 31 2e 31 00 ok", &plain),
+            "tx 48 54 54 50 2f 31 2e 31 00 |HTTP/1.1.| ok"
+        );
+        let styles = Styles::new(&DARK_PALETTE, ColorDepth::Basic);
+        let colored =
+            HexDumps { ascii: false }.apply("\x1b[31mrx 48 54 54 50 2f 31 2e 31\x1b[0m", &styles);
+        assert_eq!(
+            colored,
+            "\x1b[31mrx \x1b[36m48 54 54 50\x1b[0m\x1b[31m \x1b[34m2f 31 2e 31\x1b[0m\x1b[0m"
+        );
+        assert_eq!(visible_text(&colored), "rx 48 54 54 50 2f 31 2e 31");
+        // each group's color starts at its first 0x
+        let prefixed =
+            HexDumps { ascii: false }.apply("rx 0x48 0x54 0x54 0x50 0x2f 0x31 0x2e 0x31", &styles);
+        assert_eq!(
+            prefixed,
+            "rx \x1b[36m0x48 0x54 0x54 0x50\x1b[0m \x1b[34m0x2f 0x31 0x2e 0x31\x1b[0m"
+        );
+        let joined = HexDumps { ascii: false }.apply("rx 0x485454502f312e31", &styles);
+        assert_eq!(joined, "rx \x1b[36m0x48545450\x1b[0m\x1b[34m2f312e31\x1b[0m");
+        // the note picks the line's own color back up after it
+        let noted = hex.apply("\x1b[31mrx 48 54 54 50 2f 31 2e 31 tail\x1b[0m", &styles);
+        assert!(noted.contains("\x1b[0m\x1b[2m|HTTP/1.1|\x1b[0m\x1b[31m tail"));
+        let bare = hex.apply("rx 48 54 54 50 2f 31 2e 31 tail", &styles);
+        assert_eq!(
+            bare,
+            "rx \x1b[36m48 54 54 50\x1b[0m \x1b[34m2f 31 2e 31\x1b[0m \x1b[2m|HTTP/1.1|\x1b[0m tail"
+        );
+    }
+
+    #[test]
+    fn extras_found() {
+        let all = Extras { kinds: Extra::ALL.to_vec(), versions: true };
+        fn found<'a>(extras: &Extras, line: &'a str) -> Vec<(&'a str, Extra)> {
+            let mut found = extras.find(line);
+            found.sort_by_key(|(range, _)| range.start);
+            found.into_iter().map(|(range, extra)| (&line[range], extra)).collect()
+        }
+        // an IP in a URL is the URL's; the sentence keeps its punctuation
+        assert_eq!(
+            found(&all, "GET http://10.0.0.1:8080/a?b=1, (see https://x.io/a_(b))."),
+            [("http://10.0.0.1:8080/a?b=1", Extra::Url), ("https://x.io/a_(b)", Extra::Url)]
+        );
+        let cases: &[(&str, Option<(&str, Extra)>)] = &[
+            ("from 192.168.1.20.", Some(("192.168.1.20", Extra::Ip))),
+            ("v1.2.3.4 and 1.2.3.4.5", None),
+            ("300.1.1.1", None),
+            ("peer [fe80::1ff:fe23:4567:890a]:443", Some(("fe80::1ff:fe23:4567:890a", Extra::Ip))),
+            ("at ::ffff:10.0.0.1", Some(("::ffff:10.0.0.1", Extra::Ip))),
+            ("net::http cafe::face 12:03:04 aa:bb:cc:dd:ee:ff", None),
+            (
+                "id 550E8400-e29b-41d4-a716-446655440000",
+                Some(("550E8400-e29b-41d4-a716-446655440000", Extra::Uuid)),
+            ),
+            // half a UUID, the rest wrapped onto the next line
+            ("id 550e8400-e29b-41d4-a716-4466", None),
+            ("took 153ms", Some(("153ms", Extra::Duration))),
+            ("in 1h2m3.5s", Some(("1h2m3.5s", Extra::Duration))),
+            ("in 12\u{b5}s", Some(("12\u{b5}s", Extra::Duration))),
+            ("a 5mb file, 1.5 and 3x", None),
+            (
+                "trace 4bf92f3577b34da6a3ce929d0e0e4736",
+                Some(("4bf92f3577b34da6a3ce929d0e0e4736", Extra::Hex)),
+            ),
+            ("at 0x7ffd5c2a", Some(("0x7ffd5c2a", Extra::Hex))),
+            ("ts 1714564984000000000", None),
+        ];
+        for &(line, expected) in cases {
+            assert_eq!(found(&all, line), expected.into_iter().collect::<Vec<_>>(), "{:?}", line);
+        }
+        let strict = Extras { kinds: vec![Extra::Ip], versions: false };
+        let line = "version 1.2.3.4 app_version=1.2.3.5 from 1.2.3.6";
+        assert_eq!(found(&all, line).len(), 3);
+        assert_eq!(found(&strict, line), [("1.2.3.6", Extra::Ip)]);
+
+        // set off only where the line is otherwise unstyled
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.extras = Some(all);
+        let underline_cyan = format!("{UNDERLINE}{}", CYAN.fg_sgr(ColorDepth::Basic));
+        assert_eq!(
+            render(&hl, "ERROR 10.0.0.1 down"),
+            format!(
+                "{BOLD}{}ERROR{RESET} {underline_cyan}10.0.0.1{RESET} down\n",
+                RED.fg_sgr(ColorDepth::Basic)
+            )
+        );
+        assert_eq!(
+            render(&hl, "1.2.3.4ERROR"),
+            render(
+                &highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto),
+                "1.2.3.4ERROR"
+            )
+        );
+        // a UUID the terminal wraps stays styled on the next row
+        let line = render(&hl, "INFO request 550e8400-e29b-41d4-a716-446655440000 done");
+        let wrapped = wrap_styled_line(line.trim_end(), 24, "", 0);
+        let rows: Vec<&str> = wrapped.lines().collect();
+        let italic_magenta = format!("{ITALIC}{}", MAGENTA.fg_sgr(ColorDepth::Basic));
+        assert_eq!(
+            rows[1..=2],
+            [
+                format!("{italic_magenta}550e8400-e29b-41d4-a716-{RESET}"),
+                format!("{italic_magenta}446655440000{RESET} done")
+            ]
+        );
+        // and on a line without a level after one with
+        let mut out = Vec::new();
+        process_reader("INFO x\nhost 1.2.3.4\n".as_bytes(), &hl, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert_eq!(
+            out.lines().nth(1),
+            Some(format!("host {underline_cyan}1.2.3.4{RESET}").as_str())
+        );
+    }
+
+    #[test]
+    fn level_columns() {
+        let column = LevelColumn::parse("5").unwrap();
+        assert_eq!(column.level("0001E  disk full"), Some((4..5, Level::Error)));
+        assert_eq!(column.level("0001X  unknown"), None);
+        assert_eq!(column.level("0001"), None);
+        let wide = LevelColumn::parse("3-7:ERR=error,WRN=warn").unwrap();
+        assert_eq!(wide.level("a WRN  x"), Some((2..7, Level::Warn)));
+        assert_eq!(wide.level("a WR"), None);
+        assert_eq!(wide.level("a E    x"), None);
+        // bytes by default, characters when asked
+        let mut accented = LevelColumn::parse("3").unwrap();
+        assert_eq!(accented.level("é W"), None);
+        accented.place = LevelPlace::Columns { start: 2, width: 1, chars: true };
+        assert_eq!(accented.level("é W"), Some((3..4, Level::Warn)));
+        assert!(LevelColumn::parse("0").is_err() && LevelColumn::parse("9-3").is_err());
+        assert!(LevelColumn::parse("5:E=LOUD").is_err() && LevelColumn::parse("5:E").is_err());
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.level_column = Some(column);
+        hl.summaries = true;
+        // "error" in the text doesn't count; the column does
+        assert_eq!(
+            render(&hl, "0001W  error rate up"),
+            "0001\x1b[1m\x1b[33mW\x1b[0m  error rate up\n"
+        );
+        assert_eq!(render(&hl, "0002 error text"), "0002 error text\n");
+        hl.levels = LevelFilter { max: Some(Level::Info), ..LevelFilter::default() };
+        let mut out = Vec::new();
+        let summary = process_reader("0001E  x\n0002I  y\n".as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(
+            (summary.levels[Level::Error as usize], summary.levels[Level::Info as usize]),
+            (1, 1)
+        );
+        assert_eq!(String::from_utf8(out).unwrap(), "0002\x1b[1m\x1b[32mI\x1b[0m  y\n");
+    }
+
+    #[test]
+    fn level_patterns() {
+        // the template's first fit that is a level; blanks split by default
+        let bracketed = LevelColumn::pattern("[{level}]").unwrap();
+        assert_eq!(bracketed.level("[main] [WARN] x"), Some((8..12, Level::Warn)));
+        let word = LevelColumn::pattern("{level}:").unwrap();
+        assert_eq!(word.level("12:03 E: x"), Some((6..7, Level::Error)));
+        let second = LevelColumn { place: LevelPlace::Field { index: 1, delim: None }, map: None };
+        assert_eq!(second.level("12:03   W  x"), Some((8..9, Level::Warn)));
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.level_column = Some(LevelColumn::pattern("| {level} |").unwrap());
+        assert_eq!(render(&hl, "x | W | y"), "x | \x1b[1m\x1b[33mW\x1b[0m | y\n");
+        assert_eq!(render(&hl, "x |W| ERROR"), "x |W| ERROR\n");
+
+        let prefixed = LevelWords { prefix: Some(10), ..LevelWords::new(&[], false) };
+        assert_eq!(prefixed.scan("INFO user saw an ERROR"), Some((0, "INFO")));
+        assert_eq!(prefixed.scan("user saw an ERROR"), None);
+        assert_eq!(prefixed.scan("é é é ERROR x"), None);
+        assert_eq!(prefixed.scan("é é ERROR x"), Some((6, "ERROR")));
+    }
+
+    /// takes at most `limit` bytes a write, then fails once `fail_after`
+    /// bytes have gone through.
+    #[derive(Clone, Default)]
+    struct Sink {
+        got: Downstream,
+        limit: Option<usize>,
+        fail_after: Option<usize>,
+    }
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let so_far = self.got.0.lock().unwrap().len();
+            if self.fail_after.is_some_and(|n| so_far >= n) {
+                return Err(io::Error::from(io::ErrorKind::BrokenPipe));
+            }
+            self.got.write(&buf[..buf.len().min(self.limit.unwrap_or(usize::MAX))])
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn mirrored_output() {
+        let text = |sink: &Sink| String::from_utf8(sink.got.0.lock().unwrap().clone()).unwrap();
+        // a short write to stdout is mirrored as short, and a short mirror
+        // write is finished
+        let (primary, mirror) = (
+            Sink { limit: Some(4), ..Sink::default() },
+            Sink { limit: Some(3), ..Sink::default() },
+        );
+        let mut tee =
+            Tee::new(Box::new(primary.clone()), Some(("m".into(), Box::new(mirror.clone()))));
+        assert_eq!(tee.write(b"\x1b[31mERROR\x1b[0m\n").unwrap(), 4);
+        assert_eq!((text(&primary), text(&mirror)), ("\x1b[31".into(), "\x1b[31".into()));
+        tee.write_all(b"m...\n").unwrap();
+        assert_eq!(text(&primary), text(&mirror));
+
+        // a failing mirror is dropped, stdout carries on
+        let primary = Sink::default();
+        let mirror = Sink { fail_after: Some(6), ..Sink::default() };
+        let mut tee =
+            Tee::new(Box::new(primary.clone()), Some(("m".into(), Box::new(mirror.clone()))));
+        writeln!(tee, "first").unwrap();
+        writeln!(tee, "second").unwrap();
+        tee.flush().unwrap();
+        writeln!(tee, "third").unwrap();
+        assert!(tee.mirror.is_none());
+        assert_eq!(
+            (text(&primary), text(&mirror)),
+            ("first\nsecond\nthird\n".into(), "first\n".into())
+        );
+        // an error on stdout is still an error
+        let mut broken = Tee::new(Box::new(Sink { fail_after: Some(0), ..Sink::default() }), None);
+        assert!(broken.write(b"x").is_err());
+    }
+
+    #[test]
+    fn closed_output() {
+        // the reader of stdout gone: reading stops there, and nothing is said
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Plain);
+        let input = "INFO line\n".repeat(100_000);
+        let mut reader = input.as_bytes();
+        let mut out = Sink { fail_after: Some(30), ..Sink::default() };
+        let e = process_reader(&mut reader, &hl, &mut out).err().unwrap();
+        assert_eq!(e.kind(), io::ErrorKind::BrokenPipe);
+        assert!(output_closed());
+        assert!(!reader.is_empty());
+        assert_eq!(
+            String::from_utf8(out.got.0.lock().unwrap().clone()).unwrap(),
+            "INFO line\n".repeat(3)
+        );
+
+        let full = io::Error::new(io::ErrorKind::StorageFull, "no space");
+        assert!(write_failed(full).is_ok());
+        assert!(write_failed(io::Error::from(io::ErrorKind::BrokenPipe)).is_err());
+
+        // a read failing is no end of the input: what came is shown, and
+        // the run fails
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Plain);
+        for quiet in [0, 1] {
+            hl.quiet = quiet;
+            let mut out = Vec::new();
+            let input = "INFO a\nWARN b\n".as_bytes().chain(Unreadable);
+            let summary = process_reader(input, &hl, &mut out).unwrap();
+            assert!(summary.read_failed);
+            if quiet == 0 {
+                assert_eq!(String::from_utf8(out).unwrap(), "INFO a\nWARN b\n");
+            }
+        }
+        assert!(!process_reader("INFO a\n".as_bytes(), &hl, &mut io::sink()).unwrap().read_failed);
+    }
+
+    #[test]
+    fn output_file() {
+        // escape sequences go, even cut between writes
+        let mut plain = Plain::new(Vec::new());
+        plain.write_all(b"\x1b[1m\x1b[31mERROR\x1b[0m boom \x1b[3").unwrap();
+        plain.write_all(b"8;5;12mx\x1b]133;A\x07\x1b\x1b[0m!\n").unwrap();
+        assert_eq!(String::from_utf8(plain.inner).unwrap(), "ERROR boom x!\n");
+
+        let path = env::temp_dir().join(format!("logcolor-output-{}.log", std::process::id()));
+        let name = path.to_str().unwrap();
+        std::fs::write(&path, "old\n").unwrap();
+        OutputFile::open(name, false).unwrap().write_all(b"new\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new\n");
+        OutputFile::open(name, true).unwrap().write_all(b"more\n").unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new\nmore\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// `--demo` against a checked-in rendering; after a deliberate change,
+    /// run the tests with `LOGCOLOR_UPDATE_SNAPSHOTS=1` from this directory.
+    #[test]
+    fn demo_snapshot() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.max_line_bytes = DEMO_LINE_BYTES;
+        hl.hex = Some(HexDumps { ascii: true });
+        hl.tracking = Some(Tracking { keys: vec!["user".to_string()], filter: None });
+        let mut out = Vec::new();
+        for section in demo_log(&hl) {
+            process_reader(section.as_bytes(), &hl, &mut out).unwrap();
+        }
+        let out = String::from_utf8(out).unwrap();
+        if env::var_os("LOGCOLOR_UPDATE_SNAPSHOTS").is_some() {
+            std::fs::write(Path::new(file!()).with_file_name("log_highlighter_demo.txt"), &out)
+                .unwrap();
+            return;
+        }
+        assert_eq!(out, include_str!("log_highlighter_demo.txt"));
+        assert_eq!(demo_log(&hl)[0].lines().count(), LEVEL_TOKENS.len() + 1);
+    }
+
+    #[test]
+    fn source_locations() {
+        let name = |line: &str| {
+            source_location(line, InputFormat::Auto).map(|l| (l.name(line).to_string(), l.file))
+        };
+        let module = |m: &str| Some((m.to_string(), false));
+        let file = |f: &str| Some((f.to_string(), true));
+        assert_eq!(
+            name("[2024-05-01T12:03:04Z INFO  my_crate::net::conn] up"),
+            module("my_crate::net::conn")
+        );
+        assert_eq!(name("I0501 12:03:04.123456  4242 conn.go:142] dialing"), file("conn.go"));
+        assert_eq!(
+            name("2024-05-01 12:03:04,123 [main] INFO  com.example.Service - started"),
+            module("com.example.Service")
+        );
+        assert_eq!(
+            name("2024-05-01 12:03:04.123  INFO 4242 --- [  main] c.x.y.Service  : started"),
+            module("c.x.y.Service")
+        );
+        assert_eq!(name(r#"{"level":"info","logger":"api.auth","msg":"ok"}"#), module("api.auth"));
+        assert_eq!(
+            name(r#"{"level":"info","caller":"net/conn.go:142","msg":"ok"}"#),
+            file("net/conn.go")
+        );
+        // message wording that only looks like a location
+        assert_eq!(name("2024-05-01 12:03:04 INFO e.g. this one"), None);
+        assert_eq!(name("2024-05-01 12:03:04 INFO version 1.2.3 - up"), None);
+        assert_eq!(name("I0501 the letter I and some digits] no"), None);
+
+        let line = "2024-05-01 12:03:04,123 [main] INFO  com.example.Service - started";
+        let cells = line_cells(line, InputFormat::Auto, &[]);
+        assert_eq!((cells.source, cells.message), (Some("com.example.Service"), "started"));
+    }
+
+    #[test]
+    fn source_filter() {
+        let sources = SourceFilter {
+            modules: vec!["net::*".to_string()],
+            module_excludes: vec!["metrics*".to_string()],
+            ..SourceFilter::default()
+        };
+        let keeps =
+            |line: &str| sources.keeps(line, source_location(line, InputFormat::Auto).as_ref());
+        assert!(keeps("[2024-05-01T12:03:04Z INFO  my_crate::net::conn] up"));
+        assert!(!keeps("[2024-05-01T12:03:04Z INFO  my_crate::db] up"));
+        assert!(!keeps("[2024-05-01T12:03:04Z INFO  net::metrics] tick"));
+        assert!(!keeps("[2024-05-01T12:03:04Z INFO  metrics::net::x] tick"));
+        // no module to go by: kept, unless strict
+        assert!(keeps("a line mentioning my_crate::db, which another module logged"));
+        assert!(keeps("I0501 12:03:04.123456  4242 conn.go:142] dialing"));
+        let strict = SourceFilter { strict: true, ..sources.clone() };
+        assert!(!strict.keeps("plain", None));
+
+        let files = SourceFilter { files: vec!["conn.go".to_string()], ..SourceFilter::default() };
+        let line = r#"{"caller":"net/conn.go:142","msg":"ok"}"#;
+        assert!(files.keeps(line, source_location(line, InputFormat::Auto).as_ref()));
+        let line = "I0501 12:03:04.123456  4242 pool.go:7] waiting";
+        assert!(!files.keeps(line, source_location(line, InputFormat::Auto).as_ref()));
+    }
+
+    /// a `--budget` of `limits` lines at each of their levels.
+    fn budget(limits: &[(Level, usize)]) -> Budget {
+        let mut budget = Budget::default();
+        for &(level, lines) in limits {
+            budget.limits[level as usize] = Some(lines);
+        }
+        budget
+    }
+
+    #[test]
+    fn budget_keeps_first_and_last() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Plain);
+        hl.budget = Some(budget(&[(Level::Info, 4), (Level::Warn, 1)]));
+        let run = |hl: &Highlighter, input: &str| {
+            let mut out = Vec::new();
+            process_reader(input.as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        // the frame goes with its line; errors have no budget
+        let input = "INFO 1\nINFO 2\nWARN a\nINFO 3\n  at frame 3\nERROR x\nINFO 4\nWARN b\nINFO 5\nINFO 6\n";
+        assert_eq!(
+            run(&hl, input),
+            "INFO 1\nINFO 2\nWARN a\n  \u{22ef} 2 INFO lines left out by --budget, 4 kept \u{22ef}\n\
+             ERROR x\n  \u{22ef} 1 WARN line left out by --budget, 1 kept \u{22ef}\nINFO 5\nINFO 6\n"
+        );
+
+        // lines the other filters drop don't count; grep only sees what is kept
+        hl.levels.max = Some(Level::Info);
+        hl.budget = Some(budget(&[(Level::Info, 2)]));
+        assert_eq!(
+            run(&hl, "INFO 1\nERROR x\nINFO 2\nINFO 3\n"),
+            "INFO 1\n  \u{22ef} 1 INFO line left out by --budget, 2 kept \u{22ef}\nINFO 3\n"
+        );
+        hl.grep = Some(Grep { matcher: Matcher::Substring("2".to_string()), before: 0, after: 0 });
+        assert_eq!(
+            run(&hl, "INFO 1\nINFO 2\nINFO 3\n"),
+            "  \u{22ef} 1 INFO line left out by --budget, 2 kept \u{22ef}\n"
+        );
+    }
+
+    #[test]
+    fn budget_while_streaming() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Plain);
+        hl.budget = Some(Budget { streaming: true, ..budget(&[(Level::Info, 2)]) });
+        let mut out = Vec::new();
+        process_reader(
+            "INFO 1\nINFO 2\nERROR x\nINFO 3\n  frame\nINFO 4\n".as_bytes(),
+            &hl,
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "INFO 1\nINFO 2\nERROR x\n  \u{22ef} --budget of 2 INFO lines used up, the rest are left out \u{22ef}\n\
+             \x20 \u{22ef} 2 lines left out by --budget (2 INFO) \u{22ef}\n"
+        );
+    }
+
+    #[test]
+    fn interleaved_records() {
+        let spliced = "2024-05-01 12:03:04 INFO start2024-05-01 12:03:04 ERROR boom";
+        assert_eq!(interleaved_splits(spliced), [30]);
+        let three = "[2024-05-01T12:03:04Z INFO  a] x[2024-05-01T12:03:05Z WARN  b] y2024-05-01 12:03:06 ERROR z";
+        assert_eq!(interleaved_splits(three), [32, 64]);
+        // timestamps quoted in a message stay where they are
+        for line in [
+            "2024-05-01 12:03:04 INFO retry scheduled for 2024-05-01 12:05:00 INFO run",
+            "2024-05-01 12:03:04 WARN deadline=\"x2024-05-01T12:00:00Z ERROR late\"",
+            "2024-05-01 12:03:04 INFO last=2024-05-01T12:00:00Z level=info",
+            "2024-05-01 12:03:04 INFO replayed \"2024-05-01 12:00:00 ERROR old\"",
+            "INFO start2024-05-01 12:03:04 ERROR boom",
+            "2024-05-01 12:03:04 INFO build2024-05-01 done",
+        ] {
+            assert_eq!(interleaved_splits(line), [], "{}", line);
+        }
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Plain);
+        hl.split_interleaved = true;
+        hl.levels.max = Some(Level::Warn);
+        let mut out = Vec::new();
+        let summary =
+            process_reader(format!("{}\nnext\n", spliced).as_bytes(), &hl, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        // each record on its own, the ERROR one filtered like any other
+        assert_eq!(out, "2024-05-01 12:03:04 INFO start\nnext\n");
+        assert_eq!(summary, Summary::default());
+        hl.levels.max = None;
+        hl.summaries = true;
+        let mut out = Vec::new();
+        let summary = process_reader(format!("{}\n", spliced).as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "2024-05-01 12:03:04 INFO start\n\u{269f} 2024-05-01 12:03:04 ERROR boom\n"
+        );
+        assert_eq!((summary.lines, summary.levels[Level::Error as usize]), (2, 1));
+        let pieces = split_interleaved(RawLine {
+            text: spliced.to_string(),
+            consumed: spliced.len() + 1,
+            continued: false,
+            spliced: false,
+            unterminated: false,
+            crlf: false,
+            late: false,
+        });
+        assert_eq!(pieces.iter().map(|p| p.consumed).sum::<usize>(), spliced.len() + 1);
+    }
+
+    #[test]
+    fn severity_gutter() {
+        let colored = Styles::new(&DARK_PALETTE, ColorDepth::Basic);
+        let gutter = Gutter::new(None, &colored, true);
+        let red = colored.color(Level::Error);
+        assert_eq!(gutter.mark(Some(Level::Fatal)), format!("{}\u{258c}{} ", red, RESET));
+        assert_eq!(gutter.mark(Some(Level::Info)), "  ");
+        assert_eq!(gutter.mark(None), "  ");
+        // no color or no UTF-8: marks that tell the levels apart as text
+        let plain = Gutter::new(None, &Styles::new(&DARK_PALETTE, ColorDepth::None), true);
+        assert_eq!((plain.mark(Some(Level::Error)), plain.mark(Some(Level::Warn))), ("! ", "? "));
+        let ascii = Gutter::new(None, &colored, false);
+        assert_eq!(visible_text(ascii.mark(Some(Level::Warn))), "? ");
+        let custom = Gutter::new(Some("#"), &colored, false);
+        assert_eq!(visible_text(custom.mark(Some(Level::Warn))), "# ");
+
+        let vars = |pairs: &'static [(&str, &str)]| {
+            move |name: &str| pairs.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+        };
+        assert!(utf8_locale(vars(&[("LANG", "en_US.UTF-8")])));
+        assert!(!utf8_locale(vars(&[("LC_ALL", "C"), ("LANG", "en_US.UTF-8")])));
+        assert!(utf8_locale(vars(&[("LC_ALL", ""), ("LC_CTYPE", "de_DE.utf8")])));
+        assert!(!utf8_locale(vars(&[])));
+
+        // the line as it came, the gutter ahead of the offsets
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Plain);
+        hl.gutter = Some(gutter);
+        hl.byte_offsets = true;
+        let mut out = Vec::new();
+        process_reader("ERROR boom\ninfo ok\n".as_bytes(), &hl, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(
+            lines[0],
+            format!("{}\u{258c}{} {}ERROR boom", red, RESET, offset_prefix(0, &hl.loaded().styles))
+        );
+        assert_eq!(lines[1], format!("  {}info ok", offset_prefix(11, &hl.loaded().styles)));
+    }
+
+    #[test]
+    fn watched_rotation() {
+        let dir = env::temp_dir().join(format!("logcolor-rotate-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let log = dir.join("app.log");
+        let append = |text: &str| {
+            let mut file =
+                std::fs::OpenOptions::new().create(true).append(true).open(&log).unwrap();
+            file.write_all(text.as_bytes()).unwrap();
+        };
+        append("old\n");
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let mut watch = GlobWatch::new(dir.clone(), "*.log".to_string(), false);
+        let mut notify = term::DirNotify::new(&dir);
+        let mut poll = || {
+            let mut out = Vec::new();
+            watch.rescan(&mut out, &hl.loaded().styles).unwrap();
+            watch.read_new(&hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(poll(), "--- now following app.log ---\n");
+        // a quiet directory keeps the watcher asleep; a write wakes it
+        if let Some(notify) = &mut notify {
+            assert!(!notify.wait(Duration::from_millis(20)));
+            append("INFO before rotation\nlast wo");
+            assert!(notify.wait(Duration::from_secs(5)));
+        } else {
+            append("INFO before rotation\nlast wo");
+        }
+        assert_eq!(poll(), "INFO before rotation\n");
+        append("rds");
+        std::fs::rename(&log, dir.join("app.log.1")).unwrap();
+        append("INFO in the new file\n");
+        assert_eq!(
+            poll(),
+            "last words\n--- app.log was rotated, following the new one ---\nINFO in the new file\n"
+        );
+        // truncated in place is the same file, read again from the top
+        std::fs::write(&log, "again\n").unwrap();
+        assert_eq!(poll(), "again\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn only_new_state() {
+        let vars = |pairs: &'static [(&str, &str)]| {
+            move |name: &str| pairs.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+        };
+        let log = Path::new("/var/log/app.log");
+        let state = state_path(log, vars(&[("XDG_STATE_HOME", "/s"), ("HOME", "/h")])).unwrap();
+        assert!(state.starts_with("/s/logcolor") && state.extension().is_some());
+        let home = state_path(log, vars(&[("XDG_STATE_HOME", "rel"), ("HOME", "/h")])).unwrap();
+        assert_eq!(home.parent(), Some(Path::new("/h/.local/state/logcolor")));
+        assert_eq!(home.file_name(), state.file_name());
+        assert_eq!(state_path(log, vars(&[])), None);
+
+        let saved = ReadState { dev: 8, ino: 42, offset: 100 };
+        assert_eq!(ReadState::parse(&saved.render(log)), Some(saved));
+        assert_eq!(ReadState::parse("something else"), None);
+        let now = FileIdentity { dev: 8, ino: 42, len: 150 };
+        let day = Duration::from_secs(86_400);
+        assert_eq!(saved.resume(now, day, None), Ok(100));
+        assert_eq!(
+            saved.resume(FileIdentity { ino: 43, ..now }, day, None),
+            Err("the file was replaced")
+        );
+        assert_eq!(
+            saved.resume(FileIdentity { len: 99, ..now }, day, None),
+            Err("the file shrank")
+        );
+        assert!(saved.resume(now, day, Some(day / 2)).is_err());
+
+        let dir = env::temp_dir().join(format!("logcolor-only-new-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log").to_string_lossy().into_owned();
+        let state = Some(dir.join("state/app.state"));
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Plain);
+        let run = |only_new: OnlyNew| {
+            let mut out = Vec::new();
+            let file = File::open(&path).unwrap();
+            process_new((&path, file), state.clone(), only_new, &hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        std::fs::write(&path, "one\ntwo\nhal").unwrap();
+        assert_eq!(run(OnlyNew::default()), "one\ntwo\nhal\n");
+        // the line being written when we stopped comes again, whole
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"f\nthree\n")
+            .unwrap();
+        assert_eq!(run(OnlyNew::default()), "half\nthree\n");
+        assert_eq!(run(OnlyNew::default()), "");
+        assert_eq!(run(OnlyNew { reset: true, ..OnlyNew::default() }), "one\ntwo\nhalf\nthree\n");
+        // replaced: read whole again
+        std::fs::remove_file(&path).unwrap();
+        std::fs::write(&path, "new\n").unwrap();
+        assert_eq!(run(OnlyNew::default()), "new\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn spans_cover_lines() {
+        let corpus = [
+            "",
+            " ",
+            "ERROR",
+            "2024-05-01T12:03:04.123Z ERROR [com.acme.Billing] charge failed",
+            "[2024-05-01T12:03:04Z WARN  my_crate::net] retry n=3 host=db",
+            "2024-05-01T12:03:04Z DEBUG span{a=1}: my_crate::db: query took=5ms",
+            "<11>1 2024-05-01T12:03:04Z host app 42 ID47 [ex@1 a=\"b\"] ERROR failed",
+            "<13>1 2024-05-01T12:03:04Z host app - - -",
+            "2024-05-01 12:03:04 ERROR 1045 [Server] Access denied",
+            "Connection refused by peer \u{1f525} ünïcödé",
+            "\x1b[31mERROR\x1b[0m already colored",
+            "got E1234 from E1234 at /v1/legacy, CRITICAL",
+            "I0501 12:03:04.123456  1234 server.go:42] listening",
+            "{\"level\":\"error\",\"logger\":\"api.auth\",\"msg\":\"denied\"}",
+        ];
+        for format in [InputFormat::Auto, InputFormat::Rust, InputFormat::Plain] {
+            let rules = || {
+                let text = "E1234\tbold,magenta\thighlight\nlog\tunderline\thighlight\n";
+                parse_rules(text, "t").unwrap()
+            };
+            let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Ansi256, format);
+            hl.settings.current_mut().rules = rules();
+            let mut mono = highlighter(&DARK_PALETTE, ColorDepth::None, format);
+            mono.settings.current_mut().rules = rules();
+            for line in corpus {
+                let spans = hl.spans(line);
+                let mut pos = 0;
+                for span in &spans {
+                    assert_eq!(span.range.start, pos, "{line:?}: {spans:?}");
+                    assert!(span.range.end > pos, "{line:?}: {spans:?}");
+                    assert!(line.is_char_boundary(span.range.end));
+                    pos = span.range.end;
+                }
+                assert_eq!(pos, line.len(), "{line:?}: {spans:?}");
+
+                // both renderers draw just these spans, highlights included
+                let mut ansi = Vec::new();
+                write_spans(&mut ansi, line, &spans, &hl.loaded().styles).unwrap();
+                let ansi = String::from_utf8(ansi).unwrap();
+                assert_eq!(render(&hl, line), ansi.clone() + "\n", "{line:?}");
+                if !line.contains('\x1b') {
+                    assert_eq!(visible_text(&ansi), line);
+                }
+                let mut plain = Vec::new();
+                write_plain_spans(&mut plain, line, &spans).unwrap();
+                assert_eq!(String::from_utf8(plain).unwrap(), line);
+                let layout = |spans: Vec<Span>| -> Vec<_> {
+                    spans.into_iter().map(|s| (s.range, s.kind)).collect()
+                };
+                assert_eq!(layout(mono.spans(line)), layout(spans), "{line:?}");
+                assert_eq!(render(&mono, line), line.to_string() + "\n");
+            }
+        }
+
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let line = "2024-05-01T12:03:04Z ERROR com.acme.Billing - charge failed";
+        let kinds: Vec<_> =
+            hl.spans(line).iter().map(|s| (&line[s.range.clone()], s.kind)).collect();
+        assert_eq!(
+            kinds,
+            [
+                ("2024-05-01T12:03:04Z", SpanKind::Timestamp),
+                (" ", SpanKind::Plain),
+                ("ERROR", SpanKind::Level),
+                (" ", SpanKind::Plain),
+                ("com.acme.Billing", SpanKind::Entity),
+                (" - charge failed", SpanKind::Plain),
+            ]
+        );
+        assert_eq!(hl.spans(line)[2].style, hl.loaded().styles.level_style(Level::Error, BOLD));
+    }
+
+    #[test]
+    fn level_fast_path() {
+        assert!(LEVEL_STARTS[usize::from(b'e')] && LEVEL_STARTS[usize::from(b'W')]);
+        assert!(!LEVEL_STARTS[usize::from(b'q')] && !LEVEL_STARTS[usize::from(b'7')]);
+        // lines the fast path lets through or turns away alike
+        let pieces = "ok|200|mux|quark|[|]|:| - |  |err|WARN|Info|fatal|http://|/info/|\
+                      Ünïcödé|ñ|LOG:|[NOTE]|2024-05-01T12:03:04Z|0 ";
+        let pieces: Vec<&str> = pieces.split('|').collect();
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut next = |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as usize
+        };
+        for _ in 0..20_000 {
+            let len = next(7);
+            let line: Vec<&str> = (0..len).map(|_| pieces[next(pieces.len())]).collect();
+            let line = line.join(if next(2) == 0 { " " } else { "" });
+            assert_eq!(find_level(&line), level_words().scan(&line), "{line:?}");
+        }
+        assert_eq!(find_level("12:03:04 ok 200 quark mux"), None);
+    }
+
+    /// the word scan as it was before the single pass: the line
+    /// uppercased and searched once for each word.
+    fn uppercased_candidates<'a>(words: &LevelWords, line: &'a str) -> Vec<LevelCandidate<'a>> {
+        let mut candidates = Vec::new();
+        let upper = line.to_uppercase();
+        let bytes = upper.as_bytes();
+        let glued = |b: u8| is_word_byte(b) || b == b'/';
+        for w in &words.words {
+            for (start, _) in upper.match_indices(w.word.as_str()) {
+                let end = start + w.word.len();
+                let before = start.checked_sub(1).map(|i| bytes[i]);
+                let after = bytes.get(end).copied();
+                let url = upper[end..].starts_with("://");
+                let whole = !before.is_some_and(glued) && !after.is_some_and(glued) && !url;
+                let bracketed =
+                    matches!((before, after), (Some(b'['), Some(b']')) | (Some(b'('), Some(b')')));
+                let closing = before == Some(b' ') && after == Some(b']');
+                let colon = after == Some(b':') && !url;
+                let dash = upper[end..].starts_with(" -");
+                let allowed = match w.how {
+                    TokenMatch::Loose => words.loose || whole,
+                    TokenMatch::Word => whole && !url,
+                    TokenMatch::LineStart => start == 0 && colon,
+                    TokenMatch::Label => whole && colon,
+                    TokenMatch::Bracketed => before == Some(b'[') && after == Some(b']'),
+                };
+                let range = match () {
+                    _ if bracketed => start - 1..end + 1,
+                    _ if colon => start..end + 1,
+                    _ if dash => start..end + 2,
+                    _ => start..end,
+                };
+                let (Some(token), Some(word), true) =
+                    (line.get(range.clone()), line.get(start..end), allowed)
+                else {
+                    continue;
+                };
+                let mut evidence = 0;
+                let mut add = |e: Evidence, yes: bool| evidence |= u16::from(yes) << e as u16;
+                add(Evidence::Bracketed, bracketed || closing);
+                add(Evidence::Labeled, colon || dash);
+                add(
+                    Evidence::LineStart,
+                    line[..start].split_whitespace().count() <= LEVEL_START_WORDS,
+                );
+                add(Evidence::Uppercase, word.bytes().all(|b| !b.is_ascii_lowercase()));
+                add(Evidence::WholeWord, whole);
+                candidates.push(LevelCandidate {
+                    pos: range.start,
+                    token,
+                    level: w.level,
+                    evidence,
+                });
+            }
+        }
+        candidates
+    }
+
+    #[test]
+    fn single_pass_level_scan() {
+        let words = LevelWords::new(&[LevelWord::parse("CRIT=error").unwrap()], false);
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let mut corpus: Vec<String> =
+            demo_log(&hl).iter().flat_map(|s| s.lines().map(str::to_string)).collect();
+        let pieces = "ok|200|[|]|(|)|:| - |  |/|://|err|ERROR|Warn|warning|info|INFORMATION|\
+                      fatal|FTL|inf|Debug|trace|http|HTTP/1.1|/info/|LOG:|log|[NOTE]|note|\
+                      [SYSTEM]|SEVERE:|fine|crit|Critical|notice|panic:|Ünïcödé|ñ|\
+                      2024-05-01T12:03:04Z|0 |x";
+        let pieces: Vec<&str> = pieces.split('|').collect();
+        let mut seed = 0x9e37_79b9_7f4a_7c15_u64;
+        let mut next = |n: usize| {
+            seed ^= seed << 13;
+            seed ^= seed >> 7;
+            seed ^= seed << 17;
+            (seed % n as u64) as usize
+        };
+        for _ in 0..20_000 {
+            let len = next(8);
+            let line: Vec<&str> = (0..len).map(|_| pieces[next(pieces.len())]).collect();
+            corpus.push(line.join(if next(2) == 0 { " " } else { "" }));
+        }
+        for line in &corpus {
+            let mut new = words.word_candidates(line);
+            let mut old = uppercased_candidates(&words, line);
+            new.sort_by_key(|c| (c.pos, c.token.len()));
+            old.sort_by_key(|c| (c.pos, c.token.len()));
+            assert_eq!(new, old, "{:?}", line);
+            let old = weigh_candidates(line, old);
+            let old = best_candidate(&old).map(|c| (c.pos, c.token));
+            assert_eq!(words.scan(line), old, "{:?}", line);
+        }
+        // what `to_uppercase` used to turn into ASCII no longer counts
+        assert_eq!(find_level("ınfo: ﬁne"), None);
+    }
+
+    #[test]
+    fn time_zones() {
+        let eastern = Zone::ruled(PosixTz::parse("EST5EDT,M3.2.0,M11.1.0").unwrap());
+        let convert = |zone: &Zone, line: &str| zone.convert(line).unwrap_or_default();
+        // the repeated hour, told apart by the abbreviation
+        assert_eq!(convert(&eastern, "2024-11-03T05:30:00Z a"), "2024-11-03T01:30:00 EDT a");
+        assert_eq!(convert(&eastern, "2024-11-03T06:30:00.25Z b"), "2024-11-03T01:30:00.25 EST b");
+        // and the skipped one, which never shows
+        assert_eq!(convert(&eastern, "2024-03-10 06:59:59Z c"), "2024-03-10 01:59:59 EST c");
+        assert_eq!(
+            convert(&eastern, "[2024-03-10T09:00:00+02:00] d"),
+            "[2024-03-10T03:00:00 EDT] d"
+        );
+        // no zone, no conversion
+        assert_eq!(eastern.convert("2024-03-10T06:59:59 e"), None);
+
+        let sydney = PosixTz::parse("AEST-10AEDT,M10.1.0,M4.1.0/3").unwrap();
+        assert_eq!(sydney.local_type(1_704_067_200).abbrev, "AEDT"); // 2024-01-01
+        assert_eq!(sydney.local_type(1_719_792_000).abbrev, "AEST"); // 2024-07-01
+        assert_eq!(PosixTz::parse("<+0330>-3:30").unwrap().std.offset, 12_600);
+        assert_eq!(PosixTz::parse("EST5EDT,J60,J300"), None);
+
+        // a TZif file: two transitions, then the rule in its footer
+        let mut tzif = Vec::new();
+        let header = |tzif: &mut Vec<u8>, counts: [u32; 6]| {
+            tzif.extend(b"TZif2");
+            tzif.extend([0; 15]);
+            counts.iter().for_each(|c| tzif.extend(c.to_be_bytes()));
+        };
+        header(&mut tzif, [0; 6]);
+        header(&mut tzif, [0, 0, 0, 2, 2, 8]);
+        [1000i64, 2000].iter().for_each(|t| tzif.extend(t.to_be_bytes()));
+        tzif.extend([1, 0]);
+        tzif.extend((-18_000i32).to_be_bytes());
+        tzif.extend([0, 0]);
+        tzif.extend((-14_400i32).to_be_bytes());
+        tzif.extend([1, 4]);
+        tzif.extend(b"EST\0EDT\0\nEST5EDT,M3.2.0,M11.1.0\n");
+        let zone = Zone::parse(&tzif).unwrap();
+        let abbrev = |t| zone.local_type(t).abbrev.as_str();
+        assert_eq!((abbrev(500), abbrev(1500), abbrev(2500)), ("EST", "EDT", "EST"));
+        assert_eq!(abbrev(1_719_792_000), "EDT");
+        assert!(Zone::parse(b"TZif2 but short").is_none());
+
+        assert!(Zone::load("../../etc/passwd").is_err());
+    }
+
+    #[test]
+    fn compare_signatures() {
+        let old = "10:00:00 ERROR db timeout after 30s\n\
+                   10:00:01 ERROR db timeout after 31s\n\
+                   10:00:02 ERROR cache miss storm\n\
+                   10:00:03 INFO all good\n\
+                   10:00:04 ERROR disk full on sda\n";
+        let new = "11:00:00 ERROR db timeout after 12s\n\
+                   11:00:01 ERROR cache miss storm\n\
+                   11:00:02 ERROR cache miss storm\n\
+                   11:00:03 ERROR cache miss storm\n\
+                   11:00:04 FATAL panic in worker 7\n\
+                   11:00:05 WARN slow\n";
+        let infer = Inference::defaults();
+        let old = error_signatures(old.as_bytes(), &infer).unwrap();
+        let new = error_signatures(new.as_bytes(), &infer).unwrap();
+        assert_eq!(old.len(), 3);
+        assert_eq!(old["#:#:# ERROR db timeout after #s"].count, 2);
+
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let compare = |threshold| {
+            let mut out = Vec::new();
+            write_comparison(&mut out, ("old", &old), ("new", &new), threshold, &hl).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(
+            compare(2.0),
+            "──── new in new (1) ────\n\
+             \x20       1× 11:00:04 FATAL panic in worker 7\n\
+             ──── resolved since old (1) ────\n\
+             \x20       1× 10:00:04 ERROR disk full on sda\n\
+             ──── changed by 2x or more (2) ────\n\
+             \x20     1→3 11:00:01 ERROR cache miss storm\n\
+             \x20     2→1 11:00:00 ERROR db timeout after 12s\n"
+        );
+        assert!(compare(3.0).ends_with("(1) ────\n      1→3 11:00:01 ERROR cache miss storm\n"));
+    }
+
+    #[test]
+    fn metrics_endpoint() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let metrics = std::sync::Arc::new(Metrics::default());
+        hl.metrics = Some(metrics.clone());
+        let input = "2024-05-01T10:00:00Z ERROR a\nINFO b\nno level\n2024-05-01T10:00:05Z WARN c";
+        let mut reader = Metered::new(input.as_bytes(), "app \"1\".log", &hl);
+        // a line split across reads counts once
+        let mut buf = [0; 7];
+        while reader.read(&mut buf).unwrap() > 0 {}
+        let text = metrics.render();
+        assert!(
+            text.contains("logcolor_lines_total{input=\"app \\\"1\\\".log\",level=\"error\"} 1\n")
+        );
+        assert!(text.contains("level=\"warn\"} 1\n") && text.contains("level=\"none\"} 1\n"));
+        assert!(text.contains("logcolor_bytes_read_total{input=\"app \\\"1\\\".log\"} 72\n"));
+        assert!(
+            text.contains(
+                "logcolor_last_timestamp_seconds{input=\"app \\\"1\\\".log\"} 1714557605\n"
+            )
+        );
+
+        let server = MetricsServer::start("127.0.0.1:0", metrics).unwrap();
+        let get = |path: &str| {
+            let mut stream = std::net::TcpStream::connect(server.addr).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: x\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&text));
+        assert!(get("/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        assert!(get("/metrics/x").starts_with("HTTP/1.1 404"));
+        // and shuts down, not leaving the thread behind
+        drop(server);
+    }
+
+    #[test]
+    fn alert_rate_hysteresis() {
+        let rate = AlertRate::parse("4/10s").unwrap();
+        assert_eq!((rate.errors, rate.window), (4, Duration::from_secs(10)));
+        let mut alarm = RateAlarm::new(rate);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let changes: Vec<_> = [(0, true), (1, true), (2, false), (3, true), (4, true), (5, true)]
+            .into_iter()
+            .filter_map(|(secs, error)| alarm.observe(error, at(secs)).map(|c| (secs, c)))
+            .collect();
+        assert_eq!(changes, [(4, AlarmChange::Raised(4))]);
+        // 3 then 2 errors left in the window: under the threshold, not under half of it
+        assert_eq!(alarm.observe(false, at(10)), None);
+        assert_eq!(alarm.observe(false, at(13)), None);
+        assert_eq!(alarm.observe(false, at(14)), Some(AlarmChange::Cleared(1)));
+        assert_eq!(alarm.observe(true, at(14)), None);
+        // lines without a level come after the errors and count the time
+        assert_eq!(alarm.observe(false, at(30)), None);
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.alert = Some(Alert {
+            alarm: std::sync::Mutex::new(RateAlarm::new(AlertRate::parse("2/1m").unwrap())),
+            bell: false,
+            exec: None,
+        });
+        let mut out = Vec::new();
+        process_reader("ERROR a\nINFO b\nERROR c\nERROR d\n".as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "ERROR a\nINFO b\n \u{25b2} ALERT  2 errors in the last 1m (--alert-rate 2/1m)\n\
+             ERROR c\nERROR d\n"
+        );
+
+        hl.settings.set_styles(Styles::new(&DARK_PALETTE, ColorDepth::Basic));
+        hl.width = TermWidth::new(60);
+        hl.alert = Some(Alert {
+            alarm: std::sync::Mutex::new(RateAlarm::new(AlertRate::parse("1/1m").unwrap())),
+            bell: true,
+            exec: None,
+        });
+        let mut out = Vec::new();
+        process_reader("ERROR a\nINFO b\n".as_bytes(), &hl, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.lines().collect();
+        assert!(lines[0].starts_with('\x07'));
+        assert_eq!(visible_text(lines[0]).trim_start_matches('\x07').chars().count(), 60);
+        assert_eq!(visible_text(lines[1]), "ERROR a");
+        assert!(lines[1].ends_with(&format!(" a{}", RESET)));
+        assert!(lines[2].ends_with(" b"));
+
+        assert!(AlertRate::parse("50").is_err());
+        assert!(AlertRate::parse("0/10s").is_err());
+        assert!(AlertRate::parse("5/soon").is_err());
+    }
+
+    #[test]
+    fn display_widths() {
+        assert_eq!(display_width("ERROR 接続に失敗しました"), 6 + 2 * 9);
+        assert_eq!(display_width("ｱｲｳ"), 3);
+        assert_eq!(display_width("e\u{301}"), 1);
+        assert_eq!(display_width("deploy 🚀 done"), 14);
+        // a family, a thumbs up with a skin tone, a flag and a heart drawn as an emoji
+        assert_eq!(display_width("👨\u{200d}👩\u{200d}👧"), 2);
+        assert_eq!(display_width("👍🏽"), 2);
+        assert_eq!(display_width("🇯🇵"), 2);
+        assert_eq!(display_width("❤\u{fe0f}"), 2);
+        assert_eq!(display_width(&format!("{BOLD}日本{RESET}")), 4);
+
+        // cut before a grapheme that doesn't fit, never inside one
+        assert_eq!(fit_cell("認証サービス", 6), ("認証\u{2026}".to_string(), 1));
+        assert_eq!(
+            fit_cell("ab👨\u{200d}👩\u{200d}👧cd", 5),
+            ("ab👨\u{200d}👩\u{200d}👧\u{2026}".to_string(), 0)
+        );
+        assert_eq!(
+            fit_cell("ae\u{301}\u{301}bcd", 4),
+            ("ae\u{301}\u{301}b\u{2026}".to_string(), 0)
+        );
+        assert_eq!(fit_cell("🇯🇵🇯🇵", 3), ("🇯🇵\u{2026}".to_string(), 0));
+        assert_eq!(fit_cell("日本", 6), ("日本".to_string(), 2));
+        assert_eq!(wrap_styled_line("🇯🇵🇯🇵🇯🇵", 4, "", 0), "🇯🇵🇯🇵\n🇯🇵");
+        assert_eq!(
+            wrap_styled_line("👨\u{200d}👩\u{200d}👧x", 1, "", 0),
+            "👨\u{200d}👩\u{200d}👧\nx"
+        );
+
+        // the message of a wrapped line starts under itself after wide text
+        let wrapped =
+            wrap_styled_line("認証 ERROR トークンの期限が切れました 再試行します", 37, "", 0);
+        let rows: Vec<_> = wrapped.lines().collect();
+        assert_eq!(rows, ["認証 ERROR トークンの期限が切れました", "           再試行します"]);
+        assert!(rows.iter().all(|row| display_width(row) <= 37));
+
+        // --columns keeps the message column straight whatever the source
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let columns = Columns { source_width: 8 };
+        hl.width = TermWidth::new(80);
+        let mut out = Vec::new();
+        for source in ["api", "認証サービス", "🚀deploy🚀", "ab🇯🇵🇯🇵"] {
+            let cells =
+                line_cells("2024-05-01T12:00:00Z ERROR 失敗しました", InputFormat::Auto, &[]);
+            write_columns(&mut out, &Cells { source: Some(source), ..cells }, &columns, &hl)
+                .unwrap();
+        }
+        let out = String::from_utf8(out).unwrap();
+        let starts: Vec<_> =
+            out.lines().map(|row| display_width(&row[..row.find("失敗").unwrap()])).collect();
+        assert!(starts.iter().all(|&start| start == starts[0]), "{:?}\n{}", starts, out);
+    }
+
+    #[test]
+    fn audit_records() {
+        let path = env::temp_dir().join(format!("logcolor-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let env = |var: &str| (var == "TERM").then(|| "xterm-256color".to_string());
+        let given = args(&["--max-level", "info", "--rules", "app.rules", "app.log"]);
+        let config = audit_config(&given, Some(Path::new("/etc/logcolor/config")), env);
+        let metrics = std::sync::Arc::new(Metrics::default());
+        let audit = Audit::start(path.to_str().unwrap(), config, metrics.clone()).unwrap();
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.levels = LevelFilter { max: Some(Level::Info), ..LevelFilter::default() };
+        hl.throttle = Some(Throttle { lines: 1, errors: 1 });
+        hl.settings.current_mut().rules =
+            parse_rules("INFO a\t-\tsuppress\n", "app.rules").unwrap();
+        hl.metrics = Some(metrics);
+        hl.audit = Some(audit.clone());
+        let input = "INFO a\nERROR b\nINFO c\nINFO d\n";
+        let mut out = Vec::new();
+        process_reader(Metered::new(input.as_bytes(), "app.log", &hl), &hl, &mut out).unwrap();
+        audit.end("finished");
+        audit.end("interrupted");
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let records: Vec<Json> = text.lines().map(|line| parse_json(line).unwrap()).collect();
+        assert_eq!(records.len(), 2);
+        let field = |record: &Json, path: &str| record.path(path).map(Json::to_text);
+        assert_eq!(field(&records[0], "event").as_deref(), Some("start"));
+        assert_eq!(
+            records[0].get("config").unwrap().to_string(),
+            r#"{"args":["--max-level","info","--rules","app.rules","app.log"],"config_file":"/etc/logcolor/config","env":{"TERM":"xterm-256color"}}"#
+        );
+        let end = &records[1];
+        assert_eq!(field(end, "event").as_deref(), Some("end"));
+        assert_eq!(field(end, "exit").as_deref(), Some("finished"));
+        assert!(field(end, "time").is_some_and(|time| time.ends_with('Z')));
+        assert_eq!(
+            end.get("suppressed").unwrap().to_string(),
+            r#"{"max_level":1,"min_level":0,"since":0,"source":0,"grep":0,"elide_below":0,"dedup":0,"squeeze":0,"throttle":2,"budget":0,"rules":1}"#
+        );
+        let Some(Json::Array(inputs)) = end.get("inputs") else { panic!("no inputs") };
+        assert_eq!(field(&inputs[0], "input").as_deref(), Some("app.log"));
+        assert_eq!(field(&inputs[0], "bytes").as_deref(), Some("29"));
+        assert_eq!(field(&inputs[0], "lines.info").as_deref(), Some("3"));
+        assert_eq!(field(&inputs[0], "lines.error").as_deref(), Some("1"));
+    }
+
+    #[test]
+    fn unescaped_messages() {
+        assert_eq!(unescape_message(r#"a\nb\t"c\" \\n \x"#), "a\nb\t\"c\" \\n \\x");
+        assert!(matches!(unescape_message("plain"), std::borrow::Cow::Borrowed(_)));
+        let flood = unescape_message(&"x\\n".repeat(600)).into_owned();
+        assert_eq!(flood.lines().count(), UNESCAPE_MAX_LINES + 1);
+        assert_eq!(
+            flood.lines().last(),
+            Some("\u{22ef} 101 more lines cut by --unescape \u{22ef}")
+        );
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let line =
+            r#"{"level":"error","msg":"failed","stack":"Error: boom\\n  at run (app.js:3)","n":1}"#;
+        assert_eq!(render(&hl, line), format!("{}\n", line));
+        hl.unescape = true;
+        assert_eq!(
+            render(&hl, line),
+            "{\"level\":\"error\",\"msg\":\"failed\",\"stack\":\"Error: boom\",\"n\":1}\n      at run (app.js:3)\n"
+        );
+        // keys below the top level and values that aren't messages stay put
+        let nested = r#"{"ctx":{"msg":"a\\nb"},"path":"c:\\new"}"#;
+        assert_eq!(render(&hl, nested), format!("{}\n", nested));
+
+        hl.json_fields =
+            Some(JsonFields { fields: vec!["level".into(), "msg".into()], rest: false });
+        assert_eq!(
+            render(&hl, r#"{"level":"warn","msg":"retrying\\nattempt 2\\nattempt 3"}"#),
+            "warn retrying\n     attempt 2\n     attempt 3\n"
+        );
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Journald);
+        hl.unescape = true;
+        let entry = r#"{"SYSLOG_IDENTIFIER":"app","PRIORITY":"3","MESSAGE":"boom\\ntrace"}"#;
+        let loaded = hl.loaded();
+        let red = loaded.styles.color(Level::Error);
+        assert_eq!(
+            render(&hl, entry),
+            format!("{BOLD}{red}app{RESET}: boom\n     {red}trace{RESET}\n")
+        );
+    }
+
+    #[test]
+    fn line_ranges() {
+        assert_eq!(LineRange::parse("1000:2000"), Ok(LineRange { start: 1000, end: Some(2000) }));
+        assert_eq!(LineRange::parse("1000:"), Ok(LineRange { start: 1000, end: None }));
+        assert_eq!(LineRange::parse(":20"), Ok(LineRange { start: 1, end: Some(20) }));
+        assert_eq!(LineRange::parse("7"), Ok(LineRange { start: 7, end: Some(7) }));
+        assert!(LineRange::parse("20:10").is_err());
+        assert!(LineRange::parse("0:10").is_err());
+        assert!(LineRange::parse("a:b").is_err());
+
+        let input: String = (1..=20).map(|n| format!("INFO line {}\n", n)).collect();
+        let mut reader = BufReader::with_capacity(16, input.as_bytes());
+        assert_eq!(skip_lines(&mut reader, 12).unwrap(), (12, 147));
+        let mut rest = String::new();
+        reader.read_line(&mut rest).unwrap();
+        assert_eq!(rest, "INFO line 13\n");
+        let mut short = BufReader::new("a\nb".as_bytes());
+        assert_eq!(skip_lines(&mut short, 5).unwrap(), (1, 3));
+
+        let read_range = |hl: &Highlighter, input: &str| {
+            let mut out = Vec::new();
+            process_reader(input.as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.lines = LineRange::parse("10:11").ok();
+        hl.number = true;
+        let out = read_range(&hl, &input);
+        let numbers: Vec<&str> = out.lines().map(|line| line[..6].trim()).collect();
+        assert_eq!(numbers, ["7", "8", "9", "10", "11", "12", "13", "14"]);
+        assert!(out.contains("     9:INFO line 9\n    10:INFO line 10\n"));
+        hl.number = false;
+        hl.byte_offsets = true;
+        let out = read_range(&hl, &input);
+        assert!(out.starts_with("      72:INFO line 7\n"), "{}", out);
+
+        // dimmed on either side of the range
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.lines = LineRange::parse("19:").ok();
+        let out = read_range(&hl, &input);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], format!("{}INFO line 16{}", hl.loaded().styles.dim, RESET));
+        assert_eq!(visible_text(lines[3]), "INFO line 19");
+        assert!(!lines[3].starts_with(&hl.loaded().styles.dim));
+    }
+
+    #[test]
+    fn invisible_characters() {
+        assert!(INVISIBLES.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(invisible('\u{feff}'), Some(("BOM", false)));
+        assert_eq!(invisible('\u{202e}'), Some(("RLO", true)));
+        assert_eq!(invisible('a'), None);
+
+        let read = |hl: &Highlighter, input: &str| {
+            let mut out = Vec::new();
+            process_reader(input.as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let input = "\u{200b}ERROR id\u{202e}dcba\u{202c} x\n";
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        // only the bidi controls, unless asked
+        assert_eq!(
+            read(&hl, input),
+            "\u{200b}ERROR id\u{27e8}RLO\u{27e9}dcba\u{27e8}PDF\u{27e9} x\n"
+        );
+        hl.invisibles.allow_bidi = true;
+        assert_eq!(read(&hl, input), input);
+        hl.invisibles.show = true;
+        assert_eq!(read(&hl, "a\u{a0}b\n"), "a\u{27e8}NBSP\u{27e9}b\n");
+
+        // the level is still colored where it was, and picked up again after
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.invisibles.show = true;
+        let placeholder = |name: &str| format!("\x1b[35m\u{27e8}{}\u{27e9}{}", name, RESET);
+        let red = hl.loaded().styles.color(Level::Error).to_string();
+        let out = read(&hl, "\u{feff}ERROR: dis\u{ad}k full\n");
+        assert_eq!(
+            out,
+            format!(
+                "{}{BOLD}{red}ERROR:{RESET} dis{}k full\n",
+                placeholder("BOM"),
+                placeholder("SHY")
+            )
+        );
+        let styled = format!("{}{}bad\u{200b}word{}", BOLD, red, RESET);
+        let shown = hl.invisibles.apply(&styled, &hl.loaded().styles);
+        assert_eq!(
+            shown,
+            format!(
+                "{}{}bad{}{}{}{}word{}",
+                BOLD,
+                red,
+                RESET,
+                placeholder("ZWSP"),
+                BOLD,
+                red,
+                RESET
+            )
+        );
+    }
+
+    #[test]
+    fn continuation_modes() {
+        assert_eq!(indent_width("    x"), 4);
+        assert_eq!(indent_width("  \tx"), TAB_WIDTH);
+        assert_eq!(indent_width("\t  x"), TAB_WIDTH + 2);
+        assert_eq!(indent_width("   "), 0);
+        assert_eq!(ContinuationMode::default_for(InputFormat::K8s), ContinuationMode::Off);
+        assert_eq!(ContinuationMode::default_for(InputFormat::Plain), ContinuationMode::Indent);
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Plain);
+        let red = RED.fg_sgr(ColorDepth::Basic);
+        let dump = "2024-05-01 ERROR bad config:\n  server:\n\tport: 80\nnext line\n";
+        for mode in [None, Some(ContinuationMode::Deeper)] {
+            hl.continuation = mode;
+            let out = render_stream(&hl, dump);
+            let lines: Vec<&str> = out.lines().collect();
+            assert_eq!(lines[1], format!("{red}  server:{RESET}"));
+            assert_eq!(lines[2], format!("{red}\tport: 80{RESET}"));
+            assert_eq!(lines[3], "next line");
+        }
+
+        // every line indented the same: nothing is a continuation
+        let indented = "  12:00:01 ERROR upstream gone\n  12:00:02 heartbeat ok\n";
+        hl.continuation = Some(ContinuationMode::Deeper);
+        assert_eq!(render_stream(&hl, indented).lines().nth(1), Some("  12:00:02 heartbeat ok"));
+        hl.continuation = Some(ContinuationMode::Indent);
+        let inherited = format!("{red}  12:00:02 heartbeat ok{RESET}");
+        assert_eq!(render_stream(&hl, indented).lines().nth(1), Some(inherited.as_str()));
+        // deeper than a tab is deeper than eight spaces
+        let tabbed = "\tWARN slow\n        same depth\n\t  deeper\n";
+        let out = render_stream(&hl, tabbed).replace(hl.loaded().styles.color(Level::Warn), "<y>");
+        hl.continuation = Some(ContinuationMode::Deeper);
+        let deeper =
+            render_stream(&hl, tabbed).replace(hl.loaded().styles.color(Level::Warn), "<y>");
+        assert_eq!(out.lines().nth(1), Some(format!("<y>        same depth{RESET}").as_str()));
+        assert_eq!(deeper.lines().nth(1), Some("        same depth"));
+        // which ends the event, so only a line right under it goes deeper
+        assert_eq!(deeper.lines().nth(2), Some("\t  deeper"));
+        let deeper = render_stream(&hl, "\tWARN slow\n\t  deeper\n");
+        let deeper = deeper.replace(hl.loaded().styles.color(Level::Warn), "<y>");
+        assert_eq!(deeper.lines().nth(1), Some(format!("<y>\t  deeper{RESET}").as_str()));
+
+        // a trace whose logger dropped the indent still goes with its error
+        hl.continuation = None;
+        let trace = "ERROR failed\nat com.x.Y(Y.java:3)\nCaused by: java.io.EOFException\n\
+                     ... 4 more\nINFO ok\nplain\n";
+        let out = render_stream(&hl, trace).replace(&red, "<r>");
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[1], format!("<r>at com.x.Y(Y.java:3){RESET}"));
+        assert_eq!(lines[2], format!("<r>Caused by: java.io.EOFException{RESET}"));
+        assert_eq!(lines[3], format!("<r>... 4 more{RESET}"));
+        assert!(!lines[4].contains("<r>") && lines[5] == "plain");
+        // a line going with neither ends the event; an exception's own line doesn't
+        let out = render_stream(&hl, "ERROR x\nplain\n  indented\n");
+        assert_eq!(out.lines().nth(2), Some("  indented"));
+        let thrown = "ERROR x\njava.io.EOFException: eof\n  at a.B(B.java:1)\n";
+        let out = render_stream(&hl, thrown).replace(&red, "<r>");
+        assert_eq!(
+            out.lines().skip(1).collect::<Vec<_>>(),
+            ["java.io.EOFException: eof".to_string(), format!("<r>  at a.B(B.java:1){RESET}")]
+        );
+        assert!(is_exception_header("ValueError: bad") && !is_exception_header("plain: x"));
+        hl.continuation = Some(ContinuationMode::Any);
+        let out = render_stream(&hl, "ERROR failed\nthen this\nand this\n").replace(&red, "<r>");
+        assert_eq!(out.lines().nth(2), Some(format!("<r>and this{RESET}").as_str()));
+        hl.continuation_max = 1;
+        let out = render_stream(&hl, "ERROR failed\nthen this\nand this\n").replace(&red, "<r>");
+        assert_eq!(out.lines().nth(1), Some(format!("<r>then this{RESET}").as_str()));
+        assert_eq!(out.lines().nth(2), Some("and this"));
+        hl.continuation = Some(ContinuationMode::Off);
+        assert_eq!(render_stream(&hl, trace).lines().nth(1), Some("at com.x.Y(Y.java:3)"));
+    }
+
+    #[test]
+    fn rules_reload() {
+        let path = env::temp_dir().join(format!("logcolor-reload-{}.rules", std::process::id()));
+        std::fs::write(&path, "disk\tmagenta\n").unwrap();
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Plain);
+        let path_text = path.to_string_lossy().into_owned();
+        hl.settings.rules = Some(path_text.clone());
+        hl.settings.current_mut().rules = read_rules(&path_text).unwrap();
+        let read = |hl: &Highlighter| {
+            let mut out = Vec::new();
+            process_reader("disk full\n".as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let magenta = format!("{}disk{}", Color::Basic(5).fg_sgr(ColorDepth::Basic), RESET);
+        assert!(read(&hl).starts_with(&magenta));
+        // no signal, no reading
+        assert_eq!(hl.settings.reload_if_asked(), None);
+
+        // a broken file keeps the old rules
+        std::fs::write(&path, "disk\tmauve\n").unwrap();
+        hl.settings.reload.store(true, std::sync::atomic::Ordering::Relaxed);
+        let out = read(&hl);
+        let lines: Vec<&str> = out.lines().collect();
+        let red = format!(
+            "{}--- configuration not reloaded: {}:1: unknown style 'mauve' ---",
+            RED.fg_sgr(ColorDepth::Basic),
+            path_text
+        );
+        assert_eq!(lines[0], format!("{}{}", red, RESET));
+        assert!(lines[1].starts_with(&magenta));
+        assert_eq!(lines.len(), 2);
+
+        std::fs::write(&path, "full\tunderline\n").unwrap();
+        hl.settings.reload.store(true, std::sync::atomic::Ordering::Relaxed);
+        let out = read(&hl);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], format!("{}--- configuration reloaded ---{}", DIM, RESET));
+        assert_eq!(lines[1], format!("disk {}full{}", UNDERLINE, RESET));
+        assert_eq!(read(&hl), lines[1].to_string() + "\n");
+
+        std::fs::remove_file(&path).unwrap();
+        hl.settings.reload.store(true, std::sync::atomic::Ordering::Relaxed);
+        assert!(hl.settings.reload_if_asked().is_some_and(|r| r.is_err()));
+        assert_eq!(hl.loaded().rules.len(), 1);
+    }
+
+    #[test]
+    fn config_reload() {
+        let path = env::temp_dir().join(format!("logcolor-reload-{}.config", std::process::id()));
+        std::fs::write(&path, "WARN=magenta\n").unwrap();
+        let base = Styles::new(&DARK_PALETTE, ColorDepth::Basic);
+        let styles = base.clone().with_scheme(&read_scheme(&path).unwrap());
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Plain);
+        hl.settings =
+            Settings { config: Some(path.clone()), ..Settings::new(base, styles, Vec::new()) };
+        let read = |hl: &Highlighter| {
+            let mut out = Vec::new();
+            process_reader("WARN low\n".as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let warn =
+            |color: Color| format!("{}{}WARN{} low", BOLD, color.fg_sgr(ColorDepth::Basic), RESET);
+        let magenta = warn(Color::Basic(5));
+        assert_eq!(read(&hl), magenta.clone() + "\n");
+
+        // a broken config keeps the old colors
+        std::fs::write(&path, "WARN=mauve\n").unwrap();
+        hl.settings.reload.store(true, std::sync::atomic::Ordering::Relaxed);
+        let out = read(&hl);
+        let lines: Vec<&str> = out.lines().collect();
+        assert!(lines[0].contains("--- configuration not reloaded: "), "{out:?}");
+        assert!(lines[0].contains(":1: unknown style 'mauve' ---"), "{out:?}");
+        assert_eq!(lines[1..], [magenta.as_str()]);
+
+        // levels it no longer mentions go back to the palette's
+        std::fs::write(&path, "ERROR=blue\n").unwrap();
+        hl.settings.reload.store(true, std::sync::atomic::Ordering::Relaxed);
+        let out = read(&hl);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[0], format!("{}--- configuration reloaded ---{}", DIM, RESET));
+        assert_eq!(lines[1..], [warn(YELLOW).as_str()]);
+        assert_eq!(
+            hl.loaded().styles.color(Level::Error),
+            Color::Basic(4).fg_sgr(ColorDepth::Basic)
+        );
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn ranked_level_candidates() {
+        #[rustfmt::skip]
+        let table = [
+            ("INFO retry succeeded after previous ERROR", "INFO"),
+            ("retry succeeded after previous error, INFO: all good", "INFO:"),
+            ("2024-05-01 12:03:04 error: disk [INFO] at 90%", "error:"),
+            ("12:03:04 job=42 shard=7 phase=3 ERROR: see [DEBUG] above", "ERROR:"),
+            ("12:03:04 job=42 shard=7 phase=3 request=abc ERROR: see [DEBUG] above", "[DEBUG]"),
+            ("[WARN] an ERROR would have been worse", "[WARN]"),
+            ("debug info for the ERROR: handler", "ERROR:"),
+            ("WARNING - the INFO cache is stale", "WARNING -"),
+            ("2024-05-01T12:03:04Z warn something said error", "warn"),
+            ("[12:03:04 WRN] Warning: low WARNING", "WRN"),
+            ("infoblox sync: Error talking to peer", "Error"),
+            ("2024-05-01T12:03:04.000000Z 7 [Warning] [MY-010918] [Error] x", "[Warning]"),
+        ];
+        for (line, winner) in table {
+            let found = find_level(line).map(|(_, token)| token);
+            assert_eq!(found, Some(winner), "{:?}: {}", line, explain_level(line, &[]));
+        }
+        // ties go to the earliest, then the longest word
+        assert_eq!(find_level("warning warn"), Some((0, "warning")));
+        assert_eq!(find_level("error then fatal"), Some((0, "error")));
+
+        let why = explain_level("INFO retry succeeded after previous ERROR", &[]);
+        assert_eq!(
+            why,
+            "INFO from INFO at 0: 5 (line start 2, early 1, all caps 1, whole word 1); \
+             over ERROR at 36: 3 (early 1, all caps 1, whole word 1)"
+        );
+        assert_eq!(
+            explain_level("request timed out", &Inference::defaults()),
+            "WARN inferred from 'timed out'"
+        );
+        assert_eq!(explain_level("nothing here", &[]), "no level");
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Plain);
+        let line = "INFO retry succeeded after previous ERROR";
+        let green = GREEN.fg_sgr(ColorDepth::Basic);
+        let red = RED.fg_sgr(ColorDepth::Basic);
+        assert_eq!(
+            render(&hl, line),
+            format!("{BOLD}{green}INFO{RESET} retry succeeded after previous ERROR\n")
+        );
+        hl.all_occurrences = true;
+        assert_eq!(
+            render(&hl, line),
+            format!("{BOLD}{green}INFO{RESET} retry succeeded after previous {red}ERROR{RESET}\n")
+        );
+        assert_eq!(level_of(line, &hl), Some(Level::Info));
+
+        hl.explain = true;
+        let mut out = Vec::new();
+        process_reader("ERROR: x\n\n".as_bytes(), &hl, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let note = "  \u{21b3} ERROR from ERROR at 0: 7 \
+                    (colon 2, line start 2, early 1, all caps 1, whole word 1)";
+        assert_eq!(out.lines().nth(1), Some(format!("{DIM}{note}{RESET}").as_str()));
+        assert_eq!(out.lines().count(), 3);
+    }
+
+    #[test]
+    fn level_word_boundaries() {
+        #[rustfmt::skip]
+        let table = [
+            // inside other words, paths and names
+            ("the PREFERRED option", None),
+            ("INFORMATION_SCHEMA is read only", None),
+            ("GET /info/status 200", None),
+            ("login by warner ok", None),
+            ("errored twice, debugged once", None),
+            ("request_error_count=3", None),
+            // standing alone
+            ("wait, ERROR in the cache", Some("ERROR")),
+            ("(warn) slow disk", Some("(warn)")),
+            ("x <ERR> y", Some("ERR")),
+            // the structural forms over a bare word
+            ("DEBUG curl returned ERROR string", Some("DEBUG")),
+            ("curl returned the ERROR string [DEBUG]", Some("[DEBUG]")),
+            ("ts=12:03:04 msg=\"error budget spent\" level=info", Some("info")),
+            ("msg=upload severity=\"WARN\" debug=true", Some("WARN")),
+            ("deploy: notify ok, level=error", Some("error")),
+            // an early word over one deep in the message
+            ("12:03:04 worker-3 info retry from a previous attempt failed with error", Some("info")),
+        ];
+        for (line, winner) in table {
+            let found = find_level(line).map(|(_, token)| token);
+            assert_eq!(found, winner, "{:?}: {}", line, explain_level(line, &[]));
+        }
+        assert!(after_level_key("x LEVEL=") && after_level_key("{log.level=\""));
+        assert!(!after_level_key("sublevel=") && !after_level_key("level"));
+        // what comes before the key may be any character, however long
+        assert!(after_level_key("\u{2192}level=") && !after_level_key("\u{e9}tat="));
+        assert_eq!(find_level("\u{e9}tat=error disk").map(|(_, token)| token), Some("error"));
+        assert_eq!(find_level("\u{2192}level=warn x").map(|(_, token)| token), Some("warn"));
+
+        let loose = LevelWords::new(&[], true);
+        assert_eq!(loose.scan("the PREFERRED option"), Some((8, "ERR")));
+        assert_eq!(loose.scan("login by warner ok"), Some((9, "warn")));
+    }
+
+    #[test]
+    fn custom_level_words() {
+        let spec = ["CRIT=error", "errors=warn", "notice=DEBUG", "ALERT=fatal"];
+        let extra: Vec<LevelWord> = spec.iter().map(|s| LevelWord::parse(s).unwrap()).collect();
+        let words = LevelWords::new(&extra, false);
+        let found = |line: &'static str| {
+            words.find(line).map(|(pos, token)| {
+                let raw = token.trim_matches(|c: char| "[]():- ".contains(c)).to_uppercase();
+                (&line[pos..pos + token.len()], words.level(&raw))
+            })
+        };
+        assert_eq!(found("12:03:04 CRIT disk full"), Some(("CRIT", Some(Level::Error))));
+        // a user word inside a longer built-in one, or a built-in inside it
+        assert_eq!(found("12:03:04 CRITICAL disk full"), Some(("CRITICAL", Some(Level::Fatal))));
+        assert_eq!(found("the CRITTER got out"), None);
+        assert_eq!(found("errors: 3 retried"), Some(("errors:", Some(Level::Warn))));
+        assert_eq!(found("[ERROR] 3 errors"), Some(("[ERROR]", Some(Level::Error))));
+        // a built-in word takes the level given
+        assert_eq!(found("Notice: rotated"), Some(("Notice:", Some(Level::Debug))));
+        // the fast path knows the new first letters
+        assert!(words.starts[usize::from(b'a')] && !LEVEL_STARTS[usize::from(b'a')]);
+        assert_eq!(found("alert: power"), Some(("alert:", Some(Level::Fatal))));
+        let lengths: Vec<usize> = words.words.iter().map(|w| w.word.len()).collect();
+        assert!(lengths.windows(2).all(|w| w[0] >= w[1]));
+        // the defaults
+        let defaults = LevelWords::new(&[], false);
+        assert_eq!(defaults.find("PANIC: oh no"), Some((0, "PANIC:")));
+        assert_eq!(defaults.level("NOTICE"), Some(Level::Info));
+        assert_eq!(defaults.level("CRITICAL"), Some(Level::Fatal));
+
+        assert_eq!(
+            LevelWord::parse("CRIT").unwrap_err(),
+            "expected WORD=LEVEL for '--level-word', got 'CRIT'"
+        );
+        assert_eq!(
+            LevelWord::parse("CRIT=loud").unwrap_err(),
+            "unknown level 'loud' in '--level-word CRIT=loud'"
+        );
+        assert!(LevelWord::parse("=error").is_err());
+        assert!(LevelWord::parse("two words=error").is_err());
+    }
+
+    #[test]
+    fn platform_seams() {
+        assert!(identity_changed(Some((8, 42)), Some((8, 43))));
+        assert!(identity_changed(Some((8, 42)), Some((9, 42))));
+        assert!(!identity_changed(Some((8, 42)), Some((8, 42))));
+        // gone, or nothing to tell them apart by
+        assert!(!identity_changed(Some((8, 42)), None));
+        assert!(!identity_changed(None, None));
+
+        let mut out = Crlf::new(Vec::new());
+        out.write_all(b"one\ntwo\r\n\nthree\r").unwrap();
+        out.write_all(b"\nfour\n").unwrap();
+        assert_eq!(out.inner, b"one\r\ntwo\r\n\r\nthree\r\nfour\r\n");
+
+        let input = "INFO one\r\nERROR two\r\n";
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let mut out = Crlf::new(Vec::new());
+        process_reader(input.as_bytes(), &hl, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out.inner).unwrap(), input);
+    }
+
+    #[test]
+    fn preserved_final_newline() {
+        #[derive(Clone, Default)]
+        struct Shared(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+        let text = |sink: &Shared| String::from_utf8(sink.0.lock().unwrap().clone()).unwrap();
+        let dir = env::temp_dir().join(format!("logcolor-eol-{}", std::process::id()));
+        let input = "INFO up\nERROR last words";
+        // stdout, stdout with a mirror, then stdout, mirror and split files,
+        // each with a newline made up at the end and without
+        for (preserve, end) in [(false, "\n"), (true, "")] {
+            for (mirrored, split) in [(false, false), (true, false), (true, true)] {
+                let _ = std::fs::remove_dir_all(&dir);
+                let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+                hl.preserve_eol = preserve;
+                if split {
+                    hl.split =
+                        Some(std::sync::Mutex::new(SplitDir::new(dir.clone(), None, 1).unwrap()));
+                }
+                let (primary, mirror) = (Shared::default(), Shared::default());
+                let mut tee = Tee::new(
+                    Box::new(primary.clone()),
+                    mirrored.then(|| ("m".to_string(), Box::new(mirror.clone()) as Box<_>)),
+                );
+                process_reader(input.as_bytes(), &hl, &mut tee).unwrap();
+                let expected = format!("INFO up\nERROR last words{}", end);
+                assert_eq!(text(&primary), expected);
+                if mirrored {
+                    assert_eq!(text(&mirror), expected);
+                }
+                if split {
+                    let read = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+                    assert_eq!(read("errors.log"), format!("ERROR last words{}", end));
+                    assert_eq!(read("info.log"), "INFO up\n");
+                }
+            }
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+
+        // the layers in between don't put it back, and a newline that did
+        // come is kept
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.preserve_eol = true;
+        hl.number = true;
+        let run = |hl: &Highlighter, input: &str| {
+            let mut out = Vec::new();
+            process_reader(input.as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert!(run(&hl, "a\nERROR b").ends_with("ERROR\x1b[0m b"));
+        assert!(run(&hl, "a\nERROR b\n").ends_with("ERROR\x1b[0m b\n"));
+        hl.quiet = 1;
+        std::fs::create_dir_all(&dir).unwrap();
+        hl.split = Some(std::sync::Mutex::new(SplitDir::new(dir.clone(), None, 1).unwrap()));
+        assert_eq!(run(&hl, "WARN x\nWARN y"), "");
+        assert_eq!(std::fs::read_to_string(dir.join("warnings.log")).unwrap(), "WARN x\nWARN y");
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // followed files hold a line back until its newline comes
+        let path = dir.join("app.log");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&path, "").unwrap();
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.preserve_eol = true;
+        let mut watch = GlobWatch::new(dir.clone(), "*.log".to_string(), false);
+        let mut poll = || {
+            let mut out = Vec::new();
+            watch.rescan(&mut out, &hl.loaded().styles).unwrap();
+            watch.read_new(&hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(poll(), "--- now following app.log ---\n");
+        std::fs::write(&path, "INFO half").unwrap();
+        assert_eq!(poll(), "");
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b" done\n").unwrap();
+        assert_eq!(poll(), "INFO half done\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sort_buffer() {
+        let sorted = |window: Option<Duration>, input: &str| -> Vec<String> {
+            let lines = RawLines::new(input.as_bytes(), DEFAULT_MAX_LINE_BYTES);
+            SortBuffer::new(lines, window)
+                .map(|line| line.unwrap())
+                .map(|line| format!("{}{}", if line.late { "late " } else { "" }, line.text))
+                .collect()
+        };
+        let two = Some(Duration::from_secs(2));
+        // held until one 2s newer has come, then out oldest first, the lines
+        // without a timestamp going with theirs
+        let input = "12:03:04 no date\n\
+                     2024-05-01 12:00:01 a\n\
+                     2024-05-01 12:00:00 b\n\
+                     \x20 at b\n\
+                     2024-05-01 12:00:01 c\n\
+                     2024-05-01 12:00:03 d\n";
+        assert_eq!(
+            sorted(two, input),
+            [
+                "12:03:04 no date",
+                "2024-05-01 12:00:00 b",
+                "  at b",
+                "2024-05-01 12:00:01 a",
+                "2024-05-01 12:00:01 c",
+                "2024-05-01 12:00:03 d"
+            ]
+        );
+        assert_eq!(
+            sorted(None, "2024-05-01 12:00:01 a\n2024-05-01 12:00:00 b\n")[0],
+            "2024-05-01 12:00:01 a"
+        );
+
+        // the watermark: nothing goes out until a timestamp the window
+        // newer, and then only what it covers
+        let mut lines = RawLines::new(
+            "2024-05-01 12:00:01 a\n2024-05-01 12:00:00 b\n2024-05-01 12:00:02.5 c\n".as_bytes(),
+            DEFAULT_MAX_LINE_BYTES,
+        )
+        .map(|line| line.unwrap());
+        let mut buffer = SortBuffer::new(std::iter::empty(), two);
+        for line in lines.by_ref().take(2) {
+            buffer.push(line);
+        }
+        assert!(buffer.ready.is_empty());
+        assert_eq!(buffer.held, 2);
+        buffer.push(lines.next().unwrap());
+        let out: Vec<String> = buffer.ready.drain(..).map(|l| l.unwrap().text).collect();
+        assert_eq!(out, ["2024-05-01 12:00:00 b"]);
+        assert_eq!(
+            (buffer.held, buffer.released),
+            (2, Some(line_timestamp("2024-05-01 12:00:00").unwrap()))
+        );
+
+        // one older than what has gone out comes out marked, not sorted back
+        let input = "2024-05-01 12:00:01 a\n\
+                     2024-05-01 12:00:04 b\n\
+                     2024-05-01 12:00:00 straggler\n\
+                     \x20 at it\n\
+                     2024-05-01 12:00:05 c\n";
+        assert_eq!(
+            sorted(two, input),
+            [
+                "2024-05-01 12:00:01 a",
+                "late 2024-05-01 12:00:00 straggler",
+                "  at it",
+                "2024-05-01 12:00:04 b",
+                "2024-05-01 12:00:05 c"
+            ]
+        );
+
+        // past the line cap the oldest go out whatever their age
+        let many: String = (0..SORT_BUFFER_LINES + 2)
+            .map(|i| format!("2024-05-01 12:00:00.{:05} x\n", 99_999 - i))
+            .collect();
+        let lines = RawLines::new(many.as_bytes(), DEFAULT_MAX_LINE_BYTES);
+        let mut buffer = SortBuffer::new(lines, Some(Duration::from_secs(60)));
+        assert!(buffer.next().is_some());
+        assert_eq!(buffer.held, SORT_BUFFER_LINES);
+        let rest: Vec<RawLine> = buffer.map(|line| line.unwrap()).collect();
+        assert_eq!(rest.len(), SORT_BUFFER_LINES + 1);
+        // only what came after the cap was passed is late
+        assert_eq!(rest.iter().filter(|line| line.late).count(), 1);
+        assert_eq!(rest.last().map(|line| &line.text[20..]), Some("99999 x"));
+
+        // rendered, the straggler's mark is dimmed
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.sort_buffer = two;
+        let mut out = Vec::new();
+        process_reader(input.as_bytes(), &hl, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("\x1b[2mlate \x1b[0m"));
+    }
+
+    #[test]
+    fn followed_file() {
+        let dir = env::temp_dir().join(format!("logcolor-follow-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        std::fs::write(&path, "INFO one\nWARN tw").unwrap();
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let file = File::open(&path).unwrap();
+        let mut watched =
+            Watched { name: "app.log".to_string(), file, pos: 0, partial: Vec::new() };
+        let mut poll = || {
+            let mut out = Vec::new();
+            watched.read_new(&path, &hl, &mut out).unwrap();
+            (String::from_utf8(out).unwrap(), watched.pos - watched.partial.len() as u64)
+        };
+        // what is there already, then what is added; the line still being
+        // written waits, and isn't counted as read
+        assert_eq!(poll(), ("INFO one\n".to_string(), 9));
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"o\n").unwrap();
+        assert_eq!(poll(), ("WARN two\n".to_string(), 18));
+        assert_eq!(poll(), (String::new(), 18));
+        // truncated in place: from the top again
+        std::fs::write(&path, "again\n").unwrap();
+        assert_eq!(poll(), ("again\n".to_string(), 6));
+
+        // --only-new picks up where the saved offset says
+        let state = dir.join("state");
+        let identity = FileIdentity::of(&File::open(&path).unwrap()).unwrap();
+        let path_str = path.to_string_lossy();
+        save_offset(&state, &path_str, &path, identity, 6);
+        let only_new = OnlyNew::default();
+        let offset = saved_offset(&path_str, identity, Some(&state), only_new, &hl.loaded().styles);
+        assert_eq!(offset.unwrap(), 6);
+        let reset = OnlyNew { reset: true, ..only_new };
+        assert_eq!(
+            saved_offset(&path_str, identity, Some(&state), reset, &hl.loaded().styles).unwrap(),
+            0
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn min_level() {
+        let filter = LevelFilter { min: Some(Level::Warn), ..LevelFilter::default() };
+        assert!(filter.keeps(Some(Level::Warn)) && filter.keeps(Some(Level::Fatal)));
+        assert!(!filter.keeps(Some(Level::Info)) && !filter.keeps(Some(Level::Trace)));
+        assert!(filter.keeps(None));
+        let strict = LevelFilter { drop_unknown: true, ..filter };
+        assert!(!strict.keeps(None) && strict.keeps(Some(Level::Error)));
+        let band =
+            LevelFilter { min: Some(Level::Debug), max: Some(Level::Info), drop_unknown: false };
+        assert_eq!(band.dropped_by(Some(Level::Warn)), Suppressor::MaxLevel);
+        assert_eq!(band.dropped_by(Some(Level::Trace)), Suppressor::MinLevel);
+
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        hl.levels = filter;
+        let run = |hl: &Highlighter| {
+            let mut out = Vec::new();
+            let input = "INFO a\nWARNING b\nERR c\n  at x\nDEBUG d\nTRACE e\nplain\n";
+            process_reader(input.as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(run(&hl), "WARNING b\nERR c\n  at x\nplain\n");
+        hl.levels.drop_unknown = true;
+        assert_eq!(run(&hl), "WARNING b\nERR c\n");
+    }
+
+    #[test]
+    fn json_lines() {
+        let hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Json);
+        // the key decides, the message's words don't
+        let line = r#"{"level":"info","msg":"retrying after ERROR from upstream"}"#;
+        assert_eq!(level_of(line, &hl), Some(Level::Info));
+        let out = render(&hl, line);
+        let info = format!("{}{}", BOLD, hl.loaded().styles.color(Level::Info));
+        assert!(out.contains(&format!("\"{}info{}\"", info, RESET)), "{:?}", out);
+        assert!(out.contains(&format!("{}retrying after ERROR from upstream{}", BOLD, RESET)));
+        assert!(!out.contains("\x1b[31m"));
+        assert_eq!(
+            json_level(r#"{"lvl":"Warning","msg":"x"}"#).map(|(_, level)| level),
+            Some(Level::Warn)
+        );
+        assert_eq!(level_of(r#"{"lvl":"Warning","msg":"x"}"#, &hl), Some(Level::Warn));
+        let auto = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        assert_eq!(level_of(r#"{"lvl":"Warning","msg":"x"}"#, &auto), Some(Level::Warn));
+        // auto mode lets the key win as well
+        assert_eq!(level_of(line, &auto), Some(Level::Info));
+        // malformed or not an object: the plain path
+        assert_eq!(json_level(r#"{"level":"info","msg":"cut of"#), None);
+        assert_eq!(level_of("[ERROR] not json", &hl), Some(Level::Error));
+        assert_eq!(level_of(r#"{"msg":"no level here"}"#, &hl), None);
+        // --json=pretty, in the level's color
+        let mut pretty = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Json);
+        pretty.json_pretty = true;
+        let out = render(&pretty, r#"{"level":"warn","msg":"slow"}"#);
+        let tint = pretty.loaded().styles.color(Level::Warn).to_string();
+        assert!(out.starts_with(&format!("{}{{{}\n", tint, RESET)), "{:?}", out);
+        assert!(out.contains(&format!("{}{}\"slow\"{}", BOLD, tint, RESET)));
+        assert!(out.ends_with(&format!("{}}}{}\n", tint, RESET)));
+        assert_eq!(render(&pretty, "not json\n"), render(&hl, "not json\n"));
+        let sample = "{\"level\":\"info\",\"msg\":\"a\"}\n{\"level\":\"error\",\"msg\":\"b\"}\n";
+        assert_eq!(detect_format(sample.as_bytes()).chosen, InputFormat::Json);
+    }
+
+    #[test]
+    fn color_scheme() {
+        let text = "# on a light terminal\nWARN=bright_yellow\n\ninfo = dim\nERROR=red,bold\n";
+        let scheme = parse_scheme(text, "config").unwrap();
+        assert_eq!(
+            scheme.levels[Level::Warn as usize].as_ref().unwrap().fg,
+            Some(Color::Basic(11))
+        );
+        assert_eq!(scheme.levels[Level::Debug as usize], None);
+        let styles = Styles::new(&DARK_PALETTE, ColorDepth::TrueColor).with_scheme(&scheme);
+        assert_eq!(styles.color(Level::Warn), "\x1b[93m");
+        assert_eq!(
+            styles.level_style(Level::Info, BOLD),
+            Style { attrs: vec![BOLD, DIM], fg: None }
+        );
+        assert_eq!(styles.level_style(Level::Error, BOLD).attrs, vec![BOLD]);
+        // the rest is the palette's
+        assert_eq!(
+            styles.color(Level::Debug),
+            Styles::new(&DARK_PALETTE, ColorDepth::TrueColor).color(Level::Debug)
+        );
+        let printed = scheme_text(&styles, "test");
+        assert!(printed.contains("\nWARN=bright_yellow\nINFO=dim\nHTTP=dim,blue\n"), "{}", printed);
+        // what it prints reads back the same
+        let again = Styles::new(&LIGHT_PALETTE, ColorDepth::TrueColor)
+            .with_scheme(&parse_scheme(&printed, "printed").unwrap());
+        assert_eq!(scheme_text(&again, "test"), printed);
+        assert_eq!(
+            parse_scheme("WARN\n", "c").unwrap_err(),
+            "c:1: expected LEVEL=STYLE, got 'WARN'"
+        );
+        assert_eq!(parse_scheme("\nLOUD=red", "c").unwrap_err(), "c:2: unknown level 'LOUD'");
+        assert_eq!(parse_scheme("INFO=sparkly", "c").unwrap_err(), "c:1: unknown style 'sparkly'");
+        assert_eq!(parse_scheme("INFO=", "c").unwrap_err(), "c:1: no style for INFO");
+        let vars = |pairs: &'static [(&'static str, &'static str)]| {
+            move |name: &str| pairs.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+        };
+        assert_eq!(
+            config_path(vars(&[("HOME", "/h")])),
+            Some(PathBuf::from("/h/.config/logcolor/config"))
+        );
+        assert_eq!(
+            config_path(vars(&[("XDG_CONFIG_HOME", "/x"), ("HOME", "/h")])),
+            Some(PathBuf::from("/x/logcolor/config"))
+        );
+        assert_eq!(config_path(vars(&[])), None);
+    }
+
+    #[test]
+    fn ansi_input() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        let render = |hl: &Highlighter, input: &str| {
+            let mut out = Vec::new();
+            process_reader(input.as_bytes(), hl, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let red = "\x1b[31m2024-05-01 ERROR\x1b[0m d\u{e9}j\u{e0} vu \x1b[1;31mboom\x1b[m\n";
+        let token = format!("{}{}ERROR{}", BOLD, hl.loaded().styles.color(Level::Error), RESET);
+        // recolored as if it came plain, with no reset of its own cutting ours short
+        assert_eq!(render(&hl, red), format!("2024-05-01 {} d\u{e9}j\u{e0} vu boom\n", token));
+        assert_eq!(render(&hl, "\x1b[31m\u{fc}\x1b[0mWARN\x1b[0m\n").matches(RESET).count(), 1);
+        let mut plain = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        assert_eq!(render(&plain, red), "2024-05-01 ERROR d\u{e9}j\u{e0} vu boom\n");
+
+        hl.keep_ansi = true;
+        plain.keep_ansi = true;
+        assert_eq!(render(&hl, red), red);
+        assert_eq!(render(&plain, red), red);
+        // only lines with codes of their own are left alone
+        assert_eq!(
+            render(&hl, "WARN x\n"),
+            render(&highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto), "WARN x\n")
+        );
+
+        let lib = Highlighter::builder().color(true).build();
+        assert_eq!(lib.highlight_line("\x1b[32mINFO\x1b[0m up"), "\x1b[1m\x1b[32mINFO\x1b[0m up");
+    }
+
+    #[test]
+    fn whole_line() {
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::Basic, InputFormat::Auto);
+        hl.whole_line = true;
+        hl.number = true;
+        let input = "a [ERROR] one \x1b[31mstray\nno level here\n";
+        let mut out = Vec::new();
+        process_reader(input.as_bytes(), &hl, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let loaded = hl.loaded();
+        let (red, number) = (loaded.styles.color(Level::Error), number_prefix(1, &loaded.styles));
+        let token = format!("{}{}[ERROR]{}", BOLD, red, RESET);
+        // the prefix outside the color, the token bold inside it, a reset at the
+        // end, and the input's own escape gone
+        let first = format!("{}{}a {}{} one stray{}\n", number, red, token, red, RESET);
+        assert_eq!(
+            out,
+            format!("{}{}no level here\n", first, number_prefix(2, &hl.loaded().styles))
+        );
+    }
+
+    #[test]
+    fn tail_lines() {
+        let offset = |input: &[u8], lines| tail_offset(&mut io::Cursor::new(input), lines).unwrap();
+        assert_eq!(offset(b"a\nb\nc\nd\n", 2), 4);
+        assert_eq!(offset(b"a\nb\nc\nd", 2), 4);
+        assert_eq!(offset(b"a\nb\n", 5), 0);
+        assert_eq!(offset(b"a\nb\n", 0), 4);
+        assert_eq!(offset(b"", 3), 0);
+        // lines longer than a chunk, and a newline right at a chunk's edge
+        let long =
+            [vec![b'x'; TAIL_CHUNK_BYTES * 2], b"\n".to_vec(), vec![b'y'; 10], b"\n".to_vec()];
+        assert_eq!(offset(&long.concat(), 1), TAIL_CHUNK_BYTES as u64 * 2 + 1);
+        assert_eq!(offset(&long.concat(), 2), 0);
+        let edge = [vec![b'x'; TAIL_CHUNK_BYTES - 1], b"\nz\n".to_vec()].concat();
+        assert_eq!(offset(&edge, 1), TAIL_CHUNK_BYTES as u64);
+
+        let read = |input: &'static [u8], lines, streaming| {
+            let mut out = String::new();
+            TailLines::new(input, Tail { lines, streaming }).read_to_string(&mut out).unwrap();
+            out
+        };
+        assert_eq!(read(b"a\nb\nc\nd", 2, false), "c\nd");
+        assert_eq!(read(b"a\nb\nc\n", 5, false), "a\nb\nc\n");
+        assert_eq!(read(b"a\nb\n", 0, false), "");
+        assert_eq!(read(b"a\nb\nc\n", 1, true), "c\n");
+
+        // a live stream: what came before the pause is cut to its last
+        // lines, what comes after, up to its end, all passes through
+        #[cfg(unix)]
+        {
+            let (mut write, pipe) = std::os::unix::net::UnixStream::pair().unwrap();
+            let (released, go_on) = std::sync::mpsc::channel();
+            let writer = thread::spawn(move || {
+                write.write_all(b"1\n2\n3\n").unwrap();
+                go_on.recv().unwrap();
+                write.write_all(b"4\n5\n").unwrap();
+            });
+            let mut tail = TailLines::new(pipe, Tail { lines: 1, streaming: true });
+            let mut history = [0; 2];
+            tail.read_exact(&mut history).unwrap();
+            assert_eq!(&history, b"3\n");
+            released.send(()).unwrap();
+            let mut out = String::new();
+            tail.read_to_string(&mut out).unwrap();
+            writer.join().unwrap();
+            assert_eq!(out, "4\n5\n");
+        }
+
+        let dir = env::temp_dir().join(format!("logcolor-tail-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        std::fs::write(&path, "INFO a\nWARN b\nERROR c\n").unwrap();
+        let mut hl = highlighter(&DARK_PALETTE, ColorDepth::None, InputFormat::Auto);
+        let tail = Tail { lines: 2, streaming: false };
+        hl.tail = Some(tail);
+        let mut out = Vec::new();
+        let file = File::open(&path).unwrap();
+        process_tail((path.to_str().unwrap(), file), tail, &hl, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "WARN b\nERROR c\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
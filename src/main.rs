@@ -0,0 +1,2780 @@
+// logcolor's command line: the options, their parsing and `--help`, and the
+// exit status. The highlighting itself is the library, src/lib.rs; see there
+// for usage. Built against it:
+//   rustc --crate-type lib --crate-name logcolor src/lib.rs
+//   rustc --crate-name logcolor --extern logcolor=liblogcolor.rlib src/main.rs
+
+use std::env;
+use std::process::exit;
+use std::time::Instant;
+
+use logcolor::{
+    AlertRate, Budget, Cli, ColorDepth, ColorMode, ContinuationMode, DEFAULT_MAX_LINE_BYTES,
+    DEFAULT_OPTS_VAR, DEFAULT_SNAPSHOT_INTERVAL, DEFAULT_SNAPSHOT_LINES, DEFAULT_SPLIT_KEEP,
+    DirScan, Extra, Grep, Inference, InputFormat, InputSource, Level, LevelColumn, LevelPlace,
+    LevelWord, LineRange, Matcher, MiniRegex, OutputFormat, PROGRAM, SortKey, SourceFilter,
+    StatsFormat, ThemeChoice, parse_interval, parse_level_map, parse_since, run, with_commas,
+};
+
+/// one entry of the command line surface. the parser and `--help` are both
+/// driven by `OPTIONS`, so a flag only has to be described once.
+struct OptSpec {
+    long: &'static str,
+    short: Option<char>,
+    /// metavar shown in help; `None` for plain switches.
+    value: Option<&'static str>,
+    /// accepted values for enum-like options, empty if free-form.
+    choices: &'static [&'static str],
+    help: &'static str,
+}
+
+const OPTIONS: &[OptSpec] = &[
+    OptSpec {
+        long: "theme",
+        short: None,
+        value: Some("THEME"),
+        choices: &["auto", "dark", "light", "colorblind", "solarized-dark", "monochrome"],
+        help: "palette to use; auto (default) asks the terminal for its background, \
+               colorblind avoids red/green and marks errors in reverse video, monochrome \
+               uses only bold, dim, italic, underline and reverse video",
+    },
+    OptSpec {
+        long: "print-theme",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "print the escape sequences each level is drawn with, at the color depth in \
+               effect, and exit",
+    },
+    OptSpec {
+        long: "config",
+        short: None,
+        value: Some("PATH"),
+        choices: &[],
+        help: "level colors, one LEVEL=STYLE per line like WARN=bright_yellow or INFO=dim, \
+               over the palette's (default: ~/.config/logcolor/config, if there); read again \
+               on SIGHUP",
+    },
+    OptSpec {
+        long: "print-config",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "print the level colors in effect, in the config file's format, and exit",
+    },
+    OptSpec {
+        long: "color",
+        short: None,
+        value: Some("WHEN"),
+        choices: &["auto", "always", "never"],
+        help: "use colors; auto (default) checks that stdout is a capable terminal",
+    },
+    OptSpec {
+        long: "color-depth",
+        short: None,
+        value: Some("DEPTH"),
+        choices: &["8", "256", "true"],
+        help: "colors to draw with, whatever TERM and COLORTERM say; rgb colors are \
+               brought down to the nearest one there is",
+    },
+    OptSpec {
+        long: "format",
+        short: None,
+        value: Some("FORMAT"),
+        choices: &[
+            "auto", "plain", "rfc5424", "journald", "gelf", "rust", "k8s", "compose", "json",
+            "logfmt",
+        ],
+        help: "input format; auto (default) picks one from the first 200 lines (see --detect), \
+               else recognizes RFC 5424 syslog lines, Rust services and Kubernetes logs line by line",
+    },
+    OptSpec {
+        long: "syslog",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "write a syslog PRI like <134> as [local0.info], the severity in its level's \
+               color; the PRI gives the line its level either way",
+    },
+    OptSpec {
+        long: "journald",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "read `journalctl -o json` or `-o export` output (same as --format journald)",
+    },
+    OptSpec {
+        long: "json",
+        short: None,
+        value: None,
+        choices: &["pretty"],
+        help: "read one JSON object per line, its level only from a key like \"level\" (same as \
+               --format json); --json=pretty also spreads each object over lines in its color",
+    },
+    OptSpec {
+        long: "logfmt",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "read key=value pairs, the level from a key like level=, the msg= in bold (same \
+               as --format logfmt); else only lines with a level key and another pair are",
+    },
+    OptSpec {
+        long: "logfmt-keys",
+        short: None,
+        value: Some("LOOK"),
+        choices: &["dim", "plain"],
+        help: "how the keys of logfmt lines are drawn; plain (default) or dim, so the values \
+               stand out",
+    },
+    OptSpec {
+        long: "verbose",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "also show secondary fields of structured records (GELF full_message, _fields)",
+    },
+    OptSpec {
+        long: "no-default-opts",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "ignore the options in $LOGCOLOR_OPTS",
+    },
+    OptSpec {
+        long: "detect",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "print how well each format fits the start of each input, and the one \
+               --format auto would pick, and exit",
+    },
+    OptSpec {
+        long: "compare",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with two files, OLD and NEW, list the ERROR and FATAL messages (numbers and ids \
+               aside) new in NEW, gone from it, and come up much more or less often, and exit",
+    },
+    OptSpec {
+        long: "compare-threshold",
+        short: None,
+        value: Some("N"),
+        choices: &[],
+        help: "with --compare, how many times more or fewer lines count as a change, like 3x \
+               (the default is 2x)",
+    },
+    OptSpec {
+        long: "demo",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "render a sample log with every level, format and highlight, using the theme and \
+               options given, and exit",
+    },
+    OptSpec {
+        long: "debug-caps",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "print the detected terminal capabilities and exit",
+    },
+    OptSpec {
+        long: "quiet",
+        short: Some('q'),
+        value: None,
+        choices: &[],
+        help: "don't print lines, only the exit status (-qq: no summaries either)",
+    },
+    OptSpec {
+        long: "byte-offsets",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "prefix each line with the byte offset it starts at in its input, like \
+               grep -b",
+    },
+    OptSpec {
+        long: "byte-offset",
+        short: Some('b'),
+        value: None,
+        choices: &[],
+        help: "the same as --byte-offsets",
+    },
+    OptSpec {
+        long: "number",
+        short: Some('n'),
+        value: None,
+        choices: &[],
+        help: "prefix each line with its line number in its input, like grep -n; every \
+               line counts, shown or not, so the numbers go with sed -n 'Np'",
+    },
+    OptSpec {
+        long: "line-number",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "the same as --number",
+    },
+    OptSpec {
+        long: "lines",
+        short: None,
+        value: Some("START:END"),
+        choices: &[],
+        help: "show only lines START to END of each input, counted from 1 (either may be left \
+               out), with a few dimmed lines on either side; the lines before START are skipped \
+               unread and reading stops after END",
+    },
+    OptSpec {
+        long: "gutter",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "mark ERROR and WARN lines with a colored \u{258c} in the left margin (before \
+               --byte-offsets) and leave the lines themselves uncolored, to copy them out as is",
+    },
+    OptSpec {
+        long: "whole-line",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "draw each leveled line all in its level's color, the level token still bold; \
+               lines without a level and prefixes like --number stay uncolored",
+    },
+    OptSpec {
+        long: "gutter-glyph",
+        short: None,
+        value: Some("CHAR"),
+        choices: &[],
+        help: "with --gutter, the mark to draw instead of \u{258c}",
+    },
+    OptSpec {
+        long: "max-line-bytes",
+        short: None,
+        value: Some("SIZE"),
+        choices: &[],
+        help: "cut lines longer than this into pieces instead of buffering them whole \
+               (default 1M; K, M and G suffixes allowed)",
+    },
+    OptSpec {
+        long: "stats",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "print how many lines there were of each level to stderr at the end, \
+               or on Ctrl+C while following a stream",
+    },
+    OptSpec {
+        long: "stats-only",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "print only --stats (implied), no lines",
+    },
+    OptSpec {
+        long: "stats-format",
+        short: None,
+        value: Some("FORMAT"),
+        choices: &["human", "json"],
+        help: "how to write --stats (implied); json is one object with total `lines`, \
+               `levels` (fatal, error, warn, info, http, debug, trace, silly, unmatched), \
+               `first_timestamp`, `last_timestamp` (RFC 3339 or null), `elapsed_seconds`, \
+               and for several inputs `files`, each with its `path` and the same counts",
+    },
+    OptSpec {
+        long: "stats-output",
+        short: None,
+        value: Some("PATH"),
+        choices: &[],
+        help: "write --stats (implied) to this file instead of stderr",
+    },
+    OptSpec {
+        long: "summary-only",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "print only a summary line per input, with its line and level counts",
+    },
+    OptSpec {
+        long: "output",
+        short: Some('o'),
+        value: Some("PATH"),
+        choices: &[],
+        help: "also write the output to PATH, without colors unless --output-color, \
+               emptying it first unless --append; failing to write it is an error",
+    },
+    OptSpec {
+        long: "output-only",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --output, write the lines only to the file, not to stdout",
+    },
+    OptSpec {
+        long: "output-color",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --output, keep the colors in the file, for less -R (unless --color \
+               never), whether or not stdout gets them",
+    },
+    OptSpec {
+        long: "append",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --output, add to the end of the file instead of emptying it",
+    },
+    OptSpec {
+        long: "mirror",
+        short: None,
+        value: Some("PATH"),
+        choices: &[],
+        help: "also write the output, colors and all, to PATH: another terminal like \
+               /dev/pts/7, a fifo (which waits for its reader) or a file",
+    },
+    OptSpec {
+        long: "mirror-fd",
+        short: None,
+        value: Some("FD"),
+        choices: &[],
+        help: "also write the output to the already open file descriptor FD",
+    },
+    OptSpec {
+        long: "unbuffered",
+        short: Some('u'),
+        value: None,
+        choices: &[],
+        help: "flush after every line even when stdout is not a terminal \
+               (otherwise SIGUSR1 flushes on demand)",
+    },
+    OptSpec {
+        long: "jobs",
+        short: Some('j'),
+        value: Some("N"),
+        choices: &[],
+        help: "read and color up to N files at once, still printing them in order",
+    },
+    OptSpec {
+        long: "watch-glob",
+        short: None,
+        value: Some("PATTERN"),
+        choices: &[],
+        help: "follow every file matching PATTERN (e.g. 'app-*.log') in the directory given, \
+               or PATTERN's own, starting on new ones as they appear",
+    },
+    OptSpec {
+        long: "forget-missing",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --watch-glob, stop following files once they are removed",
+    },
+    OptSpec {
+        long: "tail",
+        short: None,
+        value: Some("N"),
+        choices: &[],
+        help: "show only the last N lines of each input: of a file by reading back from its end, \
+               of stdin by keeping the last N until it ends, or while piped in until it pauses, \
+               then the rest as it comes; with --follow like tail -n N -f",
+    },
+    OptSpec {
+        long: "follow",
+        short: Some('f'),
+        value: None,
+        choices: &[],
+        help: "keep reading the file as it grows, like tail -f, going on to the new one when it \
+               is rotated and back to the top when it is truncated",
+    },
+    OptSpec {
+        long: "retry",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "wait for the input file to appear instead of failing",
+    },
+    OptSpec {
+        long: "follow-retry",
+        short: Some('F'),
+        value: None,
+        choices: &[],
+        help: "the same as --follow --retry, like tail -F",
+    },
+    OptSpec {
+        long: "only-new",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "show only what was added to each input file since the last --only-new run on \
+               it, keeping the position under $XDG_STATE_HOME/logcolor",
+    },
+    OptSpec {
+        long: "state-ttl",
+        short: None,
+        value: Some("TIME"),
+        choices: &[],
+        help: "with --only-new, read a file whole again once its saved position is older than \
+               TIME (12h, 7d)",
+    },
+    OptSpec {
+        long: "reset-state",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --only-new, forget the saved positions and read the files whole",
+    },
+    OptSpec {
+        long: "wrap",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "wrap long lines at the terminal width, indented under the message",
+    },
+    OptSpec {
+        long: "track",
+        short: None,
+        value: Some("KEY"),
+        choices: &[],
+        help: "give each value of KEY (key=value or key: value) its own color; repeatable",
+    },
+    OptSpec {
+        long: "track-filter",
+        short: None,
+        value: Some("KEY=VALUE"),
+        choices: &[],
+        help: "like --track KEY, and bold the lines where it is VALUE while dimming the rest",
+    },
+    OptSpec {
+        long: "json-fields",
+        short: None,
+        value: Some("LIST"),
+        choices: &[],
+        help: "show only these comma-separated fields of JSON lines (dot paths allowed)",
+    },
+    OptSpec {
+        long: "json-rest",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --json-fields, append the other fields dimmed",
+    },
+    OptSpec {
+        long: "glob",
+        short: None,
+        value: Some("PATTERN"),
+        choices: &[],
+        help: "files to read from directory arguments (default: *.log and its rotations)",
+    },
+    OptSpec {
+        long: "recursive",
+        short: Some('R'),
+        value: None,
+        choices: &[],
+        help: "look for files in subdirectories of directory arguments too",
+    },
+    OptSpec {
+        long: "sort",
+        short: None,
+        value: Some("KEY"),
+        choices: &["name", "mtime"],
+        help: "order of the files found in a directory",
+    },
+    OptSpec {
+        long: "service",
+        short: None,
+        value: Some("LIST"),
+        choices: &[],
+        help: "with --format compose, only show the lines of these comma-separated services \
+               (api, or one container like api-2)",
+    },
+    OptSpec {
+        long: "level-column",
+        short: None,
+        value: Some("N[:MAP]"),
+        choices: &[],
+        help: "take the level from column N (or columns N-M, counted from 1) instead of \
+               looking for it, read with MAP like E=ERROR,W=WARN,I=INFO (by default the \
+               letters E W I D F T and level names); lines too short to reach it have none",
+    },
+    OptSpec {
+        long: "level-column-chars",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --level-column, count characters instead of bytes",
+    },
+    OptSpec {
+        long: "level-field",
+        short: None,
+        value: Some("N"),
+        choices: &[],
+        help: "take the level from field N (counted from 1) instead of looking for it, the \
+               fields split at --level-delim or else at blanks; read like --level-column's",
+    },
+    OptSpec {
+        long: "level-delim",
+        short: None,
+        value: Some("TEXT"),
+        choices: &[],
+        help: "with --level-field, what separates the fields, like '|'",
+    },
+    OptSpec {
+        long: "level-pattern",
+        short: None,
+        value: Some("TEMPLATE"),
+        choices: &[],
+        help: "take the level from where {level} is in TEMPLATE, like '| {level} |', instead of \
+               looking for it: the first text between what comes before and after it that is a \
+               level; lines where it isn't found have none",
+    },
+    OptSpec {
+        long: "level-map",
+        short: None,
+        value: Some("MAP"),
+        choices: &[],
+        help: "what the codes --level-column, --level-field or --level-pattern find mean, like \
+               1=error,2=warn,3=info,4=debug",
+    },
+    OptSpec {
+        long: "strict-prefix",
+        short: None,
+        value: Some("N"),
+        choices: &[],
+        help: "look for level words only within the first N characters of each line, so one in \
+               a message doesn't count",
+    },
+    OptSpec {
+        long: "since",
+        short: None,
+        value: Some("WHEN"),
+        choices: &[],
+        help: "drop lines stamped before WHEN: a time ago like 10m, 2h or 1d, or a timestamp \
+               like 2024-05-01T12:00:00. for files, a time ago counts back from when logcolor \
+               started; for a live stdin or --watch-glob it counts back from each line's \
+               arrival, so the window keeps sliding. lines without a timestamp go with the \
+               line before",
+    },
+    OptSpec {
+        long: "since-boot",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --since, read kernel-style [12345.678] timestamps as seconds since boot \
+               (Linux, from /proc/uptime)",
+    },
+    OptSpec {
+        long: "tz",
+        short: None,
+        value: Some("ZONE"),
+        choices: &[],
+        help: "show timestamps that say their zone (a Z or an offset) in ZONE instead, an IANA \
+               name like US/Eastern or UTC, followed by its abbreviation",
+    },
+    OptSpec {
+        long: "local",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "the same as --tz for the local time zone ($TZ, or else /etc/localtime)",
+    },
+    OptSpec {
+        long: "max-level",
+        short: None,
+        value: Some("LEVEL"),
+        choices: &[],
+        help: "drop lines above LEVEL (e.g. debug, to look at only the chatter); lines \
+               without a level are kept",
+    },
+    OptSpec {
+        long: "min-level",
+        short: None,
+        value: Some("LEVEL"),
+        choices: &[],
+        help: "drop lines below LEVEL (e.g. warn, to leave out info, debug and trace); lines \
+               without a level are kept",
+    },
+    OptSpec {
+        long: "drop-unknown",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "drop lines without a level, which --min-level and --max-level keep",
+    },
+    OptSpec {
+        long: "fail-on",
+        short: None,
+        value: Some("LEVEL"),
+        choices: &[],
+        help: "once all input is read, exit with status 2 if any line was at LEVEL or above \
+               (e.g. error), saying how many on stderr; read and write failures keep status 1",
+    },
+    OptSpec {
+        long: "module",
+        short: None,
+        value: Some("GLOBS"),
+        choices: &[],
+        help: "only show lines from these comma-separated modules or classes (net::*, \
+               c.x.y.*); lines without one are kept",
+    },
+    OptSpec {
+        long: "module-exclude",
+        short: None,
+        value: Some("GLOBS"),
+        choices: &[],
+        help: "hide lines from these comma-separated modules or classes (metrics*)",
+    },
+    OptSpec {
+        long: "file",
+        short: None,
+        value: Some("GLOBS"),
+        choices: &[],
+        help: "only show lines logged from these comma-separated source files (conn.go, \
+               net/*.go), as glog and zap's caller name them",
+    },
+    OptSpec {
+        long: "module-strict",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --module, --module-exclude or --file, also drop lines whose source \
+               can't be told",
+    },
+    OptSpec {
+        long: "columns",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "line up timestamp, level, source and message in columns, sized to the terminal",
+    },
+    OptSpec {
+        long: "source-width",
+        short: None,
+        value: Some("N"),
+        choices: &[],
+        help: "with --columns, the widest the source column gets before it is cut short \
+               (default 16, 0 to leave it out)",
+    },
+    OptSpec {
+        long: "elide-below",
+        short: None,
+        value: Some("LEVEL"),
+        choices: &[],
+        help: "leave out lines under LEVEL (e.g. info), with a dimmed line counting each run \
+               of them by level",
+    },
+    OptSpec {
+        long: "marks",
+        short: None,
+        value: Some("LEVEL"),
+        choices: &[],
+        help: "set a terminal mark on each line at LEVEL or above, to jump between them \
+               (iTerm2, WezTerm, VS Code and Ghostty, when writing to the terminal)",
+    },
+    OptSpec {
+        long: "throttle",
+        short: None,
+        value: Some("N"),
+        choices: &[],
+        help: "print at most N lines of each level a second, counting the rest in a dimmed \
+               notice (errors get 4 times as many unless --throttle-errors says otherwise)",
+    },
+    OptSpec {
+        long: "throttle-errors",
+        short: None,
+        value: Some("N"),
+        choices: &[],
+        help: "with --throttle, the cap for ERROR and FATAL lines",
+    },
+    OptSpec {
+        long: "alert-rate",
+        short: None,
+        value: Some("N/TIME"),
+        choices: &[],
+        help: "when N or more ERROR and FATAL lines come within TIME, like 50/10s, print a red \
+               banner and show errors in bold until the rate falls under half of N",
+    },
+    OptSpec {
+        long: "alert-bell",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --alert-rate, ring the terminal bell when an alert starts",
+    },
+    OptSpec {
+        long: "exec-on-alert",
+        short: None,
+        value: Some("CMD"),
+        choices: &[],
+        help: "with --alert-rate, run CMD with sh -c once each time an alert starts, with \
+               $LOGCOLOR_ALERT_COUNT and $LOGCOLOR_ALERT_RATE set",
+    },
+    OptSpec {
+        long: "snapshot",
+        short: None,
+        value: Some("PATH"),
+        choices: &[],
+        help: "keep the latest lines at --snapshot-level or above, uncolored, in PATH, \
+               replaced whole every --snapshot-interval and at the end",
+    },
+    OptSpec {
+        long: "snapshot-lines",
+        short: None,
+        value: Some("N"),
+        choices: &[],
+        help: "how many lines --snapshot keeps (default 50)",
+    },
+    OptSpec {
+        long: "snapshot-level",
+        short: None,
+        value: Some("LEVEL"),
+        choices: &[],
+        help: "the lowest level --snapshot keeps (default warn)",
+    },
+    OptSpec {
+        long: "snapshot-interval",
+        short: None,
+        value: Some("TIME"),
+        choices: &[],
+        help: "how often to rewrite the --snapshot file, like 5s (the default), 500ms or 1m",
+    },
+    OptSpec {
+        long: "metrics-listen",
+        short: None,
+        value: Some("ADDR"),
+        choices: &[],
+        help: "answer GET /metrics on ADDR, like 127.0.0.1:9109, with Prometheus counters of \
+               the lines (by level) and bytes read from each input and its last timestamp",
+    },
+    OptSpec {
+        long: "audit",
+        short: None,
+        value: Some("PATH"),
+        choices: &[],
+        help: "append a JSON lines record of the session to PATH: the arguments and environment \
+               in effect, what each input read, and how many lines each filter left out",
+    },
+    OptSpec {
+        long: "split-dir",
+        short: None,
+        value: Some("DIR"),
+        choices: &[],
+        help: "also copy each line, uncolored, into DIR/errors.log, warnings.log, info.log \
+               or debug.log by its level",
+    },
+    OptSpec {
+        long: "split-max-size",
+        short: None,
+        value: Some("SIZE"),
+        choices: &[],
+        help: "with --split-dir, rotate a file to FILE.1, FILE.2, ... once it would grow past \
+               SIZE (K, M and G suffixes allowed)",
+    },
+    OptSpec {
+        long: "split-keep",
+        short: None,
+        value: Some("N"),
+        choices: &[],
+        help: "with --split-max-size, how many rotated files of each to keep (default 5)",
+    },
+    OptSpec {
+        long: "dedup-window",
+        short: None,
+        value: Some("N"),
+        choices: &[],
+        help: "hold back lines repeating one of the last N distinct messages of their level \
+               (numbers and timestamps aside), with a dimmed count of them now and then",
+    },
+    OptSpec {
+        long: "budget",
+        short: None,
+        value: Some("LEVEL=N"),
+        choices: &[],
+        help: "print at most N lines of LEVEL (repeatable): of a file its first and last N/2, \
+               of a live stream the first N; counts what the other filters leave, before --grep",
+    },
+    OptSpec {
+        long: "dedup-errors",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --dedup-window, hold back repeated ERROR and FATAL lines too",
+    },
+    OptSpec {
+        long: "squeeze",
+        short: Some('s'),
+        value: None,
+        choices: &[],
+        help: "print a line the same as the one before (timestamps aside) only once, \
+               with a dimmed count of its repeats when they end",
+    },
+    OptSpec {
+        long: "squeeze-window",
+        short: None,
+        value: Some("K"),
+        choices: &[],
+        help: "with --squeeze, a line repeating any of the last K distinct lines counts too, \
+               so lines taking turns fold as well",
+    },
+    OptSpec {
+        long: "hex",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "color hex dumps of 8 bytes or more (0x48 0x54 ..., 48 54 ... or 485454...) \
+               four bytes at a time",
+    },
+    OptSpec {
+        long: "hex-ascii",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --hex, follow each dump with its printable characters, dimmed",
+    },
+    OptSpec {
+        long: "extras",
+        short: None,
+        value: None,
+        choices: &["url", "uuid", "ip", "duration", "hex"],
+        help: "also set off URLs, UUIDs, IPv4 and IPv6 addresses, durations like 153ms or 2.5s \
+               and long hex ids, underlined or in italic, never bold; --extras=ip,url for some",
+    },
+    OptSpec {
+        long: "no-version-ips",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --extras, don't take a dotted quad named as a version (version 1.2.3.4, \
+               app_version=1.2.3.4) for an IPv4 address",
+    },
+    OptSpec {
+        long: "split-interleaved",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "split lines that carry several records, spliced by processes writing one file \
+               unlocked, at each record's timestamp, marking the split-off ones with a dim \u{269f}",
+    },
+    OptSpec {
+        long: "sort-buffer",
+        short: None,
+        value: Some("TIME"),
+        choices: &[],
+        help: "hold lines back up to TIME (2s) by their timestamps to put ones that came out of \
+               order, as from merged streams, back in order; later stragglers are marked late",
+    },
+    OptSpec {
+        long: "highlight-new",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "mark the first line of each message (numbers and timestamps aside) with NEW and \
+               in bold, and dim the repeats",
+    },
+    OptSpec {
+        long: "baseline",
+        short: None,
+        value: Some("FILE"),
+        choices: &[],
+        help: "with --highlight-new, count the messages in FILE (say, yesterday's log) as \
+               seen already",
+    },
+    OptSpec {
+        long: "no-tail-headers",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "treat tail's '==> file <==' lines like any other, instead of as switching \
+               between files",
+    },
+    OptSpec {
+        long: "grep",
+        short: None,
+        value: Some("PATTERN"),
+        choices: &[],
+        help: "only show lines containing PATTERN (a substring, or /regex/ and /regex/i \
+               as in --rules), with the match emphasized",
+    },
+    OptSpec {
+        long: "grep-regex",
+        short: None,
+        value: Some("REGEX"),
+        choices: &[],
+        help: "like --grep, with REGEX always a regex (the subset --rules takes), no slashes \
+               needed",
+    },
+    OptSpec {
+        long: "ignore-case",
+        short: Some('i'),
+        value: None,
+        choices: &[],
+        help: "with --grep or --grep-regex, match regardless of ASCII case",
+    },
+    OptSpec {
+        long: "after",
+        short: Some('A'),
+        value: Some("N"),
+        choices: &[],
+        help: "with --grep, also show N dimmed lines after each match",
+    },
+    OptSpec {
+        long: "before",
+        short: Some('B'),
+        value: Some("N"),
+        choices: &[],
+        help: "with --grep, also show N dimmed lines before each match",
+    },
+    OptSpec {
+        long: "context",
+        short: Some('C'),
+        value: Some("N"),
+        choices: &[],
+        help: "with --grep, N lines both before and after",
+    },
+    OptSpec {
+        long: "infer",
+        short: None,
+        value: Some("PHRASE=LEVEL"),
+        choices: &[],
+        help: "when a line has no level, take PHRASE (whole words, any case) to mean LEVEL, \
+               ahead of the built-in phrases like \"timed out\"; repeatable",
+    },
+    OptSpec {
+        long: "level-word",
+        short: None,
+        value: Some("WORD=LEVEL"),
+        choices: &[],
+        help: "also read WORD (standing alone, any case) as a LEVEL token, as in CRIT=error; \
+               a built-in word given this way takes the new level; repeatable",
+    },
+    OptSpec {
+        long: "loose",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "find ERROR, WARN, INFO and the like inside other words too, as in PREFERRED, \
+               the way older versions did",
+    },
+    OptSpec {
+        long: "no-infer",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "don't guess levels from phrases, only use level tokens",
+    },
+    OptSpec {
+        long: "rules",
+        short: None,
+        value: Some("PATH"),
+        choices: &[],
+        help: "extra highlighting rules, one MATCHER<TAB>STYLE<TAB>ACTION per line; read again on SIGHUP",
+    },
+    OptSpec {
+        long: "check-rules",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "validate the --rules file and exit",
+    },
+    OptSpec {
+        long: "multiline-json",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "reassemble JSON records pretty-printed over several lines into one",
+    },
+    OptSpec {
+        long: "expand-json",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "pretty-print reassembled JSON records with colors (implies --multiline-json)",
+    },
+    OptSpec {
+        long: "unescape",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "turn \\n, \\t, \\r and \\\" in JSON, journald and GELF messages into the \
+               characters, the lines after the first indented in the record's color",
+    },
+    OptSpec {
+        long: "color-all-occurrences",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "color every level word in a line, not only the one that decides its level",
+    },
+    OptSpec {
+        long: "explain",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "follow each line with the level words found in it, their scores, and which won",
+    },
+    OptSpec {
+        long: "output-format",
+        short: None,
+        value: Some("FORMAT"),
+        choices: &["ansi", "html"],
+        help: "how colors are written; html is <span> tags (level tokens in classes like \
+               lvl-error) with the text escaped",
+    },
+    OptSpec {
+        long: "keep-ansi",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "write lines that already have escape codes as they came, instead of taking \
+               the codes out and coloring them afresh",
+    },
+    OptSpec {
+        long: "html-standalone",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "with --output-format html (implied), a whole page: the lines in a <pre>, with \
+               a stylesheet for the level classes",
+    },
+    OptSpec {
+        long: "crlf",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "end every line written to stdout with \\r\\n",
+    },
+    OptSpec {
+        long: "preserve-eol",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "leave an input's last line without a newline if it came without one, in \
+               every output",
+    },
+    OptSpec {
+        long: "continuation",
+        short: None,
+        value: Some("MODE"),
+        choices: &["indent", "deeper", "any", "off"],
+        help: "which lines without a level take the color of the event line above: any \
+               indented line, only lines indented deeper than it, every line, or none \
+               (default: indent, off for k8s and compose); stack trace lines like `at ...` \
+               and `Caused by:` always do unless it's off",
+    },
+    OptSpec {
+        long: "continuation-max",
+        short: None,
+        value: Some("N"),
+        choices: &[],
+        help: "stop coloring continuation lines after N in a row (default: 200)",
+    },
+    OptSpec {
+        long: "show-invisibles",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "draw zero-width spaces, BOMs, NBSPs, soft hyphens and bidi controls as \
+               colored placeholders like \u{27e8}ZWSP\u{27e9}",
+    },
+    OptSpec {
+        long: "allow-bidi",
+        short: None,
+        value: None,
+        choices: &[],
+        help: "pass bidi override, embedding and isolate characters through instead of \
+               drawing them as placeholders",
+    },
+    OptSpec {
+        long: "retry-timeout",
+        short: None,
+        value: Some("TIME"),
+        choices: &[],
+        help: "give up waiting for the input file after TIME, like 30s or 500ms (implies --retry)",
+    },
+    OptSpec {
+        long: "completions",
+        short: None,
+        value: Some("SHELL"),
+        choices: &["bash", "zsh", "fish"],
+        help: "print a tab completion script for SHELL and exit",
+    },
+    OptSpec { long: "help", short: Some('h'), value: None, choices: &[], help: "print this help" },
+];
+
+fn find_option(arg: &str) -> Option<&'static OptSpec> {
+    if let Some(long) = arg.strip_prefix("--") {
+        OPTIONS.iter().find(|o| o.long == long)
+    } else {
+        let mut chars = arg.strip_prefix('-')?.chars();
+        let short = chars.next()?;
+        if chars.next().is_some() {
+            return None;
+        }
+        OPTIONS.iter().find(|o| o.short == Some(short))
+    }
+}
+
+fn parse_args(args: &[String]) -> Result<Cli, String> {
+    let mut cli = Cli {
+        theme: ThemeChoice::Auto,
+        config: None,
+        print_config: false,
+        print_theme: false,
+        color: ColorMode::Auto,
+        color_depth: None,
+        format: InputFormat::Auto,
+        verbose: false,
+        inputs: Vec::new(),
+        scan: DirScan { glob: "*.log".to_string(), recursive: false, sort: SortKey::Name },
+        debug_caps: false,
+        demo: false,
+        detect: false,
+        compare: false,
+        compare_threshold: None,
+        quiet: 0,
+        summary_only: false,
+        stats: false,
+        stats_only: false,
+        stats_format: StatsFormat::Human,
+        stats_output: None,
+        byte_offsets: false,
+        number: false,
+        lines: None,
+        max_line_bytes: DEFAULT_MAX_LINE_BYTES,
+        jobs: 1,
+        output: None,
+        output_only: false,
+        output_color: false,
+        append: false,
+        mirror: None,
+        mirror_fd: None,
+        unbuffered: false,
+        tail: None,
+        follow: false,
+        retry: false,
+        retry_timeout: None,
+        only_new: false,
+        state_ttl: None,
+        reset_state: false,
+        wrap: false,
+        track: Vec::new(),
+        track_filter: None,
+        json_fields: Vec::new(),
+        json_rest: false,
+        grep: None,
+        services: None,
+        columns: false,
+        source_width: None,
+        elide_below: None,
+        max_level: None,
+        min_level: None,
+        fail_on: None,
+        drop_unknown: false,
+        sources: SourceFilter::default(),
+        since: None,
+        since_boot: false,
+        tz: None,
+        local: false,
+        level_column: None,
+        level_column_chars: false,
+        level_field: None,
+        level_delim: None,
+        level_pattern: None,
+        level_map: None,
+        strict_prefix: None,
+        marks: None,
+        throttle: None,
+        throttle_errors: None,
+        alert_rate: None,
+        alert_bell: false,
+        exec_on_alert: None,
+        dedup_window: None,
+        budget: None,
+        dedup_errors: false,
+        squeeze: false,
+        squeeze_window: None,
+        highlight_new: false,
+        baseline: None,
+        hex: false,
+        hex_ascii: false,
+        extras: None,
+        no_version_ips: false,
+        split_interleaved: false,
+        sort_buffer: None,
+        gutter: false,
+        whole_line: false,
+        gutter_glyph: None,
+        no_tail_headers: false,
+        snapshot: None,
+        snapshot_lines: DEFAULT_SNAPSHOT_LINES,
+        snapshot_level: Level::Warn,
+        snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+        metrics_listen: None,
+        audit: None,
+        split_dir: None,
+        split_max_size: None,
+        split_keep: DEFAULT_SPLIT_KEEP,
+        watch_glob: None,
+        forget_missing: false,
+        infer: Vec::new(),
+        no_infer: false,
+        level_words: Vec::new(),
+        loose: false,
+        rules: None,
+        check_rules: false,
+        completions: None,
+        multiline_json: false,
+        expand_json: false,
+        json_pretty: false,
+        dim_logfmt_keys: false,
+        unescape: false,
+        show_invisibles: false,
+        allow_bidi: false,
+        continuation: None,
+        continuation_max: 200,
+        all_occurrences: false,
+        explain: false,
+        crlf: false,
+        preserve_eol: false,
+        output_format: OutputFormat::Ansi,
+        html_standalone: false,
+        keep_ansi: false,
+        syslog_names: false,
+        help: false,
+    };
+    let (mut before, mut after) = (None, None);
+    // `--grep` is built once `-i` is known, wherever it came
+    let mut grep: Option<(&str, String)> = None;
+    let mut ignore_case = false;
+    let mut keep_given = false;
+    let mut snapshot_tuned = false;
+    let mut positional = Vec::new();
+    let mut iter = args.iter();
+    let mut only_positional = false;
+    // the rest of a `-qq` style bundle of short flags
+    let mut bundled: Vec<String> = Vec::new();
+
+    while let Some(arg) = bundled.pop().or_else(|| iter.next().cloned()) {
+        if only_positional || arg == "-" || !arg.starts_with('-') {
+            positional.push(arg.clone());
+            continue;
+        }
+        if arg == "--" {
+            only_positional = true;
+            continue;
+        }
+        if !arg.starts_with("--") && arg.chars().count() > 2 {
+            // the first that takes a value gets the rest of the bundle, as in `-C3`
+            let mut shorts = Vec::new();
+            for (i, c) in arg.char_indices().skip(1) {
+                let rest = &arg[i + c.len_utf8()..];
+                match find_option(&format!("-{}", c)) {
+                    Some(spec) if spec.value.is_some() && !rest.is_empty() => {
+                        shorts.push(format!("--{}={}", spec.long, rest));
+                        break;
+                    }
+                    _ => shorts.push(format!("-{}", c)),
+                }
+            }
+            bundled.extend(shorts.into_iter().rev());
+            continue;
+        }
+        let (name, inline_value) = match arg.split_once('=') {
+            Some((name, value)) if arg.starts_with("--") => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let spec = find_option(name).ok_or_else(|| format!("unknown option '{}'", name))?;
+        let value = match (spec.value, inline_value) {
+            (None, None) => None,
+            // a flag with choices may be given one inline, like `--json=pretty`,
+            // or several, like `--extras=ip,url`
+            (None, Some(v)) if !spec.choices.is_empty() => Some(v),
+            (None, Some(_)) => return Err(format!("option '--{}' takes no value", spec.long)),
+            (Some(_), Some(v)) => Some(v),
+            (Some(_), None) => match iter.next() {
+                Some(v) => Some(v.clone()),
+                None => return Err(format!("option '--{}' requires a value", spec.long)),
+            },
+        };
+        if let Some(v) = &value {
+            let listed = spec.value.is_none() && v.split(',').all(|v| spec.choices.contains(&v));
+            if !spec.choices.is_empty() && !spec.choices.contains(&v.as_str()) && !listed {
+                return Err(format!(
+                    "invalid value '{}' for '--{}' (expected one of: {})",
+                    v,
+                    spec.long,
+                    spec.choices.join(", ")
+                ));
+            }
+        }
+        match spec.long {
+            "theme" => cli.theme = value.as_deref().and_then(ThemeChoice::parse).unwrap(),
+            "config" => cli.config = value,
+            "print-config" => cli.print_config = true,
+            "print-theme" => cli.print_theme = true,
+            "color" => cli.color = value.as_deref().and_then(ColorMode::parse).unwrap(),
+            "color-depth" => {
+                cli.color_depth = match value.as_deref() {
+                    Some("8") => Some(ColorDepth::Basic),
+                    Some("256") => Some(ColorDepth::Ansi256),
+                    _ => Some(ColorDepth::TrueColor),
+                }
+            }
+            "format" => cli.format = value.as_deref().and_then(InputFormat::parse).unwrap(),
+            "journald" => cli.format = InputFormat::Journald,
+            "json" => (cli.format, cli.json_pretty) = (InputFormat::Json, value.is_some()),
+            "logfmt" => cli.format = InputFormat::Logfmt,
+            "logfmt-keys" => cli.dim_logfmt_keys = value.as_deref() == Some("dim"),
+            "verbose" => cli.verbose = true,
+            "debug-caps" => cli.debug_caps = true,
+            "demo" => cli.demo = true,
+            "detect" => cli.detect = true,
+            "compare" => cli.compare = true,
+            "compare-threshold" => {
+                let value = value.unwrap();
+                let number = value.strip_suffix('x').unwrap_or(&value);
+                let threshold = number.parse().ok().filter(|&n: &f64| n > 1.0 && n.is_finite());
+                cli.compare_threshold = Some(threshold.ok_or_else(|| {
+                    format!(
+                        "invalid value '{}' for '--compare-threshold' (expected more than 1, like 2x)",
+                        value
+                    )
+                })?);
+            }
+            // decided in `default_opts`, before parsing
+            "no-default-opts" => {}
+            "quiet" => cli.quiet = cli.quiet.saturating_add(1),
+            "summary-only" => cli.summary_only = true,
+            "stats" => cli.stats = true,
+            "stats-only" => (cli.stats, cli.stats_only) = (true, true),
+            "stats-format" => {
+                cli.stats = true;
+                cli.stats_format = value.as_deref().and_then(StatsFormat::parse).unwrap();
+            }
+            "stats-output" => (cli.stats, cli.stats_output) = (true, value),
+            "byte-offsets" | "byte-offset" => cli.byte_offsets = true,
+            "number" | "line-number" => cli.number = true,
+            "lines" => cli.lines = Some(LineRange::parse(&value.unwrap())?),
+            "max-line-bytes" => cli.max_line_bytes = parse_size(spec.long, &value.unwrap())?,
+            "no-tail-headers" => cli.no_tail_headers = true,
+            "snapshot" => cli.snapshot = value,
+            "snapshot-lines" => {
+                let value = value.unwrap();
+                cli.snapshot_lines = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    format!(
+                        "invalid value '{}' for '--snapshot-lines' (expected a positive number)",
+                        value
+                    )
+                })?;
+                snapshot_tuned = true;
+            }
+            "snapshot-level" => {
+                cli.snapshot_level = parse_level_value(spec.long, &value.unwrap())?;
+                snapshot_tuned = true;
+            }
+            "snapshot-interval" => {
+                cli.snapshot_interval = parse_interval(spec.long, &value.unwrap())?;
+                snapshot_tuned = true;
+            }
+            "metrics-listen" => cli.metrics_listen = value,
+            "audit" => cli.audit = value,
+            "split-dir" => cli.split_dir = value,
+            "split-max-size" => cli.split_max_size = Some(parse_size(spec.long, &value.unwrap())?),
+            "split-keep" => {
+                let value = value.unwrap();
+                cli.split_keep = value.parse().map_err(|_| {
+                    format!("invalid value '{}' for '--split-keep' (expected a file count)", value)
+                })?;
+                keep_given = true;
+            }
+            "tail" => {
+                let value = value.unwrap();
+                cli.tail = Some(value.parse().map_err(|_| {
+                    format!("invalid value '{}' for '--tail' (expected a number of lines)", value)
+                })?);
+            }
+            "follow" => cli.follow = true,
+            "retry" => cli.retry = true,
+            "follow-retry" => (cli.follow, cli.retry) = (true, true),
+            "only-new" => cli.only_new = true,
+            "state-ttl" => cli.state_ttl = Some(parse_interval(spec.long, &value.unwrap())?),
+            "reset-state" => cli.reset_state = true,
+            "unbuffered" => cli.unbuffered = true,
+            "output" => cli.output = value,
+            "output-only" => cli.output_only = true,
+            "output-color" => cli.output_color = true,
+            "append" => cli.append = true,
+            "mirror" => cli.mirror = value,
+            "mirror-fd" => {
+                let value = value.unwrap();
+                cli.mirror_fd = Some(value.parse().ok().filter(|&fd| fd > 2).ok_or_else(|| {
+                    format!(
+                        "invalid value '{}' for '--mirror-fd' (expected a descriptor above 2)",
+                        value
+                    )
+                })?);
+            }
+            "dedup-window" => {
+                let value = value.unwrap();
+                let size = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    format!(
+                        "invalid value '{}' for '--dedup-window' (expected a positive number)",
+                        value
+                    )
+                })?;
+                cli.dedup_window = Some(size);
+            }
+            "dedup-errors" => cli.dedup_errors = true,
+            "squeeze" => cli.squeeze = true,
+            "squeeze-window" => {
+                let value = value.unwrap();
+                let size = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    format!(
+                        "invalid value '{}' for '--squeeze-window' (expected a positive number)",
+                        value
+                    )
+                })?;
+                cli.squeeze_window = Some(size);
+            }
+            "budget" => {
+                let value = value.unwrap();
+                let invalid = || {
+                    format!(
+                        "invalid value '{}' for '--budget' (expected LEVEL=N, like info=500)",
+                        value
+                    )
+                };
+                let (level, lines) = value.split_once('=').ok_or_else(invalid)?;
+                let level = parse_level_value(spec.long, level)?;
+                let lines = lines.parse().map_err(|_| invalid())?;
+                cli.budget.get_or_insert_with(Budget::default).limits[level as usize] = Some(lines);
+            }
+            "highlight-new" => cli.highlight_new = true,
+            "hex" => cli.hex = true,
+            "hex-ascii" => cli.hex_ascii = true,
+            "extras" => {
+                let picked: Vec<Extra> = match value.as_deref() {
+                    Some(list) => list.split(',').filter_map(Extra::parse).collect(),
+                    None => Extra::ALL.to_vec(),
+                };
+                cli.extras = Some(Extra::ALL.into_iter().filter(|e| picked.contains(e)).collect());
+            }
+            "no-version-ips" => cli.no_version_ips = true,
+            "split-interleaved" => cli.split_interleaved = true,
+            "sort-buffer" => cli.sort_buffer = Some(parse_interval(spec.long, &value.unwrap())?),
+            "gutter" => cli.gutter = true,
+            "whole-line" => cli.whole_line = true,
+            "gutter-glyph" => {
+                let value = value.unwrap();
+                if value.chars().count() != 1 {
+                    return Err(format!(
+                        "invalid value '{}' for '--gutter-glyph' (expected one character)",
+                        value
+                    ));
+                }
+                cli.gutter_glyph = Some(value);
+            }
+            "baseline" => cli.baseline = value,
+            "throttle" | "throttle-errors" => {
+                let value = value.unwrap();
+                let cap = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    format!(
+                        "invalid value '{}' for '--{}' (expected a positive number)",
+                        value, spec.long
+                    )
+                })?;
+                match spec.long {
+                    "throttle" => cli.throttle = Some(cap),
+                    _ => cli.throttle_errors = Some(cap),
+                }
+            }
+            "alert-rate" => cli.alert_rate = Some(AlertRate::parse(&value.unwrap())?),
+            "alert-bell" => cli.alert_bell = true,
+            "exec-on-alert" => cli.exec_on_alert = value,
+            "jobs" => {
+                let value = value.unwrap();
+                cli.jobs = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    format!("invalid value '{}' for '--jobs' (expected a positive number)", value)
+                })?;
+            }
+            "wrap" => cli.wrap = true,
+            "track" => cli.track.extend(value),
+            "track-filter" => {
+                let value = value.unwrap();
+                let Some((key, wanted)) = value.split_once('=').filter(|(k, _)| !k.is_empty())
+                else {
+                    return Err(format!(
+                        "expected KEY=VALUE for '--track-filter', got '{}'",
+                        value
+                    ));
+                };
+                cli.track.push(key.to_string());
+                cli.track_filter = Some((key.to_string(), wanted.to_string()));
+            }
+            "json-fields" => {
+                let list = value.unwrap();
+                cli.json_fields = list.split(',').map(|f| f.trim().to_string()).collect();
+                if cli.json_fields.iter().any(String::is_empty) {
+                    return Err(format!("empty field name in '--json-fields {}'", list));
+                }
+            }
+            "json-rest" => cli.json_rest = true,
+            "columns" => cli.columns = true,
+            "source-width" => {
+                let value = value.unwrap();
+                cli.source_width = Some(value.parse().map_err(|_| {
+                    format!("invalid value '{}' for '--source-width' (expected a width)", value)
+                })?);
+            }
+            "elide-below" => cli.elide_below = Some(parse_level_value(spec.long, &value.unwrap())?),
+            "marks" => cli.marks = Some(parse_level_value(spec.long, &value.unwrap())?),
+            "since" => cli.since = Some(parse_since(&value.unwrap())?),
+            "since-boot" => cli.since_boot = true,
+            "tz" => cli.tz = value,
+            "local" => cli.local = true,
+            "level-column" => cli.level_column = Some(LevelColumn::parse(&value.unwrap())?),
+            "level-column-chars" => cli.level_column_chars = true,
+            "level-field" | "strict-prefix" => {
+                let value = value.unwrap();
+                let n = value.parse().ok().filter(|&n| n > 0).ok_or_else(|| {
+                    format!(
+                        "invalid value '{}' for '--{}' (expected a positive number)",
+                        value, spec.long
+                    )
+                })?;
+                match spec.long {
+                    "level-field" => cli.level_field = Some(n),
+                    _ => cli.strict_prefix = Some(n),
+                }
+            }
+            "level-delim" => {
+                let value = value.unwrap();
+                if value.is_empty() {
+                    return Err("'--level-delim' needs some text to split at".to_string());
+                }
+                cli.level_delim = Some(value);
+            }
+            "level-pattern" => cli.level_pattern = Some(LevelColumn::pattern(&value.unwrap())?),
+            "level-map" => {
+                let value = value.unwrap();
+                cli.level_map = Some(parse_level_map(&value).ok_or_else(|| {
+                    format!(
+                        "invalid value '{}' for '--level-map' (expected a map like \
+                         1=error,2=warn,3=info)",
+                        value
+                    )
+                })?);
+            }
+            "max-level" => cli.max_level = Some(parse_level_value(spec.long, &value.unwrap())?),
+            "min-level" => cli.min_level = Some(parse_level_value(spec.long, &value.unwrap())?),
+            "drop-unknown" => cli.drop_unknown = true,
+            "fail-on" => cli.fail_on = Some(parse_level_value(spec.long, &value.unwrap())?),
+            "module" | "module-exclude" | "file" => {
+                let value = value.unwrap();
+                let list: Vec<String> = value.split(',').map(|s| s.trim().to_string()).collect();
+                if list.iter().any(String::is_empty) {
+                    return Err(format!(
+                        "invalid value '{}' for '--{}' (empty pattern)",
+                        value, spec.long
+                    ));
+                }
+                match spec.long {
+                    "module" => cli.sources.modules.extend(list),
+                    "module-exclude" => cli.sources.module_excludes.extend(list),
+                    _ => cli.sources.files.extend(list),
+                }
+            }
+            "module-strict" => cli.sources.strict = true,
+            "watch-glob" => cli.watch_glob = value,
+            "forget-missing" => cli.forget_missing = true,
+            "service" => {
+                let value = value.unwrap();
+                let list: Vec<String> = value.split(',').map(|s| s.trim().to_string()).collect();
+                if list.iter().any(String::is_empty) {
+                    return Err(format!(
+                        "invalid value '{}' for '--service' (empty service name)",
+                        value
+                    ));
+                }
+                cli.services.get_or_insert_with(Vec::new).extend(list);
+            }
+            "grep" | "grep-regex" => grep = Some((spec.long, value.unwrap())),
+            "ignore-case" => ignore_case = true,
+            "after" | "before" | "context" | "continuation-max" => {
+                let value = value.unwrap();
+                let lines = value.parse::<usize>().map_err(|_| {
+                    format!(
+                        "invalid value '{}' for '--{}' (expected a line count)",
+                        value, spec.long
+                    )
+                })?;
+                match spec.long {
+                    "after" => after = Some(lines),
+                    "before" => before = Some(lines),
+                    "continuation-max" => cli.continuation_max = lines,
+                    _ => (before, after) = (Some(lines), Some(lines)),
+                }
+            }
+            "infer" => cli.infer.push(Inference::parse(&value.unwrap())?),
+            "level-word" => cli.level_words.push(LevelWord::parse(&value.unwrap())?),
+            "loose" => cli.loose = true,
+            "no-infer" => cli.no_infer = true,
+            "rules" => cli.rules = value,
+            "check-rules" => cli.check_rules = true,
+            "completions" => cli.completions = value,
+            "glob" => cli.scan.glob = value.unwrap(),
+            "recursive" => cli.scan.recursive = true,
+            "sort" => cli.scan.sort = value.as_deref().and_then(SortKey::parse).unwrap(),
+            "multiline-json" => cli.multiline_json = true,
+            "expand-json" => (cli.multiline_json, cli.expand_json) = (true, true),
+            "unescape" => cli.unescape = true,
+            "show-invisibles" => cli.show_invisibles = true,
+            "allow-bidi" => cli.allow_bidi = true,
+            "color-all-occurrences" => cli.all_occurrences = true,
+            "explain" => cli.explain = true,
+            "crlf" => cli.crlf = true,
+            "output-format" => {
+                cli.output_format = value.as_deref().and_then(OutputFormat::parse).unwrap()
+            }
+            "keep-ansi" => cli.keep_ansi = true,
+            "syslog" => cli.syslog_names = true,
+            "html-standalone" => {
+                (cli.output_format, cli.html_standalone) = (OutputFormat::Html, true)
+            }
+            "preserve-eol" => cli.preserve_eol = true,
+            "continuation" => cli.continuation = value.as_deref().and_then(ContinuationMode::parse),
+            "retry-timeout" => {
+                cli.retry = true;
+                cli.retry_timeout = Some(parse_interval(spec.long, value.as_deref().unwrap())?);
+            }
+            "help" => cli.help = true,
+            _ => unreachable!("option '--{}' has no handler", spec.long),
+        }
+    }
+
+    match grep {
+        Some((flag, pattern)) => {
+            let matcher = match flag {
+                "grep-regex" if pattern.is_empty() => Err("empty matcher".to_string()),
+                "grep-regex" => MiniRegex::new(&pattern, ignore_case).map(Matcher::Regex),
+                _ => Matcher::parse_folding(&pattern, ignore_case),
+            };
+            let matcher = matcher.map_err(|e| format!("--{}: {}", flag, e))?;
+            let (before, after) = (before.unwrap_or(0), after.unwrap_or(0));
+            cli.grep = Some(Grep { matcher, before, after });
+        }
+        None if before.is_some() || after.is_some() => {
+            return Err("'-A', '-B' and '-C' need '--grep'".to_string());
+        }
+        None if ignore_case => return Err("'-i' needs '--grep' or '--grep-regex'".to_string()),
+        None => {}
+    }
+    if snapshot_tuned && cli.snapshot.is_none() {
+        return Err(
+            "'--snapshot-lines', '--snapshot-level' and '--snapshot-interval' need '--snapshot'"
+                .to_string(),
+        );
+    }
+    if let (Some(min), Some(max)) = (cli.min_level, cli.max_level) {
+        if min > max {
+            return Err(format!(
+                "'--min-level {}' is above '--max-level {}'",
+                min.name().to_lowercase(),
+                max.name().to_lowercase()
+            ));
+        }
+    }
+    if cli.dedup_errors && cli.dedup_window.is_none() {
+        return Err("'--dedup-errors' needs '--dedup-window'".to_string());
+    }
+    if cli.squeeze_window.is_some() && !cli.squeeze {
+        return Err("'--squeeze-window' needs '--squeeze'".to_string());
+    }
+    match &mut cli.level_column {
+        Some(LevelColumn { place: LevelPlace::Columns { chars, .. }, .. }) => {
+            *chars = cli.level_column_chars
+        }
+        _ if cli.level_column_chars => {
+            return Err("'--level-column-chars' needs '--level-column'".to_string());
+        }
+        _ => {}
+    }
+    if cli.level_delim.is_some() && cli.level_field.is_none() {
+        return Err("'--level-delim' needs '--level-field'".to_string());
+    }
+    let field = cli.level_field.map(|n| LevelColumn {
+        place: LevelPlace::Field { index: n - 1, delim: cli.level_delim.take() },
+        map: None,
+    });
+    let places = [cli.level_column.take(), field, cli.level_pattern.take()];
+    let mut places = places.into_iter().flatten();
+    cli.level_column = places.next();
+    if places.next().is_some() {
+        return Err("'--level-column', '--level-field' and '--level-pattern' can't be used \
+                    together"
+            .to_string());
+    }
+    match (&mut cli.level_column, cli.level_map.take()) {
+        (Some(column), Some(_)) if column.map.is_some() => {
+            return Err(
+                "'--level-map' and a '--level-column' map can't be used together".to_string()
+            );
+        }
+        (Some(column), Some(map)) => column.map = Some(map),
+        (None, Some(_)) => {
+            return Err(
+                "'--level-map' needs '--level-column', '--level-field' or '--level-pattern'"
+                    .to_string(),
+            );
+        }
+        (_, None) => {}
+    }
+    if cli.sort_buffer.is_some() {
+        // these count the input in the order it came
+        let counted = [
+            ("byte-offsets", cli.byte_offsets),
+            ("number", cli.number),
+            ("lines", cli.lines.is_some()),
+        ];
+        if let Some((option, _)) = counted.iter().find(|(_, set)| *set) {
+            return Err(format!("'--sort-buffer' and '--{}' can't be used together", option));
+        }
+    }
+    if cli.tail.is_some() {
+        // these start from the top, or count from it
+        let counted = [
+            ("lines", cli.lines.is_some()),
+            ("number", cli.number),
+            ("byte-offsets", cli.byte_offsets),
+            ("only-new", cli.only_new),
+            ("watch-glob", cli.watch_glob.is_some()),
+        ];
+        if let Some((option, _)) = counted.iter().find(|(_, set)| *set) {
+            return Err(format!("'--tail' and '--{}' can't be used together", option));
+        }
+    }
+    if cli.mirror.is_some() && cli.mirror_fd.is_some() {
+        return Err("'--mirror' and '--mirror-fd' can't be used together".to_string());
+    }
+    if cli.output.is_none() {
+        let with = [
+            ("output-only", cli.output_only),
+            ("output-color", cli.output_color),
+            ("append", cli.append),
+        ];
+        if let Some((option, _)) = with.iter().find(|(_, set)| *set) {
+            return Err(format!("'--{}' needs '--output'", option));
+        }
+    }
+    if cli.since_boot && cli.since.is_none() {
+        return Err("'--since-boot' needs '--since'".to_string());
+    }
+    if cli.tz.is_some() && cli.local {
+        return Err("'--tz' and '--local' can't be used together".to_string());
+    }
+    if (cli.state_ttl.is_some() || cli.reset_state) && !cli.only_new {
+        let option = if cli.reset_state { "reset-state" } else { "state-ttl" };
+        return Err(format!("'--{}' needs '--only-new'", option));
+    }
+    if cli.gutter_glyph.is_some() && !cli.gutter {
+        return Err("'--gutter-glyph' needs '--gutter'".to_string());
+    }
+    if cli.whole_line && cli.gutter {
+        return Err("'--whole-line' and '--gutter' can't be used together".to_string());
+    }
+    if cli.no_version_ips && cli.extras.is_none() {
+        return Err("'--no-version-ips' needs '--extras'".to_string());
+    }
+    if cli.hex_ascii && !cli.hex {
+        return Err("'--hex-ascii' needs '--hex'".to_string());
+    }
+    if cli.baseline.is_some() && !cli.highlight_new {
+        return Err("'--baseline' needs '--highlight-new'".to_string());
+    }
+    if cli.source_width.is_some() && !cli.columns {
+        return Err("'--source-width' needs '--columns'".to_string());
+    }
+    if cli.split_max_size.is_some() && cli.split_dir.is_none() {
+        return Err("'--split-max-size' needs '--split-dir'".to_string());
+    }
+    if keep_given && cli.split_max_size.is_none() {
+        return Err("'--split-keep' needs '--split-max-size'".to_string());
+    }
+    if cli.throttle_errors.is_some() && cli.throttle.is_none() {
+        return Err("'--throttle-errors' needs '--throttle'".to_string());
+    }
+    if cli.alert_rate.is_none() && (cli.alert_bell || cli.exec_on_alert.is_some()) {
+        let option = if cli.alert_bell { "--alert-bell" } else { "--exec-on-alert" };
+        return Err(format!("'{}' needs '--alert-rate'", option));
+    }
+    if cli.forget_missing && cli.watch_glob.is_none() {
+        return Err("'--forget-missing' needs '--watch-glob'".to_string());
+    }
+    if cli.sources.strict && cli.sources.is_empty() {
+        return Err(
+            "'--module-strict' needs '--module', '--module-exclude' or '--file'".to_string()
+        );
+    }
+    if cli.services.is_some() && cli.format != InputFormat::Compose {
+        return Err("'--service' needs '--format compose'".to_string());
+    }
+    if cli.check_rules && cli.rules.is_none() {
+        return Err("'--check-rules' needs '--rules'".to_string());
+    }
+    if cli.json_rest && cli.json_fields.is_empty() {
+        return Err("'--json-rest' needs '--json-fields'".to_string());
+    }
+    cli.inputs = positional.into_iter().map(InputSource::from_arg).collect();
+    if cli.watch_glob.is_some()
+        && (cli.inputs.len() > 1 || cli.inputs.contains(&InputSource::Stdin))
+    {
+        return Err("'--watch-glob' takes at most one directory to watch".to_string());
+    }
+    // `--watch-glob` follows the files it finds, so `-f` with it says
+    // nothing more
+    if cli.follow && cli.watch_glob.is_none() {
+        match cli.inputs.as_slice() {
+            [InputSource::File(_)] => {}
+            [] | [InputSource::Stdin] => {
+                return Err("'--follow' needs a file to follow, not stdin".to_string());
+            }
+            _ => return Err("'--follow' takes one file".to_string()),
+        }
+    }
+    if cli.inputs.iter().filter(|i| **i == InputSource::Stdin).count() > 1 {
+        return Err("'-' (stdin) can only be given once".to_string());
+    }
+    if cli.only_new && !cli.inputs.iter().any(|input| matches!(input, InputSource::File(_))) {
+        return Err("'--only-new' needs a file to read".to_string());
+    }
+    if cli.compare_threshold.is_some() && !cli.compare {
+        return Err("'--compare-threshold' needs '--compare'".to_string());
+    }
+    if cli.compare && !matches!(cli.inputs[..], [InputSource::File(_), InputSource::File(_)]) {
+        return Err("'--compare' needs two files, the old one and the new one".to_string());
+    }
+    Ok(cli)
+}
+
+/// a level given on the command line, in any case.
+fn parse_level_value(option: &str, value: &str) -> Result<Level, String> {
+    Level::from_token(&value.to_uppercase()).ok_or_else(|| {
+        format!("invalid value '{}' for '--{}' (expected a level like info)", value, option)
+    })
+}
+
+/// a positive number of bytes, with an optional binary `K`, `M` or `G` suffix.
+fn parse_size(option: &str, value: &str) -> Result<usize, String> {
+    let (digits, scale) = match value.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&value[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&value[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(scale))
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("invalid value '{}' for '--{}' (expected a size)", value, option))
+}
+
+/// split `text` into words the way a shell would: whitespace separates,
+/// single quotes keep everything, double quotes keep all but `\"` and `\\`,
+/// and a backslash outside quotes escapes the next character.
+fn split_words(text: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated single quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => word.extend(['\\', c]),
+                            None => return Err("unterminated double quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated double quote".to_string()),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err("trailing backslash".to_string()),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// the words of `$LOGCOLOR_OPTS` (given as `value`) to put before `args`,
+/// unless `args` turn them off.
+fn default_opts(value: Option<&str>, args: &[String]) -> Result<Vec<String>, String> {
+    let explicit = args.iter().take_while(|a| *a != "--");
+    let Some(value) = value.filter(|_| !explicit.clone().any(|a| a == "--no-default-opts")) else {
+        return Ok(Vec::new());
+    };
+    let words = split_words(value).map_err(|e| format!("{}: {}", DEFAULT_OPTS_VAR, e))?;
+    if words.iter().any(|w| w == "--") {
+        // the command line options after it would all turn into file names
+        return Err(format!("{}: '--' is not allowed", DEFAULT_OPTS_VAR));
+    }
+    Ok(words)
+}
+
+/// options whose value names a file.
+fn takes_path(opt: &OptSpec) -> bool {
+    opt.value == Some("PATH")
+}
+
+/// a completion script for `shell`, generated from `OPTIONS` like `--help`.
+fn completion_script(shell: &str) -> String {
+    let mut out = String::new();
+    match shell {
+        "bash" => {
+            out += &format!("_{}() {{\n", PROGRAM);
+            out += "    local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\"\n";
+            out += "    case \"$prev\" in\n";
+            for opt in OPTIONS.iter().filter(|o| o.value.is_some()) {
+                let words = if !opt.choices.is_empty() {
+                    format!("compgen -W \"{}\" -- \"$cur\"", opt.choices.join(" "))
+                } else if takes_path(opt) {
+                    "compgen -f -- \"$cur\"".to_string()
+                } else {
+                    // free-form value, nothing to offer
+                    out += &format!("        --{}) return ;;\n", opt.long);
+                    continue;
+                };
+                out += &format!("        --{}) COMPREPLY=($({})); return ;;\n", opt.long, words);
+            }
+            out += "    esac\n";
+            let mut flags: Vec<String> = OPTIONS.iter().map(|o| format!("--{}", o.long)).collect();
+            flags.extend(OPTIONS.iter().filter_map(|o| o.short).map(|c| format!("-{}", c)));
+            out += "    if [[ \"$cur\" == -* ]]; then\n";
+            out +=
+                &format!("        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n", flags.join(" "));
+            out += "    else\n        COMPREPLY=($(compgen -f -- \"$cur\"))\n    fi\n}\n";
+            out += &format!("complete -o filenames -F _{0} {0}\n", PROGRAM);
+        }
+        "zsh" => {
+            let escape = |text: &str| {
+                text.replace('\'', "'\\''")
+                    .replace('[', "\\[")
+                    .replace(']', "\\]")
+                    .replace(':', "\\:")
+            };
+            out += &format!("#compdef {}\n\n_arguments -s \\\n", PROGRAM);
+            for opt in OPTIONS {
+                // repeatable options can't exclude themselves
+                let repeatable = matches!(opt.long, "track" | "quiet" | "infer");
+                let names = match (opt.short, repeatable) {
+                    (Some(c), false) => format!("'(-{0} --{1})'{{-{0},--{1}}}'", c, opt.long),
+                    (Some(c), true) => format!("'*'{{-{},--{}}}'", c, opt.long),
+                    (None, false) => format!("'--{}", opt.long),
+                    (None, true) => format!("'*--{}", opt.long),
+                };
+                let value = match opt.value {
+                    None => String::new(),
+                    Some(meta) if !opt.choices.is_empty() => {
+                        format!(":{}:({})", meta, opt.choices.join(" "))
+                    }
+                    Some(meta) if takes_path(opt) => format!(":{}:_files", meta),
+                    Some(meta) => format!(":{}: ", meta),
+                };
+                let eq = if opt.value.is_some() && opt.short.is_none() { "=" } else { "" };
+                out += &format!("  {}{}[{}]{}' \\\n", names, eq, escape(opt.help), value);
+            }
+            out += "  '*:log file:_files'\n";
+        }
+        "fish" => {
+            for opt in OPTIONS {
+                out += &format!("complete -c {} -l {}", PROGRAM, opt.long);
+                if let Some(c) = opt.short {
+                    out += &format!(" -s {}", c);
+                }
+                if opt.value.is_some() {
+                    out += " -r";
+                    if !takes_path(opt) {
+                        out += " -f";
+                    }
+                }
+                if !opt.choices.is_empty() {
+                    out += &format!(" -a '{}'", opt.choices.join(" "));
+                }
+                out += &format!(" -d '{}'\n", opt.help.replace('\\', "\\\\").replace('\'', "\\'"));
+            }
+        }
+        _ => unreachable!("shell '{}' has no completions", shell),
+    }
+    out
+}
+
+fn print_usage(program: &str) {
+    eprintln!("Usage:");
+    eprintln!("  {} [options] [path-to-log-file | -]...", program);
+    eprintln!("Options:");
+    for opt in OPTIONS {
+        let short = opt.short.map(|c| format!("-{}, ", c)).unwrap_or_default();
+        let value = match opt.value {
+            Some(v) => format!(" <{}>", v),
+            None if !opt.choices.is_empty() => format!("[={}]", opt.choices.join("|")),
+            None => String::new(),
+        };
+        eprintln!("  {:<24} {}", format!("{}--{}{}", short, opt.long, value), opt.help);
+        if !opt.choices.is_empty() && opt.value.is_some() {
+            eprintln!("  {:<24} one of: {}", "", opt.choices.join(", "));
+        }
+    }
+    eprintln!("Examples:");
+    eprintln!("  {} ./app.log", program);
+    eprintln!("  tail -f /var/log/syslog | {} -", program);
+}
+
+/// the logcolor CLI: parses the arguments, runs, and exits with its status.
+fn main() {
+    let started = Instant::now();
+    let args: Vec<String> = env::args().collect();
+    let env_opts = env::var(DEFAULT_OPTS_VAR).ok();
+    let mut full_args = default_opts(env_opts.as_deref(), &args[1..]).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+    // worth mentioning whenever behavior could surprise
+    let from_env = (!full_args.is_empty())
+        .then(|| format!("(also using {}={:?})", DEFAULT_OPTS_VAR, env_opts.unwrap_or_default()));
+    full_args.extend_from_slice(&args[1..]);
+    let cli = match parse_args(&full_args) {
+        Ok(cli) => cli,
+        Err(e) => {
+            eprintln!("{}", e);
+            from_env.iter().for_each(|note| eprintln!("{}", note));
+            print_usage(&args[0]);
+            exit(1);
+        }
+    };
+    if cli.help {
+        print_usage(&args[0]);
+        from_env.iter().for_each(|note| eprintln!("{}", note));
+        return;
+    }
+    if let Some(shell) = &cli.completions {
+        print!("{}", completion_script(shell));
+        return;
+    }
+    let fail_on = cli.fail_on;
+    let outcome = run(cli, &full_args, from_env.as_deref(), started).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    });
+    match exit_status(outcome.failed, outcome.found) {
+        0 => {}
+        2 => {
+            let found = outcome.found;
+            let plural = if found == 1 { "" } else { "s" };
+            let level = fail_on.map_or("", Level::name);
+            eprintln!("{} line{} at {} or above", with_commas(found), plural, level);
+            exit(2);
+        }
+        status => exit(status),
+    }
+}
+
+/// what a run that got to the end of its inputs exits with: 1 once reading
+/// or writing failed, else 2 for `found` lines at the `--fail-on` level.
+fn exit_status(failed: bool, found: usize) -> i32 {
+    match (failed, found) {
+        (true, _) => 1,
+        (false, 0) => 0,
+        (false, _) => 2,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn theme_flag() {
+        assert_eq!(parse_args(&args(&["--theme", "light"])).unwrap().theme, ThemeChoice::Light);
+        assert_eq!(parse_args(&args(&["--theme=dark", "a.log"])).unwrap().theme, ThemeChoice::Dark);
+        assert_eq!(parse_args(&args(&[])).unwrap().theme, ThemeChoice::Auto);
+        assert!(parse_args(&args(&["--theme", "pink"])).is_err());
+        assert!(parse_args(&args(&["--theme"])).is_err());
+        assert!(parse_args(&args(&["-", "b.log", "-"])).is_err());
+    }
+
+    #[test]
+    fn syslog_priorities() {
+        assert!(parse_args(&args(&["--syslog", "a.log"])).unwrap().syslog_names);
+    }
+
+    #[test]
+    fn logfmt_lines() {
+        let cli = parse_args(&args(&["--logfmt", "--logfmt-keys", "dim", "a.log"])).unwrap();
+        assert!(cli.format == InputFormat::Logfmt && cli.dim_logfmt_keys);
+        assert!(parse_args(&args(&["--logfmt-keys", "bright"])).is_err());
+    }
+
+    #[test]
+    fn colorblind_theme() {
+        assert_eq!(
+            parse_args(&args(&["--theme=colorblind"])).unwrap().theme,
+            ThemeChoice::Colorblind
+        );
+    }
+
+    #[test]
+    fn themes_and_depths() {
+        let cli = parse_args(&args(&["--theme", "monochrome", "--color-depth", "256"])).unwrap();
+        assert_eq!(
+            (cli.theme, cli.color_depth),
+            (ThemeChoice::Monochrome, Some(ColorDepth::Ansi256))
+        );
+        assert!(parse_args(&args(&["--color-depth", "16"])).is_err());
+    }
+
+    #[test]
+    fn quiet_flag() {
+        assert_eq!(parse_args(&args(&["-q", "a.log"])).unwrap().quiet, 1);
+        assert_eq!(parse_args(&args(&["-qq"])).unwrap().quiet, 2);
+        assert_eq!(parse_args(&args(&["--quiet", "-q"])).unwrap().quiet, 2);
+        let cli = parse_args(&args(&["-qh"])).unwrap();
+        assert!(cli.help && cli.quiet == 1);
+        assert!(parse_args(&args(&["-qx"])).is_err());
+        let inputs = parse_args(&args(&["--", "-qq"])).unwrap().inputs;
+        assert_eq!(inputs, [InputSource::File("-qq".to_string())]);
+    }
+
+    #[test]
+    fn retry_waits_for_missing_file() {
+        let cli = parse_args(&args(&["--retry-timeout=1.5", "a.log"])).unwrap();
+        assert!(cli.retry);
+        assert_eq!(cli.retry_timeout, Some(Duration::from_millis(1500)));
+        assert!(parse_args(&args(&["--retry-timeout", "soon"])).is_err());
+        for (given, timeout) in
+            [("3s", Duration::from_secs(3)), ("500ms", Duration::from_millis(500))]
+        {
+            let cli = parse_args(&args(&["--retry-timeout", given, "a.log"])).unwrap();
+            assert_eq!(cli.retry_timeout, Some(timeout), "{given}");
+        }
+        let cli = parse_args(&args(&["-F", "a.log"])).unwrap();
+        assert!(cli.follow && cli.retry);
+        assert!(parse_args(&args(&["--follow-retry", "a.log"])).unwrap().retry);
+        assert!(parse_args(&args(&["-F", "-"])).is_err());
+        assert!(parse_args(&args(&["--retry-timeout", "-1"])).is_err());
+    }
+
+    #[test]
+    fn tracked_values_get_stable_colors() {
+        let cli = parse_args(&args(&["--track", "pid", "--track-filter=trace_id=af92"])).unwrap();
+        assert_eq!(cli.track, ["pid", "trace_id"]);
+        assert_eq!(cli.track_filter, Some(("trace_id".to_string(), "af92".to_string())));
+        assert!(parse_args(&args(&["--track-filter", "af92"])).is_err());
+    }
+
+    #[test]
+    fn json_field_selection() {
+        let cli = parse_args(&args(&["--json-fields", "time, level,msg", "--json-rest"])).unwrap();
+        assert_eq!(cli.json_fields, ["time", "level", "msg"]);
+        assert!(parse_args(&args(&["--json-fields", "a,,b"])).is_err());
+        assert!(parse_args(&args(&["--json-rest"])).is_err());
+    }
+
+    #[test]
+    fn rules_file() {
+        assert!(parse_args(&args(&["--check-rules"])).is_err());
+    }
+
+    #[test]
+    fn inputs_in_order() {
+        let cli = parse_args(&args(&["header.log", "-", "--wrap", "footer.log"])).unwrap();
+        assert_eq!(
+            cli.inputs,
+            [
+                InputSource::File("header.log".to_string()),
+                InputSource::Stdin,
+                InputSource::File("footer.log".to_string()),
+            ]
+        );
+        assert_eq!(cli.inputs[1].label(), "(stdin)");
+        assert_eq!(cli.inputs[2].label(), "'footer.log'");
+        assert!(parse_args(&args(&[])).unwrap().inputs.is_empty());
+    }
+
+    #[test]
+    fn directory_arguments() {
+        let cli =
+            parse_args(&args(&["-R", "--sort", "mtime", "--glob", "*.out", "/var/log"])).unwrap();
+        assert!(cli.scan.recursive && cli.scan.sort == SortKey::Mtime && cli.scan.glob == "*.out");
+    }
+
+    #[test]
+    fn completion_scripts_cover_options() {
+        let bash = completion_script("bash");
+        assert!(bash.contains(
+            "--theme) COMPREPLY=($(compgen -W \"auto dark light colorblind solarized-dark monochrome\" -- \"$cur\"))"
+        ));
+        assert!(bash.contains("--rules) COMPREPLY=($(compgen -f -- \"$cur\"))"));
+        assert!(bash.ends_with("complete -o filenames -F _logcolor logcolor\n"));
+        let zsh = completion_script("zsh");
+        assert!(zsh.starts_with("#compdef logcolor\n"));
+        assert!(zsh.contains("'--color=[use colors; auto (default) checks that stdout is a capable terminal]:WHEN:(auto always never)' \\\n"));
+        assert!(zsh.contains("'*'{-q,--quiet}'["));
+        let fish = completion_script("fish");
+        assert!(fish.contains(
+            "complete -c logcolor -l format -r -f -a 'auto plain rfc5424 journald gelf rust k8s compose json logfmt' -d "
+        ));
+        assert!(fish.contains("complete -c logcolor -l help -s h -d 'print this help'\n"));
+        // every option shows up in each of them
+        for opt in OPTIONS {
+            let flag = format!("--{}", opt.long);
+            assert!(bash.contains(&flag) && zsh.contains(&flag), "{}", flag);
+            assert!(fish.contains(&format!("-l {} ", opt.long)), "{}", flag);
+        }
+    }
+
+    #[test]
+    fn summary_lines() {
+        assert!(parse_args(&args(&["--summary-only", "logs/"])).unwrap().summary_only);
+    }
+
+    #[test]
+    fn fail_on_counts() {
+        let cli = parse_args(&args(&["--fail-on", "warning", "-"])).unwrap();
+        assert_eq!(cli.fail_on, Some(Level::Warn));
+        assert!(parse_args(&args(&["--fail-on", "bad"])).is_err());
+    }
+
+    #[test]
+    fn byte_offsets() {
+        assert!(parse_args(&args(&["--byte-offsets", "a.log"])).unwrap().byte_offsets);
+        let cli = parse_args(&args(&["-b", "--line-number", "a.log"])).unwrap();
+        assert!(cli.byte_offsets && cli.number);
+    }
+
+    #[test]
+    fn long_lines_are_cut() {
+        assert_eq!(parse_size("max-line-bytes", "64K"), Ok(65_536));
+        assert_eq!(parse_size("max-line-bytes", "4096"), Ok(4096));
+        assert!(parse_size("max-line-bytes", "0").is_err());
+        assert!(parse_size("max-line-bytes", "M").is_err());
+    }
+
+    #[test]
+    fn html_output() {
+        let cli = parse_args(&args(&["--html-standalone", "a.log"])).unwrap();
+        assert!(cli.output_format == OutputFormat::Html && cli.html_standalone);
+        assert!(parse_args(&args(&["--output-format", "pdf"])).is_err());
+    }
+
+    #[test]
+    fn stats_output() {
+        let cli = parse_args(&args(&["--stats-format", "json", "a.log"])).unwrap();
+        assert!(cli.stats && cli.stats_format == StatsFormat::Json);
+        assert!(parse_args(&args(&["--stats-output", "s.json"])).unwrap().stats);
+        let cli = parse_args(&args(&["--stats-only", "a.log"])).unwrap();
+        assert!(cli.stats && cli.stats_only);
+    }
+
+    #[test]
+    fn default_options_from_env() {
+        let words = split_words(r#" --theme light  --track 'request id' "a \"b\" \c" x\ y '' "#);
+        assert_eq!(
+            words.unwrap(),
+            ["--theme", "light", "--track", "request id", "a \"b\" \\c", "x y", ""]
+        );
+        assert!(split_words("'open").is_err());
+        assert!(split_words("\"open\\\"").is_err());
+        assert!(split_words("end\\").is_err());
+        assert_eq!(split_words("  ").unwrap(), Vec::<String>::new());
+
+        let cli_args = args(&["--theme", "dark", "app.log"]);
+        let mut all = default_opts(Some("--theme light --wrap"), &cli_args).unwrap();
+        assert_eq!(all, ["--theme", "light", "--wrap"]);
+        all.extend(cli_args);
+        let cli = parse_args(&all).unwrap();
+        assert!(cli.theme == ThemeChoice::Dark && cli.wrap);
+
+        assert!(default_opts(Some("--wrap"), &args(&["--no-default-opts"])).unwrap().is_empty());
+        let after_dashes = args(&["--", "--no-default-opts"]);
+        assert_eq!(default_opts(Some("--wrap"), &after_dashes).unwrap(), ["--wrap"]);
+        assert!(default_opts(Some("-- x"), &[]).is_err());
+        assert!(default_opts(None, &[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn parallel_jobs_keep_order() {
+        assert_eq!(parse_args(&args(&["-j", "4"])).unwrap().jobs, 4);
+        assert!(parse_args(&args(&["--jobs=0"])).is_err());
+    }
+
+    #[test]
+    fn inferred_levels() {
+        let cli = parse_args(&args(&["--infer", "lost quorum=error", "--no-infer"])).unwrap();
+        assert!(cli.no_infer && cli.infer.len() == 1);
+    }
+
+    #[test]
+    fn unbuffered_output() {
+        assert!(parse_args(&args(&["-u", "-"])).unwrap().unbuffered);
+    }
+
+    #[test]
+    fn grep_context() {
+        let cli =
+            parse_args(&args(&["--grep", "user 4821", "-B", "2", "-A", "5", "app.log"])).unwrap();
+        let g = cli.grep.unwrap();
+        assert_eq!((g.before, g.after), (2, 5));
+        // -i after the pattern still applies to it, substring or regex
+        let cli = parse_args(&args(&["--grep", "a.B", "-i", "-"])).unwrap();
+        assert_eq!(cli.grep.unwrap().matcher.find_all("xA.b a.b aXb"), [1..4, 5..8]);
+        let cli = parse_args(&args(&["-i", "--grep-regex", "time ?out", "-C", "1"])).unwrap();
+        let g = cli.grep.unwrap();
+        assert_eq!((g.before, g.after), (1, 1));
+        assert_eq!(g.matcher.find("read TIMEOUT"), Some(5..12));
+        let g = parse_args(&args(&["--grep", "x", "-iA2", "-B0", "-"])).unwrap().grep.unwrap();
+        assert_eq!((g.before, g.after, g.matcher.find("X")), (0, 2, Some(0..1)));
+        assert_eq!(
+            parse_args(&args(&["--grep-regex", "a**"])).err().as_deref(),
+            Some("--grep-regex: nothing to repeat before '*'")
+        );
+        assert!(parse_args(&args(&["-i", "a.log"])).is_err());
+        let g = parse_args(&args(&["-C", "3", "--grep=x", "-A", "1"])).unwrap().grep.unwrap();
+        assert_eq!((g.before, g.after), (3, 1));
+        assert!(parse_args(&args(&["-C", "3"])).is_err());
+    }
+
+    #[test]
+    fn kubernetes_lines() {
+        assert_eq!(parse_args(&args(&["--format", "k8s"])).unwrap().format, InputFormat::K8s);
+    }
+
+    #[test]
+    fn compose_prefixes() {
+        let cli = parse_args(&args(&["--format", "compose", "--service", "api, worker"])).unwrap();
+        assert_eq!(cli.services.unwrap(), ["api", "worker"]);
+        assert!(parse_args(&args(&["--service", "api"])).is_err());
+        assert!(parse_args(&args(&["--format", "compose", "--service", "api,"])).is_err());
+    }
+
+    #[test]
+    fn elided_lines() {
+        assert_eq!(
+            parse_args(&args(&["--elide-below", "warn"])).unwrap().elide_below,
+            Some(Level::Warn)
+        );
+        assert!(parse_args(&args(&["--elide-below", "loud"])).is_err());
+    }
+
+    #[test]
+    fn watched_glob() {
+        let cli =
+            parse_args(&args(&["--watch-glob", "app-*.log", "--forget-missing", "/var/log/app"]))
+                .unwrap();
+        assert_eq!((cli.watch_glob.as_deref(), cli.forget_missing), (Some("app-*.log"), true));
+        assert!(parse_args(&args(&["--forget-missing"])).is_err());
+        assert!(parse_args(&args(&["--watch-glob", "*.log", "a", "b"])).is_err());
+    }
+
+    #[test]
+    fn jump_marks() {
+        assert_eq!(parse_args(&args(&["--marks", "error"])).unwrap().marks, Some(Level::Error));
+        assert!(parse_args(&args(&["--marks", "bad"])).is_err());
+    }
+
+    #[test]
+    fn throttled_lines() {
+        let cli = parse_args(&args(&["--throttle", "100"])).unwrap();
+        assert_eq!((cli.throttle, cli.throttle_errors), (Some(100), None));
+        assert!(parse_args(&args(&["--throttle", "0"])).is_err());
+        assert!(parse_args(&args(&["--throttle-errors", "5"])).is_err());
+    }
+
+    #[test]
+    fn format_detection() {
+        assert!(parse_args(&args(&["--detect", "app.log"])).unwrap().detect);
+    }
+
+    #[test]
+    fn split_files_rotate() {
+        let cli = parse_args(&args(&[
+            "--split-dir",
+            "out",
+            "--split-max-size",
+            "100M",
+            "--split-keep",
+            "3",
+        ]))
+        .unwrap();
+        assert_eq!((cli.split_max_size, cli.split_keep), (Some(100 << 20), 3));
+        assert_eq!(
+            parse_args(&args(&["--split-dir", "out"])).unwrap().split_keep,
+            DEFAULT_SPLIT_KEEP
+        );
+        assert!(parse_args(&args(&["--split-max-size", "1M"])).is_err());
+        assert!(parse_args(&args(&["--split-dir", "out", "--split-keep", "3"])).is_err());
+    }
+
+    #[test]
+    fn tail_headers() {
+        assert!(parse_args(&args(&["--no-tail-headers"])).unwrap().no_tail_headers);
+    }
+
+    #[test]
+    fn dedup_window() {
+        let cli = parse_args(&args(&["--dedup-window", "8", "--dedup-errors"])).unwrap();
+        assert_eq!((cli.dedup_window, cli.dedup_errors), (Some(8), true));
+        assert!(parse_args(&args(&["--dedup-errors"])).is_err());
+    }
+
+    #[test]
+    fn squeeze_repeats() {
+        let cli = parse_args(&args(&["-s", "--squeeze-window", "3"])).unwrap();
+        assert_eq!((cli.squeeze, cli.squeeze_window), (true, Some(3)));
+        assert!(parse_args(&args(&["--squeeze-window", "3"])).is_err());
+        assert!(parse_args(&args(&["-s", "--squeeze-window", "0"])).is_err());
+    }
+
+    #[test]
+    fn max_level() {
+        assert_eq!(
+            parse_args(&args(&["--max-level", "info"])).unwrap().max_level,
+            Some(Level::Info)
+        );
+        assert!(parse_args(&args(&["--max-level", "chatty"])).is_err());
+    }
+
+    #[test]
+    fn snapshot_file() {
+        let cli = parse_args(&args(&[
+            "--snapshot",
+            "s.txt",
+            "--snapshot-lines",
+            "5",
+            "--snapshot-level",
+            "error",
+            "--snapshot-interval",
+            "2s",
+        ]))
+        .unwrap();
+        assert_eq!(
+            (cli.snapshot_lines, cli.snapshot_level, cli.snapshot_interval),
+            (5, Level::Error, Duration::from_secs(2))
+        );
+        assert!(parse_args(&args(&["--snapshot-lines", "5"])).is_err());
+    }
+
+    #[test]
+    fn columns_layout() {
+        assert!(parse_args(&args(&["--source-width", "10"])).is_err());
+        let cli = parse_args(&args(&["--columns", "--source-width", "10"])).unwrap();
+        assert_eq!((cli.columns, cli.source_width), (true, Some(10)));
+    }
+
+    #[test]
+    fn highlight_new() {
+        assert!(parse_args(&args(&["--baseline", "old.log"])).is_err());
+    }
+
+    #[test]
+    fn since_windows() {
+        assert!(parse_args(&args(&["--since-boot"])).is_err());
+    }
+
+    #[test]
+    fn hex_dumps() {
+        assert!(parse_args(&args(&["--hex-ascii"])).is_err());
+    }
+
+    #[test]
+    fn extras_found() {
+        let cli = parse_args(&args(&["--extras=ip,url", "--no-version-ips"])).unwrap();
+        assert_eq!((cli.extras, cli.no_version_ips), (Some(vec![Extra::Url, Extra::Ip]), true));
+        assert_eq!(parse_args(&args(&["--extras"])).unwrap().extras, Some(Extra::ALL.to_vec()));
+        assert!(parse_args(&args(&["--extras=ip,mac"])).is_err());
+        assert!(parse_args(&args(&["--no-version-ips"])).is_err());
+    }
+
+    #[test]
+    fn level_columns() {
+        assert!(parse_args(&args(&["--level-column-chars"])).is_err());
+        let cli = parse_args(&args(&["--level-column", "57", "--level-column-chars"])).unwrap();
+        assert!(cli.level_column.is_some_and(|c| matches!(
+            c.place,
+            LevelPlace::Columns { start: 56, chars: true, .. }
+        )));
+    }
+
+    #[test]
+    fn level_patterns() {
+        let line = "2024-05-01 12:00:00 | 3 | module=auth | user login failed";
+        let map = "1=error,2=warn,3=info,4=debug";
+        for given in [
+            &["--level-pattern", "| {level} |", "--level-map", map][..],
+            &["--level-field", "2", "--level-delim", "|", "--level-map", map],
+        ] {
+            let place = parse_args(&args(given)).unwrap().level_column.unwrap();
+            assert_eq!(place.level(line), Some((22..23, Level::Info)), "{:?}", given);
+            // not there, or not a level: no level, and nothing else looked at
+            for other in ["2024-05-01 12:00:00 | 7 | error", "| |", "ERROR é|", ""] {
+                assert_eq!(place.level(other), None, "{:?} {:?}", given, other);
+            }
+        }
+        // a fixed-column format, mapped the same way
+        let cli = parse_args(&args(&["--level-column", "6", "--level-map", map])).unwrap();
+        assert_eq!(cli.level_column.unwrap().level("0001 1 disk full"), Some((5..6, Level::Error)));
+        for wrong in [
+            &["--level-pattern", "no level"][..],
+            &["--level-pattern", "{level}"],
+            &["--level-map", map],
+            &["--level-delim", "|"],
+            &["--level-field", "0"],
+            &["--level-field", "2", "--level-column", "3"],
+            &["--level-column", "3:E=error", "--level-map", map],
+            &["--level-field", "2", "--level-map", "1"],
+            &["--strict-prefix", "x"],
+        ] {
+            assert!(parse_args(&args(wrong)).is_err(), "{:?}", wrong);
+        }
+    }
+
+    #[test]
+    fn mirrored_output() {
+        assert!(parse_args(&args(&["--mirror-fd", "1"])).is_err());
+        assert!(parse_args(&args(&["--mirror", "/dev/null", "--mirror-fd", "5"])).is_err());
+    }
+
+    #[test]
+    fn exit_statuses() {
+        // a failed read or write wins over lines at the --fail-on level
+        assert_eq!((exit_status(false, 0), exit_status(false, 3), exit_status(true, 3)), (0, 2, 1));
+    }
+
+    #[test]
+    fn output_file() {
+        let cli =
+            parse_args(&args(&["-o", "copy.log", "--output-color", "--append", "a.log"])).unwrap();
+        assert_eq!(cli.output.as_deref(), Some("copy.log"));
+        assert!(cli.output_color && cli.append && !cli.output_only);
+        let err = parse_args(&args(&["--output-only", "a.log"])).err();
+        assert_eq!(err.as_deref(), Some("'--output-only' needs '--output'"));
+    }
+
+    #[test]
+    fn source_filter() {
+        let cli =
+            parse_args(&args(&["--module", "net::*", "--module-exclude", "metrics*"])).unwrap();
+        assert_eq!(
+            (cli.sources.modules, cli.sources.module_excludes),
+            (args(&["net::*"]), args(&["metrics*"]))
+        );
+        let files = parse_args(&args(&["--file", "conn.go, pool.go"])).unwrap().sources;
+        assert!(files.modules.is_empty() && !files.strict);
+        assert_eq!(files.files, ["conn.go", "pool.go"]);
+        assert!(parse_args(&args(&["--module-strict"])).is_err());
+        assert!(parse_args(&args(&["--module", "a,,b"])).is_err());
+    }
+
+    #[test]
+    fn budget_limits() {
+        let cli = parse_args(&args(&["--budget", "info=4", "--budget", "warn=1"])).unwrap();
+        let limits = cli.budget.unwrap().limits;
+        assert_eq!(
+            (limits[Level::Info as usize], limits[Level::Warn as usize]),
+            (Some(4), Some(1))
+        );
+        assert_eq!(limits.iter().flatten().count(), 2);
+        assert!(parse_args(&args(&["--budget", "info"])).is_err());
+        assert!(parse_args(&args(&["--budget", "loud=5"])).is_err());
+    }
+
+    #[test]
+    fn severity_gutter() {
+        assert!(parse_args(&args(&["--gutter-glyph", "#"])).is_err());
+        assert!(parse_args(&args(&["--gutter", "--gutter-glyph", "ab"])).is_err());
+    }
+
+    #[test]
+    fn only_new_state() {
+        assert!(parse_args(&args(&["--only-new"])).is_err());
+        assert!(parse_args(&args(&["--reset-state", "a.log"])).is_err());
+        let cli = parse_args(&args(&["--only-new", "--state-ttl", "7d", "a.log"])).unwrap();
+        assert_eq!(cli.state_ttl, Some(Duration::from_secs(7 * 86_400)));
+    }
+
+    #[test]
+    fn time_zones() {
+        assert!(parse_args(&args(&["--tz", "UTC", "--local"])).is_err());
+    }
+
+    #[test]
+    fn compare_signatures() {
+        assert!(parse_args(&args(&["--compare", "a.log"])).is_err());
+        assert!(parse_args(&args(&["--compare-threshold", "3x", "a", "b"])).is_err());
+        let cli =
+            parse_args(&args(&["--compare", "--compare-threshold", "1.5x", "a", "b"])).unwrap();
+        assert_eq!(cli.compare_threshold, Some(1.5));
+        assert!(parse_args(&args(&["--compare", "--compare-threshold", "1x", "a", "b"])).is_err());
+    }
+
+    #[test]
+    fn alert_rate_hysteresis() {
+        assert!(parse_args(&args(&["--alert-bell"])).is_err());
+        let cli =
+            parse_args(&args(&["--alert-rate", "50/10s", "--exec-on-alert", "true"])).unwrap();
+        assert_eq!(cli.alert_rate.map(|rate| rate.errors), Some(50));
+    }
+
+    #[test]
+    fn audit_records() {
+        assert_eq!(
+            parse_args(&args(&["--audit", "a.jsonl"])).unwrap().audit.as_deref(),
+            Some("a.jsonl")
+        );
+    }
+
+    #[test]
+    fn line_ranges() {
+        let cli = parse_args(&args(&["-n", "--lines", "48000:", "a.log"])).unwrap();
+        assert!(cli.number && cli.lines.is_some_and(|range| range.skipped() == 47_996));
+    }
+
+    #[test]
+    fn invisible_characters() {
+        let cli = parse_args(&args(&["--show-invisibles", "--allow-bidi", "a.log"])).unwrap();
+        assert!(cli.show_invisibles && cli.allow_bidi);
+    }
+
+    #[test]
+    fn continuation_modes() {
+        let cli = parse_args(&args(&["--continuation", "deeper", "a.log"])).unwrap();
+        assert_eq!(cli.continuation, Some(ContinuationMode::Deeper));
+        assert_eq!(cli.continuation_max, 200);
+        let cli = parse_args(&args(&["--continuation-max", "5", "a.log"])).unwrap();
+        assert_eq!(cli.continuation_max, 5);
+        assert!(parse_args(&args(&["--continuation", "sideways", "a.log"])).is_err());
+    }
+
+    #[test]
+    fn level_word_boundaries() {
+        assert!(parse_args(&args(&["--loose", "a.log"])).unwrap().loose);
+    }
+
+    #[test]
+    fn custom_level_words() {
+        let cli =
+            parse_args(&args(&["--level-word", "CRIT=error", "--level-word=x=info"])).unwrap();
+        assert_eq!(cli.level_words.len(), 2);
+        assert_eq!(cli.level_words[1].word, "X");
+    }
+
+    #[test]
+    fn platform_seams() {
+        assert!(parse_args(&args(&["--crlf", "a.log"])).unwrap().crlf);
+    }
+
+    #[test]
+    fn preserved_final_newline() {
+        assert!(parse_args(&args(&["--preserve-eol"])).unwrap().preserve_eol);
+    }
+
+    #[test]
+    fn sort_buffer() {
+        let two = Some(Duration::from_secs(2));
+
+        assert_eq!(parse_args(&args(&["--sort-buffer", "2s"])).unwrap().sort_buffer, two);
+        assert!(parse_args(&args(&["--sort-buffer", "2s", "--number"])).is_err());
+    }
+
+    #[test]
+    fn followed_file() {
+        let cli = parse_args(&args(&["-f", "app.log"])).unwrap();
+        assert!(cli.follow);
+        for (given, error) in [
+            (&["-f"][..], "'--follow' needs a file to follow, not stdin"),
+            (&["-f", "-"], "'--follow' needs a file to follow, not stdin"),
+            (&["-f", "a.log", "b.log"], "'--follow' takes one file"),
+        ] {
+            assert_eq!(parse_args(&args(given)).err().as_deref(), Some(error));
+        }
+        // the files --watch-glob finds are followed anyway
+        for given in
+            [&["-f", "--watch-glob", "*.log", "logs"][..], &["--watch-glob", "logs/*.log", "-F"]]
+        {
+            let cli = parse_args(&args(given)).unwrap();
+            assert!(cli.follow && cli.watch_glob.is_some(), "{given:?}");
+        }
+    }
+
+    #[test]
+    fn min_level() {
+        let cli = parse_args(&args(&["--min-level", "warn", "--drop-unknown"])).unwrap();
+        assert_eq!((cli.min_level, cli.drop_unknown), (Some(Level::Warn), true));
+        assert_eq!(
+            parse_args(&args(&["--min-level", "error", "--max-level", "info"])).err().as_deref(),
+            Some("'--min-level error' is above '--max-level info'")
+        );
+        assert!(parse_args(&args(&["--min-level", "warn", "--max-level", "warn"])).is_ok());
+    }
+
+    #[test]
+    fn json_lines() {
+        let cli = parse_args(&args(&["--json=pretty"])).unwrap();
+        assert_eq!((cli.format, cli.json_pretty), (InputFormat::Json, true));
+        let cli = parse_args(&args(&["--json"])).unwrap();
+        assert_eq!((cli.format, cli.json_pretty), (InputFormat::Json, false));
+        assert!(parse_args(&args(&["--json=ugly"])).is_err());
+        assert!(parse_args(&args(&["--follow=yes"])).is_err());
+    }
+
+    #[test]
+    fn color_scheme() {
+        let cli = parse_args(&args(&["--config", "colors", "--print-config"])).unwrap();
+        assert_eq!((cli.config.as_deref(), cli.print_config), (Some("colors"), true));
+    }
+
+    #[test]
+    fn ansi_input() {
+        assert!(parse_args(&args(&["--keep-ansi", "-"])).unwrap().keep_ansi);
+    }
+
+    #[test]
+    fn whole_line() {
+        assert!(parse_args(&args(&["--whole-line", "--gutter"])).is_err());
+        assert!(parse_args(&args(&["--whole-line"])).unwrap().whole_line);
+    }
+
+    #[test]
+    fn tail_lines() {
+        assert_eq!(parse_args(&args(&["--tail", "20", "a.log"])).unwrap().tail, Some(20));
+        assert!(parse_args(&args(&["--tail", "-1"])).is_err());
+        assert!(parse_args(&args(&["--tail", "5", "--number"])).is_err());
+        assert!(parse_args(&args(&["--tail", "5", "--lines", "1:3"])).is_err());
+        assert!(parse_args(&args(&["--tail", "5", "-f", "a.log"])).is_ok());
+    }
+}